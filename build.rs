@@ -0,0 +1,36 @@
+//! Compiles the shaders wgpu is handed as SPIR-V.
+//!
+//! The WGSL frontend of the naga wgpu uses can't parse `binding_array`,
+//! so bindless_quad.wgsl goes through a newer naga here instead, into
+//! `OUT_DIR`, and is rebuilt whenever it changes.
+
+use std::{env, fs, path::Path};
+
+use naga::{
+    back::spv,
+    valid::{Capabilities, ValidationFlags, Validator},
+};
+
+fn main() {
+    compile_spirv("src/bindless_quad.wgsl", "bindless_quad.spv");
+}
+
+fn compile_spirv(wgsl_path: &str, spv_name: &str) {
+    println!("cargo:rerun-if-changed={}", wgsl_path);
+
+    let source = fs::read_to_string(wgsl_path)
+        .unwrap_or_else(|err| panic!("failed to read {}: {}", wgsl_path, err));
+    let module = naga::front::wgsl::parse_str(&source)
+        .unwrap_or_else(|err| panic!("{}", err.emit_to_string_with_path(&source, wgsl_path)));
+    let info = Validator::new(ValidationFlags::all(), Capabilities::all())
+        .validate(&module)
+        .unwrap_or_else(|err| panic!("{}", err.emit_to_string_with_path(&source, wgsl_path)));
+    let words = spv::write_vec(&module, &info, &spv::Options::default(), None)
+        .unwrap_or_else(|err| panic!("failed to write SPIR-V for {}: {}", wgsl_path, err));
+
+    let bytes: Vec<u8> = words.iter().flat_map(|word| word.to_le_bytes()).collect();
+    let out_dir = env::var("OUT_DIR").expect("cargo sets OUT_DIR for build scripts");
+    let spv_path = Path::new(&out_dir).join(spv_name);
+    fs::write(&spv_path, bytes)
+        .unwrap_or_else(|err| panic!("failed to write {}: {}", spv_path.display(), err));
+}
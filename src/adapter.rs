@@ -0,0 +1,79 @@
+use std::fmt;
+
+/// What we know about a GPU, without opening a device on it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AdapterInfo {
+    pub name: String,
+    pub backend: wgpu::Backend,
+    pub device_type: wgpu::DeviceType,
+    pub vendor_id: usize,
+}
+
+impl From<wgpu::AdapterInfo> for AdapterInfo {
+    fn from(info: wgpu::AdapterInfo) -> Self {
+        AdapterInfo {
+            name: info.name,
+            backend: info.backend,
+            device_type: info.device_type,
+            vendor_id: info.vendor,
+        }
+    }
+}
+
+impl fmt::Display for AdapterInfo {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} ({:?}, {:?}, vendor {:#06x})",
+            self.name, self.backend, self.device_type, self.vendor_id
+        )
+    }
+}
+
+/// Picks a specific adapter, instead of whichever one wgpu prefers.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AdapterSelector {
+    /// The first adapter with this in its name, ignoring case.
+    ByName(String),
+    /// The first adapter using this backend.
+    ByBackend(wgpu::Backend),
+}
+
+impl AdapterSelector {
+    pub fn by_name(substring: &str) -> Self {
+        AdapterSelector::ByName(substring.to_lowercase())
+    }
+
+    pub fn by_backend(backend: wgpu::Backend) -> Self {
+        AdapterSelector::ByBackend(backend)
+    }
+
+    /// Reads the value of `--adapter`, which is either the name of a
+    /// backend (vulkan, metal, dx12, dx11, gl or webgpu) or part of
+    /// the name of an adapter.
+    pub fn parse(value: &str) -> Self {
+        match parse_backend(value) {
+            Some(backend) => Self::by_backend(backend),
+            None => Self::by_name(value),
+        }
+    }
+
+    pub fn matches(&self, info: &AdapterInfo) -> bool {
+        match self {
+            AdapterSelector::ByName(substring) => info.name.to_lowercase().contains(substring),
+            AdapterSelector::ByBackend(backend) => info.backend == *backend,
+        }
+    }
+}
+
+fn parse_backend(name: &str) -> Option<wgpu::Backend> {
+    match name.to_lowercase().as_str() {
+        "vulkan" => Some(wgpu::Backend::Vulkan),
+        "metal" => Some(wgpu::Backend::Metal),
+        "dx12" => Some(wgpu::Backend::Dx12),
+        "dx11" => Some(wgpu::Backend::Dx11),
+        "gl" => Some(wgpu::Backend::Gl),
+        "webgpu" => Some(wgpu::Backend::BrowserWebGpu),
+        _ => None,
+    }
+}
@@ -0,0 +1,321 @@
+use std::{
+    collections::HashMap,
+    future::Future,
+    num::NonZeroU64,
+    pin::Pin,
+    sync::atomic::{AtomicU64, Ordering},
+    task::{Context, Waker},
+};
+
+/// Tells a resource apart from every other one made while the program
+/// runs, where addresses don't: a resource recreated in the same field
+/// sits at the same address as the one it replaced.
+///
+/// A new ID has to be made whenever a resource is recreated, so
+/// bind groups with the old one aren't mistaken for its.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ResourceId(u64);
+
+impl ResourceId {
+    pub fn new() -> Self {
+        static NEXT: AtomicU64 = AtomicU64::new(0);
+        ResourceId(NEXT.fetch_add(1, Ordering::Relaxed))
+    }
+}
+
+impl Default for ResourceId {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A bind group handed out by a [`BindGroupAllocator`], which
+/// should be given back with [`BindGroupAllocator::free`].
+pub struct PooledBindGroup {
+    key: BindGroupKey,
+    bind_group: wgpu::BindGroup,
+}
+
+impl PooledBindGroup {
+    pub fn bind_group(&self) -> &wgpu::BindGroup {
+        &self.bind_group
+    }
+}
+
+/// A bind group that was freed during `generation`.
+struct PendingFree {
+    generation: u64,
+    bind_group: PooledBindGroup,
+}
+
+/// Resolves once the GPU has finished the work submitted
+/// before the frame `generation` ended.
+struct FrameFence {
+    generation: u64,
+    done: Pin<Box<dyn Future<Output = ()> + Send>>,
+}
+
+/// Reuses bind groups instead of creating new ones every frame.
+///
+/// Bind groups can't be changed once they're created, so a freed bind
+/// group is only handed out again for the same layout and resources.
+/// Those are told apart by the [`ResourceId`]s they're allocated with,
+/// which change when the resources are recreated.
+///
+/// The GPU may still be using a bind group after it's freed, so it
+/// only goes back into the pool once the work submitted during the
+/// frame it was freed in is done.
+pub struct BindGroupAllocator {
+    /// Bind groups ready to be reused, by layout and entries.
+    free: HashMap<BindGroupKey, Vec<PooledBindGroup>>,
+    pending: Vec<PendingFree>,
+    fences: Vec<FrameFence>,
+    /// Incremented at the end of every frame.
+    generation: u64,
+}
+
+impl BindGroupAllocator {
+    pub fn new() -> Self {
+        BindGroupAllocator {
+            free: HashMap::new(),
+            pending: Vec::new(),
+            fences: Vec::new(),
+            generation: 0,
+        }
+    }
+
+    /// Returns a freed bind group with the same layout and entries,
+    /// or creates one.
+    ///
+    /// `resources` are the IDs of the layout and of everything bound
+    /// in `entries`, which a freed bind group has to have been
+    /// allocated with as well to be handed out.
+    pub fn allocate(
+        &mut self,
+        device: &wgpu::Device,
+        resources: &[ResourceId],
+        layout: &wgpu::BindGroupLayout,
+        entries: &[wgpu::BindGroupEntry],
+    ) -> PooledBindGroup {
+        let key = BindGroupKey::new(resources, entries);
+        if let Some(bind_group) = self.free.get_mut(&key).and_then(Vec::pop) {
+            return bind_group;
+        }
+
+        PooledBindGroup {
+            key,
+            bind_group: device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("Pooled Bind Group"),
+                layout,
+                entries,
+            }),
+        }
+    }
+
+    /// Gives a bind group back to the pool. It can be allocated again
+    /// once the GPU is done with the current frame.
+    pub fn free(&mut self, bind_group: PooledBindGroup) {
+        self.pending.push(PendingFree {
+            generation: self.generation,
+            bind_group,
+        });
+    }
+
+    /// Must be called once per frame, after the frame's work is submitted.
+    ///
+    /// Returns the bind groups freed during frames the GPU has
+    /// finished to the pool.
+    pub fn end_frame(&mut self, device: &wgpu::Device, queue: &wgpu::Queue) {
+        self.fences.push(FrameFence {
+            generation: self.generation,
+            done: Box::pin(queue.on_submitted_work_done()),
+        });
+        self.generation += 1;
+
+        // Gives wgpu a chance to tell us about finished work,
+        // without waiting for it.
+        device.poll(wgpu::Maintain::Poll);
+
+        // Fences are pushed in order, so the last one that's done
+        // is the most recent frame the GPU has caught up with.
+        // The fences are polled every frame, so nothing needs waking up.
+        let mut cx = Context::from_waker(Waker::noop());
+        let mut completed = None;
+        self.fences.retain_mut(|fence| {
+            if fence.done.as_mut().poll(&mut cx).is_ready() {
+                completed = Some(fence.generation);
+                false
+            } else {
+                true
+            }
+        });
+
+        if let Some(completed) = completed {
+            let (ready, pending) = std::mem::take(&mut self.pending)
+                .into_iter()
+                .partition(|freed| freed.generation <= completed);
+            self.pending = pending;
+
+            for freed in ready {
+                let bind_group = freed.bind_group;
+                self.free
+                    .entry(bind_group.key.clone())
+                    .or_default()
+                    .push(bind_group);
+            }
+        }
+    }
+
+    /// Drops every pooled bind group, for when the resources they were
+    /// created with are replaced and they'd never be reused.
+    ///
+    /// Bind groups waiting on the GPU are dropped too. wgpu keeps
+    /// their resources alive until it's done with them.
+    pub fn clear(&mut self) {
+        self.free.clear();
+        self.pending.clear();
+    }
+
+    /// Number of bind groups ready to be reused.
+    #[allow(dead_code)]
+    pub fn free_count(&self) -> usize {
+        self.free.values().map(Vec::len).sum()
+    }
+}
+
+impl Default for BindGroupAllocator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The layout and entries a bind group was created with. The
+/// resources are known by their [`ResourceId`]s, and the parts of
+/// buffers bound at each binding are compared too.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct BindGroupKey {
+    resources: Vec<ResourceId>,
+    entries: Vec<(u32, Vec<BufferRange>)>,
+}
+
+/// The offset and size of a buffer binding.
+type BufferRange = (wgpu::BufferAddress, Option<NonZeroU64>);
+
+impl BindGroupKey {
+    fn new(resources: &[ResourceId], entries: &[wgpu::BindGroupEntry]) -> Self {
+        fn range(binding: &wgpu::BufferBinding) -> BufferRange {
+            (binding.offset, binding.size)
+        }
+
+        let entries = entries
+            .iter()
+            .map(|entry| {
+                let ranges = match &entry.resource {
+                    wgpu::BindingResource::Buffer(binding) => vec![range(binding)],
+                    wgpu::BindingResource::BufferArray(bindings) => {
+                        bindings.iter().map(range).collect()
+                    }
+                    _ => Vec::new(),
+                };
+                (entry.binding, ranges)
+            })
+            .collect();
+
+        BindGroupKey {
+            resources: resources.to_vec(),
+            entries,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::context::test_device;
+
+    fn uniform_layout(device: &wgpu::Device) -> wgpu::BindGroupLayout {
+        device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: None,
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::VERTEX,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+        })
+    }
+
+    fn uniform_buffer(device: &wgpu::Device) -> wgpu::Buffer {
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label: None,
+            size: 256,
+            usage: wgpu::BufferUsages::UNIFORM,
+            mapped_at_creation: false,
+        })
+    }
+
+    #[test]
+    fn recreated_resources_have_other_keys() {
+        let resource = ResourceId::new();
+        let key = BindGroupKey::new(&[resource], &[]);
+        assert_eq!(key, BindGroupKey::new(&[resource], &[]));
+        assert_ne!(key, BindGroupKey::new(&[ResourceId::new()], &[]));
+    }
+
+    #[test]
+    fn reuses_freed_bind_groups_for_the_same_entries() {
+        let (device, queue) = match test_device() {
+            Some(device) => device,
+            None => return eprintln!("skipped, there's no adapter"),
+        };
+        let layout = uniform_layout(&device);
+        let (first, second) = (uniform_buffer(&device), uniform_buffer(&device));
+        let first_ids = [ResourceId::new(), ResourceId::new()];
+        let second_ids = [first_ids[0], ResourceId::new()];
+
+        let mut allocator = BindGroupAllocator::new();
+        let bind_group = allocator.allocate(
+            &device,
+            &first_ids,
+            &layout,
+            &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: first.as_entire_binding(),
+            }],
+        );
+        allocator.free(bind_group);
+        // Still in use by the frame it was freed in.
+        assert_eq!(allocator.free_count(), 0);
+
+        device.poll(wgpu::Maintain::Wait);
+        allocator.end_frame(&device, &queue);
+        device.poll(wgpu::Maintain::Wait);
+        allocator.end_frame(&device, &queue);
+        assert_eq!(allocator.free_count(), 1);
+
+        let _other = allocator.allocate(
+            &device,
+            &second_ids,
+            &layout,
+            &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: second.as_entire_binding(),
+            }],
+        );
+        assert_eq!(allocator.free_count(), 1);
+        let _same = allocator.allocate(
+            &device,
+            &first_ids,
+            &layout,
+            &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: first.as_entire_binding(),
+            }],
+        );
+        assert_eq!(allocator.free_count(), 0);
+    }
+}
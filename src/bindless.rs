@@ -0,0 +1,219 @@
+use std::num::NonZeroU32;
+
+/// A fixed number of texture slots bound all at once, one bind group
+/// for every texture, so draws can pick theirs by index instead of
+/// switching bind groups between them.
+///
+/// The textures are bound as an array at binding 0, with a sampler
+/// they share at binding 1. Shaders index the array by a per-instance
+/// texture ID, which is the slot [`register`](Self::register) handed
+/// out for the texture.
+///
+/// Slots that haven't had a texture registered are left unbound,
+/// which needs `PARTIALLY_BOUND_BINDING_ARRAY`, and the ID differs
+/// between instances in the same draw, which needs non-uniform
+/// indexing. See [`REQUIRED_FEATURES`](Self::REQUIRED_FEATURES).
+///
+/// The WGSL frontend of naga 0.7, which wgpu 0.11 uses, can't parse
+/// `binding_array` yet, so shaders using the array have to come to
+/// wgpu as SPIR-V for now, like the one
+/// [`TexturedQuadRenderer::bindless`](crate::textured_quad::TexturedQuadRenderer::bindless)
+/// draws with.
+pub struct BindlessTextureArray {
+    max_textures: u32,
+    views: Vec<wgpu::TextureView>,
+    sampler: wgpu::Sampler,
+    bind_group_layout: wgpu::BindGroupLayout,
+    /// Only made once there's a texture to bind, since arrays can't be
+    /// bound empty.
+    bind_group: Option<wgpu::BindGroup>,
+    /// Whether textures were registered since the bind group was made.
+    dirty: bool,
+}
+
+impl BindlessTextureArray {
+    /// Features the device needs for the array, and for handing wgpu
+    /// the SPIR-V of shaders using it, which
+    /// [`GpuContext`](crate::context::GpuContext) asks for when the
+    /// adapter has them.
+    pub const REQUIRED_FEATURES: wgpu::Features = wgpu::Features::TEXTURE_BINDING_ARRAY
+        .union(wgpu::Features::PARTIALLY_BOUND_BINDING_ARRAY)
+        .union(wgpu::Features::SAMPLED_TEXTURE_AND_STORAGE_BUFFER_ARRAY_NON_UNIFORM_INDEXING)
+        .union(wgpu::Features::SPIRV_SHADER_PASSTHROUGH);
+
+    /// Whether the device has every one of the
+    /// [`REQUIRED_FEATURES`](Self::REQUIRED_FEATURES).
+    pub fn is_supported(device: &wgpu::Device) -> bool {
+        device.features().contains(Self::REQUIRED_FEATURES)
+    }
+
+    /// Makes room for `max_textures` slots. They all count towards
+    /// the device's limit on sampled textures per shader stage, so
+    /// there are only as many as the limit allows.
+    ///
+    /// The device has to be [supported](Self::is_supported).
+    pub fn new(device: &wgpu::Device, max_textures: u32) -> Self {
+        let limit = device.limits().max_sampled_textures_per_shader_stage;
+        let max_textures = if max_textures > limit {
+            log::warn!(
+                "bindless texture array can only have {} of the {} slots asked for",
+                limit,
+                max_textures
+            );
+            limit
+        } else {
+            max_textures
+        };
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Bindless Texture Bind Group Layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    },
+                    count: NonZeroU32::new(max_textures),
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler {
+                        filtering: true,
+                        comparison: false,
+                    },
+                    count: None,
+                },
+            ],
+        });
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Bindless Texture Sampler"),
+            address_mode_u: wgpu::AddressMode::Repeat,
+            address_mode_v: wgpu::AddressMode::Repeat,
+            address_mode_w: wgpu::AddressMode::Repeat,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+
+        BindlessTextureArray {
+            max_textures,
+            views: Vec::with_capacity(max_textures as usize),
+            sampler,
+            bind_group_layout,
+            bind_group: None,
+            dirty: false,
+        }
+    }
+
+    /// Puts `view` in the next free slot, and returns the slot's index
+    /// for shaders to find it by, or `None` when every slot is taken.
+    /// The bind group doesn't have it until the next
+    /// [`update`](Self::update).
+    pub fn register(&mut self, view: wgpu::TextureView) -> Option<u32> {
+        if self.views.len() >= self.max_textures as usize {
+            return None;
+        }
+        self.views.push(view);
+        self.dirty = true;
+        Some(self.views.len() as u32 - 1)
+    }
+
+    /// How many textures have been registered.
+    pub fn len(&self) -> u32 {
+        self.views.len() as u32
+    }
+
+    #[allow(dead_code)]
+    pub fn is_empty(&self) -> bool {
+        self.views.is_empty()
+    }
+
+    /// How many slots there are.
+    pub fn max_textures(&self) -> u32 {
+        self.max_textures
+    }
+
+    /// Remakes the bind group with everything registered since it
+    /// was last made. Should be called before drawing with it.
+    pub fn update(&mut self, device: &wgpu::Device) {
+        if !self.dirty {
+            return;
+        }
+
+        let views = self.views.iter().collect::<Vec<_>>();
+        self.bind_group = Some(device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Bindless Texture Bind Group"),
+            layout: &self.bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureViewArray(&views),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&self.sampler),
+                },
+            ],
+        }));
+        self.dirty = false;
+    }
+
+    pub fn bind_group_layout(&self) -> &wgpu::BindGroupLayout {
+        &self.bind_group_layout
+    }
+
+    /// Nothing until a texture has been registered and the array
+    /// [updated](Self::update).
+    pub fn bind_group(&self) -> Option<&wgpu::BindGroup> {
+        self.bind_group.as_ref()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::context::test_device_with;
+
+    #[test]
+    fn registers_textures_until_full() {
+        let (device, _queue) = match test_device_with(BindlessTextureArray::REQUIRED_FEATURES) {
+            Some(device) => device,
+            None => return eprintln!("skipped, there's no adapter with bindless textures"),
+        };
+        let mut array = BindlessTextureArray::new(&device, 100);
+        if array.max_textures() < 100 {
+            return eprintln!("skipped, the adapter can't sample 100 textures");
+        }
+
+        let view = || {
+            device
+                .create_texture(&wgpu::TextureDescriptor {
+                    label: None,
+                    size: wgpu::Extent3d {
+                        width: 1,
+                        height: 1,
+                        depth_or_array_layers: 1,
+                    },
+                    mip_level_count: 1,
+                    sample_count: 1,
+                    dimension: wgpu::TextureDimension::D2,
+                    format: wgpu::TextureFormat::Rgba8UnormSrgb,
+                    usage: wgpu::TextureUsages::TEXTURE_BINDING,
+                })
+                .create_view(&Default::default())
+        };
+        for id in 0..100 {
+            assert_eq!(array.register(view()), Some(id));
+        }
+        assert_eq!(array.register(view()), None);
+
+        array.update(&device);
+        assert_eq!(array.len(), 100);
+        assert!(array.bind_group().is_some());
+    }
+}
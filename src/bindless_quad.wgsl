@@ -0,0 +1,52 @@
+// Screen space quads, each sampling the texture of the bindless
+// texture array its instance picks by ID.
+//
+// The WGSL frontend of naga 0.7 can't parse `binding_array`, so this
+// is written for a newer naga and compiled to SPIR-V by build.rs,
+// which wgpu is handed as it is.
+
+// Must match `TexturedQuadRenderer::BINDLESS_TEXTURES` in textured_quad.rs.
+const SHADER_TEXTURES: u32 = 128u;
+
+@group(0) @binding(0)
+var textures: binding_array<texture_2d<f32>, SHADER_TEXTURES>;
+@group(0) @binding(1)
+var texture_sampler: sampler;
+
+// Must match `TexturedQuad` in textured_quad.rs.
+struct QuadInput {
+    // Top left corner and size, as fractions of the screen
+    // from its top left.
+    @location(0) position: vec2<f32>,
+    @location(1) size: vec2<f32>,
+    @location(2) texture_id: u32,
+};
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) tex_coords: vec2<f32>,
+    // The same for every fragment of a quad, but not between quads.
+    @location(1) @interpolate(flat) texture_id: u32,
+};
+
+// Drawn as a triangle strip of four vertices per instance.
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32, quad: QuadInput) -> VertexOutput {
+    let corner = vec2<f32>(f32(index & 1u), f32(index >> 1u));
+    let position = quad.position + corner * quad.size;
+
+    var out: VertexOutput;
+    // Y points down the screen, but up in clip space.
+    out.clip_position = vec4<f32>(position.x * 2.0 - 1.0, 1.0 - position.y * 2.0, 0.0, 1.0);
+    out.tex_coords = corner;
+    out.texture_id = quad.texture_id;
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    // Neighbouring quads can have different IDs, so the index
+    // isn't uniform.
+    let id = min(in.texture_id, SHADER_TEXTURES - 1u);
+    return textureSample(textures[id], texture_sampler, in.tex_coords);
+}
@@ -0,0 +1,457 @@
+use wgpu::util::DeviceExt;
+
+use crate::{
+    fullscreen_triangle::FullscreenTriangle,
+    pipeline::RenderPipelineBuilder,
+    uniform::{dynamic_offset_stride, UniformBinding},
+};
+
+/// Blur steps. The first half shrink the image a mip level at
+/// a time, and the second half grow it back.
+pub const BLUR_ITERATIONS: usize = 4;
+
+/// The blurred image is half the size of the scene at mip 0,
+/// and shrinks by half for every downsampling step.
+const MIP_LEVEL_COUNT: u32 = BLUR_ITERATIONS as u32 / 2 + 1;
+
+/// Bright pixels can add up past 1, so they're kept as floats.
+const FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct BloomSettings {
+    threshold: f32,
+    intensity: f32,
+    _padding: [f32; 2],
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct BlurStep {
+    offset: f32,
+    _padding: [f32; 3],
+}
+
+/// Makes bright parts of the scene glow, by blurring them and
+/// adding them back on top.
+///
+/// The bright pixels are blurred with a dual Kawase blur, which
+/// downsamples them through the mip levels of a texture and then
+/// upsamples them back, so only a few samples are taken per pixel
+/// however wide the glow ends up.
+pub struct BloomPass {
+    settings: BloomSettings,
+    settings_binding: UniformBinding<BloomSettings>,
+    #[allow(dead_code)]
+    blur_buffer: wgpu::Buffer,
+    /// How far apart the blur steps are in `blur_buffer`, which is
+    /// bound at a dynamic offset for each.
+    blur_step_stride: wgpu::BufferAddress,
+    blur_bind_group: wgpu::BindGroup,
+    sampler: wgpu::Sampler,
+    texture_layout: wgpu::BindGroupLayout,
+    /// Views of each mip level of the blur texture, and bind
+    /// groups sampling them.
+    mip_views: Vec<wgpu::TextureView>,
+    mip_bind_groups: Vec<wgpu::BindGroup>,
+    extract: FullscreenTriangle,
+    downsample: FullscreenTriangle,
+    upsample: FullscreenTriangle,
+    composite: FullscreenTriangle,
+}
+
+impl BloomPass {
+    /// Pixels brighter than `threshold` glow, and the glow is
+    /// scaled by `intensity` when it's added to the scene, which
+    /// is in `scene_format`.
+    pub fn new(
+        device: &wgpu::Device,
+        config: &wgpu::SurfaceConfiguration,
+        scene_format: wgpu::TextureFormat,
+        threshold: f32,
+        intensity: f32,
+    ) -> Self {
+        let settings = BloomSettings {
+            threshold,
+            intensity,
+            _padding: [0.0; 2],
+        };
+        let settings_binding = UniformBinding::new(
+            device,
+            "Bloom Settings",
+            wgpu::ShaderStages::FRAGMENT,
+            &settings,
+        );
+
+        // Spreads the samples further apart as the image gets smaller.
+        let offsets = default_kernel_offsets();
+        let blur_step_stride = dynamic_offset_stride(device, std::mem::size_of::<BlurStep>());
+        let blur_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Bloom Blur Buffer"),
+            contents: &blur_step_bytes(&offsets, blur_step_stride),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        let blur_size = wgpu::BufferSize::new(std::mem::size_of::<BlurStep>() as u64);
+        let blur_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Bloom Blur Bind Group Layout"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    // Every step reads its own part of the buffer.
+                    has_dynamic_offset: true,
+                    min_binding_size: blur_size,
+                },
+                count: None,
+            }],
+        });
+        let blur_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Bloom Blur Bind Group"),
+            layout: &blur_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                    buffer: &blur_buffer,
+                    offset: 0,
+                    size: blur_size,
+                }),
+            }],
+        });
+
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Bloom Sampler"),
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            ..Default::default()
+        });
+        let texture_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Bloom Texture Bind Group Layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler {
+                        filtering: true,
+                        comparison: false,
+                    },
+                    count: None,
+                },
+            ],
+        });
+
+        let (mip_views, mip_bind_groups) = create_mips(
+            device,
+            &texture_layout,
+            &sampler,
+            config.width,
+            config.height,
+        );
+
+        let vertex_shader = FullscreenTriangle::vertex_shader(device);
+        let bloom_shader = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+            label: Some("Bloom Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("bloom.wgsl").into()),
+        });
+        let blur_shader = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+            label: Some("Bloom Blur Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("bloom_blur.wgsl").into()),
+        });
+
+        let settings_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Bloom Pipeline Layout"),
+            bind_group_layouts: &[&texture_layout, &settings_binding.bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let blur_pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Bloom Blur Pipeline Layout"),
+            bind_group_layouts: &[&texture_layout, &blur_layout],
+            push_constant_ranges: &[],
+        });
+
+        let pipeline = |label, layout, shader, entry, format, blend| {
+            let pipeline = RenderPipelineBuilder::new()
+                .label(label)
+                .vertex_shader(&vertex_shader, "main")
+                .fragment_shader(
+                    shader,
+                    entry,
+                    &[wgpu::ColorTargetState {
+                        format,
+                        blend: Some(blend),
+                        write_mask: wgpu::ColorWrites::ALL,
+                    }],
+                )
+                .cull_mode(None)
+                .build(device, layout)
+                .expect("failed to build bloom pipeline");
+            FullscreenTriangle::new(pipeline)
+        };
+        let replace = wgpu::BlendState::REPLACE;
+        let additive = wgpu::BlendState {
+            color: wgpu::BlendComponent {
+                src_factor: wgpu::BlendFactor::One,
+                dst_factor: wgpu::BlendFactor::One,
+                operation: wgpu::BlendOperation::Add,
+            },
+            alpha: wgpu::BlendComponent::OVER,
+        };
+
+        BloomPass {
+            extract: pipeline(
+                "Bloom Extract Pipeline",
+                &settings_layout,
+                &bloom_shader,
+                "extract",
+                FORMAT,
+                replace,
+            ),
+            downsample: pipeline(
+                "Bloom Downsample Pipeline",
+                &blur_pipeline_layout,
+                &blur_shader,
+                "downsample",
+                FORMAT,
+                replace,
+            ),
+            upsample: pipeline(
+                "Bloom Upsample Pipeline",
+                &blur_pipeline_layout,
+                &blur_shader,
+                "upsample",
+                FORMAT,
+                replace,
+            ),
+            // Drawn onto the scene, so it has the scene's format.
+            composite: pipeline(
+                "Bloom Composite Pipeline",
+                &settings_layout,
+                &bloom_shader,
+                "composite",
+                scene_format,
+                additive,
+            ),
+            settings,
+            settings_binding,
+            blur_buffer,
+            blur_step_stride,
+            blur_bind_group,
+            sampler,
+            texture_layout,
+            mip_views,
+            mip_bind_groups,
+        }
+    }
+
+    pub fn threshold(&self) -> f32 {
+        self.settings.threshold
+    }
+
+    pub fn set_threshold(&mut self, queue: &wgpu::Queue, threshold: f32) {
+        self.settings.threshold = threshold;
+        self.settings_binding.update(queue, &self.settings);
+    }
+
+    #[allow(dead_code)]
+    pub fn intensity(&self) -> f32 {
+        self.settings.intensity
+    }
+
+    #[allow(dead_code)]
+    pub fn set_intensity(&mut self, queue: &wgpu::Queue, intensity: f32) {
+        self.settings.intensity = intensity;
+        self.settings_binding.update(queue, &self.settings);
+    }
+
+    /// How far apart the samples of each blur step are, in texels of
+    /// the image it reads from. Wider offsets spread the glow further,
+    /// but too wide and it breaks up into rings.
+    #[allow(dead_code)]
+    pub fn set_kernel_offsets(&self, queue: &wgpu::Queue, offsets: [f32; BLUR_ITERATIONS]) {
+        queue.write_buffer(
+            &self.blur_buffer,
+            0,
+            &blur_step_bytes(&offsets, self.blur_step_stride),
+        );
+    }
+
+    /// Recreates the blur texture to match the new surface size.
+    pub fn resize(&mut self, device: &wgpu::Device, width: u32, height: u32) {
+        let (mip_views, mip_bind_groups) =
+            create_mips(device, &self.texture_layout, &self.sampler, width, height);
+        self.mip_views = mip_views;
+        self.mip_bind_groups = mip_bind_groups;
+    }
+
+    /// Blurs the bright parts of `scene` and adds them back onto it.
+    ///
+    /// `scene` is both read and drawn into, so it needs to be a
+    /// texture that can be sampled as well as rendered to.
+    pub fn run(
+        &self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        scene: &wgpu::TextureView,
+    ) {
+        // The scene's texture is recreated on resize, so its
+        // bind group is made as it's needed.
+        let scene_bind_group =
+            create_texture_bind_group(device, &self.texture_layout, &self.sampler, scene);
+
+        {
+            let mut render_pass =
+                begin_pass(encoder, "Bloom Extract Pass", &self.mip_views[0], true);
+            render_pass.set_bind_group(0, &scene_bind_group, &[]);
+            render_pass.set_bind_group(1, &self.settings_binding.bind_group, &[]);
+            self.extract.draw(&mut render_pass);
+        }
+
+        let half = BLUR_ITERATIONS / 2;
+        for step in 0..BLUR_ITERATIONS {
+            // Down into the smaller mips, then back up again.
+            let (source, target, triangle) = if step < half {
+                (step, step + 1, &self.downsample)
+            } else {
+                let source = BLUR_ITERATIONS - step;
+                (source, source - 1, &self.upsample)
+            };
+
+            let mut render_pass =
+                begin_pass(encoder, "Bloom Blur Pass", &self.mip_views[target], true);
+            render_pass.set_bind_group(0, &self.mip_bind_groups[source], &[]);
+            let offset = (step as wgpu::BufferAddress * self.blur_step_stride) as u32;
+            render_pass.set_bind_group(1, &self.blur_bind_group, &[offset]);
+            triangle.draw(&mut render_pass);
+        }
+
+        let mut render_pass = begin_pass(encoder, "Bloom Composite Pass", scene, false);
+        render_pass.set_bind_group(0, &self.mip_bind_groups[0], &[]);
+        render_pass.set_bind_group(1, &self.settings_binding.bind_group, &[]);
+        self.composite.draw(&mut render_pass);
+    }
+}
+
+fn default_kernel_offsets() -> [f32; BLUR_ITERATIONS] {
+    let mut offsets = [0.0; BLUR_ITERATIONS];
+    for (i, offset) in offsets.iter_mut().enumerate() {
+        *offset = 1.0 + i as f32 * 0.5;
+    }
+    offsets
+}
+
+/// The blur steps laid out `stride` bytes apart, for the dynamic offsets.
+fn blur_step_bytes(offsets: &[f32; BLUR_ITERATIONS], stride: wgpu::BufferAddress) -> Vec<u8> {
+    let mut bytes = vec![0; stride as usize * BLUR_ITERATIONS];
+    for (i, offset) in offsets.iter().enumerate() {
+        let step = BlurStep {
+            offset: *offset,
+            _padding: [0.0; 3],
+        };
+        let start = i * stride as usize;
+        bytes[start..start + std::mem::size_of::<BlurStep>()]
+            .copy_from_slice(bytemuck::bytes_of(&step));
+    }
+    bytes
+}
+
+fn begin_pass<'a>(
+    encoder: &'a mut wgpu::CommandEncoder,
+    label: &'a str,
+    view: &'a wgpu::TextureView,
+    clear: bool,
+) -> wgpu::RenderPass<'a> {
+    encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+        label: Some(label),
+        color_attachments: &[wgpu::RenderPassColorAttachment {
+            view,
+            resolve_target: None,
+            ops: wgpu::Operations {
+                load: if clear {
+                    wgpu::LoadOp::Clear(wgpu::Color::BLACK)
+                } else {
+                    wgpu::LoadOp::Load
+                },
+                store: true,
+            },
+        }],
+        depth_stencil_attachment: None,
+    })
+}
+
+/// Creates the mipped blur texture, returning a view and
+/// a bind group for each of its mip levels.
+fn create_mips(
+    device: &wgpu::Device,
+    layout: &wgpu::BindGroupLayout,
+    sampler: &wgpu::Sampler,
+    width: u32,
+    height: u32,
+) -> (Vec<wgpu::TextureView>, Vec<wgpu::BindGroup>) {
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("Bloom Texture"),
+        size: wgpu::Extent3d {
+            width: (width / 2).max(1),
+            height: (height / 2).max(1),
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: MIP_LEVEL_COUNT,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: FORMAT,
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+    });
+
+    // The views keep the texture alive after it's dropped here.
+    let views = (0..MIP_LEVEL_COUNT)
+        .map(|level| {
+            texture.create_view(&wgpu::TextureViewDescriptor {
+                label: Some("Bloom Mip View"),
+                base_mip_level: level,
+                mip_level_count: std::num::NonZeroU32::new(1),
+                ..Default::default()
+            })
+        })
+        .collect::<Vec<_>>();
+    let bind_groups = views
+        .iter()
+        .map(|view| create_texture_bind_group(device, layout, sampler, view))
+        .collect();
+
+    (views, bind_groups)
+}
+
+fn create_texture_bind_group(
+    device: &wgpu::Device,
+    layout: &wgpu::BindGroupLayout,
+    sampler: &wgpu::Sampler,
+    view: &wgpu::TextureView,
+) -> wgpu::BindGroup {
+    device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some("Bloom Texture Bind Group"),
+        layout,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: wgpu::BindingResource::Sampler(sampler),
+            },
+        ],
+    })
+}
@@ -0,0 +1,35 @@
+// Fragment shaders for the start and end of the bloom pass, drawn
+// with the fullscreen triangle from `fullscreen.wgsl`.
+
+[[block]]
+struct BloomSettings {
+    threshold: f32;
+    intensity: f32;
+};
+
+[[group(0), binding(0)]]
+var t_source: texture_2d<f32>;
+[[group(0), binding(1)]]
+var s_source: sampler;
+
+[[group(1), binding(0)]]
+var<uniform> settings: BloomSettings;
+
+// Keeps the part of each pixel brighter than the threshold.
+[[stage(fragment)]]
+fn extract([[location(0)]] tex_coords: vec2<f32>) -> [[location(0)]] vec4<f32> {
+    let color = textureSample(t_source, s_source, tex_coords).rgb;
+    let brightness = max(color.r, max(color.g, color.b));
+    // Scaling the color keeps its hue, where subtracting
+    // the threshold from every channel wouldn't.
+    let contribution = max(brightness - settings.threshold, 0.0) / max(brightness, 0.0001);
+    return vec4<f32>(color * contribution, 1.0);
+}
+
+// Adds the blurred bright pixels onto the scene, which
+// the pipeline blends additively.
+[[stage(fragment)]]
+fn composite([[location(0)]] tex_coords: vec2<f32>) -> [[location(0)]] vec4<f32> {
+    let bloom = textureSample(t_source, s_source, tex_coords).rgb;
+    return vec4<f32>(bloom * settings.intensity, 1.0);
+}
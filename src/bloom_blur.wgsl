@@ -0,0 +1,48 @@
+// Dual Kawase blur. Each step halves or doubles the size of the
+// image, and samples around each pixel `offset` texels apart.
+
+[[block]]
+struct BlurStep {
+    offset: f32;
+};
+
+[[group(0), binding(0)]]
+var t_source: texture_2d<f32>;
+[[group(0), binding(1)]]
+var s_source: sampler;
+
+[[group(1), binding(0)]]
+var<uniform> blur: BlurStep;
+
+fn source_texel() -> vec2<f32> {
+    let size = textureDimensions(t_source);
+    return 1.0 / vec2<f32>(f32(size.x), f32(size.y));
+}
+
+[[stage(fragment)]]
+fn downsample([[location(0)]] tex_coords: vec2<f32>) -> [[location(0)]] vec4<f32> {
+    let d = source_texel() * blur.offset;
+
+    var sum = textureSample(t_source, s_source, tex_coords).rgb * 4.0;
+    sum = sum + textureSample(t_source, s_source, tex_coords + vec2<f32>(-d.x, -d.y)).rgb;
+    sum = sum + textureSample(t_source, s_source, tex_coords + vec2<f32>(d.x, -d.y)).rgb;
+    sum = sum + textureSample(t_source, s_source, tex_coords + vec2<f32>(-d.x, d.y)).rgb;
+    sum = sum + textureSample(t_source, s_source, tex_coords + vec2<f32>(d.x, d.y)).rgb;
+    return vec4<f32>(sum / 8.0, 1.0);
+}
+
+[[stage(fragment)]]
+fn upsample([[location(0)]] tex_coords: vec2<f32>) -> [[location(0)]] vec4<f32> {
+    let d = source_texel() * blur.offset;
+
+    // The edges of a diamond, with the diagonals counting double.
+    var sum = textureSample(t_source, s_source, tex_coords + vec2<f32>(-d.x * 2.0, 0.0)).rgb;
+    sum = sum + textureSample(t_source, s_source, tex_coords + vec2<f32>(d.x * 2.0, 0.0)).rgb;
+    sum = sum + textureSample(t_source, s_source, tex_coords + vec2<f32>(0.0, -d.y * 2.0)).rgb;
+    sum = sum + textureSample(t_source, s_source, tex_coords + vec2<f32>(0.0, d.y * 2.0)).rgb;
+    sum = sum + textureSample(t_source, s_source, tex_coords + vec2<f32>(-d.x, -d.y)).rgb * 2.0;
+    sum = sum + textureSample(t_source, s_source, tex_coords + vec2<f32>(d.x, -d.y)).rgb * 2.0;
+    sum = sum + textureSample(t_source, s_source, tex_coords + vec2<f32>(-d.x, d.y)).rgb * 2.0;
+    sum = sum + textureSample(t_source, s_source, tex_coords + vec2<f32>(d.x, d.y)).rgb * 2.0;
+    return vec4<f32>(sum / 12.0, 1.0);
+}
@@ -0,0 +1,292 @@
+use cgmath::{Matrix4, Point3, Transform as _};
+
+use crate::{transform::Transform, vertex::Vertex};
+
+/// Axis aligned bounding box.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Aabb {
+    pub min: [f32; 3],
+    pub max: [f32; 3],
+}
+
+impl Aabb {
+    /// The smallest box holding every point. Empty when there are
+    /// no points, with `min` above `max`.
+    pub fn from_points<I: IntoIterator<Item = [f32; 3]>>(points: I) -> Self {
+        let mut aabb = Aabb {
+            min: [f32::INFINITY; 3],
+            max: [f32::NEG_INFINITY; 3],
+        };
+        for point in points {
+            for (axis, value) in point.iter().enumerate() {
+                aabb.min[axis] = aabb.min[axis].min(*value);
+                aabb.max[axis] = aabb.max[axis].max(*value);
+            }
+        }
+        aabb
+    }
+
+    /// The smallest box holding the positions of `vertices`.
+    pub fn from_vertices(vertices: &[Vertex]) -> Self {
+        Aabb::from_points(vertices.iter().map(|vertex| vertex.position))
+    }
+
+    pub fn center(&self) -> [f32; 3] {
+        [
+            (self.min[0] + self.max[0]) * 0.5,
+            (self.min[1] + self.max[1]) * 0.5,
+            (self.min[2] + self.max[2]) * 0.5,
+        ]
+    }
+
+    /// Radius of the sphere around the box, through its corners.
+    pub fn radius(&self) -> f32 {
+        let size = [
+            self.max[0] - self.min[0],
+            self.max[1] - self.min[1],
+            self.max[2] - self.min[2],
+        ];
+        (size[0] * size[0] + size[1] * size[1] + size[2] * size[2]).sqrt() * 0.5
+    }
+
+    pub fn corners(&self) -> [[f32; 3]; 8] {
+        let (min, max) = (self.min, self.max);
+        [
+            [min[0], min[1], min[2]],
+            [max[0], min[1], min[2]],
+            [min[0], max[1], min[2]],
+            [max[0], max[1], min[2]],
+            [min[0], min[1], max[2]],
+            [max[0], min[1], max[2]],
+            [min[0], max[1], max[2]],
+            [max[0], max[1], max[2]],
+        ]
+    }
+
+    /// The box around this one after it's moved by `transform`. It's
+    /// rebuilt from the moved corners, so a rotated box grows to fit.
+    pub fn transformed(&self, transform: &Transform) -> Aabb {
+        let matrix = Matrix4::from(transform.to_matrix());
+        Aabb::from_points(self.corners().iter().map(|corner| {
+            let point = matrix.transform_point(Point3::from(*corner));
+            [point.x, point.y, point.z]
+        }))
+    }
+
+    /// How far along `ray` it first touches the box, in multiples
+    /// of its direction, or `None` when it misses. A ray starting in
+    /// the box hits it at 0.
+    ///
+    /// Rays running along a face, without going in, miss.
+    pub fn intersect_ray(&self, ray: &Ray) -> Option<f32> {
+        let mut near = 0.0_f32;
+        let mut far = f32::INFINITY;
+        for axis in 0..3 {
+            let origin = ray.origin[axis];
+            let direction = ray.direction[axis];
+            if direction == 0.0 {
+                // Parallel to this axis' faces, so it stays between
+                // them or never gets there.
+                if origin <= self.min[axis] || origin >= self.max[axis] {
+                    return None;
+                }
+                continue;
+            }
+
+            let a = (self.min[axis] - origin) / direction;
+            let b = (self.max[axis] - origin) / direction;
+            near = near.max(a.min(b));
+            far = far.min(a.max(b));
+            if near > far {
+                return None;
+            }
+        }
+        Some(near)
+    }
+}
+
+/// A half-line from `origin` going in `direction`, for finding what
+/// it runs into.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Ray {
+    pub origin: [f32; 3],
+    /// A unit vector, so distances along the ray are in world units.
+    pub direction: [f32; 3],
+}
+
+impl Ray {
+    /// The ray from the camera through `mouse_pos`, in pixels from the
+    /// top left of a `viewport` wide and high, given the inverse of
+    /// the camera's view projection.
+    pub fn from_screen(
+        mouse_pos: (f32, f32),
+        viewport: (f32, f32),
+        view_proj_inv: [[f32; 4]; 4],
+    ) -> Ray {
+        use cgmath::InnerSpace;
+
+        // Window coordinates have Y pointing down, unlike clip space.
+        let x = mouse_pos.0 / viewport.0 * 2.0 - 1.0;
+        let y = 1.0 - mouse_pos.1 / viewport.1 * 2.0;
+        let matrix = Matrix4::from(view_proj_inv);
+        // Depth goes from 0 at the near plane to 1 at the far one.
+        let near = matrix.transform_point(Point3::new(x, y, 0.0));
+        let far = matrix.transform_point(Point3::new(x, y, 1.0));
+        let direction = (far - near).normalize();
+
+        Ray {
+            origin: near.into(),
+            direction: direction.into(),
+        }
+    }
+}
+
+/// A plane `normal . p + distance = 0`, with the normal
+/// pointing towards the inside of the frustum.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Plane {
+    pub normal: [f32; 3],
+    pub distance: f32,
+}
+
+impl Plane {
+    /// Scales the plane so its normal is a unit vector, making
+    /// distances to it come out in world units.
+    fn normalized(self) -> Plane {
+        let [x, y, z] = self.normal;
+        let length = (x * x + y * y + z * z).sqrt();
+        Plane {
+            normal: [x / length, y / length, z / length],
+            distance: self.distance / length,
+        }
+    }
+
+    /// How far `point` is in front of the plane, negative behind it.
+    pub fn signed_distance(&self, point: [f32; 3]) -> f32 {
+        self.normal[0] * point[0]
+            + self.normal[1] * point[1]
+            + self.normal[2] * point[2]
+            + self.distance
+    }
+}
+
+/// The part of the world a camera can see, bounded by six planes.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Frustum {
+    /// Left, right, bottom, top, near and far.
+    pub planes: [Plane; 6],
+}
+
+impl Frustum {
+    /// Takes the planes out of a column major view projection
+    /// matrix, which maps depth into the 0 to 1 range wgpu uses.
+    pub fn from_view_proj(view_proj: [[f32; 4]; 4]) -> Frustum {
+        let row = |i: usize| {
+            [
+                view_proj[0][i],
+                view_proj[1][i],
+                view_proj[2][i],
+                view_proj[3][i],
+            ]
+        };
+        let (x, y, z, w) = (row(0), row(1), row(2), row(3));
+        let plane = |a: [f32; 4], b: [f32; 4], sign: f32| {
+            Plane {
+                normal: [a[0] + b[0] * sign, a[1] + b[1] * sign, a[2] + b[2] * sign],
+                distance: a[3] + b[3] * sign,
+            }
+            .normalized()
+        };
+
+        Frustum {
+            planes: [
+                plane(w, x, 1.0),
+                plane(w, x, -1.0),
+                plane(w, y, 1.0),
+                plane(w, y, -1.0),
+                // Depth starts at 0 rather than -w, so the near
+                // plane is just the z row.
+                plane(z, z, 0.0),
+                plane(w, z, -1.0),
+            ],
+        }
+    }
+
+    /// Whether any of `aabb` could be seen once it's moved by
+    /// `transform`. Boxes near the corners of the frustum can pass
+    /// without being seen, but a box that's seen never fails.
+    pub fn intersects_aabb(&self, aabb: &Aabb, transform: &Transform) -> bool {
+        let aabb = aabb.transformed(transform);
+        self.planes.iter().all(|plane| {
+            // The corner furthest along the normal is the last
+            // to go behind the plane.
+            let mut corner = aabb.min;
+            for (axis, value) in corner.iter_mut().enumerate() {
+                if plane.normal[axis] >= 0.0 {
+                    *value = aabb.max[axis];
+                }
+            }
+            plane.signed_distance(corner) >= 0.0
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const UNIT_BOX: Aabb = Aabb {
+        min: [-1.0; 3],
+        max: [1.0; 3],
+    };
+
+    #[test]
+    fn ray_hits_the_near_face() {
+        let ray = Ray {
+            origin: [0.0, 0.0, -5.0],
+            direction: [0.0, 0.0, 1.0],
+        };
+        assert_eq!(UNIT_BOX.intersect_ray(&ray), Some(4.0));
+    }
+
+    #[test]
+    fn ray_pointing_away_misses() {
+        let ray = Ray {
+            origin: [0.0, 0.0, -5.0],
+            direction: [0.0, 0.0, -1.0],
+        };
+        assert_eq!(UNIT_BOX.intersect_ray(&ray), None);
+    }
+
+    #[test]
+    fn parallel_ray_beside_the_box_misses() {
+        let ray = Ray {
+            origin: [0.0, 2.0, -5.0],
+            direction: [0.0, 0.0, 1.0],
+        };
+        assert_eq!(UNIT_BOX.intersect_ray(&ray), None);
+        // Along a face isn't inside it either.
+        let ray = Ray {
+            origin: [0.0, 1.0, -5.0],
+            ..ray
+        };
+        assert_eq!(UNIT_BOX.intersect_ray(&ray), None);
+    }
+
+    #[test]
+    fn ray_from_inside_hits_at_its_origin() {
+        let ray = Ray {
+            origin: [0.5, 0.0, 0.0],
+            direction: [1.0, 0.0, 0.0],
+        };
+        assert_eq!(UNIT_BOX.intersect_ray(&ray), Some(0.0));
+    }
+
+    #[test]
+    fn screen_center_ray_points_into_the_screen() {
+        let identity = Matrix4::<f32>::from_scale(1.0).into();
+        let ray = Ray::from_screen((400.0, 300.0), (800.0, 600.0), identity);
+        assert_eq!(ray.origin, [0.0, 0.0, 0.0]);
+        assert_eq!(ray.direction, [0.0, 0.0, 1.0]);
+    }
+}
@@ -0,0 +1,392 @@
+use winit::event::{MouseButton, VirtualKeyCode};
+
+use crate::{input::InputState, uniform::UniformBinding};
+
+// cgmath is built for OpenGL's coordinate system, where the
+// normalized device coordinates have a depth range of -1.0 to 1.0.
+// wgpu uses Metal and DirectX's range of 0.0 to 1.0, so this matrix
+// scales and translates the depth.
+#[rustfmt::skip]
+pub const OPENGL_TO_WGPU_MATRIX: cgmath::Matrix4<f32> = cgmath::Matrix4::new(
+    1.0, 0.0, 0.0, 0.0,
+    0.0, 1.0, 0.0, 0.0,
+    0.0, 0.0, 0.5, 0.0,
+    0.0, 0.0, 0.5, 1.0,
+);
+
+/// How the camera maps the view onto the screen.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CameraProjection {
+    /// Things further away appear smaller.
+    Perspective {
+        /// Vertical field of view in degrees.
+        fov: f32,
+        near: f32,
+        far: f32,
+    },
+    /// Things appear the same size regardless of distance.
+    /// The bounds are in world units, relative to the camera.
+    Orthographic {
+        left: f32,
+        right: f32,
+        bottom: f32,
+        top: f32,
+        near: f32,
+        far: f32,
+    },
+}
+
+pub struct Camera {
+    pub eye: cgmath::Point3<f32>,
+    pub target: cgmath::Point3<f32>,
+    pub up: cgmath::Vector3<f32>,
+    /// Width of the viewport divided by its height. Only
+    /// used by the perspective projection.
+    pub aspect: f32,
+    pub projection: CameraProjection,
+    /// Offset of the whole projection, in normalized device
+    /// coordinates. Nudged by a fraction of a pixel every frame for
+    /// temporal anti-aliasing, and zero otherwise.
+    pub jitter: [f32; 2],
+}
+
+impl Camera {
+    /// The view matrix moves the world to be at the position
+    /// and rotation of the camera. It's essentially an inverse
+    /// of whatever the transform matrix of the camera would be.
+    pub fn build_view_matrix(&self) -> cgmath::Matrix4<f32> {
+        cgmath::Matrix4::look_at_rh(self.eye, self.target, self.up)
+    }
+
+    /// The projection matrix maps the view onto the screen. A
+    /// perspective projection warps it to give the effect of depth.
+    pub fn build_projection_matrix(&self) -> cgmath::Matrix4<f32> {
+        let proj = match self.projection {
+            CameraProjection::Perspective { fov, near, far } => {
+                cgmath::perspective(cgmath::Deg(fov), self.aspect, near, far)
+            }
+            CameraProjection::Orthographic {
+                left,
+                right,
+                bottom,
+                top,
+                near,
+                far,
+            } => cgmath::ortho(left, right, bottom, top, near, far),
+        };
+        let jitter =
+            cgmath::Matrix4::from_translation(cgmath::vec3(self.jitter[0], self.jitter[1], 0.0));
+        jitter * OPENGL_TO_WGPU_MATRIX * proj
+    }
+
+    /// The view projection without the [`jitter`](Camera::jitter),
+    /// for working out how things moved between frames.
+    pub fn build_unjittered_view_projection_matrix(&self) -> cgmath::Matrix4<f32> {
+        let jitter =
+            cgmath::Matrix4::from_translation(cgmath::vec3(-self.jitter[0], -self.jitter[1], 0.0));
+        jitter * self.build_projection_matrix() * self.build_view_matrix()
+    }
+
+    /// Updates the projection for a viewport of the given size.
+    ///
+    /// The orthographic bounds keep their width in world units and
+    /// their center, while the height follows the viewport's aspect.
+    pub fn fit_to_viewport(&mut self, width: u32, height: u32) {
+        if width == 0 || height == 0 {
+            return;
+        }
+        self.aspect = width as f32 / height as f32;
+
+        if let CameraProjection::Orthographic {
+            bottom,
+            top,
+            left,
+            right,
+            ..
+        } = &mut self.projection
+        {
+            let half_height = (*right - *left) / self.aspect * 0.5;
+            let center = (*top + *bottom) * 0.5;
+            *bottom = center - half_height;
+            *top = center + half_height;
+        }
+    }
+
+    pub fn build_view_projection_matrix(&self) -> [[f32; 4]; 4] {
+        (self.build_projection_matrix() * self.build_view_matrix()).into()
+    }
+}
+
+/// A clip plane that every point is in front of.
+pub const NO_CLIP_PLANE: [f32; 4] = [0.0, 0.0, 0.0, 1.0];
+
+// We need this for Rust to store our data correctly for the shaders
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct CameraUniform {
+    // We can't use cgmath with bytemuck directly so we'll have
+    // to convert the Matrix4 into a 4x4 f32 array
+    pub view_proj: [[f32; 4]; 4],
+    // The camera's right and up directions in world space, for
+    // billboards that face the camera. `w` is unused padding.
+    pub right: [f32; 4],
+    pub up: [f32; 4],
+    // Geometry on the negative side of this plane is clipped, for
+    // drawing only what's above or below a water surface. The
+    // default keeps everything.
+    pub clip_plane: [f32; 4],
+    // Where the camera is in world space, for lighting that depends
+    // on the view. `w` is unused padding.
+    pub eye: [f32; 4],
+}
+
+impl CameraUniform {
+    pub fn new() -> Self {
+        use cgmath::SquareMatrix;
+        Self {
+            view_proj: cgmath::Matrix4::identity().into(),
+            right: [1.0, 0.0, 0.0, 0.0],
+            up: [0.0, 1.0, 0.0, 0.0],
+            clip_plane: NO_CLIP_PLANE,
+            eye: [0.0, 0.0, 0.0, 1.0],
+        }
+    }
+
+    pub fn update_view_proj(&mut self, camera: &Camera) {
+        use cgmath::InnerSpace;
+
+        self.view_proj = camera.build_view_projection_matrix();
+
+        let forward = (camera.target - camera.eye).normalize();
+        let right = forward.cross(camera.up).normalize();
+        let up = right.cross(forward);
+        self.right = right.extend(0.0).into();
+        self.up = up.extend(0.0).into();
+        self.eye = camera.eye.to_homogeneous().into();
+    }
+}
+
+impl Default for CameraUniform {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Uniform buffer holding the camera's view projection matrix.
+pub struct CameraBuffer {
+    uniform: CameraUniform,
+    binding: UniformBinding<CameraUniform>,
+}
+
+impl CameraBuffer {
+    pub fn new(device: &wgpu::Device, camera: &Camera) -> Self {
+        let mut uniform = CameraUniform::new();
+        uniform.update_view_proj(camera);
+
+        let binding = UniformBinding::new(
+            device,
+            "Camera Buffer",
+            wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
+            &uniform,
+        );
+
+        CameraBuffer { uniform, binding }
+    }
+
+    pub fn binding(&self) -> &UniformBinding<CameraUniform> {
+        &self.binding
+    }
+
+    /// Uploads the camera's current view projection matrix.
+    pub fn update(&mut self, queue: &wgpu::Queue, camera: &Camera) {
+        self.uniform.update_view_proj(camera);
+        self.binding.update(queue, &self.uniform);
+    }
+
+    /// Sets the plane `[a, b, c, d]` geometry behind which, where
+    /// `a*x + b*y + c*z + d < 0`, the scene shader clips away.
+    /// Takes effect on the next [`update`](Self::update).
+    pub fn set_clip_plane(&mut self, plane: [f32; 4]) {
+        self.uniform.clip_plane = plane;
+    }
+}
+
+/// Pitch is kept just short of straight up or down, where
+/// the look direction would line up with the up vector.
+const MAX_PITCH: f32 = 89.0 * std::f32::consts::PI / 180.0;
+
+/// How much the movement speed is multiplied by for each line scrolled.
+const SCROLL_SPEED_FACTOR: f32 = 1.1;
+
+/// Moves the camera around in response to keyboard and mouse input.
+///
+/// W/A/S/D moves the camera forwards, backwards and sideways,
+/// Q/E moves it down and up. Moving the mouse turns the camera
+/// while the right mouse button is held, or while mouse look
+/// is enabled. Scrolling speeds the movement up or slows it down.
+pub struct CameraController {
+    /// Movement speed in world units per second.
+    pub speed: f32,
+    /// Radians turned per unit of mouse movement.
+    pub sensitivity: f32,
+    /// Turn the camera with the mouse without holding a button.
+    pub mouse_look: bool,
+    mouse_delta: (f32, f32),
+    /// Rotation around the up axis in radians, where zero looks down +X.
+    yaw: f32,
+    /// Rotation above or below the horizon in radians.
+    pitch: f32,
+}
+
+impl CameraController {
+    /// Keys the controller moves the camera with.
+    pub const KEYS: [VirtualKeyCode; 6] = [
+        VirtualKeyCode::W,
+        VirtualKeyCode::S,
+        VirtualKeyCode::A,
+        VirtualKeyCode::D,
+        VirtualKeyCode::E,
+        VirtualKeyCode::Q,
+    ];
+
+    /// Creates a controller which starts out looking
+    /// in the same direction as the camera.
+    pub fn new(speed: f32, sensitivity: f32, camera: &Camera) -> Self {
+        use cgmath::InnerSpace;
+
+        let forward = (camera.target - camera.eye).normalize();
+        let yaw = forward.z.atan2(forward.x);
+        let pitch = forward.y.asin().clamp(-MAX_PITCH, MAX_PITCH);
+
+        Self {
+            speed,
+            sensitivity,
+            mouse_look: false,
+            mouse_delta: (0.0, 0.0),
+            yaw,
+            pitch,
+        }
+    }
+
+    /// Accumulates raw mouse movement, from `DeviceEvent::MouseMotion`.
+    ///
+    /// Device events aren't affected by the cursor hitting the
+    /// edge of the window or screen, which suits mouse look.
+    pub fn process_mouse_motion(&mut self, delta: (f64, f64)) {
+        self.mouse_delta.0 += delta.0 as f32;
+        self.mouse_delta.1 += delta.1 as f32;
+    }
+
+    /// Applies the keys held and the mouse movement since the last
+    /// update to the camera.
+    ///
+    /// `dt` is the time in seconds since the last update, so
+    /// movement speed doesn't depend on the frame rate.
+    pub fn update_camera(&mut self, camera: &mut Camera, input: &InputState, dt: f32) {
+        use cgmath::InnerSpace;
+
+        // Movement while the camera isn't turning is thrown away,
+        // so it doesn't jump when turning starts.
+        let (dx, dy) = self.mouse_delta;
+        self.mouse_delta = (0.0, 0.0);
+        if self.mouse_look || input.is_mouse_held(MouseButton::Right) {
+            self.yaw += dx * self.sensitivity;
+            self.pitch = (self.pitch - dy * self.sensitivity).clamp(-MAX_PITCH, MAX_PITCH);
+        }
+        self.speed *= SCROLL_SPEED_FACTOR.powf(input.scroll_delta());
+
+        // Rebuild the look direction from the angles, as a point on the unit sphere.
+        let (sin_pitch, cos_pitch) = self.pitch.sin_cos();
+        let (sin_yaw, cos_yaw) = self.yaw.sin_cos();
+        let forward = cgmath::Vector3::new(cos_pitch * cos_yaw, sin_pitch, cos_pitch * sin_yaw);
+        let right = forward.cross(camera.up).normalize();
+
+        let mut movement = cgmath::Vector3::new(0.0, 0.0, 0.0);
+        if input.is_key_held(VirtualKeyCode::W) {
+            movement += forward;
+        }
+        if input.is_key_held(VirtualKeyCode::S) {
+            movement -= forward;
+        }
+        if input.is_key_held(VirtualKeyCode::D) {
+            movement += right;
+        }
+        if input.is_key_held(VirtualKeyCode::A) {
+            movement -= right;
+        }
+        if input.is_key_held(VirtualKeyCode::E) {
+            movement += camera.up;
+        }
+        if input.is_key_held(VirtualKeyCode::Q) {
+            movement -= camera.up;
+        }
+        if movement.magnitude2() > 0.0 {
+            movement = movement.normalize() * self.speed * dt;
+        }
+
+        // The target moves with the eye so the camera keeps looking
+        // in the same direction while it moves.
+        let distance = (camera.target - camera.eye).magnitude();
+        camera.eye += movement;
+        camera.target = camera.eye + forward * distance;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::{SquareMatrix, Transform};
+
+    use super::*;
+
+    fn orthographic_camera() -> Camera {
+        Camera {
+            eye: (0.0, 0.0, 5.0).into(),
+            target: (0.0, 0.0, 0.0).into(),
+            up: cgmath::Vector3::unit_y(),
+            aspect: 2.0,
+            projection: CameraProjection::Orthographic {
+                left: -2.0,
+                right: 2.0,
+                bottom: -1.0,
+                top: 1.0,
+                near: 0.1,
+                far: 100.0,
+            },
+            jitter: [0.0, 0.0],
+        }
+    }
+
+    #[test]
+    fn orthographic_near_corners_map_to_ndc_edges() {
+        let camera = orthographic_camera();
+        let view_proj = cgmath::Matrix4::from(camera.build_view_projection_matrix());
+        // The near plane is 0.1 in front of the camera, which looks down -Z.
+        for (x, y) in [(-2.0, -1.0), (2.0, -1.0), (-2.0, 1.0), (2.0, 1.0)] {
+            let ndc = view_proj.transform_point(cgmath::Point3::new(x, y, 4.9));
+            assert!((ndc.x - x / 2.0).abs() < 1e-5, "{:?}", ndc);
+            assert!((ndc.y - y).abs() < 1e-5, "{:?}", ndc);
+            assert!(ndc.z.abs() < 1e-5, "{:?}", ndc);
+        }
+        let far = view_proj.transform_point(cgmath::Point3::new(2.0, 1.0, -95.0));
+        assert!((far.z - 1.0).abs() < 1e-5, "{:?}", far);
+    }
+
+    #[test]
+    fn fit_to_viewport_keeps_width() {
+        let mut camera = orthographic_camera();
+        camera.fit_to_viewport(400, 400);
+        assert_eq!(camera.aspect, 1.0);
+        assert_eq!(
+            camera.projection,
+            CameraProjection::Orthographic {
+                left: -2.0,
+                right: 2.0,
+                bottom: -2.0,
+                top: 2.0,
+                near: 0.1,
+                far: 100.0,
+            }
+        );
+        assert!(camera.build_projection_matrix().invert().is_some());
+    }
+}
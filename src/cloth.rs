@@ -0,0 +1,509 @@
+use wgpu::util::DeviceExt;
+
+use crate::{
+    depth::DepthBuffer, dynamic_mesh::DynamicMesh, msaa::MsaaConfig,
+    pipeline::RenderPipelineBuilder, uniform::UniformBinding, vertex::Vertex,
+};
+
+/// The workgroup size of the simulation's shader.
+const WORKGROUP_SIZE: u32 = 64;
+
+/// Slots for the constraints of each particle: four structural, to
+/// the particles either side, four shear, to the diagonals, and four
+/// bend, to the particles two along. Must match
+/// `CONSTRAINTS_PER_PARTICLE` in `cloth_update.wgsl`.
+const CONSTRAINTS_PER_PARTICLE: usize = 12;
+/// The other end of a constraint slot with no constraint in it.
+const NO_PARTICLE: u32 = u32::MAX;
+
+/// How far apart neighbouring particles start out.
+const SPACING: f32 = 0.1;
+/// How high above the ground the cloth starts out, lying flat.
+const START_HEIGHT: f32 = 2.0;
+
+/// How stiff the shear and bend constraints are, next to the
+/// structural ones, so the cloth folds rather than stretches.
+const SHEAR_STIFFNESS: f32 = 0.5;
+const BEND_STIFFNESS: f32 = 0.05;
+
+/// Each frame is stepped in several shorter substeps, which keeps
+/// the constraints stiffer than more iterations in a single step.
+const SUBSTEPS: u32 = 8;
+/// Passes over the constraints in each substep.
+const ITERATIONS: u32 = 2;
+/// Longer frames are stepped as if they were this long, so a hitch
+/// doesn't blow the cloth apart.
+const MAX_DELTA_TIME: f32 = 1.0 / 30.0;
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct ClothParams {
+    origin: [f32; 4],
+    /// Seconds a substep covers.
+    delta_time: f32,
+    width: u32,
+    height: u32,
+    _padding: f32,
+}
+
+/// A distance constraint, from the particle whose slots it's in.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct Constraint {
+    other: u32,
+    rest_length: f32,
+    /// The inverse of the constraint's stiffness.
+    compliance: f32,
+    _padding: f32,
+}
+
+impl Constraint {
+    const NONE: Constraint = Constraint {
+        other: NO_PARTICLE,
+        rest_length: 0.0,
+        compliance: 0.0,
+        _padding: 0.0,
+    };
+}
+
+/// A sheet of cloth simulated on the GPU, hanging from the corners
+/// along one edge.
+///
+/// The cloth is a grid of particles joined by distance constraints,
+/// solved with extended position based dynamics. Each substep moves
+/// the particles along their velocities, pulls them back towards
+/// where their constraints want them, and keeps them above the
+/// ground, which is the plane y = 0 of the cloth's own space. Every
+/// pass reads the positions written by the one before, from the other
+/// of a pair of buffers.
+///
+/// The constraints are solved a particle at a time, with each summing
+/// up the corrections of its own, so the passes don't race. The
+/// vertices drawn are then written from the positions, and copied
+/// into a [`DynamicMesh`] for the [`ClothRenderer`].
+///
+/// Every frame should go:
+///
+/// 1. [`ClothSimulation::update`] with the frame's time.
+/// 2. [`ClothSimulation::dispatch`] before the cloth is drawn.
+/// 3. [`ClothSimulation::swap`] once the frame is submitted.
+pub struct ClothSimulation {
+    width: u32,
+    height: u32,
+    /// Where the cloth's space is in the world.
+    origin: [f32; 3],
+    /// The particles' positions, of which `current` holds the latest.
+    #[allow(dead_code)]
+    positions: [wgpu::Buffer; 2],
+    /// One bind group for each direction the positions can flow
+    /// between the buffers.
+    bind_groups: [wgpu::BindGroup; 2],
+    current: usize,
+    vertex_buffer: wgpu::Buffer,
+    params: UniformBinding<ClothParams>,
+    integrate: wgpu::ComputePipeline,
+    solve: wgpu::ComputePipeline,
+    finalize: wgpu::ComputePipeline,
+    build_vertices: wgpu::ComputePipeline,
+    mesh: DynamicMesh<Vertex, u16>,
+}
+
+impl ClothSimulation {
+    /// A cloth `width` by `height` particles across. `stiffness` is
+    /// how hard the cloth pulls back against being stretched.
+    ///
+    /// # Panics
+    ///
+    /// When the particles don't fit 16-bit indices, or there aren't
+    /// at least two of them each way.
+    pub fn new(device: &wgpu::Device, width: u32, height: u32, stiffness: f32) -> Self {
+        assert!(
+            width >= 2 && height >= 2,
+            "cloth must be at least 2 by 2 particles, not {} by {}",
+            width,
+            height
+        );
+        let count = (width * height) as usize;
+        assert!(
+            count <= u16::MAX as usize,
+            "cloth with {} particles doesn't fit 16-bit indices",
+            count
+        );
+
+        // Lies flat, centered across X, and held up by the corners of
+        // the edge nearest -Z, which have no mass.
+        let positions = (0..height)
+            .flat_map(|y| (0..width).map(move |x| (x, y)))
+            .map(|(x, y)| {
+                let pinned = y == 0 && (x == 0 || x == width - 1);
+                [
+                    (x as f32 - (width - 1) as f32 * 0.5) * SPACING,
+                    START_HEIGHT,
+                    y as f32 * SPACING,
+                    if pinned { 0.0 } else { 1.0 },
+                ]
+            })
+            .collect::<Vec<_>>();
+        let constraints = grid_constraints(width, height, stiffness);
+
+        let storage_usage = wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST;
+        let position_buffers = [0, 1].map(|i| {
+            device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some(&format!("Cloth Position Buffer {}", i)),
+                contents: bytemuck::cast_slice(&positions),
+                usage: storage_usage,
+            })
+        });
+        let previous_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Cloth Previous Position Buffer"),
+            contents: bytemuck::cast_slice(&positions),
+            usage: storage_usage,
+        });
+        let velocity_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Cloth Velocity Buffer"),
+            contents: bytemuck::cast_slice(&vec![[0.0f32; 4]; count]),
+            usage: storage_usage,
+        });
+        let constraint_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Cloth Constraint Buffer"),
+            contents: bytemuck::cast_slice(&constraints),
+            usage: wgpu::BufferUsages::STORAGE,
+        });
+        // The vertices are built here on the GPU, then copied into the mesh.
+        let vertex_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Cloth Vertex Buffer"),
+            size: (count * std::mem::size_of::<Vertex>()) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+
+        let storage_entry = |binding, read_only| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Cloth Bind Group Layout"),
+            entries: &[
+                storage_entry(0, true),
+                storage_entry(1, false),
+                storage_entry(2, false),
+                storage_entry(3, false),
+                storage_entry(4, true),
+                storage_entry(5, false),
+            ],
+        });
+        let bind_groups = [0, 1].map(|src| {
+            device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("Cloth Bind Group"),
+                layout: &bind_group_layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: position_buffers[src].as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: position_buffers[1 - src].as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 2,
+                        resource: previous_buffer.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 3,
+                        resource: velocity_buffer.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 4,
+                        resource: constraint_buffer.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 5,
+                        resource: vertex_buffer.as_entire_binding(),
+                    },
+                ],
+            })
+        });
+
+        let origin = [0.0; 3];
+        let params = UniformBinding::new(
+            device,
+            "Cloth Params",
+            wgpu::ShaderStages::COMPUTE | wgpu::ShaderStages::VERTEX,
+            &ClothParams {
+                origin: [origin[0], origin[1], origin[2], 0.0],
+                delta_time: 0.0,
+                width,
+                height,
+                _padding: 0.0,
+            },
+        );
+
+        let shader = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+            label: Some("Cloth Update Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("cloth_update.wgsl").into()),
+        });
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Cloth Update Pipeline Layout"),
+            bind_group_layouts: &[&bind_group_layout, &params.bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = |entry_point| {
+            device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some("Cloth Update Pipeline"),
+                layout: Some(&layout),
+                module: &shader,
+                entry_point,
+            })
+        };
+
+        // The triangles never change, so only the vertices are copied.
+        let mesh = DynamicMesh::with_indices(device, count, &grid_indices(width, height));
+
+        ClothSimulation {
+            width,
+            height,
+            origin,
+            positions: position_buffers,
+            bind_groups,
+            current: 0,
+            vertex_buffer,
+            params,
+            integrate: pipeline("integrate"),
+            solve: pipeline("solve"),
+            finalize: pipeline("finalize"),
+            build_vertices: pipeline("build_vertices"),
+            mesh,
+        }
+    }
+
+    /// Moves the cloth's space, and the ground with it, to `origin`
+    /// in the world. Takes effect from the next
+    /// [`update`](Self::update).
+    pub fn set_origin(&mut self, origin: [f32; 3]) {
+        self.origin = origin;
+    }
+
+    /// Steps the simulation by `dt` seconds on the next dispatch.
+    pub fn update(&mut self, queue: &wgpu::Queue, dt: f32) {
+        let [x, y, z] = self.origin;
+        self.params.update(
+            queue,
+            &ClothParams {
+                origin: [x, y, z, 0.0],
+                delta_time: dt.min(MAX_DELTA_TIME) / SUBSTEPS as f32,
+                width: self.width,
+                height: self.height,
+                _padding: 0.0,
+            },
+        );
+    }
+
+    /// Records the passes stepping the cloth, and the copy of its
+    /// vertices into the mesh. Must come before the cloth is drawn.
+    pub fn dispatch(&mut self, encoder: &mut wgpu::CommandEncoder) {
+        let num_groups = (self.width * self.height).div_ceil(WORKGROUP_SIZE);
+        {
+            let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("Cloth Update Pass"),
+            });
+            compute_pass.set_bind_group(1, &self.params.bind_group, &[]);
+
+            let mut steps = Vec::new();
+            for _ in 0..SUBSTEPS {
+                steps.push(&self.integrate);
+                steps.extend(std::iter::repeat_n(&self.solve, ITERATIONS as usize));
+                steps.push(&self.finalize);
+            }
+            for pipeline in steps {
+                compute_pass.set_pipeline(pipeline);
+                compute_pass.set_bind_group(0, &self.bind_groups[self.current], &[]);
+                compute_pass.dispatch(num_groups, 1, 1);
+                self.current = 1 - self.current;
+            }
+
+            // Writes no positions, so the latest stay where they are.
+            compute_pass.set_pipeline(&self.build_vertices);
+            compute_pass.set_bind_group(0, &self.bind_groups[self.current], &[]);
+            compute_pass.dispatch(num_groups, 1, 1);
+        }
+
+        self.mesh.copy_vertices(
+            encoder,
+            &self.vertex_buffer,
+            (self.width * self.height) as usize,
+        );
+    }
+
+    /// Shows the vertices written by the last dispatch from the next
+    /// frame on, once the frame it was in is submitted.
+    pub fn swap(&mut self) {
+        self.mesh.swap();
+    }
+
+    /// The buffer holding the latest positions, with each particle's
+    /// inverse mass in `w`.
+    #[allow(dead_code)]
+    pub fn position_buffer(&self) -> &wgpu::Buffer {
+        &self.positions[self.current]
+    }
+
+    #[allow(dead_code)]
+    pub fn mesh(&self) -> &DynamicMesh<Vertex, u16> {
+        &self.mesh
+    }
+}
+
+/// The constraints in each particle's slots, to the particles either
+/// side, on the diagonals, and two along.
+fn grid_constraints(width: u32, height: u32, stiffness: f32) -> Vec<Constraint> {
+    const STRUCTURAL: [(i32, i32); 4] = [(1, 0), (-1, 0), (0, 1), (0, -1)];
+    const SHEAR: [(i32, i32); 4] = [(1, 1), (-1, 1), (1, -1), (-1, -1)];
+    const BEND: [(i32, i32); 4] = [(2, 0), (-2, 0), (0, 2), (0, -2)];
+
+    let compliance = |stiffness: f32| 1.0 / stiffness.max(f32::EPSILON);
+    let kinds = [
+        (STRUCTURAL, compliance(stiffness)),
+        (SHEAR, compliance(stiffness * SHEAR_STIFFNESS)),
+        (BEND, compliance(stiffness * BEND_STIFFNESS)),
+    ];
+
+    let mut constraints = Vec::with_capacity((width * height) as usize * CONSTRAINTS_PER_PARTICLE);
+    for y in 0..height as i32 {
+        for x in 0..width as i32 {
+            for (offsets, compliance) in &kinds {
+                for (dx, dy) in offsets {
+                    let (ox, oy) = (x + dx, y + dy);
+                    let inside =
+                        (0..width as i32).contains(&ox) && (0..height as i32).contains(&oy);
+                    constraints.push(if inside {
+                        Constraint {
+                            other: oy as u32 * width + ox as u32,
+                            rest_length: ((dx * dx + dy * dy) as f32).sqrt() * SPACING,
+                            compliance: *compliance,
+                            _padding: 0.0,
+                        }
+                    } else {
+                        Constraint::NONE
+                    });
+                }
+            }
+        }
+    }
+    constraints
+}
+
+fn grid_index_count(width: u32, height: u32) -> usize {
+    ((width - 1) * (height - 1) * 6) as usize
+}
+
+/// Two triangles for each square between four particles.
+fn grid_indices(width: u32, height: u32) -> Vec<u16> {
+    let mut indices = Vec::with_capacity(grid_index_count(width, height));
+    for y in 0..height - 1 {
+        for x in 0..width - 1 {
+            let a = (y * width + x) as u16;
+            let b = a + 1;
+            let c = a + width as u16;
+            let d = c + 1;
+            // Counter-clockwise seen from above, where the normals point.
+            indices.extend_from_slice(&[a, c, b, b, c, d]);
+        }
+    }
+    indices
+}
+
+/// Draws [`ClothSimulation`]s, from both sides.
+pub struct ClothRenderer {
+    pipeline: wgpu::RenderPipeline,
+    pipeline_layout: wgpu::PipelineLayout,
+    shader: wgpu::ShaderModule,
+    format: wgpu::TextureFormat,
+}
+
+impl ClothRenderer {
+    /// The cloth is drawn with the camera's bind group at group 0,
+    /// and its own uniform at group 1.
+    pub fn new(
+        device: &wgpu::Device,
+        camera_layout: &wgpu::BindGroupLayout,
+        cloth: &ClothSimulation,
+        format: wgpu::TextureFormat,
+        msaa: MsaaConfig,
+    ) -> Self {
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Cloth Pipeline Layout"),
+            bind_group_layouts: &[camera_layout, &cloth.params.bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let shader = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+            label: Some("Cloth Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("cloth.wgsl").into()),
+        });
+        let pipeline = create_pipeline(device, &pipeline_layout, &shader, format, msaa);
+
+        ClothRenderer {
+            pipeline,
+            pipeline_layout,
+            shader,
+            format,
+        }
+    }
+
+    pub fn draw<'a>(
+        &'a self,
+        render_pass: &mut wgpu::RenderPass<'a>,
+        camera_bind_group: &'a wgpu::BindGroup,
+        cloth: &'a ClothSimulation,
+    ) {
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, camera_bind_group, &[]);
+        render_pass.set_bind_group(1, &cloth.params.bind_group, &[]);
+        cloth.mesh.draw(render_pass, cloth.mesh.frame_index());
+    }
+
+    /// Recreates the pipeline to match the sample count of the pass.
+    pub fn set_msaa(&mut self, device: &wgpu::Device, msaa: MsaaConfig) {
+        self.pipeline = create_pipeline(
+            device,
+            &self.pipeline_layout,
+            &self.shader,
+            self.format,
+            msaa,
+        );
+    }
+}
+
+fn create_pipeline(
+    device: &wgpu::Device,
+    layout: &wgpu::PipelineLayout,
+    shader: &wgpu::ShaderModule,
+    format: wgpu::TextureFormat,
+    msaa: MsaaConfig,
+) -> wgpu::RenderPipeline {
+    RenderPipelineBuilder::new()
+        .label("Cloth Pipeline")
+        .vertex_shader(shader, "main")
+        .vertex_layouts(&[Vertex::vertex_buffer_layout()])
+        .fragment_shader(
+            shader,
+            "main",
+            &[wgpu::ColorTargetState {
+                format,
+                blend: Some(wgpu::BlendState::REPLACE),
+                write_mask: wgpu::ColorWrites::ALL,
+            }],
+        )
+        // Both sides of the cloth show as it folds over.
+        .cull_mode(None)
+        .depth_stencil(DepthBuffer::depth_stencil_state())
+        .multisample(msaa.count)
+        .build(device, layout)
+        .expect("failed to build cloth pipeline")
+}
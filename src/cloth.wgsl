@@ -0,0 +1,63 @@
+// Draws the cloth, from the vertices its simulation writes.
+
+[[block]]
+struct CameraUniform {
+    view_proj: mat4x4<f32>;
+    right: vec4<f32>;
+    up: vec4<f32>;
+};
+
+[[block]]
+struct Params {
+    // Moves the cloth from where it's simulated to the world.
+    origin: vec4<f32>;
+    delta_time: f32;
+    width: u32;
+    height: u32;
+};
+
+[[group(0), binding(0)]]
+var<uniform> camera: CameraUniform;
+
+[[group(1), binding(0)]]
+var<uniform> params: Params;
+
+struct VertexInput {
+    [[location(0)]] position: vec3<f32>;
+    [[location(1)]] color: vec3<f32>;
+    [[location(2)]] normal: vec3<f32>;
+    [[location(3)]] tex_coords: vec2<f32>;
+};
+
+struct VertexOutput {
+    [[builtin(position)]] clip_position: vec4<f32>;
+    [[location(0)]] normal: vec3<f32>;
+    [[location(1)]] tex_coords: vec2<f32>;
+};
+
+// Lit from above and in front, like the rest of the scene's
+// unshadowed geometry.
+let LIGHT_DIRECTION: vec3<f32> = vec3<f32>(0.3, 1.0, 0.5);
+let AMBIENT: f32 = 0.2;
+
+[[stage(vertex)]]
+fn main(in: VertexInput) -> VertexOutput {
+    var out: VertexOutput;
+    out.clip_position = camera.view_proj * vec4<f32>(in.position + params.origin.xyz, 1.0);
+    out.normal = in.normal;
+    out.tex_coords = in.tex_coords;
+    return out;
+}
+
+[[stage(fragment)]]
+fn main(in: VertexOutput, [[builtin(front_facing)]] front_facing: bool) -> [[location(0)]] vec4<f32> {
+    // Both sides of the cloth are seen, so the back is lit as if its
+    // normal pointed the other way.
+    let normal = select(-in.normal, in.normal, front_facing);
+    let diffuse = max(dot(normalize(normal), normalize(LIGHT_DIRECTION)), 0.0);
+    let shade = AMBIENT + (1.0 - AMBIENT) * diffuse;
+    // A check pattern, so the cloth's folds are easy to see.
+    let check = (u32(in.tex_coords.x * 8.0) + u32(in.tex_coords.y * 8.0)) % 2u;
+    let color = select(vec3<f32>(0.2, 0.4, 0.8), vec3<f32>(0.9, 0.9, 0.9), check == 0u);
+    return vec4<f32>(color * shade, 1.0);
+}
@@ -0,0 +1,216 @@
+// Steps a cloth of particles joined by distance constraints, with
+// extended position based dynamics. Each entry point is a pass over
+// every particle, reading the positions in `src` and writing `dst`.
+//
+// A substep goes `integrate`, then `solve` a few times, then
+// `finalize`. The constraints are solved a particle at a time, each
+// summing the corrections of its own constraints, so no two
+// invocations write to the same particle.
+
+[[block]]
+struct Params {
+    // Where the cloth is drawn, which the simulation doesn't use.
+    origin: vec4<f32>;
+    // Seconds a substep covers.
+    delta_time: f32;
+    width: u32;
+    height: u32;
+};
+
+// `w` holds the particle's inverse mass. Pinned particles have none,
+// so nothing moves them.
+[[block]]
+struct Positions {
+    positions: array<vec4<f32>>;
+};
+
+[[block]]
+struct Velocities {
+    velocities: array<vec4<f32>>;
+};
+
+struct Constraint {
+    // The particle at the other end, or NONE for an unused slot.
+    other: u32;
+    rest_length: f32;
+    // The inverse of the constraint's stiffness.
+    compliance: f32;
+    padding: f32;
+};
+
+// Every particle has CONSTRAINTS_PER_PARTICLE slots, one after the other.
+[[block]]
+struct Constraints {
+    constraints: array<Constraint>;
+};
+
+// The vertices drawn, laid out like `Vertex` in vertex.rs, which
+// doesn't line up with the alignment of `vec3`s.
+[[block]]
+struct Vertices {
+    data: array<f32>;
+};
+
+[[group(0), binding(0)]]
+var<storage, read> src: Positions;
+[[group(0), binding(1)]]
+var<storage, read_write> dst: Positions;
+[[group(0), binding(2)]]
+var<storage, read_write> previous: Positions;
+[[group(0), binding(3)]]
+var<storage, read_write> velocities: Velocities;
+[[group(0), binding(4)]]
+var<storage, read> constraints: Constraints;
+[[group(0), binding(5)]]
+var<storage, read_write> vertices: Vertices;
+[[group(1), binding(0)]]
+var<uniform> params: Params;
+
+// Must match `CONSTRAINTS_PER_PARTICLE` in cloth.rs.
+let CONSTRAINTS_PER_PARTICLE: u32 = 12u;
+let NONE: u32 = 4294967295u;
+let GRAVITY: vec3<f32> = vec3<f32>(0.0, -9.8, 0.0);
+// Share of the velocity lost every second.
+let DAMPING: f32 = 0.1;
+// Share of the sliding along the ground each substep takes away.
+let FRICTION: f32 = 0.5;
+// Corrections are averaged over each particle's constraints, then
+// over-relaxed, since averaging on its own converges slowly.
+let RELAXATION: f32 = 1.5;
+// Floats in a `Vertex`.
+let VERTEX_FLOATS: u32 = 11u;
+
+fn particle_count() -> u32 {
+    return params.width * params.height;
+}
+
+// Moves each particle along its velocity, to where it'd be without
+// the constraints, and remembers where it was.
+[[stage(compute), workgroup_size(64)]]
+fn integrate([[builtin(global_invocation_id)]] id: vec3<u32>) {
+    let index = id.x;
+    if (index >= particle_count()) {
+        return;
+    }
+
+    var p = src.positions[index];
+    previous.positions[index] = p;
+    if (p.w > 0.0) {
+        let velocity = velocities.velocities[index].xyz + GRAVITY * params.delta_time;
+        p = vec4<f32>(p.xyz + velocity * params.delta_time, p.w);
+    }
+    dst.positions[index] = p;
+}
+
+// Pulls each particle towards where its constraints want it.
+[[stage(compute), workgroup_size(64)]]
+fn solve([[builtin(global_invocation_id)]] id: vec3<u32>) {
+    let index = id.x;
+    if (index >= particle_count()) {
+        return;
+    }
+
+    let p = src.positions[index];
+    if (p.w == 0.0) {
+        dst.positions[index] = p;
+        return;
+    }
+
+    var correction = vec3<f32>(0.0, 0.0, 0.0);
+    var count = 0u;
+    let dt2 = params.delta_time * params.delta_time;
+    for (var i = 0u; i < CONSTRAINTS_PER_PARTICLE; i = i + 1u) {
+        let constraint = constraints.constraints[index * CONSTRAINTS_PER_PARTICLE + i];
+        if (constraint.other == NONE) {
+            continue;
+        }
+        let q = src.positions[constraint.other];
+        let delta = p.xyz - q.xyz;
+        let distance = length(delta);
+        if (distance < 0.000001) {
+            continue;
+        }
+
+        // With the compliance scaled by the substep, how stiff the
+        // constraint is doesn't depend on how many substeps there are.
+        let c = distance - constraint.rest_length;
+        let alpha = constraint.compliance / dt2;
+        let lambda = -c / (p.w + q.w + alpha);
+        correction = correction + p.w * lambda * delta / distance;
+        count = count + 1u;
+    }
+
+    if (count > 0u) {
+        correction = correction * (RELAXATION / f32(count));
+    }
+    dst.positions[index] = vec4<f32>(p.xyz + correction, p.w);
+}
+
+// Keeps the particles above the ground, and works out their
+// velocities from how far they moved.
+[[stage(compute), workgroup_size(64)]]
+fn finalize([[builtin(global_invocation_id)]] id: vec3<u32>) {
+    let index = id.x;
+    if (index >= particle_count()) {
+        return;
+    }
+
+    var p = src.positions[index];
+    let before = previous.positions[index];
+    if (p.w == 0.0) {
+        velocities.velocities[index] = vec4<f32>(0.0, 0.0, 0.0, 0.0);
+        dst.positions[index] = p;
+        return;
+    }
+
+    if (p.y < 0.0) {
+        p.y = 0.0;
+        // Friction holds back the sliding across the ground.
+        let slide = p.xz - before.xz;
+        p.x = p.x - slide.x * FRICTION;
+        p.z = p.z - slide.y * FRICTION;
+    }
+    let velocity = (p.xyz - before.xyz) / params.delta_time;
+    velocities.velocities[index] = vec4<f32>(velocity * (1.0 - DAMPING * params.delta_time), 0.0);
+    dst.positions[index] = p;
+}
+
+fn position_at(x: i32, y: i32) -> vec3<f32> {
+    let column = u32(clamp(x, 0, i32(params.width) - 1));
+    let row = u32(clamp(y, 0, i32(params.height) - 1));
+    return src.positions[row * params.width + column].xyz;
+}
+
+// Writes the vertices drawn, with normals from the neighbouring
+// particles either side.
+[[stage(compute), workgroup_size(64)]]
+fn build_vertices([[builtin(global_invocation_id)]] id: vec3<u32>) {
+    let index = id.x;
+    if (index >= particle_count()) {
+        return;
+    }
+
+    let x = i32(index % params.width);
+    let y = i32(index / params.width);
+    let across = position_at(x + 1, y) - position_at(x - 1, y);
+    let down = position_at(x, y + 1) - position_at(x, y - 1);
+    let normal = normalize(cross(down, across));
+    let position = src.positions[index].xyz;
+    let tex_coords = vec2<f32>(
+        f32(x) / f32(params.width - 1u),
+        f32(y) / f32(params.height - 1u),
+    );
+
+    let base = index * VERTEX_FLOATS;
+    vertices.data[base] = position.x;
+    vertices.data[base + 1u] = position.y;
+    vertices.data[base + 2u] = position.z;
+    vertices.data[base + 3u] = 1.0;
+    vertices.data[base + 4u] = 1.0;
+    vertices.data[base + 5u] = 1.0;
+    vertices.data[base + 6u] = normal.x;
+    vertices.data[base + 7u] = normal.y;
+    vertices.data[base + 8u] = normal.z;
+    vertices.data[base + 9u] = tex_coords.x;
+    vertices.data[base + 10u] = tex_coords.y;
+}
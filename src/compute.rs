@@ -0,0 +1,123 @@
+use wgpu::util::DeviceExt;
+
+use crate::uniform::UniformBinding;
+
+/// A compute shader that steps data held in a pair of storage buffers.
+///
+/// Each dispatch reads the buffer written by the previous one, and writes
+/// the other, so the buffers swap roles every time. The shader sees the
+/// input at group 0 binding 0, the output at group 0 binding 1, and a
+/// uniform holding `P` at group 1 binding 0.
+pub struct ComputePass<P: bytemuck::Pod> {
+    pipeline: wgpu::ComputePipeline,
+    buffers: [wgpu::Buffer; 2],
+    /// One bind group for each direction data can flow between the buffers.
+    bind_groups: [wgpu::BindGroup; 2],
+    params: UniformBinding<P>,
+    /// Index of the buffer holding the latest data.
+    current: usize,
+}
+
+impl<P: bytemuck::Pod> ComputePass<P> {
+    /// Both buffers start out holding `initial`. They can also be used
+    /// as vertex buffers, so the results can be drawn directly.
+    pub fn new(
+        device: &wgpu::Device,
+        label: &str,
+        source: &str,
+        initial: &[u8],
+        params: &P,
+    ) -> Self {
+        let shader = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+            label: Some(label),
+            source: wgpu::ShaderSource::Wgsl(source.into()),
+        });
+
+        let buffers = [0, 1].map(|i| {
+            device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some(&format!("{} Buffer {}", label, i)),
+                contents: initial,
+                usage: wgpu::BufferUsages::STORAGE
+                    | wgpu::BufferUsages::VERTEX
+                    | wgpu::BufferUsages::COPY_DST,
+            })
+        });
+
+        let storage_entry = |binding, read_only| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some(label),
+            entries: &[storage_entry(0, true), storage_entry(1, false)],
+        });
+        let bind_groups = [0, 1].map(|src| {
+            device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some(label),
+                layout: &bind_group_layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: buffers[src].as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: buffers[1 - src].as_entire_binding(),
+                    },
+                ],
+            })
+        });
+
+        let params = UniformBinding::new(device, label, wgpu::ShaderStages::COMPUTE, params);
+
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some(label),
+            bind_group_layouts: &[&bind_group_layout, &params.bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some(label),
+            layout: Some(&layout),
+            module: &shader,
+            entry_point: "main",
+        });
+
+        ComputePass {
+            pipeline,
+            buffers,
+            bind_groups,
+            params,
+            current: 0,
+        }
+    }
+
+    pub fn set_params(&self, queue: &wgpu::Queue, params: &P) {
+        self.params.update(queue, params);
+    }
+
+    /// Records a dispatch of `num_groups_x` workgroups, and swaps the buffers.
+    pub fn dispatch(&mut self, encoder: &mut wgpu::CommandEncoder, num_groups_x: u32) {
+        {
+            let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("Compute Pass"),
+            });
+            compute_pass.set_pipeline(&self.pipeline);
+            compute_pass.set_bind_group(0, &self.bind_groups[self.current], &[]);
+            compute_pass.set_bind_group(1, &self.params.bind_group, &[]);
+            compute_pass.dispatch(num_groups_x, 1, 1);
+        }
+
+        self.current = 1 - self.current;
+    }
+
+    /// The buffer written by the last dispatch.
+    pub fn output_buffer(&self) -> &wgpu::Buffer {
+        &self.buffers[self.current]
+    }
+}
@@ -0,0 +1,292 @@
+use crate::{
+    bind_group_allocator::{BindGroupAllocator, PooledBindGroup, ResourceId},
+    hdr_render_target::HdrRenderTarget,
+    readback::{ReadbackRing, READBACK_FRAMES},
+    uniform::UniformBinding,
+};
+
+/// Range of log2 luminance the bins cover. Anything brighter or
+/// darker is counted in the bins at the ends.
+const MIN_LOG_LUMINANCE: f32 = -10.0;
+const MAX_LOG_LUMINANCE: f32 = 4.0;
+/// Luminance the average of the frame is exposed to, a mid grey.
+const KEY_VALUE: f32 = 0.18;
+/// The exposure is kept within these, so a black screen or staring
+/// into the sun doesn't blow it out.
+const MIN_EXPOSURE: f32 = 0.05;
+const MAX_EXPOSURE: f32 = 8.0;
+/// How much of the way to the exposure of the latest frame the
+/// exposure moves each time it's read, so it adapts over a few
+/// frames instead of flickering.
+const ADAPTATION_RATE: f32 = 0.05;
+
+/// The workgroup size of `build_histogram` in the shader.
+const WORKGROUP_SIZE: u32 = 16;
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct HistogramParams {
+    min_log_luminance: f32,
+    log_luminance_range: f32,
+    num_bins: u32,
+    _padding: u32,
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct HistogramResult {
+    mean_log_luminance: f32,
+    pixel_count: u32,
+}
+
+const RESULT_SIZE: wgpu::BufferAddress =
+    std::mem::size_of::<HistogramResult>() as wgpu::BufferAddress;
+
+/// Measures how bright the HDR frame is, for exposing it
+/// automatically.
+///
+/// One compute pass sorts the frame's pixels into bins by their log
+/// luminance, and a second averages the bins. The average is read
+/// back once the frame is submitted, and mapped to the exposure that
+/// brings it to a mid grey.
+pub struct ComputeHistogram {
+    num_bins: u32,
+    params: UniformBinding<HistogramParams>,
+    bins: wgpu::Buffer,
+    result: wgpu::Buffer,
+    readbacks: ReadbackRing,
+    bind_group_layout: wgpu::BindGroupLayout,
+    /// Stands for the layout and buffers above, which are only
+    /// made once.
+    resource_id: ResourceId,
+    /// Taken from the pool by [`prepare`](Self::prepare) for the frame
+    /// being recorded, and given back once it's submitted.
+    bind_group: Option<PooledBindGroup>,
+    build_pipeline: wgpu::ComputePipeline,
+    average_pipeline: wgpu::ComputePipeline,
+    exposure: f32,
+}
+
+impl ComputeHistogram {
+    /// The first of the `num_bins` bins holds the pixels too dark to
+    /// count, so there have to be at least three.
+    pub fn new(device: &wgpu::Device, num_bins: u32) -> Self {
+        let num_bins = num_bins.max(3);
+        let params = UniformBinding::new(
+            device,
+            "Histogram Params",
+            wgpu::ShaderStages::COMPUTE,
+            &HistogramParams {
+                min_log_luminance: MIN_LOG_LUMINANCE,
+                log_luminance_range: MAX_LOG_LUMINANCE - MIN_LOG_LUMINANCE,
+                num_bins,
+                _padding: 0,
+            },
+        );
+        let bins = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Histogram Bins"),
+            size: (num_bins as usize * std::mem::size_of::<u32>()) as wgpu::BufferAddress,
+            // Cleared from the queue once they've been averaged.
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let result = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Histogram Result"),
+            size: RESULT_SIZE,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+        let readbacks = ReadbackRing::new(
+            device,
+            "Histogram Readback Buffer",
+            RESULT_SIZE,
+            READBACK_FRAMES,
+        );
+
+        let storage_entry = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only: false },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Histogram Bind Group Layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                storage_entry(1),
+                storage_entry(2),
+                wgpu::BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        // Only loaded from, never filtered.
+                        sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                    },
+                    count: None,
+                },
+            ],
+        });
+
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Histogram Pipeline Layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let shader = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+            label: Some("Histogram Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("histogram.wgsl").into()),
+        });
+        let pipeline = |entry_point| {
+            device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some("Histogram Pipeline"),
+                layout: Some(&layout),
+                module: &shader,
+                entry_point,
+            })
+        };
+        let build_pipeline = pipeline("build_histogram");
+        let average_pipeline = pipeline("average");
+
+        ComputeHistogram {
+            num_bins,
+            params,
+            bins,
+            result,
+            readbacks,
+            bind_group_layout,
+            resource_id: ResourceId::new(),
+            bind_group: None,
+            build_pipeline,
+            average_pipeline,
+            exposure: 1.0,
+        }
+    }
+
+    #[allow(dead_code)]
+    pub fn num_bins(&self) -> u32 {
+        self.num_bins
+    }
+
+    /// The exposure from the last read.
+    #[allow(dead_code)]
+    pub fn exposure(&self) -> f32 {
+        self.exposure
+    }
+
+    /// Binds `hdr`, the frame before it's tone mapped, for the next
+    /// [`dispatch`](Self::dispatch).
+    ///
+    /// The frame's texture is recreated on resize, so its bind group
+    /// is taken from `bind_groups` every frame.
+    pub fn prepare(
+        &mut self,
+        device: &wgpu::Device,
+        bind_groups: &mut BindGroupAllocator,
+        hdr: &HdrRenderTarget,
+    ) {
+        if let Some(bind_group) = self.bind_group.take() {
+            bind_groups.free(bind_group);
+        }
+        let bind_group = bind_groups.allocate(
+            device,
+            &[self.resource_id, hdr.id()],
+            &self.bind_group_layout,
+            &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: self.params.buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: self.bins.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: self.result.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: wgpu::BindingResource::TextureView(hdr.view()),
+                },
+            ],
+        );
+        self.bind_group = Some(bind_group);
+    }
+
+    /// Records the passes measuring the frame bound by
+    /// [`prepare`](Self::prepare), which is `width` by `height`.
+    pub fn dispatch(&self, encoder: &mut wgpu::CommandEncoder, width: u32, height: u32) {
+        let bind_group = match &self.bind_group {
+            Some(bind_group) => bind_group.bind_group(),
+            None => return,
+        };
+
+        {
+            let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("Histogram Pass"),
+            });
+            compute_pass.set_bind_group(0, bind_group, &[]);
+            compute_pass.set_pipeline(&self.build_pipeline);
+            compute_pass.dispatch(
+                width.div_ceil(WORKGROUP_SIZE),
+                height.div_ceil(WORKGROUP_SIZE),
+                1,
+            );
+            compute_pass.set_pipeline(&self.average_pipeline);
+            compute_pass.dispatch(1, 1, 1);
+        }
+
+        // Skipped while the GPU is behind on the earlier frames'.
+        if let Some(readback) = self.readbacks.target() {
+            encoder.copy_buffer_to_buffer(&self.result, 0, readback, 0, RESULT_SIZE);
+        }
+    }
+
+    /// Should be called once the encoder the frame was measured in
+    /// is submitted. Moves the exposure towards the one that brings
+    /// the average luminance of the newest frame measured to a mid
+    /// grey, and clears the bins for the next frame. The frame's bind
+    /// group goes back to `bind_groups`.
+    ///
+    /// Doesn't wait for the GPU, so the exposure is a frame or more
+    /// behind, and stays as it was while no results have come back.
+    pub fn read_exposure(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        bind_groups: &mut BindGroupAllocator,
+    ) -> f32 {
+        if let Some(bind_group) = self.bind_group.take() {
+            bind_groups.free(bind_group);
+        }
+        self.readbacks.submitted(RESULT_SIZE, ());
+        let zeros = vec![0u8; self.num_bins as usize * std::mem::size_of::<u32>()];
+        queue.write_buffer(&self.bins, 0, &zeros);
+
+        let result = self.readbacks.read(device, |data, ()| {
+            *bytemuck::from_bytes::<HistogramResult>(data)
+        });
+        // A frame that's all black has nothing to expose for.
+        if let Some(result) = result.filter(|result| result.pixel_count > 0) {
+            let average_luminance = result.mean_log_luminance.exp2();
+            let target = (KEY_VALUE / average_luminance).clamp(MIN_EXPOSURE, MAX_EXPOSURE);
+            self.exposure += (target - self.exposure) * ADAPTATION_RATE;
+        }
+        self.exposure
+    }
+}
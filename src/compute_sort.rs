@@ -0,0 +1,250 @@
+use wgpu::util::DeviceExt;
+
+use crate::uniform::dynamic_offset_stride;
+
+/// Must match `workgroup_size` in the sort shader.
+const WORKGROUP_SIZE: u32 = 256;
+
+/// Bits sorted by each pass. Must match `RADIX` in the sort shader.
+const RADIX_BITS: u32 = 4;
+const RADIX: u32 = 1 << RADIX_BITS;
+
+/// Enough passes to cover all 32 bits of the depth. The passes
+/// ping-pong between the keys and the scratch buffer, and an even
+/// number of them leaves the sorted keys back where they started.
+const NUM_PASSES: u32 = 32 / RADIX_BITS;
+
+/// A depth, and the draw it was taken from.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct SortKey {
+    pub depth: f32,
+    pub draw_index: u32,
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct PassParams {
+    shift: u32,
+    num_elements: u32,
+    num_groups: u32,
+    _padding: u32,
+}
+
+/// Sorts [`SortKey`]s by depth on the GPU, from smallest to largest,
+/// with a radix sort over 4 bits at a time.
+///
+/// Keys with the same depth keep the order they were in. Negative
+/// depths sort before positive ones, as they would on the CPU.
+pub struct ComputeSort {
+    max_elements: u32,
+    /// Where every other pass writes the keys to.
+    scratch_buffer: wgpu::Buffer,
+    /// Each workgroup's count of each digit, and then where
+    /// those elements go once they're scanned.
+    counts_buffer: wgpu::Buffer,
+    storage_layout: wgpu::BindGroupLayout,
+    params_layout: wgpu::BindGroupLayout,
+    /// How far apart each pass's params are, since they're bound at
+    /// a dynamic offset for each.
+    params_stride: wgpu::BufferAddress,
+    count_pipeline: wgpu::ComputePipeline,
+    scan_pipeline: wgpu::ComputePipeline,
+    scatter_pipeline: wgpu::ComputePipeline,
+}
+
+impl ComputeSort {
+    /// Allocates room for sorting up to `max_elements` keys at once.
+    pub fn new(device: &wgpu::Device, max_elements: u32) -> Self {
+        let max_groups = max_elements.div_ceil(WORKGROUP_SIZE).max(1);
+        let scratch_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Compute Sort Scratch Buffer"),
+            size: (max_elements.max(1) as usize * std::mem::size_of::<SortKey>())
+                as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::STORAGE,
+            mapped_at_creation: false,
+        });
+        let counts_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Compute Sort Counts Buffer"),
+            size: (RADIX * max_groups) as wgpu::BufferAddress
+                * std::mem::size_of::<u32>() as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::STORAGE,
+            mapped_at_creation: false,
+        });
+
+        let storage_entry = |binding, read_only| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        let storage_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Compute Sort Storage Bind Group Layout"),
+            entries: &[
+                storage_entry(0, true),
+                storage_entry(1, false),
+                storage_entry(2, false),
+            ],
+        });
+        let params_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Compute Sort Params Bind Group Layout"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    // Every pass reads its own part of the buffer.
+                    has_dynamic_offset: true,
+                    min_binding_size: wgpu::BufferSize::new(
+                        std::mem::size_of::<PassParams>() as u64
+                    ),
+                },
+                count: None,
+            }],
+        });
+
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Compute Sort Pipeline Layout"),
+            bind_group_layouts: &[&storage_layout, &params_layout],
+            push_constant_ranges: &[],
+        });
+        let shader = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+            label: Some("Compute Sort Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("compute_sort.wgsl").into()),
+        });
+        let create_pipeline = |label, entry_point| {
+            device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some(label),
+                layout: Some(&layout),
+                module: &shader,
+                entry_point,
+            })
+        };
+
+        ComputeSort {
+            max_elements,
+            scratch_buffer,
+            counts_buffer,
+            storage_layout,
+            params_layout,
+            params_stride: dynamic_offset_stride(device, std::mem::size_of::<PassParams>()),
+            count_pipeline: create_pipeline("Compute Sort Count Pipeline", "count"),
+            scan_pipeline: create_pipeline("Compute Sort Scan Pipeline", "scan"),
+            scatter_pipeline: create_pipeline("Compute Sort Scatter Pipeline", "scatter"),
+        }
+    }
+
+    #[allow(dead_code)]
+    pub fn max_elements(&self) -> u32 {
+        self.max_elements
+    }
+
+    /// Records the passes sorting the first `num_elements` keys of
+    /// `keys_buffer` in place. The buffer needs `STORAGE` usage.
+    ///
+    /// Keys beyond [`max_elements`](Self::max_elements) are left unsorted.
+    pub fn sort(
+        &self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        keys_buffer: &wgpu::Buffer,
+        num_elements: u32,
+    ) {
+        let num_elements = if num_elements > self.max_elements {
+            log::warn!(
+                "compute sort holds {} keys, leaving {} unsorted",
+                self.max_elements,
+                num_elements - self.max_elements
+            );
+            self.max_elements
+        } else {
+            num_elements
+        };
+        if num_elements == 0 {
+            return;
+        }
+        let num_groups = num_elements.div_ceil(WORKGROUP_SIZE);
+
+        let params_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Compute Sort Params Buffer"),
+            contents: &pass_params_bytes(num_elements, num_groups, self.params_stride),
+            usage: wgpu::BufferUsages::UNIFORM,
+        });
+        let params_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Compute Sort Params Bind Group"),
+            layout: &self.params_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                    buffer: &params_buffer,
+                    offset: 0,
+                    size: wgpu::BufferSize::new(std::mem::size_of::<PassParams>() as u64),
+                }),
+            }],
+        });
+
+        // One bind group for each direction the keys can be moved in.
+        let storage_bind_group = |src: &wgpu::Buffer, dst: &wgpu::Buffer| {
+            device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("Compute Sort Storage Bind Group"),
+                layout: &self.storage_layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: src.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: dst.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 2,
+                        resource: self.counts_buffer.as_entire_binding(),
+                    },
+                ],
+            })
+        };
+        let storage_bind_groups = [
+            storage_bind_group(keys_buffer, &self.scratch_buffer),
+            storage_bind_group(&self.scratch_buffer, keys_buffer),
+        ];
+
+        let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("Compute Sort Pass"),
+        });
+        for pass in 0..NUM_PASSES {
+            let offset = (pass as wgpu::BufferAddress * self.params_stride) as u32;
+            compute_pass.set_bind_group(0, &storage_bind_groups[pass as usize % 2], &[]);
+            compute_pass.set_bind_group(1, &params_bind_group, &[offset]);
+
+            compute_pass.set_pipeline(&self.count_pipeline);
+            compute_pass.dispatch(num_groups, 1, 1);
+            compute_pass.set_pipeline(&self.scan_pipeline);
+            compute_pass.dispatch(1, 1, 1);
+            compute_pass.set_pipeline(&self.scatter_pipeline);
+            compute_pass.dispatch(num_groups, 1, 1);
+        }
+    }
+}
+
+/// The params of every pass laid out `stride` bytes apart, for the
+/// dynamic offsets.
+fn pass_params_bytes(num_elements: u32, num_groups: u32, stride: wgpu::BufferAddress) -> Vec<u8> {
+    let mut bytes = vec![0; stride as usize * NUM_PASSES as usize];
+    for pass in 0..NUM_PASSES {
+        let params = PassParams {
+            shift: pass * RADIX_BITS,
+            num_elements,
+            num_groups,
+            _padding: 0,
+        };
+        let start = pass as usize * stride as usize;
+        bytes[start..start + std::mem::size_of::<PassParams>()]
+            .copy_from_slice(bytemuck::bytes_of(&params));
+    }
+    bytes
+}
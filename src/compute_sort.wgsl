@@ -0,0 +1,147 @@
+// One pass of a least significant digit radix sort, over pairs of a
+// depth and the index of the draw it belongs to. Each pass sorts by
+// the 4-bit digit at `shift`, keeping the order of equal digits, so
+// after eight passes the pairs are sorted by their whole depth.
+//
+// A pass runs in three steps: `count` counts the digits of every
+// workgroup's elements, `scan` turns the counts into where each
+// workgroup's elements with each digit go, and `scatter` moves the
+// elements there.
+
+let WORKGROUP_SIZE: u32 = 256u;
+let RADIX: u32 = 16u;
+
+struct SortKey {
+    depth: f32;
+    draw_index: u32;
+};
+
+[[block]]
+struct Keys {
+    keys: array<SortKey>;
+};
+
+[[block]]
+struct Counts {
+    // Indexed by `digit * num_groups + workgroup`, so a scan in
+    // order gives every digit of every workgroup its offset.
+    counts: array<u32>;
+};
+
+[[block]]
+struct Params {
+    shift: u32;
+    num_elements: u32;
+    num_groups: u32;
+};
+
+[[group(0), binding(0)]]
+var<storage, read> src: Keys;
+[[group(0), binding(1)]]
+var<storage, read_write> dst: Keys;
+[[group(0), binding(2)]]
+var<storage, read_write> counts: Counts;
+[[group(1), binding(0)]]
+var<uniform> params: Params;
+
+var<workgroup> histogram: array<atomic<u32>, 16>;
+var<workgroup> sums: array<u32, 256>;
+var<workgroup> digits: array<u32, 256>;
+
+// Flips the bits of a float so that comparing them as unsigned
+// integers orders them the same way as the floats.
+fn sortable_bits(value: f32) -> u32 {
+    let bits = bitcast<u32>(value);
+    if ((bits & 0x80000000u) != 0u) {
+        return ~bits;
+    }
+    return bits | 0x80000000u;
+}
+
+fn digit_of(key: SortKey) -> u32 {
+    return (sortable_bits(key.depth) >> params.shift) & (RADIX - 1u);
+}
+
+[[stage(compute), workgroup_size(256, 1, 1)]]
+fn count(
+    [[builtin(global_invocation_id)]] id: vec3<u32>,
+    [[builtin(local_invocation_id)]] local: vec3<u32>,
+    [[builtin(workgroup_id)]] group: vec3<u32>,
+) {
+    if (local.x < RADIX) {
+        atomicStore(&histogram[local.x], 0u);
+    }
+    workgroupBarrier();
+
+    if (id.x < params.num_elements) {
+        let previous = atomicAdd(&histogram[digit_of(src.keys[id.x])], 1u);
+    }
+    workgroupBarrier();
+
+    if (local.x < RADIX) {
+        counts.counts[local.x * params.num_groups + group.x] = atomicLoad(&histogram[local.x]);
+    }
+}
+
+// Runs as a single workgroup. Each invocation adds up a chunk of the
+// counts, the chunks are scanned, and then each chunk is scanned
+// starting from the total of the chunks before it.
+[[stage(compute), workgroup_size(256, 1, 1)]]
+fn scan([[builtin(local_invocation_id)]] local: vec3<u32>) {
+    let total = RADIX * params.num_groups;
+    let chunk = (total + WORKGROUP_SIZE - 1u) / WORKGROUP_SIZE;
+    let start = min(local.x * chunk, total);
+    let end = min(start + chunk, total);
+
+    var sum = 0u;
+    for (var i = start; i < end; i = i + 1u) {
+        sum = sum + counts.counts[i];
+    }
+    sums[local.x] = sum;
+    workgroupBarrier();
+
+    if (local.x == 0u) {
+        var running = 0u;
+        for (var i = 0u; i < WORKGROUP_SIZE; i = i + 1u) {
+            let chunk_sum = sums[i];
+            sums[i] = running;
+            running = running + chunk_sum;
+        }
+    }
+    workgroupBarrier();
+
+    var offset = sums[local.x];
+    for (var i = start; i < end; i = i + 1u) {
+        let digit_count = counts.counts[i];
+        counts.counts[i] = offset;
+        offset = offset + digit_count;
+    }
+}
+
+[[stage(compute), workgroup_size(256, 1, 1)]]
+fn scatter(
+    [[builtin(global_invocation_id)]] id: vec3<u32>,
+    [[builtin(local_invocation_id)]] local: vec3<u32>,
+    [[builtin(workgroup_id)]] group: vec3<u32>,
+) {
+    // Elements past the end get a digit no real element has.
+    var digit = RADIX;
+    if (id.x < params.num_elements) {
+        digit = digit_of(src.keys[id.x]);
+    }
+    digits[local.x] = digit;
+    workgroupBarrier();
+
+    if (id.x < params.num_elements) {
+        // Elements with the same digit keep their order, which
+        // is what makes sorting a digit at a time work.
+        var rank = 0u;
+        for (var i = 0u; i < local.x; i = i + 1u) {
+            if (digits[i] == digit) {
+                rank = rank + 1u;
+            }
+        }
+        let index = counts.counts[digit * params.num_groups + group.x] + rank;
+        dst.keys[index] = src.keys[id.x];
+    }
+}
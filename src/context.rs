@@ -0,0 +1,498 @@
+use winit::window::Window;
+
+use crate::{
+    adapter::{AdapterInfo, AdapterSelector},
+    bindless::BindlessTextureArray,
+    msaa::MsaaConfig,
+    push_constants::MAX_PUSH_CONSTANT_SIZE,
+    swapchain::{self, FrameLatencyLimiter, SwapchainConfig},
+};
+
+/// Features we make use of when the adapter has them,
+/// but can do without otherwise.
+const OPTIONAL_FEATURES: wgpu::Features = wgpu::Features::POLYGON_MODE_LINE
+    .union(wgpu::Features::TIMESTAMP_QUERY)
+    .union(wgpu::Features::MULTI_DRAW_INDIRECT)
+    .union(wgpu::Features::PUSH_CONSTANTS)
+    .union(BindlessTextureArray::REQUIRED_FEATURES);
+
+/// The optional features, by what they're used for,
+/// for reporting the ones we have to do without.
+const FEATURE_WISHLIST: &[(&str, wgpu::Features)] = &[
+    ("wireframe polygon mode", wgpu::Features::POLYGON_MODE_LINE),
+    ("timestamp queries", wgpu::Features::TIMESTAMP_QUERY),
+    ("GPU culling", wgpu::Features::MULTI_DRAW_INDIRECT),
+    ("push constants", wgpu::Features::PUSH_CONSTANTS),
+    ("bindless textures", BindlessTextureArray::REQUIRED_FEATURES),
+];
+
+/// How much to ask of the GPU.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CompatMode {
+    /// Prefers a dedicated GPU, and enables every optional
+    /// feature it has.
+    #[default]
+    HighPerformance,
+    /// Like `HighPerformance`, but prefers an integrated GPU.
+    Balanced,
+    /// Only asks for what older GPUs and the GL backend can do,
+    /// with downlevel limits and no optional features.
+    Downlevel,
+}
+
+impl CompatMode {
+    /// Reads the value of `--compat`.
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.to_lowercase().as_str() {
+            "high-performance" => Some(CompatMode::HighPerformance),
+            "balanced" => Some(CompatMode::Balanced),
+            "downlevel" => Some(CompatMode::Downlevel),
+            _ => None,
+        }
+    }
+
+    fn power_preference(self) -> wgpu::PowerPreference {
+        match self {
+            CompatMode::HighPerformance => wgpu::PowerPreference::HighPerformance,
+            CompatMode::Balanced | CompatMode::Downlevel => wgpu::PowerPreference::LowPower,
+        }
+    }
+
+    fn limits(self) -> wgpu::Limits {
+        match self {
+            CompatMode::HighPerformance | CompatMode::Balanced => wgpu::Limits::default(),
+            CompatMode::Downlevel => wgpu::Limits::downlevel_defaults(),
+        }
+    }
+
+    fn features(self) -> wgpu::Features {
+        match self {
+            CompatMode::HighPerformance | CompatMode::Balanced => OPTIONAL_FEATURES,
+            CompatMode::Downlevel => wgpu::Features::empty(),
+        }
+    }
+}
+
+/// Surface formats in the order we'd rather have them. sRGB formats come
+/// first, so colors written by shaders are gamma corrected on the way
+/// to the screen.
+const PREFERRED_SURFACE_FORMATS: [wgpu::TextureFormat; 3] = [
+    wgpu::TextureFormat::Bgra8UnormSrgb,
+    wgpu::TextureFormat::Rgba8UnormSrgb,
+    wgpu::TextureFormat::Bgra8Unorm,
+];
+
+/// Picks the format of the surface textures.
+pub struct SurfaceFormatSelector;
+
+impl SurfaceFormatSelector {
+    /// The first of `PREFERRED_SURFACE_FORMATS` in `supported`, or the
+    /// first of `supported` when it has none of them. `None` when
+    /// nothing is supported, and the surface can't be drawn to.
+    pub fn prefer_srgb(supported: &[wgpu::TextureFormat]) -> Option<wgpu::TextureFormat> {
+        let format = PREFERRED_SURFACE_FORMATS
+            .iter()
+            .find(|format| supported.contains(format))
+            .or_else(|| supported.first())
+            .copied();
+
+        match format {
+            Some(format) if PREFERRED_SURFACE_FORMATS.contains(&format) => {
+                log::info!("using surface format {:?}", format)
+            }
+            Some(format) => log::warn!("surface only supports {:?}", format),
+            None => log::error!("surface supports no formats"),
+        }
+        format
+    }
+
+    /// The formats the adapter can present to the surface with.
+    ///
+    /// wgpu 0.11 only tells us the one format it prefers, which it
+    /// picks from these same formats in the same order, with
+    /// `Rgba8Unorm` after them. Nothing means the adapter can't
+    /// present to the surface at all.
+    pub fn supported_formats(
+        adapter: &wgpu::Adapter,
+        surface: &wgpu::Surface,
+    ) -> Vec<wgpu::TextureFormat> {
+        surface.get_preferred_format(adapter).into_iter().collect()
+    }
+}
+
+/// Handles to the GPU which are shared by every render pipeline.
+pub struct GpuContext {
+    pub surface: wgpu::Surface,
+    /// Kept around to query what the hardware supports.
+    pub adapter: wgpu::Adapter,
+    pub device: wgpu::Device,
+    pub queue: wgpu::Queue,
+    pub config: wgpu::SurfaceConfiguration,
+    pub size: winit::dpi::PhysicalSize<u32>,
+    /// Whether the surface format is sRGB. When it's linear,
+    /// shaders have to gamma correct what they write themselves.
+    pub is_srgb: bool,
+    /// Optional features supported by the adapter, which
+    /// were enabled on the device.
+    pub features: wgpu::Features,
+    #[allow(dead_code)]
+    unsupported_features: Vec<String>,
+    swapchain: SwapchainConfig,
+    supported_present_modes: &'static [wgpu::PresentMode],
+    frame_latency: FrameLatencyLimiter,
+}
+
+impl GpuContext {
+    /// Every adapter on every backend, including ones that can't
+    /// present to our window.
+    pub fn enumerate_adapters(instance: &wgpu::Instance) -> Vec<AdapterInfo> {
+        instance
+            .enumerate_adapters(wgpu::Backends::all())
+            .map(|adapter| adapter.get_info().into())
+            .collect()
+    }
+
+    /// Opens a device on the first adapter matching `selector`, or the
+    /// one wgpu prefers when there's no selector or nothing matches.
+    pub async fn new(
+        window: &Window,
+        selector: Option<&AdapterSelector>,
+        mode: CompatMode,
+    ) -> Self {
+        let size = window.inner_size();
+
+        // The instance is a handle to our GPU
+        // Backends::all => Vulkan + Metal + DX12 + Browser WebGPU
+        let instance = wgpu::Instance::new(wgpu::Backends::all());
+        let surface = unsafe { instance.create_surface(window) };
+
+        // The adapter is a handle to our actual graphics card.
+        // We can use this get information about the graphics
+        // card such as its name and what backend the adapter
+        // uses. We use this to create our Device and Queue later.
+        //
+        // In debug builds we list every adapter first, to help
+        // with picking one with `--adapter`.
+        if cfg!(debug_assertions) {
+            for info in Self::enumerate_adapters(&instance) {
+                log::info!("found adapter {}", info);
+            }
+        }
+        let selected = selector.and_then(|selector| {
+            let adapter = instance
+                .enumerate_adapters(wgpu::Backends::all())
+                .filter(|adapter| adapter.is_surface_supported(&surface))
+                .find(|adapter| selector.matches(&adapter.get_info().into()));
+            if adapter.is_none() {
+                log::warn!("no adapter matches {:?}, using the default", selector);
+            }
+            adapter
+        });
+        let adapter = match selected {
+            Some(adapter) => adapter,
+            None => Self::request_adapter(&instance, &surface, mode).await,
+        };
+        log::info!("using adapter {}", AdapterInfo::from(adapter.get_info()));
+
+        // Only ask for the optional features the adapter actually has,
+        // otherwise creating the device fails.
+        let features = adapter.features() & mode.features();
+        let mut limits = mode.limits();
+        // Push constants have no room by default, so they get as much
+        // as pipelines are allowed to use.
+        if features.contains(wgpu::Features::PUSH_CONSTANTS) {
+            limits.max_push_constant_size = adapter
+                .limits()
+                .max_push_constant_size
+                .min(MAX_PUSH_CONSTANT_SIZE);
+        }
+        // Every slot of the bindless texture array counts towards the
+        // sampled textures, so it gets as many as the adapter has.
+        if features.contains(BindlessTextureArray::REQUIRED_FEATURES) {
+            limits.max_sampled_textures_per_shader_stage =
+                adapter.limits().max_sampled_textures_per_shader_stage;
+        }
+
+        // Requests a connection to a physical device, creating a logical device.
+        let (device, queue) = adapter
+            .request_device(
+                &wgpu::DeviceDescriptor {
+                    // Allows us to specify what extra features we want.
+                    //
+                    // We can get a list of features supported by our
+                    // device using `adapter.features()`, or `device.features()`
+                    features,
+                    // The limits field describes the limit of certain
+                    // types of resources we can create. If any requested
+                    // limits are beyond the hardware device, creation
+                    // will fail.
+                    limits,
+                    // Debug label for the device.
+                    label: Some("Adapter"),
+                },
+                // trace_path - Can be used for API call tracing,
+                //              if that feature is enabled in wgpu-core.
+                None,
+            )
+            .await
+            .expect("failed to create device");
+
+        // This will define how the surface creates its underlying `SurfaceTexture`
+        let supported_present_modes =
+            swapchain::supported_present_modes(adapter.get_info().backend);
+        let swapchain = SwapchainConfig::default().resolve(supported_present_modes);
+        let config = wgpu::SurfaceConfiguration {
+            // `usage` field describes how the `SurfaceTexture`s
+            // will be used. RENDER_ATTACHMENT specifies that the
+            // textures will be used to write to the screen.
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            // `format` defines how the `SurfaceTexture`s will be
+            // stored on the gpu. Different displays prefer different
+            // formats. We pick an sRGB format when the display has
+            // one, so colors look the same across platforms.
+            format: SurfaceFormatSelector::prefer_srgb(&SurfaceFormatSelector::supported_formats(
+                &adapter, &surface,
+            ))
+            .expect("adapter can't present to the surface"),
+            // The width and height in pixels of the SurfaceTexture.
+            // This should usually be the width and height of the window.
+            //
+            // WARNING: Make sure that the width and height of the
+            //          `SurfaceTexture` are not 0, as that can
+            //          cause the app to crash.
+            width: size.width,
+            height: size.height,
+            // Determines how to sync the surface with the display.
+            // We start with FIFO, which will cap the display rate
+            // at the displays framerate. This is essentially VSync
+            //  This is also the most optimal mode on mobile.
+            present_mode: swapchain.present_mode,
+        };
+        surface.configure(&device, &config);
+
+        let unsupported_features = report_unsupported_features(&adapter, features, config.format);
+        for feature in &unsupported_features {
+            log::warn!("{}", feature);
+        }
+
+        GpuContext {
+            surface,
+            adapter,
+            device,
+            queue,
+            is_srgb: config.format.describe().srgb,
+            config,
+            size,
+            features,
+            unsupported_features,
+            swapchain,
+            supported_present_modes,
+            frame_latency: FrameLatencyLimiter::new(),
+        }
+    }
+
+    /// The adapter wgpu thinks is best for presenting to `surface`.
+    async fn request_adapter(
+        instance: &wgpu::Instance,
+        surface: &wgpu::Surface,
+        mode: CompatMode,
+    ) -> wgpu::Adapter {
+        instance
+            .request_adapter(&wgpu::RequestAdapterOptions {
+                // wgpu can pick between low power devices like integrated graphics,
+                // or high power consumption like a dedicated card.
+                power_preference: mode.power_preference(),
+                // Tells wgpu to find an adapter that can present to the supplied surface.
+                // Our window needs to implement raw-window-handle's HasRawWindowHandle
+                // trait to create a surface.
+                compatible_surface: Some(surface),
+                // Forces wgpu to pick an adapter that will work on all harware.
+                // This usually means that the rendering backend will use a
+                // "software" system, instead of hardware such as a GPU.
+                force_fallback_adapter: false,
+            })
+            .await
+            .expect("failed to create adapter")
+    }
+
+    /// What we'd use if we could, but had to do without on this
+    /// adapter, or in the chosen compatibility mode.
+    #[allow(dead_code)]
+    pub fn unsupported_features(&self) -> &[String] {
+        &self.unsupported_features
+    }
+
+    /// Whether the given optional features were enabled on the device.
+    pub fn supports(&self, features: wgpu::Features) -> bool {
+        self.features.contains(features)
+    }
+
+    /// Reconfigures the surface for the new size. Sizes under
+    /// 1x1 are ignored, since they'd crash the app.
+    ///
+    /// Fails with `Lost` when the adapter can't present to the surface
+    /// anymore, which is the only error `configure` would have given.
+    pub fn resize(
+        &mut self,
+        new_size: winit::dpi::PhysicalSize<u32>,
+    ) -> Result<(), wgpu::SurfaceError> {
+        if new_size.width < 1 || new_size.height < 1 {
+            return Ok(());
+        }
+
+        self.size = new_size;
+        self.config.width = new_size.width;
+        self.config.height = new_size.height;
+        self.reconfigure()
+    }
+
+    /// The settings the surface was last configured with, with
+    /// `Fifo` in place of a present mode it doesn't support.
+    pub fn swapchain_config(&self) -> SwapchainConfig {
+        self.swapchain
+    }
+
+    /// Reconfigures the surface to present frames the way `swapchain`
+    /// says, or with `Fifo` when it doesn't support the present mode.
+    pub fn set_swapchain_config(
+        &mut self,
+        swapchain: SwapchainConfig,
+    ) -> Result<(), wgpu::SurfaceError> {
+        let swapchain = swapchain.resolve(self.supported_present_modes);
+        self.config.present_mode = swapchain.present_mode;
+        self.swapchain = swapchain;
+        self.reconfigure()
+    }
+
+    /// Waits for the GPU when the frames submitted but not yet
+    /// finished reach the swapchain's frame latency.
+    pub fn wait_for_frame_latency(&mut self) {
+        self.frame_latency
+            .wait(&self.device, self.swapchain.desired_maximum_frame_latency);
+    }
+
+    /// Should be called once a frame's work has been submitted, for
+    /// [`wait_for_frame_latency`](Self::wait_for_frame_latency).
+    pub fn frame_submitted(&mut self) {
+        self.frame_latency.submitted(&self.queue);
+    }
+
+    fn reconfigure(&mut self) -> Result<(), wgpu::SurfaceError> {
+        // `configure` panics instead of returning its errors, so the
+        // surface is checked first. It has no preferred format once
+        // the adapter can't present to it, like after its window's
+        // display has gone.
+        if self.surface.get_preferred_format(&self.adapter).is_none() {
+            return Err(wgpu::SurfaceError::Lost);
+        }
+        self.surface.configure(&self.device, &self.config);
+        Ok(())
+    }
+}
+
+/// Describes every feature on the wishlist that wasn't enabled,
+/// and whether it's the adapter or the compatibility mode to blame.
+fn report_unsupported_features(
+    adapter: &wgpu::Adapter,
+    enabled: wgpu::Features,
+    format: wgpu::TextureFormat,
+) -> Vec<String> {
+    let mut report = Vec::new();
+    for (name, feature) in FEATURE_WISHLIST {
+        if enabled.contains(*feature) {
+            continue;
+        }
+        if adapter.features().contains(*feature) {
+            report.push(format!("{} disabled by the compatibility mode", name));
+        } else {
+            report.push(format!("{} not supported by the adapter", name));
+        }
+    }
+
+    // Multisampling isn't a feature, but depends on the surface format.
+    if !MsaaConfig::new(4).is_supported(adapter, format) {
+        report.push(format!("4x MSAA not supported for {:?}", format));
+    }
+
+    report
+}
+
+/// A device on whatever adapter there is, without a window, for tests
+/// that need the GPU. `None` when there's no adapter at all, so the
+/// tests can skip themselves on machines without one.
+#[cfg(test)]
+pub fn test_device() -> Option<(wgpu::Device, wgpu::Queue)> {
+    request_test_device(wgpu::Features::empty(), |_| {
+        wgpu::Limits::downlevel_defaults()
+    })
+}
+
+/// Like [`test_device`], but with `features` and as much of every
+/// limit as the adapter has. `None` as well when the adapter doesn't
+/// have all of the features.
+#[cfg(test)]
+pub fn test_device_with(features: wgpu::Features) -> Option<(wgpu::Device, wgpu::Queue)> {
+    request_test_device(features, wgpu::Adapter::limits)
+}
+
+#[cfg(test)]
+fn request_test_device(
+    features: wgpu::Features,
+    limits: impl FnOnce(&wgpu::Adapter) -> wgpu::Limits,
+) -> Option<(wgpu::Device, wgpu::Queue)> {
+    let instance = wgpu::Instance::new(wgpu::Backends::all());
+    let adapter = pollster::block_on(instance.request_adapter(&wgpu::RequestAdapterOptions {
+        power_preference: wgpu::PowerPreference::default(),
+        compatible_surface: None,
+        force_fallback_adapter: false,
+    }))?;
+    if !adapter.features().contains(features) {
+        return None;
+    }
+    pollster::block_on(adapter.request_device(
+        &wgpu::DeviceDescriptor {
+            label: Some("Test Device"),
+            features,
+            limits: limits(&adapter),
+        },
+        None,
+    ))
+    .ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wgpu::TextureFormat;
+
+    #[test]
+    fn prefers_srgb_formats_in_order() {
+        let supported = [
+            TextureFormat::Bgra8Unorm,
+            TextureFormat::Rgba8UnormSrgb,
+            TextureFormat::Bgra8UnormSrgb,
+        ];
+        assert_eq!(
+            SurfaceFormatSelector::prefer_srgb(&supported),
+            Some(TextureFormat::Bgra8UnormSrgb)
+        );
+        assert_eq!(
+            SurfaceFormatSelector::prefer_srgb(&supported[..2]),
+            Some(TextureFormat::Rgba8UnormSrgb)
+        );
+        assert_eq!(
+            SurfaceFormatSelector::prefer_srgb(&supported[..1]),
+            Some(TextureFormat::Bgra8Unorm)
+        );
+    }
+
+    #[test]
+    fn falls_back_to_first_supported_format() {
+        let supported = [TextureFormat::Rgba8Unorm, TextureFormat::Rgba16Float];
+        assert_eq!(
+            SurfaceFormatSelector::prefer_srgb(&supported),
+            Some(TextureFormat::Rgba8Unorm)
+        );
+        assert_eq!(SurfaceFormatSelector::prefer_srgb(&[]), None);
+    }
+}
@@ -0,0 +1,257 @@
+use cgmath::{EuclideanSpace, Matrix4, MetricSpace, Point3, SquareMatrix, Transform as _};
+
+use crate::{
+    camera::{Camera, CameraProjection},
+    light::DirectionalLight,
+    shadow::ShadowPass,
+};
+
+/// How many cascades the view is split into. Must match the shadow
+/// maps bound in `shader.wgsl`.
+pub const CASCADE_COUNT: usize = 4;
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct CsmUniform {
+    light_view_proj: [[[f32; 4]; 4]; CASCADE_COUNT],
+    /// The camera's view, for working out how far a fragment is in
+    /// front of it.
+    view: [[f32; 4]; 4],
+    /// How far in front of the camera each cascade ends.
+    splits: [f32; CASCADE_COUNT],
+}
+
+/// One slice of the camera's frustum, with a shadow map of its own.
+pub struct ShadowCascade {
+    pub pass: ShadowPass,
+    /// How far in front of the camera the slice ends. It starts where
+    /// the cascade before it ends, or at the near plane.
+    pub far: f32,
+}
+
+/// Shadows from the first directional light over a long way in front
+/// of the camera, without the texels of the shadow map getting huge
+/// up close.
+///
+/// The frustum is split into [`CASCADE_COUNT`] slices along the view,
+/// each covered by a shadow map of its own. Slices further away are
+/// longer, so the shadow maps spread over more of the scene the
+/// further it is, where it takes up less of the screen. The main
+/// shader picks the cascade by how far each fragment is in front of
+/// the camera, and softens the shadow with PCF like the single map.
+///
+/// Each cascade's map covers the sphere around its slice, which stays
+/// the same size however the camera turns, and is moved in whole
+/// texels so the shadows' edges don't shimmer as the camera moves.
+pub struct CsmShadowPass {
+    cascades: [ShadowCascade; CASCADE_COUNT],
+    /// Blends between splitting the view evenly, at 0, and
+    /// logarithmically, at 1.
+    split_lambda: f32,
+    /// Nothing further than this from the camera casts shadows.
+    max_distance: f32,
+    uniform_buffer: wgpu::Buffer,
+    bind_group_layout: wgpu::BindGroupLayout,
+    bind_group: wgpu::BindGroup,
+}
+
+impl CsmShadowPass {
+    /// Entries in the bind group: the uniform, the cascades' shadow
+    /// maps and the sampler.
+    pub const BIND_GROUP_ENTRIES: usize = CASCADE_COUNT + 2;
+
+    pub fn new(device: &wgpu::Device, split_lambda: f32, max_distance: f32) -> Self {
+        let cascades = [(); CASCADE_COUNT].map(|_| ShadowCascade {
+            pass: ShadowPass::new(device),
+            far: 0.0,
+        });
+
+        let uniform_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Cascaded Shadow Uniform"),
+            size: std::mem::size_of::<CsmUniform>() as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        // Compares like the single shadow map's sampler.
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Cascaded Shadow Sampler"),
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            compare: Some(wgpu::CompareFunction::LessEqual),
+            ..Default::default()
+        });
+
+        // The uniform, then a shadow map for each cascade, then the sampler.
+        let mut layout_entries = vec![wgpu::BindGroupLayoutEntry {
+            binding: 0,
+            visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        }];
+        layout_entries.extend((1..=CASCADE_COUNT as u32).map(|binding| {
+            wgpu::BindGroupLayoutEntry {
+                binding,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Texture {
+                    multisampled: false,
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                    sample_type: wgpu::TextureSampleType::Depth,
+                },
+                count: None,
+            }
+        }));
+        layout_entries.push(wgpu::BindGroupLayoutEntry {
+            binding: CASCADE_COUNT as u32 + 1,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Sampler {
+                filtering: true,
+                comparison: true,
+            },
+            count: None,
+        });
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Cascaded Shadow Bind Group Layout"),
+            entries: &layout_entries,
+        });
+
+        let mut entries = vec![wgpu::BindGroupEntry {
+            binding: 0,
+            resource: uniform_buffer.as_entire_binding(),
+        }];
+        entries.extend(
+            (1..)
+                .zip(&cascades)
+                .map(|(binding, cascade)| wgpu::BindGroupEntry {
+                    binding,
+                    resource: wgpu::BindingResource::TextureView(cascade.pass.view()),
+                }),
+        );
+        entries.push(wgpu::BindGroupEntry {
+            binding: CASCADE_COUNT as u32 + 1,
+            resource: wgpu::BindingResource::Sampler(&sampler),
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Cascaded Shadow Bind Group"),
+            layout: &bind_group_layout,
+            entries: &entries,
+        });
+
+        CsmShadowPass {
+            cascades,
+            split_lambda,
+            max_distance,
+            uniform_buffer,
+            bind_group_layout,
+            bind_group,
+        }
+    }
+
+    /// Where each of the cascades between `near` and `far` ends, by
+    /// the practical split scheme: `lambda` of the way from an even
+    /// split to a logarithmic one. Logarithmic splits keep the shadow
+    /// maps' texels the same size on screen, but leave the nearest
+    /// cascade tiny, so they're usually blended with even ones.
+    pub fn compute_splits(near: f32, far: f32, lambda: f32) -> [f32; CASCADE_COUNT] {
+        let mut splits = [far; CASCADE_COUNT];
+        for (i, split) in splits.iter_mut().enumerate() {
+            let fraction = (i + 1) as f32 / CASCADE_COUNT as f32;
+            let logarithmic = near * (far / near).powf(fraction);
+            let uniform = near + (far - near) * fraction;
+            *split = lambda * logarithmic + (1.0 - lambda) * uniform;
+        }
+        splits
+    }
+
+    pub fn cascades(&self) -> &[ShadowCascade] {
+        &self.cascades
+    }
+
+    #[allow(dead_code)]
+    pub fn split_lambda(&self) -> f32 {
+        self.split_lambda
+    }
+
+    /// Layout of the bind group the main pass samples the cascades with.
+    pub fn bind_group_layout(&self) -> &wgpu::BindGroupLayout {
+        &self.bind_group_layout
+    }
+
+    pub fn bind_group(&self) -> &wgpu::BindGroup {
+        &self.bind_group
+    }
+
+    /// Splits `camera`'s view into cascades, and fits a shadow map of
+    /// `light` around each. Should be called once the camera has moved.
+    pub fn update(&mut self, queue: &wgpu::Queue, camera: &Camera, light: &DirectionalLight) {
+        let (near, camera_far) = match camera.projection {
+            CameraProjection::Perspective { near, far, .. }
+            | CameraProjection::Orthographic { near, far, .. } => (near, far),
+        };
+        let far = camera_far.min(self.max_distance);
+        let splits = Self::compute_splits(near, far, self.split_lambda);
+
+        // The corners of the whole frustum, near then far, to slice it up.
+        let inv_view_proj = camera
+            .build_unjittered_view_projection_matrix()
+            .invert()
+            .unwrap_or_else(Matrix4::identity);
+        let corner = |x: f32, y: f32, z: f32| inv_view_proj.transform_point(Point3::new(x, y, z));
+        let edges = [(-1.0, -1.0), (1.0, -1.0), (-1.0, 1.0), (1.0, 1.0)]
+            .map(|(x, y)| (corner(x, y, 0.0), corner(x, y, 1.0)));
+        // Corners move along the frustum's edges at the same rate as
+        // their distance in front of the camera.
+        let along_edges = |distance: f32| {
+            let t = (distance - near) / (camera_far - near);
+            edges.map(|(near_corner, far_corner)| near_corner + (far_corner - near_corner) * t)
+        };
+
+        let mut uniform = CsmUniform {
+            light_view_proj: [Matrix4::identity().into(); CASCADE_COUNT],
+            view: camera.build_view_matrix().into(),
+            splits,
+        };
+        let mut start = near;
+        for (i, cascade) in self.cascades.iter_mut().enumerate() {
+            let end = splits[i];
+            let mut corners = along_edges(start).to_vec();
+            corners.extend_from_slice(&along_edges(end));
+            let center = corners
+                .iter()
+                .fold(Point3::new(0.0, 0.0, 0.0), |sum, corner| {
+                    sum + corner.to_vec() / corners.len() as f32
+                });
+            let radius = corners
+                .iter()
+                .map(|corner| corner.distance(center))
+                .fold(0.0, f32::max);
+
+            let light_view_proj =
+                snap_to_texels(Matrix4::from(light.view_projection_matrix(center, radius)));
+            cascade.pass.update_light(queue, light_view_proj.into());
+            cascade.far = end;
+            uniform.light_view_proj[i] = light_view_proj.into();
+            start = end;
+        }
+
+        queue.write_buffer(&self.uniform_buffer, 0, bytemuck::bytes_of(&uniform));
+    }
+}
+
+/// Moves `light_view_proj` by less than a texel of the shadow map, so
+/// the world's origin lands on a texel's corner. The texels then stay
+/// in the same places in the world as the cascade follows the camera.
+fn snap_to_texels(light_view_proj: Matrix4<f32>) -> Matrix4<f32> {
+    let half_size = ShadowPass::SIZE as f32 * 0.5;
+    let origin = light_view_proj.transform_point(Point3::new(0.0, 0.0, 0.0));
+    let texels = cgmath::vec2(origin.x, origin.y) * half_size;
+    let offset = cgmath::vec2(texels.x.round() - texels.x, texels.y.round() - texels.y) / half_size;
+    Matrix4::from_translation(cgmath::vec3(offset.x, offset.y, 0.0)) * light_view_proj
+}
@@ -0,0 +1,322 @@
+use cgmath::{Matrix4, Point3, Vector3};
+
+use crate::{camera::OPENGL_TO_WGPU_MATRIX, depth::DepthBuffer};
+
+/// Format of the captured cubemap, bright enough for HDR scenes.
+pub const FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+
+/// Where each face looks, and which way is up on it, in the order
+/// of the cubemap's layers: +X, -X, +Y, -Y, +Z, -Z.
+#[rustfmt::skip]
+const FACES: [([f32; 3], [f32; 3]); 6] = [
+    ([ 1.0,  0.0,  0.0], [0.0, 1.0,  0.0]),
+    ([-1.0,  0.0,  0.0], [0.0, 1.0,  0.0]),
+    ([ 0.0,  1.0,  0.0], [0.0, 0.0, -1.0]),
+    ([ 0.0, -1.0,  0.0], [0.0, 0.0,  1.0]),
+    ([ 0.0,  0.0,  1.0], [0.0, 1.0,  0.0]),
+    ([ 0.0,  0.0, -1.0], [0.0, 1.0,  0.0]),
+];
+
+/// One face of a [`CubemapCapture`] to render the scene into.
+pub struct CubeFace<'a> {
+    /// The face's layer in the cubemap.
+    pub index: u32,
+    pub view: &'a wgpu::TextureView,
+    /// Depth and stencil the size of the face, cleared by the caller.
+    pub depth_view: &'a wgpu::TextureView,
+    pub view_matrix: Matrix4<f32>,
+    pub projection: Matrix4<f32>,
+}
+
+impl CubeFace<'_> {
+    pub fn view_projection(&self) -> [[f32; 4]; 4] {
+        (self.projection * self.view_matrix).into()
+    }
+}
+
+/// Renders the scene around a point into the six faces of a cubemap,
+/// for reflections of things that move.
+///
+/// Cubemap faces are laid out as if seen from inside a left handed
+/// cube, so each face comes out mirrored compared to what a camera
+/// would see. Triangles wind clockwise in them, and pipelines drawing
+/// into a face need [`wgpu::FrontFace::Cw`] to cull the right side.
+pub struct CubemapCapture {
+    texture: wgpu::Texture,
+    face_views: Vec<wgpu::TextureView>,
+    cube_view: wgpu::TextureView,
+    resolution: u32,
+    /// Only created when the depth buffer given to capture with
+    /// doesn't fit the faces.
+    depth: Option<DepthBuffer>,
+    near: f32,
+    far: f32,
+}
+
+impl CubemapCapture {
+    /// A cubemap with faces `resolution` texels wide, seeing from 0.1
+    /// to 100 units away.
+    pub fn new(device: &wgpu::Device, resolution: u32) -> Self {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Captured Cubemap"),
+            // A cubemap is stored as a 2D texture with a layer per face.
+            size: wgpu::Extent3d {
+                width: resolution,
+                height: resolution,
+                depth_or_array_layers: 6,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: FORMAT,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+        });
+        let face_views = (0..6)
+            .map(|face| {
+                texture.create_view(&wgpu::TextureViewDescriptor {
+                    label: Some("Captured Cubemap Face"),
+                    dimension: Some(wgpu::TextureViewDimension::D2),
+                    base_array_layer: face,
+                    array_layer_count: std::num::NonZeroU32::new(1),
+                    ..Default::default()
+                })
+            })
+            .collect();
+        let cube_view = texture.create_view(&wgpu::TextureViewDescriptor {
+            label: Some("Captured Cubemap View"),
+            dimension: Some(wgpu::TextureViewDimension::Cube),
+            ..Default::default()
+        });
+
+        CubemapCapture {
+            texture,
+            face_views,
+            cube_view,
+            resolution,
+            depth: None,
+            near: 0.1,
+            far: 100.0,
+        }
+    }
+
+    pub fn texture(&self) -> &wgpu::Texture {
+        &self.texture
+    }
+
+    /// The captured scene, to be sampled as a cubemap.
+    pub fn view(&self) -> &wgpu::TextureView {
+        &self.cube_view
+    }
+
+    pub fn resolution(&self) -> u32 {
+        self.resolution
+    }
+
+    /// How near and far from the center the faces see.
+    pub fn set_depth_range(&mut self, near: f32, far: f32) {
+        self.near = near;
+        self.far = far;
+    }
+
+    /// Calls `render` to draw each face of the cubemap, seen from
+    /// `center`, into the face's view.
+    ///
+    /// Attachments in a pass have to be the same size, so `depth`, like
+    /// the main depth buffer, is only drawn with when it's single
+    /// sampled and as big as the faces. Otherwise a depth buffer is
+    /// made for the faces the first time it's needed.
+    pub fn capture(
+        &mut self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        center: [f32; 3],
+        depth: Option<&DepthBuffer>,
+        mut render: impl FnMut(&mut wgpu::CommandEncoder, CubeFace<'_>),
+    ) {
+        let resolution = self.resolution;
+        let fits = |depth: &&DepthBuffer| {
+            depth.sample_count() == 1 && depth.width() == resolution && depth.height() == resolution
+        };
+        let depth = match depth.filter(fits) {
+            Some(depth) => depth,
+            None => &*self
+                .depth
+                .get_or_insert_with(|| DepthBuffer::new(device, resolution, resolution)),
+        };
+
+        // Mirrored across x, so the faces come out the way cubemaps
+        // are sampled.
+        let mirror = Matrix4::from_nonuniform_scale(-1.0, 1.0, 1.0);
+        let projection = mirror
+            * OPENGL_TO_WGPU_MATRIX
+            * cgmath::perspective(cgmath::Deg(90.0), 1.0, self.near, self.far);
+        for (index, view) in self.face_views.iter().enumerate() {
+            render(
+                encoder,
+                CubeFace {
+                    index: index as u32,
+                    view,
+                    depth_view: depth.view(),
+                    view_matrix: face_view_matrix(center, index),
+                    projection,
+                },
+            );
+        }
+    }
+}
+
+/// Looks from `center` through the face at `index` of [`FACES`].
+fn face_view_matrix(center: [f32; 3], index: usize) -> Matrix4<f32> {
+    let (direction, up) = FACES[index];
+    Matrix4::look_to_rh(
+        Point3::from(center),
+        Vector3::from(direction),
+        Vector3::from(up),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::context::test_device;
+    use cgmath::{InnerSpace, Transform};
+
+    #[test]
+    fn faces_look_along_their_axes() {
+        let center = [1.0, 2.0, 3.0];
+        for (index, (direction, up)) in FACES.iter().enumerate() {
+            let view = face_view_matrix(center, index);
+            let ahead = Point3::from(center) + Vector3::from(*direction) * 2.0;
+            let ahead = view.transform_point(ahead);
+            assert!(
+                (Vector3::new(ahead.x, ahead.y, ahead.z) - Vector3::new(0.0, 0.0, -2.0))
+                    .magnitude()
+                    < 1e-5,
+                "face {} sees {:?} ahead",
+                index,
+                ahead
+            );
+            let up = view.transform_vector(Vector3::from(*up));
+            assert!(
+                (up - Vector3::unit_y()).magnitude() < 1e-5,
+                "face {} has {:?} up",
+                index,
+                up
+            );
+        }
+    }
+
+    /// The bits of `value` as a half float, for the small whole
+    /// numbers the faces are cleared with.
+    fn half_bits(value: u16) -> u16 {
+        match value {
+            0 => 0,
+            // Exponent and mantissa, with the leading one implied.
+            _ => {
+                let exponent = 15 - value.leading_zeros() as u16;
+                let mantissa = (value << (10 - exponent)) & 0x3ff;
+                ((exponent + 15) << 10) | mantissa
+            }
+        }
+    }
+
+    #[test]
+    fn captures_every_face_reusing_depth_that_fits() {
+        let (device, queue) = match test_device() {
+            Some(device) => device,
+            None => return eprintln!("skipped, there's no adapter"),
+        };
+        // Rows of 32 texels of 8 bytes are the 256 bytes copies need.
+        const RESOLUTION: u32 = 32;
+        let mut capture = CubemapCapture::new(&device, RESOLUTION);
+        let depth = DepthBuffer::new(&device, RESOLUTION, RESOLUTION);
+
+        let mut encoder = device.create_command_encoder(&Default::default());
+        let mut faces = Vec::new();
+        capture.capture(
+            &device,
+            &mut encoder,
+            [0.0; 3],
+            Some(&depth),
+            |encoder, face| {
+                faces.push(face.index);
+                encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                    label: None,
+                    color_attachments: &[wgpu::RenderPassColorAttachment {
+                        view: face.view,
+                        resolve_target: None,
+                        ops: wgpu::Operations {
+                            load: wgpu::LoadOp::Clear(wgpu::Color {
+                                r: face.index as f64,
+                                g: 0.0,
+                                b: 0.0,
+                                a: 1.0,
+                            }),
+                            store: true,
+                        },
+                    }],
+                    depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                        view: face.depth_view,
+                        depth_ops: Some(wgpu::Operations {
+                            load: wgpu::LoadOp::Clear(1.0),
+                            store: true,
+                        }),
+                        stencil_ops: None,
+                    }),
+                });
+            },
+        );
+        assert_eq!(faces, [0, 1, 2, 3, 4, 5]);
+        assert!(
+            capture.depth.is_none(),
+            "a depth buffer was made for the faces"
+        );
+
+        let bytes_per_row = RESOLUTION * 8;
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: None,
+            size: (bytes_per_row * RESOLUTION * 6) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        encoder.copy_texture_to_buffer(
+            capture.texture().as_image_copy(),
+            wgpu::ImageCopyBuffer {
+                buffer: &buffer,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: std::num::NonZeroU32::new(bytes_per_row),
+                    rows_per_image: std::num::NonZeroU32::new(RESOLUTION),
+                },
+            },
+            wgpu::Extent3d {
+                width: RESOLUTION,
+                height: RESOLUTION,
+                depth_or_array_layers: 6,
+            },
+        );
+        queue.submit(Some(encoder.finish()));
+
+        let slice = buffer.slice(..);
+        let mapping = slice.map_async(wgpu::MapMode::Read);
+        device.poll(wgpu::Maintain::Wait);
+        pollster::block_on(mapping).expect("cubemap is read back");
+        let texels = bytemuck::cast_slice::<u8, [u16; 4]>(&slice.get_mapped_range()).to_vec();
+        let face_texels = (RESOLUTION * RESOLUTION) as usize;
+        for (face, texels) in texels.chunks(face_texels).enumerate() {
+            assert!(
+                texels
+                    .iter()
+                    .all(|texel| texel[0] == half_bits(face as u16)),
+                "face {} wasn't drawn into its own layer",
+                face
+            );
+        }
+
+        // Too big for the faces, so they get a depth buffer of their own.
+        let big_depth = DepthBuffer::new(&device, RESOLUTION * 2, RESOLUTION * 2);
+        let mut encoder = device.create_command_encoder(&Default::default());
+        capture.capture(&device, &mut encoder, [0.0; 3], Some(&big_depth), |_, _| {});
+        assert!(capture.depth.is_some());
+    }
+}
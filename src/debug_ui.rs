@@ -0,0 +1,263 @@
+use winit::event::MouseButton;
+
+use crate::{
+    input::InputState,
+    sprite::{SpriteImage, SpriteInstance, SpriteRenderer},
+    text::TextRenderer,
+};
+
+/// Sizes of the panel in logical pixels, scaled by the window's
+/// scale factor when it's drawn.
+const PANEL_WIDTH: f32 = 200.0;
+const PANEL_MARGIN: f32 = 8.0;
+/// Below the frame rate in the top right corner.
+const PANEL_TOP: f32 = 40.0;
+const PADDING: f32 = 8.0;
+const ROW_HEIGHT: f32 = 28.0;
+const TRACK_HEIGHT: f32 = 6.0;
+const HANDLE_WIDTH: f32 = 6.0;
+/// Scale of the labels, relative to their size in the font.
+const TEXT_SCALE: f32 = 1.0;
+
+/// The scene's parameters that can be tweaked while it runs, fed
+/// into their uniforms every frame.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SceneSettings {
+    pub clear_color: [f32; 3],
+    /// Where the sun shines, normalized when it's used.
+    pub light_direction: [f32; 3],
+    /// Multiplies the sun's color.
+    pub light_intensity: f32,
+    pub bloom_threshold: f32,
+    /// Vertical field of view of the perspective camera, in degrees.
+    pub fov: f32,
+}
+
+struct Slider {
+    label: &'static str,
+    min: f32,
+    max: f32,
+}
+
+const SLIDERS: [Slider; 9] = [
+    Slider {
+        label: "clear r",
+        min: 0.0,
+        max: 1.0,
+    },
+    Slider {
+        label: "clear g",
+        min: 0.0,
+        max: 1.0,
+    },
+    Slider {
+        label: "clear b",
+        min: 0.0,
+        max: 1.0,
+    },
+    Slider {
+        label: "light x",
+        min: -1.0,
+        max: 1.0,
+    },
+    Slider {
+        label: "light y",
+        min: -1.0,
+        max: 1.0,
+    },
+    Slider {
+        label: "light z",
+        min: -1.0,
+        max: 1.0,
+    },
+    Slider {
+        label: "intensity",
+        min: 0.0,
+        max: 4.0,
+    },
+    Slider {
+        label: "bloom threshold",
+        min: 0.0,
+        max: 2.0,
+    },
+    Slider {
+        label: "fov",
+        min: 20.0,
+        max: 120.0,
+    },
+];
+
+impl SceneSettings {
+    /// The setting behind the slider at `index` of [`SLIDERS`].
+    fn value(&self, index: usize) -> f32 {
+        match index {
+            0..=2 => self.clear_color[index],
+            3..=5 => self.light_direction[index - 3],
+            6 => self.light_intensity,
+            7 => self.bloom_threshold,
+            _ => self.fov,
+        }
+    }
+
+    fn value_mut(&mut self, index: usize) -> &mut f32 {
+        match index {
+            0..=2 => &mut self.clear_color[index],
+            3..=5 => &mut self.light_direction[index - 3],
+            6 => &mut self.light_intensity,
+            7 => &mut self.bloom_threshold,
+            _ => &mut self.fov,
+        }
+    }
+}
+
+/// A panel of sliders for the [`SceneSettings`], dragged with the
+/// mouse, so shaders can be tuned without restarting.
+///
+/// The panel is laid out in logical pixels, and scaled by the
+/// window's scale factor to the physical pixels it's drawn in.
+pub struct DebugUi {
+    pub settings: SceneSettings,
+    visible: bool,
+    scale_factor: f32,
+    /// The slider the mouse was pressed on, until it's let go.
+    dragging: Option<usize>,
+}
+
+impl DebugUi {
+    /// The panel starts hidden.
+    pub fn new(settings: SceneSettings, scale_factor: f64) -> Self {
+        DebugUi {
+            settings,
+            visible: false,
+            scale_factor: scale_factor as f32,
+            dragging: None,
+        }
+    }
+
+    pub fn is_visible(&self) -> bool {
+        self.visible
+    }
+
+    pub fn toggle(&mut self) {
+        self.visible = !self.visible;
+        self.dragging = None;
+    }
+
+    /// Called when the window moves to a display of a different
+    /// density.
+    pub fn set_scale_factor(&mut self, scale_factor: f64) {
+        self.scale_factor = scale_factor as f32;
+    }
+
+    /// Drags the slider under the cursor, in a window
+    /// `screen_width` physical pixels across. Returns `true` while
+    /// the panel has the mouse, so clicks don't go through to the
+    /// scene.
+    pub fn update(&mut self, input: &InputState, screen_width: f32) -> bool {
+        if !self.visible {
+            return false;
+        }
+
+        let (x, y) = input.mouse_position();
+        let (left, top) = self.panel_origin(screen_width);
+        let s = self.scale_factor;
+        let over_panel = x >= left
+            && x < left + PANEL_WIDTH * s
+            && y >= top
+            && y < top + self.panel_height() * s;
+
+        if input.is_mouse_just_pressed(MouseButton::Left) && over_panel {
+            let row = ((y - top - PADDING * s) / (ROW_HEIGHT * s)).floor();
+            if row >= 0.0 && (row as usize) < SLIDERS.len() {
+                self.dragging = Some(row as usize);
+            }
+        }
+        if !input.is_mouse_held(MouseButton::Left) {
+            self.dragging = None;
+        }
+
+        if let Some(index) = self.dragging {
+            let slider = &SLIDERS[index];
+            let track_left = left + PADDING * s;
+            let track_width = (PANEL_WIDTH - PADDING * 2.0) * s;
+            let t = ((x - track_left) / track_width).clamp(0.0, 1.0);
+            *self.settings.value_mut(index) = slider.min + (slider.max - slider.min) * t;
+        }
+
+        over_panel || self.dragging.is_some()
+    }
+
+    /// Queues the panel's sliders, and their labels when there's a
+    /// font to draw them with.
+    pub fn draw(
+        &self,
+        sprites: &mut SpriteRenderer,
+        white: SpriteImage,
+        mut text: Option<&mut TextRenderer>,
+        screen_width: f32,
+    ) {
+        if !self.visible {
+            return;
+        }
+
+        let s = self.scale_factor;
+        let (left, top) = self.panel_origin(screen_width);
+        let mut background =
+            SpriteInstance::new([left, top], [PANEL_WIDTH * s, self.panel_height() * s]);
+        background.color = [0.0, 0.0, 0.0, 0.6];
+        sprites.draw_image(white, background);
+
+        for (index, slider) in SLIDERS.iter().enumerate() {
+            let value = self.settings.value(index);
+            let t = ((value - slider.min) / (slider.max - slider.min)).clamp(0.0, 1.0);
+            let row_top = top + (PADDING + ROW_HEIGHT * index as f32) * s;
+            let track_left = left + PADDING * s;
+            let track_top = row_top + (ROW_HEIGHT - TRACK_HEIGHT - 4.0) * s;
+            let track_width = (PANEL_WIDTH - PADDING * 2.0) * s;
+
+            let mut track =
+                SpriteInstance::new([track_left, track_top], [track_width, TRACK_HEIGHT * s]);
+            track.color = [0.3, 0.3, 0.3, 1.0];
+            sprites.draw_image(white, track);
+
+            let mut handle = SpriteInstance::new(
+                [
+                    track_left + (track_width - HANDLE_WIDTH * s) * t,
+                    track_top - 2.0 * s,
+                ],
+                [HANDLE_WIDTH * s, (TRACK_HEIGHT + 4.0) * s],
+            );
+            handle.color = if self.dragging == Some(index) {
+                [1.0, 0.8, 0.3, 1.0]
+            } else {
+                [0.8, 0.8, 0.8, 1.0]
+            };
+            sprites.draw_image(white, handle);
+
+            if let Some(text) = text.as_deref_mut() {
+                let label = format!("{} {:.2}", slider.label, value);
+                text.draw_text(
+                    track_left,
+                    row_top,
+                    TEXT_SCALE * s,
+                    [1.0, 1.0, 1.0, 1.0],
+                    &label,
+                );
+            }
+        }
+    }
+
+    /// The top left corner of the panel, in physical pixels.
+    fn panel_origin(&self, screen_width: f32) -> (f32, f32) {
+        let s = self.scale_factor;
+        (
+            screen_width - (PANEL_WIDTH + PANEL_MARGIN) * s,
+            PANEL_TOP * s,
+        )
+    }
+
+    /// In logical pixels.
+    fn panel_height(&self) -> f32 {
+        PADDING * 2.0 + ROW_HEIGHT * SLIDERS.len() as f32
+    }
+}
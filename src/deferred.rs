@@ -0,0 +1,322 @@
+use cgmath::SquareMatrix;
+
+use crate::{
+    camera::Camera,
+    depth::DepthBuffer,
+    fullscreen_triangle::FullscreenTriangle,
+    instance::InstanceData,
+    light::{PointLight, PointLightRaw},
+    pipeline::RenderPipelineBuilder,
+    render_pass_guard::RenderPassBuilder,
+    render_target::RenderTarget,
+    uniform::{UniformArrayBuffer, UniformBinding},
+    vertex::Vertex,
+};
+
+/// Most point lights the lighting pass can take at once. Must
+/// match `MAX_POINT_LIGHTS` in the shader.
+pub const MAX_POINT_LIGHTS: u32 = 32;
+
+/// Formats of the G-buffer's slots: albedo, world space normals,
+/// and metallic in red with roughness in green.
+const GBUFFER_FORMATS: [wgpu::TextureFormat; 3] = [
+    wgpu::TextureFormat::Rgba8Unorm,
+    wgpu::TextureFormat::Rgba16Float,
+    wgpu::TextureFormat::Rg8Unorm,
+];
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct LightingUniform {
+    inv_view_proj: [[f32; 4]; 4],
+    eye: [f32; 3],
+    light_count: u32,
+}
+
+/// Renders opaque geometry in two passes, so the cost of lighting
+/// doesn't grow with how much geometry there is.
+///
+/// The geometry pass writes the surface under each pixel into a
+/// G-buffer, without lighting anything. The lighting pass then draws
+/// over the whole screen once, shading each pixel with every point
+/// light from what's in the G-buffer.
+pub struct DeferredRenderer {
+    gbuffer: RenderTarget,
+    gbuffer_layout: wgpu::BindGroupLayout,
+    gbuffer_bind_group: wgpu::BindGroup,
+    geometry_pipeline: wgpu::RenderPipeline,
+    uniform: UniformBinding<LightingUniform>,
+    point_lights: UniformArrayBuffer<PointLightRaw>,
+    lights: Vec<PointLight>,
+    lights_bind_group: wgpu::BindGroup,
+    lighting: FullscreenTriangle,
+}
+
+impl DeferredRenderer {
+    /// `material_layout` is the layout of the bind groups the
+    /// geometry is drawn with, and `format` the format of the
+    /// view the lighting pass draws into.
+    pub fn new(
+        device: &wgpu::Device,
+        width: u32,
+        height: u32,
+        camera_layout: &wgpu::BindGroupLayout,
+        material_layout: &wgpu::BindGroupLayout,
+        format: wgpu::TextureFormat,
+    ) -> Self {
+        let gbuffer = RenderTarget::new(device, width, height, &GBUFFER_FORMATS).with_depth(device);
+
+        let geometry_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Deferred Geometry Pipeline Layout"),
+            bind_group_layouts: &[camera_layout, material_layout],
+            push_constant_ranges: &[],
+        });
+        let geometry_shader = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+            label: Some("Deferred Geometry Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("deferred_geometry.wgsl").into()),
+        });
+        let geometry_pipeline = RenderPipelineBuilder::new()
+            .label("Deferred Geometry Pipeline")
+            .vertex_shader(&geometry_shader, "main")
+            .vertex_layouts(&[
+                Vertex::vertex_buffer_layout(),
+                InstanceData::vertex_buffer_layout(),
+            ])
+            .fragment_shader(&geometry_shader, "main", &gbuffer.color_targets())
+            .depth_stencil(DepthBuffer::depth_stencil_state())
+            .build(device, &geometry_layout)
+            .expect("failed to build deferred geometry pipeline");
+
+        // The lighting pass reads the pixel it's shading straight
+        // out of each slot, so nothing is sampled.
+        let texture_entry = |binding, sample_type| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                multisampled: false,
+                view_dimension: wgpu::TextureViewDimension::D2,
+                sample_type,
+            },
+            count: None,
+        };
+        let color = wgpu::TextureSampleType::Float { filterable: false };
+        let gbuffer_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("G-Buffer Bind Group Layout"),
+            entries: &[
+                texture_entry(0, color),
+                texture_entry(1, color),
+                texture_entry(2, color),
+                texture_entry(3, wgpu::TextureSampleType::Depth),
+            ],
+        });
+        let gbuffer_bind_group = create_gbuffer_bind_group(device, &gbuffer_layout, &gbuffer);
+
+        let uniform = UniformBinding::new(
+            device,
+            "Deferred Lighting Uniform",
+            wgpu::ShaderStages::FRAGMENT,
+            &LightingUniform {
+                inv_view_proj: cgmath::Matrix4::identity().into(),
+                eye: [0.0; 3],
+                light_count: 0,
+            },
+        );
+        let point_lights = UniformArrayBuffer::new(device, "Point Lights", MAX_POINT_LIGHTS);
+        let lights_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Deferred Lights Bind Group Layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                point_lights.bind_group_layout_entry(1, wgpu::ShaderStages::FRAGMENT),
+            ],
+        });
+        let lights_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Deferred Lights Bind Group"),
+            layout: &lights_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: uniform.buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: point_lights.as_entire_binding(),
+                },
+            ],
+        });
+
+        let lighting_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Deferred Lighting Pipeline Layout"),
+            bind_group_layouts: &[&gbuffer_layout, &lights_layout],
+            push_constant_ranges: &[],
+        });
+        let vertex_shader = FullscreenTriangle::vertex_shader(device);
+        let lighting_shader = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+            label: Some("Deferred Lighting Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("deferred_lighting.wgsl").into()),
+        });
+        let lighting = FullscreenTriangle::new(
+            RenderPipelineBuilder::new()
+                .label("Deferred Lighting Pipeline")
+                .vertex_shader(&vertex_shader, "main")
+                .fragment_shader(
+                    &lighting_shader,
+                    "main",
+                    &[wgpu::ColorTargetState {
+                        format,
+                        blend: Some(wgpu::BlendState::REPLACE),
+                        write_mask: wgpu::ColorWrites::ALL,
+                    }],
+                )
+                .cull_mode(None)
+                .build(device, &lighting_layout)
+                .expect("failed to build deferred lighting pipeline"),
+        );
+
+        DeferredRenderer {
+            gbuffer,
+            gbuffer_layout,
+            gbuffer_bind_group,
+            geometry_pipeline,
+            uniform,
+            point_lights,
+            lights: Vec::new(),
+            lights_bind_group,
+            lighting,
+        }
+    }
+
+    /// The textures the geometry pass writes into.
+    pub fn gbuffer(&self) -> &RenderTarget {
+        &self.gbuffer
+    }
+
+    /// Replaces the lights shading the scene, uploaded by the next
+    /// [`DeferredRenderer::update`]. Lights beyond [`MAX_POINT_LIGHTS`]
+    /// are left out.
+    pub fn set_lights(&mut self, lights: &[PointLight]) {
+        self.lights.clear();
+        self.lights.extend_from_slice(lights);
+    }
+
+    #[allow(dead_code)]
+    pub fn lights(&self) -> &[PointLight] {
+        &self.lights
+    }
+
+    /// Uploads the lights and where the camera is, for the next
+    /// lighting pass.
+    pub fn update(&mut self, queue: &wgpu::Queue, camera: &Camera) {
+        let lights = self.lights.iter().map(Into::into).collect::<Vec<_>>();
+        self.point_lights.update_all(queue, &lights);
+
+        let view_proj = cgmath::Matrix4::from(camera.build_view_projection_matrix());
+        let inv_view_proj = view_proj.invert().unwrap_or_else(cgmath::Matrix4::identity);
+        self.uniform.update(
+            queue,
+            &LightingUniform {
+                inv_view_proj: inv_view_proj.into(),
+                eye: camera.eye.into(),
+                light_count: self.point_lights.len(),
+            },
+        );
+    }
+
+    /// Records the geometry pass into the G-buffer. `draw` is given the
+    /// pass with the pipeline and camera bound, and should bind each
+    /// material at group 1 and the instances in the second vertex slot.
+    pub fn geometry_pass<'a>(
+        &'a self,
+        encoder: &'a mut wgpu::CommandEncoder,
+        camera_bind_group: &'a wgpu::BindGroup,
+        draw: impl FnOnce(&mut wgpu::RenderPass<'a>),
+    ) {
+        let color_attachments = self
+            .gbuffer
+            .color_attachment_array(wgpu::Color::TRANSPARENT);
+        let mut render_pass = RenderPassBuilder::begin_render_pass(
+            encoder,
+            &wgpu::RenderPassDescriptor {
+                label: Some("Deferred Geometry Pass"),
+                color_attachments: &color_attachments,
+                depth_stencil_attachment: self.gbuffer.depth_stencil_attachment(),
+            },
+        );
+        render_pass.set_pipeline(&self.geometry_pipeline);
+        render_pass.set_bind_group(0, camera_bind_group, &[]);
+        draw(&mut render_pass);
+    }
+
+    /// Records the lighting pass, shading the G-buffer into `output`.
+    /// Pixels nothing was drawn into are only touched by `load`.
+    pub fn lighting_pass(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        output: &wgpu::TextureView,
+        load: wgpu::LoadOp<wgpu::Color>,
+    ) {
+        let mut render_pass = RenderPassBuilder::begin_render_pass(
+            encoder,
+            &wgpu::RenderPassDescriptor {
+                label: Some("Deferred Lighting Pass"),
+                color_attachments: &[wgpu::RenderPassColorAttachment {
+                    view: output,
+                    resolve_target: None,
+                    ops: wgpu::Operations { load, store: true },
+                }],
+                depth_stencil_attachment: None,
+            },
+        );
+        render_pass.set_bind_group(0, &self.gbuffer_bind_group, &[]);
+        render_pass.set_bind_group(1, &self.lights_bind_group, &[]);
+        self.lighting.draw(&mut render_pass);
+    }
+
+    /// Recreates the G-buffer to match the new surface size.
+    pub fn resize(&mut self, device: &wgpu::Device, width: u32, height: u32) {
+        self.gbuffer.resize(device, width, height);
+        self.gbuffer_bind_group =
+            create_gbuffer_bind_group(device, &self.gbuffer_layout, &self.gbuffer);
+    }
+}
+
+fn create_gbuffer_bind_group(
+    device: &wgpu::Device,
+    layout: &wgpu::BindGroupLayout,
+    gbuffer: &RenderTarget,
+) -> wgpu::BindGroup {
+    let colors = gbuffer.colors();
+    let depth = gbuffer
+        .depth()
+        .expect("G-buffer is created with a depth buffer");
+    device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some("G-Buffer Bind Group"),
+        layout,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(&colors[0].view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: wgpu::BindingResource::TextureView(&colors[1].view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 2,
+                resource: wgpu::BindingResource::TextureView(&colors[2].view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 3,
+                resource: wgpu::BindingResource::TextureView(depth.depth_view()),
+            },
+        ],
+    })
+}
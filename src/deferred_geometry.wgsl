@@ -0,0 +1,84 @@
+// Geometry pass of the deferred renderer. Nothing is lit here, the
+// surface of each fragment is written into the G-buffer for the
+// lighting pass to shade.
+
+[[block]]
+struct CameraUniform {
+    view_proj: mat4x4<f32>;
+    right: vec4<f32>;
+    up: vec4<f32>;
+    clip_plane: vec4<f32>;
+};
+
+[[group(0), binding(0)]]
+var<uniform> camera: CameraUniform;
+
+[[block]]
+struct MaterialUniform {
+    color: vec4<f32>;
+    metallic: f32;
+    roughness: f32;
+};
+
+[[group(1), binding(0)]]
+var<uniform> material: MaterialUniform;
+
+struct VertexInput {
+    [[location(0)]] position: vec3<f32>;
+    [[location(1)]] color: vec3<f32>;
+    [[location(2)]] normal: vec3<f32>;
+    [[location(3)]] tex_coords: vec2<f32>;
+};
+
+struct InstanceInput {
+    [[location(5)]] model_matrix_0: vec4<f32>;
+    [[location(6)]] model_matrix_1: vec4<f32>;
+    [[location(7)]] model_matrix_2: vec4<f32>;
+    [[location(8)]] model_matrix_3: vec4<f32>;
+};
+
+struct VertexOutput {
+    [[builtin(position)]] clip_position: vec4<f32>;
+    [[location(0)]] color: vec3<f32>;
+    [[location(1)]] world_normal: vec3<f32>;
+};
+
+[[stage(vertex)]]
+fn main(model: VertexInput, instance: InstanceInput) -> VertexOutput {
+    let model_matrix = mat4x4<f32>(
+        instance.model_matrix_0,
+        instance.model_matrix_1,
+        instance.model_matrix_2,
+        instance.model_matrix_3,
+    );
+    // Only correct as long as the model matrix scales uniformly.
+    let normal_matrix = mat3x3<f32>(
+        model_matrix.x.xyz,
+        model_matrix.y.xyz,
+        model_matrix.z.xyz,
+    );
+
+    var out: VertexOutput;
+    out.color = model.color;
+    out.world_normal = normal_matrix * model.normal;
+    out.clip_position = camera.view_proj * model_matrix * vec4<f32>(model.position, 1.0);
+    return out;
+}
+
+// One output for each slot of the G-buffer.
+struct GBufferOutput {
+    [[location(0)]] albedo: vec4<f32>;
+    [[location(1)]] normal: vec4<f32>;
+    [[location(2)]] metallic_roughness: vec2<f32>;
+};
+
+[[stage(fragment)]]
+fn main(in: VertexOutput) -> GBufferOutput {
+    var out: GBufferOutput;
+    out.albedo = vec4<f32>(in.color, 1.0) * material.color;
+    // The normal target is a float format, so the normal
+    // doesn't have to be squeezed into 0 to 1.
+    out.normal = vec4<f32>(normalize(in.world_normal), 0.0);
+    out.metallic_roughness = vec2<f32>(material.metallic, material.roughness);
+    return out;
+}
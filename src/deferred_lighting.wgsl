@@ -0,0 +1,126 @@
+// Lighting pass of the deferred renderer, drawn over the whole screen
+// with fullscreen.wgsl. Every point light is added up for each pixel
+// of the G-buffer, using the Cook-Torrance BRDF.
+
+[[group(0), binding(0)]]
+var t_albedo: texture_2d<f32>;
+[[group(0), binding(1)]]
+var t_normal: texture_2d<f32>;
+[[group(0), binding(2)]]
+var t_metallic_roughness: texture_2d<f32>;
+[[group(0), binding(3)]]
+var t_depth: texture_depth_2d;
+
+[[block]]
+struct LightingUniform {
+    // Takes positions in clip space back to world space.
+    inv_view_proj: mat4x4<f32>;
+    eye: vec3<f32>;
+    light_count: u32;
+};
+
+[[group(1), binding(0)]]
+var<uniform> lighting: LightingUniform;
+
+struct PointLight {
+    position: vec3<f32>;
+    range: f32;
+    color: vec3<f32>;
+    intensity: f32;
+};
+
+// Must match `MAX_POINT_LIGHTS` in deferred.rs.
+let MAX_POINT_LIGHTS: u32 = 32u;
+
+[[block]]
+struct PointLights {
+    lights: array<PointLight, MAX_POINT_LIGHTS>;
+};
+
+[[group(1), binding(1)]]
+var<uniform> point_lights: PointLights;
+
+let PI: f32 = 3.14159265359;
+// Light reaching surfaces no matter which way they face.
+let AMBIENT: f32 = 0.03;
+
+// How many microfacets line up with the half vector.
+fn distribution_ggx(n_dot_h: f32, roughness: f32) -> f32 {
+    let a = roughness * roughness;
+    let a2 = a * a;
+    let d = n_dot_h * n_dot_h * (a2 - 1.0) + 1.0;
+    return a2 / (PI * d * d);
+}
+
+// How much of the microfacets shadow each other.
+fn geometry_schlick_ggx(n_dot_v: f32, roughness: f32) -> f32 {
+    let r = roughness + 1.0;
+    let k = r * r / 8.0;
+    return n_dot_v / (n_dot_v * (1.0 - k) + k);
+}
+
+// How much light is reflected rather than refracted.
+fn fresnel_schlick(cos_theta: f32, f0: vec3<f32>) -> vec3<f32> {
+    return f0 + (vec3<f32>(1.0) - f0) * pow(1.0 - cos_theta, 5.0);
+}
+
+struct FragmentInput {
+    [[builtin(position)]] position: vec4<f32>;
+    [[location(0)]] tex_coords: vec2<f32>;
+};
+
+[[stage(fragment)]]
+fn main(in: FragmentInput) -> [[location(0)]] vec4<f32> {
+    let pixel = vec2<i32>(in.position.xy);
+    let depth = textureLoad(t_depth, pixel, 0);
+    // Nothing was drawn here, so whatever is behind is kept.
+    if (depth >= 1.0) {
+        discard;
+    }
+
+    let albedo = textureLoad(t_albedo, pixel, 0).rgb;
+    let normal = normalize(textureLoad(t_normal, pixel, 0).xyz);
+    let metallic_roughness = textureLoad(t_metallic_roughness, pixel, 0).rg;
+    let metallic = metallic_roughness.r;
+    // Perfectly smooth surfaces make the distribution blow up.
+    let roughness = max(metallic_roughness.g, 0.05);
+
+    // Texture coordinates have Y pointing down, unlike clip space.
+    let ndc = vec4<f32>(in.tex_coords.x * 2.0 - 1.0, 1.0 - in.tex_coords.y * 2.0, depth, 1.0);
+    let world = lighting.inv_view_proj * ndc;
+    let world_position = world.xyz / world.w;
+
+    let view_dir = normalize(lighting.eye - world_position);
+    let n_dot_v = max(dot(normal, view_dir), 0.0001);
+    // Non-metals reflect a little of every color head on.
+    let f0 = mix(vec3<f32>(0.04), albedo, metallic);
+
+    var color = albedo * AMBIENT;
+    for (var i: u32 = 0u; i < lighting.light_count; i = i + 1u) {
+        let light = point_lights.lights[i];
+        let to_light = light.position - world_position;
+        let distance = length(to_light);
+        if (distance >= light.range) {
+            continue;
+        }
+        let light_dir = to_light / distance;
+        let half_dir = normalize(view_dir + light_dir);
+        let n_dot_l = max(dot(normal, light_dir), 0.0);
+
+        // Inverse square falloff, windowed so it reaches zero at the range.
+        let window = clamp(1.0 - pow(distance / light.range, 4.0), 0.0, 1.0);
+        let attenuation = window * window / (distance * distance + 1.0);
+        let radiance = light.color * light.intensity * attenuation;
+
+        let d = distribution_ggx(max(dot(normal, half_dir), 0.0), roughness);
+        let g = geometry_schlick_ggx(n_dot_v, roughness) * geometry_schlick_ggx(n_dot_l, roughness);
+        let f = fresnel_schlick(max(dot(half_dir, view_dir), 0.0), f0);
+        let specular = d * g * f / (4.0 * n_dot_v * n_dot_l + 0.0001);
+
+        // Metals have no diffuse light, and what's reflected isn't diffused.
+        let diffuse = (vec3<f32>(1.0) - f) * (1.0 - metallic) * albedo / PI;
+        color = color + (diffuse + specular) * radiance * n_dot_l;
+    }
+
+    return vec4<f32>(color, 1.0);
+}
@@ -0,0 +1,130 @@
+/// Depth texture used for depth testing, so fragments
+/// behind already drawn geometry are discarded.
+///
+/// It has a stencil buffer alongside the depth, for masking out
+/// the pixels geometry covers, like [`WireframeOverlay`](crate::wireframe_overlay::WireframeOverlay)
+/// does to outline it.
+pub struct DepthBuffer {
+    texture: wgpu::Texture,
+    view: wgpu::TextureView,
+    depth_view: wgpu::TextureView,
+    width: u32,
+    height: u32,
+    sample_count: u32,
+}
+
+impl DepthBuffer {
+    pub const FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth24PlusStencil8;
+
+    pub fn new(device: &wgpu::Device, width: u32, height: u32) -> Self {
+        Self::new_multisampled(device, width, height, 1)
+    }
+
+    /// Depth buffer for a multisampled render pass. The sample count
+    /// must match the color attachments it's used with.
+    pub fn new_multisampled(
+        device: &wgpu::Device,
+        width: u32,
+        height: u32,
+        sample_count: u32,
+    ) -> Self {
+        let (texture, view, depth_view) = Self::create_texture(device, width, height, sample_count);
+        DepthBuffer {
+            texture,
+            view,
+            depth_view,
+            width,
+            height,
+            sample_count,
+        }
+    }
+
+    fn create_texture(
+        device: &wgpu::Device,
+        width: u32,
+        height: u32,
+        sample_count: u32,
+    ) -> (wgpu::Texture, wgpu::TextureView, wgpu::TextureView) {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Depth Texture"),
+            // The depth texture needs to be the same size as
+            // the surface, otherwise the render pass will fail.
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count,
+            dimension: wgpu::TextureDimension::D2,
+            format: Self::FORMAT,
+            // We're rendering to this texture, so it needs RENDER_ATTACHMENT.
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+        });
+
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        // Textures with both depth and stencil can only be bound
+        // to shaders through a view of one or the other.
+        let depth_view = texture.create_view(&wgpu::TextureViewDescriptor {
+            label: Some("Depth Only View"),
+            aspect: wgpu::TextureAspect::DepthOnly,
+            ..Default::default()
+        });
+
+        (texture, view, depth_view)
+    }
+
+    /// Recreates the depth texture to match the new surface size.
+    pub fn resize(&mut self, device: &wgpu::Device, width: u32, height: u32) {
+        let (texture, view, depth_view) =
+            Self::create_texture(device, width, height, self.sample_count);
+        self.texture = texture;
+        self.view = view;
+        self.depth_view = depth_view;
+        self.width = width;
+        self.height = height;
+    }
+
+    #[allow(dead_code)]
+    pub fn texture(&self) -> &wgpu::Texture {
+        &self.texture
+    }
+
+    /// View of depth and stencil both, for attaching to render passes.
+    pub fn view(&self) -> &wgpu::TextureView {
+        &self.view
+    }
+
+    /// View of only the depth, for binding to shaders.
+    pub fn depth_view(&self) -> &wgpu::TextureView {
+        &self.depth_view
+    }
+
+    #[allow(dead_code)]
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    #[allow(dead_code)]
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn sample_count(&self) -> u32 {
+        self.sample_count
+    }
+
+    /// Depth state to plug into a render pipeline that renders
+    /// into this depth buffer.
+    pub fn depth_stencil_state() -> wgpu::DepthStencilState {
+        wgpu::DepthStencilState {
+            format: Self::FORMAT,
+            depth_write_enabled: true,
+            // Pixels are drawn front to back, so a fragment is kept
+            // when it's closer than what's already in the buffer.
+            depth_compare: wgpu::CompareFunction::Less,
+            stencil: wgpu::StencilState::default(),
+            bias: wgpu::DepthBiasState::default(),
+        }
+    }
+}
@@ -0,0 +1,166 @@
+use std::num::NonZeroU32;
+
+/// Thresholds of the 4×4 ordered dither, by row then column. Every
+/// value from 0 to 15 is there once, spread so that neighbouring
+/// cells are far apart.
+pub const BAYER_4X4: [[u8; 4]; 4] = [[0, 8, 2, 10], [12, 4, 14, 6], [3, 11, 1, 9], [15, 7, 13, 5]];
+
+/// The WGSL of `dithered_discard(alpha: f32, pixel_coord: vec2<u32>)`,
+/// added to the start of shaders that call it.
+///
+/// It reads the matrix from binding 1 of group 3, where the
+/// [`MaterialLibrary`](crate::material::MaterialLibrary) binds it.
+pub const WGSL: &str = include_str!("dithered_transparency.wgsl");
+
+/// [`WGSL`] followed by `source`, so it can call `dithered_discard`.
+/// Functions have to be declared before they're called.
+pub fn with_dithering(source: &str) -> String {
+    format!("{}\n{}", WGSL, source)
+}
+
+/// Transparency without blending, by throwing away a share of a
+/// surface's pixels in a fixed pattern instead.
+///
+/// A pixel is kept when the surface's alpha is above its cell of
+/// [`BAYER_4X4`], so at an alpha of 0.25 one pixel out of every four
+/// is drawn. What's kept is drawn and depth tested like anything
+/// opaque, so transparent surfaces don't need sorting and can be
+/// drawn in the same pass. The pattern gets softer when the frame
+/// is multisampled or filtered afterwards.
+pub struct DitheredTransparency {
+    #[allow(dead_code)]
+    texture: wgpu::Texture,
+    view: wgpu::TextureView,
+}
+
+impl DitheredTransparency {
+    /// Uploads [`BAYER_4X4`] into a 4×4 texture of integers, for
+    /// `textureLoad` to read without filtering.
+    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue) -> Self {
+        let size = wgpu::Extent3d {
+            width: 4,
+            height: 4,
+            depth_or_array_layers: 1,
+        };
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Bayer Matrix"),
+            size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::R8Uint,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+        });
+
+        queue.write_texture(
+            wgpu::ImageCopyTexture {
+                texture: &texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            },
+            bytemuck::cast_slice(&BAYER_4X4),
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: NonZeroU32::new(4),
+                rows_per_image: NonZeroU32::new(4),
+            },
+            size,
+        );
+
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        DitheredTransparency { texture, view }
+    }
+
+    #[allow(dead_code)]
+    pub fn view(&self) -> &wgpu::TextureView {
+        &self.view
+    }
+
+    /// Layout entry for the matrix, bound at `binding`.
+    pub fn bind_group_layout_entry(binding: u32) -> wgpu::BindGroupLayoutEntry {
+        wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                multisampled: false,
+                view_dimension: wgpu::TextureViewDimension::D2,
+                sample_type: wgpu::TextureSampleType::Uint,
+            },
+            count: None,
+        }
+    }
+
+    /// Bind group entry matching the layout from
+    /// [`DitheredTransparency::bind_group_layout_entry`].
+    pub fn bind_group_entry(&self, binding: u32) -> wgpu::BindGroupEntry<'_> {
+        wgpu::BindGroupEntry {
+            binding,
+            resource: wgpu::BindingResource::TextureView(&self.view),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Whether `dithered_discard` keeps a pixel at `alpha` in a cell
+    /// with `threshold`, worked out the way the shader does.
+    fn kept(alpha: f32, threshold: u8) -> bool {
+        alpha >= (threshold as f32 + 0.5) / 16.0
+    }
+
+    fn kept_count(alpha: f32) -> usize {
+        BAYER_4X4
+            .iter()
+            .flatten()
+            .filter(|&&threshold| kept(alpha, threshold))
+            .count()
+    }
+
+    #[test]
+    fn bayer_matrix_has_each_threshold_once() {
+        let mut thresholds = BAYER_4X4.iter().flatten().copied().collect::<Vec<_>>();
+        thresholds.sort_unstable();
+        assert_eq!(thresholds, (0..16).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn keeps_pixels_in_proportion_to_alpha() {
+        assert_eq!(kept_count(0.0), 0);
+        assert_eq!(kept_count(0.25), 4);
+        assert_eq!(kept_count(0.5), 8);
+        assert_eq!(kept_count(1.0), 16);
+    }
+
+    #[test]
+    fn quarter_alpha_keeps_one_pixel_of_each_2x2_block() {
+        for block in [(0, 0), (0, 2), (2, 0), (2, 2)] {
+            let kept_in_block = (0..2)
+                .flat_map(|y| (0..2).map(move |x| (x, y)))
+                .filter(|(x, y)| kept(0.25, BAYER_4X4[block.0 + y][block.1 + x]))
+                .count();
+            assert_eq!(kept_in_block, 1, "block at {:?}", block);
+        }
+    }
+
+    #[test]
+    fn dithering_is_valid_wgsl() {
+        let source = with_dithering(
+            "[[stage(fragment)]]
+fn main([[builtin(position)]] position: vec4<f32>) -> [[location(0)]] vec4<f32> {
+    dithered_discard(0.5, vec2<u32>(position.xy));
+    return vec4<f32>(1.0);
+}",
+        );
+        let module = naga::front::wgsl::parse_str(&source)
+            .unwrap_or_else(|err| panic!("{}", err.emit_to_string(&source)));
+        naga::valid::Validator::new(
+            naga::valid::ValidationFlags::all(),
+            naga::valid::Capabilities::all(),
+        )
+        .validate(&module)
+        .expect("dithered shader is valid");
+    }
+}
@@ -0,0 +1,18 @@
+// Dithered transparency, added to the start of shaders that call
+// `dithered_discard`. See dithered_transparency.rs.
+
+// Written by `DitheredTransparency`, with the thresholds from 0 to 15.
+[[group(3), binding(1)]]
+var t_bayer: texture_2d<u32>;
+
+// Throws the fragment away unless `alpha` is above the threshold of
+// its cell in the matrix, which repeats every 4 pixels.
+fn dithered_discard(alpha: f32, pixel_coord: vec2<u32>) {
+    let cell = vec2<i32>(pixel_coord % vec2<u32>(4u, 4u));
+    // Halfway between the steps, so an alpha of 0 keeps nothing
+    // and 1 keeps everything.
+    let threshold = (f32(textureLoad(t_bayer, cell, 0).r) + 0.5) / 16.0;
+    if (alpha < threshold) {
+        discard;
+    }
+}
@@ -0,0 +1,190 @@
+use std::marker::PhantomData;
+
+use wgpu::util::DeviceExt;
+
+use crate::index::Index;
+
+/// Number of copies of the geometry. One is drawn by the frame
+/// in flight while the other is written.
+const BUFFER_COUNT: usize = 2;
+
+struct Slot {
+    vertex_buffer: wgpu::Buffer,
+    index_buffer: wgpu::Buffer,
+    index_count: u32,
+}
+
+/// Geometry that's rewritten from the CPU, or copied from buffers
+/// written on the GPU, as often as every frame.
+///
+/// New geometry is written into the back buffers, while the GPU may
+/// still be drawing the front ones from the last frame, so it never
+/// has to wait for the GPU to finish with them.
+///
+/// Every frame that updates the mesh should go:
+///
+/// 1. [`DynamicMesh::update`] with the new geometry.
+/// 2. [`DynamicMesh::draw`] with [`DynamicMesh::frame_index`].
+/// 3. [`DynamicMesh::swap`] once the frame is submitted.
+pub struct DynamicMesh<V, I> {
+    slots: Vec<Slot>,
+    /// Incremented by every swap following an update.
+    frame_index: usize,
+    updated: bool,
+    max_vertices: usize,
+    #[allow(dead_code)]
+    max_indices: usize,
+    _marker: PhantomData<(V, I)>,
+}
+
+impl<V: bytemuck::Pod, I: Index> DynamicMesh<V, I> {
+    /// Creates buffers big enough for the given number of vertices and indices.
+    #[allow(dead_code)]
+    pub fn new(device: &wgpu::Device, max_vertices: usize, max_indices: usize) -> Self {
+        let slots = (0..BUFFER_COUNT)
+            .map(|_| Slot {
+                vertex_buffer: device.create_buffer(&wgpu::BufferDescriptor {
+                    label: Some("Dynamic Mesh Vertex Buffer"),
+                    size: buffer_size::<V>(max_vertices),
+                    usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+                    mapped_at_creation: false,
+                }),
+                index_buffer: device.create_buffer(&wgpu::BufferDescriptor {
+                    label: Some("Dynamic Mesh Index Buffer"),
+                    size: buffer_size::<I>(max_indices),
+                    usage: wgpu::BufferUsages::INDEX | wgpu::BufferUsages::COPY_DST,
+                    mapped_at_creation: false,
+                }),
+                index_count: 0,
+            })
+            .collect();
+
+        DynamicMesh {
+            slots,
+            frame_index: 0,
+            updated: false,
+            max_vertices,
+            max_indices,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Creates buffers for geometry whose vertices move but whose
+    /// triangles don't, like a mesh deformed on the GPU. Every copy
+    /// starts out with `indices`, and their vertices are written with
+    /// [`DynamicMesh::copy_vertices`].
+    pub fn with_indices(device: &wgpu::Device, max_vertices: usize, indices: &[I]) -> Self {
+        let slots = (0..BUFFER_COUNT)
+            .map(|_| Slot {
+                vertex_buffer: device.create_buffer(&wgpu::BufferDescriptor {
+                    label: Some("Dynamic Mesh Vertex Buffer"),
+                    size: buffer_size::<V>(max_vertices),
+                    usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+                    mapped_at_creation: false,
+                }),
+                index_buffer: device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                    label: Some("Dynamic Mesh Index Buffer"),
+                    contents: &padded(indices),
+                    usage: wgpu::BufferUsages::INDEX | wgpu::BufferUsages::COPY_DST,
+                }),
+                index_count: indices.len() as u32,
+            })
+            .collect();
+
+        DynamicMesh {
+            slots,
+            frame_index: 0,
+            updated: false,
+            max_vertices,
+            max_indices: indices.len(),
+            _marker: PhantomData,
+        }
+    }
+
+    /// Writes new geometry into the back buffers. Geometry past the
+    /// sizes the mesh was created with is dropped.
+    #[allow(dead_code)]
+    pub fn update(&mut self, queue: &wgpu::Queue, vertices: &[V], indices: &[I]) {
+        if vertices.len() > self.max_vertices || indices.len() > self.max_indices {
+            log::warn!(
+                "dynamic mesh holds {} vertices and {} indices, truncating {} and {}",
+                self.max_vertices,
+                self.max_indices,
+                vertices.len(),
+                indices.len()
+            );
+        }
+        let vertices = &vertices[..vertices.len().min(self.max_vertices)];
+        let indices = &indices[..indices.len().min(self.max_indices)];
+
+        let slot = &mut self.slots[self.frame_index % BUFFER_COUNT];
+        queue.write_buffer(&slot.vertex_buffer, 0, &padded(vertices));
+        queue.write_buffer(&slot.index_buffer, 0, &padded(indices));
+        slot.index_count = indices.len() as u32;
+        self.updated = true;
+    }
+
+    /// Copies `vertex_count` vertices from the start of `source` into
+    /// the back buffers, for geometry written on the GPU. `source`
+    /// needs `COPY_SRC`, and the triangles are the ones the mesh was
+    /// created [with](DynamicMesh::with_indices).
+    pub fn copy_vertices(
+        &mut self,
+        encoder: &mut wgpu::CommandEncoder,
+        source: &wgpu::Buffer,
+        vertex_count: usize,
+    ) {
+        let vertex_count = vertex_count.min(self.max_vertices);
+        let slot = &self.slots[self.frame_index % BUFFER_COUNT];
+        encoder.copy_buffer_to_buffer(
+            source,
+            0,
+            &slot.vertex_buffer,
+            0,
+            buffer_size::<V>(vertex_count),
+        );
+        self.updated = true;
+    }
+
+    /// Which buffers to draw this frame.
+    pub fn frame_index(&self) -> usize {
+        self.frame_index
+    }
+
+    /// Makes the back buffers the front ones, once the frame that
+    /// updated them is submitted. Does nothing when the mesh wasn't
+    /// updated, so the same buffers keep being drawn.
+    pub fn swap(&mut self) {
+        if self.updated {
+            self.frame_index += 1;
+            self.updated = false;
+        }
+    }
+
+    /// Binds the buffers for `frame_index` and draws them.
+    pub fn draw<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>, frame_index: usize) {
+        let slot = &self.slots[frame_index % BUFFER_COUNT];
+        if slot.index_count == 0 {
+            return;
+        }
+
+        render_pass.set_vertex_buffer(0, slot.vertex_buffer.slice(..));
+        render_pass.set_index_buffer(slot.index_buffer.slice(..), I::FORMAT);
+        render_pass.draw_indexed(0..slot.index_count, 0, 0..1);
+    }
+}
+
+fn buffer_size<T>(len: usize) -> wgpu::BufferAddress {
+    let size = (len * std::mem::size_of::<T>()) as wgpu::BufferAddress;
+    // Room for the padding added by `padded`.
+    size.div_ceil(wgpu::COPY_BUFFER_ALIGNMENT) * wgpu::COPY_BUFFER_ALIGNMENT
+}
+
+/// Writes have to be a multiple of 4 bytes long, which an odd
+/// number of `u16` indices isn't.
+fn padded<T: bytemuck::Pod>(data: &[T]) -> Vec<u8> {
+    let mut bytes = bytemuck::cast_slice(data).to_vec();
+    let len = buffer_size::<u8>(bytes.len()) as usize;
+    bytes.resize(len, 0);
+    bytes
+}
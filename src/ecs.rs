@@ -0,0 +1,240 @@
+use std::{
+    any::{Any, TypeId},
+    collections::HashMap,
+};
+
+/// Identifies an entity in a [`World`]. An entity is nothing but
+/// its ID, and everything about it is kept in its components.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct EntityId(pub u32);
+
+/// Components of one type, packed together so they can be
+/// iterated over without gaps.
+pub struct ComponentStorage<T> {
+    dense: Vec<T>,
+    /// The entity owning each component in `dense`.
+    entities: Vec<EntityId>,
+    /// Where each entity's component is in `dense`.
+    indices: HashMap<EntityId, usize>,
+}
+
+impl<T> ComponentStorage<T> {
+    pub fn new() -> Self {
+        ComponentStorage {
+            dense: Vec::new(),
+            entities: Vec::new(),
+            indices: HashMap::new(),
+        }
+    }
+
+    /// Gives `entity` the component, replacing the one it had.
+    pub fn insert(&mut self, entity: EntityId, component: T) -> Option<T> {
+        match self.indices.get(&entity) {
+            Some(&index) => Some(std::mem::replace(&mut self.dense[index], component)),
+            None => {
+                self.indices.insert(entity, self.dense.len());
+                self.dense.push(component);
+                self.entities.push(entity);
+                None
+            }
+        }
+    }
+
+    /// Takes the component from `entity`. The last component is
+    /// moved into its place, so the rest stay packed.
+    #[allow(dead_code)]
+    pub fn remove(&mut self, entity: EntityId) -> Option<T> {
+        let index = self.indices.remove(&entity)?;
+        let component = self.dense.swap_remove(index);
+        self.entities.swap_remove(index);
+        if let Some(moved) = self.entities.get(index) {
+            self.indices.insert(*moved, index);
+        }
+        Some(component)
+    }
+
+    pub fn get(&self, entity: EntityId) -> Option<&T> {
+        self.indices.get(&entity).map(|&index| &self.dense[index])
+    }
+
+    #[allow(dead_code)]
+    pub fn get_mut(&mut self, entity: EntityId) -> Option<&mut T> {
+        let index = *self.indices.get(&entity)?;
+        Some(&mut self.dense[index])
+    }
+
+    #[allow(dead_code)]
+    pub fn len(&self) -> usize {
+        self.dense.len()
+    }
+
+    #[allow(dead_code)]
+    pub fn is_empty(&self) -> bool {
+        self.dense.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (EntityId, &T)> {
+        self.entities.iter().copied().zip(self.dense.iter())
+    }
+}
+
+impl<T> Default for ComponentStorage<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A [`ComponentStorage`] with its component type erased, so storages
+/// of every type can be kept together.
+trait AnyStorage {
+    #[allow(dead_code)]
+    fn remove_entity(&mut self, entity: EntityId);
+    fn as_any(&self) -> &dyn Any;
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+}
+
+impl<T: 'static> AnyStorage for ComponentStorage<T> {
+    fn remove_entity(&mut self, entity: EntityId) {
+        self.remove(entity);
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+/// Entities and their components.
+///
+/// Each type of component is kept in a [`ComponentStorage`] of its own,
+/// and entities are found by the components they have with
+/// [`World::query`].
+#[derive(Default)]
+pub struct World {
+    next_id: u32,
+    entities: Vec<EntityId>,
+    /// `ComponentStorage<T>` for each component type `T`.
+    storages: HashMap<TypeId, Box<dyn AnyStorage>>,
+}
+
+impl World {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Makes a new entity without any components.
+    pub fn spawn(&mut self) -> EntityId {
+        let entity = EntityId(self.next_id);
+        self.next_id += 1;
+        self.entities.push(entity);
+        entity
+    }
+
+    /// Removes the entity along with all of its components.
+    #[allow(dead_code)]
+    pub fn despawn(&mut self, entity: EntityId) {
+        self.entities.retain(|other| *other != entity);
+        for storage in self.storages.values_mut() {
+            storage.remove_entity(entity);
+        }
+    }
+
+    /// Entities that haven't been despawned, in the order they were spawned.
+    pub fn entities(&self) -> &[EntityId] {
+        &self.entities
+    }
+
+    /// Gives `entity` the component, replacing the one of the same type it had.
+    pub fn insert<T: 'static>(&mut self, entity: EntityId, component: T) -> Option<T> {
+        self.storage_mut::<T>().insert(entity, component)
+    }
+
+    #[allow(dead_code)]
+    pub fn remove<T: 'static>(&mut self, entity: EntityId) -> Option<T> {
+        self.existing_storage_mut::<T>()
+            .and_then(|storage| storage.remove(entity))
+    }
+
+    pub fn get<T: 'static>(&self, entity: EntityId) -> Option<&T> {
+        self.storage::<T>().and_then(|storage| storage.get(entity))
+    }
+
+    #[allow(dead_code)]
+    pub fn get_mut<T: 'static>(&mut self, entity: EntityId) -> Option<&mut T> {
+        self.existing_storage_mut::<T>()
+            .and_then(|storage| storage.get_mut(entity))
+    }
+
+    /// The components of type `T`, if any entity has ever had one.
+    pub fn storage<T: 'static>(&self) -> Option<&ComponentStorage<T>> {
+        self.storages
+            .get(&TypeId::of::<T>())
+            .and_then(|storage| storage.as_any().downcast_ref())
+    }
+
+    #[allow(dead_code)]
+    fn existing_storage_mut<T: 'static>(&mut self) -> Option<&mut ComponentStorage<T>> {
+        self.storages
+            .get_mut(&TypeId::of::<T>())
+            .and_then(|storage| storage.as_any_mut().downcast_mut())
+    }
+
+    fn storage_mut<T: 'static>(&mut self) -> &mut ComponentStorage<T> {
+        self.storages
+            .entry(TypeId::of::<T>())
+            .or_insert_with(|| Box::new(ComponentStorage::<T>::new()))
+            .as_any_mut()
+            .downcast_mut()
+            .expect("storage is keyed by its component type")
+    }
+
+    /// Every entity with all of the components in `Q`, which is a tuple
+    /// of component types, such as `(Transform, MeshHandle)`.
+    pub fn query<'w, Q: Query<'w>>(&'w self) -> Box<dyn Iterator<Item = (EntityId, Q::Item)> + 'w> {
+        Q::fetch(self)
+    }
+}
+
+/// A set of component types to look entities up by, implemented
+/// for tuples of up to four components.
+pub trait Query<'w> {
+    /// References to the components of one entity.
+    type Item;
+
+    fn fetch(world: &'w World) -> Box<dyn Iterator<Item = (EntityId, Self::Item)> + 'w>;
+}
+
+// The entities are found by walking the storage of the first
+// component, and looking up the rest for each of them.
+macro_rules! impl_query {
+    ($first:ident $(, $rest:ident)*) => {
+        impl<'w, $first: 'static $(, $rest: 'static)*> Query<'w> for ($first, $($rest,)*) {
+            type Item = (&'w $first, $(&'w $rest,)*);
+
+            #[allow(non_snake_case)]
+            fn fetch(world: &'w World) -> Box<dyn Iterator<Item = (EntityId, Self::Item)> + 'w> {
+                let $first = match world.storage::<$first>() {
+                    Some(storage) => storage,
+                    None => return Box::new(std::iter::empty()),
+                };
+                $(
+                    let $rest = match world.storage::<$rest>() {
+                        Some(storage) => storage,
+                        None => return Box::new(std::iter::empty()),
+                    };
+                )*
+                Box::new($first.iter().filter_map(move |(entity, first)| {
+                    Some((entity, (first, $($rest.get(entity)?,)*)))
+                }))
+            }
+        }
+    };
+}
+
+impl_query!(A);
+impl_query!(A, B);
+impl_query!(A, B, C);
+impl_query!(A, B, C, D);
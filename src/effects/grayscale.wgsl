@@ -0,0 +1,14 @@
+// Drops the color from the frame.
+
+[[group(0), binding(0)]]
+var t_frame: texture_2d<f32>;
+[[group(0), binding(1)]]
+var s_frame: sampler;
+
+[[stage(fragment)]]
+fn main([[location(0)]] tex_coords: vec2<f32>) -> [[location(0)]] vec4<f32> {
+    let color = textureSample(t_frame, s_frame, tex_coords);
+    // Weighted by how bright each channel appears to the eye.
+    let luminance = dot(color.rgb, vec3<f32>(0.2126, 0.7152, 0.0722));
+    return vec4<f32>(vec3<f32>(luminance), color.a);
+}
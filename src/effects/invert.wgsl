@@ -0,0 +1,12 @@
+// Inverts the colors of the frame.
+
+[[group(0), binding(0)]]
+var t_frame: texture_2d<f32>;
+[[group(0), binding(1)]]
+var s_frame: sampler;
+
+[[stage(fragment)]]
+fn main([[location(0)]] tex_coords: vec2<f32>) -> [[location(0)]] vec4<f32> {
+    let color = textureSample(t_frame, s_frame, tex_coords);
+    return vec4<f32>(vec3<f32>(1.0) - color.rgb, color.a);
+}
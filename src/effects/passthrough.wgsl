@@ -0,0 +1,14 @@
+// Copies the frame to the screen unchanged.
+//
+// Post-processing effects take the rendered frame at group 0,
+// and the texture coordinates from `fullscreen.wgsl`.
+
+[[group(0), binding(0)]]
+var t_frame: texture_2d<f32>;
+[[group(0), binding(1)]]
+var s_frame: sampler;
+
+[[stage(fragment)]]
+fn main([[location(0)]] tex_coords: vec2<f32>) -> [[location(0)]] vec4<f32> {
+    return textureSample(t_frame, s_frame, tex_coords);
+}
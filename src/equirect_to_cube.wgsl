@@ -0,0 +1,66 @@
+// Projects an equirectangular panorama onto the six faces of a cubemap.
+// Each invocation writes one texel, with the face index in `z`.
+
+let PI: f32 = 3.14159265359;
+
+[[group(0), binding(0)]]
+var t_panorama: texture_2d<f32>;
+[[group(0), binding(1)]]
+var t_cubemap: texture_storage_2d_array<rgba16float, write>;
+
+// Direction through the texel at `uv`, from -1 to 1 across the face,
+// following the layout of cubemap faces: +X, -X, +Y, -Y, +Z, -Z.
+fn face_direction(face: u32, uv: vec2<f32>) -> vec3<f32> {
+    let u = uv.x;
+    let v = uv.y;
+    if (face == 0u) {
+        return vec3<f32>(1.0, -v, -u);
+    } elseif (face == 1u) {
+        return vec3<f32>(-1.0, -v, u);
+    } elseif (face == 2u) {
+        return vec3<f32>(u, 1.0, v);
+    } elseif (face == 3u) {
+        return vec3<f32>(u, -1.0, -v);
+    } elseif (face == 4u) {
+        return vec3<f32>(u, -v, 1.0);
+    }
+    return vec3<f32>(-u, -v, -1.0);
+}
+
+// Float textures can't be filtered by a sampler without an extra
+// feature, so neighbouring texels are blended by hand.
+fn sample_bilinear(coords: vec2<f32>) -> vec4<f32> {
+    let size = textureDimensions(t_panorama);
+    let texel = coords * vec2<f32>(size) - vec2<f32>(0.5, 0.5);
+    let base = vec2<i32>(floor(texel));
+    let t = fract(texel);
+
+    // Wraps around horizontally, and clamps at the poles.
+    let x0 = (base.x % size.x + size.x) % size.x;
+    let x1 = (x0 + 1) % size.x;
+    let y0 = clamp(base.y, 0, size.y - 1);
+    let y1 = clamp(base.y + 1, 0, size.y - 1);
+
+    let top = mix(textureLoad(t_panorama, vec2<i32>(x0, y0), 0), textureLoad(t_panorama, vec2<i32>(x1, y0), 0), t.x);
+    let bottom = mix(textureLoad(t_panorama, vec2<i32>(x0, y1), 0), textureLoad(t_panorama, vec2<i32>(x1, y1), 0), t.x);
+    return mix(top, bottom, t.y);
+}
+
+[[stage(compute), workgroup_size(8, 8, 1)]]
+fn main([[builtin(global_invocation_id)]] id: vec3<u32>) {
+    let size = textureDimensions(t_cubemap);
+    if (i32(id.x) >= size.x || i32(id.y) >= size.y) {
+        return;
+    }
+
+    let uv = (vec2<f32>(id.xy) + vec2<f32>(0.5, 0.5)) / vec2<f32>(size) * 2.0 - vec2<f32>(1.0, 1.0);
+    let direction = normalize(face_direction(id.z, uv));
+
+    // Longitude goes around the horizon, and latitude
+    // from the top of the panorama to the bottom.
+    let longitude = atan2(direction.z, direction.x);
+    let latitude = acos(clamp(direction.y, -1.0, 1.0));
+    let coords = vec2<f32>(longitude / (2.0 * PI) + 0.5, latitude / PI);
+
+    textureStore(t_cubemap, vec2<i32>(id.xy), i32(id.z), sample_bilinear(coords));
+}
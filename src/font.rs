@@ -0,0 +1,98 @@
+use std::path::Path;
+
+use image::GenericImageView;
+
+use crate::texture::Texture;
+
+/// Glyphs in a row, and rows in the font's image.
+pub const GRID_SIZE: u32 = 16;
+
+/// Drawn in place of characters the font has no glyph for.
+const FALLBACK: char = '?';
+
+/// A fixed-width bitmap font, made from an image holding a
+/// 16×16 grid of glyphs.
+///
+/// The glyphs are laid out in codepoint order, left to right and top to
+/// bottom, so the grid covers ASCII and the rest of Latin-1.
+pub struct Font {
+    texture: Texture,
+    glyph_width: u32,
+    glyph_height: u32,
+}
+
+impl Font {
+    pub fn from_path(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        path: impl AsRef<Path>,
+    ) -> image::ImageResult<Self> {
+        let path = path.as_ref();
+        let img = image::open(path)?;
+        Ok(Self::from_image(
+            device,
+            queue,
+            &img,
+            Some(&path.to_string_lossy()),
+        ))
+    }
+
+    pub fn from_image(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        img: &image::DynamicImage,
+        label: Option<&str>,
+    ) -> Self {
+        let (width, height) = img.dimensions();
+        let mut texture = Texture::from_image(device, queue, img, label);
+
+        // Keeps the pixels of the glyphs sharp when they're scaled up.
+        texture.sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Font Sampler"),
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Nearest,
+            min_filter: wgpu::FilterMode::Nearest,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            ..Default::default()
+        });
+
+        Font {
+            texture,
+            glyph_width: width / GRID_SIZE,
+            glyph_height: height / GRID_SIZE,
+        }
+    }
+
+    pub fn texture(&self) -> &Texture {
+        &self.texture
+    }
+
+    /// Size of every glyph in pixels, before scaling.
+    pub fn glyph_size(&self) -> [f32; 2] {
+        [self.glyph_width as f32, self.glyph_height as f32]
+    }
+
+    /// Region of the texture holding the glyph for `ch`, as
+    /// `[u, v, width, height]` in texture coordinates.
+    pub fn uv_for(&self, ch: char) -> [f32; 4] {
+        let index = Self::glyph_index(ch);
+        let size = 1.0 / GRID_SIZE as f32;
+        [
+            (index % GRID_SIZE) as f32 * size,
+            (index / GRID_SIZE) as f32 * size,
+            size,
+            size,
+        ]
+    }
+
+    fn glyph_index(ch: char) -> u32 {
+        let codepoint = ch as u32;
+        if codepoint < GRID_SIZE * GRID_SIZE {
+            codepoint
+        } else {
+            FALLBACK as u32
+        }
+    }
+}
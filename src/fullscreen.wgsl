@@ -0,0 +1,22 @@
+// Vertex shader for drawing a single triangle that covers the whole
+// screen. No vertex buffer is needed, the corners are generated from
+// the vertex index. Draw it with `draw(0..3, 0..1)`.
+
+struct VertexOutput {
+    [[builtin(position)]] clip_position: vec4<f32>;
+    [[location(0)]] tex_coords: vec2<f32>;
+};
+
+[[stage(vertex)]]
+fn main([[builtin(vertex_index)]] vertex_index: u32) -> VertexOutput {
+    // Gives (0, 0), (2, 0) and (0, 2). The triangle overshoots the
+    // screen, and the parts outside of it are clipped away.
+    let uv = vec2<f32>(f32((vertex_index << 1u) & 2u), f32(vertex_index & 2u));
+
+    var out: VertexOutput;
+    out.clip_position = vec4<f32>(uv * 2.0 - 1.0, 0.0, 1.0);
+    // Texture coordinates start at the top left, while clip
+    // space starts at the bottom left.
+    out.tex_coords = vec2<f32>(uv.x, 1.0 - uv.y);
+    return out;
+}
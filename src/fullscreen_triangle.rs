@@ -0,0 +1,41 @@
+/// A pipeline drawing a single triangle over the whole screen, for
+/// passes that run a fragment shader on every pixel.
+///
+/// The triangle's corners are generated from the vertex index by the
+/// vertex shader in `fullscreen.wgsl`, at -1, -1, then 3, -1 and
+/// -1, 3 in clip space, so there's no vertex buffer to bind. It
+/// overshoots the screen and the rest is clipped away, which unlike
+/// a quad leaves no seam down the diagonal for pixels to be shaded
+/// twice along.
+pub struct FullscreenTriangle {
+    pipeline: wgpu::RenderPipeline,
+}
+
+impl FullscreenTriangle {
+    /// The vertex shader the pipeline has to be built with, with its
+    /// entry point at `main`. The pipeline takes no vertex buffers,
+    /// and passes `tex_coords` to the fragment shader at location 0.
+    pub fn vertex_shader(device: &wgpu::Device) -> wgpu::ShaderModule {
+        device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+            label: Some("Fullscreen Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("fullscreen.wgsl").into()),
+        })
+    }
+
+    /// Wraps a pipeline built with the [`vertex_shader`](Self::vertex_shader).
+    pub fn new(pipeline: wgpu::RenderPipeline) -> Self {
+        FullscreenTriangle { pipeline }
+    }
+
+    #[allow(dead_code)]
+    pub fn pipeline(&self) -> &wgpu::RenderPipeline {
+        &self.pipeline
+    }
+
+    /// Draws the triangle with the pipeline, once the pass has the
+    /// pipeline's bind groups set.
+    pub fn draw<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>) {
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.draw(0..3, 0..1);
+    }
+}
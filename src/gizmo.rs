@@ -0,0 +1,229 @@
+use cgmath::{Matrix4, Point3, Transform as _, Vector3};
+
+use crate::{
+    pipeline::RenderPipelineBuilder, render_pass_guard::RenderPassBuilder,
+    spline_camera::SplinePath, transform::Transform,
+};
+
+/// Room for this many vertices is allocated up front, and
+/// the buffer grows when a frame needs more.
+const INITIAL_CAPACITY: usize = 1024;
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct LineVertex {
+    position: [f32; 3],
+    color: [f32; 4],
+}
+
+impl LineVertex {
+    const ATTRIBUTES: [wgpu::VertexAttribute; 2] =
+        wgpu::vertex_attr_array![0 => Float32x3, 1 => Float32x4];
+
+    fn vertex_buffer_layout<'a>() -> wgpu::VertexBufferLayout<'a> {
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<LineVertex>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes: &Self::ATTRIBUTES,
+        }
+    }
+}
+
+/// Draws debug lines over the scene.
+///
+/// Lines are queued with the `draw_*` methods during the frame,
+/// uploaded by [`GizmoPass::prepare`], and drawn by [`GizmoPass::draw`].
+/// They're drawn without depth testing, so they're never hidden
+/// behind the geometry they describe.
+pub struct GizmoPass {
+    pipeline: wgpu::RenderPipeline,
+    vertex_buffer: wgpu::Buffer,
+    /// Number of vertices the buffer has room for.
+    capacity: usize,
+    vertices: Vec<LineVertex>,
+    /// Number of vertices uploaded by the last `prepare`.
+    vertex_count: u32,
+}
+
+impl GizmoPass {
+    /// The lines are drawn with the camera's bind group at group 0.
+    pub fn new(
+        device: &wgpu::Device,
+        camera_layout: &wgpu::BindGroupLayout,
+        format: wgpu::TextureFormat,
+    ) -> Self {
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Gizmo Pipeline Layout"),
+            bind_group_layouts: &[camera_layout],
+            push_constant_ranges: &[],
+        });
+        let shader = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+            label: Some("Gizmo Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("gizmo.wgsl").into()),
+        });
+        let pipeline = RenderPipelineBuilder::new()
+            .label("Gizmo Pipeline")
+            .vertex_shader(&shader, "main")
+            .vertex_layouts(&[LineVertex::vertex_buffer_layout()])
+            .fragment_shader(
+                &shader,
+                "main",
+                &[wgpu::ColorTargetState {
+                    format,
+                    blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                    write_mask: wgpu::ColorWrites::ALL,
+                }],
+            )
+            .topology(wgpu::PrimitiveTopology::LineList)
+            .cull_mode(None)
+            .build(device, &pipeline_layout)
+            .expect("failed to build gizmo pipeline");
+
+        GizmoPass {
+            pipeline,
+            vertex_buffer: create_vertex_buffer(device, INITIAL_CAPACITY),
+            capacity: INITIAL_CAPACITY,
+            vertices: Vec::new(),
+            vertex_count: 0,
+        }
+    }
+
+    pub fn draw_line(&mut self, a: [f32; 3], b: [f32; 3], color: [f32; 4]) {
+        self.vertices.push(LineVertex { position: a, color });
+        self.vertices.push(LineVertex { position: b, color });
+    }
+
+    /// Outline of the axis aligned box between the two corners.
+    pub fn draw_aabb(&mut self, min: [f32; 3], max: [f32; 3], color: [f32; 4]) {
+        let corner = |i: usize| {
+            [
+                if i & 1 == 0 { min[0] } else { max[0] },
+                if i & 2 == 0 { min[1] } else { max[1] },
+                if i & 4 == 0 { min[2] } else { max[2] },
+            ]
+        };
+
+        // Corners are numbered by which of their coordinates are at the
+        // maximum, so an edge joins corners differing by a single bit.
+        for i in 0..8 {
+            for bit in [1, 2, 4] {
+                if i & bit == 0 {
+                    self.draw_line(corner(i), corner(i | bit), color);
+                }
+            }
+        }
+    }
+
+    /// Line from `origin`, `length` units along `direction`.
+    pub fn draw_ray(
+        &mut self,
+        origin: [f32; 3],
+        direction: [f32; 3],
+        length: f32,
+        color: [f32; 4],
+    ) {
+        use cgmath::InnerSpace;
+        let direction = Vector3::from(direction);
+        if direction.magnitude2() == 0.0 {
+            return;
+        }
+
+        let end = Point3::from(origin) + direction.normalize() * length;
+        self.draw_line(origin, end.into(), color);
+    }
+
+    /// The transform's X, Y and Z axes in red, green and blue,
+    /// `scale` units long before the transform is applied.
+    pub fn draw_axes(&mut self, transform: &Transform, scale: f32) {
+        let matrix = Matrix4::from(transform.to_matrix());
+        let origin = matrix.transform_point(Point3::new(0.0, 0.0, 0.0));
+
+        let axes = [
+            (Point3::new(scale, 0.0, 0.0), [1.0, 0.0, 0.0, 1.0]),
+            (Point3::new(0.0, scale, 0.0), [0.0, 1.0, 0.0, 1.0]),
+            (Point3::new(0.0, 0.0, scale), [0.0, 0.0, 1.0, 1.0]),
+        ];
+        for (end, color) in axes {
+            let end = matrix.transform_point(end);
+            self.draw_line(origin.into(), end.into(), color);
+        }
+    }
+
+    /// The path as `segments` straight lines, with a line from each
+    /// control point towards what the camera looks at there.
+    pub fn draw_spline(&mut self, path: &SplinePath, segments: usize, color: [f32; 4]) {
+        if path.control_points.is_empty() || segments == 0 {
+            return;
+        }
+
+        let start = path.start_time();
+        let step = path.duration() / segments as f32;
+        let mut previous = path.evaluate(start).position;
+        for i in 1..=segments {
+            let point = path.evaluate(start + step * i as f32).position;
+            self.draw_line(previous, point, color);
+            previous = point;
+        }
+
+        for point in &path.control_points {
+            let [x, y, z] = point.position;
+            let [tx, ty, tz] = point.target;
+            self.draw_ray(point.position, [tx - x, ty - y, tz - z], 0.5, color);
+        }
+    }
+
+    /// Uploads the lines queued this frame, and clears the queue
+    /// for the next one. Must be called before the pass is drawn.
+    pub fn prepare(&mut self, device: &wgpu::Device, queue: &wgpu::Queue) {
+        if self.vertices.len() > self.capacity {
+            self.capacity = self.vertices.len().next_power_of_two();
+            self.vertex_buffer = create_vertex_buffer(device, self.capacity);
+        }
+
+        queue.write_buffer(&self.vertex_buffer, 0, bytemuck::cast_slice(&self.vertices));
+        self.vertex_count = self.vertices.len() as u32;
+        self.vertices.clear();
+    }
+
+    /// Records a pass drawing the uploaded lines over `view`.
+    pub fn draw(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        view: &wgpu::TextureView,
+        camera_bind_group: &wgpu::BindGroup,
+    ) {
+        if self.vertex_count == 0 {
+            return;
+        }
+
+        let mut render_pass = RenderPassBuilder::begin_render_pass(
+            encoder,
+            &wgpu::RenderPassDescriptor {
+                label: Some("Gizmo Pass"),
+                color_attachments: &[wgpu::RenderPassColorAttachment {
+                    view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Load,
+                        store: true,
+                    },
+                }],
+                depth_stencil_attachment: None,
+            },
+        );
+
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, camera_bind_group, &[]);
+        render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        render_pass.draw(0..self.vertex_count, 0..1);
+    }
+}
+
+fn create_vertex_buffer(device: &wgpu::Device, capacity: usize) -> wgpu::Buffer {
+    device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Gizmo Vertex Buffer"),
+        size: (capacity * std::mem::size_of::<LineVertex>()) as wgpu::BufferAddress,
+        usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    })
+}
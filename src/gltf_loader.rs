@@ -0,0 +1,1071 @@
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+};
+
+use cgmath::{InnerSpace, Matrix3, Matrix4, Quaternion, SquareMatrix, Vector3};
+use serde_json::Value;
+
+use crate::{
+    bounds::Aabb,
+    mesh::Mesh,
+    pbr::{PbrEnvironment, PbrMaterial},
+    transform::Transform,
+    vertex::Vertex,
+};
+
+/// Magic at the start of a binary `.glb` file, and its chunk types.
+const GLB_MAGIC: &[u8; 4] = b"glTF";
+const CHUNK_JSON: u32 = 0x4e4f_534a;
+const CHUNK_BIN: u32 = 0x004e_4942;
+
+/// Primitive modes, from the glTF spec.
+const MODE_TRIANGLES: usize = 4;
+const MODE_TRIANGLE_STRIP: usize = 5;
+
+#[derive(Debug)]
+pub enum GltfError {
+    Io(std::io::Error),
+    Json(serde_json::Error),
+    Image(image::ImageError),
+    /// The file doesn't follow the glTF spec, or uses a part
+    /// of it that isn't supported.
+    Invalid(String),
+}
+
+impl fmt::Display for GltfError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GltfError::Io(err) => write!(f, "failed to read glTF file: {}", err),
+            GltfError::Json(err) => write!(f, "failed to parse glTF file: {}", err),
+            GltfError::Image(err) => write!(f, "failed to decode glTF image: {}", err),
+            GltfError::Invalid(reason) => write!(f, "invalid glTF file: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for GltfError {}
+
+impl From<std::io::Error> for GltfError {
+    fn from(err: std::io::Error) -> Self {
+        GltfError::Io(err)
+    }
+}
+
+impl From<serde_json::Error> for GltfError {
+    fn from(err: serde_json::Error) -> Self {
+        GltfError::Json(err)
+    }
+}
+
+impl From<image::ImageError> for GltfError {
+    fn from(err: image::ImageError) -> Self {
+        GltfError::Image(err)
+    }
+}
+
+fn invalid(reason: impl Into<String>) -> GltfError {
+    GltfError::Invalid(reason.into())
+}
+
+/// A mesh placed in the scene, with a [`Transform`] ready
+/// to be given to an entity.
+#[derive(Debug, Clone)]
+pub struct GltfEntity {
+    #[allow(dead_code)]
+    pub name: Option<String>,
+    /// Index into [`GltfScene::meshes`].
+    pub mesh: usize,
+    /// Index into [`GltfScene::materials`], or `None` for
+    /// primitives without a material.
+    pub material: Option<usize>,
+    /// Where the mesh is in the scene, with the transforms of the
+    /// nodes above it already applied.
+    pub transform: Transform,
+}
+
+/// A material as a glTF file describes it, with the images of its
+/// textures decoded, for [`PbrMaterial::from_gltf_material`].
+///
+/// The factors are linear, and default to what the glTF spec says
+/// they are when they're left out.
+#[derive(Debug, Clone)]
+pub struct GltfMaterial {
+    pub base_color_factor: [f32; 4],
+    pub metallic_factor: f32,
+    pub roughness_factor: f32,
+    pub emissive_factor: [f32; 3],
+    pub base_color_texture: Option<image::DynamicImage>,
+    /// Roughness in green and metalness in blue.
+    pub metallic_roughness_texture: Option<image::DynamicImage>,
+    pub normal_texture: Option<image::DynamicImage>,
+    pub occlusion_texture: Option<image::DynamicImage>,
+    pub emissive_texture: Option<image::DynamicImage>,
+}
+
+impl Default for GltfMaterial {
+    fn default() -> Self {
+        GltfMaterial {
+            base_color_factor: [1.0; 4],
+            metallic_factor: 1.0,
+            roughness_factor: 1.0,
+            emissive_factor: [0.0; 3],
+            base_color_texture: None,
+            metallic_roughness_texture: None,
+            normal_texture: None,
+            occlusion_texture: None,
+            emissive_texture: None,
+        }
+    }
+}
+
+/// Everything loaded from a glTF file.
+pub struct GltfScene {
+    /// A mesh for each primitive of each glTF mesh.
+    pub meshes: Vec<Mesh>,
+    pub materials: Vec<PbrMaterial>,
+    pub entities: Vec<GltfEntity>,
+}
+
+/// Loads `.gltf` and `.glb` files.
+///
+/// Buffers and images are read from files next to the scene,
+/// from `data:` URIs, or from the binary chunk of a `.glb`.
+/// Only triangles and triangle strips are loaded, and strips are
+/// turned into lists. Primitives without tangents have them worked
+/// out by the PBR shader instead. Materials are lit by `environment`.
+pub struct GltfLoader;
+
+impl GltfLoader {
+    pub fn load(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        path: &Path,
+        environment: &PbrEnvironment,
+    ) -> Result<GltfScene, GltfError> {
+        let bytes = std::fs::read(path)?;
+        let (json, bin) = if bytes.starts_with(GLB_MAGIC) {
+            parse_glb(&bytes)?
+        } else {
+            (serde_json::from_slice(&bytes)?, None)
+        };
+
+        let dir = path.parent().map(Path::to_path_buf).unwrap_or_default();
+        let document = Document::new(json, bin, dir)?;
+
+        let mut meshes = Vec::new();
+        // The engine's meshes, and materials, of each glTF mesh's primitives.
+        let mut primitives = Vec::new();
+        for mesh in document.array("meshes") {
+            let mut mesh_primitives = Vec::new();
+            for primitive in array(mesh, "primitives") {
+                if let Some(uploaded) = document.primitive(device, primitive)? {
+                    let material = primitive.get("material").and_then(as_usize);
+                    mesh_primitives.push((meshes.len(), material));
+                    meshes.push(uploaded);
+                }
+            }
+            primitives.push(mesh_primitives);
+        }
+
+        let layout = PbrMaterial::bind_group_layout(device);
+        let mut images = vec![None; document.array("images").len()];
+        let mut materials = Vec::new();
+        for material in document.array("materials") {
+            let material = document.material(&mut images, material)?;
+            materials.push(PbrMaterial::from_gltf_material(
+                device,
+                queue,
+                &layout,
+                environment,
+                &material,
+            ));
+        }
+
+        let mut entities = Vec::new();
+        for root in document.root_nodes() {
+            document.visit_node(root, Matrix4::identity(), &primitives, &mut entities, 0)?;
+        }
+
+        Ok(GltfScene {
+            meshes,
+            materials,
+            entities,
+        })
+    }
+}
+
+/// The JSON and binary chunks of a `.glb` file.
+fn parse_glb(bytes: &[u8]) -> Result<(Value, Option<Vec<u8>>), GltfError> {
+    let read_u32 = |offset: usize| {
+        bytes
+            .get(offset..offset + 4)
+            .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+            .ok_or_else(|| invalid("the binary file is cut short"))
+    };
+    if read_u32(4)? != 2 {
+        return Err(invalid("only version 2 of glTF is supported"));
+    }
+
+    let mut json = None;
+    let mut bin = None;
+    // Chunks follow the 12 byte header, each with its length and type.
+    let mut offset = 12;
+    while offset < bytes.len() {
+        let length = read_u32(offset)? as usize;
+        let chunk_type = read_u32(offset + 4)?;
+        let end = (offset + 8)
+            .checked_add(length)
+            .ok_or_else(|| invalid("a chunk runs past the end of the file"))?;
+        let data = bytes
+            .get(offset + 8..end)
+            .ok_or_else(|| invalid("a chunk runs past the end of the file"))?;
+        match chunk_type {
+            CHUNK_JSON => json = Some(serde_json::from_slice(data)?),
+            CHUNK_BIN => bin = Some(data.to_vec()),
+            // Unknown chunks are meant to be skipped.
+            _ => {}
+        }
+        offset = end;
+    }
+
+    let json = json.ok_or_else(|| invalid("there's no JSON chunk"))?;
+    Ok((json, bin))
+}
+
+/// The elements of the array at `key`, or none if there isn't one.
+fn array<'a>(json: &'a Value, key: &str) -> &'a [Value] {
+    json.get(key)
+        .and_then(Value::as_array)
+        .map_or(&[], Vec::as_slice)
+}
+
+/// The number, if it's a whole one that isn't negative, as glTF's
+/// indices and counts are.
+fn as_usize(json: &Value) -> Option<usize> {
+    json.as_u64().map(|n| n as usize)
+}
+
+/// The numbers of the array at `key`, if it has `N` of them.
+fn numbers<const N: usize>(json: &Value, key: &str) -> Option<[f32; N]> {
+    let values = json.get(key)?.as_array()?;
+    if values.len() != N {
+        return None;
+    }
+    let mut numbers = [0.0; N];
+    for (number, value) in numbers.iter_mut().zip(values) {
+        *number = value.as_f64()? as f32;
+    }
+    Some(numbers)
+}
+
+fn number(json: &Value, key: &str, default: f32) -> f32 {
+    json.get(key)
+        .and_then(Value::as_f64)
+        .map_or(default, |n| n as f32)
+}
+
+/// A primitive's vertices and triangle list, before it's uploaded.
+struct Geometry {
+    vertices: Vec<Vertex>,
+    indices: Vec<u32>,
+    /// With the handedness of the bitangent in `w`, as glTF gives them.
+    tangents: Option<Vec<[f32; 4]>>,
+}
+
+struct Document {
+    json: Value,
+    buffers: Vec<Vec<u8>>,
+    dir: PathBuf,
+}
+
+impl Document {
+    fn new(json: Value, mut bin: Option<Vec<u8>>, dir: PathBuf) -> Result<Self, GltfError> {
+        let buffers = array(&json, "buffers")
+            .iter()
+            .map(|buffer| match buffer.get("uri").and_then(Value::as_str) {
+                Some(uri) => read_uri(&dir, uri),
+                // Only the first buffer of a `.glb` can be its binary chunk.
+                None => bin.take().ok_or_else(|| invalid("a buffer has no data")),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(Document { json, buffers, dir })
+    }
+
+    fn array(&self, key: &str) -> &[Value] {
+        array(&self.json, key)
+    }
+
+    /// The element of the array at `key` that `index` refers to.
+    fn item(&self, key: &str, index: usize) -> Result<&Value, GltfError> {
+        self.array(key)
+            .get(index)
+            .ok_or_else(|| invalid(format!("there's no {} {}", key, index)))
+    }
+
+    /// The bytes of a buffer view, and how far apart its
+    /// elements are, if they aren't packed.
+    fn buffer_view(&self, index: usize) -> Result<(&[u8], Option<usize>), GltfError> {
+        let view = self.item("bufferViews", index)?;
+        let buffer = view
+            .get("buffer")
+            .and_then(as_usize)
+            .and_then(|buffer| self.buffers.get(buffer))
+            .ok_or_else(|| invalid("a buffer view has no buffer"))?;
+        let offset = view.get("byteOffset").and_then(as_usize).unwrap_or(0);
+        let length = view
+            .get("byteLength")
+            .and_then(as_usize)
+            .ok_or_else(|| invalid("a buffer view has no length"))?;
+        let stride = view.get("byteStride").and_then(as_usize);
+
+        let bytes = offset
+            .checked_add(length)
+            .and_then(|end| buffer.get(offset..end))
+            .ok_or_else(|| invalid("a buffer view runs past the end of its buffer"))?;
+        Ok((bytes, stride))
+    }
+
+    /// Every component of an accessor's elements, one element after
+    /// the other, and how many components each element has.
+    ///
+    /// Values are read as `f64`, which holds every index exactly.
+    fn accessor(&self, index: usize) -> Result<(Vec<f64>, usize), GltfError> {
+        let accessor = self.item("accessors", index)?;
+        if accessor.get("sparse").is_some() {
+            return Err(invalid("sparse accessors aren't supported"));
+        }
+
+        let count = accessor
+            .get("count")
+            .and_then(as_usize)
+            .ok_or_else(|| invalid("an accessor has no count"))?;
+        let components = match accessor.get("type").and_then(Value::as_str) {
+            Some("SCALAR") => 1,
+            Some("VEC2") => 2,
+            Some("VEC3") => 3,
+            Some("VEC4") => 4,
+            Some("MAT4") => 16,
+            _ => return Err(invalid("an accessor has an unsupported type")),
+        };
+        let component_type = accessor
+            .get("componentType")
+            .and_then(as_usize)
+            .ok_or_else(|| invalid("an accessor has no component type"))?;
+        let component_size = match component_type {
+            5120 | 5121 => 1,
+            5122 | 5123 => 2,
+            5125 | 5126 => 4,
+            _ => return Err(invalid("an accessor has an unknown component type")),
+        };
+        let normalized = accessor
+            .get("normalized")
+            .and_then(Value::as_bool)
+            .unwrap_or(false);
+
+        let len = count
+            .checked_mul(components)
+            .ok_or_else(|| invalid("an accessor has too many elements"))?;
+        let view = match accessor.get("bufferView").and_then(as_usize) {
+            Some(view) => view,
+            // Accessors without a view are all zeros.
+            None => return Ok((vec![0.0; len], components)),
+        };
+        let (bytes, stride) = self.buffer_view(view)?;
+        let offset = accessor.get("byteOffset").and_then(as_usize).unwrap_or(0);
+        let element_size = component_size * components;
+        let stride = stride.unwrap_or(element_size);
+        // Checked before anything is read, so a count too big for the
+        // view can't overflow, or be allocated for.
+        let end = match count.checked_sub(1) {
+            Some(last) => last
+                .checked_mul(stride)
+                .and_then(|start| start.checked_add(offset))
+                .and_then(|start| start.checked_add(element_size)),
+            None => Some(0),
+        };
+        if !matches!(end, Some(end) if end <= bytes.len()) {
+            return Err(invalid("an accessor runs past the end of its view"));
+        }
+
+        let mut values = Vec::with_capacity(len);
+        for element in 0..count {
+            for component in 0..components {
+                let start = offset + element * stride + component * component_size;
+                let b = &bytes[start..start + component_size];
+                values.push(read_component(b, component_type, normalized));
+            }
+        }
+        Ok((values, components))
+    }
+
+    /// The elements of an attribute's accessor, given as `N`
+    /// components each.
+    fn attribute<const N: usize>(
+        &self,
+        attributes: &Value,
+        name: &str,
+    ) -> Result<Option<Vec<[f32; N]>>, GltfError> {
+        let index = match attributes.get(name).and_then(as_usize) {
+            Some(index) => index,
+            None => return Ok(None),
+        };
+        let (values, components) = self.accessor(index)?;
+        if components < N {
+            return Err(invalid(format!("{} has too few components", name)));
+        }
+
+        let elements = values
+            .chunks(components)
+            .map(|element| {
+                let mut out = [0.0; N];
+                for (out, &value) in out.iter_mut().zip(element) {
+                    *out = value as f32;
+                }
+                out
+            })
+            .collect();
+        Ok(Some(elements))
+    }
+
+    /// Uploads a primitive, or gives `None` for the kinds that
+    /// aren't drawn as triangles.
+    fn primitive(
+        &self,
+        device: &wgpu::Device,
+        primitive: &Value,
+    ) -> Result<Option<Mesh>, GltfError> {
+        let geometry = match self.geometry(primitive)? {
+            Some(geometry) => geometry,
+            None => return Ok(None),
+        };
+
+        let bounds = Aabb::from_vertices(&geometry.vertices);
+        let mut mesh =
+            Mesh::upload(device, &geometry.vertices, &geometry.indices).with_bounds(bounds);
+        if let Some(tangents) = &geometry.tangents {
+            mesh = mesh.with_tangents(device, tangents);
+        }
+        Ok(Some(mesh))
+    }
+
+    /// The vertices and triangles of a primitive, or `None` for the
+    /// kinds that aren't drawn as triangles.
+    fn geometry(&self, primitive: &Value) -> Result<Option<Geometry>, GltfError> {
+        let mode = primitive
+            .get("mode")
+            .and_then(as_usize)
+            .unwrap_or(MODE_TRIANGLES);
+        if mode != MODE_TRIANGLES && mode != MODE_TRIANGLE_STRIP {
+            log::warn!("skipping a glTF primitive drawn with mode {}", mode);
+            return Ok(None);
+        }
+
+        let attributes = primitive
+            .get("attributes")
+            .ok_or_else(|| invalid("a primitive has no attributes"))?;
+        let positions = self
+            .attribute::<3>(attributes, "POSITION")?
+            .ok_or_else(|| invalid("a primitive has no positions"))?;
+        let normals = self.attribute::<3>(attributes, "NORMAL")?;
+        let tangents = self.attribute::<4>(attributes, "TANGENT")?;
+        let tex_coords = self.attribute::<2>(attributes, "TEXCOORD_0")?;
+        let colors = self.attribute::<3>(attributes, "COLOR_0")?;
+        // Every attribute has an element for each vertex.
+        let count_matches = |name: &str, len: Option<usize>| match len {
+            Some(len) if len != positions.len() => Err(invalid(format!(
+                "{} has {} elements, but there are {} positions",
+                name,
+                len,
+                positions.len()
+            ))),
+            _ => Ok(()),
+        };
+        count_matches("NORMAL", normals.as_ref().map(Vec::len))?;
+        count_matches("TANGENT", tangents.as_ref().map(Vec::len))?;
+        count_matches("TEXCOORD_0", tex_coords.as_ref().map(Vec::len))?;
+        count_matches("COLOR_0", colors.as_ref().map(Vec::len))?;
+
+        let mut indices = match primitive.get("indices").and_then(as_usize) {
+            Some(accessor) => {
+                let (values, _) = self.accessor(accessor)?;
+                values.into_iter().map(|index| index as u32).collect()
+            }
+            None => (0..positions.len() as u32).collect::<Vec<_>>(),
+        };
+        if indices
+            .iter()
+            .any(|&index| index as usize >= positions.len())
+        {
+            return Err(invalid("an index is past the last vertex"));
+        }
+        if mode == MODE_TRIANGLE_STRIP {
+            indices = strip_to_list(&indices);
+        }
+
+        let normals = normals.unwrap_or_else(|| smooth_normals(&positions, &indices));
+        let vertices = positions
+            .iter()
+            .enumerate()
+            .map(|(i, &position)| Vertex {
+                position,
+                color: colors.as_ref().map_or([1.0; 3], |colors| colors[i]),
+                normal: normals[i],
+                tex_coords: tex_coords.as_ref().map_or([0.0; 2], |uvs| uvs[i]),
+            })
+            .collect();
+
+        Ok(Some(Geometry {
+            vertices,
+            indices,
+            tangents,
+        }))
+    }
+
+    /// The decoded image a texture refers to, decoded the first
+    /// time it's asked for.
+    fn image(
+        &self,
+        images: &mut [Option<image::DynamicImage>],
+        texture: usize,
+    ) -> Result<image::DynamicImage, GltfError> {
+        let index = self
+            .item("textures", texture)?
+            .get("source")
+            .and_then(as_usize)
+            .ok_or_else(|| invalid("a texture has no image"))?;
+        if let Some(img) = images.get(index).and_then(Option::as_ref) {
+            return Ok(img.clone());
+        }
+
+        let image = self.item("images", index)?;
+        let img = match (
+            image.get("uri").and_then(Value::as_str),
+            image.get("bufferView").and_then(as_usize),
+        ) {
+            (Some(uri), _) => image::load_from_memory(&read_uri(&self.dir, uri)?)?,
+            (None, Some(view)) => image::load_from_memory(self.buffer_view(view)?.0)?,
+            (None, None) => return Err(invalid("an image has no data")),
+        };
+        images[index] = Some(img.clone());
+        Ok(img)
+    }
+
+    /// The image of one of a material's textures, or `None` when
+    /// it doesn't have one.
+    fn texture(
+        &self,
+        images: &mut [Option<image::DynamicImage>],
+        info: Option<&Value>,
+    ) -> Result<Option<image::DynamicImage>, GltfError> {
+        info.and_then(|info| info.get("index"))
+            .and_then(as_usize)
+            .map(|texture| self.image(images, texture))
+            .transpose()
+    }
+
+    /// A material's factors, and the images of its textures.
+    fn material(
+        &self,
+        images: &mut [Option<image::DynamicImage>],
+        material: &Value,
+    ) -> Result<GltfMaterial, GltfError> {
+        let defaults = GltfMaterial::default();
+        let pbr = material.get("pbrMetallicRoughness").unwrap_or(&Value::Null);
+        Ok(GltfMaterial {
+            base_color_factor: numbers(pbr, "baseColorFactor")
+                .unwrap_or(defaults.base_color_factor),
+            metallic_factor: number(pbr, "metallicFactor", defaults.metallic_factor),
+            roughness_factor: number(pbr, "roughnessFactor", defaults.roughness_factor),
+            emissive_factor: numbers(material, "emissiveFactor")
+                .unwrap_or(defaults.emissive_factor),
+            base_color_texture: self.texture(images, pbr.get("baseColorTexture"))?,
+            metallic_roughness_texture: self
+                .texture(images, pbr.get("metallicRoughnessTexture"))?,
+            normal_texture: self.texture(images, material.get("normalTexture"))?,
+            occlusion_texture: self.texture(images, material.get("occlusionTexture"))?,
+            emissive_texture: self.texture(images, material.get("emissiveTexture"))?,
+        })
+    }
+
+    /// The nodes of the default scene, or every node that isn't a
+    /// child of another when there are no scenes.
+    fn root_nodes(&self) -> Vec<usize> {
+        let scene = self.json.get("scene").and_then(as_usize).unwrap_or(0);
+        if let Some(scene) = self.array("scenes").get(scene) {
+            return array(scene, "nodes").iter().filter_map(as_usize).collect();
+        }
+
+        let children = self
+            .array("nodes")
+            .iter()
+            .flat_map(|node| array(node, "children"))
+            .filter_map(as_usize)
+            .collect::<Vec<_>>();
+        (0..self.array("nodes").len())
+            .filter(|node| !children.contains(node))
+            .collect()
+    }
+
+    /// Adds an entity for each primitive of the node's mesh, and
+    /// then goes on to its children, carrying its transform down.
+    fn visit_node(
+        &self,
+        index: usize,
+        parent: Matrix4<f32>,
+        primitives: &[Vec<(usize, Option<usize>)>],
+        entities: &mut Vec<GltfEntity>,
+        depth: usize,
+    ) -> Result<(), GltfError> {
+        // Nodes form a tree, so a deeper one means they loop.
+        if depth > self.array("nodes").len() {
+            return Err(invalid("the node hierarchy has a cycle"));
+        }
+
+        let node = self.item("nodes", index)?;
+        let world = parent * local_matrix(node);
+        if let Some(mesh) = node.get("mesh").and_then(as_usize) {
+            let mesh_primitives = primitives
+                .get(mesh)
+                .ok_or_else(|| invalid(format!("there's no mesh {}", mesh)))?;
+            let transform = decompose(world);
+            for &(mesh, material) in mesh_primitives {
+                entities.push(GltfEntity {
+                    name: node.get("name").and_then(Value::as_str).map(str::to_string),
+                    mesh,
+                    material,
+                    transform,
+                });
+            }
+        }
+
+        for child in array(node, "children").iter().filter_map(as_usize) {
+            self.visit_node(child, world, primitives, entities, depth + 1)?;
+        }
+        Ok(())
+    }
+}
+
+/// A component read from little endian bytes. Normalized integers
+/// are scaled to between 0 and 1, or -1 and 1 for signed ones.
+fn read_component(b: &[u8], component_type: usize, normalized: bool) -> f64 {
+    let (value, max) = match component_type {
+        5120 => (b[0] as i8 as f64, i8::MAX as f64),
+        5121 => (b[0] as f64, u8::MAX as f64),
+        5122 => (i16::from_le_bytes([b[0], b[1]]) as f64, i16::MAX as f64),
+        5123 => (u16::from_le_bytes([b[0], b[1]]) as f64, u16::MAX as f64),
+        5125 => (
+            u32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64,
+            u32::MAX as f64,
+        ),
+        _ => return f32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64,
+    };
+    if normalized {
+        (value / max).max(-1.0)
+    } else {
+        value
+    }
+}
+
+/// The contents of a buffer or image, from a `data:` URI or
+/// a file relative to the scene.
+fn read_uri(dir: &Path, uri: &str) -> Result<Vec<u8>, GltfError> {
+    if let Some(data) = uri.strip_prefix("data:") {
+        let (_, encoded) = data
+            .split_once(";base64,")
+            .ok_or_else(|| invalid("only base64 data URIs are supported"))?;
+        return decode_base64(encoded).ok_or_else(|| invalid("a data URI isn't valid base64"));
+    }
+    Ok(std::fs::read(dir.join(percent_decode(uri)))?)
+}
+
+/// Undoes the escaping of spaces and other characters in a URI.
+fn percent_decode(uri: &str) -> String {
+    let bytes = uri.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = bytes
+            .get(i + 1..i + 3)
+            .filter(|_| bytes[i] == b'%')
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match escaped {
+            Some(byte) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            None => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+fn decode_base64(encoded: &str) -> Option<Vec<u8>> {
+    let sextet = |c: u8| match c {
+        b'A'..=b'Z' => Some(c - b'A'),
+        b'a'..=b'z' => Some(c - b'a' + 26),
+        b'0'..=b'9' => Some(c - b'0' + 52),
+        b'+' => Some(62),
+        b'/' => Some(63),
+        _ => None,
+    };
+
+    let encoded = encoded.trim_end_matches('=').as_bytes();
+    let mut bytes = Vec::with_capacity(encoded.len() * 3 / 4);
+    let mut bits = 0u32;
+    let mut bit_count = 0;
+    for &c in encoded {
+        bits = bits << 6 | sextet(c)? as u32;
+        bit_count += 6;
+        if bit_count >= 8 {
+            bit_count -= 8;
+            bytes.push((bits >> bit_count) as u8);
+        }
+    }
+    Some(bytes)
+}
+
+/// Every triangle of a strip, as its own three indices.
+///
+/// Every other triangle of a strip winds the other way, so their
+/// first two indices are swapped to keep them all facing the same
+/// way. Triangles with a repeated index join strips together, and
+/// are left out.
+pub fn strip_to_list(strip: &[u32]) -> Vec<u32> {
+    let mut list = Vec::with_capacity(strip.len().saturating_sub(2) * 3);
+    for (i, window) in strip.windows(3).enumerate() {
+        let (a, b, c) = (window[0], window[1], window[2]);
+        if a == b || b == c || a == c {
+            continue;
+        }
+        if i % 2 == 0 {
+            list.extend_from_slice(&[a, b, c]);
+        } else {
+            list.extend_from_slice(&[b, a, c]);
+        }
+    }
+    list
+}
+
+/// Normals averaged from the faces around each vertex, weighted by
+/// their area, for primitives that don't come with any.
+fn smooth_normals(positions: &[[f32; 3]], indices: &[u32]) -> Vec<[f32; 3]> {
+    let mut normals = vec![Vector3::new(0.0, 0.0, 0.0); positions.len()];
+    for triangle in indices.chunks_exact(3) {
+        let [a, b, c] = [0, 1, 2].map(|i| Vector3::from(positions[triangle[i] as usize]));
+        // Longer the bigger the face is.
+        let normal = (b - a).cross(c - a);
+        for &index in triangle {
+            normals[index as usize] += normal;
+        }
+    }
+    normals
+        .into_iter()
+        .map(|normal| {
+            if normal.magnitude2() > 0.0 {
+                normal.normalize().into()
+            } else {
+                [0.0, 1.0, 0.0]
+            }
+        })
+        .collect()
+}
+
+/// A node's transform relative to its parent, given either as a
+/// matrix or as a translation, rotation and scale.
+fn local_matrix(node: &Value) -> Matrix4<f32> {
+    if let Some(m) = numbers::<16>(node, "matrix") {
+        // Stored column by column, like cgmath.
+        return Matrix4::new(
+            m[0], m[1], m[2], m[3], m[4], m[5], m[6], m[7], m[8], m[9], m[10], m[11], m[12], m[13],
+            m[14], m[15],
+        );
+    }
+
+    let translation = numbers::<3>(node, "translation").unwrap_or([0.0; 3]);
+    let rotation = numbers::<4>(node, "rotation").unwrap_or([0.0, 0.0, 0.0, 1.0]);
+    let scale = numbers::<3>(node, "scale").unwrap_or([1.0; 3]);
+    Transform {
+        translation,
+        rotation,
+        scale,
+    }
+    .to_matrix()
+    .into()
+}
+
+/// Splits a matrix back into a translation, rotation and scale.
+///
+/// Exact unless the matrix shears, which happens when a parent
+/// scales unevenly and a child rotates, and then only an
+/// approximation is possible.
+fn decompose(matrix: Matrix4<f32>) -> Transform {
+    let translation = matrix.w.truncate();
+    let mut axes = Matrix3::from_cols(
+        matrix.x.truncate(),
+        matrix.y.truncate(),
+        matrix.z.truncate(),
+    );
+    let mut scale = [axes.x.magnitude(), axes.y.magnitude(), axes.z.magnitude()];
+    // A mirrored matrix can't be a rotation, so the mirroring
+    // is moved into the scale.
+    if axes.determinant() < 0.0 {
+        scale[0] = -scale[0];
+    }
+    for (axis, &scale) in scale.iter().enumerate() {
+        if scale != 0.0 {
+            axes[axis] /= scale;
+        }
+    }
+    // Orthonormalised again, in case the axes were skewed.
+    let x = axes.x.normalize();
+    let z = x.cross(axes.y).normalize();
+    let y = z.cross(x);
+    let rotation = Quaternion::from(Matrix3::from_cols(x, y, z));
+
+    Transform {
+        translation: translation.into(),
+        rotation: rotation.normalize().into(),
+        scale,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    /// A `.glb` with `json` and `bin` as its chunks.
+    fn glb(json: &Value, bin: &[u8]) -> Vec<u8> {
+        let mut text = serde_json::to_vec(json).unwrap();
+        // Chunks are padded to four bytes, the JSON with spaces.
+        text.resize(text.len().next_multiple_of(4), b' ');
+        let mut bin = bin.to_vec();
+        bin.resize(bin.len().next_multiple_of(4), 0);
+
+        let mut bytes = GLB_MAGIC.to_vec();
+        bytes.extend_from_slice(&2u32.to_le_bytes());
+        bytes.extend_from_slice(&((12 + 16 + text.len() + bin.len()) as u32).to_le_bytes());
+        for (chunk_type, data) in [(CHUNK_JSON, text), (CHUNK_BIN, bin)] {
+            bytes.extend_from_slice(&(data.len() as u32).to_le_bytes());
+            bytes.extend_from_slice(&chunk_type.to_le_bytes());
+            bytes.extend_from_slice(&data);
+        }
+        bytes
+    }
+
+    /// A document with an accessor for each of `attributes`, given as
+    /// floats one after the other in the binary chunk.
+    fn document(primitive: Value, attributes: &[(&str, &str, Vec<f32>)]) -> (Document, Value) {
+        let mut bin = Vec::new();
+        let mut views = Vec::new();
+        let mut accessors = Vec::new();
+        let mut names = serde_json::Map::new();
+        for (i, (name, kind, values)) in attributes.iter().enumerate() {
+            let components = match *kind {
+                "VEC2" => 2,
+                "VEC3" => 3,
+                _ => 4,
+            };
+            views.push(json!({
+                "buffer": 0,
+                "byteOffset": bin.len(),
+                "byteLength": values.len() * 4,
+            }));
+            accessors.push(json!({
+                "bufferView": i,
+                "componentType": 5126,
+                "count": values.len() / components,
+                "type": kind,
+            }));
+            names.insert(name.to_string(), json!(i));
+            bin.extend(values.iter().flat_map(|value| value.to_le_bytes()));
+        }
+        let json = json!({
+            "buffers": [{ "byteLength": bin.len() }],
+            "bufferViews": views,
+            "accessors": accessors,
+        });
+        let mut primitive = primitive;
+        primitive["attributes"] = Value::Object(names);
+        (
+            Document::new(json, Some(bin), PathBuf::new()).unwrap(),
+            primitive,
+        )
+    }
+
+    /// A square, as a strip of two triangles.
+    fn square_positions() -> Vec<f32> {
+        vec![0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 1.0, 1.0, 0.0]
+    }
+
+    #[test]
+    fn glb_files_are_split_into_their_chunks() {
+        let bytes = glb(&json!({ "asset": { "version": "2.0" } }), &[1, 2, 3, 4]);
+        let (json, bin) = parse_glb(&bytes).unwrap();
+        assert_eq!(json["asset"]["version"], "2.0");
+        assert_eq!(bin, Some(vec![1, 2, 3, 4]));
+
+        // Cut off partway into the binary chunk.
+        let cut = &bytes[..bytes.len() - 2];
+        assert!(matches!(parse_glb(cut), Err(GltfError::Invalid(_))));
+
+        let mut version_1 = bytes.clone();
+        version_1[4] = 1;
+        assert!(matches!(parse_glb(&version_1), Err(GltfError::Invalid(_))));
+    }
+
+    #[test]
+    fn chunk_lengths_past_the_end_are_an_error() {
+        let mut bytes = glb(&json!({}), &[]);
+        // The binary chunk's length, which follows the JSON chunk.
+        let bin_length = bytes.len() - 8;
+        bytes[bin_length..bin_length + 4].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(matches!(parse_glb(&bytes), Err(GltfError::Invalid(_))));
+    }
+
+    #[test]
+    fn malformed_json_is_an_error() {
+        let bytes = glb(&json!({}), &[]);
+        let mut broken = bytes.clone();
+        // The closing brace of the JSON chunk.
+        broken[21] = b',';
+        assert!(matches!(parse_glb(&broken), Err(GltfError::Json(_))));
+    }
+
+    #[test]
+    fn data_uris_are_decoded_from_base64() {
+        let dir = Path::new("");
+        assert_eq!(
+            read_uri(dir, "data:application/octet-stream;base64,AAECAw==").unwrap(),
+            [0, 1, 2, 3]
+        );
+        // Without the padding.
+        assert_eq!(
+            read_uri(dir, "data:application/gltf-buffer;base64,/+8").unwrap(),
+            [0xff, 0xef]
+        );
+        assert!(read_uri(dir, "data:text/plain,hello").is_err());
+        assert!(read_uri(dir, "data:application/octet-stream;base64,AA*A").is_err());
+    }
+
+    #[test]
+    fn uris_are_percent_decoded() {
+        assert_eq!(
+            percent_decode("my%20scene%2Fbuffer.bin"),
+            "my scene/buffer.bin"
+        );
+        // Not an escape, so it's left as it is.
+        assert_eq!(percent_decode("100%.bin"), "100%.bin");
+    }
+
+    #[test]
+    fn strips_become_lists_facing_the_same_way() {
+        assert_eq!(strip_to_list(&[0, 1, 2, 3, 4]), [0, 1, 2, 2, 1, 3, 2, 3, 4]);
+        // The repeated index joins two strips, without a triangle.
+        assert_eq!(strip_to_list(&[0, 1, 2, 2, 5, 6]), [0, 1, 2, 5, 2, 6]);
+        assert!(strip_to_list(&[0, 1]).is_empty());
+    }
+
+    #[test]
+    fn decomposing_gives_back_the_matrix() {
+        let transforms = [
+            Transform {
+                translation: [1.0, -2.0, 3.0],
+                rotation: Transform::from_euler_xyz(0.3, -1.2, 2.0).rotation,
+                scale: [2.0, 0.5, 3.0],
+            },
+            // Mirrored, which goes into the scale.
+            Transform {
+                translation: [0.0, 4.0, 0.0],
+                rotation: Transform::from_euler_xyz(1.0, 0.0, 0.5).rotation,
+                scale: [-1.0, 1.0, 1.0],
+            },
+        ];
+        for transform in transforms {
+            let matrix = Matrix4::from(transform.to_matrix());
+            let decomposed = decompose(matrix);
+            assert_eq!(decomposed.translation, transform.translation);
+            let actual: [[f32; 4]; 4] = decomposed.to_matrix();
+            let expected: [[f32; 4]; 4] = matrix.into();
+            for (a, e) in actual.iter().flatten().zip(expected.iter().flatten()) {
+                assert!((a - e).abs() < 1e-5, "{:?} != {:?}", actual, expected);
+            }
+        }
+    }
+
+    #[test]
+    fn strips_are_read_with_their_tangents() {
+        let (document, primitive) = document(
+            json!({ "mode": MODE_TRIANGLE_STRIP }),
+            &[
+                ("POSITION", "VEC3", square_positions()),
+                ("TANGENT", "VEC4", [1.0, 0.0, 0.0, -1.0].repeat(4)),
+                (
+                    "TEXCOORD_0",
+                    "VEC2",
+                    vec![0.0, 0.0, 1.0, 0.0, 0.0, 1.0, 1.0, 1.0],
+                ),
+            ],
+        );
+        let geometry = document.geometry(&primitive).unwrap().unwrap();
+
+        assert_eq!(geometry.indices, [0, 1, 2, 2, 1, 3]);
+        assert_eq!(geometry.tangents, Some(vec![[1.0, 0.0, 0.0, -1.0]; 4]));
+        assert_eq!(geometry.vertices[3].tex_coords, [1.0, 1.0]);
+        // Smoothed, since the square has no normals of its own, and
+        // facing +Z, as it winds counter-clockwise.
+        for vertex in &geometry.vertices {
+            assert_eq!(vertex.normal, [0.0, 0.0, 1.0]);
+            assert_eq!(vertex.color, [1.0; 3]);
+        }
+    }
+
+    #[test]
+    fn attributes_with_too_few_elements_are_an_error() {
+        for (name, kind, values) in [
+            ("NORMAL", "VEC3", vec![0.0, 0.0, 1.0]),
+            ("TANGENT", "VEC4", vec![1.0, 0.0, 0.0, 1.0]),
+            ("TEXCOORD_0", "VEC2", vec![0.0, 0.0, 1.0, 0.0]),
+            ("COLOR_0", "VEC3", vec![1.0; 9]),
+        ] {
+            let (document, primitive) = document(
+                json!({}),
+                &[
+                    ("POSITION", "VEC3", square_positions()),
+                    (name, kind, values),
+                ],
+            );
+            assert!(
+                matches!(document.geometry(&primitive), Err(GltfError::Invalid(_))),
+                "{} was accepted",
+                name
+            );
+        }
+    }
+
+    #[test]
+    fn accessors_past_the_end_of_their_view_are_an_error() {
+        let (document, _) = document(json!({}), &[("POSITION", "VEC3", square_positions())]);
+        let mut json = document.json.clone();
+        json["accessors"][0]["count"] = json!(usize::MAX);
+        let document = Document { json, ..document };
+        assert!(matches!(document.accessor(0), Err(GltfError::Invalid(_))));
+    }
+
+    #[test]
+    fn other_primitive_modes_are_skipped() {
+        // Lines.
+        let (document, primitive) = document(
+            json!({ "mode": 1 }),
+            &[("POSITION", "VEC3", square_positions())],
+        );
+        assert!(document.geometry(&primitive).unwrap().is_none());
+    }
+}
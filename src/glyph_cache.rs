@@ -0,0 +1,500 @@
+use std::{collections::HashMap, num::NonZeroU32};
+
+use crate::{texture::Texture, texture_atlas::UvRect};
+
+/// Empty pixels around each glyph, so sampling near the edge
+/// of one doesn't pick up its neighbours.
+const PADDING: u32 = 1;
+
+/// Sizes are cached to the nearest tenth of a pixel.
+const SIZE_STEPS_PER_PIXEL: f32 = 10.0;
+
+/// Where a glyph is in the cache's atlas, and how to place it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GlyphRegion {
+    pub uv_rect: UvRect,
+    /// Size of the glyph's image in pixels. Zero for glyphs
+    /// with nothing to draw, like spaces.
+    pub size: [u32; 2],
+    /// From the pen position on the baseline to the top left
+    /// corner of the image, with Y pointing down.
+    pub offset: [f32; 2],
+    /// How far the pen moves on after the glyph.
+    pub advance: f32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct GlyphKey {
+    codepoint: char,
+    size: u32,
+}
+
+impl GlyphKey {
+    fn new(codepoint: char, size_px: f32) -> Self {
+        GlyphKey {
+            codepoint,
+            size: (size_px * SIZE_STEPS_PER_PIXEL).round() as u32,
+        }
+    }
+}
+
+struct CachedGlyph {
+    region: GlyphRegion,
+    /// Where the glyph's space in the atlas is, if it has any.
+    slot: Option<Slot>,
+    /// When the glyph was last asked for, counted in requests.
+    last_used: u64,
+}
+
+/// Space taken up on a shelf, including the padding.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Slot {
+    shelf: usize,
+    x: u32,
+    width: u32,
+}
+
+/// A row of the atlas, as tall as the first glyph put in it.
+struct Shelf {
+    y: u32,
+    height: u32,
+    /// Where the untouched space on the right starts.
+    end: u32,
+    /// Spans left by evicted glyphs, as `(x, width)`, before `end`.
+    free: Vec<(u32, u32)>,
+}
+
+impl Shelf {
+    fn allocate(&mut self, width: u32, atlas_width: u32) -> Option<u32> {
+        if let Some(index) = self.free.iter().position(|(_, span)| *span >= width) {
+            let (x, span) = self.free[index];
+            if span == width {
+                self.free.remove(index);
+            } else {
+                self.free[index] = (x + width, span - width);
+            }
+            return Some(x);
+        }
+
+        if self.end + width <= atlas_width {
+            let x = self.end;
+            self.end += width;
+            return Some(x);
+        }
+        None
+    }
+
+    /// Gives the span back, merging it with the free spans around it.
+    fn release(&mut self, x: u32, width: u32) {
+        self.free.push((x, width));
+        self.free.sort_by_key(|(x, _)| *x);
+
+        let mut merged: Vec<(u32, u32)> = Vec::with_capacity(self.free.len());
+        for (x, width) in self.free.drain(..) {
+            match merged.last_mut() {
+                Some((last_x, last_width)) if *last_x + *last_width == x => *last_width += width,
+                _ => merged.push((x, width)),
+            }
+        }
+        // A span running into the untouched space becomes part of it.
+        if let Some(&(x, width)) = merged.last() {
+            if x + width == self.end {
+                merged.pop();
+                self.end = x;
+            }
+        }
+        self.free = merged;
+    }
+
+    fn is_empty(&self) -> bool {
+        self.end == 0
+    }
+}
+
+/// Which glyphs are cached where in the atlas, and which to evict
+/// when a new one doesn't fit, apart from the texture they're in.
+struct GlyphAtlas {
+    size: u32,
+    glyphs: HashMap<GlyphKey, CachedGlyph>,
+    shelves: Vec<Shelf>,
+    /// Counts requests, to timestamp glyphs with.
+    clock: u64,
+}
+
+impl GlyphAtlas {
+    fn new(size: u32) -> Self {
+        GlyphAtlas {
+            size,
+            glyphs: HashMap::new(),
+            shelves: Vec::new(),
+            clock: 0,
+        }
+    }
+
+    /// Counts a request for the glyph, returning its region if it's
+    /// already cached.
+    fn request(&mut self, key: GlyphKey) -> Option<GlyphRegion> {
+        self.clock += 1;
+        let glyph = self.glyphs.get_mut(&key)?;
+        glyph.last_used = self.clock;
+        Some(glyph.region)
+    }
+
+    fn insert(&mut self, key: GlyphKey, region: GlyphRegion, slot: Option<Slot>) {
+        self.glyphs.insert(
+            key,
+            CachedGlyph {
+                region,
+                slot,
+                last_used: self.clock,
+            },
+        );
+    }
+
+    /// Top left corner of the slot's padded space.
+    fn slot_origin(&self, slot: Slot) -> (u32, u32) {
+        (slot.x, self.shelves[slot.shelf].y)
+    }
+
+    /// Finds space for a padded glyph, evicting the least recently
+    /// used glyphs until it fits.
+    fn allocate(&mut self, width: u32, height: u32) -> Option<Slot> {
+        if width > self.size || height > self.size {
+            log::warn!(
+                "{}x{} glyph doesn't fit in a {2}x{2} glyph cache",
+                width,
+                height,
+                self.size
+            );
+            return None;
+        }
+
+        loop {
+            if let Some(slot) = self.try_allocate(width, height) {
+                return Some(slot);
+            }
+            if !self.evict_least_recently_used() {
+                return None;
+            }
+        }
+    }
+
+    fn try_allocate(&mut self, width: u32, height: u32) -> Option<Slot> {
+        let atlas_size = self.size;
+        // Glyphs under half the height of a shelf would waste most
+        // of their space on it, unless nothing else is using it.
+        for (index, shelf) in self.shelves.iter_mut().enumerate() {
+            if height <= shelf.height && (height * 2 > shelf.height || shelf.is_empty()) {
+                if let Some(x) = shelf.allocate(width, atlas_size) {
+                    return Some(Slot {
+                        shelf: index,
+                        x,
+                        width,
+                    });
+                }
+            }
+        }
+
+        let y = self
+            .shelves
+            .last()
+            .map(|shelf| shelf.y + shelf.height)
+            .unwrap_or(0);
+        if y + height > atlas_size {
+            return None;
+        }
+        self.shelves.push(Shelf {
+            y,
+            height,
+            end: width,
+            free: Vec::new(),
+        });
+        Some(Slot {
+            shelf: self.shelves.len() - 1,
+            x: 0,
+            width,
+        })
+    }
+
+    /// Evicts the glyph that went the longest without being asked
+    /// for. Returns false when there's nothing left to evict.
+    fn evict_least_recently_used(&mut self) -> bool {
+        let key = match self
+            .glyphs
+            .iter()
+            .filter(|(_, glyph)| glyph.slot.is_some())
+            .min_by_key(|(_, glyph)| glyph.last_used)
+        {
+            Some((key, _)) => *key,
+            None => return false,
+        };
+        let glyph = self
+            .glyphs
+            .remove(&key)
+            .expect("evicted glyph was just found");
+        if let Some(slot) = glyph.slot {
+            self.shelves[slot.shelf].release(slot.x, slot.width);
+        }
+
+        // Empty shelves at the bottom give their rows back, so
+        // they can be taken by glyphs of a different height.
+        while self.shelves.last().is_some_and(Shelf::is_empty) {
+            self.shelves.pop();
+        }
+        true
+    }
+}
+
+/// Glyphs rasterized from a font as they're needed, and packed
+/// into rows of an atlas texture.
+///
+/// When a new glyph doesn't fit, the glyphs that went the longest
+/// without being asked for are evicted to make room. Regions are
+/// only good until the next glyph is rasterized, since it could
+/// take the space of an evicted one.
+pub struct GlyphCache {
+    font: rusttype::Font<'static>,
+    texture: Texture,
+    atlas: GlyphAtlas,
+}
+
+impl GlyphCache {
+    /// Creates an empty `atlas_size` by `atlas_size` atlas for `font`.
+    pub fn new(device: &wgpu::Device, font: rusttype::Font<'static>, atlas_size: u32) -> Self {
+        let size = wgpu::Extent3d {
+            width: atlas_size,
+            height: atlas_size,
+            depth_or_array_layers: 1,
+        };
+        // Only the coverage of each pixel is stored.
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Glyph Cache Atlas"),
+            size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::R8Unorm,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Glyph Cache Sampler"),
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            ..Default::default()
+        });
+
+        GlyphCache {
+            font,
+            texture: Texture {
+                texture,
+                view,
+                sampler,
+                size,
+            },
+            atlas: GlyphAtlas::new(atlas_size),
+        }
+    }
+
+    /// The atlas the glyphs are uploaded into.
+    pub fn texture(&self) -> &Texture {
+        &self.texture
+    }
+
+    /// Number of glyphs in the cache.
+    #[allow(dead_code)]
+    pub fn len(&self) -> usize {
+        self.atlas.glyphs.len()
+    }
+
+    #[allow(dead_code)]
+    pub fn is_empty(&self) -> bool {
+        self.atlas.glyphs.is_empty()
+    }
+
+    /// Whether the glyph is cached at the size, without rasterizing
+    /// it or counting it as used.
+    #[allow(dead_code)]
+    pub fn contains(&self, codepoint: char, size_px: f32) -> bool {
+        self.atlas
+            .glyphs
+            .contains_key(&GlyphKey::new(codepoint, size_px))
+    }
+
+    /// How far the pen moves on after `codepoint` at `size_px`,
+    /// without rasterizing it.
+    pub fn advance(&self, codepoint: char, size_px: f32) -> f32 {
+        self.font
+            .glyph(codepoint)
+            .scaled(rusttype::Scale::uniform(size_px))
+            .h_metrics()
+            .advance_width
+    }
+
+    /// From the top of a line to its baseline, in pixels at `size_px`.
+    pub fn ascent(&self, size_px: f32) -> f32 {
+        self.font
+            .v_metrics(rusttype::Scale::uniform(size_px))
+            .ascent
+    }
+
+    /// From the top of one line to the next, in pixels at `size_px`.
+    pub fn line_height(&self, size_px: f32) -> f32 {
+        let metrics = self.font.v_metrics(rusttype::Scale::uniform(size_px));
+        metrics.ascent - metrics.descent + metrics.line_gap
+    }
+
+    /// Where `codepoint` drawn `size_px` pixels to the em is in the
+    /// atlas, rasterizing and uploading it first if it isn't cached.
+    ///
+    /// `None` when the glyph is too big for the atlas, even with
+    /// everything else evicted.
+    pub fn get_or_rasterize(
+        &mut self,
+        queue: &wgpu::Queue,
+        codepoint: char,
+        size_px: f32,
+    ) -> Option<GlyphRegion> {
+        let key = GlyphKey::new(codepoint, size_px);
+        if let Some(region) = self.atlas.request(key) {
+            return Some(region);
+        }
+
+        let scale = rusttype::Scale::uniform(size_px);
+        let glyph = self.font.glyph(codepoint).scaled(scale);
+        let advance = glyph.h_metrics().advance_width;
+        let glyph = glyph.positioned(rusttype::point(0.0, 0.0));
+
+        let bounds = match glyph.pixel_bounding_box() {
+            Some(bounds) => bounds,
+            None => {
+                let region = GlyphRegion {
+                    uv_rect: UvRect {
+                        u: 0.0,
+                        v: 0.0,
+                        width: 0.0,
+                        height: 0.0,
+                    },
+                    size: [0, 0],
+                    offset: [0.0, 0.0],
+                    advance,
+                };
+                self.atlas.insert(key, region, None);
+                return Some(region);
+            }
+        };
+        let (width, height) = (bounds.width() as u32, bounds.height() as u32);
+        let slot = self
+            .atlas
+            .allocate(width + PADDING * 2, height + PADDING * 2)?;
+        let (slot_x, slot_y) = self.atlas.slot_origin(slot);
+        let (x, y) = (slot_x + PADDING, slot_y + PADDING);
+
+        let mut pixels = vec![0; (width * height) as usize];
+        glyph.draw(|px, py, coverage| {
+            pixels[(py * width + px) as usize] = (coverage * 255.0).round() as u8;
+        });
+        queue.write_texture(
+            wgpu::ImageCopyTexture {
+                texture: &self.texture.texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d { x, y, z: 0 },
+                aspect: wgpu::TextureAspect::All,
+            },
+            &pixels,
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: NonZeroU32::new(width),
+                rows_per_image: NonZeroU32::new(height),
+            },
+            wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+        );
+
+        let atlas_size = self.atlas.size as f32;
+        let region = GlyphRegion {
+            uv_rect: UvRect {
+                u: x as f32 / atlas_size,
+                v: y as f32 / atlas_size,
+                width: width as f32 / atlas_size,
+                height: height as f32 / atlas_size,
+            },
+            size: [width, height],
+            offset: [bounds.min.x as f32, bounds.min.y as f32],
+            advance,
+        };
+        self.atlas.insert(key, region, Some(slot));
+        Some(region)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GLYPH_SIZE: u32 = 8;
+
+    /// Does what `get_or_rasterize` does with the atlas, for a glyph
+    /// taking up `GLYPH_SIZE` pixels each way. False when it didn't fit.
+    fn request(atlas: &mut GlyphAtlas, codepoint: char) -> bool {
+        let key = GlyphKey::new(codepoint, 12.0);
+        if atlas.request(key).is_some() {
+            return true;
+        }
+        let slot = match atlas.allocate(GLYPH_SIZE, GLYPH_SIZE) {
+            Some(slot) => slot,
+            None => return false,
+        };
+        let region = GlyphRegion {
+            uv_rect: UvRect {
+                u: 0.0,
+                v: 0.0,
+                width: 0.0,
+                height: 0.0,
+            },
+            size: [GLYPH_SIZE; 2],
+            offset: [0.0, 0.0],
+            advance: GLYPH_SIZE as f32,
+        };
+        atlas.insert(key, region, Some(slot));
+        true
+    }
+
+    fn contains(atlas: &GlyphAtlas, codepoint: char) -> bool {
+        atlas.glyphs.contains_key(&GlyphKey::new(codepoint, 12.0))
+    }
+
+    #[test]
+    fn evicts_least_recently_used_glyphs_when_full() {
+        // Room for four glyphs, two on each of two shelves.
+        let mut atlas = GlyphAtlas::new(GLYPH_SIZE * 2);
+        for codepoint in "abcd".chars() {
+            assert!(request(&mut atlas, codepoint));
+        }
+        // Asking for `a` again leaves `b` as the least recently used.
+        assert!(request(&mut atlas, 'a'));
+
+        assert!(request(&mut atlas, 'e'));
+        assert!(request(&mut atlas, 'f'));
+        assert!(!contains(&atlas, 'b'));
+        assert!(!contains(&atlas, 'c'));
+        for codepoint in "adef".chars() {
+            assert!(contains(&atlas, codepoint), "{} was evicted", codepoint);
+        }
+        assert_eq!(atlas.glyphs.len(), 4);
+    }
+
+    #[test]
+    fn glyphs_bigger_than_the_atlas_are_not_cached() {
+        let mut atlas = GlyphAtlas::new(GLYPH_SIZE - 1);
+        assert!(!request(&mut atlas, 'a'));
+        assert!(atlas.glyphs.is_empty());
+    }
+}
@@ -0,0 +1,57 @@
+use std::ops::{Deref, DerefMut};
+
+/// Groups the commands recorded while it's alive under `label`, so
+/// they're labelled in GPU profilers and frame captures.
+///
+/// The encoder is borrowed until the marker is dropped, and is
+/// reached through it in the meantime. Markers do nothing in release
+/// builds.
+pub struct GpuDebugMarker<'a> {
+    encoder: &'a mut wgpu::CommandEncoder,
+}
+
+impl<'a> GpuDebugMarker<'a> {
+    pub fn new(encoder: &'a mut wgpu::CommandEncoder, label: &str) -> Self {
+        #[cfg(debug_assertions)]
+        encoder.push_debug_group(label);
+        #[cfg(not(debug_assertions))]
+        let _ = label;
+
+        GpuDebugMarker { encoder }
+    }
+}
+
+impl Drop for GpuDebugMarker<'_> {
+    fn drop(&mut self) {
+        #[cfg(debug_assertions)]
+        self.encoder.pop_debug_group();
+    }
+}
+
+impl Deref for GpuDebugMarker<'_> {
+    type Target = wgpu::CommandEncoder;
+
+    fn deref(&self) -> &Self::Target {
+        self.encoder
+    }
+}
+
+impl DerefMut for GpuDebugMarker<'_> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.encoder
+    }
+}
+
+/// Runs `body` inside a [`GpuDebugMarker`] named `label`.
+///
+/// `encoder` names a `&mut wgpu::CommandEncoder`, which is borrowed
+/// by the marker under the same name inside the body.
+macro_rules! gpu_scope {
+    ($encoder:ident, $label:expr, $body:block) => {{
+        let mut marker = $crate::gpu_debug_marker::GpuDebugMarker::new($encoder, $label);
+        let $encoder: &mut wgpu::CommandEncoder = &mut marker;
+        $body
+    }};
+}
+
+pub(crate) use gpu_scope;
@@ -0,0 +1,84 @@
+use crate::bind_group_allocator::ResourceId;
+
+/// Format the scene is rendered in, so colors can go past 1
+/// until they're tone mapped.
+pub const HDR_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+
+/// A floating point texture the scene is drawn into before it's
+/// tone mapped, keeping colors brighter than the surface can show.
+///
+/// With MSAA, the scene is drawn into a multisampled framebuffer
+/// instead, which is resolved into this target at the end of the
+/// pass. Resolving needs both textures in the same format, so the
+/// framebuffer has to be created in [`HDR_FORMAT`] too. The target
+/// itself is only ever single sampled, so it can be sampled by the
+/// passes after it.
+pub struct HdrRenderTarget {
+    texture: wgpu::Texture,
+    view: wgpu::TextureView,
+    /// New for each texture, so pooled bind groups of an old one
+    /// aren't reused.
+    id: ResourceId,
+    width: u32,
+    height: u32,
+}
+
+impl HdrRenderTarget {
+    pub fn new(device: &wgpu::Device, width: u32, height: u32) -> Self {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("HDR Render Target"),
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: HDR_FORMAT,
+            // Rendered into by one pass, and sampled by the passes after
+            // it. Passes that read and write the same target copy it
+            // first, since a texture can't be both at once.
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+                | wgpu::TextureUsages::TEXTURE_BINDING
+                | wgpu::TextureUsages::COPY_SRC
+                | wgpu::TextureUsages::COPY_DST,
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+        HdrRenderTarget {
+            texture,
+            view,
+            id: ResourceId::new(),
+            width,
+            height,
+        }
+    }
+
+    pub fn texture(&self) -> &wgpu::Texture {
+        &self.texture
+    }
+
+    pub fn view(&self) -> &wgpu::TextureView {
+        &self.view
+    }
+
+    /// Changes whenever the texture is recreated.
+    pub fn id(&self) -> ResourceId {
+        self.id
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Recreates the texture at the new size. Bind groups holding
+    /// the old view have to be recreated too.
+    pub fn resize(&mut self, device: &wgpu::Device, width: u32, height: u32) {
+        *self = Self::new(device, width, height);
+    }
+}
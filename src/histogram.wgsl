@@ -0,0 +1,78 @@
+// Sorts the pixels of the HDR frame into bins by their log
+// luminance, then averages the bins for auto-exposure.
+
+[[block]]
+struct Params {
+    min_log_luminance: f32;
+    // Width of the range of log luminance the bins cover.
+    log_luminance_range: f32;
+    num_bins: u32;
+};
+
+[[block]]
+struct Histogram {
+    bins: array<atomic<u32>>;
+};
+
+[[block]]
+struct Result {
+    mean_log_luminance: f32;
+    // Pixels bright enough to be counted.
+    pixel_count: u32;
+};
+
+[[group(0), binding(0)]]
+var<uniform> params: Params;
+[[group(0), binding(1)]]
+var<storage, read_write> histogram: Histogram;
+[[group(0), binding(2)]]
+var<storage, read_write> result: Result;
+[[group(0), binding(3)]]
+var t_hdr: texture_2d<f32>;
+
+// Pixels darker than this go in the first bin, which isn't
+// counted, so black backgrounds don't drag the exposure up.
+let MIN_LUMINANCE: f32 = 0.0001;
+
+// Rec. 709 weights of how bright each channel looks.
+fn luminance(color: vec3<f32>) -> f32 {
+    return dot(color, vec3<f32>(0.2126, 0.7152, 0.0722));
+}
+
+fn bin_of(color: vec3<f32>) -> u32 {
+    let lum = luminance(color);
+    if (lum < MIN_LUMINANCE) {
+        return 0u;
+    }
+    let t = clamp((log2(lum) - params.min_log_luminance) / params.log_luminance_range, 0.0, 1.0);
+    // The rest of the bins share the range.
+    return u32(t * f32(params.num_bins - 2u)) + 1u;
+}
+
+[[stage(compute), workgroup_size(16, 16)]]
+fn build_histogram([[builtin(global_invocation_id)]] id: vec3<u32>) {
+    let size = vec2<u32>(textureDimensions(t_hdr));
+    if (id.x >= size.x || id.y >= size.y) {
+        return;
+    }
+    let color = textureLoad(t_hdr, vec2<i32>(id.xy), 0).rgb;
+    // Calls to built-ins have to be used as values.
+    let previous = atomicAdd(&histogram.bins[bin_of(color)], 1u);
+}
+
+// Run as a single invocation, since there are only a few bins.
+[[stage(compute), workgroup_size(1)]]
+fn average() {
+    var weighted: f32 = 0.0;
+    var count: u32 = 0u;
+    for (var bin: u32 = 1u; bin < params.num_bins; bin = bin + 1u) {
+        let pixels = atomicLoad(&histogram.bins[bin]);
+        // The log luminance at the middle of the bin.
+        let t = (f32(bin - 1u) + 0.5) / f32(params.num_bins - 2u);
+        weighted = weighted + f32(pixels) * (params.min_log_luminance + t * params.log_luminance_range);
+        count = count + pixels;
+    }
+
+    result.mean_log_luminance = weighted / max(f32(count), 1.0);
+    result.pixel_count = count;
+}
@@ -0,0 +1,364 @@
+use wgpu::util::DeviceExt;
+
+/// Size of the faces of the irradiance map. Irradiance changes
+/// slowly across directions, so it can be tiny.
+const IRRADIANCE_SIZE: u32 = 32;
+
+/// Size of the faces of the sharpest level of the pre-filtered map.
+const PREFILTER_SIZE: u32 = 128;
+
+/// Mip levels of the pre-filtered map, going from a roughness of 0
+/// at the top to 1 at the bottom.
+pub const PREFILTER_MIP_LEVELS: u32 = 5;
+
+/// Size of the BRDF lookup table.
+const BRDF_LUT_SIZE: u32 = 256;
+
+/// Can be written by compute shaders, and filtered when sampled.
+const FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+
+/// Must match `workgroup_size` in the IBL shaders.
+const WORKGROUP_SIZE: u32 = 8;
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct PrefilterParams {
+    roughness: f32,
+    _padding: [f32; 3],
+}
+
+/// The diffuse light arriving from every direction, as a cubemap
+/// sampled with the normal of a surface.
+///
+/// Views keep their textures alive, so only the views are kept.
+pub struct IrradianceMap {
+    pub view: wgpu::TextureView,
+}
+
+/// The environment blurred by increasing roughness down its mip
+/// levels, as a cubemap sampled with the reflected view direction.
+///
+/// It has [`PREFILTER_MIP_LEVELS`] levels.
+pub struct PrefilteredEnvMap {
+    pub view: wgpu::TextureView,
+}
+
+/// How much of the Fresnel reflectance comes back, by the angle of
+/// the view and roughness. Red is a scale and green a bias.
+pub struct BrdfLut {
+    pub view: wgpu::TextureView,
+}
+
+/// Precomputes the maps for image based lighting from an
+/// environment cubemap, such as the one the sky is drawn with.
+///
+/// This only has to happen once per environment, since none of
+/// the maps depend on the camera or the scene.
+pub struct IblPrecompute;
+
+impl IblPrecompute {
+    /// The maps are written by compute shaders into storage textures,
+    /// which some downlevel adapters can't do.
+    pub fn is_supported(adapter: &wgpu::Adapter, device: &wgpu::Device) -> bool {
+        adapter
+            .get_downlevel_properties()
+            .flags
+            .contains(wgpu::DownlevelFlags::COMPUTE_SHADERS)
+            && device.limits().max_storage_textures_per_shader_stage > 0
+    }
+
+    /// Convolves `env_cubemap`, which must be a cube view, into all three
+    /// maps, and submits the work to `queue`.
+    pub fn run(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        env_cubemap: &wgpu::TextureView,
+    ) -> (IrradianceMap, PrefilteredEnvMap, BrdfLut) {
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("IBL Environment Sampler"),
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            ..Default::default()
+        });
+
+        let convolve_layout = create_convolve_bind_group_layout(device);
+        let convolve_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("IBL Convolution"),
+                bind_group_layouts: &[&convolve_layout],
+                push_constant_ranges: &[],
+            });
+        let convolve_shader = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+            label: Some("IBL Convolution Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("ibl.wgsl").into()),
+        });
+        let irradiance_pipeline =
+            device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some("IBL Irradiance"),
+                layout: Some(&convolve_pipeline_layout),
+                module: &convolve_shader,
+                entry_point: "irradiance",
+            });
+        let prefilter_pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("IBL Prefilter"),
+            layout: Some(&convolve_pipeline_layout),
+            module: &convolve_shader,
+            entry_point: "prefilter",
+        });
+
+        let irradiance_texture = create_cubemap(device, "IBL Irradiance Map", IRRADIANCE_SIZE, 1);
+        let prefilter_texture = create_cubemap(
+            device,
+            "IBL Prefiltered Environment Map",
+            PREFILTER_SIZE,
+            PREFILTER_MIP_LEVELS,
+        );
+
+        // The irradiance doesn't depend on roughness, but the
+        // layout is shared with the pre-filtering.
+        let irradiance_bind_group = create_convolve_bind_group(
+            device,
+            &convolve_layout,
+            env_cubemap,
+            &sampler,
+            &face_views(&irradiance_texture, 0),
+            0.0,
+        );
+        let prefilter_bind_groups = (0..PREFILTER_MIP_LEVELS)
+            .map(|mip| {
+                let roughness = mip as f32 / (PREFILTER_MIP_LEVELS - 1) as f32;
+                create_convolve_bind_group(
+                    device,
+                    &convolve_layout,
+                    env_cubemap,
+                    &sampler,
+                    &face_views(&prefilter_texture, mip),
+                    roughness,
+                )
+            })
+            .collect::<Vec<_>>();
+
+        let brdf_texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("IBL BRDF Lookup Table"),
+            size: wgpu::Extent3d {
+                width: BRDF_LUT_SIZE,
+                height: BRDF_LUT_SIZE,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: FORMAT,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::STORAGE_BINDING,
+        });
+        let brdf_view = brdf_texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let brdf_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("IBL BRDF Integration"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::StorageTexture {
+                    access: wgpu::StorageTextureAccess::WriteOnly,
+                    format: FORMAT,
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                },
+                count: None,
+            }],
+        });
+        let brdf_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("IBL BRDF Integration"),
+            layout: &brdf_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(&brdf_view),
+            }],
+        });
+        let brdf_pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("IBL BRDF Integration"),
+            bind_group_layouts: &[&brdf_layout],
+            push_constant_ranges: &[],
+        });
+        let brdf_shader = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+            label: Some("IBL BRDF Integration Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("ibl_brdf.wgsl").into()),
+        });
+        let brdf_pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("IBL BRDF Integration"),
+            layout: Some(&brdf_pipeline_layout),
+            module: &brdf_shader,
+            entry_point: "main",
+        });
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("IBL Precompute Encoder"),
+        });
+        {
+            let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("IBL Precompute"),
+            });
+
+            compute_pass.set_pipeline(&irradiance_pipeline);
+            compute_pass.set_bind_group(0, &irradiance_bind_group, &[]);
+            let groups = IRRADIANCE_SIZE.div_ceil(WORKGROUP_SIZE);
+            compute_pass.dispatch(groups, groups, 6);
+
+            // Every mip level is half the size of the one above it.
+            compute_pass.set_pipeline(&prefilter_pipeline);
+            for (mip, bind_group) in prefilter_bind_groups.iter().enumerate() {
+                let size = (PREFILTER_SIZE >> mip).max(1);
+                compute_pass.set_bind_group(0, bind_group, &[]);
+                let groups = size.div_ceil(WORKGROUP_SIZE);
+                compute_pass.dispatch(groups, groups, 6);
+            }
+
+            compute_pass.set_pipeline(&brdf_pipeline);
+            compute_pass.set_bind_group(0, &brdf_bind_group, &[]);
+            let groups = BRDF_LUT_SIZE.div_ceil(WORKGROUP_SIZE);
+            compute_pass.dispatch(groups, groups, 1);
+        }
+        queue.submit(std::iter::once(encoder.finish()));
+
+        let irradiance = IrradianceMap {
+            view: cube_view(&irradiance_texture, "IBL Irradiance Map View"),
+        };
+        let prefiltered = PrefilteredEnvMap {
+            view: cube_view(&prefilter_texture, "IBL Prefiltered Environment Map View"),
+        };
+        let brdf_lut = BrdfLut { view: brdf_view };
+        (irradiance, prefiltered, brdf_lut)
+    }
+}
+
+fn create_cubemap(
+    device: &wgpu::Device,
+    label: &str,
+    size: u32,
+    mip_level_count: u32,
+) -> wgpu::Texture {
+    device.create_texture(&wgpu::TextureDescriptor {
+        label: Some(label),
+        // A cubemap is stored as a 2D texture with a layer per face.
+        size: wgpu::Extent3d {
+            width: size,
+            height: size,
+            depth_or_array_layers: 6,
+        },
+        mip_level_count,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: FORMAT,
+        usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::STORAGE_BINDING,
+    })
+}
+
+/// The faces of one mip level of a cubemap, as the layers
+/// of an array a compute shader can write to.
+fn face_views(texture: &wgpu::Texture, mip: u32) -> wgpu::TextureView {
+    texture.create_view(&wgpu::TextureViewDescriptor {
+        label: Some("IBL Cubemap Faces"),
+        dimension: Some(wgpu::TextureViewDimension::D2Array),
+        base_mip_level: mip,
+        mip_level_count: std::num::NonZeroU32::new(1),
+        ..Default::default()
+    })
+}
+
+fn cube_view(texture: &wgpu::Texture, label: &str) -> wgpu::TextureView {
+    texture.create_view(&wgpu::TextureViewDescriptor {
+        label: Some(label),
+        dimension: Some(wgpu::TextureViewDimension::Cube),
+        ..Default::default()
+    })
+}
+
+fn create_convolve_bind_group_layout(device: &wgpu::Device) -> wgpu::BindGroupLayout {
+    device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+        label: Some("IBL Convolution"),
+        entries: &[
+            wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::Texture {
+                    multisampled: false,
+                    view_dimension: wgpu::TextureViewDimension::Cube,
+                    sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 1,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::Sampler {
+                    filtering: true,
+                    comparison: false,
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 2,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::StorageTexture {
+                    access: wgpu::StorageTextureAccess::WriteOnly,
+                    format: FORMAT,
+                    view_dimension: wgpu::TextureViewDimension::D2Array,
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 3,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            },
+        ],
+    })
+}
+
+fn create_convolve_bind_group(
+    device: &wgpu::Device,
+    layout: &wgpu::BindGroupLayout,
+    env_cubemap: &wgpu::TextureView,
+    sampler: &wgpu::Sampler,
+    output: &wgpu::TextureView,
+    roughness: f32,
+) -> wgpu::BindGroup {
+    let params = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label: Some("IBL Prefilter Params"),
+        contents: bytemuck::bytes_of(&PrefilterParams {
+            roughness,
+            _padding: [0.0; 3],
+        }),
+        usage: wgpu::BufferUsages::UNIFORM,
+    });
+
+    device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some("IBL Convolution"),
+        layout,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(env_cubemap),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: wgpu::BindingResource::Sampler(sampler),
+            },
+            wgpu::BindGroupEntry {
+                binding: 2,
+                resource: wgpu::BindingResource::TextureView(output),
+            },
+            wgpu::BindGroupEntry {
+                binding: 3,
+                resource: params.as_entire_binding(),
+            },
+        ],
+    })
+}
@@ -0,0 +1,139 @@
+// Convolves an environment cubemap into the maps image based lighting
+// samples: the irradiance for diffuse light, and the environment
+// pre-filtered by roughness for specular light. Each invocation
+// writes one texel, with the face index in `z`.
+
+let PI: f32 = 3.14159265359;
+
+// Angle between the samples of the irradiance convolution, in radians.
+let SAMPLE_DELTA: f32 = 0.05;
+
+// Importance samples taken per texel of the pre-filtered map.
+let SAMPLE_COUNT: u32 = 512u;
+
+[[block]]
+struct Prefilter {
+    roughness: f32;
+};
+
+[[group(0), binding(0)]]
+var t_environment: texture_cube<f32>;
+[[group(0), binding(1)]]
+var s_environment: sampler;
+[[group(0), binding(2)]]
+var t_output: texture_storage_2d_array<rgba16float, write>;
+[[group(0), binding(3)]]
+var<uniform> prefilter_params: Prefilter;
+
+// Direction through the texel at `uv`, from -1 to 1 across the face,
+// following the layout of cubemap faces: +X, -X, +Y, -Y, +Z, -Z.
+fn face_direction(face: u32, uv: vec2<f32>) -> vec3<f32> {
+    let u = uv.x;
+    let v = uv.y;
+    if (face == 0u) {
+        return vec3<f32>(1.0, -v, -u);
+    } elseif (face == 1u) {
+        return vec3<f32>(-1.0, -v, u);
+    } elseif (face == 2u) {
+        return vec3<f32>(u, 1.0, v);
+    } elseif (face == 3u) {
+        return vec3<f32>(u, -1.0, -v);
+    } elseif (face == 4u) {
+        return vec3<f32>(u, -v, 1.0);
+    }
+    return vec3<f32>(-u, -v, -1.0);
+}
+
+// Direction out of the center of the texel `id` writes.
+fn texel_direction(id: vec3<u32>, size: vec2<i32>) -> vec3<f32> {
+    let uv = (vec2<f32>(id.xy) + vec2<f32>(0.5, 0.5)) / vec2<f32>(size) * 2.0 - vec2<f32>(1.0, 1.0);
+    return normalize(face_direction(id.z, uv));
+}
+
+// Any axis that isn't parallel to `normal`, to build a tangent frame from.
+fn up_vector(normal: vec3<f32>) -> vec3<f32> {
+    if (abs(normal.y) > 0.999) {
+        return vec3<f32>(0.0, 0.0, 1.0);
+    }
+    return vec3<f32>(0.0, 1.0, 0.0);
+}
+
+// The `i`th of `count` points spread evenly over the unit square.
+fn hammersley(i: u32, count: u32) -> vec2<f32> {
+    var bits = i;
+    bits = (bits << 16u) | (bits >> 16u);
+    bits = ((bits & 0x55555555u) << 1u) | ((bits & 0xAAAAAAAAu) >> 1u);
+    bits = ((bits & 0x33333333u) << 2u) | ((bits & 0xCCCCCCCCu) >> 2u);
+    bits = ((bits & 0x0F0F0F0Fu) << 4u) | ((bits & 0xF0F0F0F0u) >> 4u);
+    bits = ((bits & 0x00FF00FFu) << 8u) | ((bits & 0xFF00FF00u) >> 8u);
+    return vec2<f32>(f32(i) / f32(count), f32(bits) * 2.3283064365386963e-10);
+}
+
+// A half vector around `normal`, spread the way the GGX
+// distribution spreads reflections at `roughness`.
+fn importance_sample_ggx(xi: vec2<f32>, normal: vec3<f32>, roughness: f32) -> vec3<f32> {
+    let a = roughness * roughness;
+    let phi = 2.0 * PI * xi.x;
+    let cos_theta = sqrt((1.0 - xi.y) / (1.0 + (a * a - 1.0) * xi.y));
+    let sin_theta = sqrt(1.0 - cos_theta * cos_theta);
+    let h = vec3<f32>(cos(phi) * sin_theta, sin(phi) * sin_theta, cos_theta);
+
+    let tangent = normalize(cross(up_vector(normal), normal));
+    let bitangent = cross(normal, tangent);
+    return normalize(tangent * h.x + bitangent * h.y + normal * h.z);
+}
+
+[[stage(compute), workgroup_size(8, 8, 1)]]
+fn irradiance([[builtin(global_invocation_id)]] id: vec3<u32>) {
+    let size = textureDimensions(t_output);
+    if (i32(id.x) >= size.x || i32(id.y) >= size.y) {
+        return;
+    }
+
+    let normal = texel_direction(id, size);
+    let right = normalize(cross(up_vector(normal), normal));
+    let up = cross(normal, right);
+
+    // Adds up the light from the whole hemisphere around the normal,
+    // weighted by how directly it hits the surface.
+    var sum = vec3<f32>(0.0, 0.0, 0.0);
+    var count = 0.0;
+    for (var phi = 0.0; phi < 2.0 * PI; phi = phi + SAMPLE_DELTA) {
+        for (var theta = 0.0; theta < 0.5 * PI; theta = theta + SAMPLE_DELTA) {
+            let tangent = vec3<f32>(sin(theta) * cos(phi), sin(theta) * sin(phi), cos(theta));
+            let direction = tangent.x * right + tangent.y * up + tangent.z * normal;
+            let light = textureSampleLevel(t_environment, s_environment, direction, 0.0).rgb;
+            sum = sum + light * cos(theta) * sin(theta);
+            count = count + 1.0;
+        }
+    }
+
+    textureStore(t_output, vec2<i32>(id.xy), i32(id.z), vec4<f32>(PI * sum / count, 1.0));
+}
+
+[[stage(compute), workgroup_size(8, 8, 1)]]
+fn prefilter([[builtin(global_invocation_id)]] id: vec3<u32>) {
+    let size = textureDimensions(t_output);
+    if (i32(id.x) >= size.x || i32(id.y) >= size.y) {
+        return;
+    }
+
+    // The view is assumed to look straight along the normal,
+    // so the reflection comes back the same way.
+    let normal = texel_direction(id, size);
+    let roughness = prefilter_params.roughness;
+
+    var sum = vec3<f32>(0.0, 0.0, 0.0);
+    var weight = 0.0;
+    for (var i = 0u; i < SAMPLE_COUNT; i = i + 1u) {
+        let h = importance_sample_ggx(hammersley(i, SAMPLE_COUNT), normal, roughness);
+        let l = normalize(2.0 * dot(normal, h) * h - normal);
+        let n_dot_l = dot(normal, l);
+        if (n_dot_l > 0.0) {
+            sum = sum + textureSampleLevel(t_environment, s_environment, l, 0.0).rgb * n_dot_l;
+            weight = weight + n_dot_l;
+        }
+    }
+
+    textureStore(t_output, vec2<i32>(id.xy), i32(id.z), vec4<f32>(sum / max(weight, 0.0001), 1.0));
+}
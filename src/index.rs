@@ -0,0 +1,110 @@
+use wgpu::util::DeviceExt;
+
+/// Integer types that can be used as indices into a vertex buffer.
+pub trait Index: bytemuck::Pod {
+    const FORMAT: wgpu::IndexFormat;
+}
+
+impl Index for u16 {
+    const FORMAT: wgpu::IndexFormat = wgpu::IndexFormat::Uint16;
+}
+
+impl Index for u32 {
+    const FORMAT: wgpu::IndexFormat = wgpu::IndexFormat::Uint32;
+}
+
+/// Index data uploaded to the GPU.
+///
+/// The index format is picked from the slice's element type, so
+/// the same wrapper holds either `u16` or `u32` indices.
+pub struct IndexBuffer {
+    buffer: wgpu::Buffer,
+    format: wgpu::IndexFormat,
+    len: u32,
+    #[allow(dead_code)]
+    size: wgpu::BufferAddress,
+}
+
+impl IndexBuffer {
+    pub fn from_slice<I: Index>(device: &wgpu::Device, indices: &[I]) -> Self {
+        let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Index Buffer"),
+            contents: bytemuck::cast_slice(indices),
+            usage: wgpu::BufferUsages::INDEX,
+        });
+
+        IndexBuffer {
+            buffer,
+            format: I::FORMAT,
+            len: indices.len() as u32,
+            size: std::mem::size_of_val(indices) as wgpu::BufferAddress,
+        }
+    }
+
+    #[allow(dead_code)]
+    pub fn buffer(&self) -> &wgpu::Buffer {
+        &self.buffer
+    }
+
+    pub fn format(&self) -> wgpu::IndexFormat {
+        self.format
+    }
+
+    /// Number of indices in the buffer.
+    pub fn len(&self) -> u32 {
+        self.len
+    }
+
+    /// Size of the indices in bytes.
+    #[allow(dead_code)]
+    pub fn size(&self) -> wgpu::BufferAddress {
+        self.size
+    }
+
+    #[allow(dead_code)]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn slice(&self) -> wgpu::BufferSlice<'_> {
+        self.buffer.slice(..)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{context::test_device, mesh::Mesh, primitives, vertex::Vertex};
+
+    #[test]
+    fn uploads_quad() {
+        let (device, _queue) = match test_device() {
+            Some(device) => device,
+            None => return eprintln!("skipped, there's no adapter"),
+        };
+        let (vertices, indices) = primitives::quad(1.0, 1.0);
+        let mesh = Mesh::upload(&device, &vertices, &indices);
+
+        assert_eq!(mesh.vertex_buffer.len(), 4);
+        assert_eq!(
+            mesh.vertex_buffer.size(),
+            4 * std::mem::size_of::<Vertex>() as wgpu::BufferAddress
+        );
+        assert_eq!(mesh.index_buffer.len(), 6);
+        assert_eq!(mesh.index_count, 6);
+        assert_eq!(mesh.index_buffer.format(), wgpu::IndexFormat::Uint16);
+        assert_eq!(mesh.index_buffer.size(), 6 * 2);
+    }
+
+    #[test]
+    fn u32_indices() {
+        let (device, _queue) = match test_device() {
+            Some(device) => device,
+            None => return eprintln!("skipped, there's no adapter"),
+        };
+        let index_buffer = IndexBuffer::from_slice(&device, &[0u32, 1, 2, 2, 3, 0]);
+
+        assert_eq!(index_buffer.format(), wgpu::IndexFormat::Uint32);
+        assert_eq!(index_buffer.size(), 6 * 4);
+    }
+}
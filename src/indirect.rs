@@ -0,0 +1,275 @@
+use crate::{bounds::Frustum, mesh::Mesh, uniform::UniformBinding, vertex::TANGENT_SLOT};
+
+/// Must match `workgroup_size` in the culling shader.
+const WORKGROUP_SIZE: u32 = 64;
+
+/// Arguments of `draw_indexed_indirect`.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct DrawIndexedIndirectArgs {
+    pub index_count: u32,
+    pub instance_count: u32,
+    pub first_index: u32,
+    pub base_vertex: i32,
+    pub first_instance: u32,
+}
+
+impl DrawIndexedIndirectArgs {
+    const SIZE: wgpu::BufferAddress = std::mem::size_of::<Self>() as wgpu::BufferAddress;
+}
+
+/// A buffer of indexed draws, written by compute shaders and
+/// run from the GPU without the CPU knowing what's in it.
+pub struct IndirectDrawBuffer {
+    buffer: wgpu::Buffer,
+    max_draws: u32,
+}
+
+impl IndirectDrawBuffer {
+    pub fn new(device: &wgpu::Device, max_draws: u32) -> Self {
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Indirect Draw Buffer"),
+            size: DrawIndexedIndirectArgs::SIZE * max_draws as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::INDIRECT
+                | wgpu::BufferUsages::STORAGE
+                | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        IndirectDrawBuffer { buffer, max_draws }
+    }
+
+    pub fn buffer(&self) -> &wgpu::Buffer {
+        &self.buffer
+    }
+
+    pub fn max_draws(&self) -> u32 {
+        self.max_draws
+    }
+
+    /// Binds the mesh's buffers and runs `count` of the draws, from
+    /// `first_draw` on, with one multi draw. The draws all use the
+    /// mesh, and the instance buffer must be bound by the caller.
+    ///
+    /// Needs [`wgpu::Features::MULTI_DRAW_INDIRECT`].
+    pub fn draw<'a>(
+        &'a self,
+        render_pass: &mut wgpu::RenderPass<'a>,
+        mesh: &'a Mesh,
+        first_draw: u32,
+        count: u32,
+    ) {
+        render_pass.set_vertex_buffer(0, mesh.vertex_buffer.slice());
+        render_pass.set_vertex_buffer(TANGENT_SLOT, mesh.tangent_buffer.slice());
+        render_pass.set_index_buffer(mesh.index_buffer.slice(), mesh.index_buffer.format());
+        render_pass.multi_draw_indexed_indirect(
+            &self.buffer,
+            first_draw as wgpu::BufferAddress * DrawIndexedIndirectArgs::SIZE,
+            count,
+        );
+    }
+}
+
+/// An instance to cull, laid out the way the culling shader expects it.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct CullObject {
+    pub model: [[f32; 4]; 4],
+    pub bounds_min: [f32; 3],
+    /// Indices in the mesh the object is drawn with.
+    pub index_count: u32,
+    pub bounds_max: [f32; 3],
+    /// The object's instance, in the instance buffer it's drawn with.
+    pub first_instance: u32,
+}
+
+impl CullObject {
+    /// An object drawn with `mesh`, never culled when the mesh has
+    /// no bounds.
+    pub fn new(model: [[f32; 4]; 4], mesh: &Mesh, first_instance: u32) -> Self {
+        // A minimum past the maximum tells the shader there are no bounds.
+        let (bounds_min, bounds_max) = match &mesh.bounds {
+            Some(bounds) => (bounds.min, bounds.max),
+            None => ([1.0; 3], [-1.0; 3]),
+        };
+        CullObject {
+            model,
+            bounds_min,
+            index_count: mesh.index_count,
+            bounds_max,
+            first_instance,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct CullParams {
+    planes: [[f32; 4]; 6],
+    object_count: u32,
+    _padding: [u32; 3],
+}
+
+/// Culls objects against the camera's frustum with a compute shader,
+/// writing a draw per object into an [`IndirectDrawBuffer`].
+///
+/// Culled objects get a draw with no instances rather than being left
+/// out, so object `i` is always drawn by draw `i`, and the objects
+/// sharing a mesh can be put next to each other and drawn together.
+pub struct IndirectCullPass {
+    objects: wgpu::Buffer,
+    params: UniformBinding<CullParams>,
+    draws: IndirectDrawBuffer,
+    bind_group: wgpu::BindGroup,
+    pipeline: wgpu::ComputePipeline,
+    object_count: u32,
+}
+
+impl IndirectCullPass {
+    /// Features the adapter needs for the draws to be run.
+    pub const FEATURES: wgpu::Features = wgpu::Features::MULTI_DRAW_INDIRECT;
+
+    pub fn new(device: &wgpu::Device, max_objects: u32) -> Self {
+        let objects = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Cull Object Buffer"),
+            size: (max_objects as usize * std::mem::size_of::<CullObject>()) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let draws = IndirectDrawBuffer::new(device, max_objects);
+        let params = UniformBinding::new(
+            device,
+            "Cull Params",
+            wgpu::ShaderStages::COMPUTE,
+            &CullParams {
+                planes: [[0.0; 4]; 6],
+                object_count: 0,
+                _padding: [0; 3],
+            },
+        );
+
+        let storage_entry = |binding, read_only| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Indirect Cull Bind Group Layout"),
+            entries: &[
+                storage_entry(0, true),
+                storage_entry(1, false),
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Indirect Cull Bind Group"),
+            layout: &bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: objects.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: draws.buffer().as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: params.buffer.as_entire_binding(),
+                },
+            ],
+        });
+
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Indirect Cull Pipeline Layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let shader = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+            label: Some("Indirect Cull Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("indirect_cull.wgsl").into()),
+        });
+        let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("Indirect Cull Pipeline"),
+            layout: Some(&layout),
+            module: &shader,
+            entry_point: "main",
+        });
+
+        IndirectCullPass {
+            objects,
+            params,
+            draws,
+            bind_group,
+            pipeline,
+            object_count: 0,
+        }
+    }
+
+    /// The draws written by the last dispatch.
+    pub fn draws(&self) -> &IndirectDrawBuffer {
+        &self.draws
+    }
+
+    /// Most objects that can be culled at once.
+    pub fn max_objects(&self) -> u32 {
+        self.draws.max_draws()
+    }
+
+    /// Uploads the objects to cull against `frustum` in the next
+    /// dispatch. Objects past [`max_objects`](Self::max_objects)
+    /// are dropped.
+    pub fn upload(&mut self, queue: &wgpu::Queue, objects: &[CullObject], frustum: &Frustum) {
+        let count = objects.len().min(self.max_objects() as usize);
+        if count < objects.len() {
+            log::warn!(
+                "indirect culling holds {} objects, dropping {}",
+                self.max_objects(),
+                objects.len() - count
+            );
+        }
+        queue.write_buffer(&self.objects, 0, bytemuck::cast_slice(&objects[..count]));
+        self.object_count = count as u32;
+
+        let planes = frustum.planes.map(|plane| {
+            let [x, y, z] = plane.normal;
+            [x, y, z, plane.distance]
+        });
+        self.params.update(
+            queue,
+            &CullParams {
+                planes,
+                object_count: self.object_count,
+                _padding: [0; 3],
+            },
+        );
+    }
+
+    /// Records the compute pass writing a draw for every object.
+    pub fn dispatch(&self, encoder: &mut wgpu::CommandEncoder) {
+        if self.object_count == 0 {
+            return;
+        }
+
+        let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("Indirect Cull Pass"),
+        });
+        compute_pass.set_pipeline(&self.pipeline);
+        compute_pass.set_bind_group(0, &self.bind_group, &[]);
+        compute_pass.dispatch(self.object_count.div_ceil(WORKGROUP_SIZE), 1, 1);
+    }
+}
@@ -0,0 +1,99 @@
+// Culls objects against the camera's frustum on the GPU, writing an
+// indexed indirect draw for each one. Objects outside the frustum get
+// a draw with no instances, so the draws stay in the same order as
+// the objects and can be run with a single multi draw.
+
+// Arguments of `draw_indexed_indirect`. Must match
+// `DrawIndexedIndirectArgs` in indirect.rs.
+struct DrawIndexedIndirect {
+    index_count: u32;
+    instance_count: u32;
+    first_index: u32;
+    base_vertex: i32;
+    first_instance: u32;
+};
+
+[[block]]
+struct Draws {
+    draws: array<DrawIndexedIndirect>;
+};
+
+struct CullObject {
+    model: mat4x4<f32>;
+    // Bounds of the mesh in its own space. Objects with a minimum
+    // past the maximum have no bounds, and are always drawn.
+    bounds_min: vec3<f32>;
+    index_count: u32;
+    bounds_max: vec3<f32>;
+    // The object's instance, in the instance buffer it's drawn with.
+    first_instance: u32;
+};
+
+[[block]]
+struct Objects {
+    objects: array<CullObject>;
+};
+
+[[block]]
+struct CullParams {
+    // Left, right, bottom, top, near and far, with the normals in
+    // `xyz` pointing inwards and the distance in `w`.
+    planes: array<vec4<f32>, 6>;
+    object_count: u32;
+};
+
+[[group(0), binding(0)]]
+var<storage, read> objects: Objects;
+[[group(0), binding(1)]]
+var<storage, read_write> draws: Draws;
+[[group(0), binding(2)]]
+var<uniform> params: CullParams;
+
+// Whether any of the object's bounds, once moved into the world,
+// are inside the frustum. Works the same as `Frustum::intersects_aabb`.
+fn is_visible(object: CullObject) -> bool {
+    if (any(object.bounds_min > object.bounds_max)) {
+        return true;
+    }
+
+    // The box around the moved corners, which grows to fit
+    // when the object is rotated.
+    var world_min = vec3<f32>(3.4e38);
+    var world_max = vec3<f32>(-3.4e38);
+    for (var i: u32 = 0u; i < 8u; i = i + 1u) {
+        let pick_max = vec3<bool>((i & 1u) != 0u, (i & 2u) != 0u, (i & 4u) != 0u);
+        let corner = select(object.bounds_min, object.bounds_max, pick_max);
+        let point = (object.model * vec4<f32>(corner, 1.0)).xyz;
+        world_min = min(world_min, point);
+        world_max = max(world_max, point);
+    }
+
+    for (var i: u32 = 0u; i < 6u; i = i + 1u) {
+        let plane = params.planes[i];
+        // The corner furthest along the normal is the last
+        // to go behind the plane.
+        let corner = select(world_min, world_max, plane.xyz >= vec3<f32>(0.0));
+        if (dot(plane.xyz, corner) + plane.w < 0.0) {
+            return false;
+        }
+    }
+    return true;
+}
+
+// Must match `WORKGROUP_SIZE` in indirect.rs.
+[[stage(compute), workgroup_size(64)]]
+fn main([[builtin(global_invocation_id)]] id: vec3<u32>) {
+    let index = id.x;
+    if (index >= params.object_count) {
+        return;
+    }
+
+    let object = objects.objects[index];
+    var draw: DrawIndexedIndirect;
+    draw.index_count = object.index_count;
+    draw.instance_count = select(0u, 1u, is_visible(object));
+    draw.first_index = 0u;
+    draw.base_vertex = 0;
+    draw.first_instance = object.first_instance;
+    draws.draws[index] = draw;
+}
@@ -0,0 +1,146 @@
+use std::collections::HashSet;
+
+use winit::event::{
+    ElementState, KeyboardInput, MouseButton, MouseScrollDelta, VirtualKeyCode, WindowEvent,
+};
+
+/// Lines scrolled for every pixel of a touchpad scroll, so both
+/// kinds of scrolling move about the same amount.
+const PIXELS_PER_LINE: f32 = 20.0;
+
+/// The keyboard and mouse, as they were left by the window events
+/// since the last frame.
+///
+/// Winit only reports changes, so this keeps track of what's held
+/// down in between. Presses, movement and scrolling are gathered
+/// over a frame and cleared by [`InputState::end_frame`].
+#[derive(Debug, Default)]
+pub struct InputState {
+    keys_held: HashSet<VirtualKeyCode>,
+    keys_pressed: HashSet<VirtualKeyCode>,
+    buttons_held: HashSet<MouseButton>,
+    buttons_pressed: HashSet<MouseButton>,
+    /// `None` until the cursor first moves over the window.
+    mouse_position: Option<(f32, f32)>,
+    mouse_delta: (f32, f32),
+    scroll_delta: f32,
+}
+
+impl InputState {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records a window event. Returns `true` when it was keyboard
+    /// or mouse input, rather than something about the window.
+    pub fn update(&mut self, event: &WindowEvent) -> bool {
+        match event {
+            WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput {
+                        state,
+                        virtual_keycode: Some(keycode),
+                        ..
+                    },
+                ..
+            } => {
+                match state {
+                    ElementState::Pressed => {
+                        // Held keys repeat their presses, which
+                        // don't count as being pressed again.
+                        if self.keys_held.insert(*keycode) {
+                            self.keys_pressed.insert(*keycode);
+                        }
+                    }
+                    ElementState::Released => {
+                        self.keys_held.remove(keycode);
+                    }
+                }
+                true
+            }
+            WindowEvent::MouseInput { state, button, .. } => {
+                match state {
+                    ElementState::Pressed => {
+                        if self.buttons_held.insert(*button) {
+                            self.buttons_pressed.insert(*button);
+                        }
+                    }
+                    ElementState::Released => {
+                        self.buttons_held.remove(button);
+                    }
+                }
+                true
+            }
+            WindowEvent::CursorMoved { position, .. } => {
+                let position = (position.x as f32, position.y as f32);
+                if let Some((x, y)) = self.mouse_position {
+                    self.mouse_delta.0 += position.0 - x;
+                    self.mouse_delta.1 += position.1 - y;
+                }
+                self.mouse_position = Some(position);
+                true
+            }
+            WindowEvent::MouseWheel { delta, .. } => {
+                self.scroll_delta += match delta {
+                    MouseScrollDelta::LineDelta(_, y) => *y,
+                    MouseScrollDelta::PixelDelta(position) => position.y as f32 / PIXELS_PER_LINE,
+                };
+                true
+            }
+            // Keys let go of outside the window would
+            // otherwise stay held.
+            WindowEvent::Focused(false) => {
+                self.keys_held.clear();
+                self.buttons_held.clear();
+                false
+            }
+            _ => false,
+        }
+    }
+
+    /// Clears what happened over the frame, leaving what's held.
+    /// Should be called once the frame is done with the input.
+    pub fn end_frame(&mut self) {
+        self.keys_pressed.clear();
+        self.buttons_pressed.clear();
+        self.mouse_delta = (0.0, 0.0);
+        self.scroll_delta = 0.0;
+    }
+
+    pub fn is_key_held(&self, keycode: VirtualKeyCode) -> bool {
+        self.keys_held.contains(&keycode)
+    }
+
+    /// Whether the key went down since the last frame.
+    pub fn is_key_just_pressed(&self, keycode: VirtualKeyCode) -> bool {
+        self.keys_pressed.contains(&keycode)
+    }
+
+    pub fn is_mouse_held(&self, button: MouseButton) -> bool {
+        self.buttons_held.contains(&button)
+    }
+
+    /// Whether the button went down since the last frame.
+    pub fn is_mouse_just_pressed(&self, button: MouseButton) -> bool {
+        self.buttons_pressed.contains(&button)
+    }
+
+    /// Where the cursor is over the window in physical pixels,
+    /// from the top left.
+    pub fn mouse_position(&self) -> (f32, f32) {
+        self.mouse_position.unwrap_or((0.0, 0.0))
+    }
+
+    /// How far the cursor moved since the last frame, in physical
+    /// pixels. Stops at the edges of the window, unlike the raw
+    /// motion mouse look uses.
+    #[allow(dead_code)]
+    pub fn mouse_delta(&self) -> (f32, f32) {
+        self.mouse_delta
+    }
+
+    /// Lines scrolled since the last frame, positive away from the user.
+    pub fn scroll_delta(&self) -> f32 {
+        self.scroll_delta
+    }
+}
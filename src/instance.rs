@@ -0,0 +1,135 @@
+use crate::transform::Transform;
+
+/// Per-instance data for drawing many copies of the same mesh.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct InstanceData {
+    pub model: [[f32; 4]; 4],
+}
+
+impl InstanceData {
+    pub fn from_matrix(model: cgmath::Matrix4<f32>) -> Self {
+        InstanceData {
+            model: model.into(),
+        }
+    }
+
+    pub fn from_transform(transform: &Transform) -> Self {
+        InstanceData {
+            model: transform.to_matrix(),
+        }
+    }
+
+    pub fn vertex_buffer_layout<'a>() -> wgpu::VertexBufferLayout<'a> {
+        use std::mem;
+        wgpu::VertexBufferLayout {
+            array_stride: mem::size_of::<InstanceData>() as wgpu::BufferAddress,
+            // We need to switch from using a step mode of Vertex to Instance.
+            // This means that our shaders will only change to use the next
+            // instance when the shader starts processing a new instance.
+            step_mode: wgpu::VertexStepMode::Instance,
+            // A mat4 takes up 4 vertex slots as it is technically 4 vec4s.
+            // We need to define a slot for each vec4 and reassemble the
+            // mat4 in the shader.
+            attributes: &[
+                // Instance data starts at location 5, leaving the
+                // lower locations free for the per-vertex attributes.
+                wgpu::VertexAttribute {
+                    offset: 0,
+                    shader_location: 5,
+                    format: wgpu::VertexFormat::Float32x4,
+                },
+                wgpu::VertexAttribute {
+                    offset: mem::size_of::<[f32; 4]>() as wgpu::BufferAddress,
+                    shader_location: 6,
+                    format: wgpu::VertexFormat::Float32x4,
+                },
+                wgpu::VertexAttribute {
+                    offset: mem::size_of::<[f32; 8]>() as wgpu::BufferAddress,
+                    shader_location: 7,
+                    format: wgpu::VertexFormat::Float32x4,
+                },
+                wgpu::VertexAttribute {
+                    offset: mem::size_of::<[f32; 12]>() as wgpu::BufferAddress,
+                    shader_location: 8,
+                    format: wgpu::VertexFormat::Float32x4,
+                },
+            ],
+        }
+    }
+}
+
+/// Vertex buffer holding per-instance data, which can be
+/// rewritten every frame.
+pub struct InstanceBuffer {
+    buffer: wgpu::Buffer,
+    capacity: u32,
+    len: u32,
+}
+
+impl InstanceBuffer {
+    /// Allocates room for `capacity` instances.
+    pub fn new(device: &wgpu::Device, capacity: u32) -> Self {
+        Self::with_usage(device, capacity, wgpu::BufferUsages::empty())
+    }
+
+    /// Allocates room for `capacity` instances, in a buffer that can
+    /// also be used in the `extra_usage` ways, such as from a shader.
+    pub fn with_usage(
+        device: &wgpu::Device,
+        capacity: u32,
+        extra_usage: wgpu::BufferUsages,
+    ) -> Self {
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Instance Buffer"),
+            size: (capacity as usize * std::mem::size_of::<InstanceData>()) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST | extra_usage,
+            mapped_at_creation: false,
+        });
+
+        InstanceBuffer {
+            buffer,
+            capacity,
+            len: 0,
+        }
+    }
+
+    /// Replaces the contents of the buffer with the given instances.
+    ///
+    /// Instances beyond the buffer's capacity are dropped.
+    pub fn upload(&mut self, queue: &wgpu::Queue, instances: &[InstanceData]) {
+        let count = instances.len().min(self.capacity as usize);
+        if count < instances.len() {
+            log::warn!(
+                "instance buffer holds {} instances, dropping {}",
+                self.capacity,
+                instances.len() - count
+            );
+        }
+
+        queue.write_buffer(&self.buffer, 0, bytemuck::cast_slice(&instances[..count]));
+        self.len = count as u32;
+    }
+
+    pub fn buffer(&self) -> &wgpu::Buffer {
+        &self.buffer
+    }
+
+    pub fn capacity(&self) -> u32 {
+        self.capacity
+    }
+
+    /// Number of instances uploaded.
+    pub fn len(&self) -> u32 {
+        self.len
+    }
+
+    #[allow(dead_code)]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn slice(&self) -> wgpu::BufferSlice<'_> {
+        self.buffer.slice(..)
+    }
+}
@@ -0,0 +1,43 @@
+// Screen space quads, each sampling the layer of a texture array its
+// instance picks by ID. The same as `bindless_quad.wgsl`, for devices
+// that can't index an array of textures.
+
+[[group(0), binding(0)]]
+var t_layers: texture_2d_array<f32>;
+[[group(0), binding(1)]]
+var s_layers: sampler;
+
+// Must match `TexturedQuad` in textured_quad.rs.
+struct QuadInput {
+    // Top left corner and size, as fractions of the screen
+    // from its top left.
+    [[location(0)]] position: vec2<f32>;
+    [[location(1)]] size: vec2<f32>;
+    [[location(2)]] texture_id: u32;
+};
+
+struct VertexOutput {
+    [[builtin(position)]] clip_position: vec4<f32>;
+    [[location(0)]] tex_coords: vec2<f32>;
+    // Integers can't be interpolated.
+    [[location(1), interpolate(flat)]] layer: u32;
+};
+
+// Drawn as a triangle strip of four vertices per instance.
+[[stage(vertex)]]
+fn vs_main([[builtin(vertex_index)]] index: u32, quad: QuadInput) -> VertexOutput {
+    let corner = vec2<f32>(f32(index & 1u), f32(index >> 1u));
+    let position = quad.position + corner * quad.size;
+
+    var out: VertexOutput;
+    // Y points down the screen, but up in clip space.
+    out.clip_position = vec4<f32>(position.x * 2.0 - 1.0, 1.0 - position.y * 2.0, 0.0, 1.0);
+    out.tex_coords = corner;
+    out.layer = quad.texture_id;
+    return out;
+}
+
+[[stage(fragment)]]
+fn fs_main(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    return textureSample(t_layers, s_layers, in.tex_coords, i32(in.layer));
+}
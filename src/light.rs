@@ -0,0 +1,180 @@
+use cgmath::InnerSpace;
+
+use crate::{camera::OPENGL_TO_WGPU_MATRIX, uniform::UniformBinding};
+
+/// Most lights the shader can take at once. Must match
+/// `MAX_LIGHTS` in the shader.
+pub const MAX_LIGHTS: usize = 8;
+
+/// Light coming from infinitely far away, like the sun,
+/// so every ray points the same way.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DirectionalLight {
+    /// Direction the light is shining in.
+    pub direction: [f32; 3],
+    pub color: [f32; 3],
+    /// How much of the light's color reaches surfaces facing
+    /// away from it.
+    pub ambient: f32,
+}
+
+impl DirectionalLight {
+    /// View projection for rendering a shadow map of the light, covering
+    /// a sphere of `radius` around `center`.
+    ///
+    /// The light has no position, so the view is placed outside the
+    /// sphere, looking along the light's direction.
+    pub fn view_projection_matrix(
+        &self,
+        center: cgmath::Point3<f32>,
+        radius: f32,
+    ) -> [[f32; 4]; 4] {
+        let direction = cgmath::Vector3::from(self.direction).normalize();
+        let eye = center - direction * radius * 2.0;
+        // The up vector can't be parallel to the view direction.
+        let up = if direction.y.abs() > 0.99 {
+            cgmath::Vector3::unit_z()
+        } else {
+            cgmath::Vector3::unit_y()
+        };
+
+        let view = cgmath::Matrix4::look_at_rh(eye, center, up);
+        // Directional light rays are parallel, so the projection is orthographic.
+        let proj = cgmath::ortho(-radius, radius, -radius, radius, radius, radius * 3.0);
+
+        (OPENGL_TO_WGPU_MATRIX * proj * view).into()
+    }
+}
+
+/// Light shining out in every direction from a point, fading
+/// out with distance.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PointLight {
+    pub position: [f32; 3],
+    pub color: [f32; 3],
+    /// Multiplied with the color.
+    pub intensity: f32,
+    /// Distance the light has faded out to nothing by.
+    pub range: f32,
+}
+
+/// A point light laid out the way the shader expects it, with the
+/// scalars filling the gaps after each `vec3`.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Default, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct PointLightRaw {
+    position: [f32; 3],
+    range: f32,
+    color: [f32; 3],
+    intensity: f32,
+}
+
+impl From<&PointLight> for PointLightRaw {
+    fn from(light: &PointLight) -> Self {
+        PointLightRaw {
+            position: light.position,
+            range: light.range,
+            color: light.color,
+            intensity: light.intensity,
+        }
+    }
+}
+
+/// A light laid out the way the shader expects it.
+///
+/// A `vec3` is aligned to 16 bytes in a uniform buffer, so the
+/// scalar `ambient` fills the gap after `direction`, and the
+/// struct is padded out to a multiple of 16 bytes.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Default, bytemuck::Pod, bytemuck::Zeroable)]
+struct LightRaw {
+    direction: [f32; 3],
+    ambient: f32,
+    color: [f32; 3],
+    _padding: f32,
+}
+
+impl From<&DirectionalLight> for LightRaw {
+    fn from(light: &DirectionalLight) -> Self {
+        LightRaw {
+            direction: light.direction,
+            ambient: light.ambient,
+            color: light.color,
+            _padding: 0.0,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct LightUniform {
+    lights: [LightRaw; MAX_LIGHTS],
+    count: u32,
+    _padding: [u32; 3],
+}
+
+impl LightUniform {
+    pub fn new() -> Self {
+        LightUniform {
+            lights: [LightRaw::default(); MAX_LIGHTS],
+            count: 0,
+            _padding: [0; 3],
+        }
+    }
+
+    /// Packs the lights into the fixed size array.
+    ///
+    /// Lights beyond `MAX_LIGHTS` are left out.
+    pub fn update_lights(&mut self, lights: &[DirectionalLight]) {
+        if lights.len() > MAX_LIGHTS {
+            log::warn!(
+                "only {} lights are supported, dropping {}",
+                MAX_LIGHTS,
+                lights.len() - MAX_LIGHTS
+            );
+        }
+
+        let count = lights.len().min(MAX_LIGHTS);
+        for (raw, light) in self.lights.iter_mut().zip(&lights[..count]) {
+            *raw = light.into();
+        }
+        self.count = count as u32;
+    }
+}
+
+impl Default for LightUniform {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub struct LightBuffer {
+    uniform: LightUniform,
+    binding: UniformBinding<LightUniform>,
+}
+
+impl LightBuffer {
+    pub fn new(device: &wgpu::Device, lights: &[DirectionalLight]) -> Self {
+        let mut uniform = LightUniform::new();
+        uniform.update_lights(lights);
+
+        let binding = UniformBinding::new(
+            device,
+            "Light Buffer",
+            wgpu::ShaderStages::FRAGMENT,
+            &uniform,
+        );
+
+        LightBuffer { uniform, binding }
+    }
+
+    pub fn binding(&self) -> &UniformBinding<LightUniform> {
+        &self.binding
+    }
+
+    /// Uploads the current state of the lights.
+    pub fn update(&mut self, queue: &wgpu::Queue, lights: &[DirectionalLight]) {
+        self.uniform.update_lights(lights);
+        self.binding.update(queue, &self.uniform);
+    }
+}
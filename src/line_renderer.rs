@@ -0,0 +1,213 @@
+use crate::{
+    depth::DepthBuffer, msaa::MsaaConfig, pipeline::RenderPipelineBuilder, uniform::UniformBinding,
+};
+
+/// Room for this many segments is allocated up front, and
+/// the buffer grows when a frame needs more.
+const INITIAL_CAPACITY: usize = 256;
+
+/// A line from `a` to `b`, `width` pixels wide.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct LineSegment {
+    pub a: [f32; 3],
+    pub b: [f32; 3],
+    pub color: [f32; 4],
+    pub width: f32,
+}
+
+impl LineSegment {
+    const ATTRIBUTES: [wgpu::VertexAttribute; 4] =
+        wgpu::vertex_attr_array![0 => Float32x3, 1 => Float32x3, 2 => Float32x4, 3 => Float32];
+
+    /// Segments step once per instance, with the corners of
+    /// their quads coming from the vertex index.
+    fn vertex_buffer_layout<'a>() -> wgpu::VertexBufferLayout<'a> {
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<LineSegment>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Instance,
+            attributes: &Self::ATTRIBUTES,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct ViewportUniform {
+    size: [f32; 2],
+    _padding: [f32; 2],
+}
+
+impl ViewportUniform {
+    fn new(width: u32, height: u32) -> Self {
+        ViewportUniform {
+            size: [width as f32, height as f32],
+            _padding: [0.0; 2],
+        }
+    }
+}
+
+/// Draws lines of any width into the scene, with smooth edges and
+/// round ends.
+///
+/// Pipelines can only draw lines a pixel wide, so each segment is
+/// drawn as a quad instead, spread across the screen by the vertex
+/// shader from six vertices. The quad is a pixel wider than the line
+/// all round, and the fragment shader fades out the pixels the line
+/// only partly covers.
+///
+/// Lines are queued with [`LineRenderer::draw_line`] during the frame,
+/// uploaded by [`LineRenderer::prepare`], and drawn by
+/// [`LineRenderer::draw`]. Unlike gizmos they're depth tested, so the
+/// scene hides them.
+pub struct LineRenderer {
+    viewport: UniformBinding<ViewportUniform>,
+    segment_buffer: wgpu::Buffer,
+    /// Number of segments the buffer has room for.
+    capacity: usize,
+    segments: Vec<LineSegment>,
+    /// Number of segments uploaded by the last `prepare`.
+    segment_count: u32,
+    pipeline: wgpu::RenderPipeline,
+    pipeline_layout: wgpu::PipelineLayout,
+    shader: wgpu::ShaderModule,
+    format: wgpu::TextureFormat,
+}
+
+impl LineRenderer {
+    /// The lines are drawn with the camera's bind group at group 0.
+    pub fn new(
+        device: &wgpu::Device,
+        camera_layout: &wgpu::BindGroupLayout,
+        config: &wgpu::SurfaceConfiguration,
+        format: wgpu::TextureFormat,
+        msaa: MsaaConfig,
+    ) -> Self {
+        let viewport = UniformBinding::new(
+            device,
+            "Line Viewport Uniform",
+            wgpu::ShaderStages::VERTEX,
+            &ViewportUniform::new(config.width, config.height),
+        );
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Line Pipeline Layout"),
+            bind_group_layouts: &[camera_layout, &viewport.bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let shader = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+            label: Some("Line Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("line_renderer.wgsl").into()),
+        });
+        let pipeline = create_pipeline(device, &pipeline_layout, &shader, format, msaa);
+
+        LineRenderer {
+            viewport,
+            segment_buffer: create_segment_buffer(device, INITIAL_CAPACITY),
+            capacity: INITIAL_CAPACITY,
+            segments: Vec::new(),
+            segment_count: 0,
+            pipeline,
+            pipeline_layout,
+            shader,
+            format,
+        }
+    }
+
+    /// Line widths are in pixels, so the shader needs to know
+    /// how big the screen is.
+    pub fn resize(&self, queue: &wgpu::Queue, width: u32, height: u32) {
+        self.viewport
+            .update(queue, &ViewportUniform::new(width, height));
+    }
+
+    /// Queues a line from `a` to `b`, `width` pixels wide.
+    pub fn draw_line(&mut self, a: [f32; 3], b: [f32; 3], width: f32, color: [f32; 4]) {
+        self.segments.push(LineSegment { a, b, color, width });
+    }
+
+    /// Uploads the lines queued this frame, and clears the queue
+    /// for the next one. Must be called before the lines are drawn.
+    pub fn prepare(&mut self, device: &wgpu::Device, queue: &wgpu::Queue) {
+        if self.segments.len() > self.capacity {
+            self.capacity = self.segments.len().next_power_of_two();
+            self.segment_buffer = create_segment_buffer(device, self.capacity);
+        }
+
+        queue.write_buffer(
+            &self.segment_buffer,
+            0,
+            bytemuck::cast_slice(&self.segments),
+        );
+        self.segment_count = self.segments.len() as u32;
+        self.segments.clear();
+    }
+
+    /// Recreates the pipeline to match the sample count of the pass.
+    pub fn set_msaa(&mut self, device: &wgpu::Device, msaa: MsaaConfig) {
+        self.pipeline = create_pipeline(
+            device,
+            &self.pipeline_layout,
+            &self.shader,
+            self.format,
+            msaa,
+        );
+    }
+
+    pub fn draw<'a>(
+        &'a self,
+        render_pass: &mut wgpu::RenderPass<'a>,
+        camera_bind_group: &'a wgpu::BindGroup,
+    ) {
+        if self.segment_count == 0 {
+            return;
+        }
+
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, camera_bind_group, &[]);
+        render_pass.set_bind_group(1, &self.viewport.bind_group, &[]);
+        render_pass.set_vertex_buffer(0, self.segment_buffer.slice(..));
+        render_pass.draw(0..6, 0..self.segment_count);
+    }
+}
+
+fn create_segment_buffer(device: &wgpu::Device, capacity: usize) -> wgpu::Buffer {
+    device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Line Segment Buffer"),
+        size: (capacity * std::mem::size_of::<LineSegment>()) as wgpu::BufferAddress,
+        usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    })
+}
+
+fn create_pipeline(
+    device: &wgpu::Device,
+    layout: &wgpu::PipelineLayout,
+    shader: &wgpu::ShaderModule,
+    format: wgpu::TextureFormat,
+    msaa: MsaaConfig,
+) -> wgpu::RenderPipeline {
+    RenderPipelineBuilder::new()
+        .label("Line Pipeline")
+        .vertex_shader(shader, "main")
+        .vertex_layouts(&[LineSegment::vertex_buffer_layout()])
+        .fragment_shader(
+            shader,
+            "main",
+            &[wgpu::ColorTargetState {
+                format,
+                blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                write_mask: wgpu::ColorWrites::ALL,
+            }],
+        )
+        // Which way the quad winds depends on the line's direction
+        // on screen.
+        .cull_mode(None)
+        // The faded edges would hide what's drawn behind them after.
+        .depth_stencil(wgpu::DepthStencilState {
+            depth_write_enabled: false,
+            ..DepthBuffer::depth_stencil_state()
+        })
+        .multisample(msaa.count)
+        .build(device, layout)
+        .expect("failed to build line pipeline")
+}
@@ -0,0 +1,113 @@
+// Draws wide lines as quads spread across the screen, fading out
+// at their edges and rounded at their ends.
+
+[[block]]
+struct CameraUniform {
+    view_proj: mat4x4<f32>;
+};
+
+[[block]]
+struct ViewportUniform {
+    size: vec2<f32>;
+};
+
+[[group(0), binding(0)]]
+var<uniform> camera: CameraUniform;
+
+[[group(1), binding(0)]]
+var<uniform> viewport: ViewportUniform;
+
+struct SegmentInput {
+    [[location(0)]] a: vec3<f32>;
+    [[location(1)]] b: vec3<f32>;
+    [[location(2)]] color: vec4<f32>;
+    [[location(3)]] width: f32;
+};
+
+struct VertexOutput {
+    [[builtin(position)]] clip_position: vec4<f32>;
+    [[location(0)]] color: vec4<f32>;
+    // Pixels along the line from `a`, and across it from its middle.
+    // Measured on screen, so not corrected for perspective.
+    [[location(1), interpolate(linear)]] local: vec2<f32>;
+    // Pixels from `a` to `b` on screen, and half the line's width.
+    [[location(2), interpolate(flat)]] extent: vec2<f32>;
+};
+
+// Moves `from` along the segment towards `to` until it's in front
+// of the near plane, so both ends can be projected onto the screen.
+fn clip_to_near(from: vec4<f32>, to: vec4<f32>) -> vec4<f32> {
+    if (from.z >= 0.0) {
+        return from;
+    }
+    return mix(from, to, from.z / (from.z - to.z));
+}
+
+// Each segment is an instance, with the quad's corners coming from
+// the vertex index. A corner's x picks the end, and y the side.
+[[stage(vertex)]]
+fn main(
+    [[builtin(vertex_index)]] vertex_index: u32,
+    in: SegmentInput,
+) -> VertexOutput {
+    var corners: array<vec2<f32>, 6> = array<vec2<f32>, 6>(
+        vec2<f32>(0.0, -1.0),
+        vec2<f32>(1.0, -1.0),
+        vec2<f32>(1.0, 1.0),
+        vec2<f32>(0.0, -1.0),
+        vec2<f32>(1.0, 1.0),
+        vec2<f32>(0.0, 1.0),
+    );
+    let corner = corners[vertex_index % 6u];
+
+    var out: VertexOutput;
+    let clip_a = camera.view_proj * vec4<f32>(in.a, 1.0);
+    let clip_b = camera.view_proj * vec4<f32>(in.b, 1.0);
+    if (clip_a.z < 0.0 && clip_b.z < 0.0) {
+        // Wholly behind the camera, so the quad is collapsed to nothing.
+        out.clip_position = vec4<f32>(0.0, 0.0, 0.0, 1.0);
+        return out;
+    }
+    let a = clip_to_near(clip_a, clip_b);
+    let b = clip_to_near(clip_b, a);
+
+    let screen_a = a.xy / a.w * 0.5 * viewport.size;
+    let screen_b = b.xy / b.w * 0.5 * viewport.size;
+    let screen_length = distance(screen_a, screen_b);
+    var direction = vec2<f32>(1.0, 0.0);
+    if (screen_length > 0.0001) {
+        direction = (screen_b - screen_a) / screen_length;
+    }
+    let normal = vec2<f32>(-direction.y, direction.x);
+
+    // A pixel is left around the line for its edges to fade out in,
+    // and the ends are pushed out for the round caps to fit.
+    let half_width = in.width * 0.5;
+    let radius = half_width + 1.0;
+    let along = mix(-radius, screen_length + radius, corner.x);
+    let across = corner.y * radius;
+    let offset = direction * (along - corner.x * screen_length) + normal * across;
+
+    var clip = a;
+    if (corner.x > 0.5) {
+        clip = b;
+    }
+    out.clip_position = vec4<f32>(clip.xy + offset / (0.5 * viewport.size) * clip.w, clip.zw);
+    out.color = in.color;
+    out.local = vec2<f32>(along, across);
+    out.extent = vec2<f32>(screen_length, half_width);
+    return out;
+}
+
+[[stage(fragment)]]
+fn main(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    // Distance from the nearest point on the segment, rounding off
+    // the ends.
+    let nearest = clamp(in.local.x, 0.0, in.extent.x);
+    let from_line = length(vec2<f32>(in.local.x - nearest, in.local.y));
+    let coverage = clamp(in.extent.y + 0.5 - from_line, 0.0, 1.0);
+    if (coverage <= 0.0) {
+        discard;
+    }
+    return vec4<f32>(in.color.rgb, in.color.a * coverage);
+}
@@ -0,0 +1,154 @@
+use cgmath::InnerSpace;
+
+use crate::{
+    bounds::Aabb,
+    camera::{Camera, CameraProjection},
+    ecs::World,
+    mesh::Mesh,
+    transform::Transform,
+};
+
+/// Fraction of a threshold an object has to come back inside of
+/// before switching to the more detailed level again.
+const DEFAULT_HYSTERESIS: f32 = 0.1;
+
+/// Component naming the [`LodMesh`] an entity is drawn with, by its
+/// index in the renderer's LOD meshes.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct LodHandle(pub usize);
+
+/// Component holding the level of detail an entity was last drawn at,
+/// which is needed to know which way the hysteresis goes.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Default)]
+pub struct LodLevel(pub usize);
+
+/// A mesh at several levels of detail, from the most detailed down.
+///
+/// Each level comes with the distance it's used from. Distances are
+/// measured from how big the mesh appears on the screen, see
+/// [`lod_distance`], so they're the same for near and far cameras.
+pub struct LodMesh {
+    levels: Vec<(f32, Mesh)>,
+    /// Bounds of the most detailed level, in model space.
+    bounds: Aabb,
+    hysteresis: f32,
+}
+
+impl LodMesh {
+    pub fn new(bounds: Aabb) -> Self {
+        LodMesh {
+            levels: Vec::new(),
+            bounds,
+            hysteresis: DEFAULT_HYSTERESIS,
+        }
+    }
+
+    /// Uses `mesh` from `distance` on, until the next level's distance.
+    ///
+    /// # Panics
+    ///
+    /// When `distance` isn't past the distance of the last level added.
+    pub fn add_level(&mut self, distance: f32, mesh: Mesh) {
+        if let Some((last, _)) = self.levels.last() {
+            assert!(
+                distance > *last,
+                "LOD level at {} must come after the level at {}",
+                distance,
+                last
+            );
+        }
+        self.levels.push((distance, mesh));
+    }
+
+    /// Sets how far inside a level's distance an object has to come
+    /// back before it switches to the more detailed level, as a
+    /// fraction of that distance.
+    #[allow(dead_code)]
+    pub fn with_hysteresis(mut self, hysteresis: f32) -> Self {
+        self.hysteresis = hysteresis;
+        self
+    }
+
+    pub fn bounds(&self) -> &Aabb {
+        &self.bounds
+    }
+
+    #[allow(dead_code)]
+    pub fn levels(&self) -> &[(f32, Mesh)] {
+        &self.levels
+    }
+
+    /// # Panics
+    ///
+    /// When there's no level at `level`.
+    pub fn mesh(&self, level: usize) -> &Mesh {
+        &self.levels[level].1
+    }
+
+    /// The level to draw at `distance`, moving on from `current`.
+    ///
+    /// Less detailed levels are switched to as soon as their distance
+    /// is passed, while the more detailed ones only come back once the
+    /// distance is inside of the inner threshold the hysteresis leaves,
+    /// so objects sitting on a threshold don't flicker between levels.
+    pub fn select_level(&self, current: usize, distance: f32) -> usize {
+        let target = self
+            .levels
+            .iter()
+            .rposition(|(threshold, _)| distance >= *threshold)
+            .unwrap_or(0);
+
+        let mut level = current.min(self.levels.len().saturating_sub(1));
+        if target > level {
+            return target;
+        }
+        while level > target {
+            let inner = self.levels[level].0 * (1.0 - self.hysteresis);
+            if distance >= inner {
+                break;
+            }
+            level -= 1;
+        }
+        level
+    }
+}
+
+/// How far away `bounds`, in world space, looks to `camera`.
+///
+/// This is the distance a sphere one unit in radius would have to be
+/// at to look as big as the bounds do through a 90° field of view, so
+/// bigger objects and narrower fields of view bring things closer.
+pub fn lod_distance(camera: &Camera, bounds: &Aabb) -> f32 {
+    let radius = bounds.radius().max(f32::EPSILON);
+    match camera.projection {
+        CameraProjection::Perspective { fov, .. } => {
+            let center = cgmath::Point3::from(bounds.center());
+            let distance = (center - camera.eye).magnitude();
+            // Fraction of half the screen the bounds cover is
+            // `radius / (distance * tan(fov / 2))`.
+            distance * (fov.to_radians() * 0.5).tan() / radius
+        }
+        // Things are as big at any distance, so only the
+        // size of the view matters.
+        CameraProjection::Orthographic { bottom, top, .. } => (top - bottom) * 0.5 / radius,
+    }
+}
+
+/// Picks the level of detail of every entity with a [`LodHandle`]
+/// and a transform, for how far it looks from `camera`. Entities
+/// without a [`LodLevel`] yet start at the most detailed level.
+pub fn update_levels(world: &mut World, lod_meshes: &[LodMesh], camera: &Camera) {
+    let levels = world
+        .query::<(Transform, LodHandle)>()
+        .filter_map(|(entity, (transform, handle))| {
+            let lod_mesh = lod_meshes.get(handle.0)?;
+            let current = world.get::<LodLevel>(entity).copied().unwrap_or_default();
+            let distance = lod_distance(camera, &lod_mesh.bounds().transformed(transform));
+            Some((entity, LodLevel(lod_mesh.select_level(current.0, distance))))
+        })
+        .collect::<Vec<_>>();
+
+    for (entity, level) in levels {
+        world.insert(entity, level);
+    }
+}
@@ -1,330 +1,2706 @@
-use wgpu::util::DeviceExt;
+mod adapter;
+mod bind_group_allocator;
+mod bindless;
+mod bloom;
+mod bounds;
+mod camera;
+mod cloth;
+mod compute;
+mod compute_histogram;
+mod compute_sort;
+mod context;
+mod csm;
+// Nothing captures the scene into a cubemap yet.
+#[allow(dead_code)]
+mod cubemap_capture;
+mod debug_ui;
+mod deferred;
+mod depth;
+mod dithered_transparency;
+mod dynamic_mesh;
+mod ecs;
+mod font;
+mod fullscreen_triangle;
+mod gizmo;
+mod gltf_loader;
+mod glyph_cache;
+mod gpu_debug_marker;
+mod hdr_render_target;
+mod ibl;
+mod index;
+mod indirect;
+mod input;
+mod instance;
+mod light;
+mod line_renderer;
+mod lod;
+mod material;
+mod mesh;
+mod mesh_simplifier;
+// Nothing is mipped on the GPU yet.
+#[allow(dead_code)]
+mod mip_generator;
+mod motion_blur;
+mod msaa;
+mod multi_viewport;
+mod normal_reconstruct;
+mod occlusion_query;
+mod particle_system;
+mod particles;
+mod pbr;
+mod picking;
+mod pipeline;
+mod pipeline_cache;
+mod point_cloud;
+mod post_process;
+mod primitives;
+mod proc_texture;
+mod procedural_sky;
+mod profiler;
+mod push_constants;
+mod raycast;
+mod readback;
+mod render_bundle;
+mod render_graph;
+mod render_pass_guard;
+mod render_state;
+mod render_stats;
+mod render_target;
+mod renderdoc;
+mod scene;
+mod scene_serializer;
+mod screenshot;
+mod sdf_font;
+mod shader_compiler;
+mod shader_watcher;
+mod shadow;
+mod skeletal_animation;
+mod sky_light;
+mod skybox;
+mod spline_camera;
+mod sprite;
+mod ssao;
+mod swapchain;
+mod taa;
+mod terrain;
+mod text;
+mod texture;
+mod texture_array;
+mod texture_atlas;
+mod textured_quad;
+mod timer;
+mod tone_mapping;
+mod transform;
+mod transient_texture;
+mod uniform;
+mod velocity;
+mod vertex;
+// Nothing streams pages into a virtual texture yet.
+#[allow(dead_code)]
+mod virtual_texture;
+mod voxel;
+mod water;
+mod wireframe_overlay;
+
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::Arc,
+};
+
+use adapter::AdapterSelector;
+use bind_group_allocator::BindGroupAllocator;
+use bindless::BindlessTextureArray;
+use bloom::BloomPass;
+use bounds::{Aabb, Frustum, Ray};
+use camera::{Camera, CameraBuffer, CameraController, CameraProjection, CameraUniform};
+use cloth::{ClothRenderer, ClothSimulation};
+use compute_histogram::ComputeHistogram;
+use context::{CompatMode, GpuContext};
+use csm::CsmShadowPass;
+use debug_ui::{DebugUi, SceneSettings};
+use deferred::DeferredRenderer;
+use depth::DepthBuffer;
+use ecs::{EntityId, World};
+use font::Font;
+use gizmo::GizmoPass;
+use gltf_loader::{GltfLoader, GltfScene};
+use gpu_debug_marker::gpu_scope;
+use hdr_render_target::HDR_FORMAT;
+use ibl::IblPrecompute;
+use indirect::{CullObject, IndirectCullPass};
+use input::InputState;
+use instance::InstanceData;
+use light::{DirectionalLight, LightBuffer, LightUniform, PointLight};
+use line_renderer::LineRenderer;
+use lod::{LodHandle, LodMesh};
+use material::{BlendMode, Material, MaterialLibrary, MaterialUniform};
+use mesh::Mesh;
+use mesh_simplifier::MeshSimplifier;
+use motion_blur::MotionBlurPass;
+use msaa::MsaaConfig;
+use multi_viewport::MultiViewportRenderer;
+use normal_reconstruct::NormalReconstruct;
+use occlusion_query::OcclusionQueryPool;
+use particle_system::{EmitterConfig, ParticleSystem};
+use particles::ParticleSimulation;
+use pbr::{PbrEnvironment, PbrPipeline};
+use picking::{PickingDraw, PickingPass};
+use pipeline::{PipelineState, RenderPipelineBuilder};
+use pipeline_cache::{PipelineCache, PipelineKey};
+use point_cloud::{PointCloud, PointCloudRenderer};
+use post_process::{PostProcessEffect, PostProcessPass};
+use proc_texture::ProcTexture;
+use procedural_sky::{ProceduralSky, SkyConfig};
+use profiler::Profiler;
+use raycast::RaycastPicker;
+use render_bundle::RenderBundleRecorder;
+use render_graph::{RenderGraph, RenderPass, RenderResources};
+use render_pass_guard::RenderPassBuilder;
+use render_state::{RenderStateSnapshot, ScissorRect, ViewportState};
+use render_stats::RenderStats;
+use renderdoc::RenderDocCapture;
+use scene::{BatchMesh, DrawBatch, MaterialHandle, MeshHandle};
+use scene_serializer::{MeshAsset, SceneSerializer};
+use screenshot::ScreenshotCapture;
+use sdf_font::SdfFont;
+use shader_compiler::ShaderCompiler;
+use shader_watcher::ShaderWatcher;
+use skeletal_animation::{AnimChannel, AnimationClip, Keyframe, SkinnedMesh, SkinnedMeshRenderer};
+use sky_light::{DynamicSkyLight, TimeOfDay};
+use skybox::SkyboxPass;
+use spline_camera::{CameraKeyframe, LoopMode, SplineCamera, SplinePath};
+use sprite::{SpriteImage, SpriteInstance, SpriteRenderer};
+use ssao::SsaoPass;
+use swapchain::SwapchainConfig;
+use taa::TaaPass;
+use terrain::{HeightMap, Terrain};
+use text::TextRenderer;
+use texture::Texture;
+use texture_array::TextureArray;
+use texture_atlas::TextureAtlas;
+use textured_quad::{TexturedQuad, TexturedQuadRenderer};
+use timer::FrameTimer;
+use tone_mapping::{ToneMapOperator, ToneMappingPass};
+use transform::Transform;
+use transient_texture::{TextureHandle, TransientTexturePool, TransientUsage};
+use uniform::UniformBinding;
+use velocity::VelocityPass;
+use vertex::Vertex;
+use voxel::VoxelGrid;
+use water::{WaterConfig, WaterPass};
 use winit::{
     event::*,
     event_loop::{ControlFlow, EventLoop},
     window::{Window, WindowBuilder},
 };
+use wireframe_overlay::WireframeOverlay;
+
+// Triangle
+#[rustfmt::skip]
+const VERTICES: &[Vertex] = &[
+    Vertex { position: [-0.0868241,   0.49240386, 0.0], color: [0.5, 0.0, 0.5], normal: [0.0, 0.0, 1.0], tex_coords: [0.4131759,    0.00759614] }, // A
+    Vertex { position: [-0.49513406,  0.06958647, 0.0], color: [0.5, 0.0, 0.5], normal: [0.0, 0.0, 1.0], tex_coords: [0.0048659444, 0.43041354] }, // B
+    Vertex { position: [-0.21918549, -0.44939706, 0.0], color: [0.5, 0.0, 0.5], normal: [0.0, 0.0, 1.0], tex_coords: [0.28081453,   0.949397  ] }, // C
+    Vertex { position: [ 0.35966998, -0.3473291,  0.0], color: [0.5, 0.0, 0.5], normal: [0.0, 0.0, 1.0], tex_coords: [0.85967,      0.84732914] }, // D
+    Vertex { position: [ 0.44147372,  0.2347359,  0.0], color: [0.5, 0.0, 0.5], normal: [0.0, 0.0, 1.0], tex_coords: [0.9414737,    0.2652641 ] }, // E
+];
+
+#[rustfmt::skip]
+const INDICES: &[u16] = &[
+    0, 1, 4,
+    1, 2, 4,
+    2, 3, 4,
+    // IMPORTANT: We add 2 bytes padding as wgpu requires buffers to be aligned to 4 bytes.
+    /* padding */
+    0,
+];
+
+// Instances are laid out in a grid on the XZ plane.
+const NUM_INSTANCES_PER_ROW: u32 = 5;
+const INSTANCE_SPACING: f32 = 1.5;
+
+/// Spheres in a row going away from the grid, drawn with
+/// less detail the further away they are.
+const NUM_LOD_SPHERES: u32 = 8;
+const LOD_SPHERE_SPACING: f32 = 4.0;
+
+/// Stacks and slices of the most detailed level of the LOD spheres.
+const LOD_SPHERE_DETAIL: u16 = 32;
+/// The share of the triangles of the most detailed level each level
+/// of the LOD spheres is simplified down to, and the distance it's
+/// used from.
+const LOD_SPHERE_LEVELS: [(f32, f32); 3] = [(0.0, 1.0), (6.0, 0.15), (14.0, 0.04)];
+
+/// Size of the terrain loaded from `res/heightmap.png`, and where
+/// it goes, off to the side of the grid.
+const TERRAIN_WIDTH: f32 = 24.0;
+const TERRAIN_HEIGHT_SCALE: f32 = 3.0;
+const TERRAIN_POSITION: [f32; 3] = [24.0, -1.0, 0.0];
+/// Pixels of the height map skipped between the terrain's vertices.
+const TERRAIN_STEP: u32 = 2;
+
+/// Height of the water covering the low parts of the terrain.
+const WATER_HEIGHT: f32 = -0.2;
+
+/// Most instances culled on the GPU at once.
+const MAX_GPU_CULLED_OBJECTS: u32 = 4096;
+/// Most objects tested for being hidden behind others each frame.
+const MAX_OCCLUSION_QUERIES: u32 = 1024;
+
+/// Where the point cloud given with `--points` is placed, and how
+/// wide its points are drawn.
+const POINT_CLOUD_POSITION: [f32; 3] = [-4.0, 0.0, -4.0];
+const POINT_CLOUD_POINT_SIZE: f32 = 0.02;
+
+/// Where the corner of the voxel grid goes, and how big its voxels are.
+const VOXEL_POSITION: [f32; 3] = [4.0, -0.5, -8.0];
+const VOXEL_SIZE: f32 = 0.25;
+
+/// Color of the outline around the picked entity, and how much
+/// bigger than the entity it's drawn.
+const OUTLINE_COLOR: [f32; 4] = [1.0, 0.6, 0.0, 1.0];
+const OUTLINE_SCALE: f32 = 1.05;
+
+/// Radius and height of the fly-through circling the scene, and
+/// the seconds it takes to go around once.
+const FLY_THROUGH_RADIUS: f32 = 12.0;
+const FLY_THROUGH_HEIGHT: f32 = 4.0;
+const FLY_THROUGH_PERIOD: f32 = 20.0;
+
+/// Where the skinned column stands, how many bones bend it, and how
+/// far each bends either way, in radians, over the sway's period.
+const SKINNED_COLUMN_POSITION: [f32; 3] = [-6.0, -0.5, 2.0];
+const SKINNED_COLUMN_BONES: usize = 4;
+const SWAY_ANGLE: f32 = 0.3;
+const SWAY_PERIOD: f32 = 3.0;
+
+/// Where the cloth's ground is, how many particles it has each way,
+/// and how hard it pulls back against being stretched.
+const CLOTH_POSITION: [f32; 3] = [6.0, -0.5, 3.0];
+const CLOTH_SIZE: u32 = 24;
+const CLOTH_STIFFNESS: f32 = 1.0e6;
+
+/// Samples each pixel is blurred with along its velocity, and how
+/// much of the camera's movement since the last frame the blur
+/// covers.
+const MOTION_BLUR_SAMPLES: u32 = 8;
+const MOTION_BLUR_STRENGTH: f32 = 1.0;
+/// How much of the earlier frames temporal anti-aliasing keeps
+/// each frame.
+const TAA_FEEDBACK: f32 = 0.9;
+/// Samples each pixel's ambient occlusion takes, how far around it
+/// they go in world units, and how far in front of a sample the
+/// scene has to be to count.
+const SSAO_KERNEL_SIZE: u32 = 32;
+const SSAO_RADIUS: f32 = 0.5;
+const SSAO_BIAS: f32 = 0.025;
+
+/// Frames between the CPU profiler's reports in the log.
+const PROFILE_REPORT_INTERVAL: u32 = 60;
+
+const NUM_PARTICLES: u32 = 4096;
+// Sparks from the emitter simulated on the CPU.
+const NUM_SPARKS: u32 = 256;
+
+/// How far in front of the camera shadows are cast, split up between
+/// the cascades.
+const SHADOW_DISTANCE: f32 = 40.0;
+/// How far the cascades' splits are from even towards logarithmic.
+const CSM_SPLIT_LAMBDA: f32 = 0.75;
+
+const PERSPECTIVE: CameraProjection = CameraProjection::Perspective {
+    fov: 45.0,
+    near: 0.1,
+    far: 100.0,
+};
+
+/// Wide enough to fit the whole grid of instances.
+const ORTHOGRAPHIC_WIDTH: f32 = 10.0;
+
+/// Images for the faces of the skybox, in the order the
+/// cubemap layers are in, looked for in `res/skybox`.
+const SKYBOX_FACES: [&str; 6] = ["px.png", "nx.png", "py.png", "ny.png", "pz.png", "nz.png"];
+
+/// Most shader errors kept on screen, oldest dropped first.
+const MAX_SHADER_ERRORS: usize = 4;
+
+/// Material for meshes that don't name one.
+const DEFAULT_MATERIAL: &str = "default";
+/// Dithered see-through material the LOD spheres are drawn with.
+const GLASS_MATERIAL: &str = "glass";
+
+/// Color the scene is cleared to, where the sky doesn't cover it.
+const CLEAR_COLOR: wgpu::Color = wgpu::Color {
+    r: 0.1,
+    g: 0.2,
+    b: 0.3,
+    a: 1.0,
+};
+
+/// Color of the sun at an intensity of 1.
+const SUN_COLOR: [f32; 3] = [1.0, 0.95, 0.85];
+
+/// How fast the sky turns, in radians per second.
+const SKY_ROTATION_SPEED: f32 = 0.02;
+
+/// Scales the radiance of the sky into the color of the sun, while
+/// the time of day moves it.
+const SKY_LIGHT_INTENSITY: f32 = 1.0;
+/// In-game seconds that pass every second while the time of day
+/// moves, so a day goes by in a couple of minutes.
+const TIME_OF_DAY_SPEED: f32 = 720.0;
+
+/// Pixels brighter than this glow.
+const BLOOM_THRESHOLD: f32 = 0.8;
+const BLOOM_INTENSITY: f32 = 0.6;
+
+/// Scales the HDR scene before it's tone mapped, while it isn't
+/// exposed automatically.
+const EXPOSURE: f32 = 1.0;
+/// Bins of the luminance histogram auto-exposure is measured with.
+const HISTOGRAM_BINS: u32 = 64;
 
-#[repr(C)]
-#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
-struct Vertex {
-    position: [f32; 3],
-    color: [f32; 3],
+/// Size distance field fonts are built at, in pixels to the em,
+/// and how far out from the glyphs the distances go.
+const SDF_GLYPH_SIZE: u32 = 16;
+const SDF_RADIUS: u32 = 4;
+/// Size of rasterized text at a scale of 1, in pixels to the em, so
+/// it's as big as distance field text.
+const RASTERIZED_GLYPH_SIZE: f32 = SDF_GLYPH_SIZE as f32;
+
+/// Keys that do something when pressed, handled by `State::key_action`.
+/// Tab and Escape are left to the event loop.
+const ACTION_KEYS: [VirtualKeyCode; 28] = [
+    VirtualKeyCode::F,
+    VirtualKeyCode::M,
+    VirtualKeyCode::V,
+    VirtualKeyCode::O,
+    VirtualKeyCode::B,
+    VirtualKeyCode::L,
+    VirtualKeyCode::C,
+    VirtualKeyCode::F1,
+    VirtualKeyCode::F2,
+    VirtualKeyCode::F3,
+    VirtualKeyCode::T,
+    VirtualKeyCode::G,
+    VirtualKeyCode::P,
+    VirtualKeyCode::F5,
+    VirtualKeyCode::F6,
+    VirtualKeyCode::K,
+    VirtualKeyCode::F12,
+    VirtualKeyCode::Y,
+    VirtualKeyCode::F4,
+    VirtualKeyCode::F7,
+    VirtualKeyCode::X,
+    VirtualKeyCode::N,
+    VirtualKeyCode::H,
+    VirtualKeyCode::J,
+    VirtualKeyCode::U,
+    VirtualKeyCode::Z,
+    VirtualKeyCode::R,
+    VirtualKeyCode::F9,
+];
+
+/// Where F5 saves the scene to, and F6 loads it from.
+const SCENE_PATH: &str = "scene.json";
+
+/// Where F9 writes a snapshot of the render state to.
+const RENDER_STATE_PATH: &str = "render_state.txt";
+
+/// Times a lost surface is reconfigured before giving up.
+const MAX_RECONFIGURE_ATTEMPTS: u32 = 3;
+
+/// What's drawn behind the scene.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SkyMode {
+    /// The skybox's cubemap, when one was loaded.
+    Cubemap,
+    /// Scattered sunlight, from the direction of the key light.
+    Procedural,
+}
+
+/// How the triangles of the scene are rasterized.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum WireframeMode {
+    Fill,
+    Line,
+}
+
+impl WireframeMode {
+    fn toggled(self) -> Self {
+        match self {
+            WireframeMode::Fill => WireframeMode::Line,
+            WireframeMode::Line => WireframeMode::Fill,
+        }
+    }
+
+    fn polygon_mode(self) -> wgpu::PolygonMode {
+        match self {
+            WireframeMode::Fill => wgpu::PolygonMode::Fill,
+            WireframeMode::Line => wgpu::PolygonMode::Line,
+        }
+    }
+}
+
+const RENDER_PIPELINE_LABEL: &str = "Render Pipeline";
+
+fn render_pipeline_vertex_layouts() -> [wgpu::VertexBufferLayout<'static>; 2] {
+    [
+        Vertex::vertex_buffer_layout(),
+        InstanceData::vertex_buffer_layout(),
+    ]
 }
 
-impl Vertex {
-    fn layout<'a>() -> wgpu::VertexBufferLayout<'a> {
-        wgpu::VertexBufferLayout {
-            // The `array_stride` defines how wide a vertex is. When the shader
-            // goes to read the next vertex, it will skip over `array_stride`
-            // number of bytes. In our case, `array_stride` will probably be 24 bytes.
-            array_stride: std::mem::size_of::<Vertex>() as wgpu::BufferAddress,
-            // Tells the pipeline how often it should move to the next vertex.
-            step_mode: wgpu::VertexStepMode::Vertex,
-            // Vertex attributes describe the individual parts of the vertex.
-            // Generally this is a 1:1 mapping with a struct's fields, which
-            // it is in our case.
-            attributes: &[
-                wgpu::VertexAttribute {
-                    // Defines the offset in bytes that this attribute starts.
-                    // The first attribute is usually zero, and any future
-                    // attributes are the collective size_of the previous
-                    // attributes data.
-                    offset: 0,
-                    // Tells the shader what location to store this attribute at.
-                    // For example `[[location(0)]] x: vec3<f32>` in the vertex
-                    // shader would correspond to the position field of the struct,
-                    // while `[[location(1)]] x: vec3<f32>` would be the color field.
-                    shader_location: 0,
-                    // Format tells the shader the shape of the attribute.
-                    // `Float32x3` corresponds to vec3<f32> in shader code.
-                    format: wgpu::VertexFormat::Float32x3,
+fn render_pipeline_targets(format: wgpu::TextureFormat) -> [wgpu::ColorTargetState; 1] {
+    [wgpu::ColorTargetState {
+        format,
+        blend: Some(wgpu::BlendState::REPLACE),
+        write_mask: wgpu::ColorWrites::ALL,
+    }]
+}
+
+fn render_pipeline_primitive(wireframe: WireframeMode) -> wgpu::PrimitiveState {
+    wgpu::PrimitiveState {
+        cull_mode: Some(wgpu::Face::Back),
+        polygon_mode: wireframe.polygon_mode(),
+        ..Default::default()
+    }
+}
+
+fn create_render_pipeline(
+    device: &wgpu::Device,
+    layout: &wgpu::PipelineLayout,
+    shader: &wgpu::ShaderModule,
+    format: wgpu::TextureFormat,
+    wireframe: WireframeMode,
+    msaa: MsaaConfig,
+) -> (wgpu::RenderPipeline, PipelineState) {
+    let builder = RenderPipelineBuilder::new()
+        .label(RENDER_PIPELINE_LABEL)
+        .vertex_shader(shader, "main")
+        .vertex_layouts(&render_pipeline_vertex_layouts())
+        // Fragment shader is optional in wgpu, but we need it because
+        // we're storing color data to the surface.
+        //
+        // Currently we only need one color target for the surface. We use
+        // the surface's format so that copying to it is easy, and we specify
+        // that the blending should just replace old pixel data with new data.
+        //
+        // We also tell wgpu to write to all colors: red, blue, green, and alpha.
+        .fragment_shader(shader, "main", &render_pipeline_targets(format))
+        .polygon_mode(wireframe.polygon_mode())
+        .depth_stencil(DepthBuffer::depth_stencil_state())
+        .multisample(msaa.count);
+    let pipeline = builder
+        .build(device, layout)
+        .expect("failed to build render pipeline");
+    (pipeline, builder.state())
+}
+
+/// Key of the pipeline [`create_render_pipeline`] builds with the same options.
+fn render_pipeline_key(
+    shader_path: &std::path::Path,
+    source: &str,
+    format: wgpu::TextureFormat,
+    wireframe: WireframeMode,
+    msaa: MsaaConfig,
+) -> PipelineKey {
+    PipelineKey::new(&[(&shader_path.to_string_lossy(), source)])
+        .vertex_layouts(&render_pipeline_vertex_layouts())
+        .targets(&render_pipeline_targets(format))
+        .primitive(render_pipeline_primitive(wireframe))
+        .depth_stencil(&DepthBuffer::depth_stencil_state())
+        .sample_count(msaa.count)
+}
+
+struct State {
+    ctx: GpuContext,
+    render_pipeline_layout: wgpu::PipelineLayout,
+    render_pipeline: Arc<wgpu::RenderPipeline>,
+    // What the scene pipeline was built with, for the render state dumps.
+    render_pipeline_state: PipelineState,
+    // Pipelines built so far, so toggling options back and forth
+    // doesn't rebuild them.
+    pipeline_cache: PipelineCache,
+    // Source of the current shader, kept around so the pipeline
+    // can be rebuilt when the wireframe mode changes.
+    shader_source: String,
+    wireframe: WireframeMode,
+    msaa: MsaaConfig,
+    // Multisampled texture the scene is drawn into, which is then
+    // resolved into the HDR target. `None` when MSAA is off.
+    msaa_framebuffer: Option<wgpu::TextureView>,
+    shader_watcher: ShaderWatcher,
+    shader_compiler: ShaderCompiler,
+    // Errors from the last shader reloads, shown on screen.
+    shader_errors: VecDeque<String>,
+    camera: Camera,
+    camera_buffer: CameraBuffer,
+    camera_controller: CameraController,
+    fly_through: SplineCamera,
+    // Whether the fly-through is moving the camera, rather than the controller.
+    flying: bool,
+    materials: MaterialLibrary,
+    // Draws the materials of glTF scenes.
+    pbr: PbrPipeline,
+    csm: CsmShadowPass,
+    frame_timer: FrameTimer,
+    // Times the phases of each frame on the CPU, logged every
+    // PROFILE_REPORT_INTERVAL frames.
+    profiler: Profiler,
+    profiled_frames: u32,
+    // How long the GPU takes to draw the scene.
+    render_stats: RenderStats,
+    depth_buffer: DepthBuffer,
+    // Only available when the face images are found.
+    skybox: Option<SkyboxPass>,
+    // The procedural sky, along with the lights, so the sun can
+    // follow the time of day. Toggled with R.
+    sky_light: DynamicSkyLight,
+    time_of_day: TimeOfDay,
+    time_of_day_enabled: bool,
+    // Toggled with K.
+    sky_mode: SkyMode,
+    post_process: PostProcessPass,
+    // Glow around bright pixels, toggled with B.
+    bloom: BloomPass,
+    bloom_enabled: bool,
+    // Lights the opaque batches with point lights instead, toggled with L.
+    deferred: DeferredRenderer,
+    deferred_enabled: bool,
+    // Maps the HDR frame to the surface, cycled with T.
+    tone_mapping: ToneMappingPass,
+    // Measures the frame for setting the exposure automatically,
+    // toggled with X.
+    histogram: ComputeHistogram,
+    auto_exposure: bool,
+    // Bind groups made every frame, kept for the next frames so
+    // they don't have to be made again, until the surface is resized.
+    bind_groups: BindGroupAllocator,
+    // Blurs the scene along the camera's movement, toggled with N.
+    velocity: VelocityPass,
+    motion_blur: MotionBlurPass,
+    motion_blur_enabled: bool,
+    // Textures only needed while a pass of the frame runs, handed out
+    // again whenever the surface is resized.
+    transient_textures: TransientTexturePool,
+    transient_handles: HashMap<&'static str, TextureHandle>,
+    // Smooths edges over several jittered frames, toggled with H.
+    taa: TaaPass,
+    taa_enabled: bool,
+    // Darkens creases the ambient light can't reach, toggled with Z.
+    // The normals are worked out by a compute shader, so they're only
+    // there with storage textures.
+    normal_reconstruct: Option<NormalReconstruct>,
+    ssao: SsaoPass,
+    ssao_enabled: bool,
+    // Draws overlays on top of the finished frame.
+    sprite_renderer: SpriteRenderer,
+    // A white texel of the atlas the overlays are drawn from, so
+    // sprites drawn with it take their color.
+    white: SpriteImage,
+    // Only available when the font image is found.
+    text_renderer: Option<TextRenderer>,
+    // Generated textures, shown with the sliders.
+    swatches: (SwatchTextures, TexturedQuadRenderer),
+    // Sliders for tweaking the scene, toggled with F7.
+    debug_ui: DebugUi,
+    // Whether the sliders had the mouse this frame, so clicks on
+    // them don't pick what's behind.
+    ui_has_mouse: bool,
+    // The entities in the scene, and the meshes they're drawn with.
+    world: World,
+    meshes: Vec<Mesh>,
+    lod_meshes: Vec<LodMesh>,
+    // Meshed into `meshes` again whenever it changes.
+    voxels: VoxelGrid,
+    voxel_mesh: MeshHandle,
+    // Heights of the terrain, when the height map is found.
+    #[allow(dead_code)]
+    terrain_heights: Option<HeightMap>,
+    // Saves the world with F5, and loads it back with F6.
+    scene_serializer: SceneSerializer,
+    // Drawable entities grouped into instanced draws, every frame.
+    batches: Vec<DrawBatch>,
+    // The batches' draw calls, recorded again when they change.
+    scene_bundle: Option<wgpu::RenderBundle>,
+    // Culls the batches on the GPU and draws them indirectly instead,
+    // toggled with C. Only there with multi draw indirect support.
+    gpu_culling: Option<IndirectCullPass>,
+    gpu_culling_enabled: bool,
+    // Leaves out the objects hidden behind others last frame,
+    // toggled with U.
+    occlusion: Option<OcclusionQueryPool>,
+    occlusion_culling: bool,
+    particles: ParticleSimulation,
+    sparks: ParticleSystem,
+    point_clouds: PointCloudRenderer,
+    // Outlines the entity last picked with the mouse.
+    outline: WireframeOverlay,
+    selected: Option<EntityId>,
+    // Loaded from the file given with `--points`.
+    point_cloud: Option<PointCloud>,
+    // A column swaying back and forth, bent by its bones.
+    skinned_meshes: SkinnedMeshRenderer,
+    skinned_column: SkinnedMesh,
+    sway: AnimationClip,
+    animation_time: f32,
+    // A sheet of cloth hanging by two corners, simulated on the GPU.
+    cloth: ClothSimulation,
+    cloth_renderer: ClothRenderer,
+    // Debug lines, toggled with G.
+    gizmos: GizmoPass,
+    lines: LineRenderer,
+    // Splits the window into perspective, top, front and right views.
+    multi_viewport: MultiViewportRenderer,
+    multi_viewport_enabled: bool,
+    show_gizmos: bool,
+    // Reflects and refracts the scene over the terrain.
+    water: WaterPass,
+    // Finds out what was clicked on.
+    picking: PickingPass,
+    // Picks against the bounds of the meshes on the CPU instead,
+    // toggled with J.
+    raycast_picking: bool,
+    // What's held down, and what happened since the last frame.
+    input: InputState,
+    screenshot_requested: bool,
+    // Only there when built with the `renderdoc` feature and
+    // RenderDoc could be loaded.
+    renderdoc: Option<RenderDocCapture>,
+    capture_requested: bool,
+}
+
+impl State {
+    async fn new(window: &Window, adapter: Option<&AdapterSelector>, mode: CompatMode) -> Self {
+        let ctx = GpuContext::new(window, adapter, mode).await;
+        let device = &ctx.device;
+
+        let camera = Camera {
+            // Position the camera one unit up and 2 units back.
+            // +z is out of the screen.
+            eye: (0.0, 1.0, 2.0).into(),
+            // Have it look at the origin.
+            target: (0.0, 0.0, 0.0).into(),
+            // Which way is "up".
+            up: cgmath::Vector3::unit_y(),
+            aspect: ctx.config.width as f32 / ctx.config.height as f32,
+            projection: PERSPECTIVE,
+            jitter: [0.0; 2],
+        };
+        let camera_buffer = CameraBuffer::new(device, &camera);
+        let camera_controller = CameraController::new(2.0, 0.005, &camera);
+        let mut fly_through = SplineCamera::new(fly_through_path());
+        fly_through.loop_mode = LoopMode::Loop;
+
+        let lights = vec![
+            // Warm key light from above and in front of the scene.
+            DirectionalLight {
+                direction: [-0.4, -1.0, -0.6],
+                color: SUN_COLOR,
+                ambient: 0.1,
+            },
+            // Dim, cool fill light from the other side.
+            DirectionalLight {
+                direction: [0.6, -0.3, -0.4],
+                color: [0.2, 0.25, 0.35],
+                ambient: 0.0,
+            },
+        ];
+        let light_buffer = LightBuffer::new(device, &lights);
+        let debug_ui = DebugUi::new(
+            SceneSettings {
+                clear_color: [
+                    CLEAR_COLOR.r as f32,
+                    CLEAR_COLOR.g as f32,
+                    CLEAR_COLOR.b as f32,
+                ],
+                light_direction: lights[0].direction,
+                light_intensity: 1.0,
+                bloom_threshold: BLOOM_THRESHOLD,
+                fov: 45.0,
+            },
+            window.scale_factor(),
+        );
+        let csm = CsmShadowPass::new(device, CSM_SPLIT_LAMBDA, SHADOW_DISTANCE);
+        let mut materials = MaterialLibrary::new(device, &ctx.queue);
+
+        // Render Pipeline
+        let shader_source = dithered_transparency::with_dithering(include_str!("shader.wgsl"));
+        let shader = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+            label: Some("Shader"),
+            source: wgpu::ShaderSource::Wgsl(shader_source.as_str().into()),
+        });
+
+        let render_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Render Pipeline Layout"),
+                bind_group_layouts: &[
+                    &camera_buffer.binding().bind_group_layout,
+                    &light_buffer.binding().bind_group_layout,
+                    csm.bind_group_layout(),
+                    materials.bind_group_layout(),
+                ],
+                push_constant_ranges: &[],
+            });
+
+        // Watch the shader's source file, so changes are picked up without
+        // restarting. The initial shader is baked into the binary, so this
+        // only does something when running from the source tree.
+        let shader_watcher =
+            ShaderWatcher::new(concat!(env!("CARGO_MANIFEST_DIR"), "/src/shader.wgsl"));
+
+        let wireframe = WireframeMode::Fill;
+        let msaa = MsaaConfig::default();
+        let mut pipeline_cache = PipelineCache::new();
+        let (render_pipeline, render_pipeline_state) = pipeline_cache.get_or_create(
+            device,
+            render_pipeline_key(
+                shader_watcher.path(),
+                &shader_source,
+                HDR_FORMAT,
+                wireframe,
+                msaa,
+            ),
+            |device| {
+                create_render_pipeline(
+                    device,
+                    &render_pipeline_layout,
+                    &shader,
+                    HDR_FORMAT,
+                    wireframe,
+                    msaa,
+                )
+            },
+        );
+
+        // The materials are all drawn with the scene pipeline, in different
+        // colors. Glass is see-through by leaving out some of its pixels.
+        for (name, color, roughness, blend_mode) in [
+            (
+                DEFAULT_MATERIAL,
+                [1.0, 1.0, 1.0, 1.0],
+                0.5,
+                BlendMode::Opaque,
+            ),
+            ("ground", [0.6, 0.7, 0.6, 1.0], 0.9, BlendMode::Opaque),
+            (
+                GLASS_MATERIAL,
+                [0.6, 0.8, 1.0, 0.4],
+                0.1,
+                BlendMode::Dithered,
+            ),
+        ] {
+            let uniform = MaterialUniform {
+                color,
+                metallic: 0.0,
+                roughness,
+                dithered: (blend_mode == BlendMode::Dithered) as u32,
+                _padding: 0.0,
+            };
+            let bind_group = materials.create_bind_group(device, name, &uniform);
+            materials.register(
+                name,
+                Material {
+                    pipeline: render_pipeline.clone(),
+                    bind_group,
+                    blend_mode,
+                },
+            );
+        }
+
+        let pbr = PbrPipeline::new(
+            device,
+            &camera_buffer.binding().bind_group_layout,
+            &light_buffer.binding().bind_group_layout,
+            csm.bind_group_layout(),
+            HDR_FORMAT,
+            msaa,
+        );
+
+        let depth_buffer = DepthBuffer::new(device, ctx.config.width, ctx.config.height);
+        let render_stats = RenderStats::new(device, &ctx.queue);
+        let msaa_framebuffer =
+            msaa.create_framebuffer(device, ctx.config.width, ctx.config.height, HDR_FORMAT);
+
+        // A panorama is used when there is one, otherwise the cubemap faces.
+        let res_dir = std::path::Path::new(concat!(env!("CARGO_MANIFEST_DIR"), "/res"));
+        let panorama_path = res_dir.join("skybox.hdr");
+        let skybox_dir = res_dir.join("skybox");
+        let skybox = if panorama_path.is_file() {
+            std::fs::read(&panorama_path)
+                .map_err(image::ImageError::IoError)
+                .and_then(|bytes| {
+                    SkyboxPass::from_equirectangular(
+                        device, &ctx.queue, HDR_FORMAT, &bytes, &camera, msaa,
+                    )
+                })
+                .map(Some)
+        } else if skybox_dir.is_dir() {
+            let face_paths = SKYBOX_FACES.map(|name| skybox_dir.join(name));
+            SkyboxPass::new(device, &ctx.queue, HDR_FORMAT, face_paths, &camera, msaa).map(Some)
+        } else {
+            log::info!("no skybox found in {}", res_dir.display());
+            Ok(None)
+        };
+        let skybox = skybox.unwrap_or_else(|err| {
+            log::warn!("failed to load skybox: {}", err);
+            None
+        });
+        let procedural_sky = ProceduralSky::new(
+            device,
+            SkyConfig {
+                sun_direction: sun_direction(&lights),
+                ..SkyConfig::default()
+            },
+            HDR_FORMAT,
+            msaa,
+        );
+        let sky_light =
+            DynamicSkyLight::new(procedural_sky, lights, light_buffer, SKY_LIGHT_INTENSITY);
+        // The procedural sky fills in when there's no skybox.
+        let sky_mode = if skybox.is_some() {
+            SkyMode::Cubemap
+        } else {
+            SkyMode::Procedural
+        };
+
+        // Image based lighting precomputed from the sky, for PBR materials.
+        let environment = match &skybox {
+            Some(skybox) if IblPrecompute::is_supported(&ctx.adapter, device) => {
+                let maps = IblPrecompute::run(device, &ctx.queue, skybox.cubemap());
+                PbrEnvironment::new(device, maps)
+            }
+            Some(_) => {
+                log::warn!("storage textures aren't supported, skipping image based lighting");
+                PbrEnvironment::dark(device, &ctx.queue)
+            }
+            None => PbrEnvironment::dark(device, &ctx.queue),
+        };
+
+        let post_process =
+            PostProcessPass::new(device, &ctx.config, PostProcessEffect::Passthrough)
+                .expect("failed to create post process pass");
+        let bloom = BloomPass::new(
+            device,
+            &ctx.config,
+            HDR_FORMAT,
+            BLOOM_THRESHOLD,
+            BLOOM_INTENSITY,
+        );
+        let tone_mapping = ToneMappingPass::new(
+            device,
+            &ctx.config,
+            ctx.is_srgb,
+            ToneMapOperator::default(),
+            EXPOSURE,
+        );
+        let histogram = ComputeHistogram::new(device, HISTOGRAM_BINS);
+        let velocity = VelocityPass::new(device, &ctx.config);
+        let normal_reconstruct = NormalReconstruct::is_supported(&ctx.adapter, device)
+            .then(|| NormalReconstruct::new(device, &ctx.config));
+        let ssao = SsaoPass::new(
+            device,
+            &ctx.queue,
+            &ctx.config,
+            SSAO_KERNEL_SIZE,
+            SSAO_RADIUS,
+            SSAO_BIAS,
+        );
+        let motion_blur = MotionBlurPass::new(device, MOTION_BLUR_SAMPLES, MOTION_BLUR_STRENGTH);
+        let taa = TaaPass::new(device, &ctx.config, TAA_FEEDBACK);
+
+        let mut sprite_renderer = SpriteRenderer::new(device, &ctx.config);
+        // The images the overlays are drawn with, packed together so
+        // they're all drawn in one batch.
+        let ui_atlas = TextureAtlas::builder()
+            .add_rgba("white", 1, 1, vec![255; 4])
+            .build(device, &ctx.queue)
+            .expect("failed to pack the UI atlas");
+        let ui_texture = sprite_renderer.add_texture(device, ui_atlas.texture());
+        let white = SpriteImage {
+            texture: ui_texture,
+            uv_rect: ui_atlas.regions()["white"].center().to_array(),
+        };
+
+        let text_renderer = load_text_renderer(&ctx);
+        let swatches = load_swatches(&ctx);
+
+        let (ground_vertices, ground_indices) = primitives::quad(12.0, 12.0);
+        let mut meshes = vec![
+            Mesh::upload(device, VERTICES, INDICES).with_bounds(Aabb::from_vertices(VERTICES)),
+            Mesh::upload(device, &ground_vertices, &ground_indices)
+                .with_bounds(Aabb::from_vertices(&ground_vertices)),
+        ];
+        let shape_mesh = MeshHandle(0);
+        let ground_mesh = MeshHandle(1);
+
+        let mut world = World::new();
+        for z in 0..NUM_INSTANCES_PER_ROW {
+            for x in 0..NUM_INSTANCES_PER_ROW {
+                // Center the grid around the origin.
+                let offset = (NUM_INSTANCES_PER_ROW - 1) as f32 * INSTANCE_SPACING * 0.5;
+                let position = [
+                    x as f32 * INSTANCE_SPACING - offset,
+                    0.0,
+                    z as f32 * INSTANCE_SPACING - offset,
+                ];
+
+                let entity = world.spawn();
+                world.insert(entity, Transform::from_translation(position));
+                world.insert(entity, shape_mesh);
+                world.insert(entity, MaterialHandle(DEFAULT_MATERIAL.to_string()));
+            }
+        }
+
+        // A floor for the shapes to cast shadows on. The quad
+        // faces +Z, so it's tipped over to face up.
+        let mut ground_transform =
+            Transform::from_euler_xyz(-std::f32::consts::FRAC_PI_2, 0.0, 0.0);
+        ground_transform.translation = [0.0, -0.6, 0.0];
+        let ground = world.spawn();
+        world.insert(ground, ground_transform);
+        world.insert(ground, ground_mesh);
+        world.insert(ground, MaterialHandle("ground".to_string()));
+
+        // Scene files name the meshes, since they aren't loaded from files.
+        let mut scene_serializer = SceneSerializer::new();
+        scene_serializer.register_mesh("shape", MeshAsset::Mesh(shape_mesh));
+        scene_serializer.register_mesh("ground", MeshAsset::Mesh(ground_mesh));
+
+        let terrain_heights = load_terrain(device).map(|Terrain { mesh, height_map }| {
+            let terrain_mesh = MeshHandle(meshes.len());
+            scene_serializer.register_mesh("terrain", MeshAsset::Mesh(terrain_mesh));
+            let entity = world.spawn();
+            world.insert(entity, Transform::from_translation(TERRAIN_POSITION));
+            world.insert(entity, terrain_mesh);
+            world.insert(entity, MaterialHandle("ground".to_string()));
+            meshes.push(mesh);
+            height_map
+        });
+
+        let mut voxels = voxel_steps();
+        let voxel_mesh = MeshHandle(meshes.len());
+        meshes.push(voxels.remesh(device).expect("new grids need meshing"));
+        scene_serializer.register_mesh("voxels", MeshAsset::Mesh(voxel_mesh));
+        let entity = world.spawn();
+        let mut voxel_transform = Transform::from_translation(VOXEL_POSITION);
+        voxel_transform.scale = [VOXEL_SIZE; 3];
+        world.insert(entity, voxel_transform);
+        world.insert(entity, voxel_mesh);
+        world.insert(entity, MaterialHandle(DEFAULT_MATERIAL.to_string()));
+
+        if let Some((path, scene)) = load_gltf_scene(device, &ctx.queue, &environment) {
+            let first_mesh = meshes.len();
+            for i in 0..scene.meshes.len() {
+                let name = format!("{}#{}", path, i);
+                scene_serializer.register_mesh(&name, MeshAsset::Mesh(MeshHandle(first_mesh + i)));
+            }
+            meshes.extend(scene.meshes);
+            let material_names: Vec<String> = (0..scene.materials.len())
+                .map(|i| format!("{}#material{}", path, i))
+                .collect();
+            for (name, material) in material_names.iter().zip(scene.materials) {
+                materials.register(
+                    name,
+                    Material {
+                        pipeline: pbr.pipeline().clone(),
+                        bind_group: material.bind_group,
+                        blend_mode: BlendMode::Opaque,
+                    },
+                );
+            }
+            // Primitives without a material are drawn with the scene's.
+            for gltf_entity in scene.entities {
+                let material = gltf_entity
+                    .material
+                    .map_or(DEFAULT_MATERIAL, |i| &material_names[i]);
+                let entity = world.spawn();
+                world.insert(entity, gltf_entity.transform);
+                world.insert(entity, MeshHandle(first_mesh + gltf_entity.mesh));
+                world.insert(entity, MaterialHandle(material.to_string()));
+            }
+        }
+
+        let mut sphere_lod = LodMesh::new(Aabb {
+            min: [-0.5; 3],
+            max: [0.5; 3],
+        });
+        let (sphere_vertices, sphere_indices) =
+            primitives::sphere(0.5, LOD_SPHERE_DETAIL, LOD_SPHERE_DETAIL);
+        for (distance, ratio) in LOD_SPHERE_LEVELS {
+            let (vertices, indices) =
+                MeshSimplifier::simplify(&sphere_vertices, &sphere_indices, ratio);
+            sphere_lod.add_level(distance, Mesh::upload(device, &vertices, &indices));
+        }
+        let lod_meshes = vec![sphere_lod];
+        scene_serializer.register_mesh("sphere", MeshAsset::Lod(LodHandle(0)));
+        for i in 0..NUM_LOD_SPHERES {
+            let entity = world.spawn();
+            let z = -(i as f32 + 2.0) * LOD_SPHERE_SPACING;
+            world.insert(entity, Transform::from_translation([0.0, 0.0, z]));
+            world.insert(entity, LodHandle(0));
+            world.insert(entity, MaterialHandle(GLASS_MATERIAL.to_string()));
+        }
+        lod::update_levels(&mut world, &lod_meshes, &camera);
+
+        let mut batches = Vec::new();
+        scene::update_batches(
+            device,
+            &ctx.queue,
+            &world,
+            &Frustum::from_view_proj(camera.build_view_projection_matrix()),
+            &meshes,
+            &lod_meshes,
+            None,
+            &mut batches,
+        );
+
+        let particles = ParticleSimulation::new(
+            device,
+            &camera_buffer.binding().bind_group_layout,
+            HDR_FORMAT,
+            msaa,
+            NUM_PARTICLES,
+        );
+        let sparks = ParticleSystem::new(
+            device,
+            &camera_buffer.binding().bind_group_layout,
+            HDR_FORMAT,
+            msaa,
+            NUM_SPARKS,
+            EmitterConfig {
+                position: [4.5, -0.6, -4.5],
+                direction: [0.0, 1.0, 0.0],
+                spread: 0.4,
+                speed: 3.0,
+                gravity: [0.0, -4.0, 0.0],
+                lifetime: 1.5,
+                start_color: [1.0, 0.8, 0.3, 1.0],
+                end_color: [1.0, 0.2, 0.0, 0.0],
+                size: 0.08,
+            },
+        );
+
+        let point_clouds = PointCloudRenderer::new(
+            device,
+            &camera_buffer.binding().bind_group_layout,
+            HDR_FORMAT,
+            msaa,
+        );
+        // `--points scan.xyz` shows a point cloud.
+        let point_cloud = load_point_cloud(device, &ctx.queue);
+        let skinned_meshes = SkinnedMeshRenderer::new(
+            device,
+            &camera_buffer.binding().bind_group_layout,
+            HDR_FORMAT,
+            msaa,
+        );
+        let (vertices, indices, skeleton) = skeletal_animation::skinned_column(
+            SKINNED_COLUMN_POSITION,
+            0.3,
+            3.0,
+            SKINNED_COLUMN_BONES,
+            24,
+            16,
+        );
+        let skinned_column = SkinnedMesh::new(device, &vertices, &indices, skeleton);
+        let mut cloth = ClothSimulation::new(device, CLOTH_SIZE, CLOTH_SIZE, CLOTH_STIFFNESS);
+        cloth.set_origin(CLOTH_POSITION);
+        let cloth_renderer = ClothRenderer::new(
+            device,
+            &camera_buffer.binding().bind_group_layout,
+            &cloth,
+            HDR_FORMAT,
+            msaa,
+        );
+        let outline = WireframeOverlay::new(
+            device,
+            &camera_buffer.binding().bind_group_layout,
+            HDR_FORMAT,
+            msaa,
+            OUTLINE_COLOR,
+            OUTLINE_SCALE,
+        );
+
+        let picking = PickingPass::new(
+            device,
+            &camera_buffer.binding().bind_group_layout,
+            ctx.config.width,
+            ctx.config.height,
+        );
+
+        let gizmos = GizmoPass::new(
+            device,
+            &camera_buffer.binding().bind_group_layout,
+            HDR_FORMAT,
+        );
+        let lines = LineRenderer::new(
+            device,
+            &camera_buffer.binding().bind_group_layout,
+            &ctx.config,
+            HDR_FORMAT,
+            msaa,
+        );
+        let multi_viewport = MultiViewportRenderer::new(device, &ctx.config);
+
+        // The reflection and refraction aren't multisampled, so they
+        // get a single sampled copy of the scene pipeline.
+        let (water_scene_pipeline, _) = create_render_pipeline(
+            device,
+            &render_pipeline_layout,
+            &shader,
+            HDR_FORMAT,
+            WireframeMode::Fill,
+            MsaaConfig::default(),
+        );
+        let water = WaterPass::new(
+            device,
+            &ctx.config,
+            &camera,
+            &camera_buffer.binding().bind_group_layout,
+            water_scene_pipeline,
+            msaa,
+            WaterConfig {
+                center: [TERRAIN_POSITION[0], TERRAIN_POSITION[2]],
+                size: TERRAIN_WIDTH,
+                height: WATER_HEIGHT,
+                ..Default::default()
+            },
+        );
+
+        let gpu_culling = ctx
+            .supports(IndirectCullPass::FEATURES)
+            .then(|| IndirectCullPass::new(device, MAX_GPU_CULLED_OBJECTS));
+        let occlusion = OcclusionQueryPool::is_supported(&ctx.adapter, device)
+            .then(|| OcclusionQueryPool::new(device, MAX_OCCLUSION_QUERIES));
+
+        let mut deferred = DeferredRenderer::new(
+            device,
+            ctx.config.width,
+            ctx.config.height,
+            &camera_buffer.binding().bind_group_layout,
+            materials.bind_group_layout(),
+            HDR_FORMAT,
+        );
+        deferred.set_lights(&point_lights());
+
+        let mut state = State {
+            ctx,
+            render_pipeline_layout,
+            render_pipeline,
+            render_pipeline_state,
+            pipeline_cache,
+            shader_source,
+            wireframe,
+            msaa,
+            msaa_framebuffer,
+            shader_watcher,
+            shader_compiler: ShaderCompiler::new(),
+            shader_errors: VecDeque::new(),
+            camera,
+            camera_buffer,
+            camera_controller,
+            fly_through,
+            flying: false,
+            materials,
+            pbr,
+            csm,
+            frame_timer: FrameTimer::new(),
+            profiler: Profiler::new(),
+            profiled_frames: 0,
+            render_stats,
+            depth_buffer,
+            skybox,
+            sky_light,
+            time_of_day: TimeOfDay::new(12.0),
+            time_of_day_enabled: false,
+            sky_mode,
+            post_process,
+            bloom,
+            bloom_enabled: true,
+            deferred,
+            deferred_enabled: false,
+            tone_mapping,
+            histogram,
+            auto_exposure: true,
+            bind_groups: BindGroupAllocator::new(),
+            velocity,
+            motion_blur,
+            motion_blur_enabled: false,
+            transient_textures: TransientTexturePool::new(),
+            transient_handles: HashMap::new(),
+            taa,
+            taa_enabled: false,
+            normal_reconstruct,
+            ssao,
+            ssao_enabled: false,
+            sprite_renderer,
+            white,
+            text_renderer,
+            swatches,
+            debug_ui,
+            ui_has_mouse: false,
+            world,
+            meshes,
+            lod_meshes,
+            voxels,
+            voxel_mesh,
+            terrain_heights,
+            scene_serializer,
+            batches,
+            scene_bundle: None,
+            gpu_culling,
+            gpu_culling_enabled: false,
+            occlusion,
+            occlusion_culling: false,
+            particles,
+            sparks,
+            point_clouds,
+            point_cloud,
+            skinned_meshes,
+            skinned_column,
+            sway: sway_clip(SKINNED_COLUMN_BONES),
+            animation_time: 0.0,
+            cloth,
+            cloth_renderer,
+            outline,
+            selected: None,
+            gizmos,
+            lines,
+            multi_viewport,
+            multi_viewport_enabled: false,
+            water,
+            show_gizmos: false,
+            picking,
+            raycast_picking: false,
+            input: InputState::new(),
+            screenshot_requested: false,
+            renderdoc: RenderDocCapture::try_init(),
+            capture_requested: false,
+        };
+        state.allocate_transient_textures();
+        state
+    }
+
+    /// Resizes the surface and everything drawn at its size.
+    ///
+    /// Fails when the surface can't be reconfigured, leaving
+    /// the rest as it was.
+    fn resize(
+        &mut self,
+        new_size: winit::dpi::PhysicalSize<u32>,
+    ) -> Result<(), wgpu::SurfaceError> {
+        // Only the size changes, so the cached pipelines are still
+        // good. They'd need rebuilding if the surface format changed.
+        self.ctx.resize(new_size)?;
+        self.camera
+            .fit_to_viewport(self.ctx.config.width, self.ctx.config.height);
+        self.depth_buffer.resize(
+            &self.ctx.device,
+            self.ctx.config.width,
+            self.ctx.config.height,
+        );
+        self.msaa_framebuffer = self.msaa.create_framebuffer(
+            &self.ctx.device,
+            self.ctx.config.width,
+            self.ctx.config.height,
+            HDR_FORMAT,
+        );
+        self.post_process.resize(
+            &self.ctx.device,
+            self.ctx.config.width,
+            self.ctx.config.height,
+        );
+        self.bloom.resize(
+            &self.ctx.device,
+            self.ctx.config.width,
+            self.ctx.config.height,
+        );
+        self.velocity.resize(
+            &self.ctx.device,
+            self.ctx.config.width,
+            self.ctx.config.height,
+        );
+        if let Some(normal_reconstruct) = &mut self.normal_reconstruct {
+            normal_reconstruct.resize(
+                &self.ctx.device,
+                self.ctx.config.width,
+                self.ctx.config.height,
+            );
+        }
+        self.ssao.resize(
+            &self.ctx.device,
+            self.ctx.config.width,
+            self.ctx.config.height,
+        );
+        self.transient_textures.clear();
+        self.allocate_transient_textures();
+        // Bind groups made with the old targets would never be reused.
+        self.bind_groups.clear();
+        self.taa.resize(
+            &self.ctx.device,
+            &self.ctx.queue,
+            self.ctx.config.width,
+            self.ctx.config.height,
+        );
+        self.tone_mapping.resize(
+            &self.ctx.device,
+            self.ctx.config.width,
+            self.ctx.config.height,
+        );
+        self.water.resize(
+            &self.ctx.device,
+            self.ctx.config.width,
+            self.ctx.config.height,
+        );
+        self.deferred.resize(
+            &self.ctx.device,
+            self.ctx.config.width,
+            self.ctx.config.height,
+        );
+        self.picking.resize(
+            &self.ctx.device,
+            self.ctx.config.width,
+            self.ctx.config.height,
+        );
+        self.sprite_renderer.resize(
+            &self.ctx.queue,
+            self.ctx.config.width,
+            self.ctx.config.height,
+        );
+        self.lines.resize(
+            &self.ctx.queue,
+            self.ctx.config.width,
+            self.ctx.config.height,
+        );
+        self.multi_viewport.resize(winit::dpi::PhysicalSize::new(
+            self.ctx.config.width,
+            self.ctx.config.height,
+        ));
+        if let Some(text_renderer) = &self.text_renderer {
+            text_renderer.resize(
+                &self.ctx.queue,
+                self.ctx.config.width,
+                self.ctx.config.height,
+            );
+        }
+        Ok(())
+    }
+
+    /// Builds the render pipeline from the given shader source.
+    ///
+    /// Errors are returned instead of panicking, so the caller
+    /// can keep using the previous pipeline.
+    ///
+    /// Pipelines built before are taken from the cache.
+    fn build_pipeline(
+        &mut self,
+        source: &str,
+        wireframe: WireframeMode,
+        msaa: MsaaConfig,
+    ) -> Result<(Arc<wgpu::RenderPipeline>, PipelineState), Vec<wgpu::Error>> {
+        let layout = &self.render_pipeline_layout;
+        let format = HDR_FORMAT;
+        let key = render_pipeline_key(self.shader_watcher.path(), source, format, wireframe, msaa);
+        self.pipeline_cache
+            .get_or_try_create(&self.ctx.device, key, |device| {
+                shader_watcher::try_build_pipeline(device, "Shader", source, |shader| {
+                    create_render_pipeline(device, layout, shader, format, wireframe, msaa)
+                })
+            })
+    }
+
+    /// Rebuilds the render pipeline when the shader file changes on disk.
+    ///
+    /// The old pipeline is kept if the new shader fails to compile.
+    ///
+    /// The new shader is checked in the background, and the old
+    /// pipeline is kept until it's done.
+    fn reload_shader(&mut self) {
+        if let Some(source) = self.shader_watcher.poll() {
+            self.shader_compiler
+                .submit(dithered_transparency::with_dithering(&source));
+        }
+
+        match self.shader_compiler.poll() {
+            Some(Ok(source)) => match self.build_pipeline(&source, self.wireframe, self.msaa) {
+                Ok(render_pipeline) => {
+                    log::info!("reloaded shader {}", self.shader_watcher.path().display());
+                    self.set_render_pipeline(render_pipeline);
+                    self.shader_source = source;
+                    self.shader_errors.clear();
+                }
+                Err(errors) => {
+                    for err in errors {
+                        self.push_shader_error(err.to_string());
+                    }
+                }
+            },
+            Some(Err(err)) => self.push_shader_error(err),
+            None => {}
+        }
+    }
+
+    fn push_shader_error(&mut self, err: String) {
+        eprintln!("{}", err);
+        if self.shader_errors.len() == MAX_SHADER_ERRORS {
+            self.shader_errors.pop_front();
+        }
+        self.shader_errors.push_back(err);
+    }
+
+    /// Replaces the scene pipeline, along with the materials using it.
+    fn set_render_pipeline(
+        &mut self,
+        (render_pipeline, state): (Arc<wgpu::RenderPipeline>, PipelineState),
+    ) {
+        self.materials
+            .replace_pipeline(&self.render_pipeline, &render_pipeline);
+        self.render_pipeline = render_pipeline;
+        self.render_pipeline_state = state;
+        // The bundle still holds the old pipeline.
+        self.scene_bundle = None;
+    }
+
+    /// Switches between filled and wireframe rendering.
+    fn toggle_wireframe(&mut self) {
+        if !self.ctx.supports(wgpu::Features::POLYGON_MODE_LINE) {
+            log::warn!("wireframe rendering is not supported by this adapter");
+            return;
+        }
+
+        let wireframe = self.wireframe.toggled();
+        let source = self.shader_source.clone();
+        match self.build_pipeline(&source, wireframe, self.msaa) {
+            Ok(render_pipeline) => {
+                self.set_render_pipeline(render_pipeline);
+                self.wireframe = wireframe;
+            }
+            Err(errors) => {
+                for err in errors {
+                    eprintln!("{}", err);
+                }
+            }
+        }
+    }
+
+    /// Changes the number of samples per pixel, recreating the
+    /// pipeline and the textures that depend on it.
+    fn set_msaa(&mut self, count: u32) {
+        let msaa = MsaaConfig::new(count);
+        if !msaa.is_supported(&self.ctx.adapter, HDR_FORMAT) {
+            log::warn!("{}x MSAA is not supported by this adapter", count);
+            return;
+        }
+
+        let source = self.shader_source.clone();
+        match self.build_pipeline(&source, self.wireframe, msaa) {
+            Ok(render_pipeline) => {
+                self.set_render_pipeline(render_pipeline);
+                let device = &self.ctx.device;
+                let (width, height) = (self.ctx.config.width, self.ctx.config.height);
+                self.msaa = msaa;
+                self.msaa_framebuffer = msaa.create_framebuffer(device, width, height, HDR_FORMAT);
+                // Every attachment in a pass needs the same sample count.
+                self.depth_buffer = DepthBuffer::new_multisampled(device, width, height, count);
+                if let Some(skybox) = &mut self.skybox {
+                    skybox.set_msaa(device, msaa);
+                }
+                let old_pbr = self.pbr.pipeline().clone();
+                self.pbr.set_msaa(device, msaa);
+                self.materials
+                    .replace_pipeline(&old_pbr, self.pbr.pipeline());
+                self.sky_light.sky_mut().set_msaa(device, msaa);
+                self.particles.set_msaa(device, msaa);
+                self.sparks.set_msaa(device, msaa);
+                self.point_clouds.set_msaa(device, msaa);
+                self.skinned_meshes.set_msaa(device, msaa);
+                self.cloth_renderer.set_msaa(device, msaa);
+                self.outline.set_msaa(device, msaa);
+                self.lines.set_msaa(device, msaa);
+                self.water.set_msaa(device, msaa);
+                if let Some(occlusion) = &mut self.occlusion {
+                    occlusion.set_msaa(device, msaa);
+                }
+                log::info!("MSAA set to {}x", count);
+            }
+            Err(errors) => {
+                for err in errors {
+                    eprintln!("{}", err);
+                }
+            }
+        }
+    }
+
+    /// Steps through the supported sample counts.
+    fn cycle_msaa(&mut self) {
+        let counts = MsaaConfig::SAMPLE_COUNTS;
+        let current = counts
+            .iter()
+            .position(|c| *c == self.msaa.count)
+            .unwrap_or(0);
+        let next = (1..=counts.len())
+            .map(|step| counts[(current + step) % counts.len()])
+            .find(|count| MsaaConfig::new(*count).is_supported(&self.ctx.adapter, HDR_FORMAT));
+
+        if let Some(count) = next {
+            self.set_msaa(count);
+        }
+    }
+
+    /// Reconfigures the surface to present frames differently. Modes
+    /// the surface doesn't support fall back to `Fifo`.
+    fn set_swapchain_config(&mut self, swapchain: SwapchainConfig) {
+        match self.ctx.set_swapchain_config(swapchain) {
+            Ok(()) => log::info!(
+                "presenting with {:?}",
+                self.ctx.swapchain_config().present_mode
+            ),
+            Err(err) => eprintln!("failed to reconfigure surface: {}", err),
+        }
+    }
+
+    /// Steps through the built-in post-processing effects.
+    fn cycle_post_process_effect(&mut self) {
+        let effect = self.post_process.effect().next();
+        log::info!("post process effect: {:?}", effect);
+        if let Err(err) = self.post_process.set_effect(&self.ctx.device, effect) {
+            eprintln!("{}", err);
+        }
+    }
+
+    /// Switches the camera between perspective and orthographic projection.
+    fn toggle_projection(&mut self) {
+        self.camera.projection = match self.camera.projection {
+            CameraProjection::Perspective { .. } => CameraProjection::Orthographic {
+                left: -ORTHOGRAPHIC_WIDTH * 0.5,
+                right: ORTHOGRAPHIC_WIDTH * 0.5,
+                // Fitted to the viewport below.
+                bottom: 0.0,
+                top: 0.0,
+                near: -100.0,
+                far: 100.0,
+            },
+            CameraProjection::Orthographic { .. } => PERSPECTIVE,
+        };
+        self.camera
+            .fit_to_viewport(self.ctx.config.width, self.ctx.config.height);
+    }
+
+    /// Grabs the cursor and hides it, so the mouse can be used
+    /// to look around without leaving the window.
+    fn toggle_cursor_grab(&mut self, window: &Window) {
+        let grab = !self.camera_controller.mouse_look;
+        if let Err(err) = window.set_cursor_grab(grab) {
+            log::warn!("failed to grab cursor: {}", err);
+            return;
+        }
+        window.set_cursor_visible(!grab);
+        self.camera_controller.mouse_look = grab;
+    }
+
+    /// Records keyboard and mouse input for the next frame. Returns
+    /// `true` when something in the scene is bound to it.
+    fn input(&mut self, event: &WindowEvent) -> bool {
+        if !self.input.update(event) {
+            return false;
+        }
+
+        match event {
+            WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput {
+                        virtual_keycode: Some(keycode),
+                        ..
+                    },
+                ..
+            } => ACTION_KEYS.contains(keycode) || CameraController::KEYS.contains(keycode),
+            WindowEvent::MouseInput {
+                button: MouseButton::Left | MouseButton::Right,
+                ..
+            } => true,
+            _ => false,
+        }
+    }
+
+    /// Runs the actions bound to the keys and buttons pressed
+    /// since the last frame.
+    fn handle_actions(&mut self) {
+        for keycode in ACTION_KEYS {
+            if self.input.is_key_just_pressed(keycode) {
+                self.key_action(keycode);
+            }
+        }
+        if self.input.is_mouse_just_pressed(MouseButton::Left) && !self.ui_has_mouse {
+            self.pick();
+        }
+    }
+
+    /// What each of the [`ACTION_KEYS`] does.
+    fn key_action(&mut self, keycode: VirtualKeyCode) {
+        match keycode {
+            VirtualKeyCode::F => self.toggle_wireframe(),
+            VirtualKeyCode::M => self.cycle_msaa(),
+            VirtualKeyCode::V => self.cycle_post_process_effect(),
+            VirtualKeyCode::O => self.toggle_projection(),
+            VirtualKeyCode::B => self.bloom_enabled = !self.bloom_enabled,
+            VirtualKeyCode::L => {
+                self.deferred_enabled = !self.deferred_enabled;
+                log::info!(
+                    "{} rendering",
+                    if self.deferred_enabled {
+                        "deferred"
+                    } else {
+                        "forward"
+                    }
+                );
+            }
+            VirtualKeyCode::C => {
+                if self.gpu_culling.is_some() {
+                    self.gpu_culling_enabled = !self.gpu_culling_enabled;
+                    log::info!(
+                        "culling on the {}",
+                        if self.gpu_culling_enabled {
+                            "GPU"
+                        } else {
+                            "CPU"
+                        }
+                    );
+                } else {
+                    log::warn!("GPU culling needs multi draw indirect support");
+                }
+            }
+            VirtualKeyCode::F1 => {
+                self.set_swapchain_config(SwapchainConfig::new(wgpu::PresentMode::Fifo))
+            }
+            VirtualKeyCode::F2 => {
+                self.set_swapchain_config(SwapchainConfig::new(wgpu::PresentMode::Mailbox))
+            }
+            VirtualKeyCode::F3 => {
+                self.set_swapchain_config(SwapchainConfig::new(wgpu::PresentMode::Immediate))
+            }
+            VirtualKeyCode::T => {
+                let operator = self.tone_mapping.operator().next();
+                self.tone_mapping.set_operator(&self.ctx.queue, operator);
+                log::info!("tone mapping with {:?}", operator);
+            }
+            VirtualKeyCode::G => self.show_gizmos = !self.show_gizmos,
+            VirtualKeyCode::P => {
+                // Taken after the next frame is rendered.
+                self.screenshot_requested = true;
+            }
+            VirtualKeyCode::F5 => self.save_scene(),
+            VirtualKeyCode::F6 => self.load_scene(),
+            VirtualKeyCode::K => self.toggle_sky_mode(),
+            VirtualKeyCode::F12 => {
+                if self.renderdoc.is_some() {
+                    // Captures the whole of the next frame.
+                    self.capture_requested = true;
+                } else {
+                    log::warn!("frame captures need RenderDoc and the `renderdoc` feature");
+                }
+            }
+            VirtualKeyCode::Y => self.toggle_fly_through(),
+            VirtualKeyCode::F4 => self.multi_viewport_enabled = !self.multi_viewport_enabled,
+            VirtualKeyCode::F7 => self.debug_ui.toggle(),
+            VirtualKeyCode::F9 => self.write_render_state(),
+            VirtualKeyCode::X => {
+                self.auto_exposure = !self.auto_exposure;
+                if !self.auto_exposure {
+                    self.tone_mapping.set_exposure(&self.ctx.queue, EXPOSURE);
+                }
+                log::info!(
+                    "auto-exposure {}",
+                    if self.auto_exposure { "on" } else { "off" }
+                );
+            }
+            VirtualKeyCode::J => {
+                self.raycast_picking = !self.raycast_picking;
+                log::info!(
+                    "picking with {}",
+                    if self.raycast_picking {
+                        "rays"
+                    } else {
+                        "the GPU"
+                    }
+                );
+            }
+            VirtualKeyCode::N => self.motion_blur_enabled = !self.motion_blur_enabled,
+            VirtualKeyCode::H => {
+                self.taa_enabled = !self.taa_enabled;
+                if self.taa_enabled {
+                    // Whatever's left in the history is from when it was last on.
+                    self.taa.reset_history(&self.ctx.queue);
+                }
+                log::info!("TAA {}", if self.taa_enabled { "on" } else { "off" });
+            }
+            VirtualKeyCode::Z => {
+                if self.normal_reconstruct.is_some() {
+                    self.ssao_enabled = !self.ssao_enabled;
+                    log::info!("SSAO {}", if self.ssao_enabled { "on" } else { "off" });
+                } else {
+                    log::warn!("SSAO needs storage textures for its normals");
+                }
+            }
+            VirtualKeyCode::R => {
+                self.time_of_day_enabled = !self.time_of_day_enabled;
+                if !self.time_of_day_enabled {
+                    // The sun goes back to where the debug UI has it.
+                    let direction = self.debug_ui.settings.light_direction.map(|d| -d);
+                    self.sky_light
+                        .sky_mut()
+                        .update_sun(&self.ctx.queue, direction);
+                }
+                log::info!(
+                    "time of day {}",
+                    if self.time_of_day_enabled {
+                        "on"
+                    } else {
+                        "off"
+                    }
+                );
+            }
+            VirtualKeyCode::U => {
+                if let Some(occlusion) = &mut self.occlusion {
+                    self.occlusion_culling = !self.occlusion_culling;
+                    if !self.occlusion_culling {
+                        // Everything hidden so far would stay culled otherwise.
+                        occlusion.clear();
+                    }
+                    log::info!(
+                        "occlusion culling {}",
+                        if self.occlusion_culling { "on" } else { "off" }
+                    );
+                } else {
+                    log::warn!(
+                        "occlusion culling needs fragment shaders that write storage buffers"
+                    );
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn toggle_fly_through(&mut self) {
+        self.flying = !self.flying;
+        if self.flying {
+            self.fly_through.restart();
+        } else {
+            // Picks up looking wherever the fly-through left the camera.
+            let controller = &self.camera_controller;
+            let mut camera_controller =
+                CameraController::new(controller.speed, controller.sensitivity, &self.camera);
+            camera_controller.mouse_look = controller.mouse_look;
+            self.camera_controller = camera_controller;
+        }
+    }
+
+    fn save_scene(&self) {
+        let path = std::path::Path::new(SCENE_PATH);
+        match self.scene_serializer.save(&self.world, path) {
+            Ok(()) => log::info!("saved scene to {}", path.display()),
+            Err(err) => eprintln!("{}", err),
+        }
+    }
+
+    /// Replaces the world with the one saved at [`SCENE_PATH`].
+    fn load_scene(&mut self) {
+        let path = std::path::Path::new(SCENE_PATH);
+        match self.scene_serializer.load(path) {
+            Ok(world) => {
+                self.world = world;
+                log::info!("loaded scene from {}", path.display());
+            }
+            Err(err) => eprintln!("{}", err),
+        }
+    }
+
+    /// What the scene's pipeline is drawing with right now.
+    ///
+    /// The viewports are the split views of F4 while they're on, and
+    /// otherwise the whole frame.
+    fn snapshot(&self) -> RenderStateSnapshot {
+        let state = &self.render_pipeline_state;
+        let viewports = if self.multi_viewport_enabled {
+            self.multi_viewport.views()
+        } else {
+            let (width, height) = (self.ctx.config.width, self.ctx.config.height);
+            vec![(
+                ViewportState::full(width, height),
+                ScissorRect::full(width, height),
+            )]
+        };
+        RenderStateSnapshot {
+            pipeline_label: state.label.clone().unwrap_or_default(),
+            primitive: state.primitive,
+            sample_count: state.sample_count,
+            depth_stencil_config: state.depth_stencil.clone(),
+            blend_states: state.blend_states.clone(),
+            vertex_layout_debug: state.vertex_layouts.clone(),
+            bind_group_entry_counts: vec![
+                (
+                    "camera".to_string(),
+                    UniformBinding::<CameraUniform>::BIND_GROUP_ENTRIES,
+                ),
+                (
+                    "lights".to_string(),
+                    UniformBinding::<LightUniform>::BIND_GROUP_ENTRIES,
+                ),
+                (
+                    "cascaded shadows".to_string(),
+                    CsmShadowPass::BIND_GROUP_ENTRIES,
+                ),
+                ("material".to_string(), MaterialLibrary::BIND_GROUP_ENTRIES),
+            ],
+            viewports,
+        }
+    }
+
+    /// Writes a [`snapshot`](Self::snapshot) of the render state to
+    /// [`RENDER_STATE_PATH`].
+    fn write_render_state(&self) {
+        let path = std::path::Path::new(RENDER_STATE_PATH);
+        match std::fs::write(path, self.snapshot().to_string()) {
+            Ok(()) => log::info!("wrote render state to {}", path.display()),
+            Err(err) => eprintln!("failed to write {}: {}", path.display(), err),
+        }
+    }
+
+    fn toggle_sky_mode(&mut self) {
+        self.sky_mode = match self.sky_mode {
+            SkyMode::Procedural if self.skybox.is_some() => SkyMode::Cubemap,
+            SkyMode::Procedural => {
+                log::warn!("no skybox was loaded to switch to");
+                SkyMode::Procedural
+            }
+            SkyMode::Cubemap => SkyMode::Procedural,
+        };
+        log::info!("sky set to {:?}", self.sky_mode);
+    }
+
+    /// Selects the object under the cursor, and logs which it is.
+    fn pick(&mut self) {
+        if self.raycast_picking {
+            self.selected = self.raycast_pick();
+        } else {
+            self.selected = self.gpu_pick();
+        }
+        match self.selected {
+            Some(entity) => log::info!("picked entity {}", entity.0),
+            None => log::info!("picked nothing"),
+        }
+    }
+
+    /// The entity whose mesh bounds are first along the ray under
+    /// the cursor.
+    fn raycast_pick(&self) -> Option<EntityId> {
+        use cgmath::SquareMatrix;
+
+        let view_proj = cgmath::Matrix4::from(self.camera.build_view_projection_matrix());
+        let ray = Ray::from_screen(
+            self.input.mouse_position(),
+            (self.ctx.config.width as f32, self.ctx.config.height as f32),
+            view_proj.invert()?.into(),
+        );
+        let entities = scene::entity_bounds(&self.world, &self.meshes, &self.lod_meshes);
+        RaycastPicker::pick(&ray, &entities)
+    }
+
+    /// The entity drawn at the pixel under the cursor, read back
+    /// from an ID buffer.
+    fn gpu_pick(&mut self) -> Option<EntityId> {
+        // The instances of each batch take the IDs after the last
+        // batch's, counting from 1 so that 0 is left for nothing.
+        let mut base_id = 1;
+        let draws = self
+            .batches
+            .iter()
+            .map(|batch| {
+                let draw = PickingDraw {
+                    mesh: self.mesh_for(batch.mesh),
+                    instances: &batch.instances,
+                    base_id,
+                };
+                base_id += batch.instances.len();
+                draw
+            })
+            .collect::<Vec<_>>();
+        self.picking.render(
+            &self.ctx.device,
+            &self.ctx.queue,
+            &self.depth_buffer,
+            &self.camera_buffer.binding().bind_group,
+            &draws,
+        );
+
+        let (x, y) = self.input.mouse_position();
+        let (x, y) = (x as u32, y as u32);
+        self.picking
+            .query_pixel(&self.ctx.device, &self.ctx.queue, x, y)
+            .and_then(|id| {
+                self.batches
+                    .iter()
+                    .flat_map(|batch| batch.entities.iter())
+                    .nth(id as usize - 1)
+                    .copied()
+            })
+    }
+
+    /// Outlines the selected entity, if it's still being drawn.
+    fn draw_selection_outline<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>) {
+        let selected = match self.selected {
+            Some(entity) => entity,
+            None => return,
+        };
+        for batch in &self.batches {
+            if let Some(i) = batch.entities.iter().position(|&entity| entity == selected) {
+                let i = i as u32;
+                self.outline.draw(
+                    render_pass,
+                    &self.camera_buffer.binding().bind_group,
+                    self.mesh_for(batch.mesh),
+                    &batch.instances,
+                    i..i + 1,
+                );
+                return;
+            }
+        }
+    }
+
+    fn device_input(&mut self, event: &DeviceEvent) {
+        if let DeviceEvent::MouseMotion { delta } = event {
+            self.camera_controller.process_mouse_motion(*delta);
+        }
+    }
+
+    fn update(&mut self) {
+        self.frame_timer.tick();
+
+        // Scale movement by the frame time, so the camera moves
+        // at the same speed regardless of frame rate.
+        let dt = self.frame_timer.delta_time();
+        self.ui_has_mouse = self
+            .debug_ui
+            .update(&self.input, self.ctx.config.width as f32);
+        self.handle_actions();
+        self.apply_scene_settings();
+        if self.flying {
+            self.fly_through.update_camera(&mut self.camera, dt);
+        } else {
+            self.camera_controller
+                .update_camera(&mut self.camera, &self.input, dt);
+        }
+        self.camera.jitter = if self.taa_enabled {
+            // From pixels to normalized device coordinates, which are
+            // 2 across and have Y pointing up.
+            let (x, y) = TaaPass::jitter_offset(self.taa.frame_index());
+            [
+                x * 2.0 / self.ctx.config.width as f32,
+                -y * 2.0 / self.ctx.config.height as f32,
+            ]
+        } else {
+            [0.0; 2]
+        };
+        self.camera_buffer.update(&self.ctx.queue, &self.camera);
+        // Kept up to date while it's off, so it doesn't blur across
+        // everything the camera did in the meantime when it's back on.
+        self.velocity.update(&self.ctx.queue, &self.camera);
+        if self.ssao_enabled {
+            if let Some(normal_reconstruct) = &mut self.normal_reconstruct {
+                normal_reconstruct.update(&self.ctx.queue, &self.camera);
+            }
+            self.ssao.update(&self.ctx.queue, &self.camera);
+        }
+        if self.multi_viewport_enabled {
+            self.multi_viewport.update(&self.ctx.queue, &self.camera);
+        }
+        self.water.update(&self.ctx.queue, &self.camera);
+        self.deferred.update(&self.ctx.queue, &self.camera);
+        if self.time_of_day_enabled {
+            self.time_of_day.advance(dt * TIME_OF_DAY_SPEED);
+            self.sky_light.update(&self.ctx.queue, &self.time_of_day);
+        } else {
+            self.sky_light.upload_lights(&self.ctx.queue);
+        }
+        if let Some(light) = self.sky_light.lights().first() {
+            self.csm.update(&self.ctx.queue, &self.camera, light);
+        }
+        self.particles.update(&self.ctx.queue, dt);
+        self.cloth.update(&self.ctx.queue, dt);
+        self.sparks.update(dt);
+        self.sparks.upload(&self.ctx.queue, &self.camera);
+        self.animation_time += dt;
+        self.skinned_column
+            .animate(&self.ctx.queue, &self.sway, self.animation_time);
+        if let Some(skybox) = &mut self.skybox {
+            skybox.set_rotation(skybox.rotation() + SKY_ROTATION_SPEED * dt);
+            skybox.update(&self.ctx.queue, &self.camera);
+        }
+        self.sky_light
+            .sky_mut()
+            .update(&self.ctx.queue, &self.camera);
+        if self.show_gizmos {
+            self.draw_gizmos();
+        }
+        self.input.end_frame();
+    }
+
+    /// Queues debug lines showing the world axes, the bounds
+    /// of the instance grid, and where the lights come from.
+    /// The voxel grid's bounds are drawn with smooth lines, which
+    /// the scene can hide.
+    fn draw_gizmos(&mut self) {
+        self.gizmos.draw_axes(&Transform::identity(), 1.0);
+        self.gizmos
+            .draw_spline(&self.fly_through.path, 128, [1.0, 0.3, 1.0, 1.0]);
+
+        let extent = (NUM_INSTANCES_PER_ROW - 1) as f32 * INSTANCE_SPACING * 0.5 + 0.5;
+        self.gizmos.draw_aabb(
+            [-extent, -0.5, -extent],
+            [extent, 0.5, extent],
+            [1.0, 1.0, 0.0, 1.0],
+        );
+
+        // Rays point back along each light's direction, towards the light.
+        for light in self.sky_light.lights() {
+            let [x, y, z] = light.direction;
+            let [r, g, b] = light.color;
+            self.gizmos
+                .draw_ray([0.0, 0.0, 0.0], [-x, -y, -z], 3.0, [r, g, b, 1.0]);
+        }
+
+        let [sx, sy, sz] = self.voxels.size();
+        let size = [sx as f32, sy as f32, sz as f32];
+        let corner = |i: usize| {
+            let mut corner = VOXEL_POSITION;
+            for axis in 0..3 {
+                if i & (1 << axis) != 0 {
+                    corner[axis] += size[axis] * VOXEL_SIZE;
+                }
+            }
+            corner
+        };
+        // An edge joins corners differing by a single bit.
+        for i in 0..8 {
+            for bit in [1, 2, 4] {
+                if i & bit == 0 {
+                    self.lines
+                        .draw_line(corner(i), corner(i | bit), 3.0, [0.2, 0.8, 1.0, 1.0]);
+                }
+            }
+        }
+    }
+
+    /// Feeds the debug UI's settings into the lights, bloom and
+    /// camera they tweak.
+    fn apply_scene_settings(&mut self) {
+        use cgmath::InnerSpace;
+
+        let settings = self.debug_ui.settings;
+        let direction = cgmath::Vector3::from(settings.light_direction);
+        // The time of day moves the sun instead, while it's on.
+        if let Some(sun) = self.sky_light.lights_mut().first_mut() {
+            if !self.time_of_day_enabled {
+                // A direction of nothing keeps the last one.
+                if direction.magnitude2() > 1e-6 {
+                    sun.direction = direction.normalize().into();
+                }
+                sun.color = SUN_COLOR.map(|c| c * settings.light_intensity);
+            }
+        }
+        if self.bloom.threshold() != settings.bloom_threshold {
+            self.bloom
+                .set_threshold(&self.ctx.queue, settings.bloom_threshold);
+        }
+        if let CameraProjection::Perspective { fov, .. } = &mut self.camera.projection {
+            *fov = settings.fov;
+        }
+    }
+
+    /// The color the scene is cleared to, as set in the debug UI.
+    fn clear_color(&self) -> wgpu::Color {
+        let [r, g, b] = self.debug_ui.settings.clear_color;
+        wgpu::Color {
+            r: r as f64,
+            g: g as f64,
+            b: b as f64,
+            a: CLEAR_COLOR.a,
+        }
+    }
+
+    /// Queues a bar graph of the recent frame times
+    /// in the top left corner of the screen.
+    fn draw_frame_time_graph(&mut self) {
+        const BAR_WIDTH: f32 = 3.0;
+        const MAX_HEIGHT: f32 = 100.0;
+        // Pixels of height per millisecond.
+        const SCALE: f32 = 2.0;
+
+        for (i, frame_time) in self.frame_timer.samples().enumerate() {
+            let height = (frame_time * 1000.0 * SCALE).min(MAX_HEIGHT);
+            let mut sprite = SpriteInstance::new(
+                [8.0 + i as f32 * BAR_WIDTH, 8.0 + MAX_HEIGHT - height],
+                [BAR_WIDTH - 1.0, height],
+            );
+            sprite.color = [0.4, 1.0, 0.4, 0.8];
+            self.sprite_renderer.draw_image(self.white, sprite);
+        }
+    }
+
+    /// Queues the frame rate in the top right corner of the screen.
+    fn draw_frame_rate_text(&mut self) {
+        const SCALE: f32 = 2.0;
+        const MARGIN: f32 = 8.0;
+
+        if let Some(text_renderer) = &mut self.text_renderer {
+            let text = format!("{:.1} fps", self.frame_timer.fps());
+            let x = self.ctx.config.width as f32 - text_renderer.text_width(&text, SCALE) - MARGIN;
+            text_renderer.draw_text(x, MARGIN, SCALE, [1.0, 1.0, 1.0, 1.0], &text);
+        }
+    }
+
+    /// Queues a notice while a shader is being compiled, and
+    /// the errors from the last reloads, below the frame time graph.
+    fn draw_shader_status_text(&mut self) {
+        const SCALE: f32 = 1.0;
+        const LEFT: f32 = 8.0;
+        const TOP: f32 = 120.0;
+
+        let text_renderer = match &mut self.text_renderer {
+            Some(text_renderer) => text_renderer,
+            None => return,
+        };
+        let line_height = text_renderer.line_height(SCALE);
+
+        let mut y = TOP;
+        if self.shader_compiler.is_compiling() {
+            text_renderer.draw_text(LEFT, y, SCALE, [1.0, 1.0, 0.4, 1.0], "Compiling shader...");
+            y += line_height;
+        }
+        for err in &self.shader_errors {
+            text_renderer.draw_text(LEFT, y, SCALE, [1.0, 0.4, 0.4, 1.0], err);
+            y += line_height * err.lines().count() as f32;
+        }
+    }
+
+    /// Hands out the transient textures of the frame's passes, in the
+    /// order the frame graph runs them.
+    fn allocate_transient_textures(&mut self) {
+        let (width, height) = (self.ctx.config.width, self.ctx.config.height);
+        let usages = [TransientUsage {
+            name: "motion_blur_source",
+            desc: MotionBlurPass::source_desc(width, height),
+            first_pass: "motion_blur",
+            last_pass: "motion_blur",
+        }];
+
+        let order = {
+            let mut graph = self.frame_graph();
+            if let Err(err) = graph.compile() {
+                return eprintln!("{}", err);
+            }
+            let order = graph.pass_order().unwrap_or_default();
+            order.into_iter().map(String::from).collect::<Vec<_>>()
+        };
+        let order = order.iter().map(String::as_str).collect::<Vec<_>>();
+        match self
+            .transient_textures
+            .allocate(&self.ctx.device, &order, &usages)
+        {
+            Ok(handles) => self.transient_handles = handles,
+            Err(err) => eprintln!("{}", err),
+        }
+    }
+
+    /// Records the passes that draw a frame into `view`.
+    ///
+    /// The geometry and lighting passes draw the scene into the
+    /// post-processing pass's [`HdrRenderTarget`](hdr_render_target::HdrRenderTarget),
+    /// which the post-processing pass then draws into the tone
+    /// mapping pass's, and tone mapping finally draws into `view` in
+    /// the surface's format.
+    fn draw_frame(&self, encoder: &mut wgpu::CommandEncoder, view: &wgpu::TextureView) {
+        let mut resources = RenderResources::new(view);
+        resources.insert_view("scene", self.post_process.target_view());
+        resources.insert_view("hdr", self.tone_mapping.target_view());
+
+        let mut graph = self.frame_graph();
+        let result = graph
+            .compile()
+            .and_then(|()| graph.execute(encoder, &resources));
+        if let Err(err) = result {
+            eprintln!("{}", err);
+        }
+    }
+
+    /// The passes of a frame, and what each of them depends on.
+    fn frame_graph(&self) -> RenderGraph<'_> {
+        let mut graph = RenderGraph::new();
+        graph.add_pass(
+            "shadow",
+            &[],
+            Box::new(
+                move |encoder: &mut wgpu::CommandEncoder, _: &RenderResources| {
+                    gpu_scope!(encoder, "ShadowPass", { self.draw_shadow_map(encoder) })
+                },
+            ),
+        );
+        // The water's reflection and refraction are drawn before the
+        // scene, which the water is drawn in.
+        graph.add_pass(
+            "water",
+            &["shadow"],
+            Box::new(
+                move |encoder: &mut wgpu::CommandEncoder, _: &RenderResources| {
+                    self.water.render_reflection(encoder, |render_pass| {
+                        self.draw_water_scene(render_pass)
+                    });
+                    self.water.render_refraction(encoder, |render_pass| {
+                        self.draw_water_scene(render_pass)
+                    });
+                },
+            ),
+        );
+        graph.add_pass(
+            "scene",
+            &["shadow", "water"],
+            Box::new(
+                move |encoder: &mut wgpu::CommandEncoder, resources: &RenderResources| {
+                    if self.deferred_enabled {
+                        self.draw_deferred_scene(encoder, resources.view("scene"))
+                    } else {
+                        // Lit as it's drawn, so there's no lighting pass.
+                        gpu_scope!(encoder, "GeometryPass", {
+                            self.draw_scene(encoder, resources.view("scene"))
+                        })
+                    }
+                },
+            ),
+        );
+        graph.add_pass(
+            "occlusion",
+            &["scene"],
+            Box::new(
+                move |encoder: &mut wgpu::CommandEncoder, _: &RenderResources| {
+                    let occlusion = match &self.occlusion {
+                        Some(occlusion) if self.occlusion_culling => occlusion,
+                        _ => return,
+                    };
+                    if let Some(depth) = self.scene_depth() {
+                        occlusion.run(encoder, depth);
+                    }
+                },
+            ),
+        );
+        graph.add_pass(
+            "ssao",
+            &["scene"],
+            Box::new(
+                move |encoder: &mut wgpu::CommandEncoder, _: &RenderResources| {
+                    let normal_reconstruct = match &self.normal_reconstruct {
+                        Some(normal_reconstruct) if self.ssao_enabled => normal_reconstruct,
+                        _ => return,
+                    };
+                    if let Some(depth) = self.scene_depth() {
+                        let device = &self.ctx.device;
+                        normal_reconstruct.run(device, encoder, depth);
+                        self.ssao
+                            .run(device, encoder, depth, normal_reconstruct.normal_view());
+                        self.ssao.apply(device, encoder, self.post_process.target());
+                    }
+                },
+            ),
+        );
+        graph.add_pass(
+            "velocity",
+            &["scene"],
+            Box::new(
+                move |encoder: &mut wgpu::CommandEncoder, _: &RenderResources| {
+                    if !self.motion_blur_enabled && !self.taa_enabled {
+                        return;
+                    }
+                    if let Some(depth) = self.scene_depth() {
+                        self.velocity.run(&self.ctx.device, encoder, depth);
+                    }
+                },
+            ),
+        );
+        graph.add_pass(
+            "taa",
+            &["velocity", "ssao"],
+            Box::new(
+                move |encoder: &mut wgpu::CommandEncoder, _: &RenderResources| {
+                    if self.taa_enabled {
+                        self.taa.run(
+                            &self.ctx.device,
+                            encoder,
+                            self.post_process.target(),
+                            self.velocity.velocity_view(),
+                        );
+                    }
                 },
-                wgpu::VertexAttribute {
-                    offset: std::mem::size_of::<[f32; 3]>() as wgpu::BufferAddress,
-                    shader_location: 1,
-                    format: wgpu::VertexFormat::Float32x3,
+            ),
+        );
+        // Blurred after anti-aliasing, so the blur isn't jittered.
+        graph.add_pass(
+            "motion_blur",
+            &["taa"],
+            Box::new(
+                move |encoder: &mut wgpu::CommandEncoder, _: &RenderResources| {
+                    let source = match self.transient_handles.get("motion_blur_source") {
+                        Some(source) if self.motion_blur_enabled => *source,
+                        _ => return,
+                    };
+                    self.motion_blur.run(
+                        &self.ctx.device,
+                        encoder,
+                        self.post_process.target(),
+                        self.velocity.velocity_view(),
+                        self.transient_textures.texture(source),
+                        self.transient_textures.view(source),
+                    );
                 },
-            ],
+            ),
+        );
+        graph.add_pass(
+            "bloom",
+            &["motion_blur"],
+            Box::new(
+                move |encoder: &mut wgpu::CommandEncoder, resources: &RenderResources| {
+                    if self.bloom_enabled {
+                        self.bloom
+                            .run(&self.ctx.device, encoder, resources.view("scene"))
+                    }
+                },
+            ),
+        );
+        graph.add_pass(
+            "histogram",
+            &["bloom"],
+            Box::new(
+                move |encoder: &mut wgpu::CommandEncoder, _: &RenderResources| {
+                    if self.auto_exposure {
+                        self.histogram.dispatch(
+                            encoder,
+                            self.ctx.config.width,
+                            self.ctx.config.height,
+                        )
+                    }
+                },
+            ),
+        );
+        graph.add_pass(
+            "gizmos",
+            &["bloom"],
+            Box::new(
+                move |encoder: &mut wgpu::CommandEncoder, resources: &RenderResources| {
+                    self.gizmos.draw(
+                        encoder,
+                        resources.view("scene"),
+                        &self.camera_buffer.binding().bind_group,
+                    )
+                },
+            ),
+        );
+        graph.add_pass(
+            "post_process",
+            &["gizmos"],
+            Box::new(
+                move |encoder: &mut wgpu::CommandEncoder, resources: &RenderResources| {
+                    gpu_scope!(encoder, "PostProcess", {
+                        self.post_process.run(encoder, resources.view("hdr"))
+                    })
+                },
+            ),
+        );
+        // Converts to the surface's format, so it has to come last.
+        graph.add_pass(
+            "tone_mapping",
+            &["post_process"],
+            Box::new(
+                move |encoder: &mut wgpu::CommandEncoder, resources: &RenderResources| {
+                    self.tone_mapping.execute(encoder, resources)
+                },
+            ),
+        );
+        // Nothing draws after the occlusion queries and the histogram,
+        // but their results are read back.
+        graph.add_output("tone_mapping");
+        graph.add_output("occlusion");
+        graph.add_output("histogram");
+        graph
+    }
+
+    /// The depth the scene is drawn with, for the passes reading it
+    /// after the scene's. The deferred path draws into its G-buffer's
+    /// depth, which it may not have.
+    fn scene_depth(&self) -> Option<&DepthBuffer> {
+        if self.deferred_enabled {
+            self.deferred.gbuffer().depth()
+        } else {
+            Some(&self.depth_buffer)
         }
     }
-}
 
-// Triangle
-#[rustfmt::skip]
-const VERTICES: &[Vertex] = &[
-    Vertex { position: [-0.0868241,   0.49240386, 0.0], color: [0.5, 0.0, 0.5] }, // A
-    Vertex { position: [-0.49513406,  0.06958647, 0.0], color: [0.5, 0.0, 0.5] }, // B
-    Vertex { position: [-0.21918549, -0.44939706, 0.0], color: [0.5, 0.0, 0.5] }, // C
-    Vertex { position: [ 0.35966998, -0.3473291,  0.0], color: [0.5, 0.0, 0.5] }, // D
-    Vertex { position: [ 0.44147372,  0.2347359,  0.0], color: [0.5, 0.0, 0.5] }, // E
-];
+    /// Records the passes rendering the shadow casters into the shadow
+    /// map of each cascade.
+    fn draw_shadow_map(&self, encoder: &mut wgpu::CommandEncoder) {
+        for cascade in self.csm.cascades() {
+            let mut render_pass = cascade.pass.begin(encoder);
 
-#[rustfmt::skip]
-const INDICES: &[u16] = &[
-    0, 1, 4,
-    1, 2, 4,
-    2, 3, 4,
-    // IMPORTANT: We add 2 bytes padding as wgpu requires buffers to be aligned to 4 bytes.
-    /* padding */
-    0,
-];
+            for batch in &self.batches {
+                render_pass.set_vertex_buffer(1, batch.instances.slice());
+                self.mesh_for(batch.mesh)
+                    .draw_instanced(&mut render_pass, 0..batch.instances.len());
+            }
+        }
+    }
 
-struct State {
-    surface: wgpu::Surface,
-    device: wgpu::Device,
-    queue: wgpu::Queue,
-    config: wgpu::SurfaceConfiguration,
-    size: winit::dpi::PhysicalSize<u32>,
-    render_pipeline: wgpu::RenderPipeline,
-    vertex_buffer: wgpu::Buffer,
-    index_buffer: wgpu::Buffer,
-    num_indices: u32,
-}
+    /// Records the draw calls for the instances of each batch inside
+    /// the frustum, to be replayed by the scene pass until they change.
+    fn record_scene_bundle(&self) -> wgpu::RenderBundle {
+        let mut recorder = RenderBundleRecorder::new(
+            &self.ctx.device,
+            HDR_FORMAT,
+            Some(DepthBuffer::FORMAT),
+            self.msaa.count,
+        );
 
-impl State {
-    async fn new(window: &Window) -> Self {
-        let size = window.inner_size();
-
-        // The instance is a handle to our GPU
-        // Backends::all => Vulkan + Metal + DX12 + Browser WebGPU
-        let instance = wgpu::Instance::new(wgpu::Backends::all());
-        let surface = unsafe { instance.create_surface(window) };
-
-        // The adapter is a handle to our actual graphics card.
-        // We can use this get information about the graphics
-        // card such as its name and what backend the adapter
-        // uses. We use this to create our Device and Queue later.
-        let adapter = instance
-            .request_adapter(&wgpu::RequestAdapterOptions {
-                // wgpu can pick between low power devices like integrated graphics,
-                // or high power consumption like a dedicated card.
-                power_preference: wgpu::PowerPreference::HighPerformance,
-                // Tells wgpu to find an adapter that can present to the supplied surface.
-                // Our window needs to implement raw-window-handle's HasRawWindowHandle
-                // trait to create a surface.
-                compatible_surface: Some(&surface),
-                // Forces wgpu to pick an adapter that will work on all harware.
-                // This usually means that the rendering backend will use a
-                // "software" system, instead of hardware such as a GPU.
-                force_fallback_adapter: false,
+        // Batches with everything outside the frustum are skipped.
+        let mut draws = self
+            .batches
+            .iter()
+            .filter(|batch| batch.visible > 0)
+            .map(|batch| {
+                let mesh = self.mesh_for(batch.mesh);
+                (self.material_for(&batch.material), (mesh, batch))
             })
-            .await
-            .expect("failed to create adapter");
-
-        // Requests a connection to a physical device, creating a logical device.
-        let (device, queue) = adapter
-            .request_device(
-                &wgpu::DeviceDescriptor {
-                    // Allows us to specify what extra features we want.
-                    // For this simple example, I've decided not to use
-                    // any extra features.
-                    //
-                    // We can get a list of features supported by our
-                    // device using `adapter.features()`, or `device.features()`
-                    features: wgpu::Features::empty(),
-                    // The limits field describes the limit of certain
-                    // types of resources we can create. If any requested
-                    // limits are beyond the hardware device, creation
-                    // will fail.
-                    limits: wgpu::Limits::default(),
-                    // Debug label for the device.
-                    label: Some("Adapter"),
-                },
-                // trace_path - Can be used for API call tracing,
-                //              if that feature is enabled in wgpu-core.
-                None,
-            )
-            .await
-            .expect("failed to create device");
-
-        // This will define how the surface creates its underlying `SurfaceTexture`
-        let config = wgpu::SurfaceConfiguration {
-            // `usage` field describes how the `SurfaceTexture`s
-            // will be used. RENDER_ATTACHMENT specifies that the
-            // textures will be used to write to the screen.
-            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
-            // `format` defines how the `SurfaceTexture`s will be
-            // stored on the gpu. Different displays prefer different
-            // formats. We use `get_preferred_format` to figure out
-            // the best format to use based on the display you're using.
-            format: surface.get_preferred_format(&adapter).unwrap(),
-            // The width and height in pixels of the SurfaceTexture.
-            // This should usually be the width and height of the window.
-            //
-            // WARNING: Make sure that the width and height of the
-            //          `SurfaceTexture` are not 0, as that can
-            //          cause the app to crash.
-            width: size.width,
-            height: size.height,
-            // Determines how to sync the surface with the display.
-            // The option we picked FIFO, will cap the display rate
-            // at the displays framerate. This is essentially VSync
-            //  This is also the most optimal mode on mobile.
-            present_mode: wgpu::PresentMode::Fifo,
-        };
-        surface.configure(&device, &config);
+            .collect::<Vec<_>>();
+        material::sort_draws(&mut draws);
 
-        // Render Pipeline
-        let shader = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
-            label: Some("Shader"),
-            source: wgpu::ShaderSource::Wgsl(include_str!("shader.wgsl").into()),
-        });
+        // These stay bound while switching between material pipelines,
+        // since they all share the same layout for the first groups.
+        recorder.set_bind_group(0, &self.camera_buffer.binding().bind_group, &[]);
+        recorder.set_bind_group(1, &self.sky_light.light_buffer().binding().bind_group, &[]);
+        recorder.set_bind_group(2, self.csm.bind_group(), &[]);
 
-        let render_pipeline_layout =
-            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-                label: Some("Render Pipeline Layout"),
-                bind_group_layouts: &[],
-                push_constant_ranges: &[],
-            });
+        let mut current_pipeline = None;
+        for (material, (mesh, batch)) in draws {
+            if current_pipeline != Some(Arc::as_ptr(&material.pipeline)) {
+                recorder.set_pipeline(&material.pipeline);
+                current_pipeline = Some(Arc::as_ptr(&material.pipeline));
+            }
+            recorder.set_bind_group(3, &material.bind_group, &[]);
 
-        let render_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-            label: Some("Render Pipeline"),
-            layout: Some(&render_pipeline_layout),
-            vertex: wgpu::VertexState {
-                module: &shader,
-                // Here we can specify the function name to be called
-                // in the shader module.
-                entry_point: "main",
-                // Tells wgpu what type of vertices we want to pass to
-                // the vertex shader. If the vertices are generated in
-                // the shader, then this can be left empty.
-                buffers: &[Vertex::layout()],
-            },
-            // Fragment shader is optional. We need it because we're storing
-            // color data to the surface.
-            fragment: Some(wgpu::FragmentState {
-                module: &shader,
-                entry_point: "main",
-                // The `targets` field tells wgpu what color outputs it should set up.
-                // Currently we only need one for the surface. We use the surface's
-                // format so that copying to it is easy, and we specify that the
-                // blending should just replace old pixel data with new data.
-                //
-                // We also tell wgpu to write to all colors: red, blue, green, and alpha.
-                targets: &[wgpu::ColorTargetState {
-                    format: config.format,
-                    blend: Some(wgpu::BlendState::REPLACE),
-                    write_mask: wgpu::ColorWrites::ALL,
-                }],
-            }),
-            primitive: wgpu::PrimitiveState {
-                // Means that each three vertices will correspond to one triangle.
-                topology: wgpu::PrimitiveTopology::TriangleList,
-                strip_index_format: None,
-                // The `front_face` and `cull_mode` fields tell wgpu how to
-                // determine whether a given triangle is facing forward or not.
-                front_face: wgpu::FrontFace::Ccw,
-                cull_mode: Some(wgpu::Face::Back),
-                // Setting this to anything other than Fill requires Features::NON_FILL_POLYGON_MODE
-                polygon_mode: wgpu::PolygonMode::Fill,
-                // Requires Features::DEPTH_CLAMPING
-                clamp_depth: false,
-                // Requires Features::CONSERVATIVE_RASTERIZATION
-                conservative: false,
-            },
-            depth_stencil: None,
-            multisample: wgpu::MultisampleState {
-                count: 1,
-                mask: !0,
-                // This has to do with anti-aliasing.
-                alpha_to_coverage_enabled: false,
-            },
-        });
+            // Instance data goes in the second slot, matching the order
+            // of the layouts given to the pipeline. Only the instances
+            // inside the frustum are drawn, which come first.
+            recorder.set_vertex_buffer(1, batch.instances.slice());
+            mesh.record_instanced(&mut recorder, 0..batch.visible);
+        }
 
-        // `create_buffer_init` requires the DeviceExt extension trait.
-        let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: Some("Vertex Buffer"),
-            contents: bytemuck::cast_slice(VERTICES),
-            usage: wgpu::BufferUsages::VERTEX,
-        });
+        recorder.finish()
+    }
 
-        let index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: Some("Index Buffer"),
-            contents: bytemuck::cast_slice(INDICES),
-            usage: wgpu::BufferUsages::INDEX,
-        });
+    /// Uploads every instance of every batch to be culled on the GPU,
+    /// in batch order, and records the culling pass.
+    fn cull_on_gpu(&mut self, encoder: &mut wgpu::CommandEncoder) {
+        let objects = self
+            .batches
+            .iter()
+            .flat_map(|batch| {
+                let mesh = self.mesh_for(batch.mesh);
+                let world = &self.world;
+                batch.entities.iter().enumerate().map(move |(i, entity)| {
+                    let transform = world
+                        .get::<Transform>(*entity)
+                        .copied()
+                        .unwrap_or_else(Transform::identity);
+                    CullObject::new(transform.to_matrix(), mesh, i as u32)
+                })
+            })
+            .collect::<Vec<_>>();
 
-        let num_indices = INDICES.len() as u32;
+        let frustum = Frustum::from_view_proj(self.camera.build_view_projection_matrix());
+        if let Some(gpu_culling) = &mut self.gpu_culling {
+            gpu_culling.upload(&self.ctx.queue, &objects, &frustum);
+            gpu_culling.dispatch(encoder);
+        }
+    }
 
-        State {
-            surface,
-            device,
-            queue,
-            config,
-            size,
-            render_pipeline,
-            vertex_buffer,
-            index_buffer,
-            num_indices,
+    /// Draws every instance of every batch as seen by another camera,
+    /// so none of them are culled.
+    fn draw_all_batches<'a>(
+        &'a self,
+        render_pass: &mut wgpu::RenderPass<'a>,
+        camera_bind_group: &'a wgpu::BindGroup,
+    ) {
+        let mut draws = self
+            .batches
+            .iter()
+            .map(|batch| (self.material_for(&batch.material), batch))
+            .collect::<Vec<_>>();
+        material::sort_draws(&mut draws);
+
+        render_pass.set_bind_group(0, camera_bind_group, &[]);
+        render_pass.set_bind_group(1, &self.sky_light.light_buffer().binding().bind_group, &[]);
+        render_pass.set_bind_group(2, self.csm.bind_group(), &[]);
+
+        let mut current_pipeline = None;
+        for (material, batch) in draws {
+            if current_pipeline != Some(Arc::as_ptr(&material.pipeline)) {
+                render_pass.set_pipeline(&material.pipeline);
+                current_pipeline = Some(Arc::as_ptr(&material.pipeline));
+            }
+            render_pass.set_bind_group(3, &material.bind_group, &[]);
+            render_pass.set_vertex_buffer(1, batch.instances.slice());
+            self.mesh_for(batch.mesh)
+                .draw_instanced(render_pass, 0..batch.instances.len());
         }
     }
 
-    fn resize(&mut self, new_size: winit::dpi::PhysicalSize<u32>) {
-        // Size 0 will crash the app.
-        if new_size.width > 0 && new_size.height > 0 {
-            self.size = new_size;
-            self.config.width = new_size.width;
-            self.config.height = new_size.height;
-            self.surface.configure(&self.device, &self.config);
+    /// Draws each batch with one multi draw of the draws written for
+    /// its instances by [`State::cull_on_gpu`].
+    fn draw_gpu_culled_batches<'a>(
+        &'a self,
+        render_pass: &mut wgpu::RenderPass<'a>,
+        gpu_culling: &'a IndirectCullPass,
+    ) {
+        // The draws of each batch follow on from the batch before's.
+        let mut first_draw = 0;
+        let mut draws = self
+            .batches
+            .iter()
+            .map(|batch| {
+                let draw = (batch, first_draw);
+                first_draw += batch.instances.len();
+                (self.material_for(&batch.material), draw)
+            })
+            .collect::<Vec<_>>();
+        material::sort_draws(&mut draws);
+
+        render_pass.set_bind_group(0, &self.camera_buffer.binding().bind_group, &[]);
+        render_pass.set_bind_group(1, &self.sky_light.light_buffer().binding().bind_group, &[]);
+        render_pass.set_bind_group(2, self.csm.bind_group(), &[]);
+
+        let max_draws = gpu_culling.max_objects();
+        let mut current_pipeline = None;
+        for (material, (batch, first_draw)) in draws {
+            // Batches past the last object that fit aren't drawn.
+            let count = batch
+                .instances
+                .len()
+                .min(max_draws.saturating_sub(first_draw));
+            if count == 0 {
+                continue;
+            }
+            if current_pipeline != Some(Arc::as_ptr(&material.pipeline)) {
+                render_pass.set_pipeline(&material.pipeline);
+                current_pipeline = Some(Arc::as_ptr(&material.pipeline));
+            }
+            render_pass.set_bind_group(3, &material.bind_group, &[]);
+            render_pass.set_vertex_buffer(1, batch.instances.slice());
+            gpu_culling
+                .draws()
+                .draw(render_pass, self.mesh_for(batch.mesh), first_draw, count);
         }
     }
 
-    fn input(&mut self, _event: &WindowEvent) -> bool {
-        false
+    /// Records the deferred renderer's geometry and lighting passes,
+    /// lighting the visible instances of each batch into `view`.
+    ///
+    /// Only the opaque batches are drawn. The sky, water and
+    /// particles are left to forward rendering.
+    fn draw_deferred_scene(&self, encoder: &mut wgpu::CommandEncoder, view: &wgpu::TextureView) {
+        gpu_scope!(encoder, "GeometryPass", {
+            self.deferred.geometry_pass(
+                encoder,
+                &self.camera_buffer.binding().bind_group,
+                |render_pass| {
+                    for batch in self.batches.iter().filter(|batch| batch.visible > 0) {
+                        let material = self.material_for(&batch.material);
+                        if material.blend_mode != BlendMode::Opaque {
+                            continue;
+                        }
+                        render_pass.set_bind_group(1, &material.bind_group, &[]);
+                        render_pass.set_vertex_buffer(1, batch.instances.slice());
+                        self.mesh_for(batch.mesh)
+                            .draw_instanced(render_pass, 0..batch.visible);
+                    }
+                },
+            )
+        });
+        gpu_scope!(encoder, "LightingPass", {
+            self.deferred
+                .lighting_pass(encoder, view, wgpu::LoadOp::Clear(self.clear_color()))
+        });
     }
 
-    fn update(&mut self) {
-        // remove `todo!()`
+    /// Draws every batch for the water's reflection or refraction,
+    /// into a pass with the pipeline and camera already bound.
+    ///
+    /// The reflection is seen from another camera, so none of the
+    /// instances are culled, and the materials' own pipelines
+    /// are skipped for the water's single sampled one.
+    fn draw_water_scene<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>) {
+        render_pass.set_bind_group(1, &self.sky_light.light_buffer().binding().bind_group, &[]);
+        render_pass.set_bind_group(2, self.csm.bind_group(), &[]);
+        for batch in &self.batches {
+            let material = self.material_for(&batch.material);
+            render_pass.set_bind_group(3, &material.bind_group, &[]);
+            render_pass.set_vertex_buffer(1, batch.instances.slice());
+            self.mesh_for(batch.mesh)
+                .draw_instanced(render_pass, 0..batch.instances.len());
+        }
     }
 
-    fn render(&mut self) -> Result<(), wgpu::SurfaceError> {
-        // Will wait for the surface to provide a new
-        // SurfaceTexture that we will render to.
-        let output = self.surface.get_current_texture()?;
+    /// The mesh, or level of a LOD mesh, a batch is drawn with.
+    fn mesh_for(&self, mesh: BatchMesh) -> &Mesh {
+        match mesh {
+            BatchMesh::Mesh(handle) => &self.meshes[handle.0],
+            BatchMesh::Lod(handle, level) => self.lod_meshes[handle.0].mesh(level.0),
+        }
+    }
 
-        // We do this because we want to control how the
-        // render code interacts with the texture.
-        let view = output
-            .texture
-            .create_view(&wgpu::TextureViewDescriptor::default());
+    /// The material `handle` names, falling back to the
+    /// default material when it isn't registered.
+    fn material_for(&self, handle: &MaterialHandle) -> &Material {
+        self.materials
+            .get(&handle.0)
+            .or_else(|| self.materials.get(DEFAULT_MATERIAL))
+            .expect("default material is registered")
+    }
 
-        // Most modern graphics frameworks expect commands to
-        // be stored in a command buffer before being sent
-        // to the gpu. The encoder builds a command buffer that
-        // we can then send to the gpu.
-        let mut encoder = self
-            .device
-            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
-                label: Some("Render Encoder"),
-            });
+    /// Records the pass drawing the scene into `view`.
+    fn draw_scene(&self, encoder: &mut wgpu::CommandEncoder, view: &wgpu::TextureView) {
+        let (color_view, resolve_target) = match &self.msaa_framebuffer {
+            Some(framebuffer) => (framebuffer, Some(view)),
+            None => (view, None),
+        };
 
-        {
-            // We need to use the encoder to create a RenderPass.
-            // The RenderPass has all the methods to do the actual drawing.
-            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+        // We need to use the encoder to create a RenderPass.
+        // The RenderPass has all the methods to do the actual drawing.
+        let mut render_pass = RenderPassBuilder::begin_render_pass(
+            encoder,
+            &wgpu::RenderPassDescriptor {
                 label: Some("Render Pass"),
                 // Describe where we are going to draw our color to.
                 // We use the TextureView we created earlier to make
                 // sure that we render to the screen.
                 color_attachments: &[wgpu::RenderPassColorAttachment {
-                    view: &view,
+                    view: color_view,
                     // The texture that will receive the resolved output.
-                    // This will be the same as view unless multisampling
-                    // is enabled. We don't need to specify this, so we
-                    // leave it as None.
-                    resolve_target: None,
+                    // With multisampling we draw into the multisampled
+                    // texture and resolve into `view`, otherwise we draw
+                    // into `view` directly and there's nothing to resolve.
+                    resolve_target,
                     ops: wgpu::Operations {
                         // This tells wgpu what to do with the colors on
                         // the screen (specified by `frame.view`).
                         // The `load` field tells wgpu how to handle
                         // colors stored from the previous frame.
-                        load: wgpu::LoadOp::Clear(wgpu::Color {
-                            r: 0.1,
-                            g: 0.2,
-                            b: 0.3,
-                            a: 1.0,
-                        }),
+                        load: wgpu::LoadOp::Clear(self.clear_color()),
                         // The `store` field tells wgpu with we want to
                         // store the rendered results to the Texture behind
                         // our `TextureView` (in this case it's the `SurfaceTexture`).
@@ -333,31 +2709,686 @@ impl State {
                         store: true,
                     },
                 }],
-                depth_stencil_attachment: None,
+                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                    view: self.depth_buffer.view(),
+                    // Clear to the far plane so anything drawn passes the depth test.
+                    depth_ops: Some(wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(1.0),
+                        store: true,
+                    }),
+                    // Nothing is marked until the outline marks it.
+                    stencil_ops: Some(wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(0),
+                        store: true,
+                    }),
+                }),
+            },
+        );
+        self.render_stats.begin(&mut render_pass);
+
+        if self.multi_viewport_enabled {
+            let size = winit::dpi::PhysicalSize::new(self.ctx.config.width, self.ctx.config.height);
+            self.multi_viewport
+                .render(&mut render_pass, size, |render_pass, camera_bind_group| {
+                    self.draw_all_batches(render_pass, camera_bind_group)
+                });
+            self.render_stats.end(&mut render_pass);
+            return;
+        }
+
+        // The sky goes first, so the scene is drawn over it.
+        match (self.sky_mode, &self.skybox) {
+            (SkyMode::Cubemap, Some(skybox)) => skybox.draw(&mut render_pass),
+            _ => self.sky_light.sky().draw(&mut render_pass),
+        }
+
+        // The batches are drawn from the bundle recorded by
+        // `record_scene_bundle`, rather than call by call, unless
+        // they're culled on the GPU.
+        match &self.gpu_culling {
+            Some(gpu_culling) if self.gpu_culling_enabled => {
+                self.draw_gpu_culled_batches(&mut render_pass, gpu_culling)
+            }
+            _ => {
+                if let Some(bundle) = &self.scene_bundle {
+                    render_pass.execute_bundles(std::iter::once(bundle));
+                }
+            }
+        }
+
+        self.water
+            .draw(&mut render_pass, &self.camera_buffer.binding().bind_group);
+
+        self.particles
+            .draw(&mut render_pass, &self.camera_buffer.binding().bind_group);
+        self.sparks
+            .draw(&mut render_pass, &self.camera_buffer.binding().bind_group);
+
+        if let Some(point_cloud) = &self.point_cloud {
+            self.point_clouds.draw(
+                &mut render_pass,
+                &self.camera_buffer.binding().bind_group,
+                point_cloud,
+            );
+        }
+
+        self.skinned_meshes.draw(
+            &mut render_pass,
+            &self.camera_buffer.binding().bind_group,
+            &self.skinned_column,
+        );
+        self.cloth_renderer.draw(
+            &mut render_pass,
+            &self.camera_buffer.binding().bind_group,
+            &self.cloth,
+        );
+
+        self.lines
+            .draw(&mut render_pass, &self.camera_buffer.binding().bind_group);
+
+        // Drawn last, over everything else.
+        self.draw_selection_outline(&mut render_pass);
+
+        self.render_stats.end(&mut render_pass);
+    }
+
+    /// Draws the scene again into a texture that can be copied from,
+    /// and saves it next to the working directory.
+    fn take_screenshot(&self) {
+        let (width, height) = (self.ctx.config.width, self.ctx.config.height);
+        let format = self.ctx.config.format;
+        let texture = ScreenshotCapture::create_target(&self.ctx.device, width, height, format);
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+        let mut encoder = self
+            .ctx
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Screenshot Render Encoder"),
             });
+        self.draw_frame(&mut encoder, &view);
+        self.ctx.queue.submit(std::iter::once(encoder.finish()));
+
+        let path = "screenshot.png";
+        match ScreenshotCapture::capture(
+            &self.ctx.device,
+            &self.ctx.queue,
+            &texture,
+            (width, height),
+            format,
+            path,
+        ) {
+            Ok(()) => log::info!("saved screenshot to {}", path),
+            Err(err) => eprintln!("{}", err),
+        }
+    }
+
+    fn render(&mut self, window: &Window) -> Result<(), wgpu::SurfaceError> {
+        self.profiler.reset();
+        self.profiler.begin("frame");
+
+        // Holds the frame back while the GPU is too far behind.
+        self.ctx.wait_for_frame_latency();
+
+        // Will wait for the surface to provide a new
+        // SurfaceTexture that we will render to.
+        let output = self.ctx.surface.get_current_texture()?;
+
+        let capture = std::mem::take(&mut self.capture_requested);
+        if let Some(renderdoc) = self.renderdoc.as_ref().filter(|_| capture) {
+            renderdoc.start_frame_capture();
+        }
 
-            render_pass.set_pipeline(&self.render_pipeline);
+        // We do this because we want to control how the
+        // render code interacts with the texture.
+        let view = output
+            .texture
+            .create_view(&wgpu::TextureViewDescriptor::default());
 
-            // Vertex buffer must be set, otherwise program will crash.
-            render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
-            // The method name is `set_index_buffer` not `set_index_buffers`.
-            // We can only have one index buffer set at a time.
-            render_pass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
+        // Most modern graphics frameworks expect commands to
+        // be stored in a command buffer before being sent
+        // to the gpu. The encoder builds a command buffer that
+        // we can then send to the gpu.
+        let mut encoder = self
+            .ctx
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Render Encoder"),
+            });
 
-            // This is where [[builtin(vertex_index)]] comes from.
-            // render_pass.draw(0..self.num_vertices, 0..1);
+        // The particles are simulated before they're drawn in the same frame.
+        self.profiler.begin("particles");
+        self.particles.dispatch(&mut encoder);
+        self.profiler.end("particles");
+        self.profiler.begin("cloth");
+        self.cloth.dispatch(&mut encoder);
+        self.profiler.end("cloth");
+        // Preparing the sparks is sorting them from back to front.
+        self.profiler.begin("sort");
+        self.sparks.prepare(&self.ctx.device, &mut encoder);
+        self.profiler.end("sort");
+        self.gizmos.prepare(&self.ctx.device, &self.ctx.queue);
+        self.lines.prepare(&self.ctx.device, &self.ctx.queue);
+        if let Some(mesh) = self.voxels.remesh(&self.ctx.device) {
+            self.meshes[self.voxel_mesh.0] = mesh;
+            // The bundle still draws from the old mesh's buffers.
+            self.scene_bundle = None;
+        }
+        self.profiler.begin("culling");
+        lod::update_levels(&mut self.world, &self.lod_meshes, &self.camera);
+        let frustum = Frustum::from_view_proj(self.camera.build_view_projection_matrix());
+        let batches_changed = scene::update_batches(
+            &self.ctx.device,
+            &self.ctx.queue,
+            &self.world,
+            &frustum,
+            &self.meshes,
+            &self.lod_meshes,
+            self.occlusion.as_ref().filter(|_| self.occlusion_culling),
+            &mut self.batches,
+        );
+        match &mut self.occlusion {
+            // Tested against this frame's depth, for culling the next.
+            Some(occlusion) if self.occlusion_culling => occlusion.prepare(
+                &self.ctx.queue,
+                &self.camera,
+                &frustum,
+                &scene::entity_bounds(&self.world, &self.meshes, &self.lod_meshes),
+            ),
+            _ => {}
+        }
+        if batches_changed || self.scene_bundle.is_none() {
+            self.scene_bundle = Some(self.record_scene_bundle());
+        }
+        if self.gpu_culling_enabled {
+            self.cull_on_gpu(&mut encoder);
+        }
+        let drawn = self.batches.iter().map(|batch| batch.visible).sum();
+        let culled = self.batches.iter().map(DrawBatch::culled).sum();
+        self.render_stats.set_cull_counts(drawn, culled);
+        self.profiler.end("culling");
 
-            // When using an index buffer, you need to use draw_indexed.
-            // The draw method ignores the index buffer.
-            render_pass.draw_indexed(0..self.num_indices, 0, 0..1);
+        self.profiler.begin("geometry");
+        if self.auto_exposure {
+            self.histogram.prepare(
+                &self.ctx.device,
+                &mut self.bind_groups,
+                self.post_process.target(),
+            );
         }
+        self.draw_frame(&mut encoder, &view);
+        self.render_stats.resolve(&mut encoder);
+        self.profiler.end("geometry");
 
+        // Includes taking the results of earlier frames that are back
+        // from the GPU, timed apart from recording the frame.
+        self.profiler.begin("submit");
         // submit will accept anything that implements IntoIter
-        self.queue.submit(std::iter::once(encoder.finish()));
+        self.ctx.queue.submit(std::iter::once(encoder.finish()));
+        self.ctx.frame_submitted();
+        self.render_stats.read_back(&self.ctx.device);
+        self.cloth.swap();
+        match &mut self.occlusion {
+            Some(occlusion) if self.occlusion_culling => occlusion.read_back(&self.ctx.device),
+            _ => {}
+        }
+        if self.taa_enabled {
+            self.taa.swap(&self.ctx.queue);
+        }
+        if self.auto_exposure {
+            let exposure = self.histogram.read_exposure(
+                &self.ctx.device,
+                &self.ctx.queue,
+                &mut self.bind_groups,
+            );
+            // Applied to the next frame, since this one's submitted.
+            self.tone_mapping.set_exposure(&self.ctx.queue, exposure);
+        }
+        self.bind_groups
+            .end_frame(&self.ctx.device, &self.ctx.queue);
+        self.profiler.end("submit");
+
+        self.profiler.begin("UI");
+        self.draw_frame_time_graph();
+        self.debug_ui.draw(
+            &mut self.sprite_renderer,
+            self.white,
+            self.text_renderer.as_mut(),
+            self.ctx.config.width as f32,
+        );
+        self.sprite_renderer
+            .flush(&self.ctx.device, &self.ctx.queue, &view);
+        if self.debug_ui.is_visible() {
+            let (textures, quads) = &mut self.swatches;
+            if let Some(bind_group) = textures.bind_group() {
+                draw_swatches(
+                    textures.len(),
+                    quads,
+                    self.ctx.config.width,
+                    self.ctx.config.height,
+                );
+                quads.flush(&self.ctx.device, &self.ctx.queue, &view, bind_group);
+            }
+        }
+        self.draw_frame_rate_text();
+        self.draw_shader_status_text();
+        if let Some(text_renderer) = &mut self.text_renderer {
+            text_renderer.flush(&self.ctx.device, &self.ctx.queue, &view);
+        }
+        self.profiler.end("UI");
+
+        if self.screenshot_requested {
+            self.screenshot_requested = false;
+            self.take_screenshot();
+        }
+
+        self.profiler.begin("present");
         output.present();
+        self.profiler.end("present");
+        if let Some(renderdoc) = self.renderdoc.as_ref().filter(|_| capture) {
+            renderdoc.end_frame_capture();
+        }
+
+        let mut title = format!(
+            "grok-wgpu | {:.1} fps | {:.2} ms | {:?}",
+            self.frame_timer.fps(),
+            self.frame_timer.average_frame_time() * 1000.0,
+            self.ctx.swapchain_config().present_mode
+        );
+        if self.render_stats.is_supported() {
+            title += &format!(" | gpu {:.2} ms", self.render_stats.gpu_frame_time_ms());
+        }
+        title += &format!(
+            " | drawn {} culled {}",
+            self.render_stats.objects_drawn(),
+            self.render_stats.objects_culled()
+        );
+        window.set_title(&title);
+
+        self.profiler.end("frame");
+        self.profiled_frames += 1;
+        if self.profiled_frames.is_multiple_of(PROFILE_REPORT_INTERVAL) {
+            self.log_profile();
+        }
 
         Ok(())
     }
+
+    /// Logs the profiler's times for the frame just drawn, with the
+    /// scopes indented under the ones they were opened in.
+    fn log_profile(&self) {
+        let mut report = String::from("CPU profile:");
+        for entry in self.profiler.report() {
+            report += &format!(
+                "\n{:indent$}{} {:.3} ms",
+                "",
+                entry.name(),
+                entry.total.as_secs_f64() * 1000.0,
+                indent = entry.depth * 2,
+            );
+        }
+        log::info!("{}", report);
+    }
+}
+
+/// The textures swatches are drawn from, by their index.
+enum SwatchTextures {
+    Bindless(BindlessTextureArray),
+    /// The layers of a texture array, with its bind group, where the
+    /// adapter can't index an array of textures.
+    Layered(TextureArray, wgpu::BindGroup),
+}
+
+impl SwatchTextures {
+    fn len(&self) -> u32 {
+        match self {
+            SwatchTextures::Bindless(textures) => textures.len(),
+            SwatchTextures::Layered(array, _) => array.layer_count(),
+        }
+    }
+
+    fn bind_group(&self) -> Option<&wgpu::BindGroup> {
+        match self {
+            SwatchTextures::Bindless(textures) => textures.bind_group(),
+            SwatchTextures::Layered(_, bind_group) => Some(bind_group),
+        }
+    }
+}
+
+/// Generated textures to show with [`draw_swatches`], in a bindless
+/// texture array when the adapter can index one, or as the layers of
+/// a texture array otherwise.
+fn load_swatches(ctx: &GpuContext) -> (SwatchTextures, TexturedQuadRenderer) {
+    const SIZE: u32 = 64;
+
+    let mut images = vec![
+        ProcTexture::checkerboard(SIZE, SIZE, 8, [255; 4], [40, 40, 40, 255]),
+        ProcTexture::checkerboard(SIZE, SIZE, 16, [255, 80, 80, 255], [80, 80, 255, 255]),
+        ProcTexture::uv_gradient(SIZE, SIZE),
+    ];
+    images.extend((0..5).map(|seed| ProcTexture::noise(SIZE, SIZE, seed)));
+    let images = images
+        .into_iter()
+        .map(|rgba| {
+            let img =
+                image::RgbaImage::from_raw(SIZE, SIZE, rgba).expect("swatch is the wrong size");
+            image::DynamicImage::ImageRgba8(img)
+        })
+        .collect::<Vec<_>>();
+
+    let device = &ctx.device;
+    if BindlessTextureArray::is_supported(device) {
+        let mut textures =
+            BindlessTextureArray::new(device, TexturedQuadRenderer::BINDLESS_TEXTURES);
+        if let Some(quads) = TexturedQuadRenderer::bindless(device, &textures, ctx.config.format) {
+            for img in &images {
+                let texture = Texture::from_image(device, &ctx.queue, img, Some("Swatch"));
+                let view = texture.texture.create_view(&Default::default());
+                textures
+                    .register(view)
+                    .expect("bindless texture array has a slot for every swatch");
+            }
+            textures.update(device);
+            return (SwatchTextures::Bindless(textures), quads);
+        }
+    }
+
+    let array = TextureArray::from_images(device, &ctx.queue, &images, Some("Swatch Array"))
+        .expect("swatches are all the same size");
+    let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+        label: Some("Swatch Bind Group Layout"),
+        entries: &TextureArray::bind_group_layout_entry(0),
+    });
+    let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some("Swatch Bind Group"),
+        layout: &layout,
+        entries: &array.bind_group_entries(0),
+    });
+    let quads = TexturedQuadRenderer::layered(device, &layout, ctx.config.format);
+    (SwatchTextures::Layered(array, bind_group), quads)
+}
+
+/// Queues a row of `count` quads along the bottom of the screen, one
+/// for each swatch, all drawn together.
+fn draw_swatches(
+    count: u32,
+    quads: &mut TexturedQuadRenderer,
+    screen_width: u32,
+    screen_height: u32,
+) {
+    // In pixels.
+    const SIZE: f32 = 48.0;
+    const MARGIN: f32 = 8.0;
+
+    let (width, height) = (screen_width.max(1) as f32, screen_height.max(1) as f32);
+    for texture_id in 0..count {
+        quads.draw(TexturedQuad {
+            position: [
+                (MARGIN + (SIZE + MARGIN) * texture_id as f32) / width,
+                1.0 - (SIZE + MARGIN) / height,
+            ],
+            size: [SIZE / width, SIZE / height],
+            texture_id,
+        });
+    }
+}
+
+/// Loads the font from `res/font.ttf` as a distance field font if
+/// it's there, or `res/font.png` as a bitmap font otherwise. With
+/// `--text raster`, the TTF font's glyphs are rasterized as they're
+/// drawn instead.
+fn load_text_renderer(ctx: &GpuContext) -> Option<TextRenderer> {
+    let res = std::path::Path::new(concat!(env!("CARGO_MANIFEST_DIR"), "/res"));
+
+    let ttf_path = res.join("font.ttf");
+    if ttf_path.is_file() && arg_value("--text").as_deref() == Some("raster") {
+        let font = std::fs::read(&ttf_path)
+            .map_err(|err| err.to_string())
+            .and_then(|bytes| {
+                rusttype::Font::try_from_vec(bytes).ok_or_else(|| "not a TrueType font".to_owned())
+            });
+        match font {
+            Ok(font) => {
+                return Some(TextRenderer::new_rasterized(
+                    &ctx.device,
+                    &ctx.config,
+                    font,
+                    RASTERIZED_GLYPH_SIZE,
+                ))
+            }
+            Err(err) => log::warn!("failed to load font {}: {}", ttf_path.display(), err),
+        }
+    } else if ttf_path.is_file() {
+        let font = std::fs::read(&ttf_path)
+            .map_err(|err| err.to_string())
+            .and_then(|bytes| {
+                SdfFont::from_ttf(&bytes, SDF_GLYPH_SIZE, SDF_RADIUS).map_err(|err| err.to_string())
+            });
+        match font {
+            Ok(font) => {
+                return Some(TextRenderer::new_sdf(
+                    &ctx.device,
+                    &ctx.queue,
+                    &ctx.config,
+                    font,
+                ))
+            }
+            Err(err) => log::warn!("failed to load font {}: {}", ttf_path.display(), err),
+        }
+    }
+
+    let png_path = res.join("font.png");
+    if !png_path.is_file() {
+        log::info!("no font found at {}", png_path.display());
+        return None;
+    }
+    match Font::from_path(&ctx.device, &ctx.queue, &png_path) {
+        Ok(font) => Some(TextRenderer::new(&ctx.device, &ctx.config, font)),
+        Err(err) => {
+            log::warn!("failed to load font: {}", err);
+            None
+        }
+    }
+}
+
+/// Point lights around the grid of instances, for the deferred renderer.
+fn point_lights() -> Vec<PointLight> {
+    let colors = [
+        [1.0, 0.4, 0.3],
+        [0.3, 1.0, 0.4],
+        [0.3, 0.5, 1.0],
+        [1.0, 0.9, 0.5],
+    ];
+    colors
+        .iter()
+        .enumerate()
+        .map(|(i, color)| {
+            let angle = i as f32 / colors.len() as f32 * std::f32::consts::TAU;
+            PointLight {
+                position: [angle.cos() * 4.0, 1.5, angle.sin() * 4.0],
+                color: *color,
+                intensity: 12.0,
+                range: 8.0,
+            }
+        })
+        .collect()
+}
+
+/// Loads the terrain from `res/heightmap.png`, if it's there.
+fn load_terrain(device: &wgpu::Device) -> Option<Terrain> {
+    let path = std::path::Path::new(concat!(env!("CARGO_MANIFEST_DIR"), "/res/heightmap.png"));
+    if !path.is_file() {
+        log::info!("no height map found at {}", path.display());
+        return None;
+    }
+
+    let terrain = std::fs::read(path)
+        .map_err(|err| err.to_string())
+        .and_then(|bytes| {
+            Terrain::from_heightmap(
+                device,
+                &bytes,
+                TERRAIN_WIDTH,
+                TERRAIN_HEIGHT_SCALE,
+                TERRAIN_STEP,
+            )
+            .map_err(|err| err.to_string())
+        });
+    match terrain {
+        Ok(terrain) => Some(terrain),
+        Err(err) => {
+            log::warn!("failed to load terrain {}: {}", path.display(), err);
+            None
+        }
+    }
+}
+
+/// Towards the sun, which is the first light.
+fn sun_direction(lights: &[DirectionalLight]) -> [f32; 3] {
+    match lights.first() {
+        Some(light) => light.direction.map(|d| -d),
+        None => [0.0, 1.0, 0.0],
+    }
+}
+
+/// A staircase of grass topped stone, climbing along z.
+fn voxel_steps() -> VoxelGrid {
+    const STONE: u8 = 1;
+    const GRASS: u8 = 2;
+
+    let mut voxels = VoxelGrid::new(8, 8, 8);
+    voxels.set_color(STONE, [0.5, 0.5, 0.55]);
+    voxels.set_color(GRASS, [0.3, 0.7, 0.2]);
+    for z in 0..8 {
+        for x in 0..8 {
+            for y in 0..=z {
+                voxels.set(x, y, z, if y == z { GRASS } else { STONE });
+            }
+        }
+    }
+    voxels
+}
+
+/// A circle around the scene, looking at the middle while
+/// bobbing up and down.
+fn fly_through_path() -> SplinePath {
+    const POINTS: usize = 8;
+    let control_points = (0..=POINTS)
+        .map(|i| {
+            let angle = i as f32 / POINTS as f32 * std::f32::consts::TAU;
+            let height = if i % 2 == 0 { 1.0 } else { 0.5 } * FLY_THROUGH_HEIGHT;
+            CameraKeyframe {
+                position: [
+                    angle.cos() * FLY_THROUGH_RADIUS,
+                    height,
+                    angle.sin() * FLY_THROUGH_RADIUS,
+                ],
+                target: [0.0, 0.0, 0.0],
+                time: i as f32 / POINTS as f32 * FLY_THROUGH_PERIOD,
+            }
+        })
+        .collect();
+    SplinePath::new(control_points)
+}
+
+/// Bends every bone but the root to one side and back, so the
+/// chain sways like a reed. The bends add up along the chain.
+fn sway_clip(bone_count: usize) -> AnimationClip {
+    let bend = |angle: f32, time: f32| Keyframe {
+        time,
+        value: Transform::from_euler_xyz(0.0, 0.0, angle).rotation,
+    };
+    let channels = (1..bone_count)
+        .map(|bone| AnimChannel {
+            bone,
+            rotations: vec![
+                bend(-SWAY_ANGLE, 0.0),
+                bend(SWAY_ANGLE, SWAY_PERIOD * 0.5),
+                bend(-SWAY_ANGLE, SWAY_PERIOD),
+            ],
+            ..AnimChannel::default()
+        })
+        .collect();
+
+    AnimationClip {
+        name: "sway".to_string(),
+        duration: SWAY_PERIOD,
+        channels,
+    }
+}
+
+/// Loads the point cloud from the XYZ file given with `--points`.
+fn load_point_cloud(device: &wgpu::Device, queue: &wgpu::Queue) -> Option<PointCloud> {
+    let path = arg_value("--points")?;
+    match PointCloud::from_xyz_file(device, std::path::Path::new(&path)) {
+        Ok(mut point_cloud) => {
+            log::info!("loaded {} points from {}", point_cloud.len(), path);
+            point_cloud.set_transform(queue, Transform::from_translation(POINT_CLOUD_POSITION));
+            point_cloud.set_point_size(queue, POINT_CLOUD_POINT_SIZE);
+            Some(point_cloud)
+        }
+        Err(err) => {
+            log::warn!("failed to load point cloud {}: {}", path, err);
+            None
+        }
+    }
+}
+
+/// Loads the glTF scene given with `--gltf`, along with its path.
+fn load_gltf_scene(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    environment: &PbrEnvironment,
+) -> Option<(String, GltfScene)> {
+    let path = arg_value("--gltf")?;
+    match GltfLoader::load(device, queue, std::path::Path::new(&path), environment) {
+        Ok(scene) => {
+            log::info!(
+                "loaded {} meshes and {} materials from {}",
+                scene.meshes.len(),
+                scene.materials.len(),
+                path
+            );
+            Some((path, scene))
+        }
+        Err(err) => {
+            log::warn!("failed to load glTF scene {}: {}", path, err);
+            None
+        }
+    }
+}
+
+/// Tries to reconfigure a lost surface at its current size, drawing
+/// a frame after each attempt that doesn't fail outright, to find out
+/// whether it worked. Returns false if it's still lost after every
+/// attempt.
+fn recover_surface(state: &mut State, window: &Window) -> bool {
+    for attempt in 1..=MAX_RECONFIGURE_ATTEMPTS {
+        let size = state.ctx.size;
+        match state.resize(size).and_then(|()| state.render(window)) {
+            Err(wgpu::SurfaceError::Lost) => {
+                log::warn!("attempt {} to reconfigure the surface failed", attempt)
+            }
+            // Anything else is handled by the next frame.
+            _ => return true,
+        }
+    }
+    false
+}
+
+/// The value after `name` on the command line, if it's there.
+fn arg_value(name: &str) -> Option<String> {
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == name {
+            let value = args.next();
+            if value.is_none() {
+                log::warn!("{} needs a value", name);
+            }
+            return value;
+        }
+    }
+    None
 }
 
 fn main() {
@@ -372,18 +3403,37 @@ fn main() {
     let event_loop = EventLoop::new();
     let window = WindowBuilder::new().build(&event_loop).unwrap();
 
+    // `--adapter vulkan` or `--adapter nvidia` picks a specific GPU.
+    let adapter = arg_value("--adapter").map(|value| AdapterSelector::parse(&value));
+    // `--compat downlevel` runs on older GPUs, without the extras.
+    let mode = match arg_value("--compat") {
+        Some(value) => CompatMode::parse(&value).unwrap_or_else(|| {
+            log::warn!("unknown compatibility mode {:?}", value);
+            CompatMode::default()
+        }),
+        None => CompatMode::default(),
+    };
     // State::new uses async code, so we're going to wait for it to finish
-    let mut state = pollster::block_on(State::new(&window));
+    let mut state = pollster::block_on(State::new(&window, adapter.as_ref(), mode));
 
     event_loop.run(move |event, _, control_flow| {
         *control_flow = ControlFlow::Wait;
         match event {
             Event::RedrawRequested(_) => {
                 state.update();
-                match state.render() {
+                match state.render(&window) {
                     Ok(_) => {}
-                    // Reconfigure the surface if lost
-                    Err(wgpu::SurfaceError::Lost) => state.resize(state.size),
+                    // Reconfigure the surface if lost, giving up
+                    // if it stays lost
+                    Err(wgpu::SurfaceError::Lost) => {
+                        if !recover_surface(&mut state, &window) {
+                            eprintln!(
+                                "surface lost, and still lost after {} attempts to reconfigure it",
+                                MAX_RECONFIGURE_ATTEMPTS
+                            );
+                            *control_flow = ControlFlow::Exit;
+                        }
+                    }
                     // The system is out of memory, we should probably quit
                     Err(wgpu::SurfaceError::OutOfMemory) => *control_flow = ControlFlow::Exit,
                     // All other errors (Outdated, Timeout) should be resolved by the next frame
@@ -391,36 +3441,53 @@ fn main() {
                 }
             }
             Event::MainEventsCleared => {
+                state.reload_shader();
                 // RedrawRequested will only trigger once, unless we manually
                 // request it.
                 window.request_redraw();
             }
+            Event::DeviceEvent { ref event, .. } => state.device_input(event),
             Event::WindowEvent {
                 ref event,
                 window_id,
-            } if window_id == window.id() => {
-                if !state.input(event) {
-                    match event {
-                        WindowEvent::Resized(physical_size) => {
-                            state.resize(*physical_size);
+            } if window_id == window.id() && !state.input(event) => {
+                match event {
+                    WindowEvent::Resized(physical_size) => {
+                        if let Err(err) = state.resize(*physical_size) {
+                            eprintln!("failed to resize surface: {:?}", err);
                         }
-                        WindowEvent::ScaleFactorChanged { new_inner_size, .. } => {
-                            // new_inner_size is &&mut so we have to dereference it twice
-                            state.resize(**new_inner_size);
+                    }
+                    WindowEvent::ScaleFactorChanged {
+                        scale_factor,
+                        new_inner_size,
+                    } => {
+                        state.debug_ui.set_scale_factor(*scale_factor);
+                        // new_inner_size is &&mut so we have to dereference it twice
+                        if let Err(err) = state.resize(**new_inner_size) {
+                            eprintln!("failed to resize surface: {:?}", err);
                         }
-                        WindowEvent::CloseRequested
-                        | WindowEvent::KeyboardInput {
-                            input:
-                                KeyboardInput {
-                                    state: ElementState::Pressed,
-                                    virtual_keycode: Some(VirtualKeyCode::Escape),
-                                    ..
-                                },
-                            ..
-                        } => *control_flow = ControlFlow::Exit,
-                        _ => {}
-                    };
-                }
+                    }
+                    WindowEvent::KeyboardInput {
+                        input:
+                            KeyboardInput {
+                                state: ElementState::Pressed,
+                                virtual_keycode: Some(VirtualKeyCode::Tab),
+                                ..
+                            },
+                        ..
+                    } => state.toggle_cursor_grab(&window),
+                    WindowEvent::CloseRequested
+                    | WindowEvent::KeyboardInput {
+                        input:
+                            KeyboardInput {
+                                state: ElementState::Pressed,
+                                virtual_keycode: Some(VirtualKeyCode::Escape),
+                                ..
+                            },
+                        ..
+                    } => *control_flow = ControlFlow::Exit,
+                    _ => {}
+                };
             }
             _ => {}
         }
@@ -0,0 +1,173 @@
+use std::{collections::HashMap, sync::Arc};
+
+use wgpu::util::DeviceExt;
+
+use crate::dithered_transparency::DitheredTransparency;
+
+/// How a material's fragments are combined with what's already drawn.
+///
+/// Ordered the way materials should be drawn, so opaque geometry
+/// is in place before anything is blended over it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum BlendMode {
+    Opaque,
+    /// Drawn like opaque materials, but with some of the pixels left
+    /// out depending on the alpha. See [`DitheredTransparency`].
+    Dithered,
+    #[allow(dead_code)]
+    AlphaBlend,
+    #[allow(dead_code)]
+    Additive,
+}
+
+impl BlendMode {
+    /// The blend state a pipeline for this mode should be built with.
+    #[allow(dead_code)]
+    pub fn blend_state(self) -> wgpu::BlendState {
+        match self {
+            BlendMode::Opaque | BlendMode::Dithered => wgpu::BlendState::REPLACE,
+            BlendMode::AlphaBlend => wgpu::BlendState::ALPHA_BLENDING,
+            BlendMode::Additive => wgpu::BlendState {
+                color: wgpu::BlendComponent {
+                    src_factor: wgpu::BlendFactor::SrcAlpha,
+                    dst_factor: wgpu::BlendFactor::One,
+                    operation: wgpu::BlendOperation::Add,
+                },
+                alpha: wgpu::BlendComponent::OVER,
+            },
+        }
+    }
+}
+
+/// Per-material values, seen by the fragment shader.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct MaterialUniform {
+    /// Multiplied with the lit color of the surface.
+    pub color: [f32; 4],
+    /// How metal the surface is, from 0 to 1. Only used by the
+    /// deferred renderer.
+    pub metallic: f32,
+    /// From 0 for a mirror to 1 for a fully matte surface.
+    pub roughness: f32,
+    /// Nonzero to dither the surface by the alpha of `color`, for
+    /// materials using [`BlendMode::Dithered`].
+    pub dithered: u32,
+    pub _padding: f32,
+}
+
+/// The pipeline and bind group a mesh is drawn with.
+pub struct Material {
+    pub pipeline: Arc<wgpu::RenderPipeline>,
+    pub bind_group: wgpu::BindGroup,
+    pub blend_mode: BlendMode,
+}
+
+impl Material {
+    /// Materials with equal keys can be drawn without switching pipelines.
+    fn sort_key(&self) -> (BlendMode, *const wgpu::RenderPipeline) {
+        (self.blend_mode, Arc::as_ptr(&self.pipeline))
+    }
+}
+
+/// Materials by name, along with the layout of their bind groups.
+///
+/// Every material's bind group has its uniform at binding 0, and
+/// the dither matrix at binding 1.
+pub struct MaterialLibrary {
+    materials: HashMap<String, Material>,
+    bind_group_layout: wgpu::BindGroupLayout,
+    dithering: DitheredTransparency,
+}
+
+impl MaterialLibrary {
+    /// Entries in each material's bind group.
+    pub const BIND_GROUP_ENTRIES: usize = 2;
+
+    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue) -> Self {
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Material Bind Group Layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                DitheredTransparency::bind_group_layout_entry(1),
+            ],
+        });
+
+        MaterialLibrary {
+            materials: HashMap::new(),
+            bind_group_layout,
+            dithering: DitheredTransparency::new(device, queue),
+        }
+    }
+
+    /// Layout of the bind groups created by [`MaterialLibrary::create_bind_group`],
+    /// for the pipeline layouts of material pipelines.
+    pub fn bind_group_layout(&self) -> &wgpu::BindGroupLayout {
+        &self.bind_group_layout
+    }
+
+    pub fn create_bind_group(
+        &self,
+        device: &wgpu::Device,
+        label: &str,
+        uniform: &MaterialUniform,
+    ) -> wgpu::BindGroup {
+        let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some(label),
+            contents: bytemuck::bytes_of(uniform),
+            usage: wgpu::BufferUsages::UNIFORM,
+        });
+
+        // The bind group keeps the buffer alive after it's dropped here.
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some(label),
+            layout: &self.bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: buffer.as_entire_binding(),
+                },
+                self.dithering.bind_group_entry(1),
+            ],
+        })
+    }
+
+    /// Adds a material, returning the one it replaces.
+    pub fn register(&mut self, name: &str, material: Material) -> Option<Material> {
+        self.materials.insert(name.to_string(), material)
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Material> {
+        self.materials.get(name)
+    }
+
+    /// Switches the materials drawn with `old` over to `new`,
+    /// for when a pipeline is rebuilt.
+    pub fn replace_pipeline(
+        &mut self,
+        old: &Arc<wgpu::RenderPipeline>,
+        new: &Arc<wgpu::RenderPipeline>,
+    ) {
+        for material in self.materials.values_mut() {
+            if Arc::ptr_eq(&material.pipeline, old) {
+                material.pipeline = new.clone();
+            }
+        }
+    }
+}
+
+/// Orders draws so the ones sharing a pipeline are next to each other,
+/// with opaque materials first. Draws with the same material keep
+/// the order they were in.
+pub fn sort_draws<T>(draws: &mut [(&Material, T)]) {
+    draws.sort_by_key(|(material, _)| material.sort_key());
+}
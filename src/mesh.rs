@@ -0,0 +1,103 @@
+use std::ops::Range;
+
+use crate::{
+    bounds::Aabb,
+    index::{Index, IndexBuffer},
+    render_bundle::RenderBundleRecorder,
+    vertex::{VertexBuffer, TANGENT_SLOT},
+};
+
+/// Geometry uploaded to the GPU, ready to be drawn.
+pub struct Mesh {
+    pub vertex_buffer: VertexBuffer,
+    /// All zeros unless the mesh was given tangents, which pipelines
+    /// that don't read them can leave bound.
+    pub tangent_buffer: VertexBuffer,
+    pub index_buffer: IndexBuffer,
+    /// The topology the pipeline drawing this mesh should be created with.
+    #[allow(dead_code)]
+    pub topology: wgpu::PrimitiveTopology,
+    pub index_count: u32,
+    /// Name of the material in the [`MaterialLibrary`](crate::material::MaterialLibrary)
+    /// to draw the mesh with, or the default material when `None`.
+    #[allow(dead_code)]
+    pub material_name: Option<String>,
+    /// Box around the vertices, for culling. Meshes without
+    /// bounds are always drawn.
+    pub bounds: Option<Aabb>,
+}
+
+impl Mesh {
+    pub fn upload<V, I>(device: &wgpu::Device, vertices: &[V], indices: &[I]) -> Self
+    where
+        V: bytemuck::Pod,
+        I: Index,
+    {
+        let vertex_buffer = VertexBuffer::from_slice(device, vertices);
+        let tangent_buffer = VertexBuffer::from_slice(device, &vec![[0.0f32; 4]; vertices.len()]);
+        let index_buffer = IndexBuffer::from_slice(device, indices);
+        let index_count = index_buffer.len();
+
+        Mesh {
+            vertex_buffer,
+            tangent_buffer,
+            index_buffer,
+            topology: wgpu::PrimitiveTopology::TriangleList,
+            index_count,
+            material_name: None,
+            bounds: None,
+        }
+    }
+
+    /// Gives the mesh a box around its vertices, so it can be culled.
+    pub fn with_bounds(mut self, bounds: Aabb) -> Self {
+        self.bounds = Some(bounds);
+        self
+    }
+
+    /// Gives the mesh a tangent for each vertex, with the direction of
+    /// the bitangent in `w`, for normal maps made with them.
+    pub fn with_tangents(mut self, device: &wgpu::Device, tangents: &[[f32; 4]]) -> Self {
+        self.tangent_buffer = VertexBuffer::from_slice(device, tangents);
+        self
+    }
+
+    /// Binds the mesh's buffers and draws a single instance.
+    pub fn draw<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>) {
+        self.draw_instanced(render_pass, 0..1);
+    }
+
+    /// Binds the mesh's buffers and draws the given range of instances.
+    ///
+    /// The instance buffer, if the pipeline uses one, must be bound
+    /// by the caller.
+    pub fn draw_instanced<'a>(
+        &'a self,
+        render_pass: &mut wgpu::RenderPass<'a>,
+        instances: Range<u32>,
+    ) {
+        // Vertex buffer must be set, otherwise program will crash.
+        render_pass.set_vertex_buffer(0, self.vertex_buffer.slice());
+        render_pass.set_vertex_buffer(TANGENT_SLOT, self.tangent_buffer.slice());
+        // The method name is `set_index_buffer` not `set_index_buffers`.
+        // We can only have one index buffer set at a time.
+        render_pass.set_index_buffer(self.index_buffer.slice(), self.index_buffer.format());
+
+        // When using an index buffer, you need to use draw_indexed.
+        // The draw method ignores the index buffer.
+        render_pass.draw_indexed(0..self.index_count, 0, instances);
+    }
+
+    /// Records the mesh's buffers and a draw of the given range of
+    /// instances into a bundle, the same way as [`draw_instanced`](Self::draw_instanced).
+    pub fn record_instanced<'a>(
+        &'a self,
+        recorder: &mut RenderBundleRecorder<'a>,
+        instances: Range<u32>,
+    ) {
+        recorder.set_vertex_buffer(0, self.vertex_buffer.slice());
+        recorder.set_vertex_buffer(TANGENT_SLOT, self.tangent_buffer.slice());
+        recorder.set_index_buffer(self.index_buffer.slice(), self.index_buffer.format());
+        recorder.draw_indexed(0..self.index_count, 0, instances);
+    }
+}
@@ -0,0 +1,617 @@
+use std::{
+    cmp::Ordering,
+    collections::{BinaryHeap, HashMap},
+};
+
+use cgmath::{InnerSpace, SquareMatrix};
+
+use crate::vertex::Vertex;
+
+/// Positions closer than this are welded together before simplifying,
+/// so the seams where vertices were split for their normals or texture
+/// coordinates don't come apart.
+const WELD_EPSILON: f64 = 1e-5;
+
+/// How much more it costs to move a vertex off a boundary than off a
+/// surface, so open edges keep their shape.
+const BOUNDARY_WEIGHT: f64 = 1000.0;
+
+type Vec3 = cgmath::Vector3<f64>;
+
+/// The sum of the squared distances to a set of planes, as the
+/// symmetric 4x4 matrix of the planes' outer products, of which only
+/// the upper triangle is kept.
+#[derive(Debug, Copy, Clone, Default)]
+struct Quadric([f64; 10]);
+
+impl Quadric {
+    /// The squared distance to the plane `normal·x + d = 0`, scaled
+    /// by `weight`. `normal` has to be normalized.
+    fn from_plane(normal: Vec3, d: f64, weight: f64) -> Self {
+        let (a, b, c) = (normal.x, normal.y, normal.z);
+        Quadric(
+            [
+                a * a,
+                a * b,
+                a * c,
+                a * d,
+                b * b,
+                b * c,
+                b * d,
+                c * c,
+                c * d,
+                d * d,
+            ]
+            .map(|q| q * weight),
+        )
+    }
+
+    fn add(&self, other: &Quadric) -> Quadric {
+        let mut sum = *self;
+        for (q, o) in sum.0.iter_mut().zip(&other.0) {
+            *q += o;
+        }
+        sum
+    }
+
+    /// The error of moving a vertex with this quadric to `v`.
+    fn error(&self, v: Vec3) -> f64 {
+        let [a2, ab, ac, ad, b2, bc, bd, c2, cd, d2] = self.0;
+        a2 * v.x * v.x
+            + 2.0 * ab * v.x * v.y
+            + 2.0 * ac * v.x * v.z
+            + 2.0 * ad * v.x
+            + b2 * v.y * v.y
+            + 2.0 * bc * v.y * v.z
+            + 2.0 * bd * v.y
+            + c2 * v.z * v.z
+            + 2.0 * cd * v.z
+            + d2
+    }
+
+    /// Where the error is smallest, unless the planes don't pin down
+    /// a single point, like when they're all parallel.
+    fn optimal(&self) -> Option<Vec3> {
+        let [a2, ab, ac, ad, b2, bc, bd, c2, cd, _] = self.0;
+        let m = cgmath::Matrix3::new(a2, ab, ac, ab, b2, bc, ac, bc, c2);
+        if m.determinant().abs() < 1e-12 {
+            return None;
+        }
+        m.invert().map(|inv| inv * -cgmath::vec3(ad, bd, cd))
+    }
+}
+
+/// An edge collapse waiting in the queue, with the versions of both
+/// ends when it was worked out. Once either end changes, it's stale.
+#[derive(Debug, Copy, Clone)]
+struct Collapse {
+    error: f64,
+    keep: usize,
+    remove: usize,
+    target: Vec3,
+    versions: (u32, u32),
+}
+
+impl PartialEq for Collapse {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Collapse {}
+
+impl PartialOrd for Collapse {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Collapse {
+    /// Reversed, so the heap pops the cheapest collapse first.
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.error.total_cmp(&self.error)
+    }
+}
+
+/// Reduces the number of triangles in a mesh, for its less detailed
+/// levels of detail, with Garland and Heckbert's quadric error metric.
+///
+/// Every position gets a quadric measuring how far a point is from the
+/// planes of the triangles around it. Edges are collapsed into a single
+/// position one at a time, cheapest first, where the cost is how far
+/// the position ends up from the planes of both ends. Collapses that
+/// would fold a triangle over, or join two sides of the mesh that only
+/// touch at the edge, are skipped, so the topology stays valid.
+///
+/// Vertices only hold on to their other attributes, so normals and
+/// texture coordinates are those of the vertices that are kept.
+pub struct MeshSimplifier;
+
+impl MeshSimplifier {
+    /// Collapses edges of the mesh until `target_ratio` of its
+    /// triangles are left, or until no edge can be collapsed without
+    /// breaking the mesh. Vertices that aren't used anymore are left
+    /// out of the result.
+    pub fn simplify(
+        vertices: &[Vertex],
+        indices: &[u16],
+        target_ratio: f32,
+    ) -> (Vec<Vertex>, Vec<u16>) {
+        let mut simplification = Simplification::new(vertices, indices);
+        let target = (simplification.alive as f32 * target_ratio.clamp(0.0, 1.0)).ceil() as usize;
+        simplification.run(target);
+        simplification.finish(vertices)
+    }
+}
+
+/// The state of a mesh part way through being simplified.
+///
+/// Edges are between positions, which vertices at the same point
+/// share, rather than between vertices.
+struct Simplification {
+    positions: Vec<Vec3>,
+    quadrics: Vec<Quadric>,
+    /// Bumped every time a position moves, which invalidates the
+    /// collapses it's part of in the queue.
+    versions: Vec<u32>,
+    /// Whether a position was collapsed into another.
+    removed: Vec<bool>,
+    /// The position of each vertex.
+    vertex_positions: Vec<usize>,
+    /// The vertices at each position.
+    position_vertices: Vec<Vec<usize>>,
+    /// The triangles around each position, including some that have
+    /// since collapsed.
+    position_triangles: Vec<Vec<usize>>,
+    /// The vertex at each corner of the triangles.
+    triangles: Vec<[usize; 3]>,
+    collapsed: Vec<bool>,
+    /// Triangles that haven't collapsed.
+    alive: usize,
+    queue: BinaryHeap<Collapse>,
+}
+
+impl Simplification {
+    fn new(vertices: &[Vertex], indices: &[u16]) -> Self {
+        // Welds the vertices by their positions, rounded to the epsilon.
+        let mut welded = HashMap::new();
+        let mut positions = Vec::new();
+        let mut position_vertices = Vec::<Vec<usize>>::new();
+        let vertex_positions = vertices
+            .iter()
+            .enumerate()
+            .map(|(i, vertex)| {
+                let p = Vec3::from(vertex.position.map(f64::from));
+                let key = [p.x, p.y, p.z].map(|c| (c / WELD_EPSILON).round() as i64);
+                let position = *welded.entry(key).or_insert_with(|| {
+                    positions.push(p);
+                    position_vertices.push(Vec::new());
+                    positions.len() - 1
+                });
+                position_vertices[position].push(i);
+                position
+            })
+            .collect::<Vec<_>>();
+
+        let mut simplification = Simplification {
+            quadrics: vec![Quadric::default(); positions.len()],
+            versions: vec![0; positions.len()],
+            removed: vec![false; positions.len()],
+            position_triangles: vec![Vec::new(); positions.len()],
+            positions,
+            vertex_positions,
+            position_vertices,
+            triangles: Vec::new(),
+            collapsed: Vec::new(),
+            alive: 0,
+            queue: BinaryHeap::new(),
+        };
+
+        // Triangles that are already degenerate draw nothing, so they're
+        // dropped, which also keeps them out of the quadrics.
+        for corners in indices.chunks_exact(3) {
+            let triangle = [0, 1, 2].map(|i| corners[i] as usize);
+            let [a, b, c] = triangle.map(|v| simplification.vertex_positions[v]);
+            if a == b || b == c || c == a || simplification.normal(triangle).is_none() {
+                continue;
+            }
+            let t = simplification.triangles.len();
+            simplification.triangles.push(triangle);
+            simplification.collapsed.push(false);
+            for p in [a, b, c] {
+                simplification.position_triangles[p].push(t);
+            }
+        }
+        simplification.alive = simplification.triangles.len();
+
+        simplification.add_plane_quadrics();
+        simplification.add_boundary_quadrics();
+        for p in 0..simplification.positions.len() {
+            for neighbour in simplification.neighbours(p) {
+                if p < neighbour {
+                    simplification.push_collapse(p, neighbour);
+                }
+            }
+        }
+        simplification
+    }
+
+    /// The positions of a triangle's corners.
+    fn corners(&self, triangle: [usize; 3]) -> [Vec3; 3] {
+        triangle.map(|v| self.positions[self.vertex_positions[v]])
+    }
+
+    /// The triangle's normal, scaled by twice its area, unless it has
+    /// no area.
+    fn normal(&self, triangle: [usize; 3]) -> Option<Vec3> {
+        let [a, b, c] = self.corners(triangle);
+        let normal = (b - a).cross(c - a);
+        if normal.magnitude2() > 1e-24 {
+            Some(normal)
+        } else {
+            None
+        }
+    }
+
+    /// Every triangle adds its plane to the quadrics of its corners,
+    /// weighted by its area, so small triangles count for less.
+    fn add_plane_quadrics(&mut self) {
+        for t in 0..self.triangles.len() {
+            let triangle = self.triangles[t];
+            let normal = match self.normal(triangle) {
+                Some(normal) => normal,
+                None => continue,
+            };
+            let area = normal.magnitude() * 0.5;
+            let normal = normal.normalize();
+            let [a, _, _] = self.corners(triangle);
+            let quadric = Quadric::from_plane(normal, -normal.dot(a), area);
+            for v in triangle {
+                let p = self.vertex_positions[v];
+                self.quadrics[p] = self.quadrics[p].add(&quadric);
+            }
+        }
+    }
+
+    /// Edges with a triangle on only one side add a plane through the
+    /// edge, at a right angle to the triangle, so moving along the
+    /// boundary is cheap and moving off it costly.
+    fn add_boundary_quadrics(&mut self) {
+        let mut edges = HashMap::<(usize, usize), Vec<usize>>::new();
+        for (t, triangle) in self.triangles.iter().enumerate() {
+            for i in 0..3 {
+                let a = self.vertex_positions[triangle[i]];
+                let b = self.vertex_positions[triangle[(i + 1) % 3]];
+                edges.entry((a.min(b), a.max(b))).or_default().push(t);
+            }
+        }
+
+        for ((a, b), triangles) in edges {
+            if triangles.len() != 1 {
+                continue;
+            }
+            let normal = match self.normal(self.triangles[triangles[0]]) {
+                Some(normal) => normal.normalize(),
+                None => continue,
+            };
+            let edge = self.positions[b] - self.positions[a];
+            let side = edge.cross(normal);
+            if side.magnitude2() < 1e-24 {
+                continue;
+            }
+            let side = side.normalize();
+            let quadric = Quadric::from_plane(
+                side,
+                -side.dot(self.positions[a]),
+                BOUNDARY_WEIGHT * edge.magnitude2(),
+            );
+            self.quadrics[a] = self.quadrics[a].add(&quadric);
+            self.quadrics[b] = self.quadrics[b].add(&quadric);
+        }
+    }
+
+    /// The triangles around `p` that haven't collapsed.
+    fn triangles_around(&self, p: usize) -> impl Iterator<Item = usize> + '_ {
+        self.position_triangles[p]
+            .iter()
+            .copied()
+            .filter(move |t| !self.collapsed[*t])
+    }
+
+    /// The positions sharing an edge with `p`.
+    fn neighbours(&self, p: usize) -> Vec<usize> {
+        let mut neighbours = Vec::new();
+        for t in self.triangles_around(p) {
+            for v in self.triangles[t] {
+                let q = self.vertex_positions[v];
+                if q != p && !neighbours.contains(&q) {
+                    neighbours.push(q);
+                }
+            }
+        }
+        neighbours
+    }
+
+    /// Works out where the edge between `a` and `b` would best be
+    /// collapsed to, and queues it.
+    fn push_collapse(&mut self, a: usize, b: usize) {
+        let quadric = self.quadrics[a].add(&self.quadrics[b]);
+        let (pa, pb) = (self.positions[a], self.positions[b]);
+        let target = quadric
+            .optimal()
+            .into_iter()
+            .chain([pa, pb, (pa + pb) * 0.5])
+            .map(|target| (quadric.error(target), target))
+            .min_by(|x, y| x.0.total_cmp(&y.0));
+        if let Some((error, target)) = target {
+            self.queue.push(Collapse {
+                error,
+                keep: a,
+                remove: b,
+                target,
+                versions: (self.versions[a], self.versions[b]),
+            });
+        }
+    }
+
+    /// Collapses the cheapest edges until there are only `target`
+    /// triangles left.
+    fn run(&mut self, target: usize) {
+        while self.alive > target {
+            let collapse = match self.queue.pop() {
+                Some(collapse) => collapse,
+                None => break,
+            };
+            let (keep, remove) = (collapse.keep, collapse.remove);
+            let stale = self.removed[keep]
+                || self.removed[remove]
+                || collapse.versions != (self.versions[keep], self.versions[remove]);
+            if stale || !self.can_collapse(keep, remove, collapse.target) {
+                continue;
+            }
+            self.collapse(keep, remove, collapse.target);
+        }
+    }
+
+    /// Whether the edge between `keep` and `remove` can be collapsed to
+    /// `target` without breaking the mesh.
+    fn can_collapse(&self, keep: usize, remove: usize, target: Vec3) -> bool {
+        // The link condition: the only positions both ends share are
+        // the corners opposite the edge, of the triangles on it. Any
+        // other would end up joined to the collapsed position by two
+        // edges in the same place.
+        let shared_triangles = self
+            .triangles_around(keep)
+            .filter(|t| self.has_position(*t, remove))
+            .count();
+        let keep_neighbours = self.neighbours(keep);
+        let shared_neighbours = self
+            .neighbours(remove)
+            .into_iter()
+            .filter(|q| keep_neighbours.contains(q))
+            .count();
+        if shared_triangles == 0 || shared_neighbours != shared_triangles {
+            return false;
+        }
+
+        // The link condition holds around a tetrahedron too, where the
+        // triangles off the edge would end up on top of each other.
+        let sorted_positions = |t: usize, moved: usize| {
+            let mut positions = self.triangles[t].map(|v| match self.vertex_positions[v] {
+                q if q == moved => remove,
+                q => q,
+            });
+            positions.sort_unstable();
+            positions
+        };
+        let remove_triangles = self
+            .triangles_around(remove)
+            .map(|t| sorted_positions(t, remove))
+            .collect::<Vec<_>>();
+        let doubles_up = self
+            .triangles_around(keep)
+            .filter(|t| !self.has_position(*t, remove))
+            .any(|t| remove_triangles.contains(&sorted_positions(t, keep)));
+        if doubles_up {
+            return false;
+        }
+
+        // The rest of the triangles around either end must not fold
+        // over, or shrink to nothing, once the end moves.
+        for p in [keep, remove] {
+            for t in self.triangles_around(p) {
+                if self.has_position(t, keep) && self.has_position(t, remove) {
+                    continue;
+                }
+                let triangle = self.triangles[t];
+                let before = match self.normal(triangle) {
+                    Some(normal) => normal,
+                    None => return false,
+                };
+                let [a, b, c] = triangle.map(|v| match self.vertex_positions[v] {
+                    q if q == p => target,
+                    q => self.positions[q],
+                });
+                let after = (b - a).cross(c - a);
+                if after.magnitude2() <= 1e-24 || before.dot(after) <= 0.0 {
+                    return false;
+                }
+            }
+        }
+        true
+    }
+
+    fn has_position(&self, t: usize, p: usize) -> bool {
+        self.triangles[t]
+            .iter()
+            .any(|v| self.vertex_positions[*v] == p)
+    }
+
+    /// Moves `keep` to `target` and joins `remove` into it, dropping
+    /// the triangles on the edge between them.
+    fn collapse(&mut self, keep: usize, remove: usize, target: Vec3) {
+        self.positions[keep] = target;
+        self.quadrics[keep] = self.quadrics[keep].add(&self.quadrics[remove]);
+        self.versions[keep] += 1;
+        self.removed[remove] = true;
+
+        let moved = std::mem::take(&mut self.position_vertices[remove]);
+        for v in &moved {
+            self.vertex_positions[*v] = keep;
+        }
+        self.position_vertices[keep].extend(moved);
+
+        let triangles = std::mem::take(&mut self.position_triangles[remove]);
+        for t in triangles {
+            if self.collapsed[t] {
+                continue;
+            }
+            if self.has_position_twice(t, keep) {
+                self.collapsed[t] = true;
+                self.alive -= 1;
+            } else {
+                self.position_triangles[keep].push(t);
+            }
+        }
+        let collapsed = &self.collapsed;
+        self.position_triangles[keep].retain(|t| !collapsed[*t]);
+
+        for neighbour in self.neighbours(keep) {
+            self.push_collapse(keep, neighbour);
+        }
+    }
+
+    fn has_position_twice(&self, t: usize, p: usize) -> bool {
+        self.triangles[t]
+            .iter()
+            .filter(|v| self.vertex_positions[**v] == p)
+            .count()
+            > 1
+    }
+
+    /// The vertices still in use, moved to their positions, and the
+    /// triangles that haven't collapsed, indexing them.
+    fn finish(&self, vertices: &[Vertex]) -> (Vec<Vertex>, Vec<u16>) {
+        let mut remap = vec![None; vertices.len()];
+        let mut simplified = Vec::new();
+        let mut indices = Vec::with_capacity(self.alive * 3);
+        for (t, triangle) in self.triangles.iter().enumerate() {
+            if self.collapsed[t] {
+                continue;
+            }
+            for &v in triangle {
+                let index = *remap[v].get_or_insert_with(|| {
+                    let p = self.positions[self.vertex_positions[v]];
+                    simplified.push(Vertex {
+                        position: [p.x as f32, p.y as f32, p.z as f32],
+                        ..vertices[v]
+                    });
+                    simplified.len() as u16 - 1
+                });
+                indices.push(index);
+            }
+        }
+        (simplified, indices)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{bounds::Aabb, primitives};
+
+    /// Checks every index is in range, no triangle has lost its area,
+    /// and, for a closed mesh, that every edge has a triangle on each
+    /// side.
+    fn assert_valid(vertices: &[Vertex], indices: &[u16], closed: bool) {
+        assert_eq!(indices.len() % 3, 0);
+        let position = |i: u16| {
+            vertices[i as usize]
+                .position
+                .map(|c| (c * 1e4).round() as i64)
+        };
+        let mut edges = HashMap::<_, usize>::new();
+        for triangle in indices.chunks(3) {
+            let [a, b, c] = [0, 1, 2]
+                .map(|i| Vec3::from(vertices[triangle[i] as usize].position.map(f64::from)));
+            assert!(
+                (b - a).cross(c - a).magnitude2() > 1e-12,
+                "triangle {:?} is degenerate",
+                triangle
+            );
+            for i in 0..3 {
+                let (a, b) = (position(triangle[i]), position(triangle[(i + 1) % 3]));
+                *edges.entry((a.min(b), a.max(b))).or_default() += 1;
+            }
+        }
+        if closed {
+            assert!(
+                edges.values().all(|&count| count == 2),
+                "the mesh has holes"
+            );
+        }
+    }
+
+    /// A flat square of `n` by `n` quads, on the XY plane.
+    fn grid(n: u16) -> (Vec<Vertex>, Vec<u16>) {
+        let mut vertices = Vec::new();
+        for y in 0..=n {
+            for x in 0..=n {
+                vertices.push(Vertex {
+                    position: [x as f32, y as f32, 0.0],
+                    color: [1.0; 3],
+                    normal: [0.0, 0.0, 1.0],
+                    tex_coords: [x as f32, y as f32],
+                });
+            }
+        }
+        let mut indices = Vec::new();
+        for y in 0..n {
+            for x in 0..n {
+                let a = y * (n + 1) + x;
+                let b = a + n + 1;
+                indices.extend_from_slice(&[a, a + 1, b + 1, a, b + 1, b]);
+            }
+        }
+        (vertices, indices)
+    }
+
+    #[test]
+    fn simplifies_cube_to_half() {
+        let (vertices, indices) = primitives::cube(0.5);
+        let (vertices, indices) = MeshSimplifier::simplify(&vertices, &indices, 0.5);
+        assert_eq!(indices.len() / 3, 6);
+        assert_valid(&vertices, &indices, true);
+    }
+
+    #[test]
+    fn stops_at_a_tetrahedron() {
+        let (vertices, indices) = primitives::cube(0.5);
+        let (vertices, indices) = MeshSimplifier::simplify(&vertices, &indices, 0.0);
+        assert_eq!(indices.len() / 3, 4);
+        assert_valid(&vertices, &indices, true);
+    }
+
+    #[test]
+    fn full_ratio_keeps_every_triangle() {
+        let (vertices, indices) = primitives::sphere(1.0, 8, 12);
+        let (_, simplified_indices) = MeshSimplifier::simplify(&vertices, &indices, 1.0);
+        assert_eq!(simplified_indices.len(), indices.len());
+    }
+
+    #[test]
+    fn flat_grid_keeps_its_plane_and_outline() {
+        let (vertices, indices) = grid(4);
+        let (simplified_vertices, simplified_indices) =
+            MeshSimplifier::simplify(&vertices, &indices, 0.25);
+        assert_eq!(simplified_indices.len() / 3, 8);
+        assert_valid(&simplified_vertices, &simplified_indices, false);
+        assert!(simplified_vertices
+            .iter()
+            .all(|vertex| vertex.position[2] == 0.0));
+        assert_eq!(
+            Aabb::from_vertices(&simplified_vertices),
+            Aabb::from_vertices(&vertices)
+        );
+    }
+}
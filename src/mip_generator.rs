@@ -0,0 +1,347 @@
+/// Formats the mip generator can write, with the name of each in
+/// the shader's storage texture.
+const FORMATS: [(wgpu::TextureFormat, &str); 2] = [
+    (wgpu::TextureFormat::Rgba8Unorm, "rgba8unorm"),
+    (wgpu::TextureFormat::Rgba16Float, "rgba16float"),
+];
+
+/// The workgroup size of the shader.
+const WORKGROUP_SIZE: u32 = 8;
+
+/// Fills in the mip levels of a texture on the GPU, since wgpu
+/// doesn't generate them itself.
+///
+/// Each level is downsampled from the one above it by a compute
+/// shader, which writes it as a storage texture. Textures to be
+/// mipped need the [`MipGenerator::texture_usages`] on top of their
+/// own. sRGB formats can't be storage textures, so only
+/// `Rgba8Unorm` and `Rgba16Float` textures can be mipped.
+pub struct MipGenerator {
+    sampler: wgpu::Sampler,
+    /// A layout and pipeline for each of the [`FORMATS`].
+    bind_group_layouts: Vec<wgpu::BindGroupLayout>,
+    pipelines: Vec<wgpu::ComputePipeline>,
+}
+
+impl MipGenerator {
+    pub fn new(device: &wgpu::Device) -> Self {
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Mip Generator Sampler"),
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            ..Default::default()
+        });
+
+        let source = include_str!("mip_generator.wgsl");
+        let (bind_group_layouts, pipelines) = FORMATS
+            .iter()
+            .map(|(format, name)| {
+                let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                    label: Some("Mip Generator Bind Group Layout"),
+                    entries: &[
+                        wgpu::BindGroupLayoutEntry {
+                            binding: 0,
+                            visibility: wgpu::ShaderStages::COMPUTE,
+                            ty: wgpu::BindingType::Texture {
+                                multisampled: false,
+                                view_dimension: wgpu::TextureViewDimension::D2,
+                                sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                            },
+                            count: None,
+                        },
+                        wgpu::BindGroupLayoutEntry {
+                            binding: 1,
+                            visibility: wgpu::ShaderStages::COMPUTE,
+                            ty: wgpu::BindingType::Sampler {
+                                filtering: true,
+                                comparison: false,
+                            },
+                            count: None,
+                        },
+                        wgpu::BindGroupLayoutEntry {
+                            binding: 2,
+                            visibility: wgpu::ShaderStages::COMPUTE,
+                            ty: wgpu::BindingType::StorageTexture {
+                                access: wgpu::StorageTextureAccess::WriteOnly,
+                                format: *format,
+                                view_dimension: wgpu::TextureViewDimension::D2,
+                            },
+                            count: None,
+                        },
+                    ],
+                });
+                let pipeline_layout =
+                    device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                        label: Some("Mip Generator Pipeline Layout"),
+                        bind_group_layouts: &[&layout],
+                        push_constant_ranges: &[],
+                    });
+                // Storage textures name their format in the shader, so
+                // there's a shader for each.
+                let shader = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+                    label: Some("Mip Generator Shader"),
+                    source: wgpu::ShaderSource::Wgsl(source.replace("rgba8unorm", name).into()),
+                });
+                let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                    label: Some("Mip Generator Pipeline"),
+                    layout: Some(&pipeline_layout),
+                    module: &shader,
+                    entry_point: "main",
+                });
+                (layout, pipeline)
+            })
+            .unzip();
+
+        MipGenerator {
+            sampler,
+            bind_group_layouts,
+            pipelines,
+        }
+    }
+
+    /// Whether textures can be mipped on this device at all. The
+    /// shader writes the levels as storage textures, which devices
+    /// without compute shaders, like WebGL, don't have.
+    pub fn is_supported(adapter: &wgpu::Adapter, device: &wgpu::Device) -> bool {
+        adapter
+            .get_downlevel_properties()
+            .flags
+            .contains(wgpu::DownlevelFlags::COMPUTE_SHADERS)
+            && device.limits().max_storage_textures_per_shader_stage > 0
+    }
+
+    /// Whether textures in `format` can be mipped.
+    pub fn supports_format(format: wgpu::TextureFormat) -> bool {
+        FORMATS.iter().any(|(supported, _)| *supported == format)
+    }
+
+    /// The usages a texture needs to be mipped, for adding to its
+    /// own. `STORAGE_BINDING` is only asked for when the device has
+    /// storage textures, since creating the texture would fail
+    /// otherwise, and the texture can still be used without its mips.
+    pub fn texture_usages(device: &wgpu::Device) -> wgpu::TextureUsages {
+        let mut usages = wgpu::TextureUsages::TEXTURE_BINDING;
+        if device.limits().max_storage_textures_per_shader_stage > 0 {
+            usages |= wgpu::TextureUsages::STORAGE_BINDING;
+        }
+        usages
+    }
+
+    /// Downsamples each of the first `mip_count` levels of `texture`
+    /// from the one above it, starting with level 1 from level 0.
+    /// `size` is the size of level 0, which works best as a power of
+    /// two, so every texel is averaged from a whole 2x2 block.
+    ///
+    /// Textures in formats that can't be mipped are warned about and
+    /// left alone.
+    pub fn generate(
+        &self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        texture: &wgpu::Texture,
+        format: wgpu::TextureFormat,
+        size: wgpu::Extent3d,
+        mip_count: u32,
+    ) {
+        let index = match FORMATS
+            .iter()
+            .position(|(supported, _)| *supported == format)
+        {
+            Some(index) => index,
+            None => {
+                log::warn!("mips can't be generated for {:?} textures", format);
+                return;
+            }
+        };
+
+        let views = (0..mip_count)
+            .map(|level| {
+                texture.create_view(&wgpu::TextureViewDescriptor {
+                    label: Some("Mip Generator View"),
+                    base_mip_level: level,
+                    mip_level_count: std::num::NonZeroU32::new(1),
+                    ..Default::default()
+                })
+            })
+            .collect::<Vec<_>>();
+
+        // Each level is read by the dispatch after the one writing it.
+        let bind_groups = (1..views.len())
+            .map(|target| {
+                device.create_bind_group(&wgpu::BindGroupDescriptor {
+                    label: Some("Mip Generator Bind Group"),
+                    layout: &self.bind_group_layouts[index],
+                    entries: &[
+                        wgpu::BindGroupEntry {
+                            binding: 0,
+                            resource: wgpu::BindingResource::TextureView(&views[target - 1]),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding: 1,
+                            resource: wgpu::BindingResource::Sampler(&self.sampler),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding: 2,
+                            resource: wgpu::BindingResource::TextureView(&views[target]),
+                        },
+                    ],
+                })
+            })
+            .collect::<Vec<_>>();
+
+        let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("Mip Generator Pass"),
+        });
+        compute_pass.set_pipeline(&self.pipelines[index]);
+        for (level, bind_group) in (1..).zip(&bind_groups) {
+            let width = (size.width >> level).max(1);
+            let height = (size.height >> level).max(1);
+            compute_pass.set_bind_group(0, bind_group, &[]);
+            compute_pass.dispatch(
+                width.div_ceil(WORKGROUP_SIZE),
+                height.div_ceil(WORKGROUP_SIZE),
+                1,
+            );
+        }
+    }
+
+    /// How many levels a full chain of mips has for a texture of
+    /// `size`, down to a single texel.
+    pub fn full_mip_count(size: wgpu::Extent3d) -> u32 {
+        32 - size.width.max(size.height).max(1).leading_zeros()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::context::test_device;
+
+    #[test]
+    fn full_chains_go_down_to_one_texel() {
+        let size = |width, height| wgpu::Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        };
+        assert_eq!(MipGenerator::full_mip_count(size(1, 1)), 1);
+        assert_eq!(MipGenerator::full_mip_count(size(64, 64)), 7);
+        assert_eq!(MipGenerator::full_mip_count(size(64, 16)), 7);
+        assert_eq!(MipGenerator::full_mip_count(size(100, 3)), 7);
+    }
+
+    #[test]
+    fn levels_average_the_level_above() {
+        let (device, queue) = match test_device() {
+            Some(device) => device,
+            None => return eprintln!("skipped, there's no adapter"),
+        };
+        if device.limits().max_storage_textures_per_shader_stage == 0 {
+            return eprintln!("skipped, the device can't write storage textures");
+        }
+
+        const SIZE: u32 = 64;
+        let size = wgpu::Extent3d {
+            width: SIZE,
+            height: SIZE,
+            depth_or_array_layers: 1,
+        };
+        let mip_count = MipGenerator::full_mip_count(size);
+        let format = wgpu::TextureFormat::Rgba8Unorm;
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: None,
+            size,
+            mip_level_count: mip_count,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: MipGenerator::texture_usages(&device)
+                | wgpu::TextureUsages::COPY_DST
+                | wgpu::TextureUsages::COPY_SRC,
+        });
+        // A checkerboard of single texels, which every 2x2 block
+        // averages out to grey, with red the same all over.
+        let texels = (0..SIZE * SIZE)
+            .flat_map(|i| {
+                let white = (i % SIZE + i / SIZE).is_multiple_of(2);
+                let value = if white { 255 } else { 0 };
+                [200, value, value, 255]
+            })
+            .collect::<Vec<u8>>();
+        queue.write_texture(
+            texture.as_image_copy(),
+            &texels,
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: std::num::NonZeroU32::new(SIZE * 4),
+                rows_per_image: None,
+            },
+            size,
+        );
+
+        let generator = MipGenerator::new(&device);
+        let mut encoder = device.create_command_encoder(&Default::default());
+        generator.generate(&device, &mut encoder, &texture, format, size, mip_count);
+
+        // Each level is copied into rows of 256 bytes, however few
+        // texels it has.
+        const ROW: u32 = 256;
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: None,
+            size: (ROW * SIZE * mip_count) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        for level in 1..mip_count {
+            let level_size = SIZE >> level;
+            encoder.copy_texture_to_buffer(
+                wgpu::ImageCopyTexture {
+                    texture: &texture,
+                    mip_level: level,
+                    origin: wgpu::Origin3d::ZERO,
+                    aspect: wgpu::TextureAspect::All,
+                },
+                wgpu::ImageCopyBuffer {
+                    buffer: &buffer,
+                    layout: wgpu::ImageDataLayout {
+                        offset: (ROW * SIZE * level) as wgpu::BufferAddress,
+                        bytes_per_row: std::num::NonZeroU32::new(ROW),
+                        rows_per_image: None,
+                    },
+                },
+                wgpu::Extent3d {
+                    width: level_size,
+                    height: level_size,
+                    depth_or_array_layers: 1,
+                },
+            );
+        }
+        queue.submit(Some(encoder.finish()));
+
+        let slice = buffer.slice(..);
+        let mapping = slice.map_async(wgpu::MapMode::Read);
+        device.poll(wgpu::Maintain::Wait);
+        pollster::block_on(mapping).expect("mips are read back");
+        let data = slice.get_mapped_range();
+        for level in 1..mip_count {
+            let level_size = SIZE >> level;
+            for y in 0..level_size {
+                let start = (ROW * (SIZE * level + y)) as usize;
+                let row = &data[start..start + (level_size * 4) as usize];
+                for texel in row.chunks(4) {
+                    assert_eq!(texel[0], 200, "level {} changed red", level);
+                    assert!(
+                        (texel[1] as i32 - 128).abs() <= 1 && texel[1] == texel[2],
+                        "level {} has {:?} where the checkerboard averages to grey",
+                        level,
+                        texel
+                    );
+                }
+            }
+        }
+    }
+}
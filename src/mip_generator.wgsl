@@ -0,0 +1,23 @@
+// Downsamples one mip level of a texture into the next, averaging
+// each 2x2 block of texels with a single bilinear sample.
+
+[[group(0), binding(0)]]
+var t_source: texture_2d<f32>;
+[[group(0), binding(1)]]
+var s_source: sampler;
+// Swapped for the format of the texture being mipped.
+[[group(0), binding(2)]]
+var t_destination: texture_storage_2d<rgba8unorm, write>;
+
+[[stage(compute), workgroup_size(8, 8)]]
+fn main([[builtin(global_invocation_id)]] id: vec3<u32>) {
+    let size = vec2<u32>(textureDimensions(t_destination));
+    if (id.x >= size.x || id.y >= size.y) {
+        return;
+    }
+    // The middle of the destination texel is the corner the four
+    // source texels under it share, so they're weighted evenly.
+    let uv = (vec2<f32>(id.xy) + vec2<f32>(0.5)) / vec2<f32>(size);
+    let color = textureSampleLevel(t_source, s_source, uv, 0.0);
+    textureStore(t_destination, vec2<i32>(id.xy), color);
+}
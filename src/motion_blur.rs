@@ -0,0 +1,207 @@
+use crate::{
+    fullscreen_triangle::FullscreenTriangle,
+    hdr_render_target::{HdrRenderTarget, HDR_FORMAT},
+    pipeline::RenderPipelineBuilder,
+    render_pass_guard::RenderPassBuilder,
+    transient_texture::TransientTextureDesc,
+    uniform::UniformBinding,
+};
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct MotionBlurSettings {
+    sample_count: u32,
+    strength: f32,
+    _padding: [f32; 2],
+}
+
+/// Blurs the scene along the velocities from the
+/// [`VelocityPass`](crate::velocity::VelocityPass), as if the
+/// camera's shutter stayed open while it moved.
+///
+/// The blur reads the scene while drawing over it, so the scene is
+/// copied into a transient texture first, described by
+/// [`source_desc`](Self::source_desc).
+pub struct MotionBlurPass {
+    #[allow(dead_code)]
+    settings: MotionBlurSettings,
+    settings_binding: UniformBinding<MotionBlurSettings>,
+    sampler: wgpu::Sampler,
+    texture_layout: wgpu::BindGroupLayout,
+    triangle: FullscreenTriangle,
+}
+
+impl MotionBlurPass {
+    /// Each pixel averages `sample_count` samples along its velocity,
+    /// scaled by `strength`, so 1 smears it across all of its movement
+    /// since the last frame.
+    pub fn new(device: &wgpu::Device, sample_count: u32, strength: f32) -> Self {
+        let settings = MotionBlurSettings {
+            sample_count: sample_count.max(1),
+            strength,
+            _padding: [0.0; 2],
+        };
+        let settings_binding = UniformBinding::new(
+            device,
+            "Motion Blur Settings",
+            wgpu::ShaderStages::FRAGMENT,
+            &settings,
+        );
+
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Motion Blur Sampler"),
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            ..Default::default()
+        });
+        let texture_entry = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                multisampled: false,
+                view_dimension: wgpu::TextureViewDimension::D2,
+                sample_type: wgpu::TextureSampleType::Float { filterable: true },
+            },
+            count: None,
+        };
+        let texture_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Motion Blur Texture Bind Group Layout"),
+            entries: &[
+                texture_entry(0),
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler {
+                        filtering: true,
+                        comparison: false,
+                    },
+                    count: None,
+                },
+                texture_entry(2),
+            ],
+        });
+
+        let vertex_shader = FullscreenTriangle::vertex_shader(device);
+        let fragment_shader = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+            label: Some("Motion Blur Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("motion_blur.wgsl").into()),
+        });
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Motion Blur Pipeline Layout"),
+            bind_group_layouts: &[&texture_layout, &settings_binding.bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let triangle = FullscreenTriangle::new(
+            RenderPipelineBuilder::new()
+                .label("Motion Blur Pipeline")
+                .vertex_shader(&vertex_shader, "main")
+                .fragment_shader(
+                    &fragment_shader,
+                    "main",
+                    &[wgpu::ColorTargetState {
+                        format: HDR_FORMAT,
+                        blend: Some(wgpu::BlendState::REPLACE),
+                        write_mask: wgpu::ColorWrites::ALL,
+                    }],
+                )
+                .cull_mode(None)
+                .build(device, &layout)
+                .expect("failed to build motion blur pipeline"),
+        );
+
+        MotionBlurPass {
+            settings,
+            settings_binding,
+            sampler,
+            texture_layout,
+            triangle,
+        }
+    }
+
+    #[allow(dead_code)]
+    pub fn strength(&self) -> f32 {
+        self.settings.strength
+    }
+
+    #[allow(dead_code)]
+    pub fn set_strength(&mut self, queue: &wgpu::Queue, strength: f32) {
+        self.settings.strength = strength;
+        self.settings_binding.update(queue, &self.settings);
+    }
+
+    /// The texture the scene is copied into, only needed while the pass runs.
+    pub fn source_desc(width: u32, height: u32) -> TransientTextureDesc {
+        TransientTextureDesc {
+            width,
+            height,
+            format: HDR_FORMAT,
+            usage: wgpu::TextureUsages::COPY_DST | wgpu::TextureUsages::TEXTURE_BINDING,
+        }
+    }
+
+    /// Blurs `scene` along `velocity`, which has to be the same size,
+    /// copying it into `source` first.
+    pub fn run(
+        &self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        scene: &HdrRenderTarget,
+        velocity: &wgpu::TextureView,
+        source: &wgpu::Texture,
+        source_view: &wgpu::TextureView,
+    ) {
+        encoder.copy_texture_to_texture(
+            scene.texture().as_image_copy(),
+            source.as_image_copy(),
+            wgpu::Extent3d {
+                width: scene.width(),
+                height: scene.height(),
+                depth_or_array_layers: 1,
+            },
+        );
+
+        // The velocity texture is recreated on resize, so its bind
+        // group is made as it's needed.
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Motion Blur Texture Bind Group"),
+            layout: &self.texture_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(source_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&self.sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::TextureView(velocity),
+                },
+            ],
+        });
+
+        let mut render_pass = RenderPassBuilder::begin_render_pass(
+            encoder,
+            &wgpu::RenderPassDescriptor {
+                label: Some("Motion Blur Pass"),
+                color_attachments: &[wgpu::RenderPassColorAttachment {
+                    view: scene.view(),
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Load,
+                        store: true,
+                    },
+                }],
+                depth_stencil_attachment: None,
+            },
+        );
+        render_pass.set_bind_group(0, &bind_group, &[]);
+        render_pass.set_bind_group(1, &self.settings_binding.bind_group, &[]);
+        self.triangle.draw(&mut render_pass);
+    }
+}
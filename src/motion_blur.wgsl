@@ -0,0 +1,34 @@
+// Smears the frame along each pixel's velocity, averaging samples
+// from where the pixel was over the last frame to where it is now.
+
+[[block]]
+struct MotionBlurSettings {
+    // Samples along the velocity, including the pixel itself.
+    sample_count: u32;
+    // How much of the frame's movement the shutter stays open for.
+    strength: f32;
+};
+
+[[group(0), binding(0)]]
+var t_scene: texture_2d<f32>;
+[[group(0), binding(1)]]
+var s_scene: sampler;
+[[group(0), binding(2)]]
+var t_velocity: texture_2d<f32>;
+
+[[group(1), binding(0)]]
+var<uniform> settings: MotionBlurSettings;
+
+[[stage(fragment)]]
+fn main([[location(0)]] tex_coords: vec2<f32>) -> [[location(0)]] vec4<f32> {
+    let velocity = textureSample(t_velocity, s_scene, tex_coords).xy * settings.strength;
+
+    var color = vec3<f32>(0.0);
+    let steps = max(settings.sample_count, 1u);
+    for (var i: u32 = 0u; i < steps; i = i + 1u) {
+        // From here, back to where the pixel was.
+        let t = f32(i) / f32(max(steps - 1u, 1u));
+        color = color + textureSample(t_scene, s_scene, tex_coords - velocity * t).rgb;
+    }
+    return vec4<f32>(color / f32(steps), 1.0);
+}
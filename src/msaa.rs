@@ -0,0 +1,84 @@
+/// Multisample anti-aliasing settings.
+///
+/// With more than one sample the scene is rendered into a multisampled
+/// texture, which is then resolved into the surface texture at the end
+/// of the render pass.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MsaaConfig {
+    pub count: u32,
+}
+
+impl MsaaConfig {
+    /// The sample counts that can be asked for.
+    pub const SAMPLE_COUNTS: [u32; 4] = [1, 2, 4, 8];
+
+    pub fn new(count: u32) -> Self {
+        MsaaConfig { count }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.count > 1
+    }
+
+    /// Whether textures of the given format can be rendered
+    /// with this many samples on the adapter.
+    pub fn is_supported(&self, adapter: &wgpu::Adapter, format: wgpu::TextureFormat) -> bool {
+        if !Self::SAMPLE_COUNTS.contains(&self.count) {
+            return false;
+        }
+        if !self.is_enabled() {
+            return true;
+        }
+
+        let features = adapter.get_texture_format_features(format);
+        if !features
+            .allowed_usages
+            .contains(wgpu::TextureUsages::RENDER_ATTACHMENT)
+        {
+            return false;
+        }
+
+        // The format feature flags don't report which sample counts a
+        // format supports yet, so we only allow the count WebGPU
+        // guarantees for every renderable format.
+        self.count == 4
+    }
+
+    /// Creates the multisampled texture the render pass draws into,
+    /// or `None` when multisampling is disabled.
+    pub fn create_framebuffer(
+        &self,
+        device: &wgpu::Device,
+        width: u32,
+        height: u32,
+        format: wgpu::TextureFormat,
+    ) -> Option<wgpu::TextureView> {
+        if !self.is_enabled() {
+            return None;
+        }
+
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Multisampled Framebuffer"),
+            // Must match the size and format of the texture it resolves to.
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: self.count,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+        });
+
+        // The view keeps the texture alive.
+        Some(texture.create_view(&wgpu::TextureViewDescriptor::default()))
+    }
+}
+
+impl Default for MsaaConfig {
+    fn default() -> Self {
+        MsaaConfig::new(1)
+    }
+}
@@ -0,0 +1,237 @@
+use winit::dpi::PhysicalSize;
+
+use crate::{
+    camera::{Camera, CameraBuffer, CameraProjection},
+    render_state::{ScissorRect, ViewportState},
+};
+
+/// How far from the origin the orthographic cameras are.
+const ORTHOGRAPHIC_DISTANCE: f32 = 50.0;
+/// How much of the world the orthographic views show across, in
+/// world units.
+const ORTHOGRAPHIC_WIDTH: f32 = 24.0;
+
+/// A rectangle of the window, in pixels from the top left, and
+/// the camera that's drawn into it.
+pub struct Viewport {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+    pub camera: Camera,
+}
+
+impl Viewport {
+    /// The rectangle as the pass is limited to it, with every depth.
+    pub fn state(&self) -> ViewportState {
+        ViewportState {
+            x: self.x as f32,
+            y: self.y as f32,
+            width: self.width as f32,
+            height: self.height as f32,
+            min_depth: 0.0,
+            max_depth: 1.0,
+        }
+    }
+
+    /// The rectangle as the pass's scissor.
+    pub fn scissor(&self) -> ScissorRect {
+        ScissorRect {
+            x: self.x,
+            y: self.y,
+            width: self.width,
+            height: self.height,
+        }
+    }
+}
+
+/// Four viewports splitting the window into quarters, by the order
+/// of their cameras: the top left, top right, bottom left, then
+/// bottom right.
+pub struct ViewportGrid {
+    pub viewports: [Viewport; 4],
+}
+
+impl ViewportGrid {
+    pub fn new(size: PhysicalSize<u32>, cameras: [Camera; 4]) -> Self {
+        let mut grid = ViewportGrid {
+            viewports: cameras.map(|camera| Viewport {
+                x: 0,
+                y: 0,
+                width: 0,
+                height: 0,
+                camera,
+            }),
+        };
+        grid.resize(size);
+        grid
+    }
+
+    /// Recomputes the quarters of the window, fitting each camera to
+    /// its new aspect. Odd sizes give the extra pixel to the right
+    /// and bottom viewports.
+    pub fn resize(&mut self, new_size: PhysicalSize<u32>) {
+        let left_width = new_size.width / 2;
+        let top_height = new_size.height / 2;
+        let columns = [(0, left_width), (left_width, new_size.width - left_width)];
+        let rows = [(0, top_height), (top_height, new_size.height - top_height)];
+
+        for (i, viewport) in self.viewports.iter_mut().enumerate() {
+            let (x, width) = columns[i % 2];
+            let (y, height) = rows[i / 2];
+            viewport.x = x;
+            viewport.y = y;
+            viewport.width = width;
+            viewport.height = height;
+            viewport.camera.fit_to_viewport(width, height);
+        }
+    }
+}
+
+/// Draws the scene four times, each into a quarter of the window:
+/// a perspective view following the main camera, and orthographic
+/// views from the top, front and right, like the panes of a 3D
+/// editor.
+///
+/// Each view has a camera buffer of its own, since they're all
+/// drawn in the same pass.
+pub struct MultiViewportRenderer {
+    grid: ViewportGrid,
+    camera_buffers: Vec<CameraBuffer>,
+}
+
+impl MultiViewportRenderer {
+    pub fn new(device: &wgpu::Device, config: &wgpu::SurfaceConfiguration) -> Self {
+        let perspective = Camera {
+            eye: (0.0, 5.0, 10.0).into(),
+            target: (0.0, 0.0, 0.0).into(),
+            up: cgmath::Vector3::unit_y(),
+            aspect: 1.0,
+            projection: CameraProjection::Perspective {
+                fov: 45.0,
+                near: 0.1,
+                far: 100.0,
+            },
+            jitter: [0.0; 2],
+        };
+        let d = ORTHOGRAPHIC_DISTANCE;
+        // Looking down, with the front of the scene at the bottom.
+        let top = orthographic_camera((0.0, d, 0.0), -cgmath::Vector3::unit_z());
+        let front = orthographic_camera((0.0, 0.0, d), cgmath::Vector3::unit_y());
+        let right = orthographic_camera((d, 0.0, 0.0), cgmath::Vector3::unit_y());
+
+        let size = PhysicalSize::new(config.width, config.height);
+        let grid = ViewportGrid::new(size, [perspective, top, front, right]);
+        let camera_buffers = grid
+            .viewports
+            .iter()
+            .map(|viewport| CameraBuffer::new(device, &viewport.camera))
+            .collect();
+
+        MultiViewportRenderer {
+            grid,
+            camera_buffers,
+        }
+    }
+
+    #[allow(dead_code)]
+    pub fn grid(&self) -> &ViewportGrid {
+        &self.grid
+    }
+
+    pub fn resize(&mut self, new_size: PhysicalSize<u32>) {
+        self.grid.resize(new_size);
+    }
+
+    /// Moves the perspective view to where `camera` is, and uploads
+    /// every view's camera.
+    pub fn update(&mut self, queue: &wgpu::Queue, camera: &Camera) {
+        let perspective = &mut self.grid.viewports[0].camera;
+        perspective.eye = camera.eye;
+        perspective.target = camera.target;
+        perspective.up = camera.up;
+        // The main camera's orthographic bounds are fitted to the
+        // whole window, so only a perspective projection is followed.
+        if let CameraProjection::Perspective { .. } = camera.projection {
+            perspective.projection = camera.projection;
+        }
+
+        for (buffer, viewport) in self.camera_buffers.iter_mut().zip(&self.grid.viewports) {
+            buffer.update(queue, &viewport.camera);
+        }
+    }
+
+    /// The views [`render`](Self::render) draws, with their camera
+    /// buffers. Views squeezed down to nothing are skipped.
+    fn drawn_views(&self) -> impl Iterator<Item = (&Viewport, &CameraBuffer)> {
+        self.grid
+            .viewports
+            .iter()
+            .zip(&self.camera_buffers)
+            .filter(|(viewport, _)| viewport.width > 0 && viewport.height > 0)
+    }
+
+    /// The viewport and scissor of each view [`render`](Self::render)
+    /// draws, in the order it draws them.
+    pub fn views(&self) -> Vec<(ViewportState, ScissorRect)> {
+        self.drawn_views()
+            .map(|(viewport, _)| (viewport.state(), viewport.scissor()))
+            .collect()
+    }
+
+    /// Calls `draw` once for each view, with the pass limited to the
+    /// view's rectangle and the bind group of its camera. The whole
+    /// of `size` is drawn into again afterwards.
+    pub fn render<'a>(
+        &'a self,
+        render_pass: &mut wgpu::RenderPass<'a>,
+        size: PhysicalSize<u32>,
+        mut draw: impl FnMut(&mut wgpu::RenderPass<'a>, &'a wgpu::BindGroup),
+    ) {
+        for (viewport, buffer) in self.drawn_views() {
+            set_viewport(render_pass, viewport.state(), viewport.scissor());
+            draw(render_pass, &buffer.binding().bind_group);
+        }
+
+        set_viewport(
+            render_pass,
+            ViewportState::full(size.width, size.height),
+            ScissorRect::full(size.width, size.height),
+        );
+    }
+}
+
+fn set_viewport(render_pass: &mut wgpu::RenderPass, viewport: ViewportState, scissor: ScissorRect) {
+    render_pass.set_viewport(
+        viewport.x,
+        viewport.y,
+        viewport.width,
+        viewport.height,
+        viewport.min_depth,
+        viewport.max_depth,
+    );
+    // The viewport alone doesn't stop wide lines and points from
+    // spilling into the neighbouring views.
+    render_pass.set_scissor_rect(scissor.x, scissor.y, scissor.width, scissor.height);
+}
+
+/// A camera looking at the origin from `eye`, without perspective.
+fn orthographic_camera(eye: (f32, f32, f32), up: cgmath::Vector3<f32>) -> Camera {
+    let half_width = ORTHOGRAPHIC_WIDTH * 0.5;
+    Camera {
+        eye: eye.into(),
+        target: (0.0, 0.0, 0.0).into(),
+        up,
+        aspect: 1.0,
+        projection: CameraProjection::Orthographic {
+            left: -half_width,
+            right: half_width,
+            // Fitted to the viewport by the grid.
+            bottom: -half_width,
+            top: half_width,
+            near: 0.1,
+            far: ORTHOGRAPHIC_DISTANCE * 2.0,
+        },
+        jitter: [0.0; 2],
+    }
+}
@@ -0,0 +1,346 @@
+use cgmath::{Matrix4, SquareMatrix};
+
+use crate::{camera::Camera, depth::DepthBuffer, uniform::UniformBinding};
+
+/// Format of the normal texture. Normals are unit vectors, so they
+/// fit in signed normalized bytes without being remapped.
+pub const NORMAL_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8Snorm;
+
+/// The workgroup size of the shader.
+const WORKGROUP_SIZE: u32 = 8;
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct NormalUniform {
+    inv_projection: [[f32; 4]; 4],
+}
+
+/// Works out the normal of every pixel from the depth buffer alone,
+/// for the [`SsaoPass`](crate::ssao::SsaoPass), so the forward path
+/// doesn't have to write out normals of its own.
+///
+/// The position of each pixel is reconstructed in view space from its
+/// depth, and the normal is the cross of the differences between the
+/// pixels either side of it, across and down. Pixels along the edges
+/// of the screen are clamped to, so they take a one-sided difference
+/// instead. Normals are in view space, pointing back at the camera,
+/// and zero where nothing was drawn.
+pub struct NormalReconstruct {
+    texture: wgpu::Texture,
+    view: wgpu::TextureView,
+    uniform: UniformBinding<NormalUniform>,
+    texture_layouts: [wgpu::BindGroupLayout; 2],
+    /// For a single sampled depth buffer, then a multisampled one.
+    pipelines: [wgpu::ComputePipeline; 2],
+    width: u32,
+    height: u32,
+}
+
+impl NormalReconstruct {
+    pub fn new(device: &wgpu::Device, config: &wgpu::SurfaceConfiguration) -> Self {
+        let (texture, view) = create_texture(device, config.width, config.height);
+        let uniform = UniformBinding::new(
+            device,
+            "Normal Reconstruct Uniform",
+            wgpu::ShaderStages::COMPUTE,
+            &NormalUniform {
+                inv_projection: Matrix4::identity().into(),
+            },
+        );
+
+        let texture_layouts = [false, true].map(|multisampled| {
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("Normal Reconstruct Texture Bind Group Layout"),
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Texture {
+                            multisampled,
+                            view_dimension: wgpu::TextureViewDimension::D2,
+                            sample_type: wgpu::TextureSampleType::Depth,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::StorageTexture {
+                            access: wgpu::StorageTextureAccess::WriteOnly,
+                            format: NORMAL_FORMAT,
+                            view_dimension: wgpu::TextureViewDimension::D2,
+                        },
+                        count: None,
+                    },
+                ],
+            })
+        });
+
+        let source = include_str!("normal_reconstruct.wgsl");
+        let pipelines = [false, true].map(|multisampled| {
+            // The depth is loaded from its first sample either way, so
+            // only the texture's type changes.
+            let source = if multisampled {
+                source.replace("texture_depth_2d", "texture_depth_multisampled_2d")
+            } else {
+                source.to_string()
+            };
+            let shader = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+                label: Some("Normal Reconstruct Shader"),
+                source: wgpu::ShaderSource::Wgsl(source.into()),
+            });
+            let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Normal Reconstruct Pipeline Layout"),
+                bind_group_layouts: &[
+                    &uniform.bind_group_layout,
+                    &texture_layouts[multisampled as usize],
+                ],
+                push_constant_ranges: &[],
+            });
+            device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some("Normal Reconstruct Pipeline"),
+                layout: Some(&layout),
+                module: &shader,
+                entry_point: "main",
+            })
+        });
+
+        NormalReconstruct {
+            texture,
+            view,
+            uniform,
+            texture_layouts,
+            pipelines,
+            width: config.width,
+            height: config.height,
+        }
+    }
+
+    /// The normals are written by a compute shader into a storage
+    /// texture, which some downlevel adapters can't do.
+    pub fn is_supported(adapter: &wgpu::Adapter, device: &wgpu::Device) -> bool {
+        adapter
+            .get_downlevel_properties()
+            .flags
+            .contains(wgpu::DownlevelFlags::COMPUTE_SHADERS)
+            && device.limits().max_storage_textures_per_shader_stage > 0
+    }
+
+    /// The view space normal of each pixel.
+    pub fn normal_view(&self) -> &wgpu::TextureView {
+        &self.view
+    }
+
+    #[allow(dead_code)]
+    pub fn normal_texture(&self) -> &wgpu::Texture {
+        &self.texture
+    }
+
+    /// Uploads the camera's projection, which the depth of this frame
+    /// was drawn with. Should be called once the camera has moved.
+    pub fn update(&mut self, queue: &wgpu::Queue, camera: &Camera) {
+        // With the jitter, since the depth was drawn with it.
+        let inv_projection = camera
+            .build_projection_matrix()
+            .invert()
+            .unwrap_or_else(Matrix4::identity);
+        self.uniform.update(
+            queue,
+            &NormalUniform {
+                inv_projection: inv_projection.into(),
+            },
+        );
+    }
+
+    /// Recreates the normal texture to match the new surface size.
+    pub fn resize(&mut self, device: &wgpu::Device, width: u32, height: u32) {
+        let (texture, view) = create_texture(device, width, height);
+        self.texture = texture;
+        self.view = view;
+        self.width = width;
+        self.height = height;
+    }
+
+    /// Works out the normals of the pixels in `depth`, which has to be
+    /// the same size as the normal texture.
+    pub fn run(
+        &self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        depth: &DepthBuffer,
+    ) {
+        let multisampled = (depth.sample_count() > 1) as usize;
+        // The depth buffer is recreated on resize and when MSAA
+        // changes, so its bind group is made as it's needed.
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Normal Reconstruct Texture Bind Group"),
+            layout: &self.texture_layouts[multisampled],
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(depth.depth_view()),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(&self.view),
+                },
+            ],
+        });
+
+        let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("Normal Reconstruct Pass"),
+        });
+        compute_pass.set_pipeline(&self.pipelines[multisampled]);
+        compute_pass.set_bind_group(0, &self.uniform.bind_group, &[]);
+        compute_pass.set_bind_group(1, &bind_group, &[]);
+        compute_pass.dispatch(
+            self.width.div_ceil(WORKGROUP_SIZE),
+            self.height.div_ceil(WORKGROUP_SIZE),
+            1,
+        );
+    }
+}
+
+fn create_texture(
+    device: &wgpu::Device,
+    width: u32,
+    height: u32,
+) -> (wgpu::Texture, wgpu::TextureView) {
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("Normal Texture"),
+        size: wgpu::Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: NORMAL_FORMAT,
+        // Copied from in tests, to read the normals back.
+        usage: wgpu::TextureUsages::STORAGE_BINDING
+            | wgpu::TextureUsages::TEXTURE_BINDING
+            | wgpu::TextureUsages::COPY_SRC,
+    });
+    let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+    (texture, view)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::context::test_device;
+
+    /// Wide enough that each row of normals is 256 bytes, as texture
+    /// to buffer copies need.
+    const WIDTH: u32 = 64;
+    const HEIGHT: u32 = 16;
+
+    /// Runs the pass over a depth buffer cleared to `depth`, which
+    /// `inv_projection` turns into a flat surface, and reads back the
+    /// normals.
+    fn reconstruct(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        depth: f32,
+        inv_projection: Matrix4<f32>,
+    ) -> Vec<[i8; 4]> {
+        let config = wgpu::SurfaceConfiguration {
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            format: wgpu::TextureFormat::Rgba8Unorm,
+            width: WIDTH,
+            height: HEIGHT,
+            present_mode: wgpu::PresentMode::Fifo,
+        };
+        let normals = NormalReconstruct::new(device, &config);
+        normals.uniform.update(
+            queue,
+            &NormalUniform {
+                inv_projection: inv_projection.into(),
+            },
+        );
+        let depth_buffer = DepthBuffer::new(device, WIDTH, HEIGHT);
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: None,
+            size: (WIDTH * HEIGHT * 4) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let mut encoder = device.create_command_encoder(&Default::default());
+        encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: None,
+            color_attachments: &[],
+            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                view: depth_buffer.view(),
+                depth_ops: Some(wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(depth),
+                    store: true,
+                }),
+                stencil_ops: None,
+            }),
+        });
+        normals.run(device, &mut encoder, &depth_buffer);
+        encoder.copy_texture_to_buffer(
+            normals.normal_texture().as_image_copy(),
+            wgpu::ImageCopyBuffer {
+                buffer: &buffer,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: std::num::NonZeroU32::new(WIDTH * 4),
+                    rows_per_image: None,
+                },
+            },
+            wgpu::Extent3d {
+                width: WIDTH,
+                height: HEIGHT,
+                depth_or_array_layers: 1,
+            },
+        );
+        queue.submit(Some(encoder.finish()));
+
+        let slice = buffer.slice(..);
+        let mapping = slice.map_async(wgpu::MapMode::Read);
+        device.poll(wgpu::Maintain::Wait);
+        pollster::block_on(mapping).expect("normals are read back");
+        let data = slice.get_mapped_range();
+        bytemuck::cast_slice::<u8, [i8; 4]>(&data).to_vec()
+    }
+
+    fn assert_normals_near(normals: &[[i8; 4]], expected: [f32; 3]) {
+        for (i, normal) in normals.iter().enumerate() {
+            for axis in 0..3 {
+                let expected = (expected[axis] * 127.0).round() as i32;
+                assert!(
+                    (normal[axis] as i32 - expected).abs() <= 1,
+                    "pixel {} has normal {:?}",
+                    i,
+                    normal
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn flat_surfaces_have_the_same_normal_everywhere() {
+        let (device, queue) = match test_device() {
+            Some(device) => device,
+            None => return eprintln!("skipped, there's no adapter"),
+        };
+        if device.limits().max_storage_textures_per_shader_stage == 0 {
+            return eprintln!("skipped, the device can't write storage textures");
+        }
+
+        // Facing the camera, including the pixels along the edges.
+        let normals = reconstruct(&device, &queue, 0.5, Matrix4::identity());
+        assert_normals_near(&normals, [0.0, 0.0, 1.0]);
+
+        // Sheared so the surface's Z grows to the right, which
+        // tilts it to face the left.
+        let mut shear = Matrix4::identity();
+        shear.x.z = 1.0;
+        let normals = reconstruct(&device, &queue, 0.5, shear);
+        let half = std::f32::consts::FRAC_1_SQRT_2;
+        assert_normals_near(&normals, [-half, 0.0, half]);
+    }
+}
@@ -0,0 +1,54 @@
+// Works out the view space normal of each pixel from the depth
+// buffer, by crossing the differences between the positions of the
+// pixels on either side of it.
+
+[[block]]
+struct NormalUniform {
+    inv_projection: mat4x4<f32>;
+};
+
+[[group(0), binding(0)]]
+var<uniform> uniform: NormalUniform;
+
+[[group(1), binding(0)]]
+var t_depth: texture_depth_2d;
+
+[[group(1), binding(1)]]
+var t_normal: texture_storage_2d<rgba8snorm, write>;
+
+// The view space position of the pixel at `coords`, which is clamped
+// to the screen, so pixels along the edges use their own position for
+// the missing neighbor.
+fn view_position(coords: vec2<i32>, size: vec2<i32>) -> vec3<f32> {
+    let clamped = clamp(coords, vec2<i32>(0), size - vec2<i32>(1));
+    let depth = textureLoad(t_depth, clamped, 0);
+    let uv = (vec2<f32>(clamped) + vec2<f32>(0.5)) / vec2<f32>(size);
+    // Texture coordinates have Y pointing down, unlike clip space.
+    let ndc = vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, depth, 1.0);
+    let position = uniform.inv_projection * ndc;
+    return position.xyz / position.w;
+}
+
+[[stage(compute), workgroup_size(8, 8)]]
+fn main([[builtin(global_invocation_id)]] id: vec3<u32>) {
+    let size = textureDimensions(t_depth);
+    let coords = vec2<i32>(id.xy);
+    if (coords.x >= size.x || coords.y >= size.y) {
+        return;
+    }
+
+    // Nothing was drawn where the depth is still cleared.
+    if (textureLoad(t_depth, coords, 0) >= 1.0) {
+        textureStore(t_normal, coords, vec4<f32>(0.0));
+        return;
+    }
+
+    let right = view_position(coords + vec2<i32>(1, 0), size)
+        - view_position(coords - vec2<i32>(1, 0), size);
+    // Down the screen, since texture rows go down.
+    let down = view_position(coords + vec2<i32>(0, 1), size)
+        - view_position(coords - vec2<i32>(0, 1), size);
+    // Down crossed with right points back at the camera, along +Z.
+    let normal = normalize(cross(down, right));
+    textureStore(t_normal, coords, vec4<f32>(normal, 0.0));
+}
@@ -0,0 +1,375 @@
+use std::collections::HashSet;
+
+use cgmath::SquareMatrix;
+
+use crate::{
+    bounds::{Aabb, Frustum},
+    camera::{Camera, CameraProjection},
+    depth::DepthBuffer,
+    ecs::EntityId,
+    msaa::MsaaConfig,
+    pipeline::RenderPipelineBuilder,
+    readback::{ReadbackRing, READBACK_FRAMES},
+    render_pass_guard::RenderPassBuilder,
+    transform::Transform,
+    uniform::UniformBinding,
+};
+
+/// The vertices drawn for each box, two triangles for each face.
+const BOX_VERTICES: u32 = 36;
+
+/// The bounds of an object, in world space, to be drawn as a box.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct OcclusionBox {
+    min: [f32; 3],
+    max: [f32; 3],
+}
+
+impl OcclusionBox {
+    const ATTRIBUTES: [wgpu::VertexAttribute; 2] =
+        wgpu::vertex_attr_array![0 => Float32x3, 1 => Float32x3];
+
+    fn vertex_buffer_layout<'a>() -> wgpu::VertexBufferLayout<'a> {
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<OcclusionBox>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Instance,
+            attributes: &Self::ATTRIBUTES,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct OcclusionUniform {
+    view_proj: [[f32; 4]; 4],
+}
+
+/// Finds the objects hidden behind others, so they can be left out
+/// of the next frame's draw calls.
+///
+/// Once the scene is drawn, the bounding box of each object in the
+/// frustum is drawn against its depth, and the boxes that don't get
+/// a single fragment past the depth test are hidden. The results are
+/// read back after the frame is submitted, and go into the next
+/// frame's batches, so they're always a frame behind. Hidden objects
+/// keep being tested while they're culled, and are drawn again the
+/// frame after they'd have shown, while objects that were seen last
+/// frame are always drawn.
+///
+/// wgpu 0.11 can create `Occlusion` query sets, but has no way to
+/// begin or end the queries in a render pass, so the boxes mark
+/// themselves as seen in a storage buffer instead, one slot for each
+/// query.
+pub struct OcclusionQueryPool {
+    capacity: u32,
+    uniform: UniformBinding<OcclusionUniform>,
+    boxes_buffer: wgpu::Buffer,
+    /// Left at zero for the boxes none of which passed the depth test.
+    samples_buffer: wgpu::Buffer,
+    samples_bind_group: wgpu::BindGroup,
+    /// The samples are copied here to be read, since storage buffers
+    /// can't be mapped, along with the entities tested that frame.
+    readbacks: ReadbackRing<Vec<EntityId>>,
+    layout: wgpu::PipelineLayout,
+    shader: wgpu::ShaderModule,
+    /// For a single sampled depth buffer, then one with the sample
+    /// count set by [`set_msaa`](Self::set_msaa). The deferred path's
+    /// depth stays single sampled while MSAA is on.
+    pipelines: [wgpu::RenderPipeline; 2],
+    /// The entity of each box drawn this frame, by its query.
+    tested: Vec<EntityId>,
+    /// Entities whose boxes were hidden when they were last tested.
+    occluded: HashSet<EntityId>,
+}
+
+impl OcclusionQueryPool {
+    /// The boxes write to a storage buffer from the fragment shader,
+    /// which some downlevel adapters can't do.
+    pub fn is_supported(adapter: &wgpu::Adapter, device: &wgpu::Device) -> bool {
+        adapter
+            .get_downlevel_properties()
+            .flags
+            .contains(wgpu::DownlevelFlags::FRAGMENT_WRITABLE_STORAGE)
+            && device.limits().max_storage_buffers_per_shader_stage > 0
+    }
+
+    /// Makes room for testing `capacity` objects a frame. Any more
+    /// than that aren't tested, and are always drawn.
+    pub fn new(device: &wgpu::Device, capacity: u32) -> Self {
+        let uniform = UniformBinding::new(
+            device,
+            "Occlusion Query Uniform",
+            wgpu::ShaderStages::VERTEX,
+            &OcclusionUniform {
+                view_proj: cgmath::Matrix4::identity().into(),
+            },
+        );
+        let boxes_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Occlusion Query Box Buffer"),
+            size: capacity as wgpu::BufferAddress
+                * std::mem::size_of::<OcclusionBox>() as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let samples_size =
+            capacity as wgpu::BufferAddress * std::mem::size_of::<u32>() as wgpu::BufferAddress;
+        let samples_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Occlusion Query Samples Buffer"),
+            size: samples_size,
+            usage: wgpu::BufferUsages::STORAGE
+                | wgpu::BufferUsages::COPY_SRC
+                | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let readbacks = ReadbackRing::new(
+            device,
+            "Occlusion Query Readback Buffer",
+            samples_size,
+            READBACK_FRAMES,
+        );
+
+        let samples_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Occlusion Query Samples Bind Group Layout"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Storage { read_only: false },
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+        });
+        let samples_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Occlusion Query Samples Bind Group"),
+            layout: &samples_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: samples_buffer.as_entire_binding(),
+            }],
+        });
+
+        let shader = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+            label: Some("Occlusion Query Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("occlusion_query.wgsl").into()),
+        });
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Occlusion Query Pipeline Layout"),
+            bind_group_layouts: &[&uniform.bind_group_layout, &samples_layout],
+            push_constant_ranges: &[],
+        });
+        // Both single sampled, until MSAA is turned on.
+        let pipelines =
+            [MsaaConfig::default(); 2].map(|msaa| create_pipeline(device, &layout, &shader, msaa));
+
+        OcclusionQueryPool {
+            capacity,
+            uniform,
+            boxes_buffer,
+            samples_buffer,
+            samples_bind_group,
+            readbacks,
+            layout,
+            shader,
+            pipelines,
+            tested: Vec::new(),
+            occluded: HashSet::new(),
+        }
+    }
+
+    /// Uploads the boxes of the `objects` in `frustum` to be tested
+    /// this frame, as seen by `camera`.
+    ///
+    /// Objects the camera is inside of are never hidden, so they're
+    /// left out.
+    pub fn prepare(
+        &mut self,
+        queue: &wgpu::Queue,
+        camera: &Camera,
+        frustum: &Frustum,
+        objects: &[(EntityId, Aabb, Transform)],
+    ) {
+        // Boxes closer than the near plane are clipped, so they're
+        // treated as containing the camera too.
+        let margin = match camera.projection {
+            CameraProjection::Perspective { near, .. }
+            | CameraProjection::Orthographic { near, .. } => near,
+        };
+        let eye = [camera.eye.x, camera.eye.y, camera.eye.z];
+
+        self.tested.clear();
+        let mut boxes = Vec::new();
+        for (entity, bounds, transform) in objects {
+            if boxes.len() as u32 == self.capacity {
+                break;
+            }
+            if !frustum.intersects_aabb(bounds, transform) {
+                continue;
+            }
+            let bounds = bounds.transformed(transform);
+            let contains_eye = (0..3).all(|axis| {
+                bounds.min[axis] - margin <= eye[axis] && eye[axis] <= bounds.max[axis] + margin
+            });
+            if contains_eye {
+                continue;
+            }
+            self.tested.push(*entity);
+            boxes.push(OcclusionBox {
+                min: bounds.min,
+                max: bounds.max,
+            });
+        }
+        if boxes.is_empty() {
+            return;
+        }
+
+        self.uniform.update(
+            queue,
+            &OcclusionUniform {
+                view_proj: camera.build_view_projection_matrix(),
+            },
+        );
+        queue.write_buffer(&self.boxes_buffer, 0, bytemuck::cast_slice(&boxes));
+        queue.write_buffer(
+            &self.samples_buffer,
+            0,
+            bytemuck::cast_slice(&vec![0u32; boxes.len()]),
+        );
+    }
+
+    /// Recreates the multisampled pipeline to match the sample count
+    /// of the scene's depth buffer.
+    pub fn set_msaa(&mut self, device: &wgpu::Device, msaa: MsaaConfig) {
+        self.pipelines[1] = create_pipeline(device, &self.layout, &self.shader, msaa);
+    }
+
+    /// Draws the boxes uploaded by [`prepare`](Self::prepare) against
+    /// `depth`, which has to hold the scene drawn this frame, and
+    /// copies the results to be read back. `depth` has to be single
+    /// sampled, or have the sample count given to
+    /// [`set_msaa`](Self::set_msaa).
+    pub fn run(&self, encoder: &mut wgpu::CommandEncoder, depth: &DepthBuffer) {
+        if self.tested.is_empty() {
+            return;
+        }
+        let multisampled = (depth.sample_count() > 1) as usize;
+
+        {
+            let mut render_pass = RenderPassBuilder::begin_render_pass(
+                encoder,
+                &wgpu::RenderPassDescriptor {
+                    label: Some("Occlusion Query Pass"),
+                    color_attachments: &[],
+                    depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                        view: depth.view(),
+                        depth_ops: Some(wgpu::Operations {
+                            load: wgpu::LoadOp::Load,
+                            store: true,
+                        }),
+                        stencil_ops: Some(wgpu::Operations {
+                            load: wgpu::LoadOp::Load,
+                            store: true,
+                        }),
+                    }),
+                },
+            );
+            render_pass.set_pipeline(&self.pipelines[multisampled]);
+            render_pass.set_bind_group(0, &self.uniform.bind_group, &[]);
+            render_pass.set_bind_group(1, &self.samples_bind_group, &[]);
+            render_pass.set_vertex_buffer(0, self.boxes_buffer.slice(..));
+            render_pass.draw(0..BOX_VERTICES, 0..self.tested.len() as u32);
+        }
+
+        // Skipped while the GPU is behind on the earlier frames'.
+        if let Some(readback) = self.readbacks.target() {
+            encoder.copy_buffer_to_buffer(
+                &self.samples_buffer,
+                0,
+                readback,
+                0,
+                self.samples_size(),
+            );
+        }
+    }
+
+    /// Should be called once the encoder the boxes were drawn in is
+    /// submitted. Takes which boxes were hidden from the newest frame
+    /// whose results are back.
+    ///
+    /// Doesn't wait for the GPU, so the results are a frame or more
+    /// behind, and stay as they were while none have come back.
+    pub fn read_back(&mut self, device: &wgpu::Device) {
+        let tested_any = !self.tested.is_empty();
+        if tested_any {
+            let size = self.samples_size();
+            self.readbacks
+                .submitted(size, std::mem::take(&mut self.tested));
+        }
+
+        let occluded = self.readbacks.read(device, |data, tested| {
+            let samples: &[u32] = bytemuck::cast_slice(data);
+            tested
+                .into_iter()
+                .zip(samples)
+                .filter(|(_, samples)| **samples == 0)
+                .map(|(entity, _)| entity)
+                .collect()
+        });
+        if !tested_any {
+            // Nothing was tested this frame, so nothing is hidden.
+            self.occluded.clear();
+        } else if let Some(occluded) = occluded {
+            self.occluded = occluded;
+        }
+    }
+
+    /// Bytes of samples written for the boxes tested this frame.
+    fn samples_size(&self) -> wgpu::BufferAddress {
+        self.tested.len() as wgpu::BufferAddress * std::mem::size_of::<u32>() as wgpu::BufferAddress
+    }
+
+    /// Whether `entity` was hidden when it was last tested. Entities
+    /// that weren't tested aren't.
+    pub fn is_occluded(&self, entity: EntityId) -> bool {
+        self.occluded.contains(&entity)
+    }
+
+    /// Entities hidden when they were last tested.
+    #[allow(dead_code)]
+    pub fn occluded_count(&self) -> u32 {
+        self.occluded.len() as u32
+    }
+
+    /// Forgets every result, for when occlusion culling is turned off.
+    pub fn clear(&mut self) {
+        self.tested.clear();
+        self.occluded.clear();
+    }
+}
+
+fn create_pipeline(
+    device: &wgpu::Device,
+    layout: &wgpu::PipelineLayout,
+    shader: &wgpu::ShaderModule,
+    msaa: MsaaConfig,
+) -> wgpu::RenderPipeline {
+    RenderPipelineBuilder::new()
+        .label("Occlusion Query Pipeline")
+        .vertex_shader(shader, "vs_main")
+        .fragment_shader(shader, "fs_main", &[])
+        .vertex_layouts(&[OcclusionBox::vertex_buffer_layout()])
+        // The back of a box still counts when the front is clipped
+        // by the near plane.
+        .cull_mode(None)
+        .depth_stencil(wgpu::DepthStencilState {
+            // Only tests against the scene, without drawing over it.
+            depth_write_enabled: false,
+            depth_compare: wgpu::CompareFunction::LessEqual,
+            ..DepthBuffer::depth_stencil_state()
+        })
+        .multisample(msaa.count)
+        .build(device, layout)
+        .expect("failed to build occlusion query pipeline")
+}
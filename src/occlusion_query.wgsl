@@ -0,0 +1,64 @@
+// Draws the bounding box of each object tested for occlusion, and
+// marks the object as seen when any of its box passes the depth test.
+
+[[block]]
+struct OcclusionUniform {
+    view_proj: mat4x4<f32>;
+};
+
+[[block]]
+struct Samples {
+    // One for each box, which is left at zero unless a fragment of
+    // the box passes the depth test.
+    values: array<u32>;
+};
+
+[[group(0), binding(0)]]
+var<uniform> uniform: OcclusionUniform;
+
+[[group(1), binding(0)]]
+var<storage, read_write> samples: Samples;
+
+struct VertexOutput {
+    [[builtin(position)]] clip_position: vec4<f32>;
+    [[location(0), interpolate(flat)]] query: u32;
+};
+
+[[stage(vertex)]]
+fn vs_main(
+    [[builtin(vertex_index)]] vertex_index: u32,
+    [[builtin(instance_index)]] instance_index: u32,
+    [[location(0)]] box_min: vec3<f32>,
+    [[location(1)]] box_max: vec3<f32>,
+) -> VertexOutput {
+    // Two triangles for each face of the box, by the corner they're
+    // at, with a bit set for each axis the corner is at the max of.
+    var corners: array<u32, 36> = array<u32, 36>(
+        0u, 2u, 6u, 0u, 6u, 4u,
+        1u, 5u, 7u, 1u, 7u, 3u,
+        0u, 4u, 5u, 0u, 5u, 1u,
+        2u, 3u, 7u, 2u, 7u, 6u,
+        0u, 1u, 3u, 0u, 3u, 2u,
+        4u, 6u, 7u, 4u, 7u, 5u,
+    );
+    let corner = corners[vertex_index];
+    let position = vec3<f32>(
+        select(box_min.x, box_max.x, (corner & 1u) != 0u),
+        select(box_min.y, box_max.y, (corner & 2u) != 0u),
+        select(box_min.z, box_max.z, (corner & 4u) != 0u),
+    );
+
+    var out: VertexOutput;
+    out.clip_position = uniform.view_proj * vec4<f32>(position, 1.0);
+    out.query = instance_index;
+    return out;
+}
+
+// Tested early, so fragments behind the depth buffer never get to
+// mark the box.
+[[stage(fragment), early_depth_test]]
+fn fs_main(in: VertexOutput) {
+    // Every fragment writes the same value, so there's no need for
+    // them to wait on each other with an atomic.
+    samples.values[in.query] = 1u;
+}
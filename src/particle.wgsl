@@ -0,0 +1,29 @@
+// Draws each particle as a single point.
+
+[[block]]
+struct CameraUniform {
+    view_proj: mat4x4<f32>;
+};
+
+[[group(0), binding(0)]]
+var<uniform> camera: CameraUniform;
+
+struct VertexOutput {
+    [[builtin(position)]] clip_position: vec4<f32>;
+    [[location(0)]] age: f32;
+};
+
+[[stage(vertex)]]
+fn main([[location(0)]] position: vec4<f32>) -> VertexOutput {
+    var out: VertexOutput;
+    out.clip_position = camera.view_proj * vec4<f32>(position.xyz, 1.0);
+    out.age = position.w;
+    return out;
+}
+
+[[stage(fragment)]]
+fn main(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    // Fades from yellow to red as the particle gets older.
+    let t = clamp(in.age / 2.0, 0.0, 1.0);
+    return vec4<f32>(1.0, 1.0 - t, 0.2 * (1.0 - t), 1.0);
+}
@@ -0,0 +1,63 @@
+// Draws each particle as a quad facing the camera.
+
+[[block]]
+struct CameraUniform {
+    view_proj: mat4x4<f32>;
+    right: vec4<f32>;
+    up: vec4<f32>;
+};
+
+[[group(0), binding(0)]]
+var<uniform> camera: CameraUniform;
+
+struct InstanceInput {
+    [[location(5)]] model_matrix_0: vec4<f32>;
+    [[location(6)]] model_matrix_1: vec4<f32>;
+    [[location(7)]] model_matrix_2: vec4<f32>;
+    [[location(8)]] model_matrix_3: vec4<f32>;
+    [[location(9)]] color: vec4<f32>;
+};
+
+struct VertexOutput {
+    [[builtin(position)]] clip_position: vec4<f32>;
+    [[location(0)]] color: vec4<f32>;
+    // From -1 to 1 across the quad.
+    [[location(1)]] corner: vec2<f32>;
+};
+
+[[stage(vertex)]]
+fn main(
+    [[builtin(vertex_index)]] vertex_index: u32,
+    instance: InstanceInput,
+) -> VertexOutput {
+    // Two triangles, wound counter-clockwise.
+    var corners: array<vec2<f32>, 6> = array<vec2<f32>, 6>(
+        vec2<f32>(-1.0, -1.0),
+        vec2<f32>(1.0, -1.0),
+        vec2<f32>(1.0, 1.0),
+        vec2<f32>(-1.0, -1.0),
+        vec2<f32>(1.0, 1.0),
+        vec2<f32>(-1.0, 1.0),
+    );
+    let corner = corners[vertex_index];
+
+    // Only the translation and scale of the instance are used.
+    // The quad is spanned by the camera's own axes instead, so
+    // it always faces the camera.
+    let center = instance.model_matrix_3.xyz;
+    let size = length(instance.model_matrix_0.xyz);
+    let offset = (camera.right.xyz * corner.x + camera.up.xyz * corner.y) * size * 0.5;
+
+    var out: VertexOutput;
+    out.clip_position = camera.view_proj * vec4<f32>(center + offset, 1.0);
+    out.color = instance.color;
+    out.corner = corner;
+    return out;
+}
+
+[[stage(fragment)]]
+fn main(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    // Round, with soft edges.
+    let falloff = clamp((1.0 - length(in.corner)) * 2.0, 0.0, 1.0);
+    return vec4<f32>(in.color.rgb, in.color.a * falloff);
+}
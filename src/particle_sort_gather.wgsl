@@ -0,0 +1,55 @@
+// Copies the particles into the order their keys were sorted in,
+// so they can be drawn from back to front.
+
+struct SortKey {
+    depth: f32;
+    draw_index: u32;
+};
+
+[[block]]
+struct Keys {
+    keys: array<SortKey>;
+};
+
+[[block]]
+struct Vectors {
+    data: array<vec4<f32>>;
+};
+
+// Also the arguments of the indirect draw, which hold the
+// number of particles to copy.
+[[block]]
+struct DrawArgs {
+    vertex_count: u32;
+    instance_count: u32;
+    first_vertex: u32;
+    first_instance: u32;
+};
+
+[[group(0), binding(0)]]
+var<storage, read> sorted: Keys;
+// Instance matrices take four vectors each.
+[[group(0), binding(1)]]
+var<storage, read> src_instances: Vectors;
+[[group(0), binding(2)]]
+var<storage, read> src_colors: Vectors;
+[[group(0), binding(3)]]
+var<storage, read_write> dst_instances: Vectors;
+[[group(0), binding(4)]]
+var<storage, read_write> dst_colors: Vectors;
+[[group(0), binding(5)]]
+var<storage, read> draw_args: DrawArgs;
+
+[[stage(compute), workgroup_size(64, 1, 1)]]
+fn main([[builtin(global_invocation_id)]] id: vec3<u32>) {
+    let index = id.x;
+    if (index >= draw_args.instance_count) {
+        return;
+    }
+
+    let source = sorted.keys[index].draw_index;
+    for (var i = 0u; i < 4u; i = i + 1u) {
+        dst_instances.data[index * 4u + i] = src_instances.data[source * 4u + i];
+    }
+    dst_colors.data[index] = src_colors.data[source];
+}
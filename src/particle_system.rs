@@ -0,0 +1,462 @@
+use cgmath::{Matrix4, Vector3, Vector4};
+
+use crate::{
+    camera::Camera,
+    compute_sort::{ComputeSort, SortKey},
+    depth::DepthBuffer,
+    instance::{InstanceBuffer, InstanceData},
+    msaa::MsaaConfig,
+    pipeline::RenderPipelineBuilder,
+};
+
+/// Must match `workgroup_size` in the gather shader.
+const GATHER_WORKGROUP_SIZE: u32 = 64;
+
+/// Arguments of `draw_indirect`.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct DrawIndirectArgs {
+    vertex_count: u32,
+    instance_count: u32,
+    first_vertex: u32,
+    first_instance: u32,
+}
+
+/// Where particles come from, and how they behave.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EmitterConfig {
+    pub position: [f32; 3],
+    /// Direction particles are launched in, on average.
+    pub direction: [f32; 3],
+    /// How far particles stray from `direction`, as the largest
+    /// random offset added to it before it's scaled by `speed`.
+    pub spread: f32,
+    pub speed: f32,
+    /// Acceleration applied to every particle.
+    pub gravity: [f32; 3],
+    /// Seconds a particle lives before it's respawned.
+    pub lifetime: f32,
+    /// Particles fade from the start color to the end color over their life.
+    pub start_color: [f32; 4],
+    pub end_color: [f32; 4],
+    pub size: f32,
+}
+
+impl Default for EmitterConfig {
+    fn default() -> Self {
+        EmitterConfig {
+            position: [0.0, 0.0, 0.0],
+            direction: [0.0, 1.0, 0.0],
+            spread: 0.3,
+            speed: 1.0,
+            gravity: [0.0, -1.0, 0.0],
+            lifetime: 2.0,
+            start_color: [1.0, 1.0, 1.0, 1.0],
+            end_color: [1.0, 1.0, 1.0, 0.0],
+            size: 0.1,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Particle {
+    pub position: [f32; 3],
+    pub velocity: [f32; 3],
+    /// Seconds left before the particle is respawned.
+    pub lifetime: f32,
+    pub color: [f32; 4],
+    pub size: f32,
+}
+
+/// Particles simulated on the CPU, and drawn as instanced
+/// quads facing the camera.
+///
+/// The number of particles is fixed. Particles that die are
+/// respawned at the emitter straight away.
+///
+/// Since the particles are blended, they're sorted on the GPU by
+/// [`prepare`](Self::prepare) and drawn from the farthest to the
+/// nearest.
+pub struct ParticleSystem {
+    pub particles: Vec<Particle>,
+    pub emitter: EmitterConfig,
+    rng: XorShift,
+    instances: InstanceBuffer,
+    /// One color per instance, next to the transforms.
+    color_buffer: wgpu::Buffer,
+    sort: ComputeSort,
+    /// The camera space depth of each particle, and its index.
+    key_buffer: wgpu::Buffer,
+    /// The instances and colors, copied into sorted order.
+    sorted_instances: wgpu::Buffer,
+    sorted_colors: wgpu::Buffer,
+    /// Written by [`upload`](Self::upload), and read by the gather
+    /// shader for the number of particles to copy.
+    indirect_buffer: wgpu::Buffer,
+    gather_pipeline: wgpu::ComputePipeline,
+    gather_bind_group: wgpu::BindGroup,
+    pipeline: wgpu::RenderPipeline,
+    pipeline_layout: wgpu::PipelineLayout,
+    shader: wgpu::ShaderModule,
+    format: wgpu::TextureFormat,
+}
+
+impl ParticleSystem {
+    /// The particles are drawn with the camera's bind group at group 0.
+    pub fn new(
+        device: &wgpu::Device,
+        camera_layout: &wgpu::BindGroupLayout,
+        format: wgpu::TextureFormat,
+        msaa: MsaaConfig,
+        count: u32,
+        emitter: EmitterConfig,
+    ) -> Self {
+        let mut rng = XorShift::new(0x2545_f491);
+        // Spawned at different points in their lives, so they
+        // don't all come out of the emitter at once.
+        let particles = (0..count)
+            .map(|i| {
+                let mut particle = spawn(&emitter, &mut rng);
+                particle.lifetime *= (i + 1) as f32 / count as f32;
+                particle
+            })
+            .collect();
+
+        // The unsorted particles are only read by the gather shader.
+        let instances = InstanceBuffer::with_usage(device, count, wgpu::BufferUsages::STORAGE);
+        let colors_size = (count as usize * std::mem::size_of::<[f32; 4]>()) as wgpu::BufferAddress;
+        let color_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Particle System Color Buffer"),
+            size: colors_size,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let sort = ComputeSort::new(device, count);
+        let key_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Particle System Key Buffer"),
+            size: (count as usize * std::mem::size_of::<SortKey>()) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let sorted_instances = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Particle System Sorted Instance Buffer"),
+            size: (count as usize * std::mem::size_of::<InstanceData>()) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::VERTEX,
+            mapped_at_creation: false,
+        });
+        let sorted_colors = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Particle System Sorted Color Buffer"),
+            size: colors_size,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::VERTEX,
+            mapped_at_creation: false,
+        });
+        let indirect_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Particle System Indirect Buffer"),
+            size: std::mem::size_of::<DrawIndirectArgs>() as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::STORAGE
+                | wgpu::BufferUsages::INDIRECT
+                | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let storage_entry = |binding, read_only| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        let gather_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Particle System Gather Bind Group Layout"),
+            entries: &[
+                storage_entry(0, true),
+                storage_entry(1, true),
+                storage_entry(2, true),
+                storage_entry(3, false),
+                storage_entry(4, false),
+                storage_entry(5, true),
+            ],
+        });
+        let gather_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Particle System Gather Bind Group"),
+            layout: &gather_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: key_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: instances.buffer().as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: color_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: sorted_instances.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: sorted_colors.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 5,
+                    resource: indirect_buffer.as_entire_binding(),
+                },
+            ],
+        });
+        let gather_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Particle System Gather Pipeline Layout"),
+                bind_group_layouts: &[&gather_layout],
+                push_constant_ranges: &[],
+            });
+        let gather_shader = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+            label: Some("Particle System Gather Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("particle_sort_gather.wgsl").into()),
+        });
+        let gather_pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("Particle System Gather Pipeline"),
+            layout: Some(&gather_pipeline_layout),
+            module: &gather_shader,
+            entry_point: "main",
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Particle System Pipeline Layout"),
+            bind_group_layouts: &[camera_layout],
+            push_constant_ranges: &[],
+        });
+        let shader = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+            label: Some("Particle System Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("particle_billboard.wgsl").into()),
+        });
+        let pipeline = create_pipeline(device, &pipeline_layout, &shader, format, msaa);
+
+        ParticleSystem {
+            particles,
+            emitter,
+            rng,
+            instances,
+            color_buffer,
+            sort,
+            key_buffer,
+            sorted_instances,
+            sorted_colors,
+            indirect_buffer,
+            gather_pipeline,
+            gather_bind_group,
+            pipeline,
+            pipeline_layout,
+            shader,
+            format,
+        }
+    }
+
+    /// Moves the particles on by `delta_time` seconds.
+    pub fn update(&mut self, delta_time: f32) {
+        let gravity = Vector3::from(self.emitter.gravity);
+
+        for particle in &mut self.particles {
+            particle.lifetime -= delta_time;
+            if particle.lifetime <= 0.0 {
+                *particle = spawn(&self.emitter, &mut self.rng);
+                continue;
+            }
+
+            let velocity = Vector3::from(particle.velocity) + gravity * delta_time;
+            let position = Vector3::from(particle.position) + velocity * delta_time;
+            particle.velocity = velocity.into();
+            particle.position = position.into();
+
+            // Fades towards the end color as the particle ages.
+            let age = 1.0 - particle.lifetime / self.emitter.lifetime;
+            for channel in 0..4 {
+                particle.color[channel] = lerp(
+                    self.emitter.start_color[channel],
+                    self.emitter.end_color[channel],
+                    age,
+                );
+            }
+        }
+    }
+
+    /// Uploads the particles, along with their depths from `camera`
+    /// to be sorted by.
+    pub fn upload(&mut self, queue: &wgpu::Queue, camera: &Camera) {
+        let instances = self
+            .particles
+            .iter()
+            .map(|particle| {
+                InstanceData::from_matrix(
+                    Matrix4::from_translation(Vector3::from(particle.position))
+                        * Matrix4::from_scale(particle.size),
+                )
+            })
+            .collect::<Vec<_>>();
+        self.instances.upload(queue, &instances);
+
+        let colors = self
+            .particles
+            .iter()
+            .map(|particle| particle.color)
+            .collect::<Vec<_>>();
+        queue.write_buffer(&self.color_buffer, 0, bytemuck::cast_slice(&colors));
+
+        // The camera looks down negative z, so camera space z is the
+        // negated distance in front of it, and sorting from smallest
+        // to largest puts the farthest particles first.
+        let view = camera.build_view_matrix();
+        let keys = self
+            .particles
+            .iter()
+            .enumerate()
+            .map(|(index, particle)| {
+                let position = Vector3::from(particle.position);
+                SortKey {
+                    depth: (view * Vector4::new(position.x, position.y, position.z, 1.0)).z,
+                    draw_index: index as u32,
+                }
+            })
+            .collect::<Vec<_>>();
+        queue.write_buffer(&self.key_buffer, 0, bytemuck::cast_slice(&keys));
+
+        let args = DrawIndirectArgs {
+            // The corners of the quad come from the vertex index.
+            vertex_count: 6,
+            instance_count: self.instances.len(),
+            first_vertex: 0,
+            first_instance: 0,
+        };
+        queue.write_buffer(&self.indirect_buffer, 0, bytemuck::bytes_of(&args));
+    }
+
+    /// Records the passes sorting the uploaded particles from back to
+    /// front. Must come before the particles are drawn.
+    pub fn prepare(&self, device: &wgpu::Device, encoder: &mut wgpu::CommandEncoder) {
+        let count = self.instances.len();
+        self.sort.sort(device, encoder, &self.key_buffer, count);
+
+        let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("Particle System Gather Pass"),
+        });
+        compute_pass.set_pipeline(&self.gather_pipeline);
+        compute_pass.set_bind_group(0, &self.gather_bind_group, &[]);
+        compute_pass.dispatch(count.div_ceil(GATHER_WORKGROUP_SIZE), 1, 1);
+    }
+
+    /// Should be drawn after the opaque geometry, since the
+    /// particles are blended over it, and after [`prepare`](Self::prepare).
+    pub fn draw<'a>(
+        &'a self,
+        render_pass: &mut wgpu::RenderPass<'a>,
+        camera_bind_group: &'a wgpu::BindGroup,
+    ) {
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, camera_bind_group, &[]);
+        render_pass.set_vertex_buffer(0, self.sorted_instances.slice(..));
+        render_pass.set_vertex_buffer(1, self.sorted_colors.slice(..));
+        render_pass.draw_indirect(&self.indirect_buffer, 0);
+    }
+
+    /// Recreates the pipeline to match the sample count of the pass.
+    pub fn set_msaa(&mut self, device: &wgpu::Device, msaa: MsaaConfig) {
+        self.pipeline = create_pipeline(
+            device,
+            &self.pipeline_layout,
+            &self.shader,
+            self.format,
+            msaa,
+        );
+    }
+}
+
+fn spawn(emitter: &EmitterConfig, rng: &mut XorShift) -> Particle {
+    let jitter = Vector3::new(rng.next_signed(), rng.next_signed(), rng.next_signed());
+    let direction = Vector3::from(emitter.direction) + jitter * emitter.spread;
+
+    Particle {
+        position: emitter.position,
+        velocity: (direction * emitter.speed).into(),
+        lifetime: emitter.lifetime,
+        color: emitter.start_color,
+        size: emitter.size,
+    }
+}
+
+fn lerp(a: f32, b: f32, t: f32) -> f32 {
+    a + (b - a) * t
+}
+
+/// Small, fast random number generator. Good enough for
+/// scattering particles and SSAO samples, and nothing else.
+pub(crate) struct XorShift(u32);
+
+impl XorShift {
+    pub(crate) fn new(seed: u32) -> Self {
+        // Zero would stay zero forever.
+        XorShift(seed.max(1))
+    }
+
+    /// Random number from -1 to 1.
+    pub(crate) fn next_signed(&mut self) -> f32 {
+        self.next_unit() * 2.0 - 1.0
+    }
+
+    /// Random number from 0 to 1.
+    pub(crate) fn next_unit(&mut self) -> f32 {
+        let mut x = self.0;
+        x ^= x << 13;
+        x ^= x >> 17;
+        x ^= x << 5;
+        self.0 = x;
+        x as f32 / u32::MAX as f32
+    }
+}
+
+fn create_pipeline(
+    device: &wgpu::Device,
+    layout: &wgpu::PipelineLayout,
+    shader: &wgpu::ShaderModule,
+    format: wgpu::TextureFormat,
+    msaa: MsaaConfig,
+) -> wgpu::RenderPipeline {
+    RenderPipelineBuilder::new()
+        .label("Particle System Pipeline")
+        .vertex_shader(shader, "main")
+        .vertex_layouts(&[
+            InstanceData::vertex_buffer_layout(),
+            wgpu::VertexBufferLayout {
+                array_stride: std::mem::size_of::<[f32; 4]>() as wgpu::BufferAddress,
+                step_mode: wgpu::VertexStepMode::Instance,
+                attributes: &wgpu::vertex_attr_array![9 => Float32x4],
+            },
+        ])
+        .fragment_shader(
+            shader,
+            "main",
+            &[wgpu::ColorTargetState {
+                format,
+                blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                write_mask: wgpu::ColorWrites::ALL,
+            }],
+        )
+        // Billboards are only ever seen from the front, but
+        // the winding flips when the camera looks straight down.
+        .cull_mode(None)
+        // Tested against the scene, but not written, so the
+        // particles don't hide each other.
+        .depth_stencil(wgpu::DepthStencilState {
+            depth_write_enabled: false,
+            ..DepthBuffer::depth_stencil_state()
+        })
+        .multisample(msaa.count)
+        .build(device, layout)
+        .expect("failed to build particle system pipeline")
+}
@@ -0,0 +1,71 @@
+// Moves the particles of a fountain, and relaunches
+// them from the emitter when they've lived too long.
+
+struct Particle {
+    // `w` holds the particle's age in seconds. A negative age
+    // means the particle is still waiting to be launched.
+    position: vec4<f32>;
+    velocity: vec4<f32>;
+};
+
+[[block]]
+struct Particles {
+    particles: array<Particle>;
+};
+
+[[block]]
+struct Params {
+    delta_time: f32;
+    time: f32;
+};
+
+[[group(0), binding(0)]]
+var<storage, read> src: Particles;
+[[group(0), binding(1)]]
+var<storage, read_write> dst: Particles;
+[[group(1), binding(0)]]
+var<uniform> params: Params;
+
+let LIFETIME: f32 = 2.0;
+let GRAVITY: f32 = -9.8;
+
+// Cheap integer hash, used as a random number generator.
+fn hash(x: u32) -> u32 {
+    var h = x * 747796405u + 2891336453u;
+    h = ((h >> ((h >> 28u) + 4u)) ^ h) * 277803737u;
+    return (h >> 22u) ^ h;
+}
+
+// Random number between 0 and 1.
+fn random(seed: u32) -> f32 {
+    return f32(hash(seed)) / 4294967295.0;
+}
+
+[[stage(compute), workgroup_size(64)]]
+fn main([[builtin(global_invocation_id)]] id: vec3<u32>) {
+    let index = id.x;
+    if (index >= arrayLength(&src.particles)) {
+        return;
+    }
+
+    var particle = src.particles[index];
+    let age = particle.position.w + params.delta_time;
+
+    if (age >= LIFETIME || (age >= 0.0 && particle.position.w < 0.0)) {
+        // Launch upwards in a random direction within a cone.
+        let seed = index * 3u + u32(params.time * 1000.0) * 7919u;
+        let angle = random(seed) * 6.2831853;
+        let spread = random(seed + 1u) * 1.5;
+        let speed = 4.0 + random(seed + 2u);
+        particle.position = vec4<f32>(0.0, 0.0, 0.0, age % LIFETIME);
+        particle.velocity = vec4<f32>(cos(angle) * spread, speed, sin(angle) * spread, 0.0);
+    } elseif (age >= 0.0) {
+        particle.velocity.y = particle.velocity.y + GRAVITY * params.delta_time;
+        let position = particle.position.xyz + particle.velocity.xyz * params.delta_time;
+        particle.position = vec4<f32>(position, age);
+    } else {
+        particle.position.w = age;
+    }
+
+    dst.particles[index] = particle;
+}
@@ -0,0 +1,176 @@
+use crate::{
+    compute::ComputePass, depth::DepthBuffer, msaa::MsaaConfig, pipeline::RenderPipelineBuilder,
+};
+
+/// Must match `workgroup_size` in the update shader.
+const WORKGROUP_SIZE: u32 = 64;
+
+/// Must match `LIFETIME` in the update shader.
+const LIFETIME: f32 = 2.0;
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct Particle {
+    /// `w` is the particle's age in seconds.
+    position: [f32; 4],
+    velocity: [f32; 4],
+}
+
+impl Particle {
+    fn vertex_buffer_layout<'a>() -> wgpu::VertexBufferLayout<'a> {
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<Particle>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Vertex,
+            // Only the position is needed for drawing,
+            // the velocity is skipped over.
+            attributes: &[wgpu::VertexAttribute {
+                offset: 0,
+                shader_location: 0,
+                format: wgpu::VertexFormat::Float32x4,
+            }],
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct ParticleParams {
+    delta_time: f32,
+    time: f32,
+    // Uniform buffers are at least 16 bytes.
+    _padding: [f32; 2],
+}
+
+/// A fountain of particles simulated on the GPU, and drawn as points.
+pub struct ParticleSimulation {
+    compute: ComputePass<ParticleParams>,
+    count: u32,
+    time: f32,
+    pipeline: wgpu::RenderPipeline,
+    pipeline_layout: wgpu::PipelineLayout,
+    shader: wgpu::ShaderModule,
+    format: wgpu::TextureFormat,
+}
+
+impl ParticleSimulation {
+    /// The particles are drawn with the camera's bind group at group 0.
+    pub fn new(
+        device: &wgpu::Device,
+        camera_layout: &wgpu::BindGroupLayout,
+        format: wgpu::TextureFormat,
+        msaa: MsaaConfig,
+        count: u32,
+    ) -> Self {
+        // Launches are staggered over the first lifetime, so the
+        // particles don't all move together.
+        let particles = (0..count)
+            .map(|i| Particle {
+                position: [0.0, 0.0, 0.0, -(i as f32 / count as f32) * LIFETIME],
+                velocity: [0.0; 4],
+            })
+            .collect::<Vec<_>>();
+
+        let compute = ComputePass::new(
+            device,
+            "Particle Update",
+            include_str!("particle_update.wgsl"),
+            bytemuck::cast_slice(&particles),
+            &ParticleParams {
+                delta_time: 0.0,
+                time: 0.0,
+                _padding: [0.0; 2],
+            },
+        );
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Particle Pipeline Layout"),
+            bind_group_layouts: &[camera_layout],
+            push_constant_ranges: &[],
+        });
+        let shader = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+            label: Some("Particle Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("particle.wgsl").into()),
+        });
+        let pipeline = create_pipeline(device, &pipeline_layout, &shader, format, msaa);
+
+        ParticleSimulation {
+            compute,
+            count,
+            time: 0.0,
+            pipeline,
+            pipeline_layout,
+            shader,
+            format,
+        }
+    }
+
+    /// Advances the simulation by `dt` seconds on the next dispatch.
+    pub fn update(&mut self, queue: &wgpu::Queue, dt: f32) {
+        self.time += dt;
+        self.compute.set_params(
+            queue,
+            &ParticleParams {
+                delta_time: dt,
+                time: self.time,
+                _padding: [0.0; 2],
+            },
+        );
+    }
+
+    /// Records the compute pass that steps the particles.
+    /// Must come before the particles are drawn.
+    pub fn dispatch(&mut self, encoder: &mut wgpu::CommandEncoder) {
+        let num_groups = self.count.div_ceil(WORKGROUP_SIZE);
+        self.compute.dispatch(encoder, num_groups);
+    }
+
+    pub fn draw<'a>(
+        &'a self,
+        render_pass: &mut wgpu::RenderPass<'a>,
+        camera_bind_group: &'a wgpu::BindGroup,
+    ) {
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, camera_bind_group, &[]);
+        // The output of the simulation is drawn directly.
+        render_pass.set_vertex_buffer(0, self.compute.output_buffer().slice(..));
+        render_pass.draw(0..self.count, 0..1);
+    }
+
+    /// Recreates the pipeline to match the sample count of the pass.
+    pub fn set_msaa(&mut self, device: &wgpu::Device, msaa: MsaaConfig) {
+        self.pipeline = create_pipeline(
+            device,
+            &self.pipeline_layout,
+            &self.shader,
+            self.format,
+            msaa,
+        );
+    }
+}
+
+fn create_pipeline(
+    device: &wgpu::Device,
+    layout: &wgpu::PipelineLayout,
+    shader: &wgpu::ShaderModule,
+    format: wgpu::TextureFormat,
+    msaa: MsaaConfig,
+) -> wgpu::RenderPipeline {
+    RenderPipelineBuilder::new()
+        .label("Particle Pipeline")
+        .vertex_shader(shader, "main")
+        .vertex_layouts(&[Particle::vertex_buffer_layout()])
+        .fragment_shader(
+            shader,
+            "main",
+            &[wgpu::ColorTargetState {
+                format,
+                blend: Some(wgpu::BlendState::REPLACE),
+                write_mask: wgpu::ColorWrites::ALL,
+            }],
+        )
+        .topology(wgpu::PrimitiveTopology::PointList)
+        .depth_stencil(DepthBuffer::depth_stencil_state())
+        .multisample(msaa.count)
+        .build(device, layout)
+        .expect("failed to build particle pipeline")
+}
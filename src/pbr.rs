@@ -0,0 +1,449 @@
+use std::sync::Arc;
+
+use wgpu::util::DeviceExt;
+
+use crate::{
+    depth::DepthBuffer,
+    gltf_loader::GltfMaterial,
+    ibl::{BrdfLut, IrradianceMap, PrefilteredEnvMap},
+    instance::InstanceData,
+    msaa::MsaaConfig,
+    pipeline::RenderPipelineBuilder,
+    texture::Texture,
+    vertex::{self, Vertex},
+};
+
+/// The textures of a [`PbrMaterial`], in the order they're bound.
+const TEXTURE_COUNT: u32 = 5;
+
+/// Where the [`PbrEnvironment`] is bound, after the textures.
+const ENVIRONMENT_BINDING: u32 = TEXTURE_COUNT * 2;
+
+/// The light surrounding the scene, for the ambient light of
+/// [`PbrMaterial`]s, from maps precomputed by
+/// [`IblPrecompute`](crate::ibl::IblPrecompute).
+///
+/// Bound with each material at bindings 10 to 13: the irradiance
+/// map, the pre-filtered map, the BRDF lookup table and a sampler
+/// for all three.
+pub struct PbrEnvironment {
+    irradiance: wgpu::TextureView,
+    prefiltered: wgpu::TextureView,
+    brdf_lut: wgpu::TextureView,
+    sampler: wgpu::Sampler,
+}
+
+impl PbrEnvironment {
+    pub fn new(
+        device: &wgpu::Device,
+        (irradiance, prefiltered, brdf_lut): (IrradianceMap, PrefilteredEnvMap, BrdfLut),
+    ) -> Self {
+        PbrEnvironment {
+            irradiance: irradiance.view,
+            prefiltered: prefiltered.view,
+            brdf_lut: brdf_lut.view,
+            sampler: create_environment_sampler(device),
+        }
+    }
+
+    /// An environment giving off no light, for when there's no sky
+    /// to precompute the maps from. Materials are then only lit by
+    /// the ambient light of the scene's lights.
+    pub fn dark(device: &wgpu::Device, queue: &wgpu::Queue) -> Self {
+        let cube = |label| {
+            let texture = device.create_texture_with_data(
+                queue,
+                &wgpu::TextureDescriptor {
+                    label: Some(label),
+                    size: wgpu::Extent3d {
+                        width: 1,
+                        height: 1,
+                        depth_or_array_layers: 6,
+                    },
+                    mip_level_count: 1,
+                    sample_count: 1,
+                    dimension: wgpu::TextureDimension::D2,
+                    format: Texture::LINEAR_FORMAT,
+                    usage: wgpu::TextureUsages::TEXTURE_BINDING,
+                },
+                &[0; 4 * 6],
+            );
+            texture.create_view(&wgpu::TextureViewDescriptor {
+                label: Some(label),
+                dimension: Some(wgpu::TextureViewDimension::Cube),
+                ..Default::default()
+            })
+        };
+        let brdf_lut = Texture::solid(
+            device,
+            queue,
+            [0, 0, 0, 255],
+            Texture::LINEAR_FORMAT,
+            Some("Dark BRDF Lookup Table"),
+        );
+
+        PbrEnvironment {
+            irradiance: cube("Dark Irradiance Map"),
+            prefiltered: cube("Dark Prefiltered Environment Map"),
+            brdf_lut: brdf_lut.view,
+            sampler: create_environment_sampler(device),
+        }
+    }
+
+    fn bind_group_layout_entries() -> [wgpu::BindGroupLayoutEntry; 4] {
+        let texture = |binding, view_dimension| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                multisampled: false,
+                view_dimension,
+                sample_type: wgpu::TextureSampleType::Float { filterable: true },
+            },
+            count: None,
+        };
+        [
+            texture(ENVIRONMENT_BINDING, wgpu::TextureViewDimension::Cube),
+            texture(ENVIRONMENT_BINDING + 1, wgpu::TextureViewDimension::Cube),
+            texture(ENVIRONMENT_BINDING + 2, wgpu::TextureViewDimension::D2),
+            wgpu::BindGroupLayoutEntry {
+                binding: ENVIRONMENT_BINDING + 3,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Sampler {
+                    comparison: false,
+                    filtering: true,
+                },
+                count: None,
+            },
+        ]
+    }
+
+    fn bind_group_entries(&self) -> [wgpu::BindGroupEntry<'_>; 4] {
+        [
+            wgpu::BindGroupEntry {
+                binding: ENVIRONMENT_BINDING,
+                resource: wgpu::BindingResource::TextureView(&self.irradiance),
+            },
+            wgpu::BindGroupEntry {
+                binding: ENVIRONMENT_BINDING + 1,
+                resource: wgpu::BindingResource::TextureView(&self.prefiltered),
+            },
+            wgpu::BindGroupEntry {
+                binding: ENVIRONMENT_BINDING + 2,
+                resource: wgpu::BindingResource::TextureView(&self.brdf_lut),
+            },
+            wgpu::BindGroupEntry {
+                binding: ENVIRONMENT_BINDING + 3,
+                resource: wgpu::BindingResource::Sampler(&self.sampler),
+            },
+        ]
+    }
+}
+
+/// Blends between the levels of the pre-filtered map, since
+/// roughness changes smoothly across a surface.
+fn create_environment_sampler(device: &wgpu::Device) -> wgpu::Sampler {
+    device.create_sampler(&wgpu::SamplerDescriptor {
+        label: Some("PBR Environment Sampler"),
+        address_mode_u: wgpu::AddressMode::ClampToEdge,
+        address_mode_v: wgpu::AddressMode::ClampToEdge,
+        address_mode_w: wgpu::AddressMode::ClampToEdge,
+        mag_filter: wgpu::FilterMode::Linear,
+        min_filter: wgpu::FilterMode::Linear,
+        mipmap_filter: wgpu::FilterMode::Linear,
+        ..Default::default()
+    })
+}
+
+/// A surface described by the metallic-roughness workflow, the
+/// way glTF describes materials.
+///
+/// Each texture is bound with its sampler after it, at bindings
+/// 0 to 9, in the order of the fields:
+///
+/// - `albedo` is the base color, in sRGB.
+/// - `normal` is a tangent space normal map.
+/// - `metallic_roughness` has the roughness in green and how
+///   metal the surface is in blue, like glTF.
+/// - `ao` has how much ambient light reaches the surface in red.
+/// - `emissive` is light given off by the surface, in sRGB.
+///
+/// The [`PbrEnvironment`] lighting it is bound after them. The bind
+/// group keeps the textures alive, so only it is kept.
+pub struct PbrMaterial {
+    pub bind_group: wgpu::BindGroup,
+}
+
+impl PbrMaterial {
+    /// Layout of every material's bind group. Layouts are the same
+    /// when their entries are, so this can be called for each
+    /// pipeline that needs it.
+    pub fn bind_group_layout(device: &wgpu::Device) -> wgpu::BindGroupLayout {
+        let entries = (0..TEXTURE_COUNT)
+            .flat_map(|texture| Texture::bind_group_layout_entry(texture * 2))
+            .chain(PbrEnvironment::bind_group_layout_entries())
+            .collect::<Vec<_>>();
+        device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("PBR Material Bind Group Layout"),
+            entries: &entries,
+        })
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        environment: &PbrEnvironment,
+        albedo: Texture,
+        normal: Texture,
+        metallic_roughness: Texture,
+        ao: Texture,
+        emissive: Texture,
+    ) -> Self {
+        let textures = [&albedo, &normal, &metallic_roughness, &ao, &emissive];
+        let entries = textures
+            .iter()
+            .zip(0..)
+            .flat_map(|(texture, i)| texture.bind_group_entries(i * 2))
+            .chain(environment.bind_group_entries())
+            .collect::<Vec<_>>();
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("PBR Material Bind Group"),
+            layout,
+            entries: &entries,
+        });
+
+        PbrMaterial { bind_group }
+    }
+
+    /// Uploads a material read from a glTF file. The factors are only
+    /// used for the textures that aren't there, rather than scaling
+    /// them.
+    pub fn from_gltf_material(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        layout: &wgpu::BindGroupLayout,
+        environment: &PbrEnvironment,
+        material: &GltfMaterial,
+    ) -> Self {
+        let fallbacks = gltf_fallbacks(material);
+        let texture = |img: &Option<image::DynamicImage>, format, fallback| match img {
+            Some(img) => {
+                Texture::from_image_with_format(device, queue, img, Some("glTF Texture"), format)
+            }
+            None => solid_texture(device, queue, fallback, format),
+        };
+
+        Self::new(
+            device,
+            layout,
+            environment,
+            texture(
+                &material.base_color_texture,
+                Texture::FORMAT,
+                fallbacks.albedo,
+            ),
+            texture(
+                &material.normal_texture,
+                Texture::LINEAR_FORMAT,
+                FLAT_NORMAL,
+            ),
+            texture(
+                &material.metallic_roughness_texture,
+                Texture::LINEAR_FORMAT,
+                fallbacks.metallic_roughness,
+            ),
+            texture(&material.occlusion_texture, Texture::LINEAR_FORMAT, WHITE),
+            texture(
+                &material.emissive_texture,
+                Texture::FORMAT,
+                fallbacks.emissive,
+            ),
+        )
+    }
+}
+
+pub const WHITE: [u8; 4] = [255, 255, 255, 255];
+/// Straight out of the surface, in tangent space.
+pub const FLAT_NORMAL: [u8; 4] = [128, 128, 255, 255];
+
+/// The texels standing in for a glTF material's missing textures,
+/// made from its factors.
+struct GltfFallbacks {
+    albedo: [u8; 4],
+    metallic_roughness: [u8; 4],
+    emissive: [u8; 4],
+}
+
+fn gltf_fallbacks(material: &GltfMaterial) -> GltfFallbacks {
+    let to_byte = |value: f32| (value.clamp(0.0, 1.0) * 255.0).round() as u8;
+    // Factors are linear, but the color textures are sRGB.
+    let to_srgb_byte = |value: f32| to_byte(value.clamp(0.0, 1.0).powf(1.0 / 2.2));
+
+    let [r, g, b, a] = material.base_color_factor;
+    let [er, eg, eb] = material.emissive_factor;
+    GltfFallbacks {
+        albedo: [
+            to_srgb_byte(r),
+            to_srgb_byte(g),
+            to_srgb_byte(b),
+            to_byte(a),
+        ],
+        metallic_roughness: [
+            0,
+            to_byte(material.roughness_factor),
+            to_byte(material.metallic_factor),
+            255,
+        ],
+        emissive: [to_srgb_byte(er), to_srgb_byte(eg), to_srgb_byte(eb), 255],
+    }
+}
+
+/// A single texel texture, standing in for a map that wasn't given.
+fn solid_texture(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    color: [u8; 4],
+    format: wgpu::TextureFormat,
+) -> Texture {
+    Texture::solid(device, queue, color, format, Some("PBR Fallback Texture"))
+}
+
+/// Draws meshes with [`PbrMaterial`]s, lit by the scene's lights
+/// with the Cook-Torrance BRDF, and by their [`PbrEnvironment`].
+///
+/// Drawn like the scene pipeline, with the camera, lights and shadow
+/// cascades at groups 0 to 2, and the material at group 3. Normal maps
+/// are oriented by the mesh's tangents, or for meshes without any, by
+/// how the texture coordinates change across the screen.
+pub struct PbrPipeline {
+    pipeline: Arc<wgpu::RenderPipeline>,
+    pipeline_layout: wgpu::PipelineLayout,
+    shader: wgpu::ShaderModule,
+    format: wgpu::TextureFormat,
+}
+
+impl PbrPipeline {
+    pub fn new(
+        device: &wgpu::Device,
+        camera_layout: &wgpu::BindGroupLayout,
+        light_layout: &wgpu::BindGroupLayout,
+        shadow_layout: &wgpu::BindGroupLayout,
+        format: wgpu::TextureFormat,
+        msaa: MsaaConfig,
+    ) -> Self {
+        let material_layout = PbrMaterial::bind_group_layout(device);
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("PBR Pipeline Layout"),
+            bind_group_layouts: &[camera_layout, light_layout, shadow_layout, &material_layout],
+            push_constant_ranges: &[],
+        });
+        let shader = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+            label: Some("PBR Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("pbr.wgsl").into()),
+        });
+        let pipeline = create_pipeline(device, &pipeline_layout, &shader, format, msaa);
+
+        PbrPipeline {
+            pipeline: Arc::new(pipeline),
+            pipeline_layout,
+            shader,
+            format,
+        }
+    }
+
+    /// Shared with the [`Material`](crate::material::Material)s
+    /// drawn with it.
+    pub fn pipeline(&self) -> &Arc<wgpu::RenderPipeline> {
+        &self.pipeline
+    }
+
+    /// Recreates the pipeline to match the sample count of the pass.
+    /// Materials still hold the old one until it's replaced in them.
+    pub fn set_msaa(&mut self, device: &wgpu::Device, msaa: MsaaConfig) {
+        self.pipeline = Arc::new(create_pipeline(
+            device,
+            &self.pipeline_layout,
+            &self.shader,
+            self.format,
+            msaa,
+        ));
+    }
+}
+
+fn create_pipeline(
+    device: &wgpu::Device,
+    layout: &wgpu::PipelineLayout,
+    shader: &wgpu::ShaderModule,
+    format: wgpu::TextureFormat,
+    msaa: MsaaConfig,
+) -> wgpu::RenderPipeline {
+    RenderPipelineBuilder::new()
+        .label("PBR Pipeline")
+        .vertex_shader(shader, "main")
+        .vertex_layouts(&[
+            Vertex::vertex_buffer_layout(),
+            InstanceData::vertex_buffer_layout(),
+            vertex::tangent_buffer_layout(),
+        ])
+        .fragment_shader(
+            shader,
+            "main",
+            &[wgpu::ColorTargetState {
+                format,
+                blend: Some(wgpu::BlendState::REPLACE),
+                write_mask: wgpu::ColorWrites::ALL,
+            }],
+        )
+        .depth_stencil(DepthBuffer::depth_stencil_state())
+        .multisample(msaa.count)
+        .build(device, layout)
+        .expect("failed to build PBR pipeline")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::context::test_device;
+
+    #[test]
+    fn gltf_defaults_fall_back_to_white_rough_metal() {
+        let fallbacks = gltf_fallbacks(&GltfMaterial::default());
+        assert_eq!(fallbacks.albedo, WHITE);
+        assert_eq!(fallbacks.metallic_roughness, [0, 255, 255, 255]);
+        assert_eq!(fallbacks.emissive, [0, 0, 0, 255]);
+    }
+
+    #[test]
+    fn gltf_factors_are_converted_to_srgb() {
+        let material = GltfMaterial {
+            base_color_factor: [0.5, 0.0, 1.0, 0.5],
+            metallic_factor: 0.0,
+            roughness_factor: 0.5,
+            ..GltfMaterial::default()
+        };
+        let fallbacks = gltf_fallbacks(&material);
+        // 0.5 ^ (1 / 2.2) is about 0.73, but alpha stays linear.
+        assert_eq!(fallbacks.albedo, [186, 0, 255, 128]);
+        assert_eq!(fallbacks.metallic_roughness, [0, 128, 0, 255]);
+    }
+
+    #[test]
+    fn materials_bind_in_a_dark_environment() {
+        let (device, queue) = match test_device() {
+            Some(device) => device,
+            None => return eprintln!("skipped, there's no adapter"),
+        };
+        let layout = PbrMaterial::bind_group_layout(&device);
+        let environment = PbrEnvironment::dark(&device, &queue);
+        // Bind groups that don't match the layout are a validation
+        // error, which panics.
+        PbrMaterial::from_gltf_material(
+            &device,
+            &queue,
+            &layout,
+            &environment,
+            &GltfMaterial::default(),
+        );
+        device.poll(wgpu::Maintain::Wait);
+    }
+}
@@ -0,0 +1,329 @@
+// Draws meshes with PBR materials in the metallic-roughness workflow,
+// lit by the scene's directional lights with the Cook-Torrance BRDF,
+// and by the environment with maps precomputed by ibl.rs.
+
+[[block]]
+struct CameraUniform {
+    view_proj: mat4x4<f32>;
+    right: vec4<f32>;
+    up: vec4<f32>;
+    // Fragments behind this plane are thrown away.
+    clip_plane: vec4<f32>;
+    eye: vec4<f32>;
+};
+
+[[group(0), binding(0)]]
+var<uniform> camera: CameraUniform;
+
+struct VertexInput {
+    [[location(0)]] position: vec3<f32>;
+    [[location(1)]] color: vec3<f32>;
+    [[location(2)]] normal: vec3<f32>;
+    [[location(3)]] tex_coords: vec2<f32>;
+    // From a buffer of its own. All zeros for meshes without tangents.
+    [[location(4)]] tangent: vec4<f32>;
+};
+
+struct InstanceInput {
+    [[location(5)]] model_matrix_0: vec4<f32>;
+    [[location(6)]] model_matrix_1: vec4<f32>;
+    [[location(7)]] model_matrix_2: vec4<f32>;
+    [[location(8)]] model_matrix_3: vec4<f32>;
+};
+
+struct VertexOutput {
+    [[builtin(position)]] clip_position: vec4<f32>;
+    [[location(0)]] color: vec3<f32>;
+    [[location(1)]] world_normal: vec3<f32>;
+    [[location(2)]] world_position: vec3<f32>;
+    [[location(3)]] tex_coords: vec2<f32>;
+    [[location(4)]] clip_distance: f32;
+    [[location(5)]] world_tangent: vec4<f32>;
+};
+
+[[stage(vertex)]]
+fn main(
+    model: VertexInput,
+    instance: InstanceInput,
+) -> VertexOutput {
+    let model_matrix = mat4x4<f32>(
+        instance.model_matrix_0,
+        instance.model_matrix_1,
+        instance.model_matrix_2,
+        instance.model_matrix_3,
+    );
+    // Only correct as long as the model matrix scales uniformly.
+    let normal_matrix = mat3x3<f32>(
+        model_matrix.x.xyz,
+        model_matrix.y.xyz,
+        model_matrix.z.xyz,
+    );
+    let world_position = model_matrix * vec4<f32>(model.position, 1.0);
+
+    var out: VertexOutput;
+    out.color = model.color;
+    out.world_normal = normal_matrix * model.normal;
+    out.world_tangent = vec4<f32>(normal_matrix * model.tangent.xyz, model.tangent.w);
+    out.world_position = world_position.xyz;
+    out.tex_coords = model.tex_coords;
+    out.clip_distance = dot(world_position, camera.clip_plane);
+    out.clip_position = camera.view_proj * world_position;
+    return out;
+}
+
+struct Light {
+    // Direction the light is shining in.
+    direction: vec3<f32>;
+    ambient: f32;
+    color: vec3<f32>;
+};
+
+// Must match `MAX_LIGHTS` in light.rs.
+let MAX_LIGHTS: u32 = 8u;
+
+[[block]]
+struct LightUniform {
+    lights: array<Light, MAX_LIGHTS>;
+    count: u32;
+};
+
+[[group(1), binding(0)]]
+var<uniform> light: LightUniform;
+
+[[block]]
+struct ShadowUniform {
+    light_view_proj: array<mat4x4<f32>, 4>;
+    // The camera's view, to find the fragment's cascade by its depth.
+    view: mat4x4<f32>;
+    // How far in front of the camera each cascade ends.
+    splits: vec4<f32>;
+};
+
+[[group(2), binding(0)]]
+var<uniform> shadow: ShadowUniform;
+[[group(2), binding(1)]]
+var t_shadow_0: texture_depth_2d;
+[[group(2), binding(2)]]
+var t_shadow_1: texture_depth_2d;
+[[group(2), binding(3)]]
+var t_shadow_2: texture_depth_2d;
+[[group(2), binding(4)]]
+var t_shadow_3: texture_depth_2d;
+[[group(2), binding(5)]]
+var s_shadow: sampler_comparison;
+
+[[group(3), binding(0)]]
+var t_albedo: texture_2d<f32>;
+[[group(3), binding(1)]]
+var s_albedo: sampler;
+[[group(3), binding(2)]]
+var t_normal: texture_2d<f32>;
+[[group(3), binding(3)]]
+var s_normal: sampler;
+[[group(3), binding(4)]]
+var t_metallic_roughness: texture_2d<f32>;
+[[group(3), binding(5)]]
+var s_metallic_roughness: sampler;
+[[group(3), binding(6)]]
+var t_ao: texture_2d<f32>;
+[[group(3), binding(7)]]
+var s_ao: sampler;
+[[group(3), binding(8)]]
+var t_emissive: texture_2d<f32>;
+[[group(3), binding(9)]]
+var s_emissive: sampler;
+[[group(3), binding(10)]]
+var t_irradiance: texture_cube<f32>;
+[[group(3), binding(11)]]
+var t_prefiltered: texture_cube<f32>;
+[[group(3), binding(12)]]
+var t_brdf_lut: texture_2d<f32>;
+[[group(3), binding(13)]]
+var s_environment: sampler;
+
+// Must match `ShadowPass::SIZE` in shadow.rs.
+let SHADOW_MAP_SIZE: f32 = 2048.0;
+let PI: f32 = 3.14159265359;
+// The last mip level of the pre-filtered map, for a roughness of 1.
+// Must match `PREFILTER_MIP_LEVELS` in ibl.rs, less one.
+let MAX_PREFILTER_LOD: f32 = 4.0;
+
+// Compares `depth` with the shadow map of the cascade at `index`,
+// the same as the scene shader.
+fn sample_cascade(index: i32, uv: vec2<f32>, depth: f32) -> f32 {
+    if (index == 0) {
+        return textureSampleCompareLevel(t_shadow_0, s_shadow, uv, depth);
+    } elseif (index == 1) {
+        return textureSampleCompareLevel(t_shadow_1, s_shadow, uv, depth);
+    } elseif (index == 2) {
+        return textureSampleCompareLevel(t_shadow_2, s_shadow, uv, depth);
+    }
+    return textureSampleCompareLevel(t_shadow_3, s_shadow, uv, depth);
+}
+
+// How much of the first light reaches the fragment, from the cascade
+// it's in and averaged over neighbouring texels like the scene shader.
+fn shadow_factor(world_position: vec3<f32>) -> f32 {
+    let depth = -(shadow.view * vec4<f32>(world_position, 1.0)).z;
+    if (depth >= shadow.splits.w) {
+        return 1.0;
+    }
+    let cascade = i32(depth >= shadow.splits.x)
+        + i32(depth >= shadow.splits.y)
+        + i32(depth >= shadow.splits.z);
+
+    let light_space = shadow.light_view_proj[cascade] * vec4<f32>(world_position, 1.0);
+    let ndc = light_space.xyz / light_space.w;
+    if (ndc.x < -1.0 || ndc.x > 1.0 || ndc.y < -1.0 || ndc.y > 1.0 || ndc.z > 1.0) {
+        return 1.0;
+    }
+
+    let uv = ndc.xy * vec2<f32>(0.5, -0.5) + vec2<f32>(0.5, 0.5);
+    let texel = 1.0 / SHADOW_MAP_SIZE;
+
+    var lit = 0.0;
+    for (var y: i32 = -1; y <= 1; y = y + 1) {
+        for (var x: i32 = -1; x <= 1; x = x + 1) {
+            let offset = vec2<f32>(f32(x), f32(y)) * texel;
+            lit = lit + sample_cascade(cascade, uv + offset, ndc.z);
+        }
+    }
+    return lit / 9.0;
+}
+
+// How many microfacets line up with the half vector, from
+// the GGX distribution.
+fn distribution_ggx(n_dot_h: f32, roughness: f32) -> f32 {
+    let a = roughness * roughness;
+    let a2 = a * a;
+    let d = n_dot_h * n_dot_h * (a2 - 1.0) + 1.0;
+    return a2 / (PI * d * d);
+}
+
+// How much of the microfacets shadow each other, from
+// Schlick's approximation of Smith's.
+fn geometry_schlick_ggx(n_dot_v: f32, roughness: f32) -> f32 {
+    let r = roughness + 1.0;
+    let k = r * r / 8.0;
+    return n_dot_v / (n_dot_v * (1.0 - k) + k);
+}
+
+// How much light is reflected rather than refracted.
+fn fresnel_schlick(cos_theta: f32, f0: vec3<f32>) -> vec3<f32> {
+    return f0 + (vec3<f32>(1.0) - f0) * pow(1.0 - cos_theta, 5.0);
+}
+
+// Like `fresnel_schlick`, for light from every direction at once,
+// which rough surfaces reflect less of at grazing angles.
+fn fresnel_schlick_roughness(cos_theta: f32, f0: vec3<f32>, roughness: f32) -> vec3<f32> {
+    let f90 = max(vec3<f32>(1.0 - roughness), f0);
+    return f0 + (f90 - f0) * pow(1.0 - cos_theta, 5.0);
+}
+
+// Takes a normal from the normal map out of tangent space, oriented by
+// the vertex's tangent. Without tangents on the vertices, the tangent
+// and bitangent are worked out from how the position and texture
+// coordinates change between neighbouring pixels.
+fn perturb_normal(
+    normal: vec3<f32>,
+    vertex_tangent: vec4<f32>,
+    position: vec3<f32>,
+    uv: vec2<f32>,
+    mapped: vec3<f32>,
+) -> vec3<f32> {
+    let dp1 = dpdx(position);
+    let dp2 = dpdy(position);
+    let duv1 = dpdx(uv);
+    let duv2 = dpdy(uv);
+
+    let dp2_perp = cross(dp2, normal);
+    let dp1_perp = cross(normal, dp1);
+    let tangent = dp2_perp * duv1.x + dp1_perp * duv2.x;
+    let bitangent = dp2_perp * duv1.y + dp1_perp * duv2.y;
+    // Scaled by the same amount, so the frame isn't skewed.
+    let scale = inverseSqrt(max(max(dot(tangent, tangent), dot(bitangent, bitangent)), 0.0000001));
+    let derived = mat3x3<f32>(tangent * scale, bitangent * scale, normal);
+    if (vertex_tangent.w == 0.0) {
+        return normalize(derived * mapped);
+    }
+
+    // Made perpendicular to the normal again, after interpolation.
+    let t = normalize(vertex_tangent.xyz - normal * dot(normal, vertex_tangent.xyz));
+    let b = cross(normal, t) * sign(vertex_tangent.w);
+    return normalize(mat3x3<f32>(t, b, normal) * mapped);
+}
+
+[[stage(fragment)]]
+fn main(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    // Sampled before the discard, since sampling needs the
+    // neighbouring pixels to still be running.
+    let albedo = textureSample(t_albedo, s_albedo, in.tex_coords).rgb * in.color;
+    let mapped = textureSample(t_normal, s_normal, in.tex_coords).xyz * 2.0 - vec3<f32>(1.0);
+    let metallic_roughness = textureSample(t_metallic_roughness, s_metallic_roughness, in.tex_coords);
+    let ao = textureSample(t_ao, s_ao, in.tex_coords).r;
+    let emissive = textureSample(t_emissive, s_emissive, in.tex_coords).rgb;
+    let normal = perturb_normal(
+        normalize(in.world_normal),
+        in.world_tangent,
+        in.world_position,
+        in.tex_coords,
+        mapped,
+    );
+
+    if (in.clip_distance < 0.0) {
+        discard;
+    }
+
+    // Roughness is in green and metalness in blue, like glTF.
+    let metallic = metallic_roughness.b;
+    // Perfectly smooth surfaces make the distribution blow up.
+    let roughness = max(metallic_roughness.g, 0.05);
+
+    let view_dir = normalize(camera.eye.xyz - in.world_position);
+    let n_dot_v = max(dot(normal, view_dir), 0.0001);
+    // Non-metals reflect a little of every color head on.
+    let f0 = mix(vec3<f32>(0.04), albedo, metallic);
+
+    var color = emissive;
+    for (var i: u32 = 0u; i < light.count; i = i + 1u) {
+        let l = light.lights[i];
+        // Points from the surface towards the light.
+        let light_dir = normalize(-l.direction);
+        let half_dir = normalize(view_dir + light_dir);
+        let n_dot_l = max(dot(normal, light_dir), 0.0);
+
+        var radiance = l.color;
+        // Only the first light casts shadows.
+        if (i == 0u) {
+            radiance = radiance * shadow_factor(in.world_position);
+        }
+
+        let d = distribution_ggx(max(dot(normal, half_dir), 0.0), roughness);
+        let g = geometry_schlick_ggx(n_dot_v, roughness) * geometry_schlick_ggx(n_dot_l, roughness);
+        let f = fresnel_schlick(max(dot(half_dir, view_dir), 0.0), f0);
+        let specular = d * g * f / (4.0 * n_dot_v * n_dot_l + 0.0001);
+
+        // Metals have no diffuse light, and what's reflected isn't diffused.
+        let diffuse = (vec3<f32>(1.0) - f) * (1.0 - metallic) * albedo / PI;
+        let ambient = l.color * l.ambient * albedo * ao;
+        color = color + ambient + (diffuse + specular) * radiance * n_dot_l;
+    }
+
+    // Light from the environment, with the split sum approximation.
+    // Sampled at explicit levels, since this is after the discard.
+    let f = fresnel_schlick_roughness(n_dot_v, f0, roughness);
+    let irradiance = textureSampleLevel(t_irradiance, s_environment, normal, 0.0).rgb;
+    let diffuse = (vec3<f32>(1.0) - f) * (1.0 - metallic) * irradiance * albedo;
+    let reflected = reflect(-view_dir, normal);
+    let prefiltered = textureSampleLevel(
+        t_prefiltered,
+        s_environment,
+        reflected,
+        roughness * MAX_PREFILTER_LOD
+    ).rgb;
+    let brdf = textureSampleLevel(t_brdf_lut, s_environment, vec2<f32>(n_dot_v, roughness), 0.0).rg;
+    let specular = prefiltered * (f * brdf.x + brdf.y);
+    color = color + (diffuse + specular) * ao;
+
+    return vec4<f32>(color, 1.0);
+}
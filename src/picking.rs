@@ -0,0 +1,370 @@
+use crate::{
+    depth::DepthBuffer,
+    instance::{InstanceBuffer, InstanceData},
+    mesh::Mesh,
+    pipeline::RenderPipelineBuilder,
+    push_constants::{PushConstantRange, SetPushConstantsTyped},
+    render_pass_guard::RenderPassBuilder,
+    uniform::dynamic_offset_stride,
+    vertex::Vertex,
+};
+
+/// Most draws in a single picking pass.
+pub const MAX_DRAWS: usize = 64;
+
+/// Nothing was drawn on the pixel.
+const NO_ID: u32 = 0;
+
+/// Where `picking.wgsl` reads the draw's first ID from, when it's
+/// pushed rather than bound.
+const DRAW_UNIFORM_BINDING: &str = "[[group(1), binding(0)]]\nvar<uniform> draw";
+const DRAW_PUSH_CONSTANT: &str = "var<push_constant> draw";
+
+/// Something to draw into the picking texture.
+///
+/// The instances are given the IDs `base_id`, `base_id + 1`, and so on.
+/// IDs start at 1, since 0 is where nothing was drawn.
+pub struct PickingDraw<'a> {
+    pub mesh: &'a Mesh,
+    pub instances: &'a InstanceBuffer,
+    pub base_id: u32,
+}
+
+/// Finds out which object is on a pixel, by drawing the scene
+/// with every object in a color encoding its ID, and reading
+/// that pixel back.
+pub struct PickingPass {
+    texture: wgpu::Texture,
+    view: wgpu::TextureView,
+    /// Used when the main depth buffer is multisampled, since it
+    /// can't be attached next to the single-sampled ID texture.
+    depth: DepthBuffer,
+    pipeline: wgpu::RenderPipeline,
+    /// The uniforms each draw's first ID is bound from, when the
+    /// device doesn't have push constants to push it with.
+    draw_uniforms: Option<(wgpu::Buffer, wgpu::BindGroup)>,
+    /// How far apart the draws' uniforms are, since they're bound at a
+    /// dynamic offset for each.
+    draw_uniform_stride: wgpu::BufferAddress,
+    width: u32,
+    height: u32,
+}
+
+impl PickingPass {
+    /// The IDs are stored unchanged, so the format mustn't be sRGB.
+    pub const FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8Unorm;
+
+    /// The objects are drawn with the camera's bind group at group 0.
+    pub fn new(
+        device: &wgpu::Device,
+        camera_layout: &wgpu::BindGroupLayout,
+        width: u32,
+        height: u32,
+    ) -> Self {
+        let (texture, view) = create_texture(device, width, height);
+
+        // The first ID of each draw is pushed when the device can,
+        // and bound from a uniform buffer otherwise.
+        let push_constants = device.features().contains(wgpu::Features::PUSH_CONSTANTS);
+        let draw_uniform_stride = dynamic_offset_stride(device, std::mem::size_of::<[u32; 4]>());
+        let draw_uniforms =
+            (!push_constants).then(|| create_draw_uniforms(device, draw_uniform_stride));
+        let source = if push_constants {
+            include_str!("picking.wgsl").replace(DRAW_UNIFORM_BINDING, DRAW_PUSH_CONSTANT)
+        } else {
+            include_str!("picking.wgsl").to_owned()
+        };
+
+        let shader = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+            label: Some("Picking Shader"),
+            source: wgpu::ShaderSource::Wgsl(source.into()),
+        });
+        let mut builder = RenderPipelineBuilder::new()
+            .label("Picking Pipeline")
+            .vertex_shader(&shader, "main")
+            .vertex_layouts(&[
+                Vertex::vertex_buffer_layout(),
+                InstanceData::vertex_buffer_layout(),
+            ])
+            .fragment_shader(
+                &shader,
+                "main",
+                &[wgpu::ColorTargetState {
+                    format: Self::FORMAT,
+                    blend: None,
+                    write_mask: wgpu::ColorWrites::ALL,
+                }],
+            )
+            .depth_stencil(DepthBuffer::depth_stencil_state());
+        let pipeline_layout = match &draw_uniforms {
+            Some((_, draw_bind_group_layout, _)) => {
+                builder.create_layout(device, &[camera_layout, draw_bind_group_layout])
+            }
+            None => {
+                builder = builder
+                    .push_constants(&[PushConstantRange::of::<u32>(wgpu::ShaderStages::VERTEX, 0)]);
+                builder.create_layout(device, &[camera_layout])
+            }
+        };
+        let pipeline = builder
+            .build(device, &pipeline_layout)
+            .expect("failed to build picking pipeline");
+        let draw_uniforms = draw_uniforms.map(|(buffer, _, bind_group)| (buffer, bind_group));
+
+        PickingPass {
+            texture,
+            view,
+            depth: DepthBuffer::new(device, width, height),
+            pipeline,
+            draw_uniforms,
+            draw_uniform_stride,
+            width,
+            height,
+        }
+    }
+
+    /// Keeps the picking texture matching the surface size.
+    pub fn resize(&mut self, device: &wgpu::Device, width: u32, height: u32) {
+        let (texture, view) = create_texture(device, width, height);
+        self.texture = texture;
+        self.view = view;
+        self.depth.resize(device, width, height);
+        self.width = width;
+        self.height = height;
+    }
+
+    /// Draws the objects into the picking texture, and submits the pass.
+    ///
+    /// The depth buffer of the main pass is cleared and drawn into,
+    /// unless it's multisampled.
+    pub fn render(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        depth: &DepthBuffer,
+        camera_bind_group: &wgpu::BindGroup,
+        draws: &[PickingDraw],
+    ) {
+        if draws.len() > MAX_DRAWS {
+            log::warn!(
+                "picking pass draws at most {} objects, dropping {}",
+                MAX_DRAWS,
+                draws.len() - MAX_DRAWS
+            );
+        }
+        let draws = &draws[..draws.len().min(MAX_DRAWS)];
+
+        if let Some((draw_buffer, _)) = &self.draw_uniforms {
+            for (i, draw) in draws.iter().enumerate() {
+                queue.write_buffer(
+                    draw_buffer,
+                    i as wgpu::BufferAddress * self.draw_uniform_stride,
+                    bytemuck::cast_slice(&[draw.base_id, 0, 0, 0]),
+                );
+            }
+        }
+
+        let depth_view = if depth.sample_count() == 1 {
+            depth.view()
+        } else {
+            self.depth.view()
+        };
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Picking Encoder"),
+        });
+        let mut render_pass = RenderPassBuilder::begin_render_pass(
+            &mut encoder,
+            &wgpu::RenderPassDescriptor {
+                label: Some("Picking Pass"),
+                color_attachments: &[wgpu::RenderPassColorAttachment {
+                    view: &self.view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                        store: true,
+                    },
+                }],
+                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                    view: depth_view,
+                    depth_ops: Some(wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(1.0),
+                        store: true,
+                    }),
+                    stencil_ops: None,
+                }),
+            },
+        );
+
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, camera_bind_group, &[]);
+        for (i, draw) in draws.iter().enumerate() {
+            match &self.draw_uniforms {
+                Some((_, draw_bind_group)) => {
+                    let offset = (i as wgpu::BufferAddress * self.draw_uniform_stride) as u32;
+                    render_pass.set_bind_group(1, draw_bind_group, &[offset]);
+                }
+                None => render_pass.set_push_constants_typed(
+                    wgpu::ShaderStages::VERTEX,
+                    0,
+                    &draw.base_id,
+                ),
+            }
+            render_pass.set_vertex_buffer(1, draw.instances.slice());
+            draw.mesh
+                .draw_instanced(&mut render_pass, 0..draw.instances.len());
+        }
+        render_pass.end();
+        queue.submit(std::iter::once(encoder.finish()));
+    }
+
+    /// Reads back the ID drawn on the pixel at `(x, y)`, counted from
+    /// the top left. Returns `None` when nothing was drawn there, or
+    /// the pixel is outside of the texture.
+    ///
+    /// Blocks until the GPU has finished all submitted work.
+    pub fn query_pixel(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        x: u32,
+        y: u32,
+    ) -> Option<u32> {
+        if x >= self.width || y >= self.height {
+            return None;
+        }
+
+        // Copies work in whole rows, which are at least this long.
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Picking Readback Buffer"),
+            size: wgpu::COPY_BYTES_PER_ROW_ALIGNMENT as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Picking Readback Encoder"),
+        });
+        encoder.copy_texture_to_buffer(
+            wgpu::ImageCopyTexture {
+                texture: &self.texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d { x, y, z: 0 },
+                aspect: wgpu::TextureAspect::All,
+            },
+            wgpu::ImageCopyBuffer {
+                buffer: &buffer,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: std::num::NonZeroU32::new(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT),
+                    rows_per_image: None,
+                },
+            },
+            wgpu::Extent3d {
+                width: 1,
+                height: 1,
+                depth_or_array_layers: 1,
+            },
+        );
+        queue.submit(std::iter::once(encoder.finish()));
+
+        let slice = buffer.slice(..);
+        let mapping = slice.map_async(wgpu::MapMode::Read);
+        device.poll(wgpu::Maintain::Wait);
+        if let Err(err) = pollster::block_on(mapping) {
+            eprintln!("failed to map picking buffer: {}", err);
+            return None;
+        }
+
+        let id = {
+            let data = slice.get_mapped_range();
+            u32::from_le_bytes([data[0], data[1], data[2], data[3]])
+        };
+        buffer.unmap();
+
+        if id == NO_ID {
+            None
+        } else {
+            Some(id)
+        }
+    }
+}
+
+/// A buffer with room for the first ID of every draw, bound at a
+/// dynamic offset for each `stride` bytes apart, with its bind group
+/// and the layout of it.
+fn create_draw_uniforms(
+    device: &wgpu::Device,
+    stride: wgpu::BufferAddress,
+) -> (wgpu::Buffer, wgpu::BindGroupLayout, wgpu::BindGroup) {
+    let draw_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Picking Draw Buffer"),
+        size: stride * MAX_DRAWS as wgpu::BufferAddress,
+        usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    });
+    let draw_size = wgpu::BufferSize::new(std::mem::size_of::<[u32; 4]>() as u64);
+    let draw_bind_group_layout =
+        device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Picking Draw Bind Group Layout"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::VERTEX,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    // Every draw reads its own part of the buffer.
+                    has_dynamic_offset: true,
+                    min_binding_size: draw_size,
+                },
+                count: None,
+            }],
+        });
+    let draw_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some("Picking Draw Bind Group"),
+        layout: &draw_bind_group_layout,
+        entries: &[wgpu::BindGroupEntry {
+            binding: 0,
+            resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                buffer: &draw_buffer,
+                offset: 0,
+                size: draw_size,
+            }),
+        }],
+    });
+    (draw_buffer, draw_bind_group_layout, draw_bind_group)
+}
+
+fn create_texture(
+    device: &wgpu::Device,
+    width: u32,
+    height: u32,
+) -> (wgpu::Texture, wgpu::TextureView) {
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("Picking Texture"),
+        size: wgpu::Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: PickingPass::FORMAT,
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+    });
+    let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+    (texture, view)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shader_has_the_draw_uniform_to_push() {
+        let source = include_str!("picking.wgsl");
+        assert_eq!(source.matches(DRAW_UNIFORM_BINDING).count(), 1);
+        let pushed = source.replace(DRAW_UNIFORM_BINDING, DRAW_PUSH_CONSTANT);
+        assert!(!pushed.contains("[[group(1)"));
+    }
+}
@@ -0,0 +1,60 @@
+// Draws every object in a color encoding its ID, which is
+// read back to find out what's under the mouse.
+
+[[block]]
+struct CameraUniform {
+    view_proj: mat4x4<f32>;
+};
+
+[[group(0), binding(0)]]
+var<uniform> camera: CameraUniform;
+
+[[block]]
+struct DrawUniform {
+    // ID of the draw's first instance. The others follow on.
+    base_id: u32;
+};
+
+// Pushed rather than bound when the device has push constants, by
+// `PickingPass::new` swapping this for a `var<push_constant>`.
+[[group(1), binding(0)]]
+var<uniform> draw: DrawUniform;
+
+struct InstanceInput {
+    [[location(5)]] model_matrix_0: vec4<f32>;
+    [[location(6)]] model_matrix_1: vec4<f32>;
+    [[location(7)]] model_matrix_2: vec4<f32>;
+    [[location(8)]] model_matrix_3: vec4<f32>;
+};
+
+struct VertexOutput {
+    [[builtin(position)]] clip_position: vec4<f32>;
+    // Integers can't be interpolated.
+    [[location(0), interpolate(flat)]] id: u32;
+};
+
+[[stage(vertex)]]
+fn main(
+    [[location(0)]] position: vec3<f32>,
+    instance: InstanceInput,
+    [[builtin(instance_index)]] instance_index: u32,
+) -> VertexOutput {
+    let model_matrix = mat4x4<f32>(
+        instance.model_matrix_0,
+        instance.model_matrix_1,
+        instance.model_matrix_2,
+        instance.model_matrix_3,
+    );
+
+    var out: VertexOutput;
+    out.clip_position = camera.view_proj * model_matrix * vec4<f32>(position, 1.0);
+    out.id = draw.base_id + instance_index;
+    return out;
+}
+
+[[stage(fragment)]]
+fn main(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    // One byte of the ID in each channel, lowest first.
+    let bytes = vec4<u32>(in.id, in.id >> 8u, in.id >> 16u, in.id >> 24u) & vec4<u32>(255u);
+    return vec4<f32>(bytes) / 255.0;
+}
@@ -0,0 +1,245 @@
+use std::fmt;
+
+use crate::{push_constants::PushConstantRange, render_state::RenderStateSnapshot};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PipelineBuildError {
+    MissingVertexShader,
+    MissingFragmentShader,
+}
+
+impl fmt::Display for PipelineBuildError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PipelineBuildError::MissingVertexShader => {
+                write!(f, "render pipeline has no vertex shader")
+            }
+            PipelineBuildError::MissingFragmentShader => {
+                write!(f, "render pipeline has no fragment shader")
+            }
+        }
+    }
+}
+
+impl std::error::Error for PipelineBuildError {}
+
+/// The state a pipeline was built with, from
+/// [`RenderPipelineBuilder::state`], since wgpu can't be asked for it
+/// once the pipeline is built.
+#[derive(Debug, Clone)]
+pub struct PipelineState {
+    pub label: Option<String>,
+    pub primitive: wgpu::PrimitiveState,
+    pub depth_stencil: Option<wgpu::DepthStencilState>,
+    pub sample_count: u32,
+    /// The blending of each color target.
+    pub blend_states: Vec<Option<wgpu::BlendState>>,
+    /// A line for each vertex buffer, from
+    /// [`RenderStateSnapshot::describe_vertex_layout`].
+    pub vertex_layouts: Vec<String>,
+}
+
+/// Builds a render pipeline, starting out with the options
+/// most of our pipelines use.
+///
+/// By default triangles are wound counter-clockwise with back faces
+/// culled, filled in, and drawn without depth testing or multisampling.
+pub struct RenderPipelineBuilder<'a> {
+    label: Option<&'a str>,
+    vertex_shader: Option<(&'a wgpu::ShaderModule, &'a str)>,
+    fragment_shader: Option<(&'a wgpu::ShaderModule, &'a str)>,
+    depth_only: bool,
+    targets: Vec<wgpu::ColorTargetState>,
+    vertex_layouts: Vec<wgpu::VertexBufferLayout<'a>>,
+    primitive: wgpu::PrimitiveState,
+    depth_stencil: Option<wgpu::DepthStencilState>,
+    multisample: wgpu::MultisampleState,
+    push_constant_ranges: Vec<wgpu::PushConstantRange>,
+}
+
+impl<'a> RenderPipelineBuilder<'a> {
+    pub fn new() -> Self {
+        RenderPipelineBuilder {
+            label: None,
+            vertex_shader: None,
+            fragment_shader: None,
+            depth_only: false,
+            targets: Vec::new(),
+            vertex_layouts: Vec::new(),
+            primitive: wgpu::PrimitiveState {
+                // Means that each three vertices will correspond to one triangle.
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                strip_index_format: None,
+                // The `front_face` and `cull_mode` fields tell wgpu how to
+                // determine whether a given triangle is facing forward or not.
+                front_face: wgpu::FrontFace::Ccw,
+                cull_mode: Some(wgpu::Face::Back),
+                // Setting this to Line requires Features::POLYGON_MODE_LINE
+                polygon_mode: wgpu::PolygonMode::Fill,
+                // Requires Features::DEPTH_CLAMPING
+                clamp_depth: false,
+                // Requires Features::CONSERVATIVE_RASTERIZATION
+                conservative: false,
+            },
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState {
+                count: 1,
+                mask: !0,
+                // This has to do with anti-aliasing.
+                alpha_to_coverage_enabled: false,
+            },
+            push_constant_ranges: Vec::new(),
+        }
+    }
+
+    pub fn label(mut self, label: &'a str) -> Self {
+        self.label = Some(label);
+        self
+    }
+
+    /// The `entry` is the name of the function to be called
+    /// in the shader module.
+    pub fn vertex_shader(mut self, module: &'a wgpu::ShaderModule, entry: &'a str) -> Self {
+        self.vertex_shader = Some((module, entry));
+        self
+    }
+
+    /// The `targets` tell wgpu what color outputs it should set up.
+    pub fn fragment_shader(
+        mut self,
+        module: &'a wgpu::ShaderModule,
+        entry: &'a str,
+        targets: &[wgpu::ColorTargetState],
+    ) -> Self {
+        self.fragment_shader = Some((module, entry));
+        self.targets = targets.to_vec();
+        self
+    }
+
+    /// Builds the pipeline without a fragment shader, for passes
+    /// that only write depth, like rendering a shadow map.
+    pub fn depth_only(mut self) -> Self {
+        self.depth_only = true;
+        self
+    }
+
+    /// Tells wgpu what type of vertices we want to pass to the
+    /// vertex shader. If the vertices are generated in the shader,
+    /// then this can be left empty.
+    pub fn vertex_layouts(mut self, layouts: &[wgpu::VertexBufferLayout<'a>]) -> Self {
+        self.vertex_layouts = layouts.to_vec();
+        self
+    }
+
+    pub fn topology(mut self, topology: wgpu::PrimitiveTopology) -> Self {
+        self.primitive.topology = topology;
+        self
+    }
+
+    pub fn polygon_mode(mut self, polygon_mode: wgpu::PolygonMode) -> Self {
+        self.primitive.polygon_mode = polygon_mode;
+        self
+    }
+
+    pub fn cull_mode(mut self, cull_mode: Option<wgpu::Face>) -> Self {
+        self.primitive.cull_mode = cull_mode;
+        self
+    }
+
+    pub fn depth_stencil(mut self, depth_stencil: wgpu::DepthStencilState) -> Self {
+        self.depth_stencil = Some(depth_stencil);
+        self
+    }
+
+    /// Number of samples per pixel, for multisample anti-aliasing.
+    pub fn multisample(mut self, count: u32) -> Self {
+        self.multisample.count = count;
+        self
+    }
+
+    /// Push constants the pipeline's shaders read, which go in the
+    /// layout from [`RenderPipelineBuilder::create_layout`]. They
+    /// need [`wgpu::Features::PUSH_CONSTANTS`].
+    pub fn push_constants(mut self, ranges: &[PushConstantRange]) -> Self {
+        self.push_constant_ranges = ranges.iter().map(|range| range.to_wgpu()).collect();
+        self
+    }
+
+    /// A layout for the pipeline with the given bind groups, and the
+    /// push constants given to [`RenderPipelineBuilder::push_constants`].
+    pub fn create_layout(
+        &self,
+        device: &wgpu::Device,
+        bind_group_layouts: &[&wgpu::BindGroupLayout],
+    ) -> wgpu::PipelineLayout {
+        device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: self.label,
+            bind_group_layouts,
+            push_constant_ranges: &self.push_constant_ranges,
+        })
+    }
+
+    /// The state [`build`](Self::build) builds the pipeline with.
+    pub fn state(&self) -> PipelineState {
+        PipelineState {
+            label: self.label.map(str::to_string),
+            primitive: self.primitive,
+            depth_stencil: self.depth_stencil.clone(),
+            sample_count: self.multisample.count,
+            blend_states: if self.depth_only {
+                Vec::new()
+            } else {
+                self.targets.iter().map(|target| target.blend).collect()
+            },
+            vertex_layouts: self
+                .vertex_layouts
+                .iter()
+                .map(RenderStateSnapshot::describe_vertex_layout)
+                .collect(),
+        }
+    }
+
+    pub fn build(
+        &self,
+        device: &wgpu::Device,
+        layout: &wgpu::PipelineLayout,
+    ) -> Result<wgpu::RenderPipeline, PipelineBuildError> {
+        let (vertex_module, vertex_entry) = self
+            .vertex_shader
+            .ok_or(PipelineBuildError::MissingVertexShader)?;
+        let fragment = if self.depth_only {
+            None
+        } else {
+            let (module, entry_point) = self
+                .fragment_shader
+                .ok_or(PipelineBuildError::MissingFragmentShader)?;
+            Some(wgpu::FragmentState {
+                module,
+                entry_point,
+                targets: &self.targets,
+            })
+        };
+
+        Ok(
+            device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: self.label,
+                layout: Some(layout),
+                vertex: wgpu::VertexState {
+                    module: vertex_module,
+                    entry_point: vertex_entry,
+                    buffers: &self.vertex_layouts,
+                },
+                fragment,
+                primitive: self.primitive,
+                depth_stencil: self.depth_stencil.clone(),
+                multisample: self.multisample,
+            }),
+        )
+    }
+}
+
+impl<'a> Default for RenderPipelineBuilder<'a> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,202 @@
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap},
+    hash::{Hash, Hasher},
+    sync::Arc,
+};
+
+use crate::pipeline::PipelineState;
+
+/// Everything that makes one render pipeline different from another.
+///
+/// Two pipelines built from equal keys are interchangeable, so a
+/// [`PipelineCache`] hands out the one it already has instead of
+/// building another.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct PipelineKey {
+    /// Where the shaders were loaded from.
+    shader_paths: Vec<String>,
+    /// Hash of the shader sources, so an edited shader
+    /// isn't mistaken for the one it replaces.
+    shader_hash: u64,
+    vertex_layout_hash: u64,
+    targets: Vec<wgpu::ColorTargetState>,
+    primitive: wgpu::PrimitiveState,
+    depth_stencil: Option<DepthStencilKey>,
+    sample_count: u32,
+}
+
+impl PipelineKey {
+    /// Key for a pipeline built from the given `(path, source)` shaders.
+    /// The rest of the state starts out as the defaults of
+    /// [`RenderPipelineBuilder`](crate::pipeline::RenderPipelineBuilder).
+    pub fn new(shaders: &[(&str, &str)]) -> Self {
+        let mut hasher = DefaultHasher::new();
+        for (_, source) in shaders {
+            source.hash(&mut hasher);
+        }
+
+        PipelineKey {
+            shader_paths: shaders.iter().map(|(path, _)| path.to_string()).collect(),
+            shader_hash: hasher.finish(),
+            vertex_layout_hash: 0,
+            targets: Vec::new(),
+            primitive: wgpu::PrimitiveState {
+                cull_mode: Some(wgpu::Face::Back),
+                ..Default::default()
+            },
+            depth_stencil: None,
+            sample_count: 1,
+        }
+    }
+
+    pub fn vertex_layouts(mut self, layouts: &[wgpu::VertexBufferLayout]) -> Self {
+        // The layouts borrow their attributes, so only their hash is kept.
+        let mut hasher = DefaultHasher::new();
+        for layout in layouts {
+            layout.array_stride.hash(&mut hasher);
+            layout.step_mode.hash(&mut hasher);
+            layout.attributes.hash(&mut hasher);
+        }
+        self.vertex_layout_hash = hasher.finish();
+        self
+    }
+
+    pub fn targets(mut self, targets: &[wgpu::ColorTargetState]) -> Self {
+        self.targets = targets.to_vec();
+        self
+    }
+
+    pub fn primitive(mut self, primitive: wgpu::PrimitiveState) -> Self {
+        self.primitive = primitive;
+        self
+    }
+
+    pub fn depth_stencil(mut self, depth_stencil: &wgpu::DepthStencilState) -> Self {
+        self.depth_stencil = Some(DepthStencilKey::new(depth_stencil));
+        self
+    }
+
+    pub fn sample_count(mut self, count: u32) -> Self {
+        self.sample_count = count;
+        self
+    }
+
+    /// Whether the pipeline renders into a texture of `format`.
+    #[allow(dead_code)]
+    fn uses_format(&self, format: wgpu::TextureFormat) -> bool {
+        self.targets.iter().any(|target| target.format == format)
+    }
+}
+
+/// The depth bias is made of floats, which can't be hashed,
+/// so their bits are compared instead.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct DepthStencilKey {
+    format: wgpu::TextureFormat,
+    depth_write_enabled: bool,
+    depth_compare: wgpu::CompareFunction,
+    stencil: wgpu::StencilState,
+    bias_constant: i32,
+    bias_slope_scale: u32,
+    bias_clamp: u32,
+}
+
+impl DepthStencilKey {
+    fn new(state: &wgpu::DepthStencilState) -> Self {
+        DepthStencilKey {
+            format: state.format,
+            depth_write_enabled: state.depth_write_enabled,
+            depth_compare: state.depth_compare,
+            stencil: state.stencil.clone(),
+            bias_constant: state.bias.constant,
+            bias_slope_scale: state.bias.slope_scale.to_bits(),
+            bias_clamp: state.bias.clamp.to_bits(),
+        }
+    }
+}
+
+/// Render pipelines that have been built, so switching back to
+/// a previous set of options doesn't build the pipeline again.
+///
+/// Each is kept with the state it was built with.
+pub struct PipelineCache {
+    pipelines: HashMap<PipelineKey, (Arc<wgpu::RenderPipeline>, PipelineState)>,
+}
+
+impl PipelineCache {
+    pub fn new() -> Self {
+        PipelineCache {
+            pipelines: HashMap::new(),
+        }
+    }
+
+    /// Returns the pipeline built for `key`, or builds it with `create`.
+    pub fn get_or_create<F>(
+        &mut self,
+        device: &wgpu::Device,
+        key: PipelineKey,
+        create: F,
+    ) -> (Arc<wgpu::RenderPipeline>, PipelineState)
+    where
+        F: FnOnce(&wgpu::Device) -> (wgpu::RenderPipeline, PipelineState),
+    {
+        self.pipelines
+            .entry(key)
+            .or_insert_with(|| {
+                let (pipeline, state) = create(device);
+                (Arc::new(pipeline), state)
+            })
+            .clone()
+    }
+
+    /// Like [`get_or_create`](Self::get_or_create), for pipelines that
+    /// can fail to build. Nothing is cached when `create` fails, so the
+    /// next call tries again.
+    pub fn get_or_try_create<F, E>(
+        &mut self,
+        device: &wgpu::Device,
+        key: PipelineKey,
+        create: F,
+    ) -> Result<(Arc<wgpu::RenderPipeline>, PipelineState), E>
+    where
+        F: FnOnce(&wgpu::Device) -> Result<(wgpu::RenderPipeline, PipelineState), E>,
+    {
+        if let Some(pipeline) = self.pipelines.get(&key) {
+            return Ok(pipeline.clone());
+        }
+
+        let (pipeline, state) = create(device)?;
+        let pipeline = (Arc::new(pipeline), state);
+        self.pipelines.insert(key, pipeline.clone());
+        Ok(pipeline)
+    }
+
+    /// Drops the pipelines rendering into `format`, for when the surface
+    /// is reconfigured with a different format. Pipelines that don't
+    /// depend on it are kept.
+    #[allow(dead_code)]
+    pub fn invalidate_format(&mut self, format: wgpu::TextureFormat) {
+        self.pipelines.retain(|key, _| !key.uses_format(format));
+    }
+
+    #[allow(dead_code)]
+    pub fn len(&self) -> usize {
+        self.pipelines.len()
+    }
+
+    #[allow(dead_code)]
+    pub fn is_empty(&self) -> bool {
+        self.pipelines.is_empty()
+    }
+
+    #[allow(dead_code)]
+    pub fn clear(&mut self) {
+        self.pipelines.clear();
+    }
+}
+
+impl Default for PipelineCache {
+    fn default() -> Self {
+        Self::new()
+    }
+}
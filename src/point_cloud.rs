@@ -0,0 +1,330 @@
+use std::{fmt, path::Path};
+
+use crate::{
+    depth::DepthBuffer, msaa::MsaaConfig, pipeline::RenderPipelineBuilder, transform::Transform,
+    uniform::UniformBinding, vertex::VertexBuffer,
+};
+
+/// A point of a [`PointCloud`].
+#[repr(C)]
+#[derive(Debug, Copy, Clone, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct PointVertex {
+    pub position: [f32; 3],
+    pub color: [f32; 3],
+}
+
+impl PointVertex {
+    const ATTRIBUTES: [wgpu::VertexAttribute; 2] =
+        wgpu::vertex_attr_array![0 => Float32x3, 1 => Float32x3];
+
+    /// Points step once per vertex when drawn as a point list, and
+    /// once per instance when each is drawn as a quad.
+    fn vertex_buffer_layout<'a>(step_mode: wgpu::VertexStepMode) -> wgpu::VertexBufferLayout<'a> {
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<PointVertex>() as wgpu::BufferAddress,
+            step_mode,
+            attributes: &Self::ATTRIBUTES,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum PointCloudError {
+    Io(std::io::Error),
+    /// A line that isn't three or six numbers, counting from 1.
+    Parse {
+        line: usize,
+        text: String,
+    },
+}
+
+impl fmt::Display for PointCloudError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PointCloudError::Io(err) => write!(f, "failed to read point cloud: {}", err),
+            PointCloudError::Parse { line, text } => {
+                write!(f, "line {} isn't a point: {:?}", line, text)
+            }
+        }
+    }
+}
+
+impl std::error::Error for PointCloudError {}
+
+impl From<std::io::Error> for PointCloudError {
+    fn from(err: std::io::Error) -> Self {
+        PointCloudError::Io(err)
+    }
+}
+
+/// Reads the points of an XYZ file, with a point of `x y z r g b` on
+/// each line. Colors can be left off for white points.
+///
+/// Scanners write colors either from 0 to 255 or from 0 to 1, so
+/// they're taken to be out of 255 if any of them is above 1.
+/// Blank lines and lines starting with `#` are skipped.
+pub fn parse_xyz(text: &str) -> Result<Vec<PointVertex>, PointCloudError> {
+    let mut points = Vec::new();
+    for (index, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let error = || PointCloudError::Parse {
+            line: index + 1,
+            text: line.to_string(),
+        };
+        let values = line
+            .split_whitespace()
+            .map(str::parse::<f32>)
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|_| error())?;
+        let point = match values[..] {
+            [x, y, z] => PointVertex {
+                position: [x, y, z],
+                color: [1.0; 3],
+            },
+            [x, y, z, r, g, b] => PointVertex {
+                position: [x, y, z],
+                color: [r, g, b],
+            },
+            _ => return Err(error()),
+        };
+        points.push(point);
+    }
+
+    let out_of_255 = points
+        .iter()
+        .any(|point| point.color.iter().any(|&c| c > 1.0));
+    if out_of_255 {
+        for point in &mut points {
+            point.color = point.color.map(|c| c / 255.0);
+        }
+    }
+    Ok(points)
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct PointCloudUniform {
+    model: [[f32; 4]; 4],
+    point_size: f32,
+    _padding: [f32; 3],
+}
+
+/// Points uploaded to the GPU, like a scan, drawn by a
+/// [`PointCloudRenderer`].
+pub struct PointCloud {
+    vertices: VertexBuffer,
+    uniform: UniformBinding<PointCloudUniform>,
+    transform: Transform,
+    point_size: f32,
+}
+
+impl PointCloud {
+    /// The points are drawn as single pixels until they're given a size.
+    pub fn new(device: &wgpu::Device, points: &[PointVertex]) -> Self {
+        let transform = Transform::identity();
+        let uniform = UniformBinding::new(
+            device,
+            "Point Cloud Uniform",
+            wgpu::ShaderStages::VERTEX,
+            &PointCloudUniform {
+                model: transform.to_matrix(),
+                point_size: 0.0,
+                _padding: [0.0; 3],
+            },
+        );
+
+        PointCloud {
+            vertices: VertexBuffer::from_slice(device, points),
+            uniform,
+            transform,
+            point_size: 0.0,
+        }
+    }
+
+    /// Loads the points of an XYZ file, as read by [`parse_xyz`].
+    pub fn from_xyz_file(device: &wgpu::Device, path: &Path) -> Result<Self, PointCloudError> {
+        let text = std::fs::read_to_string(path)?;
+        let points = parse_xyz(&text)?;
+        Ok(Self::new(device, &points))
+    }
+
+    /// Number of points in the cloud.
+    pub fn len(&self) -> u32 {
+        self.vertices.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.vertices.is_empty()
+    }
+
+    #[allow(dead_code)]
+    pub fn transform(&self) -> &Transform {
+        &self.transform
+    }
+
+    /// Moves the cloud in world space. The points keep their size
+    /// when it's scaled.
+    pub fn set_transform(&mut self, queue: &wgpu::Queue, transform: Transform) {
+        self.transform = transform;
+        self.upload(queue);
+    }
+
+    #[allow(dead_code)]
+    pub fn point_size(&self) -> f32 {
+        self.point_size
+    }
+
+    /// Width of the points in world units. Points of size zero are
+    /// drawn a single pixel wide, however near they are.
+    pub fn set_point_size(&mut self, queue: &wgpu::Queue, point_size: f32) {
+        self.point_size = point_size.max(0.0);
+        self.upload(queue);
+    }
+
+    fn upload(&self, queue: &wgpu::Queue) {
+        self.uniform.update(
+            queue,
+            &PointCloudUniform {
+                model: self.transform.to_matrix(),
+                point_size: self.point_size,
+                _padding: [0.0; 3],
+            },
+        );
+    }
+}
+
+/// Draws [`PointCloud`]s into the scene.
+///
+/// WGSL has no way of setting how big a point is drawn, so points are
+/// only drawn as a point list while they're a pixel wide. Clouds of
+/// bigger points are drawn with a quad facing the camera per point
+/// instead, which takes six vertices rather than one.
+pub struct PointCloudRenderer {
+    point_pipeline: wgpu::RenderPipeline,
+    quad_pipeline: wgpu::RenderPipeline,
+    pipeline_layout: wgpu::PipelineLayout,
+    shader: wgpu::ShaderModule,
+    format: wgpu::TextureFormat,
+}
+
+impl PointCloudRenderer {
+    /// The clouds are drawn with the camera's bind group at group 0.
+    pub fn new(
+        device: &wgpu::Device,
+        camera_layout: &wgpu::BindGroupLayout,
+        format: wgpu::TextureFormat,
+        msaa: MsaaConfig,
+    ) -> Self {
+        // Only used for its layout, which every cloud's uniform shares.
+        let uniform = UniformBinding::new(
+            device,
+            "Point Cloud Uniform",
+            wgpu::ShaderStages::VERTEX,
+            &PointCloudUniform {
+                model: Transform::identity().to_matrix(),
+                point_size: 0.0,
+                _padding: [0.0; 3],
+            },
+        );
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Point Cloud Pipeline Layout"),
+            bind_group_layouts: &[camera_layout, &uniform.bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let shader = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+            label: Some("Point Cloud Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("point_cloud.wgsl").into()),
+        });
+        let (point_pipeline, quad_pipeline) =
+            create_pipelines(device, &pipeline_layout, &shader, format, msaa);
+
+        PointCloudRenderer {
+            point_pipeline,
+            quad_pipeline,
+            pipeline_layout,
+            shader,
+            format,
+        }
+    }
+
+    pub fn draw<'a>(
+        &'a self,
+        render_pass: &mut wgpu::RenderPass<'a>,
+        camera_bind_group: &'a wgpu::BindGroup,
+        cloud: &'a PointCloud,
+    ) {
+        if cloud.is_empty() {
+            return;
+        }
+
+        render_pass.set_bind_group(0, camera_bind_group, &[]);
+        render_pass.set_bind_group(1, &cloud.uniform.bind_group, &[]);
+        render_pass.set_vertex_buffer(0, cloud.vertices.slice());
+        if cloud.point_size > 0.0 {
+            render_pass.set_pipeline(&self.quad_pipeline);
+            render_pass.draw(0..6, 0..cloud.len());
+        } else {
+            render_pass.set_pipeline(&self.point_pipeline);
+            render_pass.draw(0..cloud.len(), 0..1);
+        }
+    }
+
+    /// Recreates the pipelines to match the sample count of the pass.
+    pub fn set_msaa(&mut self, device: &wgpu::Device, msaa: MsaaConfig) {
+        let (point_pipeline, quad_pipeline) = create_pipelines(
+            device,
+            &self.pipeline_layout,
+            &self.shader,
+            self.format,
+            msaa,
+        );
+        self.point_pipeline = point_pipeline;
+        self.quad_pipeline = quad_pipeline;
+    }
+}
+
+/// The pipelines drawing points as a point list, and as quads.
+fn create_pipelines(
+    device: &wgpu::Device,
+    layout: &wgpu::PipelineLayout,
+    shader: &wgpu::ShaderModule,
+    format: wgpu::TextureFormat,
+    msaa: MsaaConfig,
+) -> (wgpu::RenderPipeline, wgpu::RenderPipeline) {
+    let targets = [wgpu::ColorTargetState {
+        format,
+        blend: Some(wgpu::BlendState::REPLACE),
+        write_mask: wgpu::ColorWrites::ALL,
+    }];
+
+    let point_pipeline = RenderPipelineBuilder::new()
+        .label("Point Cloud Pipeline")
+        .vertex_shader(shader, "point")
+        .vertex_layouts(&[PointVertex::vertex_buffer_layout(
+            wgpu::VertexStepMode::Vertex,
+        )])
+        .fragment_shader(shader, "main", &targets)
+        .topology(wgpu::PrimitiveTopology::PointList)
+        .depth_stencil(DepthBuffer::depth_stencil_state())
+        .multisample(msaa.count)
+        .build(device, layout)
+        .expect("failed to build point cloud pipeline");
+
+    let quad_pipeline = RenderPipelineBuilder::new()
+        .label("Point Cloud Quad Pipeline")
+        .vertex_shader(shader, "quad")
+        .vertex_layouts(&[PointVertex::vertex_buffer_layout(
+            wgpu::VertexStepMode::Instance,
+        )])
+        .fragment_shader(shader, "main", &targets)
+        .depth_stencil(DepthBuffer::depth_stencil_state())
+        .multisample(msaa.count)
+        .build(device, layout)
+        .expect("failed to build point cloud quad pipeline");
+
+    (point_pipeline, quad_pipeline)
+}
@@ -0,0 +1,74 @@
+// Draws point clouds, either as single pixel points or as quads
+// facing the camera when the points have a size.
+
+[[block]]
+struct CameraUniform {
+    view_proj: mat4x4<f32>;
+    right: vec4<f32>;
+    up: vec4<f32>;
+};
+
+[[block]]
+struct PointCloudUniform {
+    model: mat4x4<f32>;
+    // Width of the quads in world units.
+    point_size: f32;
+};
+
+[[group(0), binding(0)]]
+var<uniform> camera: CameraUniform;
+
+[[group(1), binding(0)]]
+var<uniform> cloud: PointCloudUniform;
+
+struct PointInput {
+    [[location(0)]] position: vec3<f32>;
+    [[location(1)]] color: vec3<f32>;
+};
+
+struct VertexOutput {
+    [[builtin(position)]] clip_position: vec4<f32>;
+    [[location(0)]] color: vec3<f32>;
+};
+
+[[stage(vertex)]]
+fn point(in: PointInput) -> VertexOutput {
+    var out: VertexOutput;
+    out.clip_position = camera.view_proj * cloud.model * vec4<f32>(in.position, 1.0);
+    out.color = in.color;
+    return out;
+}
+
+// Each point is an instance, with the quad's corners coming
+// from the vertex index.
+[[stage(vertex)]]
+fn quad(
+    [[builtin(vertex_index)]] vertex_index: u32,
+    in: PointInput,
+) -> VertexOutput {
+    // Two triangles, wound counter-clockwise.
+    var corners: array<vec2<f32>, 6> = array<vec2<f32>, 6>(
+        vec2<f32>(-1.0, -1.0),
+        vec2<f32>(1.0, -1.0),
+        vec2<f32>(1.0, 1.0),
+        vec2<f32>(-1.0, -1.0),
+        vec2<f32>(1.0, 1.0),
+        vec2<f32>(-1.0, 1.0),
+    );
+    let corner = corners[vertex_index];
+
+    // The quad is spanned by the camera's own axes, so it always
+    // faces the camera, and isn't scaled by the cloud's transform.
+    let center = (cloud.model * vec4<f32>(in.position, 1.0)).xyz;
+    let offset = (camera.right.xyz * corner.x + camera.up.xyz * corner.y) * cloud.point_size * 0.5;
+
+    var out: VertexOutput;
+    out.clip_position = camera.view_proj * vec4<f32>(center + offset, 1.0);
+    out.color = in.color;
+    return out;
+}
+
+[[stage(fragment)]]
+fn main(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    return vec4<f32>(in.color, 1.0);
+}
@@ -0,0 +1,258 @@
+use std::{borrow::Cow, fmt, fs, io, path::PathBuf};
+
+use crate::{
+    fullscreen_triangle::FullscreenTriangle,
+    hdr_render_target::{HdrRenderTarget, HDR_FORMAT},
+    pipeline::RenderPipelineBuilder,
+    render_pass_guard::RenderPassBuilder,
+    shader_watcher,
+};
+
+/// Fragment shader run over the whole frame.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PostProcessEffect {
+    Passthrough,
+    Grayscale,
+    Invert,
+    /// Fragment shader loaded from a WGSL file, with the same
+    /// bindings as the built-in effects in `src/effects`.
+    #[allow(dead_code)]
+    Custom(PathBuf),
+}
+
+impl PostProcessEffect {
+    fn source(&self) -> io::Result<Cow<'static, str>> {
+        match self {
+            PostProcessEffect::Passthrough => Ok(include_str!("effects/passthrough.wgsl").into()),
+            PostProcessEffect::Grayscale => Ok(include_str!("effects/grayscale.wgsl").into()),
+            PostProcessEffect::Invert => Ok(include_str!("effects/invert.wgsl").into()),
+            PostProcessEffect::Custom(path) => fs::read_to_string(path).map(Cow::Owned),
+        }
+    }
+
+    /// The built-in effect after this one, wrapping around.
+    pub fn next(&self) -> Self {
+        match self {
+            PostProcessEffect::Passthrough => PostProcessEffect::Grayscale,
+            PostProcessEffect::Grayscale => PostProcessEffect::Invert,
+            PostProcessEffect::Invert | PostProcessEffect::Custom(_) => {
+                PostProcessEffect::Passthrough
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum PostProcessError {
+    Io(io::Error),
+    Shader(Vec<wgpu::Error>),
+}
+
+impl fmt::Display for PostProcessError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PostProcessError::Io(err) => write!(f, "failed to read effect shader: {}", err),
+            PostProcessError::Shader(errors) => {
+                write!(f, "failed to build effect pipeline:")?;
+                for err in errors {
+                    write!(f, "\n{}", err)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl std::error::Error for PostProcessError {}
+
+/// Renders a fullscreen effect over the frame.
+///
+/// The scene is drawn into the pass's HDR target instead of the
+/// surface, and the pass then draws the target into the next pass's
+/// target through the effect's fragment shader.
+pub struct PostProcessPass {
+    target: HdrRenderTarget,
+    sampler: wgpu::Sampler,
+    bind_group_layout: wgpu::BindGroupLayout,
+    bind_group: wgpu::BindGroup,
+    pipeline_layout: wgpu::PipelineLayout,
+    vertex_shader: wgpu::ShaderModule,
+    triangle: FullscreenTriangle,
+    effect: PostProcessEffect,
+}
+
+impl PostProcessPass {
+    pub fn new(
+        device: &wgpu::Device,
+        config: &wgpu::SurfaceConfiguration,
+        effect: PostProcessEffect,
+    ) -> Result<Self, PostProcessError> {
+        let target = HdrRenderTarget::new(device, config.width, config.height);
+
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Post Process Sampler"),
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            ..Default::default()
+        });
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Post Process Bind Group Layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler {
+                        filtering: true,
+                        comparison: false,
+                    },
+                    count: None,
+                },
+            ],
+        });
+        let bind_group = create_bind_group(device, &bind_group_layout, &target, &sampler);
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Post Process Pipeline Layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let vertex_shader = FullscreenTriangle::vertex_shader(device);
+        let triangle = create_pipeline(device, &pipeline_layout, &vertex_shader, &effect)?;
+
+        Ok(PostProcessPass {
+            target,
+            sampler,
+            bind_group_layout,
+            bind_group,
+            pipeline_layout,
+            vertex_shader,
+            triangle,
+            effect,
+        })
+    }
+
+    /// The view the scene should be rendered into.
+    pub fn target_view(&self) -> &wgpu::TextureView {
+        self.target.view()
+    }
+
+    /// The target the scene is rendered into, for passes that need
+    /// more of it than its view.
+    pub fn target(&self) -> &HdrRenderTarget {
+        &self.target
+    }
+
+    pub fn effect(&self) -> &PostProcessEffect {
+        &self.effect
+    }
+
+    /// Swaps in a different effect. The current one is kept
+    /// if the new one fails to build.
+    pub fn set_effect(
+        &mut self,
+        device: &wgpu::Device,
+        effect: PostProcessEffect,
+    ) -> Result<(), PostProcessError> {
+        self.triangle =
+            create_pipeline(device, &self.pipeline_layout, &self.vertex_shader, &effect)?;
+        self.effect = effect;
+        Ok(())
+    }
+
+    /// Recreates the HDR target to match the new surface size.
+    pub fn resize(&mut self, device: &wgpu::Device, width: u32, height: u32) {
+        self.target.resize(device, width, height);
+        self.bind_group =
+            create_bind_group(device, &self.bind_group_layout, &self.target, &self.sampler);
+    }
+
+    /// Draws the HDR target into `output` through the effect.
+    pub fn run(&self, encoder: &mut wgpu::CommandEncoder, output: &wgpu::TextureView) {
+        let mut render_pass = RenderPassBuilder::begin_render_pass(
+            encoder,
+            &wgpu::RenderPassDescriptor {
+                label: Some("Post Process Pass"),
+                color_attachments: &[wgpu::RenderPassColorAttachment {
+                    view: output,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        // Every pixel is drawn over, so there's no need to clear.
+                        load: wgpu::LoadOp::Load,
+                        store: true,
+                    },
+                }],
+                depth_stencil_attachment: None,
+            },
+        );
+
+        render_pass.set_bind_group(0, &self.bind_group, &[]);
+        self.triangle.draw(&mut render_pass);
+    }
+}
+
+fn create_bind_group(
+    device: &wgpu::Device,
+    layout: &wgpu::BindGroupLayout,
+    target: &HdrRenderTarget,
+    sampler: &wgpu::Sampler,
+) -> wgpu::BindGroup {
+    device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some("Post Process Bind Group"),
+        layout,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(target.view()),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: wgpu::BindingResource::Sampler(sampler),
+            },
+        ],
+    })
+}
+
+fn create_pipeline(
+    device: &wgpu::Device,
+    layout: &wgpu::PipelineLayout,
+    vertex_shader: &wgpu::ShaderModule,
+    effect: &PostProcessEffect,
+) -> Result<FullscreenTriangle, PostProcessError> {
+    let source = effect.source().map_err(PostProcessError::Io)?;
+
+    shader_watcher::try_build_pipeline(device, "Post Process Shader", &source, |fragment_shader| {
+        RenderPipelineBuilder::new()
+            .label("Post Process Pipeline")
+            .vertex_shader(vertex_shader, "main")
+            .fragment_shader(
+                fragment_shader,
+                "main",
+                // The effect draws into the tone mapping pass's HDR target.
+                &[wgpu::ColorTargetState {
+                    format: HDR_FORMAT,
+                    blend: Some(wgpu::BlendState::REPLACE),
+                    write_mask: wgpu::ColorWrites::ALL,
+                }],
+            )
+            .cull_mode(None)
+            .build(device, layout)
+            .expect("failed to build post process pipeline")
+    })
+    .map(FullscreenTriangle::new)
+    .map_err(PostProcessError::Shader)
+}
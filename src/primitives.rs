@@ -0,0 +1,211 @@
+//! Procedurally generated meshes.
+//!
+//! Each function returns the vertex and index data for a shape,
+//! ready to be passed to [`Mesh::upload`](crate::mesh::Mesh::upload).
+//! Triangles are wound counter-clockwise when seen from the outside.
+
+use std::f32::consts::PI;
+
+use crate::vertex::Vertex;
+
+const WHITE: [f32; 3] = [1.0, 1.0, 1.0];
+
+/// Axis aligned cube centered on the origin.
+///
+/// Every face has its own four vertices, so the normals and
+/// texture coordinates aren't shared between faces.
+pub fn cube(half_extent: f32) -> (Vec<Vertex>, Vec<u16>) {
+    // Each face is described by its normal, and an "up" direction
+    // on the face. The face's "right" direction is derived from them.
+    #[rustfmt::skip]
+    const FACES: [([f32; 3], [f32; 3]); 6] = [
+        ([ 1.0,  0.0,  0.0], [0.0, 1.0,  0.0]), // +X
+        ([-1.0,  0.0,  0.0], [0.0, 1.0,  0.0]), // -X
+        ([ 0.0,  1.0,  0.0], [0.0, 0.0, -1.0]), // +Y
+        ([ 0.0, -1.0,  0.0], [0.0, 0.0,  1.0]), // -Y
+        ([ 0.0,  0.0,  1.0], [0.0, 1.0,  0.0]), // +Z
+        ([ 0.0,  0.0, -1.0], [0.0, 1.0,  0.0]), // -Z
+    ];
+
+    let mut vertices = Vec::with_capacity(24);
+    let mut indices = Vec::with_capacity(36);
+
+    for (normal, up) in FACES.iter() {
+        let right = cross(*up, *normal);
+        let base = vertices.len() as u16;
+
+        // Corners go counter-clockwise around the face, starting
+        // at the bottom left.
+        for (sx, sy, uv) in [
+            (-1.0, -1.0, [0.0, 1.0]),
+            (1.0, -1.0, [1.0, 1.0]),
+            (1.0, 1.0, [1.0, 0.0]),
+            (-1.0, 1.0, [0.0, 0.0]),
+        ]
+        .iter()
+        {
+            let mut position = [0.0; 3];
+            for axis in 0..3 {
+                position[axis] = (normal[axis] + right[axis] * sx + up[axis] * sy) * half_extent;
+            }
+
+            vertices.push(Vertex {
+                position,
+                color: WHITE,
+                normal: *normal,
+                tex_coords: *uv,
+            });
+        }
+
+        indices.extend_from_slice(&[base, base + 1, base + 2, base, base + 2, base + 3]);
+    }
+
+    (vertices, indices)
+}
+
+/// Flat rectangle on the XY plane centered on the origin, facing +Z.
+pub fn quad(width: f32, height: f32) -> (Vec<Vertex>, Vec<u16>) {
+    let (hw, hh) = (width * 0.5, height * 0.5);
+    let normal = [0.0, 0.0, 1.0];
+
+    #[rustfmt::skip]
+    let vertices = vec![
+        Vertex { position: [-hw, -hh, 0.0], color: WHITE, normal, tex_coords: [0.0, 1.0] },
+        Vertex { position: [ hw, -hh, 0.0], color: WHITE, normal, tex_coords: [1.0, 1.0] },
+        Vertex { position: [ hw,  hh, 0.0], color: WHITE, normal, tex_coords: [1.0, 0.0] },
+        Vertex { position: [-hw,  hh, 0.0], color: WHITE, normal, tex_coords: [0.0, 0.0] },
+    ];
+    let indices = vec![0, 1, 2, 0, 2, 3];
+
+    (vertices, indices)
+}
+
+/// UV sphere centered on the origin.
+///
+/// `stacks` is the number of horizontal bands from pole to pole,
+/// and `slices` the number of segments around the vertical axis.
+pub fn sphere(radius: f32, stacks: u16, slices: u16) -> (Vec<Vertex>, Vec<u16>) {
+    let stacks = stacks.max(2);
+    let slices = slices.max(3);
+
+    // The seam is duplicated so the texture coordinates can wrap.
+    let vertex_count = (stacks as usize + 1) * (slices as usize + 1);
+    assert!(
+        vertex_count <= u16::MAX as usize,
+        "sphere with {} vertices doesn't fit 16-bit indices",
+        vertex_count
+    );
+
+    let mut vertices = Vec::with_capacity(vertex_count);
+    for stack in 0..=stacks {
+        let v = stack as f32 / stacks as f32;
+        // Angle from the north pole.
+        let phi = v * PI;
+
+        for slice in 0..=slices {
+            let u = slice as f32 / slices as f32;
+            // Angle around the vertical axis.
+            let theta = u * PI * 2.0;
+
+            let normal = [phi.sin() * theta.cos(), phi.cos(), phi.sin() * theta.sin()];
+            vertices.push(Vertex {
+                position: [normal[0] * radius, normal[1] * radius, normal[2] * radius],
+                color: WHITE,
+                normal,
+                tex_coords: [u, v],
+            });
+        }
+    }
+
+    let mut indices = Vec::new();
+    let row = slices + 1;
+    for stack in 0..stacks {
+        for slice in 0..slices {
+            let a = stack * row + slice;
+            let b = a + row;
+
+            // The first and last rows meet at the poles, where one of the
+            // two triangles collapses to a line and is left out.
+            if stack != 0 {
+                indices.extend_from_slice(&[a, a + 1, b]);
+            }
+            if stack != stacks - 1 {
+                indices.extend_from_slice(&[a + 1, b + 1, b]);
+            }
+        }
+    }
+
+    (vertices, indices)
+}
+
+fn cross(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+    [
+        a[1] * b[2] - a[2] * b[1],
+        a[2] * b[0] - a[0] * b[2],
+        a[0] * b[1] - a[1] * b[0],
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Checks every triangle has some area, and is wound
+    /// counter-clockwise seen from the side its normals face.
+    fn assert_valid_triangles(vertices: &[Vertex], indices: &[u16]) {
+        assert_eq!(indices.len() % 3, 0);
+        for triangle in indices.chunks(3) {
+            let [a, b, c] = [0, 1, 2].map(|i| vertices[triangle[i] as usize]);
+            let ab = sub(b.position, a.position);
+            let ac = sub(c.position, a.position);
+            let n = cross(ab, ac);
+            let area = 0.5 * dot(n, n).sqrt();
+            assert!(area > 1e-6, "triangle {:?} is degenerate", triangle);
+
+            let normal = [0, 1, 2].map(|axis| a.normal[axis] + b.normal[axis] + c.normal[axis]);
+            assert!(
+                dot(n, normal) > 0.0,
+                "triangle {:?} faces inwards",
+                triangle
+            );
+        }
+    }
+
+    fn sub(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+        [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+    }
+
+    fn dot(a: [f32; 3], b: [f32; 3]) -> f32 {
+        a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+    }
+
+    #[test]
+    fn cube_counts() {
+        let (vertices, indices) = cube(0.5);
+        assert_eq!(vertices.len(), 24);
+        assert_eq!(indices.len(), 36);
+        assert_valid_triangles(&vertices, &indices);
+    }
+
+    #[test]
+    fn quad_counts() {
+        let (vertices, indices) = quad(2.0, 1.0);
+        assert_eq!(vertices.len(), 4);
+        assert_eq!(indices.len(), 6);
+        assert_valid_triangles(&vertices, &indices);
+    }
+
+    #[test]
+    fn sphere_counts() {
+        let (stacks, slices) = (8, 12);
+        let (vertices, indices) = sphere(1.0, stacks, slices);
+        assert_eq!(
+            vertices.len(),
+            (stacks as usize + 1) * (slices as usize + 1)
+        );
+        // Two triangles a quad, less the one of each quad touching a pole.
+        let quads = stacks as usize * slices as usize;
+        assert_eq!(indices.len(), (quads * 2 - 2 * slices as usize) * 3);
+        assert_valid_triangles(&vertices, &indices);
+    }
+}
@@ -0,0 +1,160 @@
+/// Size of the noise's coarsest cells, in pixels.
+const NOISE_SCALE: f32 = 32.0;
+/// Layers of finer noise added on top of the coarsest.
+const NOISE_OCTAVES: u32 = 4;
+
+/// Textures generated on the CPU, for testing with something better
+/// than a flat color.
+///
+/// Each returns `width * height` RGBA pixels, row by row from the
+/// top, for [`Texture::from_rgba`](crate::texture::Texture::from_rgba).
+pub struct ProcTexture;
+
+impl ProcTexture {
+    /// Squares of `cell_size` pixels, alternating between the
+    /// colors, with `color_a` in the top left.
+    pub fn checkerboard(
+        width: u32,
+        height: u32,
+        cell_size: u32,
+        color_a: [u8; 4],
+        color_b: [u8; 4],
+    ) -> Vec<u8> {
+        let cell_size = cell_size.max(1);
+        pixels(width, height, |x, y| {
+            if (x / cell_size + y / cell_size).is_multiple_of(2) {
+                color_a
+            } else {
+                color_b
+            }
+        })
+    }
+
+    /// Grey value noise, the same for the same seed. Octaves of
+    /// smaller and fainter noise are added for detail.
+    pub fn noise(width: u32, height: u32, seed: u32) -> Vec<u8> {
+        pixels(width, height, |x, y| {
+            let mut value = 0.0;
+            let mut amplitude = 0.5;
+            let mut scale = NOISE_SCALE;
+            for octave in 0..NOISE_OCTAVES {
+                let seed = seed.wrapping_add(octave.wrapping_mul(0x9e37_79b9));
+                value += amplitude * value_noise(x as f32 / scale, y as f32 / scale, seed);
+                amplitude *= 0.5;
+                scale *= 0.5;
+            }
+            // The amplitudes add up to just under 1.
+            let grey = (value * 255.0).round().clamp(0.0, 255.0) as u8;
+            [grey, grey, grey, 255]
+        })
+    }
+
+    /// The texture coordinates of each pixel's center, with U in
+    /// red and V in green, for checking how a mesh is unwrapped.
+    pub fn uv_gradient(width: u32, height: u32) -> Vec<u8> {
+        pixels(width, height, |x, y| {
+            let u = (x as f32 + 0.5) / width as f32;
+            let v = (y as f32 + 0.5) / height as f32;
+            [(u * 255.0).round() as u8, (v * 255.0).round() as u8, 0, 255]
+        })
+    }
+}
+
+fn pixels(width: u32, height: u32, mut pixel: impl FnMut(u32, u32) -> [u8; 4]) -> Vec<u8> {
+    let mut rgba = Vec::with_capacity((width * height * 4) as usize);
+    for y in 0..height {
+        for x in 0..width {
+            rgba.extend_from_slice(&pixel(x, y));
+        }
+    }
+    rgba
+}
+
+/// Noise from 0 to 1, smoothly blending between random values at
+/// the corners of each unit cell.
+fn value_noise(x: f32, y: f32, seed: u32) -> f32 {
+    let (x0, y0) = (x.floor(), y.floor());
+    let (tx, ty) = (smoothstep(x - x0), smoothstep(y - y0));
+    let (cx, cy) = (x0 as i32, y0 as i32);
+
+    let corner = |dx: i32, dy: i32| lattice_value(cx + dx, cy + dy, seed);
+    let top = lerp(corner(0, 0), corner(1, 0), tx);
+    let bottom = lerp(corner(0, 1), corner(1, 1), tx);
+    lerp(top, bottom, ty)
+}
+
+/// A random value from 0 to 1 for a corner of the lattice, by
+/// hashing its coordinates.
+fn lattice_value(x: i32, y: i32, seed: u32) -> f32 {
+    let mut h = seed ^ (x as u32).wrapping_mul(0x27d4_eb2d) ^ (y as u32).wrapping_mul(0x1656_67b1);
+    // Mixes the bits, so neighbouring corners look unrelated.
+    h ^= h >> 15;
+    h = h.wrapping_mul(0x85eb_ca6b);
+    h ^= h >> 13;
+    h = h.wrapping_mul(0xc2b2_ae35);
+    h ^= h >> 16;
+    h as f32 / u32::MAX as f32
+}
+
+fn smoothstep(t: f32) -> f32 {
+    t * t * (3.0 - 2.0 * t)
+}
+
+fn lerp(a: f32, b: f32, t: f32) -> f32 {
+    a + (b - a) * t
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RED: [u8; 4] = [255, 0, 0, 255];
+    const BLUE: [u8; 4] = [0, 0, 255, 255];
+
+    fn pixel(rgba: &[u8], width: u32, x: u32, y: u32) -> &[u8] {
+        let start = ((y * width + x) * 4) as usize;
+        &rgba[start..start + 4]
+    }
+
+    #[test]
+    fn textures_are_their_size() {
+        assert_eq!(
+            ProcTexture::checkerboard(5, 3, 2, RED, BLUE).len(),
+            5 * 3 * 4
+        );
+        assert_eq!(ProcTexture::noise(5, 3, 7).len(), 5 * 3 * 4);
+        assert_eq!(ProcTexture::uv_gradient(5, 3).len(), 5 * 3 * 4);
+    }
+
+    #[test]
+    fn checkerboard_alternates_cells() {
+        let rgba = ProcTexture::checkerboard(4, 4, 2, RED, BLUE);
+        assert_eq!(pixel(&rgba, 4, 0, 0), RED);
+        assert_eq!(pixel(&rgba, 4, 1, 1), RED);
+        assert_eq!(pixel(&rgba, 4, 2, 0), BLUE);
+        assert_eq!(pixel(&rgba, 4, 0, 2), BLUE);
+        assert_eq!(pixel(&rgba, 4, 3, 3), RED);
+    }
+
+    #[test]
+    fn noise_is_opaque_grey_and_seeded() {
+        let rgba = ProcTexture::noise(64, 64, 3);
+        for pixel in rgba.chunks(4) {
+            assert_eq!(pixel[0], pixel[1]);
+            assert_eq!(pixel[0], pixel[2]);
+            assert_eq!(pixel[3], 255);
+        }
+        // Not flat, and the same again for the same seed.
+        assert!(rgba.chunks(4).any(|pixel| pixel[0] != rgba[0]));
+        assert_eq!(rgba, ProcTexture::noise(64, 64, 3));
+        assert_ne!(rgba, ProcTexture::noise(64, 64, 4));
+    }
+
+    #[test]
+    fn uv_gradient_is_pixel_centers() {
+        let rgba = ProcTexture::uv_gradient(4, 2);
+        // (0.5 / 4, 0.5 / 2) and (3.5 / 4, 1.5 / 2)
+        assert_eq!(pixel(&rgba, 4, 0, 0), [32, 64, 0, 255]);
+        assert_eq!(pixel(&rgba, 4, 3, 1), [223, 191, 0, 255]);
+    }
+}
@@ -0,0 +1,280 @@
+use cgmath::{InnerSpace, SquareMatrix};
+
+use crate::{
+    camera::Camera, depth::DepthBuffer, fullscreen_triangle::FullscreenTriangle, msaa::MsaaConfig,
+    pipeline::RenderPipelineBuilder, uniform::UniformBinding,
+};
+
+/// What the sky looks like.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct SkyConfig {
+    /// Towards the sun. Doesn't need to be normalized.
+    pub sun_direction: [f32; 3],
+    /// How hazy the air is, from 2 for a clear sky to about 10 for
+    /// a hazy one.
+    pub turbidity: f32,
+    /// Color of the ground below the horizon.
+    pub ground_albedo: [f32; 3],
+}
+
+impl Default for SkyConfig {
+    fn default() -> Self {
+        SkyConfig {
+            sun_direction: [0.0, 1.0, 0.0],
+            turbidity: 2.5,
+            ground_albedo: [0.3, 0.3, 0.3],
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct SkyUniform {
+    inv_view_proj: [[f32; 4]; 4],
+    sun_direction: [f32; 3],
+    _padding0: f32,
+    ground_albedo: [f32; 3],
+    _padding1: f32,
+    /// The coefficients A to E, each for Y, x and y.
+    perez: [[f32; 4]; 5],
+    zenith: [f32; 4],
+}
+
+/// The Perez coefficients A to E for the luminance Y and the
+/// chromaticities x and y, from Preetham et al.'s fit to turbidity.
+fn perez_coefficients(turbidity: f32) -> [[f32; 4]; 5] {
+    let t = turbidity;
+    [
+        [
+            0.1787 * t - 1.4630,
+            -0.0193 * t - 0.2592,
+            -0.0167 * t - 0.2608,
+            0.0,
+        ],
+        [
+            -0.3554 * t + 0.4275,
+            -0.0665 * t + 0.0008,
+            -0.0950 * t + 0.0092,
+            0.0,
+        ],
+        [
+            -0.0227 * t + 5.3251,
+            -0.0004 * t + 0.2125,
+            -0.0079 * t + 0.2102,
+            0.0,
+        ],
+        [
+            0.1206 * t - 2.5771,
+            -0.0641 * t - 0.8989,
+            -0.0441 * t - 1.6537,
+            0.0,
+        ],
+        [
+            -0.0670 * t + 0.3703,
+            -0.0033 * t + 0.0452,
+            -0.0109 * t + 0.0529,
+            0.0,
+        ],
+    ]
+}
+
+/// Y, x and y straight up, with the sun `theta_sun` radians from it.
+fn zenith_values(turbidity: f32, theta_sun: f32) -> [f32; 4] {
+    let t = turbidity;
+    let (s, s2, s3) = (theta_sun, theta_sun * theta_sun, theta_sun.powi(3));
+
+    let chi = (4.0 / 9.0 - t / 120.0) * (std::f32::consts::PI - 2.0 * theta_sun);
+    let luminance = (4.0453 * t - 4.9710) * chi.tan() - 0.2155 * t + 2.4192;
+    let x = t * t * (0.00166 * s3 - 0.00375 * s2 + 0.00209 * s)
+        + t * (-0.02903 * s3 + 0.06377 * s2 - 0.03202 * s + 0.00394)
+        + (0.11693 * s3 - 0.21196 * s2 + 0.06052 * s + 0.25886);
+    let y = t * t * (0.00275 * s3 - 0.00610 * s2 + 0.00317 * s)
+        + t * (-0.04214 * s3 + 0.08970 * s2 - 0.04153 * s + 0.00516)
+        + (0.15346 * s3 - 0.26756 * s2 + 0.06670 * s + 0.26688);
+    [luminance.max(0.0), x, y, 0.0]
+}
+
+/// Brings the luminance, in thousands of candela per square metre,
+/// down to what the scene is lit with. Must match `LUMINANCE_SCALE`
+/// in `procedural_sky.wgsl`.
+const LUMINANCE_SCALE: f32 = 0.05;
+
+/// Linear sRGB of Y, x and y, like `yxy_to_rgb` in the shader.
+fn yxy_to_rgb([luminance, x, y, _]: [f32; 4]) -> [f32; 3] {
+    let xyz = [x * luminance / y, luminance, (1.0 - x - y) * luminance / y];
+    [
+        3.2406 * xyz[0] - 1.5372 * xyz[1] - 0.4986 * xyz[2],
+        -0.9689 * xyz[0] + 1.8758 * xyz[1] + 0.0415 * xyz[2],
+        0.0557 * xyz[0] - 0.2040 * xyz[1] + 1.0570 * xyz[2],
+    ]
+    .map(|c| c.max(0.0))
+}
+
+/// Draws a sky lit by the sun, with the Preetham model of how
+/// sunlight scatters through the atmosphere, instead of a cubemap.
+///
+/// Drawn over the whole screen, so it should be drawn first in the
+/// pass. It doesn't touch the depth buffer.
+pub struct ProceduralSky {
+    config: SkyConfig,
+    /// From the last camera update, kept for when only the sun moves.
+    inv_view_proj: cgmath::Matrix4<f32>,
+    uniform: UniformBinding<SkyUniform>,
+    triangle: FullscreenTriangle,
+    pipeline_layout: wgpu::PipelineLayout,
+    vertex_shader: wgpu::ShaderModule,
+    shader: wgpu::ShaderModule,
+    format: wgpu::TextureFormat,
+}
+
+impl ProceduralSky {
+    pub fn new(
+        device: &wgpu::Device,
+        config: SkyConfig,
+        format: wgpu::TextureFormat,
+        msaa: MsaaConfig,
+    ) -> Self {
+        let uniform = UniformBinding::new(
+            device,
+            "Procedural Sky Uniform",
+            wgpu::ShaderStages::FRAGMENT,
+            &sky_uniform(&config, cgmath::Matrix4::identity()),
+        );
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Procedural Sky Pipeline Layout"),
+            bind_group_layouts: &[&uniform.bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let vertex_shader = FullscreenTriangle::vertex_shader(device);
+        let shader = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+            label: Some("Procedural Sky Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("procedural_sky.wgsl").into()),
+        });
+        let triangle = create_pipeline(
+            device,
+            &pipeline_layout,
+            &vertex_shader,
+            &shader,
+            format,
+            msaa,
+        );
+
+        ProceduralSky {
+            config,
+            inv_view_proj: cgmath::Matrix4::identity(),
+            uniform,
+            triangle,
+            pipeline_layout,
+            vertex_shader,
+            shader,
+            format,
+        }
+    }
+
+    #[allow(dead_code)]
+    pub fn config(&self) -> &SkyConfig {
+        &self.config
+    }
+
+    /// Moves the sun to shine from `direction`.
+    pub fn update_sun(&mut self, queue: &wgpu::Queue, direction: [f32; 3]) {
+        self.config.sun_direction = direction;
+        self.upload(queue);
+    }
+
+    /// Light coming from straight up, as the sky is drawn there with
+    /// the sun where it is now.
+    pub fn zenith_radiance(&self) -> [f32; 3] {
+        let uniform = sky_uniform(&self.config, self.inv_view_proj);
+        let [luminance, x, y, w] = uniform.zenith;
+        yxy_to_rgb([luminance * LUMINANCE_SCALE, x, y, w])
+    }
+
+    /// Follows the camera's rotation and projection.
+    pub fn update(&mut self, queue: &wgpu::Queue, camera: &Camera) {
+        // Only the rotation of the view is kept, so the
+        // sky is always centered on the camera.
+        let mut view = camera.build_view_matrix();
+        view.w = cgmath::Vector4::new(0.0, 0.0, 0.0, 1.0);
+        self.inv_view_proj = (camera.build_projection_matrix() * view)
+            .invert()
+            .unwrap_or_else(cgmath::Matrix4::identity);
+        self.upload(queue);
+    }
+
+    fn upload(&self, queue: &wgpu::Queue) {
+        self.uniform
+            .update(queue, &sky_uniform(&self.config, self.inv_view_proj));
+    }
+
+    /// Recreates the pipeline to match the sample count of the pass.
+    pub fn set_msaa(&mut self, device: &wgpu::Device, msaa: MsaaConfig) {
+        self.triangle = create_pipeline(
+            device,
+            &self.pipeline_layout,
+            &self.vertex_shader,
+            &self.shader,
+            self.format,
+            msaa,
+        );
+    }
+
+    pub fn draw<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>) {
+        render_pass.set_bind_group(0, &self.uniform.bind_group, &[]);
+        self.triangle.draw(render_pass);
+    }
+}
+
+fn sky_uniform(config: &SkyConfig, inv_view_proj: cgmath::Matrix4<f32>) -> SkyUniform {
+    let sun = cgmath::Vector3::from(config.sun_direction);
+    let sun = if sun.magnitude2() > 0.0 {
+        sun.normalize()
+    } else {
+        cgmath::Vector3::unit_y()
+    };
+    // The model only holds up while the sun is above the horizon.
+    let theta_sun = sun.y.max(0.001).acos();
+
+    SkyUniform {
+        inv_view_proj: inv_view_proj.into(),
+        sun_direction: sun.into(),
+        _padding0: 0.0,
+        ground_albedo: config.ground_albedo,
+        _padding1: 0.0,
+        perez: perez_coefficients(config.turbidity),
+        zenith: zenith_values(config.turbidity, theta_sun),
+    }
+}
+
+fn create_pipeline(
+    device: &wgpu::Device,
+    layout: &wgpu::PipelineLayout,
+    vertex_shader: &wgpu::ShaderModule,
+    shader: &wgpu::ShaderModule,
+    format: wgpu::TextureFormat,
+    msaa: MsaaConfig,
+) -> FullscreenTriangle {
+    let pipeline = RenderPipelineBuilder::new()
+        .label("Procedural Sky Pipeline")
+        .vertex_shader(vertex_shader, "main")
+        .fragment_shader(
+            shader,
+            "main",
+            &[wgpu::ColorTargetState {
+                format,
+                blend: Some(wgpu::BlendState::REPLACE),
+                write_mask: wgpu::ColorWrites::ALL,
+            }],
+        )
+        .cull_mode(None)
+        // Everything drawn after the sky goes in front of it.
+        .depth_stencil(wgpu::DepthStencilState {
+            depth_write_enabled: false,
+            depth_compare: wgpu::CompareFunction::Always,
+            ..DepthBuffer::depth_stencil_state()
+        })
+        .multisample(msaa.count)
+        .build(device, layout)
+        .expect("failed to build procedural sky pipeline");
+    FullscreenTriangle::new(pipeline)
+}
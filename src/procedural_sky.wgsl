@@ -0,0 +1,87 @@
+// Draws the sky with the Preetham model of atmospheric scattering.
+
+[[block]]
+struct SkyUniform {
+    // Inverse of the view projection with the camera's translation
+    // removed, for turning screen positions into view directions.
+    inv_view_proj: mat4x4<f32>;
+    // Towards the sun, normalized.
+    sun_direction: vec3<f32>;
+    ground_albedo: vec3<f32>;
+    // The Perez coefficients A to E, each for Y, x and y in its xyz.
+    perez_a: vec4<f32>;
+    perez_b: vec4<f32>;
+    perez_c: vec4<f32>;
+    perez_d: vec4<f32>;
+    perez_e: vec4<f32>;
+    // Y, x and y at the zenith.
+    zenith: vec4<f32>;
+};
+
+[[group(0), binding(0)]]
+var<uniform> sky: SkyUniform;
+
+// Brings the luminance, in thousands of candela per square
+// metre, down to what the scene is lit with.
+let LUMINANCE_SCALE: f32 = 0.05;
+// Cosine of the angle the sun's disk covers around its center.
+let SUN_DISK_COS: f32 = 0.99996;
+let SUN_DISK_BRIGHTNESS: f32 = 20.0;
+
+// The Perez distribution of sky brightness, relative to the zenith,
+// for each of Y, x and y.
+fn perez(cos_theta: f32, gamma: f32, cos_gamma: f32) -> vec3<f32> {
+    let a = sky.perez_a.xyz;
+    let b = sky.perez_b.xyz;
+    let c = sky.perez_c.xyz;
+    let d = sky.perez_d.xyz;
+    let e = sky.perez_e.xyz;
+    return (1.0 + a * exp(b / cos_theta)) * (1.0 + c * exp(d * gamma) + e * cos_gamma * cos_gamma);
+}
+
+fn yxy_to_rgb(yxy: vec3<f32>) -> vec3<f32> {
+    let big_y = yxy.x;
+    let x = yxy.y;
+    let y = yxy.z;
+    let xyz = vec3<f32>(x * big_y / y, big_y, (1.0 - x - y) * big_y / y);
+    // The XYZ to linear sRGB matrix, column by column.
+    let to_rgb = mat3x3<f32>(
+        vec3<f32>(3.2406, -0.9689, 0.0557),
+        vec3<f32>(-1.5372, 1.8758, -0.2040),
+        vec3<f32>(-0.4986, 0.0415, 1.0570),
+    );
+    return max(to_rgb * xyz, vec3<f32>(0.0));
+}
+
+// Light coming from `direction` in the sky, which must be above the horizon.
+fn sky_radiance(direction: vec3<f32>) -> vec3<f32> {
+    let cos_theta = max(direction.y, 0.001);
+    let cos_gamma = clamp(dot(direction, sky.sun_direction), -1.0, 1.0);
+    let gamma = acos(cos_gamma);
+    let cos_theta_sun = max(sky.sun_direction.y, 0.001);
+    let theta_sun = acos(cos_theta_sun);
+
+    let yxy = sky.zenith.xyz * perez(cos_theta, gamma, cos_gamma)
+        / perez(1.0, theta_sun, cos_theta_sun);
+    return yxy_to_rgb(yxy * vec3<f32>(LUMINANCE_SCALE, 1.0, 1.0));
+}
+
+[[stage(fragment)]]
+fn main([[location(0)]] tex_coords: vec2<f32>) -> [[location(0)]] vec4<f32> {
+    let ndc = vec2<f32>(tex_coords.x * 2.0 - 1.0, 1.0 - tex_coords.y * 2.0);
+    let world = sky.inv_view_proj * vec4<f32>(ndc, 1.0, 1.0);
+    let direction = normalize(world.xyz / world.w);
+
+    if (direction.y < 0.0) {
+        // The ground reflects the sky along the horizon. Kept just
+        // above it, so looking straight down still has a direction.
+        let horizon = normalize(vec3<f32>(direction.x, 0.001, direction.z));
+        return vec4<f32>(sky.ground_albedo * sky_radiance(horizon), 1.0);
+    }
+
+    var color = sky_radiance(direction);
+    if (dot(direction, sky.sun_direction) > SUN_DISK_COS) {
+        color = color * SUN_DISK_BRIGHTNESS;
+    }
+    return vec4<f32>(color, 1.0);
+}
@@ -0,0 +1,106 @@
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+/// Separates the names of nested scopes in their paths.
+const PATH_SEPARATOR: char = '/';
+
+/// The time spent in one scope since the profiler was last reset.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProfileEntry {
+    /// The names of the scopes it was opened in, down to its own,
+    /// such as `frame/culling`.
+    pub path: String,
+    /// How many scopes it was opened in.
+    pub depth: usize,
+    pub total: Duration,
+}
+
+impl ProfileEntry {
+    /// The scope's own name, at the end of its path.
+    pub fn name(&self) -> &str {
+        self.path
+            .rsplit(PATH_SEPARATOR)
+            .next()
+            .unwrap_or(&self.path)
+    }
+}
+
+/// Times named scopes of the CPU's work, which can be opened inside
+/// each other to break a scope down.
+///
+/// Scopes are kept apart by the scopes they were opened in, so
+/// `sort` inside `geometry` isn't added to a `sort` at the top. A
+/// scope opened more than once between resets adds up its time.
+pub struct Profiler {
+    /// Scopes that have begun and not ended yet, innermost last.
+    open: Vec<(String, Instant)>,
+    /// Keyed by path.
+    totals: HashMap<String, Duration>,
+}
+
+impl Profiler {
+    pub fn new() -> Self {
+        Profiler {
+            open: Vec::new(),
+            totals: HashMap::new(),
+        }
+    }
+
+    /// Starts timing `name`, inside whichever scope is open.
+    pub fn begin(&mut self, name: &str) {
+        let path = match self.open.last() {
+            Some((parent, _)) => format!("{}{}{}", parent, PATH_SEPARATOR, name),
+            None => name.to_string(),
+        };
+        self.open.push((path, Instant::now()));
+    }
+
+    /// Stops timing `name`, which has to be the innermost open scope.
+    /// Anything else is warned about and left open.
+    pub fn end(&mut self, name: &str) {
+        let is_innermost = self
+            .open
+            .last()
+            .is_some_and(|(path, _)| path.rsplit(PATH_SEPARATOR).next() == Some(name));
+        if !is_innermost {
+            log::warn!(
+                "profiler scope {:?} ended while it wasn't the innermost",
+                name
+            );
+            return;
+        }
+
+        if let Some((path, start)) = self.open.pop() {
+            *self.totals.entry(path).or_default() += start.elapsed();
+        }
+    }
+
+    /// Every scope that's ended since the last reset, the longest first.
+    pub fn report(&self) -> Vec<ProfileEntry> {
+        let mut entries = self
+            .totals
+            .iter()
+            .map(|(path, total)| ProfileEntry {
+                path: path.clone(),
+                depth: path.matches(PATH_SEPARATOR).count(),
+                total: *total,
+            })
+            .collect::<Vec<_>>();
+        entries.sort_by(|a, b| b.total.cmp(&a.total).then_with(|| a.path.cmp(&b.path)));
+        entries
+    }
+
+    /// Forgets the times so far, and any scopes left open.
+    pub fn reset(&mut self) {
+        self.open.clear();
+        self.totals.clear();
+    }
+}
+
+impl Default for Profiler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
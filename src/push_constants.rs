@@ -0,0 +1,65 @@
+use std::ops::Range;
+
+/// Most bytes of push constants a pipeline is given, which every
+/// adapter with push constants has room for.
+pub const MAX_PUSH_CONSTANT_SIZE: u32 = 128;
+
+/// Bytes of push constants seen by some of the shader stages.
+///
+/// Push constants are written straight into the commands, so small
+/// values that change with every draw, like a model matrix, don't
+/// need a buffer of their own. Offsets and sizes have to be
+/// multiples of 4.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PushConstantRange {
+    pub stages: wgpu::ShaderStages,
+    pub range: Range<u32>,
+}
+
+impl PushConstantRange {
+    pub fn new(stages: wgpu::ShaderStages, offset: u32, size: u32) -> Self {
+        debug_assert!(
+            offset.is_multiple_of(4) && size.is_multiple_of(4),
+            "push constant ranges must be aligned to 4 bytes"
+        );
+        PushConstantRange {
+            stages,
+            range: offset..offset + size,
+        }
+    }
+
+    /// Room for one `T` at `offset`.
+    pub fn of<T: bytemuck::Pod>(stages: wgpu::ShaderStages, offset: u32) -> Self {
+        Self::new(stages, offset, std::mem::size_of::<T>() as u32)
+    }
+
+    pub fn to_wgpu(&self) -> wgpu::PushConstantRange {
+        wgpu::PushConstantRange {
+            stages: self.stages,
+            range: self.range.clone(),
+        }
+    }
+}
+
+/// Writes push constants from values rather than bytes.
+pub trait SetPushConstantsTyped {
+    /// Writes `value` at `offset` of the push constants seen by
+    /// `stages`, which must match a range of the pipeline's layout.
+    fn set_push_constants_typed<T: bytemuck::Pod>(
+        &mut self,
+        stages: wgpu::ShaderStages,
+        offset: u32,
+        value: &T,
+    );
+}
+
+impl SetPushConstantsTyped for wgpu::RenderPass<'_> {
+    fn set_push_constants_typed<T: bytemuck::Pod>(
+        &mut self,
+        stages: wgpu::ShaderStages,
+        offset: u32,
+        value: &T,
+    ) {
+        self.set_push_constants(stages, offset, bytemuck::bytes_of(value));
+    }
+}
@@ -0,0 +1,84 @@
+use crate::{
+    bounds::{Aabb, Ray},
+    ecs::EntityId,
+    transform::Transform,
+};
+
+/// Picks entities by casting a ray into their bounding boxes on the
+/// CPU.
+///
+/// Unlike the [`PickingPass`](crate::picking::PickingPass), nothing
+/// is drawn or read back from the GPU, which makes it cheaper for a
+/// small scene. The boxes are only as tight as the bounds of the
+/// meshes, so clicks near the corners of a round mesh can still
+/// pick it.
+pub struct RaycastPicker;
+
+impl RaycastPicker {
+    /// The entity whose box `ray` hits first. The boxes are moved by
+    /// the entities' transforms before they're tested.
+    pub fn pick(ray: &Ray, entities: &[(EntityId, Aabb, Transform)]) -> Option<EntityId> {
+        entities
+            .iter()
+            .filter_map(|(entity, bounds, transform)| {
+                bounds
+                    .transformed(transform)
+                    .intersect_ray(ray)
+                    .map(|distance| (*entity, distance))
+            })
+            .min_by(|(_, a), (_, b)| a.total_cmp(b))
+            .map(|(entity, _)| entity)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const UNIT_BOX: Aabb = Aabb {
+        min: [-0.5; 3],
+        max: [0.5; 3],
+    };
+
+    fn ray_down_z() -> Ray {
+        Ray {
+            origin: [0.0, 0.0, -10.0],
+            direction: [0.0, 0.0, 1.0],
+        }
+    }
+
+    #[test]
+    fn picks_the_nearest_entity() {
+        let entities = [
+            (
+                EntityId(0),
+                UNIT_BOX,
+                Transform::from_translation([0.0, 0.0, 3.0]),
+            ),
+            (
+                EntityId(1),
+                UNIT_BOX,
+                Transform::from_translation([0.0, 0.0, -3.0]),
+            ),
+            (
+                EntityId(2),
+                UNIT_BOX,
+                Transform::from_translation([0.0, 0.0, 0.0]),
+            ),
+        ];
+        assert_eq!(
+            RaycastPicker::pick(&ray_down_z(), &entities),
+            Some(EntityId(1))
+        );
+    }
+
+    #[test]
+    fn misses_entities_moved_out_of_the_way() {
+        let entities = [(
+            EntityId(0),
+            UNIT_BOX,
+            Transform::from_translation([2.0, 0.0, 0.0]),
+        )];
+        assert_eq!(RaycastPicker::pick(&ray_down_z(), &entities), None);
+    }
+}
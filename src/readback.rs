@@ -0,0 +1,205 @@
+use std::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll, Waker},
+};
+
+/// Frames of results that can be waited on at once, enough that
+/// the GPU is rarely waited on.
+pub const READBACK_FRAMES: usize = 3;
+
+type Mapping = Pin<Box<dyn Future<Output = Result<(), wgpu::BufferAsyncError>> + Send>>;
+
+enum SlotState<T> {
+    Free,
+    /// Being mapped after a frame copied `size` bytes into it, with
+    /// what the frame needs to make sense of them.
+    Mapping {
+        mapping: Mapping,
+        size: wgpu::BufferAddress,
+        tag: T,
+    },
+}
+
+struct Slot<T> {
+    buffer: wgpu::Buffer,
+    state: SlotState<T>,
+}
+
+/// Buffers results are copied into on the GPU, to be read on the CPU
+/// a frame or more later without waiting for the GPU to catch up.
+///
+/// Each frame copies into the [`target`](Self::target), which is
+/// mapped once the frame is [submitted](Self::submitted). A
+/// [`read`](Self::read) later hands back the newest results that have
+/// finished mapping. When every buffer is still being mapped there's
+/// no target, and that frame's results are skipped.
+///
+/// Each result carries a `T` from the frame that copied it, for
+/// results that only make sense with something else from that frame.
+pub struct ReadbackRing<T = ()> {
+    slots: Vec<Slot<T>>,
+    /// The free slot the frame being recorded copies into.
+    target: Option<usize>,
+    /// The slot after the one most recently submitted, which is where
+    /// the oldest results are.
+    next: usize,
+}
+
+impl<T> ReadbackRing<T> {
+    /// Makes `frames` buffers of `size` bytes each, which is how many
+    /// frames can be waited on at once.
+    pub fn new(
+        device: &wgpu::Device,
+        label: &str,
+        size: wgpu::BufferAddress,
+        frames: usize,
+    ) -> Self {
+        let slots = (0..frames.max(1))
+            .map(|i| Slot {
+                buffer: device.create_buffer(&wgpu::BufferDescriptor {
+                    label: Some(&format!("{} {}", label, i)),
+                    size,
+                    usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+                    mapped_at_creation: false,
+                }),
+                state: SlotState::Free,
+            })
+            .collect();
+
+        ReadbackRing {
+            slots,
+            target: Some(0),
+            next: 0,
+        }
+    }
+
+    /// Where the frame being recorded should copy its results, or
+    /// `None` when every buffer is waiting to be read.
+    pub fn target(&self) -> Option<&wgpu::Buffer> {
+        self.target.map(|index| &self.slots[index].buffer)
+    }
+
+    /// Starts mapping the first `size` bytes of the target, once the
+    /// frame that copied into it is submitted. Does nothing when there
+    /// was no target.
+    pub fn submitted(&mut self, size: wgpu::BufferAddress, tag: T) {
+        if let Some(index) = self.target.take() {
+            let slot = &mut self.slots[index];
+            let mapping = slot.buffer.slice(..size).map_async(wgpu::MapMode::Read);
+            slot.state = SlotState::Mapping {
+                mapping: Box::pin(mapping),
+                size,
+                tag,
+            };
+            self.next = (index + 1) % self.slots.len();
+        }
+        self.find_target();
+    }
+
+    /// Reads the newest results that have finished mapping with
+    /// `read`, without waiting for the rest. Older finished results
+    /// are thrown away.
+    pub fn read<R>(
+        &mut self,
+        device: &wgpu::Device,
+        mut read: impl FnMut(&[u8], T) -> R,
+    ) -> Option<R> {
+        // Mappings only finish when the device is polled.
+        device.poll(wgpu::Maintain::Poll);
+
+        let mut context = Context::from_waker(Waker::noop());
+        let (count, oldest) = (self.slots.len(), self.next);
+        let mut latest = None;
+        // Oldest first, so the newest results are read last.
+        for index in (0..count).map(|i| (oldest + i) % count) {
+            let slot = &mut self.slots[index];
+            let result = match &mut slot.state {
+                SlotState::Mapping { mapping, .. } => match mapping.as_mut().poll(&mut context) {
+                    Poll::Ready(result) => result,
+                    Poll::Pending => continue,
+                },
+                SlotState::Free => continue,
+            };
+
+            let (size, tag) = match std::mem::replace(&mut slot.state, SlotState::Free) {
+                SlotState::Mapping { size, tag, .. } => (size, tag),
+                SlotState::Free => unreachable!(),
+            };
+            match result {
+                Ok(()) => {
+                    latest = {
+                        let data = slot.buffer.slice(..size).get_mapped_range();
+                        Some(read(&data, tag))
+                    };
+                    slot.buffer.unmap();
+                }
+                Err(err) => eprintln!("failed to map readback buffer: {}", err),
+            }
+        }
+
+        self.find_target();
+        latest
+    }
+
+    /// Takes the oldest free slot as the target, if there isn't one.
+    fn find_target(&mut self) {
+        if self.target.is_some() {
+            return;
+        }
+        let count = self.slots.len();
+        self.target = (0..count)
+            .map(|i| (self.next + i) % count)
+            .find(|index| matches!(self.slots[*index].state, SlotState::Free));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::context::test_device;
+    use wgpu::util::DeviceExt;
+
+    #[test]
+    fn reads_newest_results() {
+        let (device, queue) = match test_device() {
+            Some(device) => device,
+            None => return eprintln!("skipped, there's no adapter"),
+        };
+        let mut ring = ReadbackRing::<u32>::new(&device, "Test Readback", 4, 3);
+
+        for frame in 1..=2u32 {
+            let source = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: None,
+                contents: bytemuck::bytes_of(&(frame * 10)),
+                usage: wgpu::BufferUsages::COPY_SRC,
+            });
+            let mut encoder = device.create_command_encoder(&Default::default());
+            encoder.copy_buffer_to_buffer(&source, 0, ring.target().unwrap(), 0, 4);
+            queue.submit(Some(encoder.finish()));
+            ring.submitted(4, frame);
+        }
+
+        device.poll(wgpu::Maintain::Wait);
+        let latest = ring.read(&device, |data, frame| {
+            (*bytemuck::from_bytes::<u32>(data), frame)
+        });
+        assert_eq!(latest, Some((20, 2)));
+        // Everything was read, so there's nothing left.
+        assert_eq!(ring.read(&device, |_, frame| frame), None);
+        assert!(ring.target().is_some());
+    }
+
+    #[test]
+    fn skips_frames_when_full() {
+        let (device, _queue) = match test_device() {
+            Some(device) => device,
+            None => return eprintln!("skipped, there's no adapter"),
+        };
+        let mut ring = ReadbackRing::<()>::new(&device, "Test Readback", 4, 2);
+        ring.submitted(4, ());
+        ring.submitted(4, ());
+
+        assert!(ring.target().is_none());
+    }
+}
@@ -0,0 +1,70 @@
+use std::ops::Range;
+
+/// Records draw calls once, so they can be replayed by render
+/// passes every frame without recording them again.
+///
+/// A bundle can only be replayed in passes with the same color
+/// format, depth format and sample count it was recorded with.
+/// Bundles start with nothing bound, and don't leave anything
+/// bound in the pass after them either.
+pub struct RenderBundleRecorder<'a> {
+    encoder: wgpu::RenderBundleEncoder<'a>,
+}
+
+impl<'a> RenderBundleRecorder<'a> {
+    pub fn new(
+        device: &'a wgpu::Device,
+        format: wgpu::TextureFormat,
+        depth_format: Option<wgpu::TextureFormat>,
+        sample_count: u32,
+    ) -> Self {
+        let encoder = device.create_render_bundle_encoder(&wgpu::RenderBundleEncoderDescriptor {
+            label: Some("Render Bundle Encoder"),
+            color_formats: &[format],
+            depth_stencil: depth_format.map(|format| wgpu::RenderBundleDepthStencil {
+                format,
+                depth_read_only: false,
+                stencil_read_only: false,
+            }),
+            sample_count,
+        });
+
+        RenderBundleRecorder { encoder }
+    }
+
+    pub fn set_pipeline(&mut self, pipeline: &'a wgpu::RenderPipeline) {
+        self.encoder.set_pipeline(pipeline);
+    }
+
+    pub fn set_bind_group(
+        &mut self,
+        index: u32,
+        bind_group: &'a wgpu::BindGroup,
+        offsets: &[wgpu::DynamicOffset],
+    ) {
+        self.encoder.set_bind_group(index, bind_group, offsets);
+    }
+
+    pub fn set_vertex_buffer(&mut self, slot: u32, buffer_slice: wgpu::BufferSlice<'a>) {
+        self.encoder.set_vertex_buffer(slot, buffer_slice);
+    }
+
+    pub fn set_index_buffer(
+        &mut self,
+        buffer_slice: wgpu::BufferSlice<'a>,
+        index_format: wgpu::IndexFormat,
+    ) {
+        self.encoder.set_index_buffer(buffer_slice, index_format);
+    }
+
+    pub fn draw_indexed(&mut self, indices: Range<u32>, base_vertex: i32, instances: Range<u32>) {
+        self.encoder.draw_indexed(indices, base_vertex, instances);
+    }
+
+    /// Ends recording. The bundle keeps the resources it uses alive.
+    pub fn finish(self) -> wgpu::RenderBundle {
+        self.encoder.finish(&wgpu::RenderBundleDescriptor {
+            label: Some("Render Bundle"),
+        })
+    }
+}
@@ -0,0 +1,373 @@
+use std::{collections::HashMap, fmt};
+
+/// Textures shared between the passes of a [`RenderGraph`].
+pub struct RenderResources<'a> {
+    /// Where the final pass should draw, usually the surface texture.
+    pub output: &'a wgpu::TextureView,
+    views: HashMap<&'static str, &'a wgpu::TextureView>,
+}
+
+impl<'a> RenderResources<'a> {
+    pub fn new(output: &'a wgpu::TextureView) -> Self {
+        RenderResources {
+            output,
+            views: HashMap::new(),
+        }
+    }
+
+    /// Makes a view available to passes under `name`.
+    pub fn insert_view(&mut self, name: &'static str, view: &'a wgpu::TextureView) {
+        self.views.insert(name, view);
+    }
+
+    /// # Panics
+    ///
+    /// When no view was inserted under `name`.
+    pub fn view(&self, name: &str) -> &'a wgpu::TextureView {
+        self.views
+            .get(name)
+            .copied()
+            .unwrap_or_else(|| panic!("render graph has no view named {:?}", name))
+    }
+}
+
+/// A step of the frame, recorded into the encoder by the [`RenderGraph`].
+pub trait RenderPass {
+    fn execute(&self, encoder: &mut wgpu::CommandEncoder, resources: &RenderResources);
+}
+
+impl<F> RenderPass for F
+where
+    F: Fn(&mut wgpu::CommandEncoder, &RenderResources),
+{
+    fn execute(&self, encoder: &mut wgpu::CommandEncoder, resources: &RenderResources) {
+        self(encoder, resources)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RenderGraphError {
+    DuplicatePass(String),
+    UnknownDependency {
+        pass: String,
+        dependency: String,
+    },
+    /// A pass was marked as an output, but never added.
+    UnknownOutput(String),
+    /// The passes depend on each other in a loop.
+    Cycle(Vec<String>),
+    NotCompiled,
+}
+
+impl fmt::Display for RenderGraphError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RenderGraphError::DuplicatePass(name) => {
+                write!(f, "render pass {:?} was added more than once", name)
+            }
+            RenderGraphError::UnknownDependency { pass, dependency } => write!(
+                f,
+                "render pass {:?} depends on unknown pass {:?}",
+                pass, dependency
+            ),
+            RenderGraphError::UnknownOutput(name) => {
+                write!(f, "unknown render pass {:?} was marked as an output", name)
+            }
+            RenderGraphError::Cycle(passes) => {
+                write!(
+                    f,
+                    "render passes depend on each other: {}",
+                    passes.join(", ")
+                )
+            }
+            RenderGraphError::NotCompiled => {
+                write!(f, "render graph must be compiled before it's executed")
+            }
+        }
+    }
+}
+
+impl std::error::Error for RenderGraphError {}
+
+struct Node<'a> {
+    name: String,
+    deps: Vec<String>,
+    pass: Box<dyn RenderPass + 'a>,
+}
+
+/// Runs render passes in an order where every pass comes after
+/// the passes it depends on.
+///
+/// Passes may borrow whatever they render with, so a graph can be
+/// put together each frame from the renderer's state.
+///
+/// Once any pass is marked as an [output](Self::add_output), passes
+/// that no output depends on are culled when the graph is compiled.
+pub struct RenderGraph<'a> {
+    nodes: Vec<Node<'a>>,
+    outputs: Vec<String>,
+    /// Indices into `nodes`, in execution order. `None` until compiled,
+    /// and whenever a pass has been added since.
+    order: Option<Vec<usize>>,
+}
+
+impl<'a> RenderGraph<'a> {
+    pub fn new() -> Self {
+        RenderGraph {
+            nodes: Vec::new(),
+            outputs: Vec::new(),
+            order: None,
+        }
+    }
+
+    pub fn add_pass(&mut self, name: &str, deps: &[&str], pass: Box<dyn RenderPass + 'a>) {
+        self.nodes.push(Node {
+            name: name.to_string(),
+            deps: deps.iter().map(|dep| dep.to_string()).collect(),
+            pass,
+        });
+        self.order = None;
+    }
+
+    /// Marks a pass whose work is wanted for its own sake, like one
+    /// drawing into the output or reading results back to the CPU.
+    pub fn add_output(&mut self, name: &str) {
+        self.outputs.push(name.to_string());
+        self.order = None;
+    }
+
+    /// Sorts the passes by their dependencies, leaving out the ones
+    /// no output needs when there are outputs.
+    ///
+    /// Passes that don't depend on each other run in the
+    /// order they were added.
+    pub fn compile(&mut self) -> Result<(), RenderGraphError> {
+        let mut indices = HashMap::new();
+        for (index, node) in self.nodes.iter().enumerate() {
+            if indices.insert(node.name.as_str(), index).is_some() {
+                return Err(RenderGraphError::DuplicatePass(node.name.clone()));
+            }
+        }
+
+        // Number of unfinished dependencies for each pass, and
+        // the passes waiting for each pass to finish.
+        let mut pending = vec![0; self.nodes.len()];
+        let mut dependents = vec![Vec::new(); self.nodes.len()];
+        for (index, node) in self.nodes.iter().enumerate() {
+            for dep in &node.deps {
+                let dep_index = *indices.get(dep.as_str()).ok_or_else(|| {
+                    RenderGraphError::UnknownDependency {
+                        pass: node.name.clone(),
+                        dependency: dep.clone(),
+                    }
+                })?;
+                pending[index] += 1;
+                dependents[dep_index].push(index);
+            }
+        }
+
+        // Kahn's algorithm, always picking the earliest added pass
+        // that's ready, so the order is predictable.
+        let mut order = Vec::with_capacity(self.nodes.len());
+        let mut done = vec![false; self.nodes.len()];
+        while let Some(index) = (0..self.nodes.len()).find(|i| !done[*i] && pending[*i] == 0) {
+            done[index] = true;
+            order.push(index);
+            for dependent in &dependents[index] {
+                pending[*dependent] -= 1;
+            }
+        }
+
+        if order.len() < self.nodes.len() {
+            let cycle = self
+                .nodes
+                .iter()
+                .zip(&done)
+                .filter(|(_, done)| !**done)
+                .map(|(node, _)| node.name.clone())
+                .collect();
+            return Err(RenderGraphError::Cycle(cycle));
+        }
+
+        if !self.outputs.is_empty() {
+            let needed = self.needed(&indices)?;
+            order.retain(|index| needed[*index]);
+        }
+
+        self.order = Some(order);
+        Ok(())
+    }
+
+    /// Which passes the outputs depend on, along with the outputs.
+    fn needed(&self, indices: &HashMap<&str, usize>) -> Result<Vec<bool>, RenderGraphError> {
+        let mut needed = vec![false; self.nodes.len()];
+        let mut stack = Vec::new();
+        for output in &self.outputs {
+            let index = *indices
+                .get(output.as_str())
+                .ok_or_else(|| RenderGraphError::UnknownOutput(output.clone()))?;
+            stack.push(index);
+        }
+        // The dependencies were all found while sorting.
+        while let Some(index) = stack.pop() {
+            if !std::mem::replace(&mut needed[index], true) {
+                stack.extend(
+                    self.nodes[index]
+                        .deps
+                        .iter()
+                        .map(|dep| indices[dep.as_str()]),
+                );
+            }
+        }
+        Ok(needed)
+    }
+
+    /// Names of the passes in the order they'll execute.
+    pub fn pass_order(&self) -> Option<Vec<&str>> {
+        self.order.as_ref().map(|order| {
+            order
+                .iter()
+                .map(|index| self.nodes[*index].name.as_str())
+                .collect()
+        })
+    }
+
+    pub fn execute(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        resources: &RenderResources,
+    ) -> Result<(), RenderGraphError> {
+        let order = self.order.as_ref().ok_or(RenderGraphError::NotCompiled)?;
+        for index in order {
+            self.nodes[*index].pass.execute(encoder, resources);
+        }
+        Ok(())
+    }
+}
+
+impl<'a> Default for RenderGraph<'a> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn graph_of(passes: &[(&str, &[&str])]) -> RenderGraph<'static> {
+        let mut graph = RenderGraph::new();
+        for (name, deps) in passes {
+            graph.add_pass(
+                name,
+                deps,
+                Box::new(|_: &mut wgpu::CommandEncoder, _: &RenderResources| {}),
+            );
+        }
+        graph
+    }
+
+    #[test]
+    fn passes_run_after_their_dependencies() {
+        let mut graph = graph_of(&[
+            ("post", &["scene", "bloom"]),
+            ("bloom", &["scene"]),
+            ("scene", &["shadow"]),
+            ("shadow", &[]),
+        ]);
+        assert_eq!(graph.pass_order(), None);
+        graph.compile().unwrap();
+        assert_eq!(
+            graph.pass_order(),
+            Some(vec!["shadow", "scene", "bloom", "post"])
+        );
+    }
+
+    #[test]
+    fn independent_passes_keep_the_order_they_were_added() {
+        let mut graph = graph_of(&[("b", &[]), ("c", &["a"]), ("a", &[]), ("d", &[])]);
+        graph.compile().unwrap();
+        assert_eq!(graph.pass_order(), Some(vec!["b", "a", "c", "d"]));
+    }
+
+    #[test]
+    fn cycles_are_an_error() {
+        let mut graph = graph_of(&[
+            ("shadow", &[]),
+            ("a", &["shadow", "c"]),
+            ("b", &["a"]),
+            ("c", &["b"]),
+        ]);
+        assert_eq!(
+            graph.compile(),
+            Err(RenderGraphError::Cycle(vec![
+                "a".to_string(),
+                "b".to_string(),
+                "c".to_string()
+            ]))
+        );
+        assert_eq!(graph.pass_order(), None);
+
+        let mut graph = graph_of(&[("a", &["a"])]);
+        assert_eq!(
+            graph.compile(),
+            Err(RenderGraphError::Cycle(vec!["a".to_string()]))
+        );
+    }
+
+    #[test]
+    fn unknown_and_duplicate_passes_are_errors() {
+        let mut graph = graph_of(&[("a", &["missing"])]);
+        assert_eq!(
+            graph.compile(),
+            Err(RenderGraphError::UnknownDependency {
+                pass: "a".to_string(),
+                dependency: "missing".to_string(),
+            })
+        );
+
+        let mut graph = graph_of(&[("a", &[]), ("a", &[])]);
+        assert_eq!(
+            graph.compile(),
+            Err(RenderGraphError::DuplicatePass("a".to_string()))
+        );
+
+        let mut graph = graph_of(&[("a", &[])]);
+        graph.add_output("missing");
+        assert_eq!(
+            graph.compile(),
+            Err(RenderGraphError::UnknownOutput("missing".to_string()))
+        );
+    }
+
+    #[test]
+    fn passes_no_output_needs_are_culled() {
+        let passes: &[(&str, &[&str])] = &[
+            ("shadow", &[]),
+            ("scene", &["shadow"]),
+            ("debug", &["scene"]),
+            ("readback", &["scene"]),
+            ("post", &["scene"]),
+        ];
+
+        let mut graph = graph_of(passes);
+        graph.compile().unwrap();
+        assert_eq!(
+            graph.pass_order(),
+            Some(vec!["shadow", "scene", "debug", "readback", "post"])
+        );
+
+        let mut graph = graph_of(passes);
+        graph.add_output("post");
+        graph.compile().unwrap();
+        assert_eq!(graph.pass_order(), Some(vec!["shadow", "scene", "post"]));
+
+        graph.add_output("readback");
+        assert_eq!(graph.pass_order(), None);
+        graph.compile().unwrap();
+        assert_eq!(
+            graph.pass_order(),
+            Some(vec!["shadow", "scene", "readback", "post"])
+        );
+    }
+}
@@ -0,0 +1,59 @@
+use std::ops::{Deref, DerefMut};
+
+/// A render pass that ends when it's dropped, or when
+/// [`RenderPassGuard::end`] says so.
+///
+/// wgpu ends passes when they're dropped already. The guard makes
+/// that explicit, so a pass can be ended before
+/// `encoder.finish()` without wrapping it in a block. The pass
+/// borrows its encoder, so it can't be kept past it.
+pub struct RenderPassGuard<'enc> {
+    pass: wgpu::RenderPass<'enc>,
+}
+
+impl<'enc> RenderPassGuard<'enc> {
+    /// Ends the pass, letting go of the encoder.
+    pub fn end(self) {}
+}
+
+impl<'enc> Deref for RenderPassGuard<'enc> {
+    type Target = wgpu::RenderPass<'enc>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.pass
+    }
+}
+
+impl<'enc> DerefMut for RenderPassGuard<'enc> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.pass
+    }
+}
+
+impl Drop for RenderPassGuard<'_> {
+    fn drop(&mut self) {
+        // Dropping the pass after this ends it, like any other pass.
+    }
+}
+
+/// Where render passes start.
+pub struct RenderPassBuilder;
+
+impl RenderPassBuilder {
+    pub fn begin_render_pass<'enc>(
+        encoder: &'enc mut wgpu::CommandEncoder,
+        desc: &wgpu::RenderPassDescriptor<'enc, '_>,
+    ) -> RenderPassGuard<'enc> {
+        RenderPassGuard {
+            pass: encoder.begin_render_pass(desc),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn pass_cannot_outlive_its_encoder() {
+        trybuild::TestCases::new().compile_fail("tests/ui/render_pass_outlives_encoder.rs");
+    }
+}
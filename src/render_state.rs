@@ -0,0 +1,191 @@
+use std::fmt;
+
+/// The rectangle a pass draws into, in pixels from the top left, and
+/// the range of depths it maps to.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ViewportState {
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+    pub min_depth: f32,
+    pub max_depth: f32,
+}
+
+impl ViewportState {
+    /// The whole of a `width` by `height` target, with every depth.
+    pub fn full(width: u32, height: u32) -> Self {
+        ViewportState {
+            x: 0.0,
+            y: 0.0,
+            width: width as f32,
+            height: height as f32,
+            min_depth: 0.0,
+            max_depth: 1.0,
+        }
+    }
+}
+
+/// The rectangle fragments outside of are thrown away, in pixels from
+/// the top left.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ScissorRect {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl ScissorRect {
+    /// The whole of a `width` by `height` target.
+    pub fn full(width: u32, height: u32) -> Self {
+        ScissorRect {
+            x: 0,
+            y: 0,
+            width,
+            height,
+        }
+    }
+}
+
+/// What the scene's pipeline draws with at one moment, readable by a
+/// person, for dumping when rendering goes wrong.
+#[derive(Debug, Clone)]
+pub struct RenderStateSnapshot {
+    pub pipeline_label: String,
+    pub primitive: wgpu::PrimitiveState,
+    pub sample_count: u32,
+    /// `None` when the pipeline doesn't test depth.
+    pub depth_stencil_config: Option<wgpu::DepthStencilState>,
+    /// The blending of each color target, `None` where the fragment
+    /// replaces what's there.
+    pub blend_states: Vec<Option<wgpu::BlendState>>,
+    /// A line for each vertex buffer, from [`describe_vertex_layout`](Self::describe_vertex_layout).
+    pub vertex_layout_debug: Vec<String>,
+    /// What's bound at each bind group index, and how many entries it has.
+    pub bind_group_entry_counts: Vec<(String, usize)>,
+    /// The viewport and scissor of each view the scene's pass draws,
+    /// of which there are several with split views.
+    pub viewports: Vec<(ViewportState, ScissorRect)>,
+}
+
+impl RenderStateSnapshot {
+    /// Describes `layout` on one line: its stride, whether it steps
+    /// per vertex or per instance, and where each attribute is.
+    pub fn describe_vertex_layout(layout: &wgpu::VertexBufferLayout) -> String {
+        let attributes: Vec<String> = layout
+            .attributes
+            .iter()
+            .map(|attribute| {
+                format!(
+                    "location {} {:?} at {}",
+                    attribute.shader_location, attribute.format, attribute.offset
+                )
+            })
+            .collect();
+        format!(
+            "stride {}, per {:?}: {}",
+            layout.array_stride,
+            layout.step_mode,
+            attributes.join(", ")
+        )
+    }
+}
+
+fn write_blend_component(
+    f: &mut fmt::Formatter<'_>,
+    component: &wgpu::BlendComponent,
+) -> fmt::Result {
+    write!(
+        f,
+        "src * {:?} {:?} dst * {:?}",
+        component.src_factor, component.operation, component.dst_factor
+    )
+}
+
+impl fmt::Display for RenderStateSnapshot {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Pipeline: {}", self.pipeline_label)?;
+        let primitive = &self.primitive;
+        writeln!(
+            f,
+            "  topology {:?}, front face {:?}, cull {:?}, polygon mode {:?}",
+            primitive.topology, primitive.front_face, primitive.cull_mode, primitive.polygon_mode
+        )?;
+        writeln!(f, "  samples {}", self.sample_count)?;
+
+        writeln!(f, "Depth stencil:")?;
+        match &self.depth_stencil_config {
+            Some(depth_stencil) => {
+                writeln!(
+                    f,
+                    "  format {:?}, depth write {}, depth compare {:?}",
+                    depth_stencil.format,
+                    depth_stencil.depth_write_enabled,
+                    depth_stencil.depth_compare
+                )?;
+                let stencil = &depth_stencil.stencil;
+                writeln!(
+                    f,
+                    "  stencil read mask {:#x}, write mask {:#x}",
+                    stencil.read_mask, stencil.write_mask
+                )?;
+                writeln!(f, "  stencil front {:?}", stencil.front)?;
+                writeln!(f, "  stencil back {:?}", stencil.back)?;
+                let bias = &depth_stencil.bias;
+                writeln!(
+                    f,
+                    "  bias constant {}, slope scale {}, clamp {}",
+                    bias.constant, bias.slope_scale, bias.clamp
+                )?;
+            }
+            None => writeln!(f, "  none")?,
+        }
+
+        writeln!(f, "Blend states:")?;
+        for (i, blend) in self.blend_states.iter().enumerate() {
+            write!(f, "  target {}: ", i)?;
+            match blend {
+                Some(blend) => {
+                    write!(f, "color ")?;
+                    write_blend_component(f, &blend.color)?;
+                    write!(f, ", alpha ")?;
+                    write_blend_component(f, &blend.alpha)?;
+                    writeln!(f)?;
+                }
+                None => writeln!(f, "replace")?,
+            }
+        }
+
+        writeln!(f, "Vertex buffers:")?;
+        for (slot, layout) in self.vertex_layout_debug.iter().enumerate() {
+            writeln!(f, "  slot {}: {}", slot, layout)?;
+        }
+
+        writeln!(f, "Bind groups:")?;
+        for (index, (name, count)) in self.bind_group_entry_counts.iter().enumerate() {
+            writeln!(f, "  group {} ({}): {} entries", index, name, count)?;
+        }
+
+        writeln!(f, "Viewports:")?;
+        for (i, (viewport, scissor)) in self.viewports.iter().enumerate() {
+            writeln!(
+                f,
+                "  view {}: {}x{} at ({}, {}), depth {} to {}",
+                i,
+                viewport.width,
+                viewport.height,
+                viewport.x,
+                viewport.y,
+                viewport.min_depth,
+                viewport.max_depth
+            )?;
+            writeln!(
+                f,
+                "    scissor {}x{} at ({}, {})",
+                scissor.width, scissor.height, scissor.x, scissor.y
+            )?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,154 @@
+use crate::readback::{ReadbackRing, READBACK_FRAMES};
+
+/// Where the scene pass starts and ends, in the query set.
+const BEGIN_QUERY: u32 = 0;
+const END_QUERY: u32 = 1;
+const QUERY_COUNT: u32 = 2;
+
+/// Size of the resolved timestamps, which are a `u64` each.
+const QUERY_BUFFER_SIZE: wgpu::BufferAddress =
+    QUERY_COUNT as wgpu::BufferAddress * std::mem::size_of::<u64>() as wgpu::BufferAddress;
+
+/// Everything needed to time a pass on the GPU.
+struct TimestampQueries {
+    query_set: wgpu::QuerySet,
+    /// The timestamps are resolved straight into here, to be read back.
+    readbacks: ReadbackRing,
+    /// Nanoseconds per timestamp tick.
+    period: f32,
+}
+
+/// Measures how long the GPU takes to draw the scene, using
+/// timestamps written at the start and end of its render pass.
+///
+/// Timestamps need `wgpu::Features::TIMESTAMP_QUERY`. Without it
+/// nothing is recorded, and the frame time is always zero.
+///
+/// Also keeps count of the objects frustum culling let through.
+pub struct RenderStats {
+    queries: Option<TimestampQueries>,
+    gpu_frame_time_ms: f32,
+    objects_drawn: u32,
+    objects_culled: u32,
+}
+
+impl RenderStats {
+    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue) -> Self {
+        if !device.features().contains(wgpu::Features::TIMESTAMP_QUERY) {
+            log::info!("timestamp queries are not supported, GPU frame times won't be measured");
+            return RenderStats {
+                queries: None,
+                gpu_frame_time_ms: 0.0,
+                objects_drawn: 0,
+                objects_culled: 0,
+            };
+        }
+
+        let query_set = device.create_query_set(&wgpu::QuerySetDescriptor {
+            label: Some("Render Stats Query Set"),
+            ty: wgpu::QueryType::Timestamp,
+            count: QUERY_COUNT,
+        });
+        let readbacks = ReadbackRing::new(
+            device,
+            "Render Stats Readback Buffer",
+            QUERY_BUFFER_SIZE,
+            READBACK_FRAMES,
+        );
+
+        RenderStats {
+            queries: Some(TimestampQueries {
+                query_set,
+                readbacks,
+                period: queue.get_timestamp_period(),
+            }),
+            gpu_frame_time_ms: 0.0,
+            objects_drawn: 0,
+            objects_culled: 0,
+        }
+    }
+
+    /// Whether the GPU frame time is actually measured.
+    pub fn is_supported(&self) -> bool {
+        self.queries.is_some()
+    }
+
+    /// Should be called first thing in the timed render pass.
+    pub fn begin(&self, render_pass: &mut wgpu::RenderPass) {
+        if let Some(queries) = &self.queries {
+            render_pass.write_timestamp(&queries.query_set, BEGIN_QUERY);
+        }
+    }
+
+    /// Should be called last thing in the timed render pass.
+    pub fn end(&self, render_pass: &mut wgpu::RenderPass) {
+        if let Some(queries) = &self.queries {
+            render_pass.write_timestamp(&queries.query_set, END_QUERY);
+        }
+    }
+
+    /// Writes the timestamps somewhere they can be read, once the
+    /// timed pass has ended. Should be recorded into the same
+    /// encoder as the pass.
+    pub fn resolve(&self, encoder: &mut wgpu::CommandEncoder) {
+        let queries = match &self.queries {
+            Some(queries) => queries,
+            None => return,
+        };
+        // Skipped while the GPU is behind on the earlier frames'.
+        if let Some(readback) = queries.readbacks.target() {
+            encoder.resolve_query_set(&queries.query_set, 0..QUERY_COUNT, readback, 0);
+        }
+    }
+
+    /// Should be called once the encoder the timestamps were resolved
+    /// in is submitted. Takes the frame time from the newest frame
+    /// whose timestamps are back.
+    ///
+    /// Doesn't wait for the GPU, so the frame time is a frame or
+    /// more behind, and stays as it was while none have come back.
+    pub fn read_back(&mut self, device: &wgpu::Device) {
+        let queries = match &mut self.queries {
+            Some(queries) => queries,
+            None => return,
+        };
+
+        queries.readbacks.submitted(QUERY_BUFFER_SIZE, ());
+        let timestamps = queries.readbacks.read(device, |data, ()| {
+            let timestamps: &[u64] = bytemuck::cast_slice(data);
+            (
+                timestamps[BEGIN_QUERY as usize],
+                timestamps[END_QUERY as usize],
+            )
+        });
+
+        if let Some((begin, end)) = timestamps {
+            // Ticks can go backwards when the GPU changes clock speed,
+            // which would make the subtraction overflow.
+            let ticks = end.saturating_sub(begin);
+            self.gpu_frame_time_ms = ticks as f32 * queries.period / 1_000_000.0;
+        }
+    }
+
+    /// How long the GPU took to draw the scene a recent frame,
+    /// in milliseconds. Zero when timestamps aren't supported.
+    pub fn gpu_frame_time_ms(&self) -> f32 {
+        self.gpu_frame_time_ms
+    }
+
+    /// Records how many objects were drawn and culled this frame.
+    pub fn set_cull_counts(&mut self, drawn: u32, culled: u32) {
+        self.objects_drawn = drawn;
+        self.objects_culled = culled;
+    }
+
+    /// Objects inside the camera's frustum last frame.
+    pub fn objects_drawn(&self) -> u32 {
+        self.objects_drawn
+    }
+
+    /// Objects skipped for being outside the camera's frustum last frame.
+    pub fn objects_culled(&self) -> u32 {
+        self.objects_culled
+    }
+}
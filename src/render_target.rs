@@ -0,0 +1,163 @@
+use crate::depth::DepthBuffer;
+
+/// A single color output of a [`RenderTarget`].
+pub struct ColorTexture {
+    pub texture: wgpu::Texture,
+    pub view: wgpu::TextureView,
+    pub format: wgpu::TextureFormat,
+}
+
+/// A set of off-screen textures a render pass can draw into
+/// at the same time, such as the slots of a G-buffer.
+///
+/// Each slot has its own texture format, and they all share
+/// the same size and optional depth buffer.
+pub struct RenderTarget {
+    colors: Vec<ColorTexture>,
+    depth: Option<DepthBuffer>,
+    width: u32,
+    height: u32,
+}
+
+impl RenderTarget {
+    pub fn new(
+        device: &wgpu::Device,
+        width: u32,
+        height: u32,
+        formats: &[wgpu::TextureFormat],
+    ) -> Self {
+        let colors = formats
+            .iter()
+            .enumerate()
+            .map(|(slot, format)| create_color_texture(device, width, height, *format, slot))
+            .collect();
+
+        RenderTarget {
+            colors,
+            depth: None,
+            width,
+            height,
+        }
+    }
+
+    /// Adds a depth buffer to the render target.
+    pub fn with_depth(mut self, device: &wgpu::Device) -> Self {
+        self.depth = Some(DepthBuffer::new(device, self.width, self.height));
+        self
+    }
+
+    #[allow(dead_code)]
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    #[allow(dead_code)]
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn colors(&self) -> &[ColorTexture] {
+        &self.colors
+    }
+
+    #[allow(dead_code)]
+    pub fn color(&self, slot: usize) -> Option<&ColorTexture> {
+        self.colors.get(slot)
+    }
+
+    pub fn depth(&self) -> Option<&DepthBuffer> {
+        self.depth.as_ref()
+    }
+
+    /// Color target states for a pipeline that renders into this target.
+    pub fn color_targets(&self) -> Vec<wgpu::ColorTargetState> {
+        self.colors
+            .iter()
+            .map(|color| wgpu::ColorTargetState {
+                format: color.format,
+                blend: Some(wgpu::BlendState::REPLACE),
+                write_mask: wgpu::ColorWrites::ALL,
+            })
+            .collect()
+    }
+
+    /// One color attachment per slot, in slot order, each
+    /// cleared to `clear_color` at the start of the pass.
+    pub fn color_attachment_array(
+        &self,
+        clear_color: wgpu::Color,
+    ) -> Vec<wgpu::RenderPassColorAttachment<'_>> {
+        self.colors
+            .iter()
+            .map(|color| wgpu::RenderPassColorAttachment {
+                view: &color.view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(clear_color),
+                    store: true,
+                },
+            })
+            .collect()
+    }
+
+    pub fn depth_stencil_attachment(&self) -> Option<wgpu::RenderPassDepthStencilAttachment<'_>> {
+        self.depth
+            .as_ref()
+            .map(|depth| wgpu::RenderPassDepthStencilAttachment {
+                view: depth.view(),
+                depth_ops: Some(wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(1.0),
+                    store: true,
+                }),
+                stencil_ops: None,
+            })
+    }
+
+    /// Recreates all textures at the new size.
+    pub fn resize(&mut self, device: &wgpu::Device, width: u32, height: u32) {
+        self.width = width;
+        self.height = height;
+
+        for (slot, color) in self.colors.iter_mut().enumerate() {
+            *color = create_color_texture(device, width, height, color.format, slot);
+        }
+
+        if let Some(depth) = &mut self.depth {
+            depth.resize(device, width, height);
+        }
+    }
+}
+
+fn create_color_texture(
+    device: &wgpu::Device,
+    width: u32,
+    height: u32,
+    format: wgpu::TextureFormat,
+    slot: usize,
+) -> ColorTexture {
+    let label = format!("Render Target {}", slot);
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some(&label),
+        size: wgpu::Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format,
+        // Rendered into by one pass, and sampled by the passes after
+        // it, or copied out of whole.
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+            | wgpu::TextureUsages::TEXTURE_BINDING
+            | wgpu::TextureUsages::COPY_SRC,
+    });
+    let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+    ColorTexture {
+        texture,
+        view,
+        format,
+    }
+}
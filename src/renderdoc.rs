@@ -0,0 +1,109 @@
+//! Frame captures with RenderDoc, triggered from inside the app.
+//!
+//! Only built with the `renderdoc` feature. Without it,
+//! [`RenderDocCapture::try_init`] always gives `None`, so call
+//! sites don't need to know whether the feature is on.
+
+#[cfg(feature = "renderdoc")]
+pub use self::api::RenderDocCapture;
+#[cfg(not(feature = "renderdoc"))]
+pub use self::stub::RenderDocCapture;
+
+#[cfg(feature = "renderdoc")]
+mod api {
+    use std::{ffi::c_void, ptr};
+
+    use renderdoc_sys::{eRENDERDOC_API_Version_1_4_1, RENDERDOC_API_1_4_1};
+
+    #[cfg(windows)]
+    const LIBRARY: &str = "renderdoc.dll";
+    #[cfg(not(windows))]
+    const LIBRARY: &str = "librenderdoc.so";
+
+    type GetApiFn = unsafe extern "C" fn(version: u32, out: *mut *mut c_void) -> i32;
+
+    /// The API of a RenderDoc library loaded into the app.
+    ///
+    /// The library is only of any use when the app is launched from
+    /// RenderDoc, which hooks the graphics API before it starts.
+    pub struct RenderDocCapture {
+        api: RENDERDOC_API_1_4_1,
+        /// The function table points into the library, so it's
+        /// kept loaded for as long as the table is.
+        _library: libloading::Library,
+    }
+
+    impl RenderDocCapture {
+        /// Loads the RenderDoc library and asks it for its API,
+        /// or `None` when it can't be found.
+        pub fn try_init() -> Option<Self> {
+            // Safety: RenderDoc's library doesn't do anything
+            // when it's loaded beyond hooking the graphics API.
+            let library = match unsafe { libloading::Library::new(LIBRARY) } {
+                Ok(library) => library,
+                Err(err) => {
+                    log::info!("RenderDoc isn't available: {}", err);
+                    return None;
+                }
+            };
+
+            let mut api = ptr::null_mut();
+            // Safety: `RENDERDOC_GetAPI` has this signature in every
+            // version of RenderDoc, and fills in a table of the
+            // version asked for when it returns 1.
+            unsafe {
+                let get_api = library.get::<GetApiFn>(b"RENDERDOC_GetAPI\0").ok()?;
+                if get_api(eRENDERDOC_API_Version_1_4_1, &mut api) != 1 || api.is_null() {
+                    log::warn!("RenderDoc is too old for API version 1.4.1");
+                    return None;
+                }
+                Some(RenderDocCapture {
+                    api: *(api as *const RENDERDOC_API_1_4_1),
+                    _library: library,
+                })
+            }
+        }
+
+        /// Starts capturing everything submitted until
+        /// [`RenderDocCapture::end_frame_capture`].
+        pub fn start_frame_capture(&self) {
+            if let Some(start) = self.api.StartFrameCapture {
+                // Null handles capture whichever device and window
+                // RenderDoc last saw presenting.
+                unsafe { start(ptr::null_mut(), ptr::null_mut()) };
+            }
+        }
+
+        /// Ends the capture, and saves it where RenderDoc
+        /// shows its captures.
+        pub fn end_frame_capture(&self) {
+            if let Some(end) = self.api.EndFrameCapture {
+                let saved = unsafe { end(ptr::null_mut(), ptr::null_mut()) };
+                if saved == 1 {
+                    log::info!("captured a frame with RenderDoc");
+                } else {
+                    log::warn!("RenderDoc failed to capture the frame");
+                }
+            }
+        }
+    }
+}
+
+#[cfg(not(feature = "renderdoc"))]
+mod stub {
+    /// Stands in for the RenderDoc API when the `renderdoc`
+    /// feature is off. It can never be made.
+    pub struct RenderDocCapture {
+        _private: (),
+    }
+
+    impl RenderDocCapture {
+        pub fn try_init() -> Option<Self> {
+            None
+        }
+
+        pub fn start_frame_capture(&self) {}
+
+        pub fn end_frame_capture(&self) {}
+    }
+}
@@ -0,0 +1,206 @@
+use crate::{
+    bounds::{Aabb, Frustum},
+    ecs::{EntityId, World},
+    instance::{InstanceBuffer, InstanceData},
+    lod::{LodHandle, LodLevel, LodMesh},
+    mesh::Mesh,
+    occlusion_query::OcclusionQueryPool,
+    transform::Transform,
+};
+
+/// Component naming the mesh an entity is drawn with, by its
+/// index in the renderer's meshes.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct MeshHandle(pub usize);
+
+/// Component naming the material an entity is drawn with,
+/// by its name in the material library.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct MaterialHandle(pub String);
+
+/// The mesh a batch is drawn with.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum BatchMesh {
+    Mesh(MeshHandle),
+    /// One level of a mesh with several levels of detail.
+    Lod(LodHandle, LodLevel),
+}
+
+/// Entities sharing a mesh and a material, drawn together
+/// with one instanced draw call.
+pub struct DrawBatch {
+    pub mesh: BatchMesh,
+    pub material: MaterialHandle,
+    /// The entity of each instance, in the order they were uploaded.
+    pub entities: Vec<EntityId>,
+    pub instances: InstanceBuffer,
+    /// How many instances, from the first, are inside the camera's
+    /// frustum and weren't hidden behind others. Only these need
+    /// drawing from the camera, but the rest can still cast shadows
+    /// into view.
+    pub visible: u32,
+}
+
+impl DrawBatch {
+    /// Instances outside the camera's frustum, or hidden.
+    pub fn culled(&self) -> u32 {
+        self.instances.len() - self.visible
+    }
+}
+
+/// The entities of a batch, before they're uploaded.
+struct Group {
+    mesh: BatchMesh,
+    material: MaterialHandle,
+    entities: Vec<EntityId>,
+    instances: Vec<InstanceData>,
+    /// Entities outside the frustum or hidden, which go after the rest.
+    culled: Vec<(EntityId, InstanceData)>,
+}
+
+/// The bounds of the mesh a batch is drawn with, if it has any.
+fn bounds_of(mesh: BatchMesh, meshes: &[Mesh], lod_meshes: &[LodMesh]) -> Option<Aabb> {
+    match mesh {
+        BatchMesh::Mesh(handle) => meshes[handle.0].bounds,
+        BatchMesh::Lod(handle, _) => Some(*lod_meshes[handle.0].bounds()),
+    }
+}
+
+/// The drawable entities in `world` whose meshes have bounds, with
+/// their bounds and transforms, for picking them with a ray.
+pub fn entity_bounds(
+    world: &World,
+    meshes: &[Mesh],
+    lod_meshes: &[LodMesh],
+) -> Vec<(EntityId, Aabb, Transform)> {
+    let plain_entities = world
+        .query::<(Transform, MeshHandle, MaterialHandle)>()
+        .map(|(entity, (transform, mesh, _))| (entity, transform, BatchMesh::Mesh(*mesh)));
+    let lod_entities = world
+        .query::<(Transform, LodHandle, LodLevel, MaterialHandle)>()
+        .map(|(entity, (transform, lod, level, _))| {
+            (entity, transform, BatchMesh::Lod(*lod, *level))
+        });
+
+    plain_entities
+        .chain(lod_entities)
+        .filter_map(|(entity, transform, mesh)| {
+            bounds_of(mesh, meshes, lod_meshes).map(|bounds| (entity, bounds, *transform))
+        })
+        .collect()
+}
+
+/// Groups the drawable entities in `world` by mesh and material,
+/// taking the level of detail entities with a [`LodHandle`] were
+/// last given by [`lod::update_levels`](crate::lod::update_levels),
+/// and uploads their transforms into the instance buffers of
+/// `batches`. Buffers are reused from the last call when they're
+/// big enough, so this can be called every frame.
+///
+/// The instances of each batch are ordered with those whose mesh
+/// bounds touch `frustum` first, counted by [`DrawBatch::visible`].
+/// Entities `occlusion` found hidden last frame are left out of them.
+///
+/// Returns whether the draw calls for the visible instances changed
+/// from the last call, rather than just the instances' transforms.
+#[allow(clippy::too_many_arguments)]
+pub fn update_batches(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    world: &World,
+    frustum: &Frustum,
+    meshes: &[Mesh],
+    lod_meshes: &[LodMesh],
+    occlusion: Option<&OcclusionQueryPool>,
+    batches: &mut Vec<DrawBatch>,
+) -> bool {
+    let plain_entities = world
+        .query::<(Transform, MeshHandle, MaterialHandle)>()
+        .map(|(entity, (transform, mesh, material))| {
+            (entity, transform, BatchMesh::Mesh(*mesh), material)
+        });
+    let lod_entities = world
+        .query::<(Transform, LodHandle, LodLevel, MaterialHandle)>()
+        .map(|(entity, (transform, lod, level, material))| {
+            (entity, transform, BatchMesh::Lod(*lod, *level), material)
+        });
+
+    let mut groups: Vec<Group> = Vec::new();
+    for (entity, transform, mesh, material) in plain_entities.chain(lod_entities) {
+        let index = match groups
+            .iter()
+            .position(|group| group.mesh == mesh && group.material == *material)
+        {
+            Some(index) => index,
+            None => {
+                groups.push(Group {
+                    mesh,
+                    material: material.clone(),
+                    entities: Vec::new(),
+                    instances: Vec::new(),
+                    culled: Vec::new(),
+                });
+                groups.len() - 1
+            }
+        };
+
+        let instance = InstanceData::from_transform(transform);
+        let visible = match bounds_of(mesh, meshes, lod_meshes) {
+            Some(bounds) => frustum.intersects_aabb(&bounds, transform),
+            None => true,
+        } && !occlusion.is_some_and(|occlusion| occlusion.is_occluded(entity));
+        let group = &mut groups[index];
+        if visible {
+            group.entities.push(entity);
+            group.instances.push(instance);
+        } else {
+            group.culled.push((entity, instance));
+        }
+    }
+
+    // Keeps the old batches around to take their buffers from.
+    let mut old_batches = std::mem::take(batches);
+    let mut changed = false;
+    for mut group in groups {
+        let visible = group.instances.len() as u32;
+        for (entity, instance) in group.culled.drain(..) {
+            group.entities.push(entity);
+            group.instances.push(instance);
+        }
+
+        // The same batch's buffer is taken when it's big enough, so
+        // batches that stay the same keep drawing from the same buffer.
+        let count = group.instances.len();
+        let fits = |batch: &DrawBatch| batch.instances.capacity() as usize >= count;
+        let same = old_batches
+            .iter()
+            .position(|batch| batch.mesh == group.mesh && batch.material == group.material);
+        let reused = match same.filter(|index| fits(&old_batches[*index])) {
+            Some(index) => {
+                let batch = old_batches.swap_remove(index);
+                changed |= batch.visible != visible;
+                Some(batch.instances)
+            }
+            None => {
+                changed = true;
+                old_batches
+                    .iter()
+                    .position(fits)
+                    .map(|index| old_batches.swap_remove(index).instances)
+            }
+        };
+        let mut buffer = reused.unwrap_or_else(|| InstanceBuffer::new(device, count as u32));
+        buffer.upload(queue, &group.instances);
+
+        batches.push(DrawBatch {
+            mesh: group.mesh,
+            material: group.material,
+            entities: group.entities,
+            instances: buffer,
+            visible,
+        });
+    }
+
+    // Batches that weren't taken up have gone.
+    changed || !old_batches.is_empty()
+}
@@ -0,0 +1,352 @@
+use std::{fmt, path::Path};
+
+use crate::{
+    ecs::World,
+    lod::LodHandle,
+    scene::{MaterialHandle, MeshHandle},
+    transform::Transform,
+};
+
+/// Version scene files are written with. Bump it whenever the format
+/// changes, and add a migration from the version before.
+pub const SCENE_VERSION: u32 = 2;
+
+/// A saved scene, as it's written to disk.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct SceneFile {
+    pub version: u32,
+    pub entities: Vec<EntityRecord>,
+}
+
+/// One drawable entity in a [`SceneFile`].
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct EntityRecord {
+    pub transform: Transform,
+    /// Name the mesh was registered under with the serializer.
+    pub mesh: String,
+    pub material: String,
+}
+
+/// Scenes from before entities could be rotated or scaled.
+#[derive(Debug, serde::Deserialize)]
+struct SceneFileV1 {
+    entities: Vec<EntityRecordV1>,
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct EntityRecordV1 {
+    position: [f32; 3],
+    mesh: String,
+    material: String,
+}
+
+/// Brings a version 1 scene up to date, leaving its
+/// entities unrotated and unscaled.
+fn migrate_v1(file: SceneFileV1) -> SceneFile {
+    SceneFile {
+        version: SCENE_VERSION,
+        entities: file
+            .entities
+            .into_iter()
+            .map(|entity| EntityRecord {
+                transform: Transform::from_translation(entity.position),
+                mesh: entity.mesh,
+                material: entity.material,
+            })
+            .collect(),
+    }
+}
+
+/// Only the version, read first to know how to read the rest.
+#[derive(Debug, serde::Deserialize)]
+struct VersionHeader {
+    version: u32,
+}
+
+/// What a mesh name in a scene file stands for.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum MeshAsset {
+    Mesh(MeshHandle),
+    Lod(LodHandle),
+}
+
+#[derive(Debug)]
+pub enum SceneError {
+    Io(std::io::Error),
+    Format(serde_json::Error),
+    /// The file was written by a newer version, or isn't a scene.
+    UnsupportedVersion(u32),
+    /// The scene names a mesh that isn't registered.
+    UnknownMesh(String),
+}
+
+impl fmt::Display for SceneError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SceneError::Io(err) => write!(f, "failed to access scene file: {}", err),
+            SceneError::Format(err) => write!(f, "malformed scene file: {}", err),
+            SceneError::UnsupportedVersion(version) => write!(
+                f,
+                "scene file version {} isn't supported, the latest is {}",
+                version, SCENE_VERSION
+            ),
+            SceneError::UnknownMesh(name) => write!(f, "scene uses unknown mesh {:?}", name),
+        }
+    }
+}
+
+impl std::error::Error for SceneError {}
+
+impl From<std::io::Error> for SceneError {
+    fn from(err: std::io::Error) -> Self {
+        SceneError::Io(err)
+    }
+}
+
+impl From<serde_json::Error> for SceneError {
+    fn from(err: serde_json::Error) -> Self {
+        SceneError::Format(err)
+    }
+}
+
+/// Saves the drawable entities of a [`World`] to a JSON file, and
+/// loads them back.
+///
+/// Meshes are uploaded when the app starts rather than being loaded
+/// from files, so scene files name them instead, by the names they
+/// were registered under with [`SceneSerializer::register_mesh`].
+#[derive(Debug, Default)]
+pub struct SceneSerializer {
+    meshes: Vec<(String, MeshAsset)>,
+}
+
+impl SceneSerializer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Names a mesh, so entities drawn with it can be saved and loaded.
+    pub fn register_mesh(&mut self, name: &str, mesh: MeshAsset) {
+        self.meshes.push((name.to_string(), mesh));
+    }
+
+    fn mesh_name(&self, mesh: MeshAsset) -> Option<&str> {
+        self.meshes
+            .iter()
+            .find(|(_, asset)| *asset == mesh)
+            .map(|(name, _)| name.as_str())
+    }
+
+    fn mesh_asset(&self, name: &str) -> Option<MeshAsset> {
+        self.meshes
+            .iter()
+            .find(|(registered, _)| registered == name)
+            .map(|(_, asset)| *asset)
+    }
+
+    /// The drawable entities of `world`, in the order they were spawned.
+    /// Entities drawn with unregistered meshes are left out.
+    pub fn to_scene_file(&self, world: &World) -> SceneFile {
+        let entities = world
+            .entities()
+            .iter()
+            .filter_map(|&entity| {
+                let transform = world.get::<Transform>(entity)?;
+                let material = world.get::<MaterialHandle>(entity)?;
+                let mesh = match world.get::<MeshHandle>(entity) {
+                    Some(handle) => MeshAsset::Mesh(*handle),
+                    None => MeshAsset::Lod(*world.get::<LodHandle>(entity)?),
+                };
+                match self.mesh_name(mesh) {
+                    Some(name) => Some(EntityRecord {
+                        transform: *transform,
+                        mesh: name.to_string(),
+                        material: material.0.clone(),
+                    }),
+                    None => {
+                        log::warn!("not saving entity {} with unnamed {:?}", entity.0, mesh);
+                        None
+                    }
+                }
+            })
+            .collect();
+
+        SceneFile {
+            version: SCENE_VERSION,
+            entities,
+        }
+    }
+
+    /// Spawns the entities of `file` into a new world.
+    pub fn to_world(&self, file: &SceneFile) -> Result<World, SceneError> {
+        let mut world = World::new();
+        for record in &file.entities {
+            let mesh = self
+                .mesh_asset(&record.mesh)
+                .ok_or_else(|| SceneError::UnknownMesh(record.mesh.clone()))?;
+
+            let entity = world.spawn();
+            world.insert(entity, record.transform);
+            world.insert(entity, MaterialHandle(record.material.clone()));
+            match mesh {
+                MeshAsset::Mesh(handle) => {
+                    world.insert(entity, handle);
+                }
+                MeshAsset::Lod(handle) => {
+                    world.insert(entity, handle);
+                }
+            }
+        }
+        Ok(world)
+    }
+
+    /// Writes the drawable entities of `world` to `path`.
+    pub fn save(&self, world: &World, path: &Path) -> Result<(), SceneError> {
+        let file = self.to_scene_file(world);
+        let text = serde_json::to_string_pretty(&file)?;
+        std::fs::write(path, text)?;
+        Ok(())
+    }
+
+    /// Reads the scene at `path`, migrating it from older versions.
+    pub fn load(&self, path: &Path) -> Result<World, SceneError> {
+        let text = std::fs::read_to_string(path)?;
+        let header: VersionHeader = serde_json::from_str(&text)?;
+        let file = match header.version {
+            1 => migrate_v1(serde_json::from_str(&text)?),
+            SCENE_VERSION => serde_json::from_str(&text)?,
+            version => return Err(SceneError::UnsupportedVersion(version)),
+        };
+        self.to_world(&file)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn serializer() -> SceneSerializer {
+        let mut serializer = SceneSerializer::new();
+        serializer.register_mesh("cube", MeshAsset::Mesh(MeshHandle(0)));
+        serializer.register_mesh("sphere", MeshAsset::Lod(LodHandle(0)));
+        serializer
+    }
+
+    /// A path in the temporary directory no other test writes to.
+    fn temp_path(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("grok-wgpu-{}-{}.json", name, std::process::id()))
+    }
+
+    #[test]
+    fn saved_scenes_load_back_the_same() {
+        let serializer = serializer();
+        let mut world = World::new();
+        let cube = world.spawn();
+        world.insert(
+            cube,
+            Transform {
+                translation: [1.0, 2.0, 3.0],
+                rotation: [0.0, 0.6, 0.0, 0.8],
+                scale: [0.5, 1.0, 2.0],
+            },
+        );
+        world.insert(cube, MeshHandle(0));
+        world.insert(cube, MaterialHandle("stone".to_string()));
+        let sphere = world.spawn();
+        world.insert(sphere, Transform::from_translation([-4.0, 0.0, 0.25]));
+        world.insert(sphere, LodHandle(0));
+        world.insert(sphere, MaterialHandle("gold".to_string()));
+
+        let path = temp_path("round-trip");
+        serializer.save(&world, &path).unwrap();
+        let loaded = serializer.load(&path);
+        std::fs::remove_file(&path).unwrap();
+        let loaded = loaded.unwrap();
+
+        assert_eq!(
+            serializer.to_scene_file(&loaded),
+            serializer.to_scene_file(&world)
+        );
+        let sphere = loaded.entities()[1];
+        assert_eq!(loaded.get::<LodHandle>(sphere), Some(&LodHandle(0)));
+        assert_eq!(loaded.get::<MeshHandle>(sphere), None);
+    }
+
+    #[test]
+    fn entities_with_unregistered_meshes_are_left_out() {
+        let serializer = serializer();
+        let mut world = World::new();
+        let entity = world.spawn();
+        world.insert(entity, Transform::identity());
+        world.insert(entity, MeshHandle(7));
+        world.insert(entity, MaterialHandle("stone".to_string()));
+
+        assert!(serializer.to_scene_file(&world).entities.is_empty());
+    }
+
+    #[test]
+    fn version_1_scenes_are_migrated() {
+        let v1 = serde_json::from_str(
+            r#"{
+                "version": 1,
+                "entities": [
+                    { "position": [1.0, 2.0, 3.0], "mesh": "cube", "material": "stone" }
+                ]
+            }"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            migrate_v1(v1),
+            SceneFile {
+                version: SCENE_VERSION,
+                entities: vec![EntityRecord {
+                    transform: Transform::from_translation([1.0, 2.0, 3.0]),
+                    mesh: "cube".to_string(),
+                    material: "stone".to_string(),
+                }],
+            }
+        );
+    }
+
+    #[test]
+    fn loading_reads_the_version_first() {
+        let serializer = serializer();
+        let path = temp_path("versions");
+
+        std::fs::write(
+            &path,
+            r#"{ "version": 1, "entities": [{ "position": [0.0, 1.0, 0.0], "mesh": "cube", "material": "stone" }] }"#,
+        )
+        .unwrap();
+        let v1 = serializer.load(&path);
+        std::fs::write(&path, r#"{ "version": 99, "entities": [] }"#).unwrap();
+        let newer = serializer.load(&path);
+        std::fs::remove_file(&path).unwrap();
+
+        let world = v1.unwrap();
+        let entity = world.entities()[0];
+        assert_eq!(
+            world.get::<Transform>(entity),
+            Some(&Transform::from_translation([0.0, 1.0, 0.0]))
+        );
+        assert!(matches!(newer, Err(SceneError::UnsupportedVersion(99))));
+    }
+
+    #[test]
+    fn unknown_meshes_are_an_error() {
+        let file = SceneFile {
+            version: SCENE_VERSION,
+            entities: vec![EntityRecord {
+                transform: Transform::identity(),
+                mesh: "teapot".to_string(),
+                material: "stone".to_string(),
+            }],
+        };
+
+        assert!(matches!(
+            serializer().to_world(&file),
+            Err(SceneError::UnknownMesh(name)) if name == "teapot"
+        ));
+    }
+}
@@ -0,0 +1,143 @@
+use std::{fmt, path::Path};
+
+#[derive(Debug)]
+pub enum ScreenshotError {
+    /// Only 8-bit RGBA and BGRA textures can be written out.
+    UnsupportedFormat(wgpu::TextureFormat),
+    Map(wgpu::BufferAsyncError),
+    Image(image::ImageError),
+}
+
+impl fmt::Display for ScreenshotError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ScreenshotError::UnsupportedFormat(format) => {
+                write!(f, "can't take a screenshot of a {:?} texture", format)
+            }
+            ScreenshotError::Map(err) => write!(f, "failed to map screenshot buffer: {}", err),
+            ScreenshotError::Image(err) => write!(f, "failed to save screenshot: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for ScreenshotError {}
+
+impl From<image::ImageError> for ScreenshotError {
+    fn from(err: image::ImageError) -> Self {
+        ScreenshotError::Image(err)
+    }
+}
+
+/// Reads textures back from the GPU and saves them as PNG files.
+pub struct ScreenshotCapture;
+
+impl ScreenshotCapture {
+    /// Creates a texture the frame can be rendered into for capturing.
+    ///
+    /// Not every backend allows copying out of the surface texture,
+    /// so screenshots are taken from a separate texture instead.
+    pub fn create_target(
+        device: &wgpu::Device,
+        width: u32,
+        height: u32,
+        format: wgpu::TextureFormat,
+    ) -> wgpu::Texture {
+        device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Screenshot Texture"),
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+        })
+    }
+
+    /// Copies the texture to the CPU and writes it to `path` as a PNG.
+    ///
+    /// Blocks until the GPU has finished all submitted work. The
+    /// texture must have been created with `COPY_SRC` usage.
+    pub fn capture(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        texture: &wgpu::Texture,
+        size: (u32, u32),
+        format: wgpu::TextureFormat,
+        path: impl AsRef<Path>,
+    ) -> Result<(), ScreenshotError> {
+        let swap_red_blue = match format {
+            wgpu::TextureFormat::Rgba8Unorm | wgpu::TextureFormat::Rgba8UnormSrgb => false,
+            wgpu::TextureFormat::Bgra8Unorm | wgpu::TextureFormat::Bgra8UnormSrgb => true,
+            _ => return Err(ScreenshotError::UnsupportedFormat(format)),
+        };
+
+        let (width, height) = size;
+        let unpadded_bytes_per_row = width * 4;
+        // Texture to buffer copies must have rows that are a
+        // multiple of 256 bytes long, so each row is padded.
+        let align = wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+        let padded_bytes_per_row = unpadded_bytes_per_row.div_ceil(align) * align;
+
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Screenshot Buffer"),
+            size: (padded_bytes_per_row * height) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Screenshot Encoder"),
+        });
+        encoder.copy_texture_to_buffer(
+            wgpu::ImageCopyTexture {
+                texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            },
+            wgpu::ImageCopyBuffer {
+                buffer: &buffer,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: std::num::NonZeroU32::new(padded_bytes_per_row),
+                    rows_per_image: None,
+                },
+            },
+            wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+        );
+        queue.submit(std::iter::once(encoder.finish()));
+
+        // The mapping only resolves once the device is polled.
+        let slice = buffer.slice(..);
+        let mapping = slice.map_async(wgpu::MapMode::Read);
+        device.poll(wgpu::Maintain::Wait);
+        pollster::block_on(mapping).map_err(ScreenshotError::Map)?;
+
+        let mut pixels = Vec::with_capacity((unpadded_bytes_per_row * height) as usize);
+        {
+            let data = slice.get_mapped_range();
+            for row in data.chunks(padded_bytes_per_row as usize) {
+                pixels.extend_from_slice(&row[..unpadded_bytes_per_row as usize]);
+            }
+        }
+        buffer.unmap();
+
+        if swap_red_blue {
+            for pixel in pixels.chunks_exact_mut(4) {
+                pixel.swap(0, 2);
+            }
+        }
+
+        image::save_buffer(path, &pixels, width, height, image::ColorType::Rgba8)?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,440 @@
+use std::{collections::HashMap, fmt, num::NonZeroU32};
+
+use crate::{texture::Texture, texture_atlas};
+
+/// Drawn in place of characters the font has no glyph for.
+const FALLBACK: char = '?';
+
+/// The characters put in the atlas, which is printable ASCII.
+const CHARACTERS: std::ops::RangeInclusive<char> = ' '..='~';
+
+/// Segments each curve of an outline is split into.
+const CURVE_SEGMENTS: usize = 8;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SdfFontError {
+    /// The data isn't a TrueType or OpenType font.
+    InvalidFont,
+    /// The glyphs don't fit in an atlas of the maximum size.
+    TooLarge { max_dimension: u32 },
+}
+
+impl fmt::Display for SdfFontError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SdfFontError::InvalidFont => write!(f, "failed to parse font"),
+            SdfFontError::TooLarge { max_dimension } => {
+                write!(f, "glyphs don't fit in a {0}x{0} font atlas", max_dimension)
+            }
+        }
+    }
+}
+
+impl std::error::Error for SdfFontError {}
+
+/// Where a glyph is, and how it's placed on a line of text, in
+/// pixels at the size the font was built with.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GlyphMetrics {
+    /// How far to move along the line after the glyph.
+    pub advance: f32,
+    /// From the pen position on the baseline to the top left of
+    /// the glyph's image, with `y` going up.
+    pub bearing: [f32; 2],
+    /// Size of the glyph's image, including the distance field
+    /// around it. Zero for glyphs with nothing to draw, like spaces.
+    pub size: [f32; 2],
+    /// Region of the atlas holding the glyph, as `[u, v, width, height]`.
+    pub uv_rect: [f32; 4],
+}
+
+/// A font made of signed distance fields, which stay sharp at
+/// any scale.
+///
+/// Each pixel of a glyph's image holds how far it is from the glyph's
+/// outline, with 0.5 on the outline and more inside it. The distance
+/// is scaled so `sdf_radius` pixels away is 0 or 1.
+pub struct SdfFont {
+    /// One channel, holding the distances.
+    atlas: Vec<u8>,
+    atlas_width: u32,
+    atlas_height: u32,
+    glyphs: HashMap<char, GlyphMetrics>,
+    #[allow(dead_code)]
+    glyph_size: u32,
+    ascent: f32,
+    line_height: f32,
+}
+
+impl SdfFont {
+    /// Builds the glyphs of a TrueType or OpenType font, `glyph_size`
+    /// pixels to the em, with distances up to `sdf_radius` pixels.
+    pub fn from_ttf(
+        ttf_bytes: &[u8],
+        glyph_size: u32,
+        sdf_radius: u32,
+    ) -> Result<Self, SdfFontError> {
+        FontAtlasBuilder::new(ttf_bytes)
+            .glyph_size(glyph_size)
+            .sdf_radius(sdf_radius)
+            .build()
+    }
+
+    /// Uploads the atlas. The distances are linear, so unlike
+    /// images they aren't stored as sRGB.
+    pub fn create_texture(&self, device: &wgpu::Device, queue: &wgpu::Queue) -> Texture {
+        let size = wgpu::Extent3d {
+            width: self.atlas_width,
+            height: self.atlas_height,
+            depth_or_array_layers: 1,
+        };
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("SDF Font Atlas"),
+            size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::R8Unorm,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+        });
+        queue.write_texture(
+            wgpu::ImageCopyTexture {
+                texture: &texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            },
+            &self.atlas,
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: NonZeroU32::new(self.atlas_width),
+                rows_per_image: NonZeroU32::new(self.atlas_height),
+            },
+            size,
+        );
+
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        // Interpolating the distances is what keeps the edges smooth.
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("SDF Font Sampler"),
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            ..Default::default()
+        });
+
+        Texture {
+            texture,
+            view,
+            sampler,
+            size,
+        }
+    }
+
+    /// Pixels to the em the glyphs were built at.
+    #[allow(dead_code)]
+    pub fn glyph_size(&self) -> u32 {
+        self.glyph_size
+    }
+
+    /// From the top of a line to its baseline, in pixels.
+    pub fn ascent(&self) -> f32 {
+        self.ascent
+    }
+
+    /// From one baseline to the next, in pixels.
+    pub fn line_height(&self) -> f32 {
+        self.line_height
+    }
+
+    /// The glyph for `ch`, or the fallback glyph when the
+    /// font doesn't have one.
+    pub fn glyph(&self, ch: char) -> Option<&GlyphMetrics> {
+        self.glyphs.get(&ch).or_else(|| self.glyphs.get(&FALLBACK))
+    }
+}
+
+/// Rasterizes the glyphs of a font into distance fields, and
+/// packs them into an atlas.
+pub struct FontAtlasBuilder<'a> {
+    ttf_bytes: &'a [u8],
+    glyph_size: u32,
+    sdf_radius: u32,
+    max_dimension: u32,
+}
+
+impl<'a> FontAtlasBuilder<'a> {
+    pub fn new(ttf_bytes: &'a [u8]) -> Self {
+        FontAtlasBuilder {
+            ttf_bytes,
+            glyph_size: 32,
+            sdf_radius: 4,
+            max_dimension: 2048,
+        }
+    }
+
+    /// Pixels to the em.
+    pub fn glyph_size(mut self, glyph_size: u32) -> Self {
+        self.glyph_size = glyph_size;
+        self
+    }
+
+    /// Furthest distance from an outline stored, in pixels. Wider
+    /// radii leave room for outlines and glows, but cost atlas space.
+    pub fn sdf_radius(mut self, sdf_radius: u32) -> Self {
+        self.sdf_radius = sdf_radius;
+        self
+    }
+
+    /// Largest width and height the atlas may grow to.
+    #[allow(dead_code)]
+    pub fn max_dimension(mut self, max_dimension: u32) -> Self {
+        self.max_dimension = max_dimension;
+        self
+    }
+
+    pub fn build(&self) -> Result<SdfFont, SdfFontError> {
+        let face =
+            ttf_parser::Font::from_data(self.ttf_bytes, 0).ok_or(SdfFontError::InvalidFont)?;
+        let units_per_em = face.units_per_em().ok_or(SdfFontError::InvalidFont)?;
+        let scale = self.glyph_size as f32 / units_per_em as f32;
+        let radius = self.sdf_radius as f32;
+
+        let mut fields = Vec::new();
+        for ch in CHARACTERS {
+            if let Some(field) = self.rasterize(&face, ch, scale, radius) {
+                fields.push(field);
+            }
+        }
+
+        // A pixel of padding stops filtering from picking up
+        // the edges of neighbouring glyphs.
+        let sizes = fields
+            .iter()
+            .map(|field| (field.width + 1, field.height + 1))
+            .collect::<Vec<_>>();
+        let texture_atlas::Packing {
+            width,
+            height,
+            positions,
+        } = texture_atlas::pack(&sizes, self.max_dimension).ok_or(SdfFontError::TooLarge {
+            max_dimension: self.max_dimension,
+        })?;
+
+        let mut atlas = vec![0; (width * height) as usize];
+        let mut glyphs = HashMap::new();
+        for (field, (x, y)) in fields.iter().zip(positions) {
+            for row in 0..field.height {
+                let src = (row * field.width) as usize;
+                let dst = ((y + row) * width + x) as usize;
+                atlas[dst..dst + field.width as usize]
+                    .copy_from_slice(&field.pixels[src..src + field.width as usize]);
+            }
+
+            let mut metrics = field.metrics;
+            if field.width > 0 {
+                metrics.uv_rect = [
+                    x as f32 / width as f32,
+                    y as f32 / height as f32,
+                    field.width as f32 / width as f32,
+                    field.height as f32 / height as f32,
+                ];
+            }
+            glyphs.insert(field.ch, metrics);
+        }
+
+        let ascent = face.ascender() as f32 * scale;
+        let line_height = (face.height() + face.line_gap()) as f32 * scale;
+
+        Ok(SdfFont {
+            atlas,
+            atlas_width: width,
+            atlas_height: height,
+            glyphs,
+            glyph_size: self.glyph_size,
+            ascent,
+            line_height,
+        })
+    }
+
+    /// Computes the distance field of one glyph, by measuring the
+    /// distance from every pixel to every segment of its outline.
+    fn rasterize(
+        &self,
+        face: &ttf_parser::Font,
+        ch: char,
+        scale: f32,
+        radius: f32,
+    ) -> Option<GlyphField> {
+        let glyph_id = face.glyph_index(ch)?;
+        let advance = face.glyph_hor_advance(glyph_id).unwrap_or(0) as f32 * scale;
+
+        let mut outline = Outline::default();
+        let bounds = match face.outline_glyph(glyph_id, &mut outline) {
+            Some(bounds) if !outline.segments.is_empty() => bounds,
+            // Nothing to draw, but the pen still moves on.
+            _ => {
+                return Some(GlyphField {
+                    ch,
+                    width: 0,
+                    height: 0,
+                    pixels: Vec::new(),
+                    metrics: GlyphMetrics {
+                        advance,
+                        bearing: [0.0, 0.0],
+                        size: [0.0, 0.0],
+                        uv_rect: [0.0; 4],
+                    },
+                })
+            }
+        };
+
+        // The image covers the outline, and the radius around it.
+        let left = bounds.x_min as f32 * scale - radius;
+        let top = bounds.y_max as f32 * scale + radius;
+        let width = ((bounds.x_max - bounds.x_min) as f32 * scale + radius * 2.0).ceil() as u32;
+        let height = ((bounds.y_max - bounds.y_min) as f32 * scale + radius * 2.0).ceil() as u32;
+
+        // Into pixels of the image, with `y` going down.
+        let segments = outline
+            .segments
+            .iter()
+            .map(|[a, b]| {
+                [
+                    [a[0] * scale - left, top - a[1] * scale],
+                    [b[0] * scale - left, top - b[1] * scale],
+                ]
+            })
+            .collect::<Vec<_>>();
+
+        let mut pixels = Vec::with_capacity((width * height) as usize);
+        for y in 0..height {
+            for x in 0..width {
+                let point = [x as f32 + 0.5, y as f32 + 0.5];
+                let distance = segments
+                    .iter()
+                    .map(|segment| distance_to_segment(point, segment))
+                    .fold(f32::MAX, f32::min);
+                let signed = if winding_number(point, &segments) != 0 {
+                    distance
+                } else {
+                    -distance
+                };
+                let value = (0.5 + signed / (radius * 2.0)).clamp(0.0, 1.0);
+                pixels.push((value * 255.0).round() as u8);
+            }
+        }
+
+        Some(GlyphField {
+            ch,
+            width,
+            height,
+            pixels,
+            metrics: GlyphMetrics {
+                advance,
+                bearing: [left, top],
+                size: [width as f32, height as f32],
+                uv_rect: [0.0; 4],
+            },
+        })
+    }
+}
+
+/// A glyph's distance field, waiting to be packed.
+struct GlyphField {
+    ch: char,
+    width: u32,
+    height: u32,
+    pixels: Vec<u8>,
+    metrics: GlyphMetrics,
+}
+
+/// A glyph's outline flattened into straight segments,
+/// in font units with `y` going up.
+#[derive(Default)]
+struct Outline {
+    segments: Vec<[[f32; 2]; 2]>,
+    start: [f32; 2],
+    current: [f32; 2],
+}
+
+impl Outline {
+    fn push(&mut self, to: [f32; 2]) {
+        self.segments.push([self.current, to]);
+        self.current = to;
+    }
+}
+
+impl ttf_parser::OutlineBuilder for Outline {
+    fn move_to(&mut self, x: f32, y: f32) {
+        self.start = [x, y];
+        self.current = [x, y];
+    }
+
+    fn line_to(&mut self, x: f32, y: f32) {
+        self.push([x, y]);
+    }
+
+    fn quad_to(&mut self, x1: f32, y1: f32, x: f32, y: f32) {
+        let p0 = self.current;
+        for i in 1..=CURVE_SEGMENTS {
+            let t = i as f32 / CURVE_SEGMENTS as f32;
+            let u = 1.0 - t;
+            self.push([
+                u * u * p0[0] + 2.0 * u * t * x1 + t * t * x,
+                u * u * p0[1] + 2.0 * u * t * y1 + t * t * y,
+            ]);
+        }
+    }
+
+    fn curve_to(&mut self, x1: f32, y1: f32, x2: f32, y2: f32, x: f32, y: f32) {
+        let p0 = self.current;
+        for i in 1..=CURVE_SEGMENTS {
+            let t = i as f32 / CURVE_SEGMENTS as f32;
+            let u = 1.0 - t;
+            self.push([
+                u * u * u * p0[0] + 3.0 * u * u * t * x1 + 3.0 * u * t * t * x2 + t * t * t * x,
+                u * u * u * p0[1] + 3.0 * u * u * t * y1 + 3.0 * u * t * t * y2 + t * t * t * y,
+            ]);
+        }
+    }
+
+    fn close(&mut self) {
+        if self.current != self.start {
+            self.push(self.start);
+        }
+    }
+}
+
+fn distance_to_segment(point: [f32; 2], [a, b]: &[[f32; 2]; 2]) -> f32 {
+    let ab = [b[0] - a[0], b[1] - a[1]];
+    let ap = [point[0] - a[0], point[1] - a[1]];
+    let length_squared = ab[0] * ab[0] + ab[1] * ab[1];
+    let t = if length_squared > 0.0 {
+        ((ap[0] * ab[0] + ap[1] * ab[1]) / length_squared).clamp(0.0, 1.0)
+    } else {
+        0.0
+    };
+    let dx = ap[0] - ab[0] * t;
+    let dy = ap[1] - ab[1] * t;
+    (dx * dx + dy * dy).sqrt()
+}
+
+/// How many times the outline winds around `point`. It's inside
+/// the glyph when that isn't zero, whichever way the contours go.
+fn winding_number(point: [f32; 2], segments: &[[[f32; 2]; 2]]) -> i32 {
+    let mut winding = 0;
+    for [a, b] in segments {
+        // Which side of the segment the point is on.
+        let cross = (b[0] - a[0]) * (point[1] - a[1]) - (point[0] - a[0]) * (b[1] - a[1]);
+        if a[1] <= point[1] && b[1] > point[1] && cross > 0.0 {
+            winding += 1;
+        } else if b[1] <= point[1] && a[1] > point[1] && cross < 0.0 {
+            winding -= 1;
+        }
+    }
+    winding
+}
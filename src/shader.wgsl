@@ -7,9 +7,33 @@
 // =============================
 // Vertex Shader
 
+// Uniform buffers need the `block` attribute.
+[[block]]
+struct CameraUniform {
+    view_proj: mat4x4<f32>;
+    right: vec4<f32>;
+    up: vec4<f32>;
+    // Fragments behind this plane are thrown away.
+    clip_plane: vec4<f32>;
+};
+
+[[group(0), binding(0)]]
+var<uniform> camera: CameraUniform;
+
 struct VertexInput {
     [[location(0)]] position: vec3<f32>;
     [[location(1)]] color: vec3<f32>;
+    [[location(2)]] normal: vec3<f32>;
+    [[location(3)]] tex_coords: vec2<f32>;
+};
+
+// The model matrix is split over four vertex attributes,
+// because a vertex attribute can be at most a vec4.
+struct InstanceInput {
+    [[location(5)]] model_matrix_0: vec4<f32>;
+    [[location(6)]] model_matrix_1: vec4<f32>;
+    [[location(7)]] model_matrix_2: vec4<f32>;
+    [[location(8)]] model_matrix_3: vec4<f32>;
 };
 
 struct VertexOutput {
@@ -18,15 +42,38 @@ struct VertexOutput {
     // This is analogous to GLSL's gl_Position variable.
     [[builtin(position)]] clip_position: vec4<f32>;
     [[location(0)]] color: vec3<f32>;
+    [[location(1)]] world_normal: vec3<f32>;
+    [[location(2)]] world_position: vec3<f32>;
+    // Negative behind the camera's clip plane.
+    [[location(3)]] clip_distance: f32;
 };
 
 [[stage(vertex)]]
 fn main(
     model: VertexInput,
+    instance: InstanceInput,
 ) -> VertexOutput {
+    let model_matrix = mat4x4<f32>(
+        instance.model_matrix_0,
+        instance.model_matrix_1,
+        instance.model_matrix_2,
+        instance.model_matrix_3,
+    );
+
     var out: VertexOutput;
     out.color = model.color;
-    out.clip_position = vec4<f32>(model.position, 1.0);
+    // Only correct as long as the model matrix scales
+    // uniformly, otherwise the inverse transpose is needed.
+    let normal_matrix = mat3x3<f32>(
+        model_matrix.x.xyz,
+        model_matrix.y.xyz,
+        model_matrix.z.xyz,
+    );
+    out.world_normal = normal_matrix * model.normal;
+    let world_position = model_matrix * vec4<f32>(model.position, 1.0);
+    out.world_position = world_position.xyz;
+    out.clip_distance = dot(world_position, camera.clip_plane);
+    out.clip_position = camera.view_proj * world_position;
     return out;
 }
 
@@ -37,7 +84,140 @@ fn main(
 //       vec4 value returned by this function in the first
 //       color target.
 
+struct Light {
+    // Direction the light is shining in.
+    direction: vec3<f32>;
+    ambient: f32;
+    color: vec3<f32>;
+};
+
+// Must match `MAX_LIGHTS` in light.rs.
+let MAX_LIGHTS: u32 = 8u;
+
+[[block]]
+struct LightUniform {
+    lights: array<Light, MAX_LIGHTS>;
+    count: u32;
+};
+
+[[group(1), binding(0)]]
+var<uniform> light: LightUniform;
+
+[[block]]
+struct ShadowUniform {
+    light_view_proj: array<mat4x4<f32>, 4>;
+    // The camera's view, to find the fragment's cascade by its depth.
+    view: mat4x4<f32>;
+    // How far in front of the camera each cascade ends.
+    splits: vec4<f32>;
+};
+
+[[group(2), binding(0)]]
+var<uniform> shadow: ShadowUniform;
+[[group(2), binding(1)]]
+var t_shadow_0: texture_depth_2d;
+[[group(2), binding(2)]]
+var t_shadow_1: texture_depth_2d;
+[[group(2), binding(3)]]
+var t_shadow_2: texture_depth_2d;
+[[group(2), binding(4)]]
+var t_shadow_3: texture_depth_2d;
+[[group(2), binding(5)]]
+var s_shadow: sampler_comparison;
+
+[[block]]
+struct MaterialUniform {
+    color: vec4<f32>;
+    metallic: f32;
+    roughness: f32;
+    // Nonzero to dither by the alpha, with `dithered_discard`.
+    dithered: u32;
+};
+
+[[group(3), binding(0)]]
+var<uniform> material: MaterialUniform;
+
+// Must match `ShadowPass::SIZE` in shadow.rs.
+let SHADOW_MAP_SIZE: f32 = 2048.0;
+
+// Compares `depth` with the shadow map of the cascade at `index`.
+// Textures can't be indexed, so each has a branch of its own.
+fn sample_cascade(index: i32, uv: vec2<f32>, depth: f32) -> f32 {
+    if (index == 0) {
+        return textureSampleCompareLevel(t_shadow_0, s_shadow, uv, depth);
+    } elseif (index == 1) {
+        return textureSampleCompareLevel(t_shadow_1, s_shadow, uv, depth);
+    } elseif (index == 2) {
+        return textureSampleCompareLevel(t_shadow_2, s_shadow, uv, depth);
+    }
+    return textureSampleCompareLevel(t_shadow_3, s_shadow, uv, depth);
+}
+
+// How much of the first light reaches the fragment, from 0 in full
+// shadow to 1 fully lit. The cascade is picked by how far the fragment
+// is in front of the camera, and neighbouring texels of its shadow map
+// are sampled as well, and averaged to soften the shadow's edges.
+fn shadow_factor(world_position: vec3<f32>) -> f32 {
+    let depth = -(shadow.view * vec4<f32>(world_position, 1.0)).z;
+    // Past the last cascade nothing casts a shadow.
+    if (depth >= shadow.splits.w) {
+        return 1.0;
+    }
+    let cascade = i32(depth >= shadow.splits.x)
+        + i32(depth >= shadow.splits.y)
+        + i32(depth >= shadow.splits.z);
+
+    let light_space = shadow.light_view_proj[cascade] * vec4<f32>(world_position, 1.0);
+    let ndc = light_space.xyz / light_space.w;
+    // Outside of the shadow map nothing casts a shadow.
+    if (ndc.x < -1.0 || ndc.x > 1.0 || ndc.y < -1.0 || ndc.y > 1.0 || ndc.z > 1.0) {
+        return 1.0;
+    }
+
+    // Texture coordinates have Y pointing down, unlike
+    // normalized device coordinates.
+    let uv = ndc.xy * vec2<f32>(0.5, -0.5) + vec2<f32>(0.5, 0.5);
+    let texel = 1.0 / SHADOW_MAP_SIZE;
+
+    var lit = 0.0;
+    for (var y: i32 = -1; y <= 1; y = y + 1) {
+        for (var x: i32 = -1; x <= 1; x = x + 1) {
+            let offset = vec2<f32>(f32(x), f32(y)) * texel;
+            lit = lit + sample_cascade(cascade, uv + offset, ndc.z);
+        }
+    }
+    return lit / 9.0;
+}
+
 [[stage(fragment)]]
 fn main(in: VertexOutput) -> [[location(0)]] vec4<f32> {
-    return vec4<f32>(in.color, 1.0);
+    if (in.clip_distance < 0.0) {
+        discard;
+    }
+
+    // The normal is interpolated between vertices, so it
+    // has to be normalized again.
+    let normal = normalize(in.world_normal);
+
+    var lighting = vec3<f32>(0.0, 0.0, 0.0);
+    for (var i: u32 = 0u; i < light.count; i = i + 1u) {
+        let l = light.lights[i];
+        // Points from the surface towards the light.
+        let light_dir = normalize(-l.direction);
+
+        let ambient = l.color * l.ambient;
+        var diffuse = l.color * max(dot(normal, light_dir), 0.0);
+        // Only the first light casts shadows.
+        if (i == 0u) {
+            diffuse = diffuse * shadow_factor(in.world_position);
+        }
+        lighting = lighting + ambient + diffuse;
+    }
+
+    let color = vec4<f32>(lighting * in.color, 1.0) * material.color;
+    if (material.dithered != 0u) {
+        // The position is of the pixel's center, in pixels.
+        dithered_discard(color.a, vec2<u32>(in.clip_position.xy));
+    }
+    return color;
 }
@@ -0,0 +1,75 @@
+use std::{
+    sync::mpsc::{self, Receiver, TryRecvError},
+    thread,
+};
+
+/// The outcome of checking a shader. The source is
+/// passed back, so it can be built into a pipeline.
+pub type CompileResult = Result<String, String>;
+
+/// Checks WGSL shaders on a background thread, so the window keeps
+/// rendering with the previous pipeline while a reload is checked.
+///
+/// Parsing and validating the shader is where the time goes, and
+/// where the errors come from, so a shader that passes is quick for
+/// wgpu to build a pipeline from.
+pub struct ShaderCompiler {
+    pending: Option<Receiver<CompileResult>>,
+}
+
+impl ShaderCompiler {
+    pub fn new() -> Self {
+        ShaderCompiler { pending: None }
+    }
+
+    /// Starts checking `source`. A shader that's still being
+    /// checked is forgotten about.
+    pub fn submit(&mut self, source: String) {
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            // The receiver is gone when a newer shader was
+            // submitted, in which case nobody needs the result.
+            let _ = sender.send(check(source));
+        });
+        self.pending = Some(receiver);
+    }
+
+    pub fn is_compiling(&self) -> bool {
+        self.pending.is_some()
+    }
+
+    /// Returns the result once the shader has been checked.
+    pub fn poll(&mut self) -> Option<CompileResult> {
+        let result = match self.pending.as_ref()?.try_recv() {
+            Ok(result) => result,
+            Err(TryRecvError::Empty) => return None,
+            Err(TryRecvError::Disconnected) => Err("shader compiler thread panicked".to_string()),
+        };
+
+        self.pending = None;
+        Some(result)
+    }
+}
+
+impl Default for ShaderCompiler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn check(source: String) -> CompileResult {
+    let module =
+        naga::front::wgsl::parse_str(&source).map_err(|err| err.emit_to_string(&source))?;
+
+    // The device checks the shader against its own capabilities
+    // when the pipeline is built, so anything goes here.
+    let mut validator = naga::valid::Validator::new(
+        naga::valid::ValidationFlags::all(),
+        naga::valid::Capabilities::all(),
+    );
+    validator
+        .validate(&module)
+        .map_err(|err| format!("shader validation failed: {}", err))?;
+
+    Ok(source)
+}
@@ -0,0 +1,95 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::SystemTime,
+};
+
+/// Watches a shader file on disk so it can be reloaded
+/// while the program is running.
+pub struct ShaderWatcher {
+    path: PathBuf,
+    last_modified: Option<SystemTime>,
+}
+
+impl ShaderWatcher {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        let path = path.into();
+        let last_modified = modified_time(&path);
+        ShaderWatcher {
+            path,
+            last_modified,
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Checks the file's modification time, and returns
+    /// the new shader source if it has changed since the
+    /// last poll.
+    pub fn poll(&mut self) -> Option<String> {
+        let modified = modified_time(&self.path)?;
+        if self.last_modified == Some(modified) {
+            return None;
+        }
+        self.last_modified = Some(modified);
+
+        match std::fs::read_to_string(&self.path) {
+            Ok(source) => Some(source),
+            Err(err) => {
+                eprintln!("failed to read shader {}: {}", self.path.display(), err);
+                None
+            }
+        }
+    }
+}
+
+fn modified_time(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path)
+        .and_then(|meta| meta.modified())
+        .ok()
+}
+
+/// Compiles the shader source and builds a pipeline from it.
+///
+/// By default wgpu panics on validation errors. While the pipeline
+/// is being built the errors are collected instead, so a broken
+/// shader can be reported without taking down the whole program.
+///
+/// Whatever `build` returns is handed back, so the pipeline can come
+/// with the state it was built with.
+pub fn try_build_pipeline<T, F>(
+    device: &wgpu::Device,
+    label: &str,
+    source: &str,
+    build: F,
+) -> Result<T, Vec<wgpu::Error>>
+where
+    F: FnOnce(&wgpu::ShaderModule) -> T,
+{
+    let errors = Arc::new(Mutex::new(Vec::new()));
+    {
+        let errors = errors.clone();
+        device.on_uncaptured_error(move |err| errors.lock().unwrap().push(err));
+    }
+
+    let shader = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+        label: Some(label),
+        source: wgpu::ShaderSource::Wgsl(source.into()),
+    });
+    let pipeline = build(&shader);
+
+    // Restore wgpu's default behaviour of treating errors as fatal.
+    device.on_uncaptured_error(|err| {
+        log::error!("Handling wgpu errors as fatal by default");
+        panic!("wgpu error: {}\n", err);
+    });
+
+    let errors = std::mem::take(&mut *errors.lock().unwrap());
+    if errors.is_empty() {
+        Ok(pipeline)
+    } else {
+        Err(errors)
+    }
+}
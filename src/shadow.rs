@@ -0,0 +1,216 @@
+use crate::{
+    instance::InstanceData, pipeline::RenderPipelineBuilder, uniform::UniformBinding,
+    vertex::Vertex,
+};
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct ShadowUniform {
+    light_view_proj: [[f32; 4]; 4],
+}
+
+/// Renders the scene's depth from a directional light's point of view
+/// into a shadow map, which the main pass samples to find out which
+/// fragments the light can't reach.
+pub struct ShadowPass {
+    view: wgpu::TextureView,
+    uniform: UniformBinding<ShadowUniform>,
+    pipeline: wgpu::RenderPipeline,
+    #[allow(dead_code)]
+    bind_group_layout: wgpu::BindGroupLayout,
+    #[allow(dead_code)]
+    bind_group: wgpu::BindGroup,
+}
+
+impl ShadowPass {
+    pub const FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth32Float;
+    pub const SIZE: u32 = 2048;
+
+    pub fn new(device: &wgpu::Device) -> Self {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Shadow Map"),
+            size: wgpu::Extent3d {
+                width: Self::SIZE,
+                height: Self::SIZE,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: Self::FORMAT,
+            // Rendered into by the shadow pass, and sampled by the main pass.
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+        // A comparison sampler compares the depth in the shadow map with
+        // the depth we give it, instead of returning the depth. With linear
+        // filtering the results of the neighbouring texels are blended too.
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Shadow Sampler"),
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            compare: Some(wgpu::CompareFunction::LessEqual),
+            ..Default::default()
+        });
+
+        use cgmath::SquareMatrix;
+        let uniform = UniformBinding::new(
+            device,
+            "Shadow Uniform",
+            wgpu::ShaderStages::VERTEX,
+            &ShadowUniform {
+                light_view_proj: cgmath::Matrix4::identity().into(),
+            },
+        );
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Shadow Map Bind Group Layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        sample_type: wgpu::TextureSampleType::Depth,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler {
+                        filtering: true,
+                        comparison: true,
+                    },
+                    count: None,
+                },
+            ],
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Shadow Map Bind Group"),
+            layout: &bind_group_layout,
+            entries: &[
+                // The main pass reads the same matrix the shadow pass renders with.
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: uniform.buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(&view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::Sampler(&sampler),
+                },
+            ],
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Shadow Pipeline Layout"),
+            bind_group_layouts: &[&uniform.bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let shader = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+            label: Some("Shadow Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("shadow.wgsl").into()),
+        });
+        let pipeline = RenderPipelineBuilder::new()
+            .label("Shadow Pipeline")
+            .vertex_shader(&shader, "main")
+            .vertex_layouts(&[
+                Vertex::vertex_buffer_layout(),
+                InstanceData::vertex_buffer_layout(),
+            ])
+            .depth_only()
+            // Thin geometry casts shadows from both sides.
+            .cull_mode(None)
+            .depth_stencil(wgpu::DepthStencilState {
+                format: Self::FORMAT,
+                depth_write_enabled: true,
+                depth_compare: wgpu::CompareFunction::LessEqual,
+                stencil: wgpu::StencilState::default(),
+                // Pushes the depth slightly away from the light, so surfaces
+                // don't shadow themselves due to the shadow map's precision.
+                bias: wgpu::DepthBiasState {
+                    constant: 2,
+                    slope_scale: 2.0,
+                    clamp: 0.0,
+                },
+            })
+            .build(device, &pipeline_layout)
+            .expect("failed to build shadow pipeline");
+
+        ShadowPass {
+            view,
+            uniform,
+            pipeline,
+            bind_group_layout,
+            bind_group,
+        }
+    }
+
+    /// The shadow map, for passes sampling it with bind groups of their own.
+    pub fn view(&self) -> &wgpu::TextureView {
+        &self.view
+    }
+
+    /// Layout of the bind group the main pass samples the shadow map with.
+    #[allow(dead_code)]
+    pub fn bind_group_layout(&self) -> &wgpu::BindGroupLayout {
+        &self.bind_group_layout
+    }
+
+    #[allow(dead_code)]
+    pub fn bind_group(&self) -> &wgpu::BindGroup {
+        &self.bind_group
+    }
+
+    /// Sets the view projection matrix the shadow map is rendered with.
+    pub fn update_light(&self, queue: &wgpu::Queue, light_vp: [[f32; 4]; 4]) {
+        self.uniform.update(
+            queue,
+            &ShadowUniform {
+                light_view_proj: light_vp,
+            },
+        );
+    }
+
+    /// Starts rendering into the shadow map. The caller draws the
+    /// geometry that casts shadows, with the same vertex and
+    /// instance buffers as the main pass.
+    pub fn begin<'a>(&'a self, encoder: &'a mut wgpu::CommandEncoder) -> wgpu::RenderPass<'a> {
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Shadow Pass"),
+            color_attachments: &[],
+            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                view: &self.view,
+                depth_ops: Some(wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(1.0),
+                    store: true,
+                }),
+                stencil_ops: None,
+            }),
+        });
+
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, &self.uniform.bind_group, &[]);
+        render_pass
+    }
+}
@@ -0,0 +1,435 @@
+use std::f32::consts::PI;
+
+use cgmath::{InnerSpace, Matrix4, Quaternion, SquareMatrix, Vector3, VectorSpace};
+
+use crate::{
+    depth::DepthBuffer, index::IndexBuffer, msaa::MsaaConfig, pipeline::RenderPipelineBuilder,
+    transform::Transform, uniform::UniformBinding, vertex::VertexBuffer,
+};
+
+/// Most bones a skinned mesh can have, as many as fit in the
+/// shader's array of bone matrices.
+pub const MAX_BONES: usize = 64;
+
+/// A vertex moved by up to four bones of a [`Skeleton`].
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct SkinnedVertex {
+    pub position: [f32; 3],
+    pub normal: [f32; 3],
+    pub tex_coords: [f32; 2],
+    /// Indices into the skeleton's bones.
+    pub bone_indices: [u8; 4],
+    /// How much each bone moves the vertex, adding up to 1.
+    pub bone_weights: [f32; 4],
+}
+
+impl SkinnedVertex {
+    const ATTRIBUTES: [wgpu::VertexAttribute; 5] = wgpu::vertex_attr_array![
+        0 => Float32x3,
+        1 => Float32x3,
+        2 => Float32x2,
+        3 => Uint8x4,
+        4 => Float32x4,
+    ];
+
+    pub fn vertex_buffer_layout<'a>() -> wgpu::VertexBufferLayout<'a> {
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<SkinnedVertex>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes: &Self::ATTRIBUTES,
+        }
+    }
+}
+
+/// A joint of a [`Skeleton`].
+#[derive(Debug, Clone)]
+pub struct Bone {
+    pub name: String,
+    /// Index of the bone this one hangs from, which comes before it
+    /// in the skeleton. Root bones have no parent.
+    pub parent: Option<usize>,
+    /// Where the bone is relative to its parent when it isn't
+    /// animated.
+    pub rest: Transform,
+}
+
+/// A value of an [`AnimChannel`] at `time`, in seconds from the
+/// start of the clip.
+#[derive(Debug, Clone, Copy)]
+pub struct Keyframe<T> {
+    pub time: f32,
+    pub value: T,
+}
+
+/// Keyframes moving one bone, in order of time. Parts of the
+/// transform without keyframes are left at the bone's rest pose.
+#[derive(Debug, Clone, Default)]
+pub struct AnimChannel {
+    pub bone: usize,
+    pub translations: Vec<Keyframe<[f32; 3]>>,
+    /// Unit quaternions stored as `[x, y, z, w]`.
+    pub rotations: Vec<Keyframe<[f32; 4]>>,
+    pub scales: Vec<Keyframe<[f32; 3]>>,
+}
+
+/// An animation of a skeleton's bones, like a walk or a wave.
+#[derive(Debug, Clone)]
+pub struct AnimationClip {
+    #[allow(dead_code)]
+    pub name: String,
+    /// Length of the clip in seconds, after which it loops.
+    pub duration: f32,
+    pub channels: Vec<AnimChannel>,
+}
+
+/// The bones moving a skinned mesh.
+#[derive(Debug, Clone)]
+pub struct Skeleton {
+    pub bones: Vec<Bone>,
+    /// Takes each bone's vertices from model space into the bone's
+    /// own space in the rest pose.
+    pub inverse_bind_matrices: Vec<[[f32; 4]; 4]>,
+}
+
+impl Skeleton {
+    /// A skeleton bound in its rest pose.
+    ///
+    /// # Panics
+    ///
+    /// If there are more than [`MAX_BONES`] bones, or a bone comes
+    /// before its parent.
+    pub fn new(bones: Vec<Bone>) -> Self {
+        assert!(
+            bones.len() <= MAX_BONES,
+            "skeletons can't have more than {} bones",
+            MAX_BONES
+        );
+        let locals = bones
+            .iter()
+            .map(|bone| Matrix4::from(bone.rest.to_matrix()))
+            .collect::<Vec<_>>();
+        let inverse_bind_matrices = global_matrices(&bones, &locals)
+            .into_iter()
+            .map(|global| {
+                global
+                    .invert()
+                    .expect("bones' rest poses can't be scaled to nothing")
+                    .into()
+            })
+            .collect();
+
+        Skeleton {
+            bones,
+            inverse_bind_matrices,
+        }
+    }
+
+    /// The matrices taking vertices from the rest pose to where the
+    /// bones are `time` seconds into `clip`, one for each bone.
+    /// Times past the end of the clip loop around to the start.
+    pub fn compute_pose(&self, clip: &AnimationClip, time: f32) -> Vec<[[f32; 4]; 4]> {
+        let time = if clip.duration > 0.0 {
+            time.rem_euclid(clip.duration)
+        } else {
+            0.0
+        };
+
+        let mut poses = self.bones.iter().map(|bone| bone.rest).collect::<Vec<_>>();
+        for channel in &clip.channels {
+            let pose = match poses.get_mut(channel.bone) {
+                Some(pose) => pose,
+                None => continue,
+            };
+            if let Some(translation) = sample(&channel.translations, time, lerp_vector) {
+                pose.translation = translation;
+            }
+            if let Some(rotation) = sample(&channel.rotations, time, slerp) {
+                pose.rotation = rotation;
+            }
+            if let Some(scale) = sample(&channel.scales, time, lerp_vector) {
+                pose.scale = scale;
+            }
+        }
+
+        let locals = poses
+            .iter()
+            .map(|pose| Matrix4::from(pose.to_matrix()))
+            .collect::<Vec<_>>();
+        global_matrices(&self.bones, &locals)
+            .into_iter()
+            .zip(&self.inverse_bind_matrices)
+            .map(|(global, &inverse_bind)| (global * Matrix4::from(inverse_bind)).into())
+            .collect()
+    }
+}
+
+/// The bones' local matrices, each multiplied by the matrices of
+/// its parents.
+fn global_matrices(bones: &[Bone], locals: &[Matrix4<f32>]) -> Vec<Matrix4<f32>> {
+    let mut globals: Vec<Matrix4<f32>> = Vec::with_capacity(bones.len());
+    for (index, (bone, local)) in bones.iter().zip(locals).enumerate() {
+        let global = match bone.parent {
+            Some(parent) => {
+                assert!(parent < index, "bone {} comes before its parent", bone.name);
+                globals[parent] * local
+            }
+            None => *local,
+        };
+        globals.push(global);
+    }
+    globals
+}
+
+/// The value of `keyframes` at `time`, blending between the
+/// keyframes either side of it. Before the first keyframe and
+/// after the last, they're held.
+fn sample<T: Copy>(keyframes: &[Keyframe<T>], time: f32, blend: fn(T, T, f32) -> T) -> Option<T> {
+    let first = keyframes.first()?;
+    let next = keyframes.iter().position(|keyframe| keyframe.time > time);
+    let value = match next {
+        Some(0) => first.value,
+        Some(next) => {
+            let (a, b) = (&keyframes[next - 1], &keyframes[next]);
+            let t = (time - a.time) / (b.time - a.time);
+            blend(a.value, b.value, t)
+        }
+        None => keyframes[keyframes.len() - 1].value,
+    };
+    Some(value)
+}
+
+fn lerp_vector(a: [f32; 3], b: [f32; 3], t: f32) -> [f32; 3] {
+    Vector3::from(a).lerp(Vector3::from(b), t).into()
+}
+
+fn slerp(a: [f32; 4], b: [f32; 4], t: f32) -> [f32; 4] {
+    let (a, b) = (Quaternion::from(a), Quaternion::from(b));
+    // Keeps to the shorter way around.
+    let b = if a.dot(b) < 0.0 { -b } else { b };
+    a.slerp(b, t).normalize().into()
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct BoneMatrices {
+    matrices: [[[f32; 4]; 4]; MAX_BONES],
+}
+
+impl BoneMatrices {
+    /// Bones past the end of `pose` are left where they are bound.
+    fn new(pose: &[[[f32; 4]; 4]]) -> Self {
+        let mut matrices = [Matrix4::identity().into(); MAX_BONES];
+        for (matrix, bone) in matrices.iter_mut().zip(pose) {
+            *matrix = *bone;
+        }
+        BoneMatrices { matrices }
+    }
+}
+
+/// A mesh moved by a skeleton, with its bones' matrices in a
+/// uniform buffer.
+pub struct SkinnedMesh {
+    vertices: VertexBuffer,
+    indices: IndexBuffer,
+    bones: UniformBinding<BoneMatrices>,
+    skeleton: Skeleton,
+}
+
+impl SkinnedMesh {
+    /// The mesh is drawn in its rest pose until it's posed.
+    pub fn new(
+        device: &wgpu::Device,
+        vertices: &[SkinnedVertex],
+        indices: &[u16],
+        skeleton: Skeleton,
+    ) -> Self {
+        SkinnedMesh {
+            vertices: VertexBuffer::from_slice(device, vertices),
+            indices: IndexBuffer::from_slice(device, indices),
+            bones: bone_binding(device),
+            skeleton,
+        }
+    }
+
+    #[allow(dead_code)]
+    pub fn skeleton(&self) -> &Skeleton {
+        &self.skeleton
+    }
+
+    /// Poses the mesh `time` seconds into `clip`.
+    pub fn animate(&self, queue: &wgpu::Queue, clip: &AnimationClip, time: f32) {
+        let pose = self.skeleton.compute_pose(clip, time);
+        self.upload_pose(queue, &pose);
+    }
+
+    /// Uploads the bone matrices, as computed by
+    /// [`Skeleton::compute_pose`].
+    pub fn upload_pose(&self, queue: &wgpu::Queue, pose: &[[[f32; 4]; 4]]) {
+        self.bones.update(queue, &BoneMatrices::new(pose));
+    }
+}
+
+fn bone_binding(device: &wgpu::Device) -> UniformBinding<BoneMatrices> {
+    UniformBinding::new(
+        device,
+        "Bone Matrices",
+        wgpu::ShaderStages::VERTEX,
+        &BoneMatrices::new(&[]),
+    )
+}
+
+/// Draws [`SkinnedMesh`]es, blending the matrices of each vertex's
+/// bones in the vertex shader.
+pub struct SkinnedMeshRenderer {
+    pipeline: wgpu::RenderPipeline,
+    pipeline_layout: wgpu::PipelineLayout,
+    shader: wgpu::ShaderModule,
+    format: wgpu::TextureFormat,
+}
+
+impl SkinnedMeshRenderer {
+    /// The meshes are drawn with the camera's bind group at group 0,
+    /// and their bones at group 1.
+    pub fn new(
+        device: &wgpu::Device,
+        camera_layout: &wgpu::BindGroupLayout,
+        format: wgpu::TextureFormat,
+        msaa: MsaaConfig,
+    ) -> Self {
+        // Only used for its layout, which every mesh's bones share.
+        let bones = bone_binding(device);
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Skinned Mesh Pipeline Layout"),
+            bind_group_layouts: &[camera_layout, &bones.bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let shader = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+            label: Some("Skinned Mesh Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("skinned.wgsl").into()),
+        });
+        let pipeline = create_pipeline(device, &pipeline_layout, &shader, format, msaa);
+
+        SkinnedMeshRenderer {
+            pipeline,
+            pipeline_layout,
+            shader,
+            format,
+        }
+    }
+
+    pub fn draw<'a>(
+        &'a self,
+        render_pass: &mut wgpu::RenderPass<'a>,
+        camera_bind_group: &'a wgpu::BindGroup,
+        mesh: &'a SkinnedMesh,
+    ) {
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, camera_bind_group, &[]);
+        render_pass.set_bind_group(1, &mesh.bones.bind_group, &[]);
+        render_pass.set_vertex_buffer(0, mesh.vertices.slice());
+        render_pass.set_index_buffer(mesh.indices.slice(), mesh.indices.format());
+        render_pass.draw_indexed(0..mesh.indices.len(), 0, 0..1);
+    }
+
+    /// Recreates the pipeline to match the sample count of the pass.
+    pub fn set_msaa(&mut self, device: &wgpu::Device, msaa: MsaaConfig) {
+        self.pipeline = create_pipeline(
+            device,
+            &self.pipeline_layout,
+            &self.shader,
+            self.format,
+            msaa,
+        );
+    }
+}
+
+fn create_pipeline(
+    device: &wgpu::Device,
+    layout: &wgpu::PipelineLayout,
+    shader: &wgpu::ShaderModule,
+    format: wgpu::TextureFormat,
+    msaa: MsaaConfig,
+) -> wgpu::RenderPipeline {
+    RenderPipelineBuilder::new()
+        .label("Skinned Mesh Pipeline")
+        .vertex_shader(shader, "main")
+        .vertex_layouts(&[SkinnedVertex::vertex_buffer_layout()])
+        .fragment_shader(
+            shader,
+            "main",
+            &[wgpu::ColorTargetState {
+                format,
+                blend: Some(wgpu::BlendState::REPLACE),
+                write_mask: wgpu::ColorWrites::ALL,
+            }],
+        )
+        .depth_stencil(DepthBuffer::depth_stencil_state())
+        .multisample(msaa.count)
+        .build(device, layout)
+        .expect("failed to build skinned mesh pipeline")
+}
+
+/// An upright tube of `height` standing on `base`, with a chain of
+/// `bone_count` bones up the middle, for trying out animations.
+/// Each ring of vertices is shared between the two nearest bones.
+pub fn skinned_column(
+    base: [f32; 3],
+    radius: f32,
+    height: f32,
+    bone_count: usize,
+    rings: u16,
+    slices: u16,
+) -> (Vec<SkinnedVertex>, Vec<u16>, Skeleton) {
+    let bone_count = bone_count.clamp(1, MAX_BONES);
+    let bone_length = height / bone_count as f32;
+    let bones = (0..bone_count)
+        .map(|index| Bone {
+            name: format!("bone{}", index),
+            parent: index.checked_sub(1),
+            rest: Transform::from_translation(if index == 0 {
+                base
+            } else {
+                [0.0, bone_length, 0.0]
+            }),
+        })
+        .collect();
+
+    let mut vertices = Vec::new();
+    for ring in 0..=rings {
+        let v = ring as f32 / rings as f32;
+        let y = v * height;
+        // Halfway along a bone is all that bone, blending into the
+        // next towards its ends.
+        let along = (y / bone_length - 0.5).clamp(0.0, (bone_count - 1) as f32);
+        let lower = (along.floor() as usize).min(bone_count - 1);
+        let upper = (lower + 1).min(bone_count - 1);
+        let weight = along - lower as f32;
+
+        for slice in 0..=slices {
+            let u = slice as f32 / slices as f32;
+            let angle = u * 2.0 * PI;
+            let (sin, cos) = angle.sin_cos();
+            vertices.push(SkinnedVertex {
+                position: [base[0] + cos * radius, base[1] + y, base[2] + sin * radius],
+                normal: [cos, 0.0, sin],
+                tex_coords: [u, 1.0 - v],
+                bone_indices: [lower as u8, upper as u8, 0, 0],
+                bone_weights: [1.0 - weight, weight, 0.0, 0.0],
+            });
+        }
+    }
+
+    let mut indices = Vec::new();
+    let stride = slices + 1;
+    for ring in 0..rings {
+        for slice in 0..slices {
+            let a = ring * stride + slice;
+            let b = a + stride;
+            // Wound counter-clockwise from outside the tube.
+            indices.extend_from_slice(&[a, b, a + 1, a + 1, b, b + 1]);
+        }
+    }
+
+    (vertices, indices, Skeleton::new(bones))
+}
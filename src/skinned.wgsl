@@ -0,0 +1,64 @@
+// Draws meshes moved by a skeleton, blending the matrices of up to
+// four bones for each vertex.
+
+[[block]]
+struct CameraUniform {
+    view_proj: mat4x4<f32>;
+    right: vec4<f32>;
+    up: vec4<f32>;
+};
+
+[[block]]
+struct BoneMatrices {
+    matrices: array<mat4x4<f32>, 64>;
+};
+
+[[group(0), binding(0)]]
+var<uniform> camera: CameraUniform;
+
+[[group(1), binding(0)]]
+var<uniform> bones: BoneMatrices;
+
+struct VertexInput {
+    [[location(0)]] position: vec3<f32>;
+    [[location(1)]] normal: vec3<f32>;
+    [[location(2)]] tex_coords: vec2<f32>;
+    [[location(3)]] bone_indices: vec4<u32>;
+    [[location(4)]] bone_weights: vec4<f32>;
+};
+
+struct VertexOutput {
+    [[builtin(position)]] clip_position: vec4<f32>;
+    [[location(0)]] normal: vec3<f32>;
+    [[location(1)]] tex_coords: vec2<f32>;
+};
+
+// Lit from above and in front, like the rest of the scene's
+// unshadowed geometry.
+let LIGHT_DIRECTION: vec3<f32> = vec3<f32>(0.3, 1.0, 0.5);
+let AMBIENT: f32 = 0.2;
+
+[[stage(vertex)]]
+fn main(in: VertexInput) -> VertexOutput {
+    let skin = bones.matrices[in.bone_indices.x] * in.bone_weights.x
+        + bones.matrices[in.bone_indices.y] * in.bone_weights.y
+        + bones.matrices[in.bone_indices.z] * in.bone_weights.z
+        + bones.matrices[in.bone_indices.w] * in.bone_weights.w;
+
+    var out: VertexOutput;
+    out.clip_position = camera.view_proj * skin * vec4<f32>(in.position, 1.0);
+    // The bones aren't scaled unevenly, so the normals needn't be
+    // moved by the inverse transpose.
+    out.normal = (skin * vec4<f32>(in.normal, 0.0)).xyz;
+    out.tex_coords = in.tex_coords;
+    return out;
+}
+
+[[stage(fragment)]]
+fn main(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    let diffuse = max(dot(normalize(in.normal), normalize(LIGHT_DIRECTION)), 0.0);
+    let shade = AMBIENT + (1.0 - AMBIENT) * diffuse;
+    // Bands along the tube, so its bending is easy to see.
+    let band = select(0.6, 1.0, fract(in.tex_coords.y * 8.0) < 0.5);
+    return vec4<f32>(vec3<f32>(0.9, 0.5, 0.3) * band * shade, 1.0);
+}
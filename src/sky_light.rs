@@ -0,0 +1,174 @@
+use crate::{
+    light::{DirectionalLight, LightBuffer},
+    procedural_sky::ProceduralSky,
+};
+
+/// How far north of the equator the scene is, in radians, which tips
+/// the sun's path towards the south.
+const LATITUDE: f32 = 40.0 * std::f32::consts::PI / 180.0;
+
+/// The sun's elevation, in radians, over which the light fades out as
+/// it sets, centered on the horizon.
+const HORIZON_FADE: f32 = 0.1;
+
+/// A time of the day, which moves the sun across the sky.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct TimeOfDay {
+    /// Hours since midnight, from 0 up to 24.
+    pub hours: f32,
+}
+
+impl TimeOfDay {
+    pub const HOURS_PER_DAY: f32 = 24.0;
+
+    pub fn new(hours: f32) -> Self {
+        TimeOfDay {
+            hours: hours.rem_euclid(Self::HOURS_PER_DAY),
+        }
+    }
+
+    /// Moves the clock on by `delta_secs` seconds, wrapping around
+    /// past midnight.
+    pub fn advance(&mut self, delta_secs: f32) {
+        self.hours = (self.hours + delta_secs / 3600.0).rem_euclid(Self::HOURS_PER_DAY);
+    }
+
+    /// Towards the sun, in world space, with north along -Z and east
+    /// along +X.
+    ///
+    /// The sun goes around the celestial equator once a day, which is
+    /// tipped by the [`LATITUDE`], so it's highest in the south at
+    /// noon, and rises and sets due east and west.
+    fn sun_vector(&self) -> cgmath::Vector3<f32> {
+        // Zero at noon, and negative in the morning.
+        let hour_angle = (self.hours / Self::HOURS_PER_DAY - 0.5) * std::f32::consts::TAU;
+        let up = LATITUDE.cos() * hour_angle.cos();
+        let east = -hour_angle.sin();
+        let north = -LATITUDE.sin() * hour_angle.cos();
+        cgmath::vec3(east, up, -north)
+    }
+
+    /// The angle of the sun above the horizon, in radians. Negative
+    /// at night.
+    pub fn sun_elevation(&self) -> f32 {
+        self.sun_vector().y.clamp(-1.0, 1.0).asin()
+    }
+
+    /// The compass bearing of the sun, in radians clockwise from
+    /// north, so east is a quarter turn.
+    pub fn sun_azimuth(&self) -> f32 {
+        let sun = self.sun_vector();
+        sun.x.atan2(-sun.z).rem_euclid(std::f32::consts::TAU)
+    }
+
+    /// Towards the sun, from its [elevation](Self::sun_elevation) and
+    /// [azimuth](Self::sun_azimuth).
+    pub fn sun_direction(&self) -> [f32; 3] {
+        let (elevation, azimuth) = (self.sun_elevation(), self.sun_azimuth());
+        [
+            elevation.cos() * azimuth.sin(),
+            elevation.sin(),
+            -elevation.cos() * azimuth.cos(),
+        ]
+    }
+}
+
+/// Keeps the sun in the sky and the light it casts on the scene in
+/// step, as the [`TimeOfDay`] moves it.
+///
+/// The first light is the sun. It shines from where the sky draws
+/// the sun, and takes the color of the sky straight up, so it dims
+/// and goes warmer towards sunset, and fades out below the horizon.
+/// The rest of the lights are left as they are.
+pub struct DynamicSkyLight {
+    sky: ProceduralSky,
+    lights: Vec<DirectionalLight>,
+    light_buffer: LightBuffer,
+    /// Scales the sky's radiance into the sun's color.
+    intensity: f32,
+}
+
+impl DynamicSkyLight {
+    /// Takes over `sky`, and the `lights` uploaded to `light_buffer`.
+    pub fn new(
+        sky: ProceduralSky,
+        lights: Vec<DirectionalLight>,
+        light_buffer: LightBuffer,
+        intensity: f32,
+    ) -> Self {
+        DynamicSkyLight {
+            sky,
+            lights,
+            light_buffer,
+            intensity,
+        }
+    }
+
+    pub fn sky(&self) -> &ProceduralSky {
+        &self.sky
+    }
+
+    pub fn sky_mut(&mut self) -> &mut ProceduralSky {
+        &mut self.sky
+    }
+
+    pub fn lights(&self) -> &[DirectionalLight] {
+        &self.lights
+    }
+
+    /// Changes to the lights are uploaded by the next
+    /// [`upload_lights`](Self::upload_lights) or
+    /// [`update`](Self::update).
+    pub fn lights_mut(&mut self) -> &mut Vec<DirectionalLight> {
+        &mut self.lights
+    }
+
+    pub fn light_buffer(&self) -> &LightBuffer {
+        &self.light_buffer
+    }
+
+    #[allow(dead_code)]
+    pub fn intensity(&self) -> f32 {
+        self.intensity
+    }
+
+    #[allow(dead_code)]
+    pub fn set_intensity(&mut self, intensity: f32) {
+        self.intensity = intensity;
+    }
+
+    /// Uploads the lights as they are, without moving the sun.
+    pub fn upload_lights(&mut self, queue: &wgpu::Queue) {
+        self.light_buffer.update(queue, &self.lights);
+    }
+
+    /// Moves the sun to where it is at `time_of_day`, and relights the
+    /// scene with it.
+    ///
+    /// The sky's uniform and the lights are both written through the
+    /// queue, which holds the writes back until the next submission,
+    /// so the frame after sees either both of them move or neither.
+    pub fn update(&mut self, queue: &wgpu::Queue, time_of_day: &TimeOfDay) {
+        let direction = time_of_day.sun_direction();
+        self.sky.update_sun(queue, direction);
+
+        let fade = smooth_step(
+            -HORIZON_FADE * 0.5,
+            HORIZON_FADE * 0.5,
+            time_of_day.sun_elevation(),
+        );
+        let scale = self.intensity * fade;
+        let radiance = self.sky.zenith_radiance();
+        if let Some(sun) = self.lights.first_mut() {
+            sun.direction = direction.map(|d| -d);
+            sun.color = radiance.map(|c| c * scale);
+        }
+        self.light_buffer.update(queue, &self.lights);
+    }
+}
+
+/// 0 below `edge0`, 1 above `edge1`, and a smooth curve between.
+fn smooth_step(edge0: f32, edge1: f32, x: f32) -> f32 {
+    let t = ((x - edge0) / (edge1 - edge0)).clamp(0.0, 1.0);
+    t * t * (3.0 - 2.0 * t)
+}
@@ -0,0 +1,509 @@
+use std::{io::Cursor, num::NonZeroU32, path::Path};
+
+use image::{
+    error::{ParameterError, ParameterErrorKind},
+    GenericImageView, ImageError, ImageResult,
+};
+use wgpu::util::DeviceExt;
+
+use crate::{
+    camera::Camera, depth::DepthBuffer, msaa::MsaaConfig, pipeline::RenderPipelineBuilder,
+    primitives, uniform::UniformBinding,
+};
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct SkyUniform {
+    view_proj: [[f32; 4]; 4],
+    rotation: [[f32; 4]; 4],
+}
+
+impl SkyUniform {
+    fn new(camera: &Camera, yaw: f32) -> Self {
+        // Only the rotation of the view is kept, so the
+        // sky is always centered on the camera.
+        let mut view = camera.build_view_matrix();
+        view.w = cgmath::Vector4::new(0.0, 0.0, 0.0, 1.0);
+
+        SkyUniform {
+            view_proj: (camera.build_projection_matrix() * view).into(),
+            rotation: cgmath::Matrix4::from_angle_y(cgmath::Rad(yaw)).into(),
+        }
+    }
+}
+
+/// Size of the cubemap faces made from a panorama.
+const MAX_FACE_SIZE: u32 = 2048;
+
+/// Must match `workgroup_size` in the projection shader.
+const WORKGROUP_SIZE: u32 = 8;
+
+/// Draws a cubemap as the background of the scene.
+///
+/// The sky should be drawn first in the pass. It's placed on the far
+/// plane without writing depth, so everything drawn after it ends up
+/// in front.
+pub struct SkyboxPass {
+    pipeline: wgpu::RenderPipeline,
+    pipeline_layout: wgpu::PipelineLayout,
+    shader: wgpu::ShaderModule,
+    format: wgpu::TextureFormat,
+    uniform: UniformBinding<SkyUniform>,
+    cubemap_view: wgpu::TextureView,
+    cubemap_bind_group: wgpu::BindGroup,
+    vertex_buffer: wgpu::Buffer,
+    index_buffer: wgpu::Buffer,
+    index_count: u32,
+    /// Rotation around the Y axis, in radians.
+    yaw: f32,
+}
+
+impl SkyboxPass {
+    /// The cubemap faces are given in the order +X, -X, +Y, -Y, +Z, -Z,
+    /// and must all be the same size.
+    pub fn new<P: AsRef<Path>>(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        format: wgpu::TextureFormat,
+        cubemap_paths: [P; 6],
+        camera: &Camera,
+        msaa: MsaaConfig,
+    ) -> ImageResult<Self> {
+        let cubemap_view = load_cubemap(device, queue, cubemap_paths)?;
+        Ok(Self::from_cubemap(
+            device,
+            format,
+            cubemap_view,
+            camera,
+            msaa,
+        ))
+    }
+
+    /// Makes the sky from an equirectangular panorama, decoded from
+    /// the contents of a Radiance HDR file, which a compute shader
+    /// projects onto the faces of a cubemap.
+    pub fn from_equirectangular(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        format: wgpu::TextureFormat,
+        hdr_bytes: &[u8],
+        camera: &Camera,
+        msaa: MsaaConfig,
+    ) -> ImageResult<Self> {
+        let cubemap_view = project_equirectangular(device, queue, hdr_bytes)?;
+        Ok(Self::from_cubemap(
+            device,
+            format,
+            cubemap_view,
+            camera,
+            msaa,
+        ))
+    }
+
+    fn from_cubemap(
+        device: &wgpu::Device,
+        format: wgpu::TextureFormat,
+        cubemap_view: wgpu::TextureView,
+        camera: &Camera,
+        msaa: MsaaConfig,
+    ) -> Self {
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Skybox Sampler"),
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            ..Default::default()
+        });
+
+        let cubemap_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("Skybox Cubemap"),
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Texture {
+                            multisampled: false,
+                            view_dimension: wgpu::TextureViewDimension::Cube,
+                            sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Sampler {
+                            filtering: true,
+                            comparison: false,
+                        },
+                        count: None,
+                    },
+                ],
+            });
+        let cubemap_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Skybox Cubemap"),
+            layout: &cubemap_bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&cubemap_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&sampler),
+                },
+            ],
+        });
+
+        let uniform = UniformBinding::new(
+            device,
+            "Skybox Uniform",
+            wgpu::ShaderStages::VERTEX,
+            &SkyUniform::new(camera, 0.0),
+        );
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Skybox Pipeline Layout"),
+            bind_group_layouts: &[&uniform.bind_group_layout, &cubemap_bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let shader = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+            label: Some("Skybox Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("skybox.wgsl").into()),
+        });
+        let pipeline = create_pipeline(device, &pipeline_layout, &shader, format, msaa);
+
+        // Only the positions of the cube are needed. The winding is
+        // flipped, since we're looking at the cube from the inside.
+        let (vertices, indices) = primitives::cube(1.0);
+        let positions = vertices
+            .iter()
+            .map(|vertex| vertex.position)
+            .collect::<Vec<_>>();
+        let indices = indices
+            .chunks(3)
+            .flat_map(|triangle| [triangle[0], triangle[2], triangle[1]])
+            .collect::<Vec<u16>>();
+
+        let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Skybox Vertex Buffer"),
+            contents: bytemuck::cast_slice(&positions),
+            usage: wgpu::BufferUsages::VERTEX,
+        });
+        let index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Skybox Index Buffer"),
+            contents: bytemuck::cast_slice(&indices),
+            usage: wgpu::BufferUsages::INDEX,
+        });
+
+        SkyboxPass {
+            pipeline,
+            pipeline_layout,
+            shader,
+            format,
+            uniform,
+            cubemap_view,
+            cubemap_bind_group,
+            vertex_buffer,
+            index_buffer,
+            index_count: indices.len() as u32,
+            yaw: 0.0,
+        }
+    }
+
+    /// Follows the camera's rotation and projection.
+    pub fn update(&self, queue: &wgpu::Queue, camera: &Camera) {
+        self.uniform
+            .update(queue, &SkyUniform::new(camera, self.yaw));
+    }
+
+    /// A cube view of the sky, for lighting the scene with.
+    pub fn cubemap(&self) -> &wgpu::TextureView {
+        &self.cubemap_view
+    }
+
+    pub fn rotation(&self) -> f32 {
+        self.yaw
+    }
+
+    /// Turns the sky around the Y axis by `yaw` radians,
+    /// from the next update on.
+    pub fn set_rotation(&mut self, yaw: f32) {
+        self.yaw = yaw;
+    }
+
+    /// Recreates the pipeline to match the sample count of the pass.
+    pub fn set_msaa(&mut self, device: &wgpu::Device, msaa: MsaaConfig) {
+        self.pipeline = create_pipeline(
+            device,
+            &self.pipeline_layout,
+            &self.shader,
+            self.format,
+            msaa,
+        );
+    }
+
+    pub fn draw<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>) {
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, &self.uniform.bind_group, &[]);
+        render_pass.set_bind_group(1, &self.cubemap_bind_group, &[]);
+        render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        render_pass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
+        render_pass.draw_indexed(0..self.index_count, 0, 0..1);
+    }
+}
+
+fn create_pipeline(
+    device: &wgpu::Device,
+    layout: &wgpu::PipelineLayout,
+    shader: &wgpu::ShaderModule,
+    format: wgpu::TextureFormat,
+    msaa: MsaaConfig,
+) -> wgpu::RenderPipeline {
+    RenderPipelineBuilder::new()
+        .label("Skybox Pipeline")
+        .vertex_shader(shader, "main")
+        .vertex_layouts(&[wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<[f32; 3]>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes: &wgpu::vertex_attr_array![0 => Float32x3],
+        }])
+        .fragment_shader(
+            shader,
+            "main",
+            &[wgpu::ColorTargetState {
+                format,
+                blend: Some(wgpu::BlendState::REPLACE),
+                write_mask: wgpu::ColorWrites::ALL,
+            }],
+        )
+        // The sky sits exactly on the far plane, which the depth buffer
+        // is cleared to, so it has to pass when the depth is equal.
+        .depth_stencil(wgpu::DepthStencilState {
+            depth_write_enabled: false,
+            depth_compare: wgpu::CompareFunction::LessEqual,
+            ..DepthBuffer::depth_stencil_state()
+        })
+        .multisample(msaa.count)
+        .build(device, layout)
+        .expect("failed to build skybox pipeline")
+}
+
+/// Loads the six faces into the layers of a texture, and returns a cube view of it.
+fn load_cubemap<P: AsRef<Path>>(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    paths: [P; 6],
+) -> ImageResult<wgpu::TextureView> {
+    let mut faces = Vec::with_capacity(6);
+    for path in paths.iter() {
+        faces.push(image::open(path)?);
+    }
+
+    let (width, height) = faces[0].dimensions();
+    if faces
+        .iter()
+        .any(|face| face.dimensions() != (width, height))
+    {
+        return Err(ImageError::Parameter(ParameterError::from_kind(
+            ParameterErrorKind::DimensionMismatch,
+        )));
+    }
+
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("Skybox Cubemap"),
+        // A cubemap is stored as a 2D texture with a layer per face.
+        size: wgpu::Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 6,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: wgpu::TextureFormat::Rgba8UnormSrgb,
+        usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+    });
+
+    for (layer, face) in faces.iter().enumerate() {
+        queue.write_texture(
+            wgpu::ImageCopyTexture {
+                texture: &texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d {
+                    x: 0,
+                    y: 0,
+                    z: layer as u32,
+                },
+                aspect: wgpu::TextureAspect::All,
+            },
+            &face.to_rgba8(),
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: NonZeroU32::new(4 * width),
+                rows_per_image: NonZeroU32::new(height),
+            },
+            wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+        );
+    }
+
+    Ok(texture.create_view(&wgpu::TextureViewDescriptor {
+        label: Some("Skybox Cubemap View"),
+        dimension: Some(wgpu::TextureViewDimension::Cube),
+        ..Default::default()
+    }))
+}
+
+/// Decodes a Radiance HDR panorama, and projects it onto
+/// the layers of a cubemap with a compute shader.
+fn project_equirectangular(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    hdr_bytes: &[u8],
+) -> ImageResult<wgpu::TextureView> {
+    let decoder = image::codecs::hdr::HdrDecoder::new(Cursor::new(hdr_bytes))?;
+    let metadata = decoder.metadata();
+    let (width, height) = (metadata.width, metadata.height);
+    let pixels = decoder
+        .read_image_hdr()?
+        .into_iter()
+        .flat_map(|pixel| [pixel[0], pixel[1], pixel[2], 1.0])
+        .collect::<Vec<f32>>();
+
+    let panorama_size = wgpu::Extent3d {
+        width,
+        height,
+        depth_or_array_layers: 1,
+    };
+    let panorama = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("Skybox Panorama"),
+        size: panorama_size,
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: wgpu::TextureFormat::Rgba32Float,
+        usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+    });
+    queue.write_texture(
+        wgpu::ImageCopyTexture {
+            texture: &panorama,
+            mip_level: 0,
+            origin: wgpu::Origin3d::ZERO,
+            aspect: wgpu::TextureAspect::All,
+        },
+        bytemuck::cast_slice(&pixels),
+        wgpu::ImageDataLayout {
+            offset: 0,
+            bytes_per_row: NonZeroU32::new(16 * width),
+            rows_per_image: NonZeroU32::new(height),
+        },
+        panorama_size,
+    );
+    let panorama_view = panorama.create_view(&wgpu::TextureViewDescriptor::default());
+
+    // A quarter of the panorama's width goes around each side face.
+    let face_size = (width / 4).clamp(1, MAX_FACE_SIZE);
+    let cubemap = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("Skybox Cubemap"),
+        size: wgpu::Extent3d {
+            width: face_size,
+            height: face_size,
+            depth_or_array_layers: 6,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        // Keeps the range of the HDR image, and can be written
+        // by compute shaders, unlike an sRGB format.
+        format: wgpu::TextureFormat::Rgba16Float,
+        usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::STORAGE_BINDING,
+    });
+    let faces_view = cubemap.create_view(&wgpu::TextureViewDescriptor {
+        label: Some("Skybox Cubemap Faces"),
+        dimension: Some(wgpu::TextureViewDimension::D2Array),
+        ..Default::default()
+    });
+
+    let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+        label: Some("Equirectangular Projection"),
+        entries: &[
+            wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::Texture {
+                    multisampled: false,
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                    sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 1,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::StorageTexture {
+                    access: wgpu::StorageTextureAccess::WriteOnly,
+                    format: wgpu::TextureFormat::Rgba16Float,
+                    view_dimension: wgpu::TextureViewDimension::D2Array,
+                },
+                count: None,
+            },
+        ],
+    });
+    let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some("Equirectangular Projection"),
+        layout: &bind_group_layout,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(&panorama_view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: wgpu::BindingResource::TextureView(&faces_view),
+            },
+        ],
+    });
+
+    let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+        label: Some("Equirectangular Projection"),
+        bind_group_layouts: &[&bind_group_layout],
+        push_constant_ranges: &[],
+    });
+    let shader = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+        label: Some("Equirectangular Projection Shader"),
+        source: wgpu::ShaderSource::Wgsl(include_str!("equirect_to_cube.wgsl").into()),
+    });
+    let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+        label: Some("Equirectangular Projection"),
+        layout: Some(&layout),
+        module: &shader,
+        entry_point: "main",
+    });
+
+    let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+        label: Some("Equirectangular Projection Encoder"),
+    });
+    {
+        let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("Equirectangular Projection"),
+        });
+        compute_pass.set_pipeline(&pipeline);
+        compute_pass.set_bind_group(0, &bind_group, &[]);
+        let groups = face_size.div_ceil(WORKGROUP_SIZE);
+        compute_pass.dispatch(groups, groups, 6);
+    }
+    queue.submit(std::iter::once(encoder.finish()));
+
+    Ok(cubemap.create_view(&wgpu::TextureViewDescriptor {
+        label: Some("Skybox Cubemap View"),
+        dimension: Some(wgpu::TextureViewDimension::Cube),
+        ..Default::default()
+    }))
+}
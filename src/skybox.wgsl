@@ -0,0 +1,41 @@
+// Draws a cubemap behind everything else in the scene.
+
+[[block]]
+struct SkyUniform {
+    // View projection with the camera's translation removed,
+    // so the sky stays put no matter where the camera moves.
+    view_proj: mat4x4<f32>;
+    // Turns the sky around the camera.
+    rotation: mat4x4<f32>;
+};
+
+[[group(0), binding(0)]]
+var<uniform> sky: SkyUniform;
+
+struct VertexOutput {
+    [[builtin(position)]] clip_position: vec4<f32>;
+    // The cube is centered on the camera, so the position of
+    // each vertex doubles as the direction to sample in.
+    [[location(0)]] direction: vec3<f32>;
+};
+
+[[stage(vertex)]]
+fn main([[location(0)]] position: vec3<f32>) -> VertexOutput {
+    var out: VertexOutput;
+    out.direction = (sky.rotation * vec4<f32>(position, 0.0)).xyz;
+    let clip = sky.view_proj * vec4<f32>(position, 1.0);
+    // Setting z to w puts the sky on the far plane after the
+    // perspective divide, so it ends up behind everything.
+    out.clip_position = clip.xyww;
+    return out;
+}
+
+[[group(1), binding(0)]]
+var t_cubemap: texture_cube<f32>;
+[[group(1), binding(1)]]
+var s_cubemap: sampler;
+
+[[stage(fragment)]]
+fn main(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    return textureSample(t_cubemap, s_cubemap, in.direction);
+}
@@ -0,0 +1,179 @@
+use crate::camera::Camera;
+
+/// Where the camera is and what it looks at, `time` seconds
+/// into a [`SplinePath`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CameraKeyframe {
+    pub position: [f32; 3],
+    pub target: [f32; 3],
+    pub time: f32,
+}
+
+/// A smooth path for the camera through its control points, in the
+/// order of their times.
+///
+/// The path goes through every control point, curving between them
+/// along a Catmull-Rom spline. At the ends, where a point has no
+/// neighbour on one side, it stands in as its own neighbour.
+#[derive(Debug, Clone, Default)]
+pub struct SplinePath {
+    pub control_points: Vec<CameraKeyframe>,
+}
+
+impl SplinePath {
+    pub fn new(control_points: Vec<CameraKeyframe>) -> Self {
+        Self { control_points }
+    }
+
+    /// Time of the first control point.
+    pub fn start_time(&self) -> f32 {
+        self.control_points.first().map_or(0.0, |point| point.time)
+    }
+
+    /// Time from the first control point to the last.
+    pub fn duration(&self) -> f32 {
+        self.control_points
+            .last()
+            .map_or(0.0, |point| point.time - self.start_time())
+    }
+
+    /// The camera at time `t`, held at the first or last control
+    /// point outside of their times.
+    ///
+    /// # Panics
+    ///
+    /// If the path has no control points.
+    pub fn evaluate(&self, t: f32) -> CameraKeyframe {
+        let points = &self.control_points;
+        assert!(!points.is_empty(), "spline paths need a control point");
+        let last = points.len() - 1;
+
+        // The segment starts at the last point that isn't after `t`.
+        let i = points
+            .partition_point(|point| point.time <= t)
+            .saturating_sub(1)
+            .min(last.saturating_sub(1));
+        let p0 = &points[i.saturating_sub(1)];
+        let p1 = &points[i];
+        let p2 = &points[(i + 1).min(last)];
+        let p3 = &points[(i + 2).min(last)];
+
+        let span = p2.time - p1.time;
+        let u = if span > 0.0 {
+            ((t - p1.time) / span).clamp(0.0, 1.0)
+        } else {
+            0.0
+        };
+
+        CameraKeyframe {
+            position: catmull_rom(p0.position, p1.position, p2.position, p3.position, u),
+            target: catmull_rom(p0.target, p1.target, p2.target, p3.target, u),
+            time: t,
+        }
+    }
+}
+
+/// The point `u` of the way from `p1` to `p2`, on the uniform
+/// Catmull-Rom spline through all four points.
+fn catmull_rom(p0: [f32; 3], p1: [f32; 3], p2: [f32; 3], p3: [f32; 3], u: f32) -> [f32; 3] {
+    let u2 = u * u;
+    let u3 = u2 * u;
+    let mut point = [0.0; 3];
+    for axis in 0..3 {
+        let (a, b, c, d) = (p0[axis], p1[axis], p2[axis], p3[axis]);
+        point[axis] = 0.5
+            * (2.0 * b
+                + (c - a) * u
+                + (2.0 * a - 5.0 * b + 4.0 * c - d) * u2
+                + (3.0 * b - a - 3.0 * c + d) * u3);
+    }
+    point
+}
+
+/// What a [`SplineCamera`] does once it reaches the end of its path.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum LoopMode {
+    /// Stops at the end.
+    #[default]
+    Once,
+    /// Jumps back to the start.
+    Loop,
+    /// Turns around and goes back, and forth again when it
+    /// reaches the start.
+    #[allow(dead_code)]
+    PingPong,
+}
+
+/// Flies the camera along a [`SplinePath`], for fly-throughs that
+/// don't need anyone at the controls.
+pub struct SplineCamera {
+    pub path: SplinePath,
+    pub loop_mode: LoopMode,
+    /// Seconds played since the start, which keeps counting past
+    /// the end of the path.
+    elapsed: f32,
+}
+
+impl SplineCamera {
+    pub fn new(path: SplinePath) -> Self {
+        Self {
+            path,
+            loop_mode: LoopMode::default(),
+            elapsed: 0.0,
+        }
+    }
+
+    /// Goes back to the start of the path.
+    pub fn restart(&mut self) {
+        self.elapsed = 0.0;
+    }
+
+    /// Whether the camera has stopped at the end of the path.
+    /// Only ever true in [`LoopMode::Once`].
+    #[allow(dead_code)]
+    pub fn is_finished(&self) -> bool {
+        self.loop_mode == LoopMode::Once && self.elapsed >= self.path.duration()
+    }
+
+    /// Time on the path the camera is at, the way the loop mode
+    /// plays it.
+    pub fn path_time(&self) -> f32 {
+        let duration = self.path.duration();
+        if duration <= 0.0 {
+            return self.path.start_time();
+        }
+
+        let offset = match self.loop_mode {
+            LoopMode::Once => self.elapsed.min(duration),
+            LoopMode::Loop => self.elapsed.rem_euclid(duration),
+            LoopMode::PingPong => {
+                let phase = self.elapsed.rem_euclid(2.0 * duration);
+                if phase > duration {
+                    2.0 * duration - phase
+                } else {
+                    phase
+                }
+            }
+        };
+        self.path.start_time() + offset
+    }
+
+    /// Moves along the path by `dt` seconds, and puts the camera
+    /// where the path is. Does nothing when the path is empty.
+    pub fn update_camera(&mut self, camera: &mut Camera, dt: f32) {
+        if self.path.control_points.is_empty() {
+            return;
+        }
+
+        self.elapsed += dt;
+        // Once the camera has stopped, the time doesn't need to keep
+        // counting.
+        if self.loop_mode == LoopMode::Once {
+            self.elapsed = self.elapsed.min(self.path.duration());
+        }
+
+        let keyframe = self.path.evaluate(self.path_time());
+        camera.eye = keyframe.position.into();
+        camera.target = keyframe.target.into();
+    }
+}
@@ -0,0 +1,335 @@
+use wgpu::util::DeviceExt;
+
+use crate::{
+    camera::OPENGL_TO_WGPU_MATRIX, pipeline::RenderPipelineBuilder,
+    render_pass_guard::RenderPassBuilder, texture::Texture, uniform::UniformBinding,
+};
+
+/// Most sprites drawn with a single upload of the vertex buffer.
+/// Batches with more sprites are split up and drawn in turn.
+pub const MAX_SPRITES: usize = 10_000;
+
+/// A rectangle to draw, in pixels from the top left of the screen.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SpriteInstance {
+    /// Top left corner of the sprite.
+    pub position: [f32; 2],
+    pub size: [f32; 2],
+    /// Region of the texture to draw, as `[u, v, width, height]`
+    /// in texture coordinates.
+    pub uv_rect: [f32; 4],
+    /// Multiplied with the texture's color.
+    pub color: [f32; 4],
+}
+
+impl SpriteInstance {
+    /// Sprite showing all of its texture, untinted.
+    pub fn new(position: [f32; 2], size: [f32; 2]) -> Self {
+        SpriteInstance {
+            position,
+            size,
+            uv_rect: [0.0, 0.0, 1.0, 1.0],
+            color: [1.0, 1.0, 1.0, 1.0],
+        }
+    }
+
+    fn vertices(&self) -> [SpriteVertex; 4] {
+        let [x, y] = self.position;
+        let [w, h] = self.size;
+        let [u, v, uw, vh] = self.uv_rect;
+        let color = self.color;
+
+        // Counter-clockwise once the projection flips the Y axis.
+        [
+            SpriteVertex {
+                position: [x, y],
+                tex_coords: [u, v],
+                color,
+            },
+            SpriteVertex {
+                position: [x, y + h],
+                tex_coords: [u, v + vh],
+                color,
+            },
+            SpriteVertex {
+                position: [x + w, y + h],
+                tex_coords: [u + uw, v + vh],
+                color,
+            },
+            SpriteVertex {
+                position: [x + w, y],
+                tex_coords: [u + uw, v],
+                color,
+            },
+        ]
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct SpriteVertex {
+    position: [f32; 2],
+    tex_coords: [f32; 2],
+    color: [f32; 4],
+}
+
+impl SpriteVertex {
+    const ATTRIBUTES: [wgpu::VertexAttribute; 3] =
+        wgpu::vertex_attr_array![0 => Float32x2, 1 => Float32x2, 2 => Float32x4];
+
+    fn vertex_buffer_layout<'a>() -> wgpu::VertexBufferLayout<'a> {
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<SpriteVertex>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes: &Self::ATTRIBUTES,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct ProjectionUniform {
+    proj: [[f32; 4]; 4],
+}
+
+impl ProjectionUniform {
+    /// Maps pixel coordinates, with the origin at the top left
+    /// and Y pointing down, to clip space.
+    fn new(width: u32, height: u32) -> Self {
+        let proj = cgmath::ortho(0.0, width as f32, height as f32, 0.0, -1.0, 1.0);
+        ProjectionUniform {
+            proj: (OPENGL_TO_WGPU_MATRIX * proj).into(),
+        }
+    }
+}
+
+/// Handle to a texture registered with a [`SpriteRenderer`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SpriteTexture(usize);
+
+/// A region of a texture registered with a [`SpriteRenderer`], such
+/// as one of the images of a [`TextureAtlas`](crate::texture_atlas::TextureAtlas).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SpriteImage {
+    pub texture: SpriteTexture,
+    /// As `[u, v, width, height]`, like [`SpriteInstance::uv_rect`].
+    pub uv_rect: [f32; 4],
+}
+
+/// Draws batches of 2D sprites on top of the frame.
+///
+/// Sprites are collected with [`SpriteRenderer::draw`] over the course
+/// of a frame, and drawn by [`SpriteRenderer::flush`] with one draw call
+/// per texture. Sprites using the same texture are drawn in the order
+/// they were submitted.
+pub struct SpriteRenderer {
+    pipeline: wgpu::RenderPipeline,
+    projection: UniformBinding<ProjectionUniform>,
+    texture_bind_group_layout: wgpu::BindGroupLayout,
+    texture_bind_groups: Vec<wgpu::BindGroup>,
+    vertex_buffer: wgpu::Buffer,
+    index_buffer: wgpu::Buffer,
+    /// Sprites waiting to be drawn, one list per texture.
+    batches: Vec<Vec<SpriteVertex>>,
+    vertices: Vec<SpriteVertex>,
+}
+
+impl SpriteRenderer {
+    pub fn new(device: &wgpu::Device, config: &wgpu::SurfaceConfiguration) -> Self {
+        Self::with_shader(device, config, include_str!("sprite.wgsl"))
+    }
+
+    /// Draws sprites with a shader of their own, which has the
+    /// same vertex inputs and bindings as `sprite.wgsl`.
+    pub fn with_shader(
+        device: &wgpu::Device,
+        config: &wgpu::SurfaceConfiguration,
+        source: &str,
+    ) -> Self {
+        let projection = UniformBinding::new(
+            device,
+            "Sprite Projection",
+            wgpu::ShaderStages::VERTEX,
+            &ProjectionUniform::new(config.width, config.height),
+        );
+
+        let texture_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("Sprite Texture Bind Group Layout"),
+                entries: &Texture::bind_group_layout_entry(0),
+            });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Sprite Pipeline Layout"),
+            bind_group_layouts: &[&projection.bind_group_layout, &texture_bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let shader = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+            label: Some("Sprite Shader"),
+            source: wgpu::ShaderSource::Wgsl(source.into()),
+        });
+        let pipeline = RenderPipelineBuilder::new()
+            .label("Sprite Pipeline")
+            .vertex_shader(&shader, "main")
+            .vertex_layouts(&[SpriteVertex::vertex_buffer_layout()])
+            .fragment_shader(
+                &shader,
+                "main",
+                &[wgpu::ColorTargetState {
+                    format: config.format,
+                    // Sprites are usually partly transparent.
+                    blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                    write_mask: wgpu::ColorWrites::ALL,
+                }],
+            )
+            .build(device, &pipeline_layout)
+            .expect("failed to build sprite pipeline");
+
+        let vertex_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Sprite Vertex Buffer"),
+            size: (MAX_SPRITES * 4 * std::mem::size_of::<SpriteVertex>()) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        // Every sprite is a quad, so the indices never change.
+        // A full batch has more than 65536 vertices, so 32-bit
+        // indices are needed.
+        let indices = (0..MAX_SPRITES as u32)
+            .flat_map(|sprite| {
+                let base = sprite * 4;
+                [base, base + 1, base + 2, base, base + 2, base + 3]
+            })
+            .collect::<Vec<u32>>();
+        let index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Sprite Index Buffer"),
+            contents: bytemuck::cast_slice(&indices),
+            usage: wgpu::BufferUsages::INDEX,
+        });
+
+        SpriteRenderer {
+            pipeline,
+            projection,
+            texture_bind_group_layout,
+            texture_bind_groups: Vec::new(),
+            vertex_buffer,
+            index_buffer,
+            batches: Vec::new(),
+            vertices: Vec::new(),
+        }
+    }
+
+    /// Makes a texture available for drawing sprites with.
+    pub fn add_texture(&mut self, device: &wgpu::Device, texture: &Texture) -> SpriteTexture {
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Sprite Texture Bind Group"),
+            layout: &self.texture_bind_group_layout,
+            entries: &texture.bind_group_entries(0),
+        });
+
+        self.texture_bind_groups.push(bind_group);
+        self.batches.push(Vec::new());
+        SpriteTexture(self.texture_bind_groups.len() - 1)
+    }
+
+    /// Queues a sprite to be drawn on the next flush.
+    pub fn draw(&mut self, texture: SpriteTexture, sprite: SpriteInstance) {
+        self.batches[texture.0].extend_from_slice(&sprite.vertices());
+    }
+
+    /// Queues a sprite showing `image`, in place of the region of
+    /// the texture the sprite had.
+    pub fn draw_image(&mut self, image: SpriteImage, mut sprite: SpriteInstance) {
+        sprite.uv_rect = image.uv_rect;
+        self.draw(image.texture, sprite);
+    }
+
+    /// Keeps the projection matching the surface size.
+    pub fn resize(&self, queue: &wgpu::Queue, width: u32, height: u32) {
+        self.projection
+            .update(queue, &ProjectionUniform::new(width, height));
+    }
+
+    /// Draws the queued sprites into `view`, and clears the queue.
+    ///
+    /// Sprites are packed into the vertex buffer until it's full,
+    /// at which point the packed sprites are drawn and submitted
+    /// before carrying on with the rest.
+    pub fn flush(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, view: &wgpu::TextureView) {
+        let max_vertices = MAX_SPRITES * 4;
+        // Ranges of the vertex buffer to draw with each texture.
+        let mut draws = Vec::new();
+
+        for texture in 0..self.batches.len() {
+            let mut batch = std::mem::take(&mut self.batches[texture]);
+            let mut remaining = &batch[..];
+
+            while !remaining.is_empty() {
+                if self.vertices.len() == max_vertices {
+                    self.submit(device, queue, view, &draws);
+                    draws.clear();
+                }
+
+                let count = remaining.len().min(max_vertices - self.vertices.len());
+                let start = self.vertices.len() as u32;
+                self.vertices.extend_from_slice(&remaining[..count]);
+                draws.push((texture, start..self.vertices.len() as u32));
+                remaining = &remaining[count..];
+            }
+
+            // Give the allocation back, so it's reused next frame.
+            batch.clear();
+            self.batches[texture] = batch;
+        }
+
+        if !draws.is_empty() {
+            self.submit(device, queue, view, &draws);
+        }
+    }
+
+    /// Uploads the packed vertices and draws them.
+    fn submit(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        view: &wgpu::TextureView,
+        draws: &[(usize, std::ops::Range<u32>)],
+    ) {
+        queue.write_buffer(&self.vertex_buffer, 0, bytemuck::cast_slice(&self.vertices));
+        self.vertices.clear();
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Sprite Encoder"),
+        });
+        let mut render_pass = RenderPassBuilder::begin_render_pass(
+            &mut encoder,
+            &wgpu::RenderPassDescriptor {
+                label: Some("Sprite Pass"),
+                color_attachments: &[wgpu::RenderPassColorAttachment {
+                    view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        // Sprites are drawn over what's already there.
+                        load: wgpu::LoadOp::Load,
+                        store: true,
+                    },
+                }],
+                depth_stencil_attachment: None,
+            },
+        );
+
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, &self.projection.bind_group, &[]);
+        render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        render_pass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+
+        for (texture, vertices) in draws {
+            // Each sprite's four vertices use six indices.
+            let indices = vertices.start / 4 * 6..vertices.end / 4 * 6;
+            render_pass.set_bind_group(1, &self.texture_bind_groups[*texture], &[]);
+            render_pass.draw_indexed(indices, 0, 0..1);
+        }
+        render_pass.end();
+        queue.submit(std::iter::once(encoder.finish()));
+    }
+}
@@ -0,0 +1,41 @@
+// Textured, tinted quads in screen space.
+
+[[block]]
+struct ProjectionUniform {
+    // Orthographic projection from pixels to clip space.
+    proj: mat4x4<f32>;
+};
+
+[[group(0), binding(0)]]
+var<uniform> projection: ProjectionUniform;
+
+struct VertexInput {
+    [[location(0)]] position: vec2<f32>;
+    [[location(1)]] tex_coords: vec2<f32>;
+    [[location(2)]] color: vec4<f32>;
+};
+
+struct VertexOutput {
+    [[builtin(position)]] clip_position: vec4<f32>;
+    [[location(0)]] tex_coords: vec2<f32>;
+    [[location(1)]] color: vec4<f32>;
+};
+
+[[stage(vertex)]]
+fn main(in: VertexInput) -> VertexOutput {
+    var out: VertexOutput;
+    out.clip_position = projection.proj * vec4<f32>(in.position, 0.0, 1.0);
+    out.tex_coords = in.tex_coords;
+    out.color = in.color;
+    return out;
+}
+
+[[group(1), binding(0)]]
+var t_sprite: texture_2d<f32>;
+[[group(1), binding(1)]]
+var s_sprite: sampler;
+
+[[stage(fragment)]]
+fn main(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    return textureSample(t_sprite, s_sprite, in.tex_coords) * in.color;
+}
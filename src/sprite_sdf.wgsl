@@ -0,0 +1,55 @@
+// Sprites whose texture holds a signed distance field, such as
+// the glyphs of an `SdfFont`. The same as `sprite.wgsl`, apart
+// from how the texture is read.
+
+[[block]]
+struct ProjectionUniform {
+    // Orthographic projection from pixels to clip space.
+    proj: mat4x4<f32>;
+};
+
+[[group(0), binding(0)]]
+var<uniform> projection: ProjectionUniform;
+
+struct VertexInput {
+    [[location(0)]] position: vec2<f32>;
+    [[location(1)]] tex_coords: vec2<f32>;
+    [[location(2)]] color: vec4<f32>;
+};
+
+struct VertexOutput {
+    [[builtin(position)]] clip_position: vec4<f32>;
+    [[location(0)]] tex_coords: vec2<f32>;
+    [[location(1)]] color: vec4<f32>;
+};
+
+[[stage(vertex)]]
+fn main(in: VertexInput) -> VertexOutput {
+    var out: VertexOutput;
+    out.clip_position = projection.proj * vec4<f32>(in.position, 0.0, 1.0);
+    out.tex_coords = in.tex_coords;
+    out.color = in.color;
+    return out;
+}
+
+[[group(1), binding(0)]]
+var t_sprite: texture_2d<f32>;
+[[group(1), binding(1)]]
+var s_sprite: sampler;
+
+// WGSL's smoothstep, which naga doesn't have yet.
+fn smooth_step(edge0: f32, edge1: f32, x: f32) -> f32 {
+    let t = clamp((x - edge0) / (edge1 - edge0), 0.0, 1.0);
+    return t * t * (3.0 - 2.0 * t);
+}
+
+[[stage(fragment)]]
+fn main(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    // 0.5 is on the outline. How much the distance changes across
+    // a pixel depends on how far the glyph is scaled, so the edge
+    // is blurred over about a pixel at any size.
+    let distance = textureSample(t_sprite, s_sprite, in.tex_coords).r;
+    let edge = max(fwidth(distance) * 0.7, 0.001);
+    let alpha = smooth_step(0.5 - edge, 0.5 + edge, distance);
+    return vec4<f32>(in.color.rgb, in.color.a * alpha);
+}
@@ -0,0 +1,552 @@
+use std::num::NonZeroU32;
+
+use cgmath::{InnerSpace, Matrix4, SquareMatrix, Vector3};
+
+use crate::{
+    camera::Camera,
+    depth::DepthBuffer,
+    fullscreen_triangle::FullscreenTriangle,
+    hdr_render_target::{HdrRenderTarget, HDR_FORMAT},
+    particle_system::XorShift,
+    pipeline::RenderPipelineBuilder,
+    render_pass_guard::RenderPassBuilder,
+    render_target::RenderTarget,
+    uniform::UniformBinding,
+};
+
+/// Most samples the kernel can have, which is how many the uniform
+/// has room for.
+pub const MAX_KERNEL_SIZE: u32 = 64;
+
+/// Format of the ambient occlusion, which is a single brightness for
+/// each pixel.
+const AO_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::R8Unorm;
+
+/// Width and height of the noise tiled over the screen to turn the
+/// kernel around, in pixels.
+const NOISE_SIZE: u32 = 4;
+
+/// Seeds the kernel and noise, so they're the same every run.
+const SEED: u32 = 0x9e37_79b9;
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct SsaoUniform {
+    projection: [[f32; 4]; 4],
+    inv_projection: [[f32; 4]; 4],
+    kernel: [[f32; 4]; MAX_KERNEL_SIZE as usize],
+    kernel_size: u32,
+    radius: f32,
+    bias: f32,
+    _padding: f32,
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct BlurSettings {
+    direction: [i32; 2],
+    _padding: [i32; 2],
+}
+
+/// Screen-space ambient occlusion, which darkens creases and corners
+/// the ambient light wouldn't reach into.
+///
+/// Each pixel takes `kernel_size` samples in the hemisphere around its
+/// normal, from [`NormalReconstruct`](crate::normal_reconstruct::NormalReconstruct),
+/// out to `radius` in world units. Samples that end up behind what's
+/// in the depth buffer are occluded, so the more of them there are,
+/// the darker the pixel. The kernel is turned a different way for
+/// each pixel in a 4x4 tile of noise, which the [`SsaoBlurPass`]
+/// then smooths out, and the blurred occlusion is multiplied into
+/// the scene by [`SsaoPass::apply`].
+pub struct SsaoPass {
+    uniform: SsaoUniform,
+    uniform_binding: UniformBinding<SsaoUniform>,
+    noise_view: wgpu::TextureView,
+    /// The occlusion before it's blurred.
+    ao_rt: RenderTarget,
+    texture_layouts: [wgpu::BindGroupLayout; 2],
+    /// For a single sampled depth buffer, then a multisampled one.
+    triangles: [FullscreenTriangle; 2],
+    blur: SsaoBlurPass,
+}
+
+impl SsaoPass {
+    /// `bias` is how far in front of a sample the scene has to be to
+    /// occlude it, which keeps surfaces from occluding themselves.
+    pub fn new(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        config: &wgpu::SurfaceConfiguration,
+        kernel_size: u32,
+        radius: f32,
+        bias: f32,
+    ) -> Self {
+        let kernel_size = if kernel_size > MAX_KERNEL_SIZE {
+            log::warn!(
+                "SSAO kernel can have at most {} samples, not {}",
+                MAX_KERNEL_SIZE,
+                kernel_size
+            );
+            MAX_KERNEL_SIZE
+        } else {
+            kernel_size.max(1)
+        };
+
+        let mut rng = XorShift::new(SEED);
+        let uniform = SsaoUniform {
+            projection: Matrix4::identity().into(),
+            inv_projection: Matrix4::identity().into(),
+            kernel: hemisphere_kernel(&mut rng, kernel_size),
+            kernel_size,
+            radius,
+            bias,
+            _padding: 0.0,
+        };
+        let uniform_binding = UniformBinding::new(
+            device,
+            "SSAO Uniform",
+            wgpu::ShaderStages::FRAGMENT,
+            &uniform,
+        );
+        let noise_view = create_noise_texture(device, queue, &mut rng);
+        let ao_rt = RenderTarget::new(device, config.width, config.height, &[AO_FORMAT]);
+
+        let texture_entry = |binding, multisampled, sample_type| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                multisampled,
+                view_dimension: wgpu::TextureViewDimension::D2,
+                sample_type,
+            },
+            count: None,
+        };
+        let float = wgpu::TextureSampleType::Float { filterable: true };
+        let texture_layouts = [false, true].map(|multisampled| {
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("SSAO Texture Bind Group Layout"),
+                entries: &[
+                    texture_entry(0, multisampled, wgpu::TextureSampleType::Depth),
+                    texture_entry(1, false, float),
+                    texture_entry(2, false, float),
+                ],
+            })
+        });
+
+        let vertex_shader = FullscreenTriangle::vertex_shader(device);
+        let source = include_str!("ssao.wgsl");
+        let triangles = [false, true].map(|multisampled| {
+            // The depth is loaded from its first sample either way, so
+            // only the texture's type changes.
+            let source = if multisampled {
+                source.replace("texture_depth_2d", "texture_depth_multisampled_2d")
+            } else {
+                source.to_string()
+            };
+            let fragment_shader = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+                label: Some("SSAO Shader"),
+                source: wgpu::ShaderSource::Wgsl(source.into()),
+            });
+            let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("SSAO Pipeline Layout"),
+                bind_group_layouts: &[
+                    &uniform_binding.bind_group_layout,
+                    &texture_layouts[multisampled as usize],
+                ],
+                push_constant_ranges: &[],
+            });
+            FullscreenTriangle::new(
+                RenderPipelineBuilder::new()
+                    .label("SSAO Pipeline")
+                    .vertex_shader(&vertex_shader, "main")
+                    .fragment_shader(&fragment_shader, "main", &ao_rt.color_targets())
+                    .cull_mode(None)
+                    .build(device, &layout)
+                    .expect("failed to build SSAO pipeline"),
+            )
+        });
+
+        SsaoPass {
+            uniform,
+            uniform_binding,
+            noise_view,
+            ao_rt,
+            texture_layouts,
+            triangles,
+            blur: SsaoBlurPass::new(device, config),
+        }
+    }
+
+    #[allow(dead_code)]
+    pub fn radius(&self) -> f32 {
+        self.uniform.radius
+    }
+
+    /// How far around each pixel, in world units, the scene can
+    /// occlude it from.
+    #[allow(dead_code)]
+    pub fn set_radius(&mut self, queue: &wgpu::Queue, radius: f32) {
+        self.uniform.radius = radius;
+        self.uniform_binding.update(queue, &self.uniform);
+    }
+
+    /// Uploads the camera's projection, which the depth of this frame
+    /// was drawn with. Should be called once the camera has moved.
+    pub fn update(&mut self, queue: &wgpu::Queue, camera: &Camera) {
+        let projection = camera.build_projection_matrix();
+        self.uniform.projection = projection.into();
+        self.uniform.inv_projection = projection.invert().unwrap_or_else(Matrix4::identity).into();
+        self.uniform_binding.update(queue, &self.uniform);
+    }
+
+    /// Recreates the targets to match the new surface size.
+    pub fn resize(&mut self, device: &wgpu::Device, width: u32, height: u32) {
+        self.ao_rt.resize(device, width, height);
+        self.blur.resize(device, width, height);
+    }
+
+    /// The ambient occlusion of each pixel, once it's blurred, from
+    /// black where it's fully occluded to white where it isn't at all.
+    #[allow(dead_code)]
+    pub fn ao_view(&self) -> &wgpu::TextureView {
+        self.blur.output_view()
+    }
+
+    /// Works out the occlusion of the pixels in `depth`, with their
+    /// `normals` in view space, and blurs it.
+    pub fn run(
+        &self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        depth: &DepthBuffer,
+        normals: &wgpu::TextureView,
+    ) {
+        let multisampled = (depth.sample_count() > 1) as usize;
+        // The depth and normals are recreated on resize, and the depth
+        // when MSAA changes, so the bind group is made as it's needed.
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("SSAO Texture Bind Group"),
+            layout: &self.texture_layouts[multisampled],
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(depth.depth_view()),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(normals),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::TextureView(&self.noise_view),
+                },
+            ],
+        });
+
+        let ao = &self.ao_rt.colors()[0];
+        {
+            let mut render_pass = RenderPassBuilder::begin_render_pass(
+                encoder,
+                &wgpu::RenderPassDescriptor {
+                    label: Some("SSAO Pass"),
+                    color_attachments: &[wgpu::RenderPassColorAttachment {
+                        view: &ao.view,
+                        resolve_target: None,
+                        ops: wgpu::Operations {
+                            // Every pixel is drawn over, so there's no need to clear.
+                            load: wgpu::LoadOp::Load,
+                            store: true,
+                        },
+                    }],
+                    depth_stencil_attachment: None,
+                },
+            );
+            render_pass.set_bind_group(0, &self.uniform_binding.bind_group, &[]);
+            render_pass.set_bind_group(1, &bind_group, &[]);
+            self.triangles[multisampled].draw(&mut render_pass);
+        }
+
+        self.blur.run(device, encoder, &ao.view, normals);
+    }
+
+    /// Darkens `scene` by the occlusion from the last
+    /// [`run`](Self::run).
+    pub fn apply(
+        &self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        scene: &HdrRenderTarget,
+    ) {
+        self.blur.apply(device, encoder, scene);
+    }
+}
+
+/// Blurs the ambient occlusion from the [`SsaoPass`], five pixels
+/// across and then five down, to smooth out the noise its kernel is
+/// turned by.
+///
+/// The blur is bilateral: neighbors whose normals face away from the
+/// pixel's are on another surface, and count for less, so the edges
+/// between surfaces stay sharp.
+pub struct SsaoBlurPass {
+    /// Blurred across, before it's blurred down.
+    horizontal_rt: RenderTarget,
+    output_rt: RenderTarget,
+    /// Across, then down.
+    directions: [UniformBinding<BlurSettings>; 2],
+    texture_layout: wgpu::BindGroupLayout,
+    triangle: FullscreenTriangle,
+    /// Multiplies the output into the scene.
+    apply_triangle: FullscreenTriangle,
+}
+
+impl SsaoBlurPass {
+    pub fn new(device: &wgpu::Device, config: &wgpu::SurfaceConfiguration) -> Self {
+        let horizontal_rt = RenderTarget::new(device, config.width, config.height, &[AO_FORMAT]);
+        let output_rt = RenderTarget::new(device, config.width, config.height, &[AO_FORMAT]);
+        let directions = [[1, 0], [0, 1]].map(|direction| {
+            UniformBinding::new(
+                device,
+                "SSAO Blur Settings",
+                wgpu::ShaderStages::FRAGMENT,
+                &BlurSettings {
+                    direction,
+                    _padding: [0; 2],
+                },
+            )
+        });
+
+        let texture_entry = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                multisampled: false,
+                view_dimension: wgpu::TextureViewDimension::D2,
+                sample_type: wgpu::TextureSampleType::Float { filterable: true },
+            },
+            count: None,
+        };
+        let texture_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("SSAO Blur Texture Bind Group Layout"),
+            entries: &[texture_entry(0), texture_entry(1)],
+        });
+
+        let vertex_shader = FullscreenTriangle::vertex_shader(device);
+        let fragment_shader = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+            label: Some("SSAO Blur Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("ssao_blur.wgsl").into()),
+        });
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("SSAO Blur Pipeline Layout"),
+            bind_group_layouts: &[&directions[0].bind_group_layout, &texture_layout],
+            push_constant_ranges: &[],
+        });
+        let triangle = FullscreenTriangle::new(
+            RenderPipelineBuilder::new()
+                .label("SSAO Blur Pipeline")
+                .vertex_shader(&vertex_shader, "main")
+                .fragment_shader(&fragment_shader, "main", &output_rt.color_targets())
+                .cull_mode(None)
+                .build(device, &layout)
+                .expect("failed to build SSAO blur pipeline"),
+        );
+        let apply_triangle = FullscreenTriangle::new(
+            RenderPipelineBuilder::new()
+                .label("SSAO Apply Pipeline")
+                .vertex_shader(&vertex_shader, "main")
+                .fragment_shader(
+                    &fragment_shader,
+                    "apply",
+                    &[wgpu::ColorTargetState {
+                        format: HDR_FORMAT,
+                        // The scene's color times the occlusion, with
+                        // its alpha left as it is.
+                        blend: Some(wgpu::BlendState {
+                            color: wgpu::BlendComponent {
+                                src_factor: wgpu::BlendFactor::Zero,
+                                dst_factor: wgpu::BlendFactor::Src,
+                                operation: wgpu::BlendOperation::Add,
+                            },
+                            alpha: wgpu::BlendComponent {
+                                src_factor: wgpu::BlendFactor::Zero,
+                                dst_factor: wgpu::BlendFactor::One,
+                                operation: wgpu::BlendOperation::Add,
+                            },
+                        }),
+                        write_mask: wgpu::ColorWrites::ALL,
+                    }],
+                )
+                .cull_mode(None)
+                .build(device, &layout)
+                .expect("failed to build SSAO apply pipeline"),
+        );
+
+        SsaoBlurPass {
+            horizontal_rt,
+            output_rt,
+            directions,
+            texture_layout,
+            triangle,
+            apply_triangle,
+        }
+    }
+
+    /// The blurred occlusion.
+    pub fn output_view(&self) -> &wgpu::TextureView {
+        &self.output_rt.colors()[0].view
+    }
+
+    /// Recreates both targets to match the new surface size.
+    pub fn resize(&mut self, device: &wgpu::Device, width: u32, height: u32) {
+        self.horizontal_rt.resize(device, width, height);
+        self.output_rt.resize(device, width, height);
+    }
+
+    /// Blurs `ao` into the output, keeping to the surfaces in `normals`.
+    pub fn run(
+        &self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        ao: &wgpu::TextureView,
+        normals: &wgpu::TextureView,
+    ) {
+        let passes = [
+            (ao, &self.horizontal_rt),
+            (&self.horizontal_rt.colors()[0].view, &self.output_rt),
+        ];
+        for ((source, target), direction) in passes.iter().zip(&self.directions) {
+            let bind_group = self.texture_bind_group(device, source, normals);
+            let mut render_pass = RenderPassBuilder::begin_render_pass(
+                encoder,
+                &wgpu::RenderPassDescriptor {
+                    label: Some("SSAO Blur Pass"),
+                    color_attachments: &[wgpu::RenderPassColorAttachment {
+                        view: &target.colors()[0].view,
+                        resolve_target: None,
+                        ops: wgpu::Operations {
+                            load: wgpu::LoadOp::Load,
+                            store: true,
+                        },
+                    }],
+                    depth_stencil_attachment: None,
+                },
+            );
+            render_pass.set_bind_group(0, &direction.bind_group, &[]);
+            render_pass.set_bind_group(1, &bind_group, &[]);
+            self.triangle.draw(&mut render_pass);
+        }
+    }
+
+    /// Multiplies the blurred occlusion into `scene`.
+    pub fn apply(
+        &self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        scene: &HdrRenderTarget,
+    ) {
+        // Only the occlusion is read, but the layout is shared with
+        // the blur, which has the normals in the second slot.
+        let output = self.output_view();
+        let bind_group = self.texture_bind_group(device, output, output);
+        let mut render_pass = RenderPassBuilder::begin_render_pass(
+            encoder,
+            &wgpu::RenderPassDescriptor {
+                label: Some("SSAO Apply Pass"),
+                color_attachments: &[wgpu::RenderPassColorAttachment {
+                    view: scene.view(),
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Load,
+                        store: true,
+                    },
+                }],
+                depth_stencil_attachment: None,
+            },
+        );
+        render_pass.set_bind_group(0, &self.directions[0].bind_group, &[]);
+        render_pass.set_bind_group(1, &bind_group, &[]);
+        self.apply_triangle.draw(&mut render_pass);
+    }
+
+    fn texture_bind_group(
+        &self,
+        device: &wgpu::Device,
+        ao: &wgpu::TextureView,
+        normals: &wgpu::TextureView,
+    ) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("SSAO Blur Texture Bind Group"),
+            layout: &self.texture_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(ao),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(normals),
+                },
+            ],
+        })
+    }
+}
+
+/// `kernel_size` random points in the hemisphere around +Z, with more
+/// of them close to the middle, where occluders matter most.
+fn hemisphere_kernel(rng: &mut XorShift, kernel_size: u32) -> [[f32; 4]; MAX_KERNEL_SIZE as usize] {
+    let mut kernel = [[0.0; 4]; MAX_KERNEL_SIZE as usize];
+    for (i, sample) in kernel.iter_mut().take(kernel_size as usize).enumerate() {
+        let direction =
+            Vector3::new(rng.next_signed(), rng.next_signed(), rng.next_unit()).normalize();
+        let scale = i as f32 / kernel_size as f32;
+        let scale = 0.1 + 0.9 * scale * scale;
+        let point = direction * rng.next_unit() * scale;
+        *sample = [point.x, point.y, point.z, 0.0];
+    }
+    kernel
+}
+
+/// Random directions around Z, for turning the kernel around the
+/// normal of each pixel in a tile.
+fn create_noise_texture(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    rng: &mut XorShift,
+) -> wgpu::TextureView {
+    let size = wgpu::Extent3d {
+        width: NOISE_SIZE,
+        height: NOISE_SIZE,
+        depth_or_array_layers: 1,
+    };
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("SSAO Noise Texture"),
+        size,
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        // Signed, so the directions can point any way.
+        format: wgpu::TextureFormat::Rgba8Snorm,
+        usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+    });
+
+    let texels = (0..NOISE_SIZE * NOISE_SIZE)
+        .flat_map(|_| {
+            let x = (rng.next_signed() * 127.0) as i8;
+            let y = (rng.next_signed() * 127.0) as i8;
+            [x, y, 0, 0]
+        })
+        .collect::<Vec<i8>>();
+    queue.write_texture(
+        texture.as_image_copy(),
+        bytemuck::cast_slice(&texels),
+        wgpu::ImageDataLayout {
+            offset: 0,
+            bytes_per_row: NonZeroU32::new(4 * NOISE_SIZE),
+            rows_per_image: NonZeroU32::new(NOISE_SIZE),
+        },
+        size,
+    );
+
+    texture.create_view(&wgpu::TextureViewDescriptor::default())
+}
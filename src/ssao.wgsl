@@ -0,0 +1,90 @@
+// Screen-space ambient occlusion. Samples in a hemisphere around each
+// pixel's normal are projected back onto the depth buffer, and the
+// pixel is darkened by how many of them end up behind the scene.
+
+let MAX_KERNEL_SIZE: u32 = 64u;
+
+[[block]]
+struct SsaoUniform {
+    projection: mat4x4<f32>;
+    inv_projection: mat4x4<f32>;
+    // Offsets in the hemisphere around +Z, more of them close to the
+    // middle. Only the first `kernel_size` are used.
+    kernel: array<vec4<f32>, MAX_KERNEL_SIZE>;
+    kernel_size: u32;
+    radius: f32;
+    bias: f32;
+};
+
+[[group(0), binding(0)]]
+var<uniform> ssao: SsaoUniform;
+
+[[group(1), binding(0)]]
+var t_depth: texture_depth_2d;
+// View space normals, from the normal reconstruction.
+[[group(1), binding(1)]]
+var t_normal: texture_2d<f32>;
+// Tiled over the screen to turn the kernel around a different way
+// for each pixel in a tile, which the blur then smooths out.
+[[group(1), binding(2)]]
+var t_noise: texture_2d<f32>;
+
+struct FragmentInput {
+    [[builtin(position)]] position: vec4<f32>;
+    [[location(0)]] tex_coords: vec2<f32>;
+};
+
+fn view_position(coords: vec2<i32>, size: vec2<i32>) -> vec3<f32> {
+    let depth = textureLoad(t_depth, coords, 0);
+    let uv = (vec2<f32>(coords) + vec2<f32>(0.5)) / vec2<f32>(size);
+    // Texture coordinates have Y pointing down, unlike clip space.
+    let ndc = vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, depth, 1.0);
+    let position = ssao.inv_projection * ndc;
+    return position.xyz / position.w;
+}
+
+[[stage(fragment)]]
+fn main(in: FragmentInput) -> [[location(0)]] vec4<f32> {
+    let size = textureDimensions(t_depth);
+    let coords = vec2<i32>(in.position.xy);
+    let normal = textureLoad(t_normal, coords, 0).xyz;
+    // Nothing was drawn here to be occluded.
+    if (dot(normal, normal) < 0.5) {
+        return vec4<f32>(1.0);
+    }
+
+    let position = view_position(coords, size);
+    let noise_size = textureDimensions(t_noise);
+    let random = textureLoad(t_noise, coords % noise_size, 0).xyz;
+    // Turns the kernel to face along the normal, spun around it by
+    // the noise.
+    let tangent = normalize(random - normal * dot(random, normal));
+    let bitangent = cross(normal, tangent);
+    let tbn = mat3x3<f32>(tangent, bitangent, normal);
+
+    var occlusion = 0.0;
+    let kernel_size = min(ssao.kernel_size, MAX_KERNEL_SIZE);
+    for (var i = 0u; i < kernel_size; i = i + 1u) {
+        let sample = position + tbn * ssao.kernel[i].xyz * ssao.radius;
+        let clip = ssao.projection * vec4<f32>(sample, 1.0);
+        let ndc = clip.xy / clip.w;
+        let uv = vec2<f32>(ndc.x * 0.5 + 0.5, 0.5 - ndc.y * 0.5);
+        let sample_coords = clamp(
+            vec2<i32>(uv * vec2<f32>(size)),
+            vec2<i32>(0),
+            size - vec2<i32>(1),
+        );
+        let scene_z = view_position(sample_coords, size).z;
+
+        // The view looks down -Z, so the scene in front of the sample
+        // is further along +Z. Whatever's much further in front than
+        // the radius is too far away to occlude, and fades out.
+        let range = smoothStep(0.0, 1.0, ssao.radius / abs(position.z - scene_z));
+        if (scene_z >= sample.z + ssao.bias) {
+            occlusion = occlusion + range;
+        }
+    }
+
+    let ambient = 1.0 - occlusion / f32(max(kernel_size, 1u));
+    return vec4<f32>(ambient, ambient, ambient, 1.0);
+}
@@ -0,0 +1,55 @@
+// Blurs the ambient occlusion along one direction, five pixels wide,
+// to smooth out the noise it was sampled with. Neighbors facing away
+// from the pixel are on another surface, and count for less.
+
+let NORMAL_POWER: f32 = 8.0;
+
+[[block]]
+struct BlurSettings {
+    direction: vec2<i32>;
+};
+
+[[group(0), binding(0)]]
+var<uniform> settings: BlurSettings;
+
+[[group(1), binding(0)]]
+var t_ao: texture_2d<f32>;
+[[group(1), binding(1)]]
+var t_normal: texture_2d<f32>;
+
+struct FragmentInput {
+    [[builtin(position)]] position: vec4<f32>;
+    [[location(0)]] tex_coords: vec2<f32>;
+};
+
+[[stage(fragment)]]
+fn main(in: FragmentInput) -> [[location(0)]] vec4<f32> {
+    let size = textureDimensions(t_ao);
+    let coords = vec2<i32>(in.position.xy);
+    let normal = textureLoad(t_normal, coords, 0).xyz;
+
+    var total = 0.0;
+    var total_weight = 0.0;
+    for (var offset = -2; offset <= 2; offset = offset + 1) {
+        let tap = clamp(coords + settings.direction * offset, vec2<i32>(0), size - vec2<i32>(1));
+        let tap_normal = textureLoad(t_normal, tap, 0).xyz;
+        let weight = select(
+            pow(max(dot(normal, tap_normal), 0.0), NORMAL_POWER),
+            1.0,
+            offset == 0,
+        );
+        total = total + textureLoad(t_ao, tap, 0).r * weight;
+        total_weight = total_weight + weight;
+    }
+
+    let ambient = total / total_weight;
+    return vec4<f32>(ambient, ambient, ambient, 1.0);
+}
+
+// Darkens the scene by the blurred occlusion, which the pipeline
+// multiplies into what's already there.
+[[stage(fragment)]]
+fn apply(in: FragmentInput) -> [[location(0)]] vec4<f32> {
+    let ambient = textureLoad(t_ao, vec2<i32>(in.position.xy), 0).r;
+    return vec4<f32>(ambient, ambient, ambient, 1.0);
+}
@@ -0,0 +1,170 @@
+use std::{
+    collections::VecDeque,
+    future::Future,
+    pin::Pin,
+    task::{Context, Waker},
+};
+
+/// How frames are handed to the display.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SwapchainConfig {
+    /// `Fifo` waits for vsync, `Mailbox` replaces the waiting frame
+    /// with newer ones, and `Immediate` presents right away and may tear.
+    ///
+    /// Every surface supports `Fifo`, which is used instead of modes
+    /// the surface doesn't, see [`resolve`](Self::resolve).
+    pub present_mode: wgpu::PresentMode,
+    /// Frames that may be submitted to the GPU before the first of
+    /// them has finished. wgpu picks how many surface textures there
+    /// are itself, so this is kept to by a [`FrameLatencyLimiter`].
+    pub desired_maximum_frame_latency: u32,
+}
+
+impl SwapchainConfig {
+    pub fn new(present_mode: wgpu::PresentMode) -> Self {
+        SwapchainConfig {
+            present_mode,
+            ..Self::default()
+        }
+    }
+
+    /// This config with `Fifo` in place of a present mode that
+    /// isn't in `supported`.
+    pub fn resolve(self, supported: &[wgpu::PresentMode]) -> Self {
+        if supported.contains(&self.present_mode) {
+            return self;
+        }
+
+        log::warn!(
+            "surface doesn't support {:?}, falling back to Fifo",
+            self.present_mode
+        );
+        SwapchainConfig {
+            present_mode: wgpu::PresentMode::Fifo,
+            ..self
+        }
+    }
+}
+
+impl Default for SwapchainConfig {
+    fn default() -> Self {
+        SwapchainConfig {
+            present_mode: wgpu::PresentMode::Fifo,
+            desired_maximum_frame_latency: 2,
+        }
+    }
+}
+
+/// The present modes surfaces on `backend` can support.
+///
+/// wgpu doesn't tell us what a surface supports, and on its own
+/// falls back to `Fifo` with only a warning. GL surfaces only ever
+/// support `Fifo`. The others may support any mode, which wgpu
+/// still falls back from when they don't.
+pub fn supported_present_modes(backend: wgpu::Backend) -> &'static [wgpu::PresentMode] {
+    match backend {
+        wgpu::Backend::Gl => &[wgpu::PresentMode::Fifo],
+        _ => &[
+            wgpu::PresentMode::Fifo,
+            wgpu::PresentMode::Mailbox,
+            wgpu::PresentMode::Immediate,
+        ],
+    }
+}
+
+type SubmittedFrame = Pin<Box<dyn Future<Output = ()> + Send>>;
+
+/// Keeps the CPU from getting more than a few frames ahead of the GPU.
+pub struct FrameLatencyLimiter {
+    /// Finishes when the GPU is done with the frame, oldest first.
+    in_flight: VecDeque<SubmittedFrame>,
+}
+
+impl FrameLatencyLimiter {
+    pub fn new() -> Self {
+        FrameLatencyLimiter {
+            in_flight: VecDeque::new(),
+        }
+    }
+
+    /// Should be called once a frame's work has been submitted.
+    pub fn submitted(&mut self, queue: &wgpu::Queue) {
+        self.in_flight
+            .push_back(Box::pin(queue.on_submitted_work_done()));
+    }
+
+    /// Waits until fewer than `max_latency` frames are still running on
+    /// the GPU, so the next one can be started.
+    pub fn wait(&mut self, device: &wgpu::Device, max_latency: u32) {
+        loop {
+            self.retire_finished();
+            if self.in_flight.len() < max_latency.max(1) as usize {
+                return;
+            }
+            // Finished frames are only noticed when the device is polled.
+            device.poll(wgpu::Maintain::Poll);
+            std::thread::yield_now();
+        }
+    }
+
+    /// Frames submitted that the GPU may not have finished.
+    #[allow(dead_code)]
+    pub fn in_flight(&self) -> usize {
+        self.in_flight.len()
+    }
+
+    fn retire_finished(&mut self) {
+        let mut context = Context::from_waker(Waker::noop());
+        self.in_flight
+            .retain_mut(|frame| frame.as_mut().poll(&mut context).is_pending());
+    }
+}
+
+impl Default for FrameLatencyLimiter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fifo_only_falls_back_to_fifo() {
+        let supported = supported_present_modes(wgpu::Backend::Gl);
+        for mode in [
+            wgpu::PresentMode::Fifo,
+            wgpu::PresentMode::Mailbox,
+            wgpu::PresentMode::Immediate,
+        ] {
+            let swapchain = SwapchainConfig::new(mode).resolve(supported);
+            assert_eq!(swapchain.present_mode, wgpu::PresentMode::Fifo);
+            assert_eq!(swapchain.desired_maximum_frame_latency, 2);
+        }
+    }
+
+    #[test]
+    fn keeps_supported_mode() {
+        let supported = supported_present_modes(wgpu::Backend::Vulkan);
+        let swapchain = SwapchainConfig::new(wgpu::PresentMode::Mailbox).resolve(supported);
+        assert_eq!(swapchain.present_mode, wgpu::PresentMode::Mailbox);
+    }
+
+    #[test]
+    fn retires_finished_frames() {
+        let mut limiter = FrameLatencyLimiter::new();
+        limiter
+            .in_flight
+            .push_back(Box::pin(std::future::ready(())));
+        limiter
+            .in_flight
+            .push_back(Box::pin(std::future::pending()));
+        limiter
+            .in_flight
+            .push_back(Box::pin(std::future::ready(())));
+
+        limiter.retire_finished();
+        assert_eq!(limiter.in_flight(), 1);
+    }
+}
@@ -0,0 +1,287 @@
+use crate::{
+    fullscreen_triangle::FullscreenTriangle,
+    hdr_render_target::{HdrRenderTarget, HDR_FORMAT},
+    pipeline::RenderPipelineBuilder,
+    render_pass_guard::RenderPassBuilder,
+    render_target::RenderTarget,
+    uniform::UniformBinding,
+};
+
+/// Jitter offsets repeat after this many frames.
+const JITTER_PHASES: u32 = 8;
+/// How far from the mean of its neighbors, in standard deviations,
+/// the history of a pixel can be.
+const CLIP_GAMMA: f32 = 1.0;
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct TaaSettings {
+    feedback_factor: f32,
+    clip_gamma: f32,
+    _padding: [f32; 2],
+}
+
+/// Temporal anti-aliasing, which smooths edges by blending each
+/// frame into the ones before it.
+///
+/// The camera is jittered by a different fraction of a pixel every
+/// frame, by the [`jitter_offset`](TaaPass::jitter_offset) for the
+/// frame, so over a few frames every pixel is covered by samples
+/// across its whole area. The history is followed along the
+/// velocities of the [`VelocityPass`](crate::velocity::VelocityPass),
+/// and clamped to the colors around each pixel this frame, so what's
+/// since moved doesn't leave ghosts behind.
+///
+/// The blended frame is drawn into `current_rt` and copied back into
+/// the scene. [`TaaPass::swap`] then makes it the history of the next
+/// frame.
+pub struct TaaPass {
+    current_rt: RenderTarget,
+    history_rt: RenderTarget,
+    /// How much of the history is kept each frame. Higher is smoother,
+    /// but slower to catch up with changes.
+    feedback_factor: f32,
+    settings: UniformBinding<TaaSettings>,
+    /// Whether the history has a frame in it yet. Until it does, the
+    /// frame is drawn as it is.
+    history_valid: bool,
+    frame_index: u32,
+    sampler: wgpu::Sampler,
+    texture_layout: wgpu::BindGroupLayout,
+    triangle: FullscreenTriangle,
+}
+
+impl TaaPass {
+    pub fn new(
+        device: &wgpu::Device,
+        config: &wgpu::SurfaceConfiguration,
+        feedback_factor: f32,
+    ) -> Self {
+        let current_rt = RenderTarget::new(device, config.width, config.height, &[HDR_FORMAT]);
+        let history_rt = RenderTarget::new(device, config.width, config.height, &[HDR_FORMAT]);
+        let settings = UniformBinding::new(
+            device,
+            "TAA Settings",
+            wgpu::ShaderStages::FRAGMENT,
+            &settings(0.0),
+        );
+
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("TAA Sampler"),
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            ..Default::default()
+        });
+        let texture_entry = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                multisampled: false,
+                view_dimension: wgpu::TextureViewDimension::D2,
+                sample_type: wgpu::TextureSampleType::Float { filterable: true },
+            },
+            count: None,
+        };
+        let texture_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("TAA Texture Bind Group Layout"),
+            entries: &[
+                texture_entry(0),
+                texture_entry(1),
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler {
+                        filtering: true,
+                        comparison: false,
+                    },
+                    count: None,
+                },
+                texture_entry(3),
+            ],
+        });
+
+        let vertex_shader = FullscreenTriangle::vertex_shader(device);
+        let fragment_shader = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+            label: Some("TAA Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("taa.wgsl").into()),
+        });
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("TAA Pipeline Layout"),
+            bind_group_layouts: &[&texture_layout, &settings.bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let triangle = FullscreenTriangle::new(
+            RenderPipelineBuilder::new()
+                .label("TAA Pipeline")
+                .vertex_shader(&vertex_shader, "main")
+                .fragment_shader(&fragment_shader, "main", &current_rt.color_targets())
+                .cull_mode(None)
+                .build(device, &layout)
+                .expect("failed to build TAA pipeline"),
+        );
+
+        TaaPass {
+            current_rt,
+            history_rt,
+            feedback_factor,
+            settings,
+            history_valid: false,
+            frame_index: 0,
+            sampler,
+            texture_layout,
+            triangle,
+        }
+    }
+
+    /// How far to jitter the camera on the frame at `frame_index`, in
+    /// pixels from the middle of the pixel, each between -0.5 and 0.5.
+    ///
+    /// The offsets follow the Halton sequence in bases 2 and 3, which
+    /// spreads them evenly over the pixel without lining up.
+    pub fn jitter_offset(frame_index: u32) -> (f32, f32) {
+        // The sequence starts at 1, since its first point is 0, 0.
+        let index = frame_index % JITTER_PHASES + 1;
+        (halton(index, 2) - 0.5, halton(index, 3) - 0.5)
+    }
+
+    /// The frames blended so far, for looking up this frame's
+    /// [`jitter_offset`](TaaPass::jitter_offset).
+    pub fn frame_index(&self) -> u32 {
+        self.frame_index
+    }
+
+    #[allow(dead_code)]
+    pub fn feedback_factor(&self) -> f32 {
+        self.feedback_factor
+    }
+
+    #[allow(dead_code)]
+    pub fn set_feedback_factor(&mut self, queue: &wgpu::Queue, feedback_factor: f32) {
+        self.feedback_factor = feedback_factor;
+        if self.history_valid {
+            self.settings.update(queue, &settings(feedback_factor));
+        }
+    }
+
+    /// Throws away the history, for when it no longer has anything to
+    /// do with what's on screen, like after the pass has been off.
+    pub fn reset_history(&mut self, queue: &wgpu::Queue) {
+        self.history_valid = false;
+        self.settings.update(queue, &settings(0.0));
+    }
+
+    /// Recreates both targets to match the new surface size, which
+    /// throws away the history.
+    pub fn resize(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, width: u32, height: u32) {
+        self.current_rt.resize(device, width, height);
+        self.history_rt.resize(device, width, height);
+        self.reset_history(queue);
+    }
+
+    /// Blends `scene` with the history and copies the result back
+    /// into it. `velocity` is where each pixel moved from since the
+    /// last frame, in texture coordinates.
+    pub fn run(
+        &self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        scene: &HdrRenderTarget,
+        velocity: &wgpu::TextureView,
+    ) {
+        let current = &self.current_rt.colors()[0];
+        let history = &self.history_rt.colors()[0];
+        // The scene and velocity are recreated on resize, and the
+        // targets swap every frame, so the bind group is made as it's
+        // needed.
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("TAA Texture Bind Group"),
+            layout: &self.texture_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(scene.view()),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(&history.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::Sampler(&self.sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: wgpu::BindingResource::TextureView(velocity),
+                },
+            ],
+        });
+
+        {
+            let mut render_pass = RenderPassBuilder::begin_render_pass(
+                encoder,
+                &wgpu::RenderPassDescriptor {
+                    label: Some("TAA Pass"),
+                    color_attachments: &[wgpu::RenderPassColorAttachment {
+                        view: &current.view,
+                        resolve_target: None,
+                        ops: wgpu::Operations {
+                            // Every pixel is drawn over, so there's no need to clear.
+                            load: wgpu::LoadOp::Load,
+                            store: true,
+                        },
+                    }],
+                    depth_stencil_attachment: None,
+                },
+            );
+            render_pass.set_bind_group(0, &bind_group, &[]);
+            render_pass.set_bind_group(1, &self.settings.bind_group, &[]);
+            self.triangle.draw(&mut render_pass);
+        }
+
+        encoder.copy_texture_to_texture(
+            current.texture.as_image_copy(),
+            scene.texture().as_image_copy(),
+            wgpu::Extent3d {
+                width: scene.width(),
+                height: scene.height(),
+                depth_or_array_layers: 1,
+            },
+        );
+    }
+
+    /// Makes the frame just blended the history of the next one.
+    /// Should be called once the frame's passes are recorded.
+    pub fn swap(&mut self, queue: &wgpu::Queue) {
+        std::mem::swap(&mut self.current_rt, &mut self.history_rt);
+        self.frame_index = self.frame_index.wrapping_add(1);
+        if !self.history_valid {
+            self.history_valid = true;
+            self.settings.update(queue, &settings(self.feedback_factor));
+        }
+    }
+}
+
+fn settings(feedback_factor: f32) -> TaaSettings {
+    TaaSettings {
+        feedback_factor,
+        clip_gamma: CLIP_GAMMA,
+        _padding: [0.0; 2],
+    }
+}
+
+/// The `index`th point of the Halton sequence in `base`, between 0
+/// and 1.
+fn halton(mut index: u32, base: u32) -> f32 {
+    let mut result = 0.0;
+    let mut fraction = 1.0;
+    while index > 0 {
+        fraction /= base as f32;
+        result += fraction * (index % base) as f32;
+        index /= base;
+    }
+    result
+}
@@ -0,0 +1,72 @@
+// Blends the frame into the history of the frames before it, so the
+// sub-pixel jitter of each frame averages out into smooth edges.
+
+[[block]]
+struct TaaSettings {
+    // How much of the history is kept each frame.
+    feedback_factor: f32;
+    // How many standard deviations from the neighborhood's mean the
+    // history can be before it's clipped.
+    clip_gamma: f32;
+};
+
+[[group(0), binding(0)]]
+var t_current: texture_2d<f32>;
+[[group(0), binding(1)]]
+var t_history: texture_2d<f32>;
+[[group(0), binding(2)]]
+var s_history: sampler;
+[[group(0), binding(3)]]
+var t_velocity: texture_2d<f32>;
+
+[[group(1), binding(0)]]
+var<uniform> settings: TaaSettings;
+
+struct FragmentInput {
+    [[builtin(position)]] position: vec4<f32>;
+    [[location(0)]] tex_coords: vec2<f32>;
+};
+
+[[stage(fragment)]]
+fn main(in: FragmentInput) -> [[location(0)]] vec4<f32> {
+    let size = textureDimensions(t_current);
+    let pixel = vec2<i32>(in.position.xy);
+    let current = textureLoad(t_current, pixel, 0).rgb;
+
+    // The range of colors around the pixel this frame. History
+    // outside of it is something that's since moved away or been
+    // uncovered, and would leave ghosts behind if it was kept.
+    var min_color = current;
+    var max_color = current;
+    var sum = vec3<f32>(0.0);
+    var sum_squared = vec3<f32>(0.0);
+    for (var y: i32 = -1; y <= 1; y = y + 1) {
+        for (var x: i32 = -1; x <= 1; x = x + 1) {
+            let neighbor_pixel = clamp(pixel + vec2<i32>(x, y), vec2<i32>(0), size - vec2<i32>(1));
+            let neighbor = textureLoad(t_current, neighbor_pixel, 0).rgb;
+            min_color = min(min_color, neighbor);
+            max_color = max(max_color, neighbor);
+            sum = sum + neighbor;
+            sum_squared = sum_squared + neighbor * neighbor;
+        }
+    }
+    // Narrowed to the spread of the colors, so one bright neighbor
+    // doesn't let all of the history through.
+    let mean = sum / 9.0;
+    let deviation = sqrt(max(sum_squared / 9.0 - mean * mean, vec3<f32>(0.0)));
+    min_color = max(min_color, mean - deviation * settings.clip_gamma);
+    max_color = min(max_color, mean + deviation * settings.clip_gamma);
+
+    // Where the pixel was last frame.
+    let velocity = textureLoad(t_velocity, pixel, 0).xy;
+    let history_coords = in.tex_coords - velocity;
+    var history = textureSample(t_history, s_history, history_coords).rgb;
+    history = clamp(history, min_color, max_color);
+
+    // Off the edge of the last frame there's no history to keep.
+    var feedback = settings.feedback_factor;
+    if (any(history_coords < vec2<f32>(0.0)) || any(history_coords > vec2<f32>(1.0))) {
+        feedback = 0.0;
+    }
+    return vec4<f32>(mix(current, history, feedback), 1.0);
+}
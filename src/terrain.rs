@@ -0,0 +1,156 @@
+use crate::{bounds::Aabb, mesh::Mesh, vertex::Vertex};
+
+/// Heights sampled on a regular grid, centered on the origin.
+#[derive(Debug, Clone)]
+pub struct HeightMap {
+    heights: Vec<f32>,
+    columns: u32,
+    rows: u32,
+    /// Distance between neighbouring samples, along either axis.
+    spacing: f32,
+}
+
+impl HeightMap {
+    /// The height of sample `(column, row)`, clamped to the edges.
+    fn sample(&self, column: i64, row: i64) -> f32 {
+        let column = column.clamp(0, self.columns as i64 - 1) as usize;
+        let row = row.clamp(0, self.rows as i64 - 1) as usize;
+        self.heights[row * self.columns as usize + column]
+    }
+
+    /// Where sample `(column, row)` is, before its height is added.
+    fn position(&self, column: u32, row: u32) -> [f32; 2] {
+        [
+            (column as f32 - (self.columns - 1) as f32 * 0.5) * self.spacing,
+            (row as f32 - (self.rows - 1) as f32 * 0.5) * self.spacing,
+        ]
+    }
+
+    /// Upwards normal at a sample, from the slope between its
+    /// neighbours `step` samples away on either side.
+    fn normal(&self, column: u32, row: u32, step: u32) -> [f32; 3] {
+        let (column, row, step) = (column as i64, row as i64, step as i64);
+        let distance = 2.0 * step as f32 * self.spacing;
+        let dx = (self.sample(column + step, row) - self.sample(column - step, row)) / distance;
+        let dz = (self.sample(column, row + step) - self.sample(column, row - step)) / distance;
+
+        let length = (dx * dx + 1.0 + dz * dz).sqrt();
+        [-dx / length, 1.0 / length, -dz / length]
+    }
+
+    /// Height of the ground at `(x, z)` in the terrain's own space,
+    /// blended between the four samples around it. Points off the
+    /// edge take the height of the nearest edge.
+    #[allow(dead_code)]
+    pub fn height_at(&self, x: f32, z: f32) -> f32 {
+        let column = (x / self.spacing + (self.columns - 1) as f32 * 0.5)
+            .clamp(0.0, (self.columns - 1) as f32);
+        let row =
+            (z / self.spacing + (self.rows - 1) as f32 * 0.5).clamp(0.0, (self.rows - 1) as f32);
+
+        let (left, top) = (column.floor() as i64, row.floor() as i64);
+        let (tx, tz) = (column.fract(), row.fract());
+        let near = lerp(self.sample(left, top), self.sample(left + 1, top), tx);
+        let far = lerp(
+            self.sample(left, top + 1),
+            self.sample(left + 1, top + 1),
+            tx,
+        );
+        lerp(near, far, tz)
+    }
+}
+
+/// Ground generated from a greyscale height map image, where
+/// white is the highest and black the lowest.
+pub struct Terrain {
+    pub mesh: Mesh,
+    /// The full resolution heights, so collisions don't depend on
+    /// how coarse the mesh is.
+    pub height_map: HeightMap,
+}
+
+impl Terrain {
+    /// Builds a terrain `width_units` wide along x, with a sample for
+    /// every pixel of the image, and heights up to `height_scale`.
+    ///
+    /// The mesh only takes every `step`th pixel, for a coarser level
+    /// of detail. Its last row and column always reach the edges.
+    pub fn from_heightmap(
+        device: &wgpu::Device,
+        image_bytes: &[u8],
+        width_units: f32,
+        height_scale: f32,
+        step: u32,
+    ) -> image::ImageResult<Self> {
+        let image = image::load_from_memory(image_bytes)?.into_luma16();
+        let (columns, rows) = image.dimensions();
+        let heights = image
+            .pixels()
+            .map(|pixel| pixel.0[0] as f32 / u16::MAX as f32 * height_scale)
+            .collect();
+        let height_map = HeightMap {
+            heights,
+            columns,
+            rows,
+            spacing: width_units / (columns.max(2) - 1) as f32,
+        };
+
+        let step = step.max(1);
+        let sampled = |count: u32| {
+            let mut indices = (0..count - 1).step_by(step as usize).collect::<Vec<_>>();
+            indices.push(count - 1);
+            indices
+        };
+        let (sampled_columns, sampled_rows) = (sampled(columns), sampled(rows));
+
+        let mut vertices = Vec::with_capacity(sampled_columns.len() * sampled_rows.len());
+        for &row in &sampled_rows {
+            for &column in &sampled_columns {
+                let [x, z] = height_map.position(column, row);
+                vertices.push(Vertex {
+                    position: [x, height_map.sample(column as i64, row as i64), z],
+                    color: [1.0, 1.0, 1.0],
+                    normal: height_map.normal(column, row, step),
+                    tex_coords: [
+                        column as f32 / (columns.max(2) - 1) as f32,
+                        row as f32 / (rows.max(2) - 1) as f32,
+                    ],
+                });
+            }
+        }
+
+        // Two triangles per cell, wound counter-clockwise when
+        // seen from above.
+        let stride = sampled_columns.len() as u32;
+        let mut indices = Vec::new();
+        for row in 0..sampled_rows.len() as u32 - 1 {
+            for column in 0..stride - 1 {
+                let a = row * stride + column;
+                let (b, c, d) = (a + 1, a + stride, a + stride + 1);
+                indices.extend_from_slice(&[a, c, b, b, c, d]);
+            }
+        }
+
+        let mesh =
+            Mesh::upload(device, &vertices, &indices).with_bounds(Aabb::from_vertices(&vertices));
+        log::info!(
+            "generated {}x{} terrain with {} triangles",
+            columns,
+            rows,
+            indices.len() / 3
+        );
+
+        Ok(Terrain { mesh, height_map })
+    }
+
+    /// See [`HeightMap::height_at`].
+    #[allow(dead_code)]
+    pub fn height_at(&self, x: f32, z: f32) -> f32 {
+        self.height_map.height_at(x, z)
+    }
+}
+
+#[allow(dead_code)]
+fn lerp(a: f32, b: f32, t: f32) -> f32 {
+    a + (b - a) * t
+}
@@ -0,0 +1,247 @@
+use crate::{
+    font::Font,
+    glyph_cache::GlyphCache,
+    sdf_font::SdfFont,
+    sprite::{SpriteInstance, SpriteRenderer, SpriteTexture},
+    texture::Texture,
+};
+
+/// The font text is drawn with, which decides how its
+/// glyphs are laid out and shaded.
+enum TextFont {
+    Bitmap(Font),
+    /// Kept with its uploaded atlas, since the font itself
+    /// only holds the distances.
+    Sdf(SdfFont, #[allow(dead_code)] Texture),
+    /// Rasterized at each size it's drawn at, `pixel_size` pixels to
+    /// the em at a scale of 1.
+    Rasterized {
+        cache: GlyphCache,
+        pixel_size: f32,
+    },
+}
+
+/// Sides of the atlas rasterized glyphs are cached in, in pixels.
+const GLYPH_CACHE_SIZE: u32 = 512;
+
+/// A glyph of a rasterized font, waiting for the flush to rasterize
+/// it, since that needs the queue.
+struct PendingGlyph {
+    codepoint: char,
+    size_px: f32,
+    /// Where the glyph starts on the baseline.
+    pen: [f32; 2],
+    color: [f32; 4],
+}
+
+/// Draws lines of text with a bitmap [`Font`], an [`SdfFont`], or a
+/// TrueType font rasterized into a [`GlyphCache`].
+///
+/// Every glyph is drawn as a sprite, with a sprite renderer of its own,
+/// so text is queued and flushed the same way sprites are. SDF fonts
+/// are drawn with a shader that turns the distances into smooth edges,
+/// so they can be scaled without going blocky. Rasterized fonts are
+/// rasterized again at every size they're drawn at, on the flush.
+pub struct TextRenderer {
+    font: TextFont,
+    sprites: SpriteRenderer,
+    texture: SpriteTexture,
+    pending: Vec<PendingGlyph>,
+}
+
+impl TextRenderer {
+    pub fn new(device: &wgpu::Device, config: &wgpu::SurfaceConfiguration, font: Font) -> Self {
+        let mut sprites = SpriteRenderer::new(device, config);
+        let texture = sprites.add_texture(device, font.texture());
+        TextRenderer {
+            font: TextFont::Bitmap(font),
+            sprites,
+            texture,
+            pending: Vec::new(),
+        }
+    }
+
+    pub fn new_sdf(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        config: &wgpu::SurfaceConfiguration,
+        font: SdfFont,
+    ) -> Self {
+        let mut sprites =
+            SpriteRenderer::with_shader(device, config, include_str!("sprite_sdf.wgsl"));
+        let atlas = font.create_texture(device, queue);
+        let texture = sprites.add_texture(device, &atlas);
+        TextRenderer {
+            font: TextFont::Sdf(font, atlas),
+            sprites,
+            texture,
+            pending: Vec::new(),
+        }
+    }
+
+    /// Text in `font`, `pixel_size` pixels to the em at a scale of 1.
+    pub fn new_rasterized(
+        device: &wgpu::Device,
+        config: &wgpu::SurfaceConfiguration,
+        font: rusttype::Font<'static>,
+        pixel_size: f32,
+    ) -> Self {
+        let mut sprites =
+            SpriteRenderer::with_shader(device, config, include_str!("sprite_coverage.wgsl"));
+        let cache = GlyphCache::new(device, font, GLYPH_CACHE_SIZE);
+        let texture = sprites.add_texture(device, cache.texture());
+        TextRenderer {
+            font: TextFont::Rasterized { cache, pixel_size },
+            sprites,
+            texture,
+            pending: Vec::new(),
+        }
+    }
+
+    /// From the top of one line to the next, in pixels at `scale`.
+    pub fn line_height(&self, scale: f32) -> f32 {
+        match &self.font {
+            TextFont::Bitmap(font) => font.glyph_size()[1] * scale,
+            TextFont::Sdf(font, _) => font.line_height() * scale,
+            TextFont::Rasterized { cache, pixel_size } => cache.line_height(pixel_size * scale),
+        }
+    }
+
+    /// Queues `text` with its top left corner at `(x, y)` in pixels.
+    /// Glyphs are `scale` times their size in the font, and each
+    /// newline starts a new line below the first.
+    pub fn draw_text(&mut self, x: f32, y: f32, scale: f32, color: [f32; 4], text: &str) {
+        match &self.font {
+            TextFont::Bitmap(font) => {
+                let [glyph_width, glyph_height] = font.glyph_size();
+                let size = [glyph_width * scale, glyph_height * scale];
+
+                let mut position = [x, y];
+                for ch in text.chars() {
+                    if ch == '\n' {
+                        position = [x, position[1] + size[1]];
+                        continue;
+                    }
+
+                    if ch != ' ' {
+                        let mut sprite = SpriteInstance::new(position, size);
+                        sprite.uv_rect = font.uv_for(ch);
+                        sprite.color = color;
+                        self.sprites.draw(self.texture, sprite);
+                    }
+                    position[0] += size[0];
+                }
+            }
+            TextFont::Sdf(font, _) => {
+                let mut pen = [x, y + font.ascent() * scale];
+                for ch in text.chars() {
+                    if ch == '\n' {
+                        pen = [x, pen[1] + font.line_height() * scale];
+                        continue;
+                    }
+
+                    let glyph = match font.glyph(ch) {
+                        Some(glyph) => glyph,
+                        None => continue,
+                    };
+                    if glyph.size[0] > 0.0 {
+                        // Bearings go up from the baseline, while
+                        // screen space goes down.
+                        let position = [
+                            pen[0] + glyph.bearing[0] * scale,
+                            pen[1] - glyph.bearing[1] * scale,
+                        ];
+                        let size = [glyph.size[0] * scale, glyph.size[1] * scale];
+                        let mut sprite = SpriteInstance::new(position, size);
+                        sprite.uv_rect = glyph.uv_rect;
+                        sprite.color = color;
+                        self.sprites.draw(self.texture, sprite);
+                    }
+                    pen[0] += glyph.advance * scale;
+                }
+            }
+            TextFont::Rasterized { cache, pixel_size } => {
+                let size_px = pixel_size * scale;
+                let mut pen = [x, y + cache.ascent(size_px)];
+                for codepoint in text.chars() {
+                    if codepoint == '\n' {
+                        pen = [x, pen[1] + cache.line_height(size_px)];
+                        continue;
+                    }
+
+                    self.pending.push(PendingGlyph {
+                        codepoint,
+                        size_px,
+                        pen,
+                        color,
+                    });
+                    pen[0] += cache.advance(codepoint, size_px);
+                }
+            }
+        }
+    }
+
+    /// Width in pixels of the longest line of `text`, drawn at `scale`.
+    pub fn text_width(&self, text: &str, scale: f32) -> f32 {
+        match &self.font {
+            TextFont::Bitmap(font) => {
+                let [glyph_width, _] = font.glyph_size();
+                let longest = text
+                    .lines()
+                    .map(|line| line.chars().count())
+                    .max()
+                    .unwrap_or(0);
+                longest as f32 * glyph_width * scale
+            }
+            TextFont::Sdf(font, _) => text
+                .lines()
+                .map(|line| {
+                    line.chars()
+                        .filter_map(|ch| font.glyph(ch))
+                        .map(|glyph| glyph.advance * scale)
+                        .sum::<f32>()
+                })
+                .fold(0.0, f32::max),
+            TextFont::Rasterized { cache, pixel_size } => text
+                .lines()
+                .map(|line| {
+                    line.chars()
+                        .map(|codepoint| cache.advance(codepoint, pixel_size * scale))
+                        .sum::<f32>()
+                })
+                .fold(0.0, f32::max),
+        }
+    }
+
+    pub fn resize(&self, queue: &wgpu::Queue, width: u32, height: u32) {
+        self.sprites.resize(queue, width, height);
+    }
+
+    /// Draws the queued text into `view`, and clears the queue.
+    ///
+    /// Glyphs of a rasterized font that aren't cached at their size
+    /// are rasterized first.
+    pub fn flush(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, view: &wgpu::TextureView) {
+        if let TextFont::Rasterized { cache, .. } = &mut self.font {
+            for glyph in self.pending.drain(..) {
+                let region = match cache.get_or_rasterize(queue, glyph.codepoint, glyph.size_px) {
+                    Some(region) => region,
+                    None => continue,
+                };
+                if region.size[0] == 0 {
+                    continue;
+                }
+                let position = [
+                    glyph.pen[0] + region.offset[0],
+                    glyph.pen[1] + region.offset[1],
+                ];
+                let size = [region.size[0] as f32, region.size[1] as f32];
+                let mut sprite = SpriteInstance::new(position, size);
+                sprite.uv_rect = region.uv_rect.to_array();
+                sprite.color = glyph.color;
+                self.sprites.draw(self.texture, sprite);
+            }
+        }
+        self.sprites.flush(device, queue, view);
+    }
+}
@@ -0,0 +1,204 @@
+use std::{num::NonZeroU32, path::Path};
+
+use image::GenericImageView;
+
+/// A 2D texture with its view and sampler, ready to be
+/// bound to a shader.
+pub struct Texture {
+    pub texture: wgpu::Texture,
+    pub view: wgpu::TextureView,
+    pub sampler: wgpu::Sampler,
+    #[allow(dead_code)]
+    pub size: wgpu::Extent3d,
+}
+
+impl Texture {
+    pub const FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8UnormSrgb;
+    /// For images holding data rather than colors, like normal maps.
+    pub const LINEAR_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8Unorm;
+
+    /// Decodes an encoded image, such as the contents of a PNG or JPEG file.
+    #[allow(dead_code)]
+    pub fn from_bytes(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        bytes: &[u8],
+        label: &str,
+    ) -> image::ImageResult<Self> {
+        let img = image::load_from_memory(bytes)?;
+        Ok(Self::from_image(device, queue, &img, Some(label)))
+    }
+
+    #[allow(dead_code)]
+    pub fn from_path(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        path: impl AsRef<Path>,
+    ) -> image::ImageResult<Self> {
+        let path = path.as_ref();
+        let img = image::open(path)?;
+        let label = path.to_string_lossy();
+        Ok(Self::from_image(device, queue, &img, Some(&label)))
+    }
+
+    pub fn from_image(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        img: &image::DynamicImage,
+        label: Option<&str>,
+    ) -> Self {
+        Self::from_image_with_format(device, queue, img, label, Self::FORMAT)
+    }
+
+    /// A texture from raw RGBA pixels in sRGB, `width` by `height`,
+    /// like the ones [`ProcTexture`](crate::proc_texture::ProcTexture)
+    /// generates.
+    ///
+    /// # Panics
+    ///
+    /// If `rgba` isn't `width * height * 4` bytes long.
+    #[allow(dead_code)]
+    pub fn from_rgba(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        width: u32,
+        height: u32,
+        rgba: Vec<u8>,
+        label: Option<&str>,
+    ) -> Self {
+        let img = image::RgbaImage::from_raw(width, height, rgba)
+            .expect("RGBA pixels don't match the texture's size");
+        Self::from_image(device, queue, &image::DynamicImage::ImageRgba8(img), label)
+    }
+
+    /// A single texel of `color`, for standing in where there's no image.
+    pub fn solid(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        color: [u8; 4],
+        format: wgpu::TextureFormat,
+        label: Option<&str>,
+    ) -> Self {
+        let img =
+            image::DynamicImage::ImageRgba8(image::RgbaImage::from_pixel(1, 1, image::Rgba(color)));
+        Self::from_image_with_format(device, queue, &img, label, format)
+    }
+
+    /// Like [`Texture::from_image`], but stored in `format`, which
+    /// must have four 8 bit channels.
+    pub fn from_image_with_format(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        img: &image::DynamicImage,
+        label: Option<&str>,
+        format: wgpu::TextureFormat,
+    ) -> Self {
+        let rgba = img.to_rgba8();
+        let dimensions = img.dimensions();
+
+        let size = wgpu::Extent3d {
+            width: dimensions.0,
+            height: dimensions.1,
+            // All textures are stored as 3D, we represent our 2D texture
+            // by setting depth to 1.
+            depth_or_array_layers: 1,
+        };
+
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label,
+            size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            // Most images are stored using sRGB so we need to reflect that here.
+            format,
+            // TEXTURE_BINDING tells wgpu that we want to use this texture in shaders.
+            // COPY_DST means that we want to copy data to this texture.
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+        });
+
+        queue.write_texture(
+            // Tells wgpu where to copy the pixel data
+            wgpu::ImageCopyTexture {
+                texture: &texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            },
+            // The actual pixel data
+            &rgba,
+            // The layout of the texture
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: NonZeroU32::new(4 * dimensions.0),
+                rows_per_image: NonZeroU32::new(dimensions.1),
+            },
+            size,
+        );
+
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            // The address mode tells wgpu what to do when the sampler
+            // gets a texture coordinate that's outside the texture.
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            // The filter mode describes what to do when a fragment
+            // covers multiple pixels, or there are multiple fragments
+            // for a single pixel.
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Nearest,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            ..Default::default()
+        });
+
+        Texture {
+            texture,
+            view,
+            sampler,
+            size,
+        }
+    }
+
+    /// Layout entries for a texture and its sampler, bound at
+    /// `binding` and `binding + 1` respectively.
+    pub fn bind_group_layout_entry(binding: u32) -> [wgpu::BindGroupLayoutEntry; 2] {
+        [
+            wgpu::BindGroupLayoutEntry {
+                binding,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Texture {
+                    multisampled: false,
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                    sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: binding + 1,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                // This should match the filterable field of the
+                // corresponding Texture entry above.
+                ty: wgpu::BindingType::Sampler {
+                    comparison: false,
+                    filtering: true,
+                },
+                count: None,
+            },
+        ]
+    }
+
+    /// Bind group entries matching the layout from [`Texture::bind_group_layout_entry`].
+    pub fn bind_group_entries(&self, binding: u32) -> [wgpu::BindGroupEntry<'_>; 2] {
+        [
+            wgpu::BindGroupEntry {
+                binding,
+                resource: wgpu::BindingResource::TextureView(&self.view),
+            },
+            wgpu::BindGroupEntry {
+                binding: binding + 1,
+                resource: wgpu::BindingResource::Sampler(&self.sampler),
+            },
+        ]
+    }
+}
@@ -0,0 +1,260 @@
+use std::{fmt, num::NonZeroU32, path::Path};
+
+use image::GenericImageView;
+
+use crate::texture::Texture;
+
+#[derive(Debug)]
+pub enum TextureArrayError {
+    Image(image::ImageError),
+    /// There were no images to make layers from.
+    Empty,
+    /// An image wasn't the same size as the first, by its index.
+    SizeMismatch {
+        index: usize,
+        expected: (u32, u32),
+        found: (u32, u32),
+    },
+}
+
+impl fmt::Display for TextureArrayError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TextureArrayError::Image(err) => {
+                write!(f, "failed to decode texture array image: {}", err)
+            }
+            TextureArrayError::Empty => write!(f, "texture arrays need at least one image"),
+            TextureArrayError::SizeMismatch {
+                index,
+                expected,
+                found,
+            } => write!(
+                f,
+                "image {} of the texture array is {}x{}, but the first is {}x{}",
+                index, found.0, found.1, expected.0, expected.1
+            ),
+        }
+    }
+}
+
+impl std::error::Error for TextureArrayError {}
+
+impl From<image::ImageError> for TextureArrayError {
+    fn from(err: image::ImageError) -> Self {
+        TextureArrayError::Image(err)
+    }
+}
+
+/// The size every image shares, or which one doesn't match the first.
+fn common_size(sizes: &[(u32, u32)]) -> Result<(u32, u32), TextureArrayError> {
+    let (&expected, rest) = sizes.split_first().ok_or(TextureArrayError::Empty)?;
+    match rest.iter().position(|&size| size != expected) {
+        Some(index) => Err(TextureArrayError::SizeMismatch {
+            index: index + 1,
+            expected,
+            found: rest[index],
+        }),
+        None => Ok(expected),
+    }
+}
+
+/// Images of the same size stacked as the layers of one texture,
+/// sampled in shaders as a `texture_2d_array` by layer index.
+///
+/// Unlike a [`TextureAtlas`](crate::texture_atlas::TextureAtlas), the
+/// images can't bleed into each other at their edges, so each of
+/// them can repeat across a surface.
+pub struct TextureArray {
+    #[allow(dead_code)]
+    texture: wgpu::Texture,
+    view: wgpu::TextureView,
+    sampler: wgpu::Sampler,
+    size: wgpu::Extent3d,
+}
+
+impl TextureArray {
+    /// Loads each image into the layer of its index. The images are
+    /// all decoded before anything is uploaded, so nothing is made
+    /// when one of them can't be.
+    #[allow(dead_code)]
+    pub fn from_paths(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        paths: &[&Path],
+    ) -> Result<Self, TextureArrayError> {
+        let images = paths
+            .iter()
+            .map(image::open)
+            .collect::<Result<Vec<_>, _>>()?;
+        Self::from_images(device, queue, &images, Some("Texture Array"))
+    }
+
+    pub fn from_images(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        images: &[image::DynamicImage],
+        label: Option<&str>,
+    ) -> Result<Self, TextureArrayError> {
+        let sizes = images
+            .iter()
+            .map(|img| img.dimensions())
+            .collect::<Vec<_>>();
+        let (width, height) = common_size(&sizes)?;
+
+        let size = wgpu::Extent3d {
+            width,
+            height,
+            depth_or_array_layers: images.len() as u32,
+        };
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label,
+            size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: Texture::FORMAT,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+        });
+
+        for (layer, img) in images.iter().enumerate() {
+            queue.write_texture(
+                wgpu::ImageCopyTexture {
+                    texture: &texture,
+                    mip_level: 0,
+                    // The layer is picked by the origin's z.
+                    origin: wgpu::Origin3d {
+                        x: 0,
+                        y: 0,
+                        z: layer as u32,
+                    },
+                    aspect: wgpu::TextureAspect::All,
+                },
+                &img.to_rgba8(),
+                wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: NonZeroU32::new(4 * width),
+                    rows_per_image: NonZeroU32::new(height),
+                },
+                wgpu::Extent3d {
+                    depth_or_array_layers: 1,
+                    ..size
+                },
+            );
+        }
+
+        let view = texture.create_view(&wgpu::TextureViewDescriptor {
+            label,
+            dimension: Some(wgpu::TextureViewDimension::D2Array),
+            ..Default::default()
+        });
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            address_mode_u: wgpu::AddressMode::Repeat,
+            address_mode_v: wgpu::AddressMode::Repeat,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Nearest,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            ..Default::default()
+        });
+
+        Ok(TextureArray {
+            texture,
+            view,
+            sampler,
+            size,
+        })
+    }
+
+    #[allow(dead_code)]
+    pub fn texture(&self) -> &wgpu::Texture {
+        &self.texture
+    }
+
+    #[allow(dead_code)]
+    pub fn view(&self) -> &wgpu::TextureView {
+        &self.view
+    }
+
+    #[allow(dead_code)]
+    pub fn sampler(&self) -> &wgpu::Sampler {
+        &self.sampler
+    }
+
+    /// Number of images in the array.
+    pub fn layer_count(&self) -> u32 {
+        self.size.depth_or_array_layers
+    }
+
+    /// Layout entries for the array and its sampler, bound at
+    /// `binding` and `binding + 1` respectively.
+    pub fn bind_group_layout_entry(binding: u32) -> [wgpu::BindGroupLayoutEntry; 2] {
+        let [texture, sampler] = Texture::bind_group_layout_entry(binding);
+        [
+            wgpu::BindGroupLayoutEntry {
+                ty: wgpu::BindingType::Texture {
+                    multisampled: false,
+                    view_dimension: wgpu::TextureViewDimension::D2Array,
+                    sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                },
+                ..texture
+            },
+            sampler,
+        ]
+    }
+
+    /// Bind group entries matching the layout from
+    /// [`TextureArray::bind_group_layout_entry`].
+    pub fn bind_group_entries(&self, binding: u32) -> [wgpu::BindGroupEntry<'_>; 2] {
+        [
+            wgpu::BindGroupEntry {
+                binding,
+                resource: wgpu::BindingResource::TextureView(&self.view),
+            },
+            wgpu::BindGroupEntry {
+                binding: binding + 1,
+                resource: wgpu::BindingResource::Sampler(&self.sampler),
+            },
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::context::test_device;
+
+    #[test]
+    fn images_of_different_sizes_are_an_error() {
+        let result = common_size(&[(4, 4), (4, 4), (8, 4)]);
+        match result {
+            Err(TextureArrayError::SizeMismatch {
+                index,
+                expected,
+                found,
+            }) => {
+                assert_eq!(index, 2);
+                assert_eq!(expected, (4, 4));
+                assert_eq!(found, (8, 4));
+            }
+            other => panic!("expected a size mismatch, got {:?}", other),
+        }
+        assert!(matches!(common_size(&[]), Err(TextureArrayError::Empty)));
+        assert_eq!(common_size(&[(4, 2), (4, 2)]).unwrap(), (4, 2));
+    }
+
+    #[test]
+    fn mismatched_images_make_no_texture() {
+        let (device, queue) = match test_device() {
+            Some(device) => device,
+            None => return eprintln!("skipped, there's no adapter"),
+        };
+        let images = [
+            image::DynamicImage::new_rgba8(4, 4),
+            image::DynamicImage::new_rgba8(2, 2),
+        ];
+        let result = TextureArray::from_images(&device, &queue, &images, None);
+        assert!(matches!(
+            result,
+            Err(TextureArrayError::SizeMismatch { index: 1, .. })
+        ));
+    }
+}
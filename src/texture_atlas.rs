@@ -0,0 +1,346 @@
+use std::{collections::HashMap, fmt};
+
+use image::{GenericImage, RgbaImage};
+
+use crate::texture::Texture;
+
+/// Region of the atlas holding one image, in texture
+/// coordinates from the top left.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct UvRect {
+    pub u: f32,
+    pub v: f32,
+    pub width: f32,
+    pub height: f32,
+}
+
+impl UvRect {
+    /// As `[u, v, width, height]`, the way [`SpriteInstance`](crate::sprite::SpriteInstance)
+    /// takes it.
+    pub fn to_array(self) -> [f32; 4] {
+        [self.u, self.v, self.width, self.height]
+    }
+
+    /// An empty rectangle in the middle of this one. Sprites drawn
+    /// with it take the color of the middle of the image all over,
+    /// without filtering picking up anything around it.
+    pub fn center(self) -> UvRect {
+        UvRect {
+            u: self.u + self.width * 0.5,
+            v: self.v + self.height * 0.5,
+            width: 0.0,
+            height: 0.0,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum AtlasError {
+    Image(image::ImageError),
+    /// The images don't fit in an atlas of the maximum size.
+    TooLarge {
+        max_dimension: u32,
+    },
+}
+
+impl fmt::Display for AtlasError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AtlasError::Image(err) => write!(f, "failed to decode atlas image: {}", err),
+            AtlasError::TooLarge { max_dimension } => write!(
+                f,
+                "images don't fit in a {0}x{0} texture atlas",
+                max_dimension
+            ),
+        }
+    }
+}
+
+impl std::error::Error for AtlasError {}
+
+impl From<image::ImageError> for AtlasError {
+    fn from(err: image::ImageError) -> Self {
+        AtlasError::Image(err)
+    }
+}
+
+/// Many images packed into a single texture, so sprites
+/// using any of them can be drawn in one batch.
+pub struct TextureAtlas {
+    texture: Texture,
+    regions: HashMap<String, UvRect>,
+}
+
+impl TextureAtlas {
+    pub fn builder() -> TextureAtlasBuilder {
+        TextureAtlasBuilder::new()
+    }
+
+    pub fn texture(&self) -> &Texture {
+        &self.texture
+    }
+
+    pub fn regions(&self) -> &HashMap<String, UvRect> {
+        &self.regions
+    }
+
+    /// Where the named image is in the atlas, as `[u, v, width, height]`.
+    #[allow(dead_code)]
+    pub fn uv_for(&self, name: &str) -> Option<[f32; 4]> {
+        self.regions.get(name).map(|rect| rect.to_array())
+    }
+}
+
+/// An image given to the builder, decoded when the atlas is built.
+enum AtlasImage {
+    #[allow(dead_code)]
+    Encoded(Vec<u8>),
+    Rgba(RgbaImage),
+}
+
+pub struct TextureAtlasBuilder {
+    images: Vec<(String, AtlasImage)>,
+    max_dimension: u32,
+    padding: u32,
+}
+
+impl TextureAtlasBuilder {
+    pub fn new() -> Self {
+        TextureAtlasBuilder {
+            images: Vec::new(),
+            max_dimension: 4096,
+            padding: 1,
+        }
+    }
+
+    /// Adds an encoded image, such as the contents of a PNG file.
+    #[allow(dead_code)]
+    pub fn add_image(mut self, name: impl Into<String>, image_bytes: &[u8]) -> Self {
+        self.images
+            .push((name.into(), AtlasImage::Encoded(image_bytes.to_vec())));
+        self
+    }
+
+    /// Adds raw RGBA pixels, `width` by `height`, like the ones
+    /// [`ProcTexture`](crate::proc_texture::ProcTexture) generates.
+    ///
+    /// # Panics
+    ///
+    /// If `rgba` isn't `width * height * 4` bytes long.
+    pub fn add_rgba(
+        mut self,
+        name: impl Into<String>,
+        width: u32,
+        height: u32,
+        rgba: Vec<u8>,
+    ) -> Self {
+        let image = RgbaImage::from_raw(width, height, rgba)
+            .expect("RGBA pixels don't match the image's size");
+        self.images.push((name.into(), AtlasImage::Rgba(image)));
+        self
+    }
+
+    /// Largest width and height the atlas may grow to.
+    #[allow(dead_code)]
+    pub fn max_dimension(mut self, max_dimension: u32) -> Self {
+        self.max_dimension = max_dimension;
+        self
+    }
+
+    /// Empty pixels left between images, so filtering doesn't
+    /// bleed neighbouring images into each other.
+    #[allow(dead_code)]
+    pub fn padding(mut self, padding: u32) -> Self {
+        self.padding = padding;
+        self
+    }
+
+    pub fn build(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+    ) -> Result<TextureAtlas, AtlasError> {
+        let images = self
+            .images
+            .iter()
+            .map(|(name, image)| match image {
+                AtlasImage::Encoded(bytes) => {
+                    Ok((name, image::load_from_memory(bytes)?.to_rgba8()))
+                }
+                AtlasImage::Rgba(image) => Ok((name, image.clone())),
+            })
+            .collect::<Result<Vec<_>, AtlasError>>()?;
+
+        // Each image is packed with its padding on the right and bottom.
+        let sizes = images
+            .iter()
+            .map(|(_, image)| (image.width() + self.padding, image.height() + self.padding))
+            .collect::<Vec<_>>();
+        let Packing {
+            width,
+            height,
+            positions,
+        } = pack(&sizes, self.max_dimension).ok_or(AtlasError::TooLarge {
+            max_dimension: self.max_dimension,
+        })?;
+
+        let mut atlas = RgbaImage::new(width, height);
+        let mut regions = HashMap::new();
+        for ((name, image), (x, y)) in images.iter().zip(positions) {
+            // Can't fail, since the packing keeps every image inside the atlas.
+            atlas.copy_from(image, x, y)?;
+            regions.insert(
+                name.to_string(),
+                UvRect {
+                    u: x as f32 / width as f32,
+                    v: y as f32 / height as f32,
+                    width: image.width() as f32 / width as f32,
+                    height: image.height() as f32 / height as f32,
+                },
+            );
+        }
+
+        let texture = Texture::from_image(
+            device,
+            queue,
+            &image::DynamicImage::ImageRgba8(atlas),
+            Some("Texture Atlas"),
+        );
+
+        Ok(TextureAtlas { texture, regions })
+    }
+}
+
+impl Default for TextureAtlasBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Where rectangles were placed by [`pack`].
+pub struct Packing {
+    pub width: u32,
+    pub height: u32,
+    /// Top left corner of each rectangle, in the order they were given.
+    pub positions: Vec<(u32, u32)>,
+}
+
+/// Finds the smallest power of two atlas the rectangles fit in.
+pub fn pack(sizes: &[(u32, u32)], max_dimension: u32) -> Option<Packing> {
+    let area: u64 = sizes.iter().map(|(w, h)| *w as u64 * *h as u64).sum();
+    let widest = sizes.iter().map(|(w, _)| *w).max().unwrap_or(1);
+    let tallest = sizes.iter().map(|(_, h)| *h).max().unwrap_or(1);
+
+    let mut width = widest.max(1).next_power_of_two();
+    let mut height = tallest.max(1).next_power_of_two();
+    // No point trying sizes that can't hold the total area.
+    while (width as u64 * height as u64) < area {
+        if width <= height {
+            width *= 2;
+        } else {
+            height *= 2;
+        }
+    }
+
+    while width <= max_dimension && height <= max_dimension {
+        if let Some(positions) = pack_shelves(sizes, width, height) {
+            return Some(Packing {
+                width,
+                height,
+                positions,
+            });
+        }
+        if width <= height {
+            width *= 2;
+        } else {
+            height *= 2;
+        }
+    }
+
+    None
+}
+
+/// Shelf packing: rectangles are placed left to right in rows, tallest
+/// first, and a new row is started below the last one when a rectangle
+/// doesn't fit in the current one.
+fn pack_shelves(sizes: &[(u32, u32)], width: u32, height: u32) -> Option<Vec<(u32, u32)>> {
+    let mut order = (0..sizes.len()).collect::<Vec<_>>();
+    order.sort_by_key(|i| std::cmp::Reverse(sizes[*i].1));
+
+    let mut positions = vec![(0, 0); sizes.len()];
+    let (mut x, mut y) = (0, 0);
+    let mut shelf_height = 0;
+
+    for i in order {
+        let (w, h) = sizes[i];
+        if x + w > width {
+            // Start a new shelf.
+            x = 0;
+            y += shelf_height;
+            shelf_height = 0;
+        }
+        if x + w > width || y + h > height {
+            return None;
+        }
+
+        positions[i] = (x, y);
+        x += w;
+        shelf_height = shelf_height.max(h);
+    }
+
+    Some(positions)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn overlaps(a: ((u32, u32), (u32, u32)), b: ((u32, u32), (u32, u32))) -> bool {
+        let (((ax, ay), (aw, ah)), ((bx, by), (bw, bh))) = (a, b);
+        ax < bx + bw && bx < ax + aw && ay < by + bh && by < ay + ah
+    }
+
+    #[test]
+    fn packs_without_overlaps() {
+        let sizes = [
+            (64, 32),
+            (16, 16),
+            (100, 20),
+            (8, 120),
+            (33, 33),
+            (1, 1),
+            (50, 70),
+            (128, 10),
+            (20, 45),
+            (7, 90),
+        ];
+        let packing = pack(&sizes, 4096).expect("rectangles fit in the atlas");
+        assert!(packing.width.is_power_of_two());
+        assert!(packing.height.is_power_of_two());
+        assert_eq!(packing.positions.len(), sizes.len());
+
+        let rects = packing
+            .positions
+            .iter()
+            .copied()
+            .zip(sizes.iter().copied())
+            .collect::<Vec<_>>();
+        for (i, &((x, y), (w, h))) in rects.iter().enumerate() {
+            assert!(x + w <= packing.width && y + h <= packing.height);
+            for &other in &rects[i + 1..] {
+                assert!(
+                    !overlaps(rects[i], other),
+                    "{:?} overlaps {:?}",
+                    rects[i],
+                    other
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn too_large() {
+        assert!(pack(&[(300, 10)], 256).is_none());
+        assert!(pack(&[(200, 200), (200, 200)], 256).is_none());
+    }
+}
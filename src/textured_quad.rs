@@ -0,0 +1,194 @@
+use crate::{
+    bindless::BindlessTextureArray, pipeline::RenderPipelineBuilder,
+    render_pass_guard::RenderPassBuilder,
+};
+
+/// A rectangle to draw with one of a set of textures, as fractions of
+/// the screen from its top left.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct TexturedQuad {
+    /// Top left corner of the quad.
+    pub position: [f32; 2],
+    pub size: [f32; 2],
+    /// Which of the textures to draw with: what
+    /// [`BindlessTextureArray::register`] returned for it, or its
+    /// layer of a [`TextureArray`](crate::texture_array::TextureArray).
+    pub texture_id: u32,
+}
+
+impl TexturedQuad {
+    const ATTRIBUTES: [wgpu::VertexAttribute; 3] =
+        wgpu::vertex_attr_array![0 => Float32x2, 1 => Float32x2, 2 => Uint32];
+
+    /// One quad per instance, with its texture ID at location 2.
+    pub fn vertex_buffer_layout<'a>() -> wgpu::VertexBufferLayout<'a> {
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<TexturedQuad>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Instance,
+            attributes: &Self::ATTRIBUTES,
+        }
+    }
+}
+
+/// Draws [`TexturedQuad`]s on top of the frame in a single draw, each
+/// sampling the texture its ID picks.
+///
+/// The textures come from a [`BindlessTextureArray`] where the device
+/// can index one, or from the layers of a
+/// [`TextureArray`](crate::texture_array::TextureArray) otherwise,
+/// which all have to be the same size.
+pub struct TexturedQuadRenderer {
+    pipeline: wgpu::RenderPipeline,
+    instance_buffer: wgpu::Buffer,
+    quads: Vec<TexturedQuad>,
+}
+
+impl TexturedQuadRenderer {
+    /// Size of the texture array in `bindless_quad.wgsl`. Textures
+    /// registered past it can't be drawn.
+    pub const BINDLESS_TEXTURES: u32 = 128;
+    /// Most quads drawn in a frame.
+    pub const MAX_QUADS: usize = 1024;
+
+    /// Draws with the textures of `textures`, bound at group 0.
+    ///
+    /// `None` when the array has fewer slots than the shader's
+    /// [`BINDLESS_TEXTURES`](Self::BINDLESS_TEXTURES), which it can't
+    /// be bound with.
+    pub fn bindless(
+        device: &wgpu::Device,
+        textures: &BindlessTextureArray,
+        format: wgpu::TextureFormat,
+    ) -> Option<Self> {
+        if textures.max_textures() < Self::BINDLESS_TEXTURES {
+            log::warn!(
+                "bindless quads need {} texture slots, the array only has {}",
+                Self::BINDLESS_TEXTURES,
+                textures.max_textures()
+            );
+            return None;
+        }
+
+        // The SPIR-V is built from bindless_quad.wgsl by build.rs,
+        // with a naga that validates it.
+        let shader = unsafe {
+            device.create_shader_module_spirv(&wgpu::include_spirv_raw!(concat!(
+                env!("OUT_DIR"),
+                "/bindless_quad.spv"
+            )))
+        };
+        Some(Self::new(
+            device,
+            &shader,
+            textures.bind_group_layout(),
+            format,
+        ))
+    }
+
+    /// Draws with the layers of a texture array, bound at group 0 with
+    /// a layout of [`TextureArray::bind_group_layout_entry`](crate::texture_array::TextureArray::bind_group_layout_entry).
+    pub fn layered(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        format: wgpu::TextureFormat,
+    ) -> Self {
+        let shader = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+            label: Some("Layered Quad Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("layered_quad.wgsl").into()),
+        });
+        Self::new(device, &shader, layout, format)
+    }
+
+    fn new(
+        device: &wgpu::Device,
+        shader: &wgpu::ShaderModule,
+        layout: &wgpu::BindGroupLayout,
+        format: wgpu::TextureFormat,
+    ) -> Self {
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Textured Quad Pipeline Layout"),
+            bind_group_layouts: &[layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = RenderPipelineBuilder::new()
+            .label("Textured Quad Pipeline")
+            .vertex_shader(shader, "vs_main")
+            .vertex_layouts(&[TexturedQuad::vertex_buffer_layout()])
+            .fragment_shader(
+                shader,
+                "fs_main",
+                &[wgpu::ColorTargetState {
+                    format,
+                    blend: Some(wgpu::BlendState::REPLACE),
+                    write_mask: wgpu::ColorWrites::ALL,
+                }],
+            )
+            .topology(wgpu::PrimitiveTopology::TriangleStrip)
+            .cull_mode(None)
+            .build(device, &pipeline_layout)
+            .expect("failed to build textured quad pipeline");
+
+        let instance_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Textured Quad Instance Buffer"),
+            size: (Self::MAX_QUADS * std::mem::size_of::<TexturedQuad>()) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        TexturedQuadRenderer {
+            pipeline,
+            instance_buffer,
+            quads: Vec::new(),
+        }
+    }
+
+    /// Queues a quad to be drawn on the next flush. Quads past
+    /// [`MAX_QUADS`](Self::MAX_QUADS) are dropped.
+    pub fn draw(&mut self, quad: TexturedQuad) {
+        if self.quads.len() < Self::MAX_QUADS {
+            self.quads.push(quad);
+        }
+    }
+
+    /// Draws the queued quads into `view` with the textures in
+    /// `bind_group`, and clears the queue.
+    pub fn flush(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        view: &wgpu::TextureView,
+        bind_group: &wgpu::BindGroup,
+    ) {
+        if self.quads.is_empty() {
+            return;
+        }
+        queue.write_buffer(&self.instance_buffer, 0, bytemuck::cast_slice(&self.quads));
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Textured Quad Encoder"),
+        });
+        let mut render_pass = RenderPassBuilder::begin_render_pass(
+            &mut encoder,
+            &wgpu::RenderPassDescriptor {
+                label: Some("Textured Quad Pass"),
+                color_attachments: &[wgpu::RenderPassColorAttachment {
+                    view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Load,
+                        store: true,
+                    },
+                }],
+                depth_stencil_attachment: None,
+            },
+        );
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, bind_group, &[]);
+        render_pass.set_vertex_buffer(0, self.instance_buffer.slice(..));
+        render_pass.draw(0..4, 0..self.quads.len() as u32);
+        render_pass.end();
+        queue.submit(std::iter::once(encoder.finish()));
+        self.quads.clear();
+    }
+}
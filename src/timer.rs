@@ -0,0 +1,74 @@
+use std::{collections::VecDeque, time::Instant};
+
+/// Number of frames the FPS counter is averaged over.
+const SAMPLE_COUNT: usize = 60;
+
+/// Measures the time between frames.
+pub struct FrameTimer {
+    last_frame: Instant,
+    delta_time: f32,
+    /// Frame times of the most recent frames, in seconds.
+    samples: VecDeque<f32>,
+    sum: f32,
+}
+
+impl FrameTimer {
+    pub fn new() -> Self {
+        FrameTimer {
+            last_frame: Instant::now(),
+            delta_time: 0.0,
+            samples: VecDeque::with_capacity(SAMPLE_COUNT),
+            sum: 0.0,
+        }
+    }
+
+    /// Marks the start of a new frame. Must be called once per frame.
+    pub fn tick(&mut self) {
+        let now = Instant::now();
+        self.delta_time = (now - self.last_frame).as_secs_f32();
+        self.last_frame = now;
+
+        if self.samples.len() == SAMPLE_COUNT {
+            if let Some(oldest) = self.samples.pop_front() {
+                self.sum -= oldest;
+            }
+        }
+        self.samples.push_back(self.delta_time);
+        self.sum += self.delta_time;
+    }
+
+    /// Seconds elapsed between the last two ticks.
+    pub fn delta_time(&self) -> f32 {
+        self.delta_time
+    }
+
+    /// Frame time in seconds, averaged over the last 60 frames.
+    pub fn average_frame_time(&self) -> f32 {
+        if self.samples.is_empty() {
+            0.0
+        } else {
+            self.sum / self.samples.len() as f32
+        }
+    }
+
+    /// Frame times of the last 60 frames in seconds, oldest first.
+    pub fn samples(&self) -> impl Iterator<Item = f32> + '_ {
+        self.samples.iter().copied()
+    }
+
+    /// Frames per second, averaged over the last 60 frames.
+    pub fn fps(&self) -> f32 {
+        let frame_time = self.average_frame_time();
+        if frame_time > 0.0 {
+            1.0 / frame_time
+        } else {
+            0.0
+        }
+    }
+}
+
+impl Default for FrameTimer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
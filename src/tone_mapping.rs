@@ -0,0 +1,261 @@
+use crate::{
+    fullscreen_triangle::FullscreenTriangle,
+    hdr_render_target::HdrRenderTarget,
+    pipeline::RenderPipelineBuilder,
+    render_graph::{RenderPass, RenderResources},
+    render_pass_guard::RenderPassBuilder,
+    uniform::UniformBinding,
+};
+
+/// Curve squeezing HDR colors into the range of the surface.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum ToneMapOperator {
+    Reinhard,
+    #[default]
+    Aces,
+    Uncharted2,
+}
+
+impl ToneMapOperator {
+    /// The operator after this one, wrapping around.
+    pub fn next(self) -> Self {
+        match self {
+            ToneMapOperator::Reinhard => ToneMapOperator::Aces,
+            ToneMapOperator::Aces => ToneMapOperator::Uncharted2,
+            ToneMapOperator::Uncharted2 => ToneMapOperator::Reinhard,
+        }
+    }
+
+    /// Must match the operators in the shader.
+    fn index(self) -> u32 {
+        match self {
+            ToneMapOperator::Reinhard => 0,
+            ToneMapOperator::Aces => 1,
+            ToneMapOperator::Uncharted2 => 2,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct ToneMapSettings {
+    exposure: f32,
+    operator: u32,
+    encode_srgb: u32,
+    _padding: u32,
+}
+
+/// Draws the HDR frame to the surface, through a tone mapping operator.
+///
+/// The frame is drawn into the pass's HDR target, and this runs last,
+/// converting it to the surface's format. Surfaces that aren't sRGB
+/// get the gamma correction done by the shader instead.
+pub struct ToneMappingPass {
+    target: HdrRenderTarget,
+    sampler: wgpu::Sampler,
+    bind_group_layout: wgpu::BindGroupLayout,
+    bind_group: wgpu::BindGroup,
+    settings: UniformBinding<ToneMapSettings>,
+    triangle: FullscreenTriangle,
+    operator: ToneMapOperator,
+    exposure: f32,
+    encode_srgb: bool,
+}
+
+impl ToneMappingPass {
+    pub fn new(
+        device: &wgpu::Device,
+        config: &wgpu::SurfaceConfiguration,
+        surface_is_srgb: bool,
+        operator: ToneMapOperator,
+        exposure: f32,
+    ) -> Self {
+        let target = HdrRenderTarget::new(device, config.width, config.height);
+
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Tone Mapping Sampler"),
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            ..Default::default()
+        });
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Tone Mapping Bind Group Layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler {
+                        filtering: true,
+                        comparison: false,
+                    },
+                    count: None,
+                },
+            ],
+        });
+        let bind_group = create_bind_group(device, &bind_group_layout, &target, &sampler);
+
+        let encode_srgb = !surface_is_srgb;
+        let settings = UniformBinding::new(
+            device,
+            "Tone Mapping Settings",
+            wgpu::ShaderStages::FRAGMENT,
+            &ToneMapSettings {
+                exposure,
+                operator: operator.index(),
+                encode_srgb: encode_srgb as u32,
+                _padding: 0,
+            },
+        );
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Tone Mapping Pipeline Layout"),
+            bind_group_layouts: &[&bind_group_layout, &settings.bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let vertex_shader = FullscreenTriangle::vertex_shader(device);
+        let fragment_shader = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+            label: Some("Tone Mapping Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("tone_mapping.wgsl").into()),
+        });
+        let pipeline = RenderPipelineBuilder::new()
+            .label("Tone Mapping Pipeline")
+            .vertex_shader(&vertex_shader, "main")
+            .fragment_shader(
+                &fragment_shader,
+                "main",
+                &[wgpu::ColorTargetState {
+                    format: config.format,
+                    blend: Some(wgpu::BlendState::REPLACE),
+                    write_mask: wgpu::ColorWrites::ALL,
+                }],
+            )
+            .cull_mode(None)
+            .build(device, &pipeline_layout)
+            .expect("failed to build tone mapping pipeline");
+        let triangle = FullscreenTriangle::new(pipeline);
+
+        ToneMappingPass {
+            target,
+            sampler,
+            bind_group_layout,
+            bind_group,
+            settings,
+            triangle,
+            operator,
+            exposure,
+            encode_srgb,
+        }
+    }
+
+    /// The HDR view the frame should be drawn into.
+    pub fn target_view(&self) -> &wgpu::TextureView {
+        self.target.view()
+    }
+
+    pub fn operator(&self) -> ToneMapOperator {
+        self.operator
+    }
+
+    pub fn set_operator(&mut self, queue: &wgpu::Queue, operator: ToneMapOperator) {
+        self.operator = operator;
+        self.write_settings(queue);
+    }
+
+    #[allow(dead_code)]
+    pub fn exposure(&self) -> f32 {
+        self.exposure
+    }
+
+    /// Scales the HDR colors before they're tone mapped.
+    pub fn set_exposure(&mut self, queue: &wgpu::Queue, exposure: f32) {
+        self.exposure = exposure;
+        self.write_settings(queue);
+    }
+
+    fn write_settings(&self, queue: &wgpu::Queue) {
+        self.settings.update(
+            queue,
+            &ToneMapSettings {
+                exposure: self.exposure,
+                operator: self.operator.index(),
+                encode_srgb: self.encode_srgb as u32,
+                _padding: 0,
+            },
+        );
+    }
+
+    /// Recreates the HDR target to match the new surface size.
+    pub fn resize(&mut self, device: &wgpu::Device, width: u32, height: u32) {
+        self.target.resize(device, width, height);
+        self.bind_group =
+            create_bind_group(device, &self.bind_group_layout, &self.target, &self.sampler);
+    }
+
+    /// Tone maps the HDR target into `output`.
+    pub fn run(&self, encoder: &mut wgpu::CommandEncoder, output: &wgpu::TextureView) {
+        let mut render_pass = RenderPassBuilder::begin_render_pass(
+            encoder,
+            &wgpu::RenderPassDescriptor {
+                label: Some("Tone Mapping Pass"),
+                color_attachments: &[wgpu::RenderPassColorAttachment {
+                    view: output,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        // Every pixel is drawn over, so there's no need to clear.
+                        load: wgpu::LoadOp::Load,
+                        store: true,
+                    },
+                }],
+                depth_stencil_attachment: None,
+            },
+        );
+
+        render_pass.set_bind_group(0, &self.bind_group, &[]);
+        render_pass.set_bind_group(1, &self.settings.bind_group, &[]);
+        self.triangle.draw(&mut render_pass);
+    }
+}
+
+/// Draws into the graph's output.
+impl RenderPass for ToneMappingPass {
+    fn execute(&self, encoder: &mut wgpu::CommandEncoder, resources: &RenderResources) {
+        self.run(encoder, resources.output);
+    }
+}
+
+fn create_bind_group(
+    device: &wgpu::Device,
+    layout: &wgpu::BindGroupLayout,
+    target: &HdrRenderTarget,
+    sampler: &wgpu::Sampler,
+) -> wgpu::BindGroup {
+    device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some("Tone Mapping Bind Group"),
+        layout,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(target.view()),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: wgpu::BindingResource::Sampler(sampler),
+            },
+        ],
+    })
+}
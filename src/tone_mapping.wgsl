@@ -0,0 +1,75 @@
+// Maps the HDR frame into the range of the surface. The frame is
+// scaled by the exposure first, then squeezed by the selected
+// operator, and finally encoded as sRGB when the surface won't.
+
+[[block]]
+struct ToneMapping {
+    exposure: f32;
+    // 0 is Reinhard, 1 is ACES and 2 is Uncharted 2.
+    operator: u32;
+    // Whether the surface expects the colors to be sRGB encoded already.
+    encode_srgb: u32;
+};
+
+[[group(0), binding(0)]]
+var t_hdr: texture_2d<f32>;
+[[group(0), binding(1)]]
+var s_hdr: sampler;
+
+[[group(1), binding(0)]]
+var<uniform> tone_mapping: ToneMapping;
+
+fn reinhard(color: vec3<f32>) -> vec3<f32> {
+    return color / (vec3<f32>(1.0) + color);
+}
+
+// Krzysztof Narkowicz's fit of the ACES filmic curve.
+fn aces(color: vec3<f32>) -> vec3<f32> {
+    let a = 2.51;
+    let b = 0.03;
+    let c = 2.43;
+    let d = 0.59;
+    let e = 0.14;
+    return clamp((color * (a * color + b)) / (color * (c * color + d) + e), vec3<f32>(0.0), vec3<f32>(1.0));
+}
+
+fn uncharted2_curve(x: vec3<f32>) -> vec3<f32> {
+    let a = 0.15;
+    let b = 0.50;
+    let c = 0.10;
+    let d = 0.20;
+    let e = 0.02;
+    let f = 0.30;
+    return ((x * (a * x + c * b) + d * e) / (x * (a * x + b) + d * f)) - e / f;
+}
+
+// John Hable's filmic curve, scaled so that the white point maps to 1.
+fn uncharted2(color: vec3<f32>) -> vec3<f32> {
+    let white = vec3<f32>(11.2);
+    return uncharted2_curve(color * 2.0) / uncharted2_curve(white);
+}
+
+fn linear_to_srgb(color: vec3<f32>) -> vec3<f32> {
+    let low = color * 12.92;
+    let high = 1.055 * pow(color, vec3<f32>(1.0 / 2.4)) - 0.055;
+    return select(high, low, color <= vec3<f32>(0.0031308));
+}
+
+[[stage(fragment)]]
+fn main([[location(0)]] tex_coords: vec2<f32>) -> [[location(0)]] vec4<f32> {
+    let hdr = textureSample(t_hdr, s_hdr, tex_coords).rgb * tone_mapping.exposure;
+
+    var color: vec3<f32>;
+    if (tone_mapping.operator == 0u) {
+        color = reinhard(hdr);
+    } elseif (tone_mapping.operator == 1u) {
+        color = aces(hdr);
+    } else {
+        color = uncharted2(hdr);
+    }
+
+    if (tone_mapping.encode_srgb != 0u) {
+        color = linear_to_srgb(color);
+    }
+    return vec4<f32>(color, 1.0);
+}
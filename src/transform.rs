@@ -0,0 +1,125 @@
+use cgmath::{Matrix4, Quaternion, Rad, Vector3};
+
+/// Position, orientation and size of an object in the scene.
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct Transform {
+    pub translation: [f32; 3],
+    /// Unit quaternion stored as `[x, y, z, w]`.
+    pub rotation: [f32; 4],
+    pub scale: [f32; 3],
+}
+
+impl Transform {
+    pub fn identity() -> Self {
+        Transform {
+            translation: [0.0, 0.0, 0.0],
+            rotation: [0.0, 0.0, 0.0, 1.0],
+            scale: [1.0, 1.0, 1.0],
+        }
+    }
+
+    pub fn from_translation(translation: [f32; 3]) -> Self {
+        Transform {
+            translation,
+            ..Self::identity()
+        }
+    }
+
+    /// Rotation from angles in radians, applied around
+    /// the X axis first, then Y, then Z.
+    pub fn from_euler_xyz(x: f32, y: f32, z: f32) -> Self {
+        let rotation = Quaternion::from(cgmath::Euler::new(Rad(x), Rad(y), Rad(z)));
+        Transform {
+            rotation: rotation.into(),
+            ..Self::identity()
+        }
+    }
+
+    /// The model matrix, which scales first, then rotates,
+    /// then translates.
+    pub fn to_matrix(self) -> [[f32; 4]; 4] {
+        let translation = Matrix4::from_translation(Vector3::from(self.translation));
+        let rotation = Matrix4::from(Quaternion::from(self.rotation));
+        let scale = Matrix4::from_nonuniform_scale(self.scale[0], self.scale[1], self.scale[2]);
+
+        (translation * rotation * scale).into()
+    }
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Self::identity()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::InnerSpace;
+
+    use super::*;
+
+    fn assert_matrix_eq(actual: [[f32; 4]; 4], expected: [[f32; 4]; 4]) {
+        for (column, (a, e)) in actual.iter().zip(expected.iter()).enumerate() {
+            for row in 0..4 {
+                assert!(
+                    (a[row] - e[row]).abs() < 1e-6,
+                    "{:?} != {:?} at column {}, row {}",
+                    actual,
+                    expected,
+                    column,
+                    row
+                );
+            }
+        }
+    }
+
+    const IDENTITY: [[f32; 4]; 4] = [
+        [1.0, 0.0, 0.0, 0.0],
+        [0.0, 1.0, 0.0, 0.0],
+        [0.0, 0.0, 1.0, 0.0],
+        [0.0, 0.0, 0.0, 1.0],
+    ];
+
+    #[test]
+    fn identity() {
+        assert_matrix_eq(Transform::identity().to_matrix(), IDENTITY);
+        assert_matrix_eq(
+            Transform::from_euler_xyz(0.0, 0.0, 0.0).to_matrix(),
+            IDENTITY,
+        );
+    }
+
+    #[test]
+    fn translation() {
+        let matrix = Transform::from_translation([1.0, 2.0, 3.0]).to_matrix();
+        let mut expected = IDENTITY;
+        expected[3] = [1.0, 2.0, 3.0, 1.0];
+        assert_matrix_eq(matrix, expected);
+    }
+
+    #[test]
+    fn quarter_turn_around_z() {
+        // Columns are where the axes end up: X turns into Y, and Y into -X.
+        let matrix = Transform::from_euler_xyz(0.0, 0.0, std::f32::consts::FRAC_PI_2).to_matrix();
+        assert_matrix_eq(
+            matrix,
+            [
+                [0.0, 1.0, 0.0, 0.0],
+                [-1.0, 0.0, 0.0, 0.0],
+                [0.0, 0.0, 1.0, 0.0],
+                [0.0, 0.0, 0.0, 1.0],
+            ],
+        );
+    }
+
+    #[test]
+    fn scales_then_rotates_then_translates() {
+        let transform = Transform {
+            translation: [1.0, 0.0, 0.0],
+            scale: [2.0, 2.0, 2.0],
+            ..Transform::from_euler_xyz(0.0, 0.0, std::f32::consts::FRAC_PI_2)
+        };
+        let point = Matrix4::from(transform.to_matrix()) * cgmath::vec4(1.0, 0.0, 0.0, 1.0);
+        assert!((point - cgmath::vec4(1.0, 2.0, 0.0, 1.0)).magnitude() < 1e-6);
+    }
+}
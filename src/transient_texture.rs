@@ -0,0 +1,361 @@
+use std::{collections::HashMap, fmt};
+
+/// What a transient texture is needed for. Textures are only
+/// shared between users asking for the same description.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TransientTextureDesc {
+    pub width: u32,
+    pub height: u32,
+    pub format: wgpu::TextureFormat,
+    pub usage: wgpu::TextureUsages,
+}
+
+impl TransientTextureDesc {
+    /// A texture that's drawn into by one pass, and sampled by later ones.
+    #[allow(dead_code)]
+    pub fn render_target(width: u32, height: u32, format: wgpu::TextureFormat) -> Self {
+        TransientTextureDesc {
+            width,
+            height,
+            format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+        }
+    }
+}
+
+/// Handle to a texture in a [`TransientTexturePool`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TextureHandle(usize);
+
+/// What a pooled texture was created for, and whether it's handed out.
+/// Kept apart from the textures so which users share a texture can be
+/// worked out before any are created.
+struct Slot {
+    desc: TransientTextureDesc,
+    in_use: bool,
+}
+
+/// A transient texture some pass of a render graph needs.
+///
+/// It's written by `first_pass`, and isn't needed anymore
+/// once `last_pass` has run.
+pub struct TransientUsage<'a> {
+    pub name: &'static str,
+    pub desc: TransientTextureDesc,
+    pub first_pass: &'a str,
+    pub last_pass: &'a str,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TransientTextureError {
+    UnknownPass {
+        texture: &'static str,
+        pass: String,
+    },
+    /// The texture's last pass runs before the pass writing it.
+    ReleasedBeforeWritten {
+        texture: &'static str,
+        first_pass: String,
+        last_pass: String,
+    },
+}
+
+impl fmt::Display for TransientTextureError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TransientTextureError::UnknownPass { texture, pass } => write!(
+                f,
+                "transient texture {:?} is used by unknown pass {:?}",
+                texture, pass
+            ),
+            TransientTextureError::ReleasedBeforeWritten {
+                texture,
+                first_pass,
+                last_pass,
+            } => write!(
+                f,
+                "transient texture {:?} is last used by {:?}, which runs before {:?} writes it",
+                texture, last_pass, first_pass
+            ),
+        }
+    }
+}
+
+impl std::error::Error for TransientTextureError {}
+
+/// Textures that are only needed for part of a frame, such as the
+/// intermediate results of a post-processing chain.
+///
+/// A texture released by one user is handed to the next one asking
+/// for the same description, so users that aren't needed at the same
+/// time end up sharing one allocation.
+pub struct TransientTexturePool {
+    slots: Vec<Slot>,
+    /// The texture of each slot, created by [`create_textures`](Self::create_textures).
+    textures: Vec<(wgpu::Texture, wgpu::TextureView)>,
+}
+
+impl TransientTexturePool {
+    pub fn new() -> Self {
+        TransientTexturePool {
+            slots: Vec::new(),
+            textures: Vec::new(),
+        }
+    }
+
+    /// Hands out a free texture matching `desc`, or creates one.
+    #[allow(dead_code)]
+    pub fn acquire(&mut self, device: &wgpu::Device, desc: &TransientTextureDesc) -> TextureHandle {
+        let handle = self.acquire_slot(desc);
+        self.create_textures(device);
+        handle
+    }
+
+    fn acquire_slot(&mut self, desc: &TransientTextureDesc) -> TextureHandle {
+        if let Some(index) = self
+            .slots
+            .iter()
+            .position(|slot| !slot.in_use && slot.desc == *desc)
+        {
+            self.slots[index].in_use = true;
+            return TextureHandle(index);
+        }
+
+        self.slots.push(Slot {
+            desc: *desc,
+            in_use: true,
+        });
+        TextureHandle(self.slots.len() - 1)
+    }
+
+    /// Creates the textures of slots added since the last call.
+    fn create_textures(&mut self, device: &wgpu::Device) {
+        for (index, slot) in self.slots.iter().enumerate().skip(self.textures.len()) {
+            let texture = device.create_texture(&wgpu::TextureDescriptor {
+                label: Some(&format!("Transient Texture {}", index)),
+                size: wgpu::Extent3d {
+                    width: slot.desc.width,
+                    height: slot.desc.height,
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: slot.desc.format,
+                usage: slot.desc.usage,
+            });
+            let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+            self.textures.push((texture, view));
+        }
+    }
+
+    /// Makes the texture available to the next `acquire`.
+    pub fn release(&mut self, handle: TextureHandle) {
+        self.slots[handle.0].in_use = false;
+    }
+
+    /// Acquires a texture for every usage, taking the passes in `order`,
+    /// which should come from [`RenderGraph::pass_order`](crate::render_graph::RenderGraph::pass_order).
+    ///
+    /// Each texture is released once its last pass has run, so usages
+    /// whose passes don't overlap are given the same texture. The
+    /// textures are all released again when this returns, so the
+    /// handles can be kept for the next frames, until the pool is
+    /// [cleared](Self::clear).
+    ///
+    /// Nothing is acquired when a usage names a pass that isn't in
+    /// `order`, or a last pass running before its first one.
+    pub fn allocate(
+        &mut self,
+        device: &wgpu::Device,
+        order: &[&str],
+        usages: &[TransientUsage],
+    ) -> Result<HashMap<&'static str, TextureHandle>, TransientTextureError> {
+        let handles = self.assign(order, usages)?;
+        self.create_textures(device);
+        Ok(handles)
+    }
+
+    /// Works out which slot each usage gets for [`allocate`](Self::allocate).
+    fn assign(
+        &mut self,
+        order: &[&str],
+        usages: &[TransientUsage],
+    ) -> Result<HashMap<&'static str, TextureHandle>, TransientTextureError> {
+        let position = |texture: &'static str, pass: &str| {
+            order.iter().position(|name| *name == pass).ok_or_else(|| {
+                TransientTextureError::UnknownPass {
+                    texture,
+                    pass: pass.to_string(),
+                }
+            })
+        };
+
+        // The steps each usage is acquired and released at.
+        let mut lifetimes = Vec::with_capacity(usages.len());
+        for usage in usages {
+            let first = position(usage.name, usage.first_pass)?;
+            let last = position(usage.name, usage.last_pass)?;
+            if last < first {
+                return Err(TransientTextureError::ReleasedBeforeWritten {
+                    texture: usage.name,
+                    first_pass: usage.first_pass.to_string(),
+                    last_pass: usage.last_pass.to_string(),
+                });
+            }
+            lifetimes.push((usage, first, last));
+        }
+
+        let mut handles = HashMap::new();
+        for step in 0..order.len() {
+            // Textures first written by this pass.
+            for (usage, _, _) in lifetimes.iter().filter(|(_, first, _)| *first == step) {
+                handles.insert(usage.name, self.acquire_slot(&usage.desc));
+            }
+            // Textures no longer needed after this pass.
+            for (usage, _, _) in lifetimes.iter().filter(|(_, _, last)| *last == step) {
+                self.release(handles[usage.name]);
+            }
+        }
+
+        Ok(handles)
+    }
+
+    pub fn texture(&self, handle: TextureHandle) -> &wgpu::Texture {
+        &self.textures[handle.0].0
+    }
+
+    pub fn view(&self, handle: TextureHandle) -> &wgpu::TextureView {
+        &self.textures[handle.0].1
+    }
+
+    /// Number of textures created, shared or not.
+    #[allow(dead_code)]
+    pub fn allocation_count(&self) -> usize {
+        self.slots.len()
+    }
+
+    /// Drops every texture, for when the surface is resized
+    /// and the old sizes won't be asked for again.
+    pub fn clear(&mut self) {
+        self.slots.clear();
+        self.textures.clear();
+    }
+}
+
+impl Default for TransientTexturePool {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ORDER: &[&str] = &["scene", "blur", "composite", "tone_mapping"];
+
+    fn desc() -> TransientTextureDesc {
+        TransientTextureDesc::render_target(640, 480, wgpu::TextureFormat::Rgba16Float)
+    }
+
+    fn usage(
+        name: &'static str,
+        first_pass: &'static str,
+        last_pass: &'static str,
+    ) -> TransientUsage<'static> {
+        TransientUsage {
+            name,
+            desc: desc(),
+            first_pass,
+            last_pass,
+        }
+    }
+
+    #[test]
+    fn successive_passes_share_a_texture() {
+        let mut pool = TransientTexturePool::new();
+        let handles = pool
+            .assign(
+                ORDER,
+                &[
+                    usage("blurred", "blur", "blur"),
+                    usage("composited", "composite", "composite"),
+                ],
+            )
+            .unwrap();
+
+        assert_eq!(handles["blurred"], handles["composited"]);
+        assert_eq!(pool.allocation_count(), 1);
+    }
+
+    #[test]
+    fn overlapping_usages_get_their_own_textures() {
+        let mut pool = TransientTexturePool::new();
+        let handles = pool
+            .assign(
+                ORDER,
+                &[
+                    usage("blurred", "blur", "composite"),
+                    usage("composited", "composite", "tone_mapping"),
+                ],
+            )
+            .unwrap();
+
+        assert_ne!(handles["blurred"], handles["composited"]);
+        assert_eq!(pool.allocation_count(), 2);
+    }
+
+    #[test]
+    fn different_descriptions_get_their_own_textures() {
+        let mut pool = TransientTexturePool::new();
+        let mut smaller = usage("composited", "composite", "composite");
+        smaller.desc.width /= 2;
+        let handles = pool
+            .assign(ORDER, &[usage("blurred", "blur", "blur"), smaller])
+            .unwrap();
+
+        assert_ne!(handles["blurred"], handles["composited"]);
+    }
+
+    #[test]
+    fn handles_are_reused_next_frame() {
+        let mut pool = TransientTexturePool::new();
+        let usages = [usage("blurred", "blur", "composite")];
+        let first = pool.assign(ORDER, &usages).unwrap();
+        let second = pool.assign(ORDER, &usages).unwrap();
+
+        assert_eq!(first, second);
+        assert_eq!(pool.allocation_count(), 1);
+    }
+
+    #[test]
+    fn released_before_written() {
+        let mut pool = TransientTexturePool::new();
+        let result = pool.assign(ORDER, &[usage("blurred", "composite", "blur")]);
+
+        assert_eq!(
+            result,
+            Err(TransientTextureError::ReleasedBeforeWritten {
+                texture: "blurred",
+                first_pass: "composite".to_string(),
+                last_pass: "blur".to_string(),
+            })
+        );
+        assert_eq!(pool.allocation_count(), 0);
+    }
+
+    #[test]
+    fn unknown_pass() {
+        let mut pool = TransientTexturePool::new();
+        let result = pool.assign(ORDER, &[usage("blurred", "blur", "ssao")]);
+
+        assert_eq!(
+            result,
+            Err(TransientTextureError::UnknownPass {
+                texture: "blurred",
+                pass: "ssao".to_string(),
+            })
+        );
+    }
+}
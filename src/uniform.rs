@@ -0,0 +1,295 @@
+use std::marker::PhantomData;
+
+use wgpu::util::DeviceExt;
+
+/// A uniform buffer holding a single `T`, together with the
+/// bind group used to expose it to shaders at binding 0.
+pub struct UniformBinding<T: bytemuck::Pod> {
+    pub buffer: wgpu::Buffer,
+    pub bind_group_layout: wgpu::BindGroupLayout,
+    pub bind_group: wgpu::BindGroup,
+    _marker: PhantomData<T>,
+}
+
+impl<T: bytemuck::Pod> UniformBinding<T> {
+    /// Entries in the bind group, which is just the uniform.
+    pub const BIND_GROUP_ENTRIES: usize = 1;
+
+    pub fn new(
+        device: &wgpu::Device,
+        label: &str,
+        visibility: wgpu::ShaderStages,
+        value: &T,
+    ) -> Self {
+        let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some(label),
+            contents: bytemuck::bytes_of(value),
+            // COPY_DST allows us to write to the buffer after creation.
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some(label),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                // Which shader stages can see the uniform.
+                visibility,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    // The data in the buffer doesn't change size,
+                    // so dynamic offsets aren't needed.
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+        });
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some(label),
+            layout: &bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: buffer.as_entire_binding(),
+            }],
+        });
+
+        UniformBinding {
+            buffer,
+            bind_group_layout,
+            bind_group,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Writes a new value into the uniform buffer.
+    pub fn update(&self, queue: &wgpu::Queue, value: &T) {
+        queue.write_buffer(&self.buffer, 0, bytemuck::bytes_of(value));
+    }
+}
+
+/// A uniform buffer holding an array of `T`, which can be
+/// rewritten in whole or one element at a time.
+///
+/// Elements of arrays in uniform buffers are laid out 16 bytes
+/// apart, so `T` has to be padded to a multiple of 16 bytes.
+pub struct UniformArrayBuffer<T: bytemuck::Pod> {
+    buffer: wgpu::Buffer,
+    capacity: u32,
+    len: u32,
+    _marker: PhantomData<T>,
+}
+
+impl<T: bytemuck::Pod> UniformArrayBuffer<T> {
+    const STRIDE: wgpu::BufferAddress = std::mem::size_of::<T>() as wgpu::BufferAddress;
+
+    /// Allocates room for `capacity` elements.
+    pub fn new(device: &wgpu::Device, label: &str, capacity: u32) -> Self {
+        assert!(
+            Self::STRIDE % 16 == 0,
+            "uniform array elements must be a multiple of 16 bytes, {} is {} bytes",
+            std::any::type_name::<T>(),
+            Self::STRIDE
+        );
+
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some(label),
+            size: Self::STRIDE * capacity as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::UNIFORM
+                | wgpu::BufferUsages::COPY_DST
+                | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+
+        UniformArrayBuffer {
+            buffer,
+            capacity,
+            len: 0,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Overwrites a single element, leaving the others as they are.
+    ///
+    /// # Panics
+    ///
+    /// When `index` is outside of the buffer's capacity.
+    #[allow(dead_code)]
+    pub fn update_element(&mut self, queue: &wgpu::Queue, index: u32, value: &T) {
+        assert!(
+            index < self.capacity,
+            "index {} is outside of uniform array with capacity {}",
+            index,
+            self.capacity
+        );
+
+        let offset = index as wgpu::BufferAddress * Self::STRIDE;
+        queue.write_buffer(&self.buffer, offset, bytemuck::bytes_of(value));
+        self.len = self.len.max(index + 1);
+    }
+
+    /// Replaces the contents of the buffer with the given values.
+    ///
+    /// Values beyond the buffer's capacity are dropped.
+    pub fn update_all(&mut self, queue: &wgpu::Queue, values: &[T]) {
+        let count = values.len().min(self.capacity as usize);
+        if count < values.len() {
+            log::warn!(
+                "uniform array holds {} elements, dropping {}",
+                self.capacity,
+                values.len() - count
+            );
+        }
+
+        queue.write_buffer(&self.buffer, 0, bytemuck::cast_slice(&values[..count]));
+        self.len = count as u32;
+    }
+
+    /// Layout entry for binding the whole array as a uniform buffer.
+    pub fn bind_group_layout_entry(
+        &self,
+        binding: u32,
+        visibility: wgpu::ShaderStages,
+    ) -> wgpu::BindGroupLayoutEntry {
+        wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size: wgpu::BufferSize::new(self.buffer_size()),
+            },
+            count: None,
+        }
+    }
+
+    pub fn as_entire_binding(&self) -> wgpu::BindingResource<'_> {
+        self.buffer.as_entire_binding()
+    }
+
+    #[allow(dead_code)]
+    pub fn buffer(&self) -> &wgpu::Buffer {
+        &self.buffer
+    }
+
+    fn buffer_size(&self) -> wgpu::BufferAddress {
+        Self::STRIDE * self.capacity as wgpu::BufferAddress
+    }
+
+    #[allow(dead_code)]
+    pub fn capacity(&self) -> u32 {
+        self.capacity
+    }
+
+    /// Number of elements written, up to the highest one updated.
+    pub fn len(&self) -> u32 {
+        self.len
+    }
+
+    #[allow(dead_code)]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+/// How far apart values of `size` bytes go in a uniform buffer that's
+/// bound at a dynamic offset for each of them. The offsets have to be
+/// multiples of the device's `min_uniform_buffer_offset_alignment`.
+pub fn dynamic_offset_stride(device: &wgpu::Device, size: usize) -> wgpu::BufferAddress {
+    align_to(
+        size as wgpu::BufferAddress,
+        device.limits().min_uniform_buffer_offset_alignment as wgpu::BufferAddress,
+    )
+}
+
+/// `value` rounded up to a multiple of `alignment`.
+fn align_to(value: wgpu::BufferAddress, alignment: wgpu::BufferAddress) -> wgpu::BufferAddress {
+    value.div_ceil(alignment) * alignment
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::context::test_device;
+
+    #[repr(C)]
+    #[derive(Debug, Clone, Copy, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+    struct Element([u32; 4]);
+
+    fn read_back(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        buffer: &wgpu::Buffer,
+        size: u64,
+    ) -> Vec<Element> {
+        let staging = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Uniform Array Readback"),
+            size,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+        let mut encoder = device.create_command_encoder(&Default::default());
+        encoder.copy_buffer_to_buffer(buffer, 0, &staging, 0, size);
+        queue.submit(Some(encoder.finish()));
+
+        let slice = staging.slice(..);
+        let mapping = slice.map_async(wgpu::MapMode::Read);
+        device.poll(wgpu::Maintain::Wait);
+        pollster::block_on(mapping).expect("failed to map the readback buffer");
+        let elements = bytemuck::cast_slice(&slice.get_mapped_range()).to_vec();
+        elements
+    }
+
+    #[test]
+    fn strides_are_rounded_up_to_the_alignment() {
+        assert_eq!(align_to(16, 256), 256);
+        assert_eq!(align_to(256, 256), 256);
+        assert_eq!(align_to(257, 256), 512);
+        assert_eq!(align_to(16, 64), 64);
+    }
+
+    #[test]
+    fn update_element_leaves_neighbours() {
+        let (device, queue) = match test_device() {
+            Some(device) => device,
+            None => return eprintln!("skipped, there's no adapter"),
+        };
+        let mut array = UniformArrayBuffer::<Element>::new(&device, "Test Array", 4);
+        array.update_all(
+            &queue,
+            &[
+                Element([1; 4]),
+                Element([2; 4]),
+                Element([3; 4]),
+                Element([4; 4]),
+            ],
+        );
+        array.update_element(&queue, 2, &Element([9, 8, 7, 6]));
+
+        let elements = read_back(&device, &queue, array.buffer(), array.buffer_size());
+        assert_eq!(
+            elements,
+            [
+                Element([1; 4]),
+                Element([2; 4]),
+                Element([9, 8, 7, 6]),
+                Element([4; 4]),
+            ]
+        );
+        assert_eq!(array.len(), 4);
+    }
+
+    #[test]
+    fn update_element_grows_len() {
+        let (device, queue) = match test_device() {
+            Some(device) => device,
+            None => return eprintln!("skipped, there's no adapter"),
+        };
+        let mut array = UniformArrayBuffer::<Element>::new(&device, "Test Array", 8);
+        assert!(array.is_empty());
+        array.update_element(&queue, 3, &Element([1; 4]));
+        assert_eq!(array.len(), 4);
+        array.update_element(&queue, 1, &Element([1; 4]));
+        assert_eq!(array.len(), 4);
+    }
+}
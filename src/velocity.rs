@@ -0,0 +1,218 @@
+use cgmath::{Matrix4, SquareMatrix};
+
+use crate::{
+    camera::Camera, depth::DepthBuffer, fullscreen_triangle::FullscreenTriangle,
+    pipeline::RenderPipelineBuilder, render_pass_guard::RenderPassBuilder, uniform::UniformBinding,
+};
+
+/// Format of the velocity texture. Velocities are small fractions
+/// of the screen, which half floats keep plenty of precision for.
+pub const VELOCITY_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rg16Float;
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct VelocityUniform {
+    inv_view_proj: [[f32; 4]; 4],
+    previous_view_proj: [[f32; 4]; 4],
+}
+
+/// Works out how far each pixel moved across the screen since the
+/// last frame, for motion blur and temporal anti-aliasing.
+///
+/// The depth of each pixel is reprojected with the last frame's
+/// view projection, so only the camera's movement is seen. The
+/// velocity is in texture coordinates, from where the pixel was to
+/// where it is now.
+///
+/// It's drawn with a fullscreen triangle rather than a compute
+/// shader, since `Rg16Float` can be rendered to but isn't one of
+/// the formats storage textures support.
+pub struct VelocityPass {
+    texture: wgpu::Texture,
+    view: wgpu::TextureView,
+    uniform: UniformBinding<VelocityUniform>,
+    previous_view_proj: Matrix4<f32>,
+    depth_layouts: [wgpu::BindGroupLayout; 2],
+    /// For a single sampled depth buffer, then a multisampled one.
+    triangles: [FullscreenTriangle; 2],
+}
+
+impl VelocityPass {
+    pub fn new(device: &wgpu::Device, config: &wgpu::SurfaceConfiguration) -> Self {
+        let (texture, view) = create_texture(device, config.width, config.height);
+        let uniform = UniformBinding::new(
+            device,
+            "Velocity Uniform",
+            wgpu::ShaderStages::FRAGMENT,
+            &VelocityUniform {
+                inv_view_proj: Matrix4::identity().into(),
+                previous_view_proj: Matrix4::identity().into(),
+            },
+        );
+
+        let depth_layouts = [false, true].map(|multisampled| {
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("Velocity Depth Bind Group Layout"),
+                entries: &[wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        sample_type: wgpu::TextureSampleType::Depth,
+                    },
+                    count: None,
+                }],
+            })
+        });
+
+        let vertex_shader = FullscreenTriangle::vertex_shader(device);
+        let source = include_str!("velocity.wgsl");
+        let triangles = [false, true].map(|multisampled| {
+            // The samples are loaded from the same way either way, so
+            // only the texture's type changes.
+            let source = if multisampled {
+                source.replace("texture_depth_2d", "texture_depth_multisampled_2d")
+            } else {
+                source.to_string()
+            };
+            let fragment_shader = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+                label: Some("Velocity Shader"),
+                source: wgpu::ShaderSource::Wgsl(source.into()),
+            });
+            let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Velocity Pipeline Layout"),
+                bind_group_layouts: &[
+                    &uniform.bind_group_layout,
+                    &depth_layouts[multisampled as usize],
+                ],
+                push_constant_ranges: &[],
+            });
+            let pipeline = RenderPipelineBuilder::new()
+                .label("Velocity Pipeline")
+                .vertex_shader(&vertex_shader, "main")
+                .fragment_shader(
+                    &fragment_shader,
+                    "main",
+                    &[wgpu::ColorTargetState {
+                        format: VELOCITY_FORMAT,
+                        blend: Some(wgpu::BlendState::REPLACE),
+                        write_mask: wgpu::ColorWrites::ALL,
+                    }],
+                )
+                .cull_mode(None)
+                .build(device, &layout)
+                .expect("failed to build velocity pipeline");
+            FullscreenTriangle::new(pipeline)
+        });
+
+        VelocityPass {
+            texture,
+            view,
+            uniform,
+            previous_view_proj: Matrix4::identity(),
+            depth_layouts,
+            triangles,
+        }
+    }
+
+    /// The velocity of each pixel, in texture coordinates.
+    pub fn velocity_view(&self) -> &wgpu::TextureView {
+        &self.view
+    }
+
+    #[allow(dead_code)]
+    pub fn velocity_texture(&self) -> &wgpu::Texture {
+        &self.texture
+    }
+
+    /// Uploads the camera for this frame, keeping last frame's for
+    /// reprojecting. Should be called once a frame, after the camera
+    /// has moved.
+    pub fn update(&mut self, queue: &wgpu::Queue, camera: &Camera) {
+        // Without the jitter of temporal anti-aliasing, which isn't
+        // movement of the scene.
+        let view_proj = camera.build_unjittered_view_projection_matrix();
+        let inv_view_proj = view_proj.invert().unwrap_or_else(Matrix4::identity);
+        self.uniform.update(
+            queue,
+            &VelocityUniform {
+                inv_view_proj: inv_view_proj.into(),
+                previous_view_proj: self.previous_view_proj.into(),
+            },
+        );
+        self.previous_view_proj = view_proj;
+    }
+
+    /// Recreates the velocity texture to match the new surface size.
+    pub fn resize(&mut self, device: &wgpu::Device, width: u32, height: u32) {
+        let (texture, view) = create_texture(device, width, height);
+        self.texture = texture;
+        self.view = view;
+    }
+
+    /// Draws the velocities of the pixels in `depth`, which the scene
+    /// was drawn with.
+    pub fn run(
+        &self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        depth: &DepthBuffer,
+    ) {
+        let multisampled = (depth.sample_count() > 1) as usize;
+        // The depth buffer is recreated on resize and when MSAA
+        // changes, so its bind group is made as it's needed.
+        let depth_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Velocity Depth Bind Group"),
+            layout: &self.depth_layouts[multisampled],
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(depth.depth_view()),
+            }],
+        });
+
+        let mut render_pass = RenderPassBuilder::begin_render_pass(
+            encoder,
+            &wgpu::RenderPassDescriptor {
+                label: Some("Velocity Pass"),
+                color_attachments: &[wgpu::RenderPassColorAttachment {
+                    view: &self.view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        // Every pixel is drawn over, so there's no need to clear.
+                        load: wgpu::LoadOp::Load,
+                        store: true,
+                    },
+                }],
+                depth_stencil_attachment: None,
+            },
+        );
+        render_pass.set_bind_group(0, &self.uniform.bind_group, &[]);
+        render_pass.set_bind_group(1, &depth_bind_group, &[]);
+        self.triangles[multisampled].draw(&mut render_pass);
+    }
+}
+
+fn create_texture(
+    device: &wgpu::Device,
+    width: u32,
+    height: u32,
+) -> (wgpu::Texture, wgpu::TextureView) {
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("Velocity Texture"),
+        size: wgpu::Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: VELOCITY_FORMAT,
+        // Rendered into by the velocity pass, and sampled by the
+        // passes after it.
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+    });
+    let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+    (texture, view)
+}
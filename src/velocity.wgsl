@@ -0,0 +1,37 @@
+// Works out how far each pixel moved on screen since the last frame,
+// by reprojecting its depth with the last frame's camera. Only the
+// camera's movement is seen, so moving objects have none of their own.
+
+[[block]]
+struct VelocityUniform {
+    inv_view_proj: mat4x4<f32>;
+    previous_view_proj: mat4x4<f32>;
+};
+
+[[group(0), binding(0)]]
+var<uniform> matrices: VelocityUniform;
+
+// Swapped for a multisampled depth texture when MSAA is on, which
+// is loaded from the same way.
+[[group(1), binding(0)]]
+var t_depth: texture_depth_2d;
+
+struct FragmentInput {
+    [[builtin(position)]] position: vec4<f32>;
+    [[location(0)]] tex_coords: vec2<f32>;
+};
+
+[[stage(fragment)]]
+fn main(in: FragmentInput) -> [[location(0)]] vec4<f32> {
+    let depth = textureLoad(t_depth, vec2<i32>(in.position.xy), 0);
+
+    // Texture coordinates have Y pointing down, unlike clip space.
+    let ndc = vec4<f32>(in.tex_coords.x * 2.0 - 1.0, 1.0 - in.tex_coords.y * 2.0, depth, 1.0);
+    let world = matrices.inv_view_proj * ndc;
+    let previous = matrices.previous_view_proj * vec4<f32>(world.xyz / world.w, 1.0);
+    let previous_ndc = previous.xy / previous.w;
+
+    // Texture coordinates moved since the last frame.
+    let velocity = (ndc.xy - previous_ndc) * vec2<f32>(0.5, -0.5);
+    return vec4<f32>(velocity, 0.0, 1.0);
+}
@@ -0,0 +1,125 @@
+use wgpu::util::DeviceExt;
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct Vertex {
+    pub position: [f32; 3],
+    pub color: [f32; 3],
+    pub normal: [f32; 3],
+    pub tex_coords: [f32; 2],
+}
+
+impl Vertex {
+    pub fn vertex_buffer_layout<'a>() -> wgpu::VertexBufferLayout<'a> {
+        wgpu::VertexBufferLayout {
+            // The `array_stride` defines how wide a vertex is. When the shader
+            // goes to read the next vertex, it will skip over `array_stride`
+            // number of bytes. In our case, `array_stride` will probably be 44 bytes.
+            array_stride: std::mem::size_of::<Vertex>() as wgpu::BufferAddress,
+            // Tells the pipeline how often it should move to the next vertex.
+            step_mode: wgpu::VertexStepMode::Vertex,
+            // Vertex attributes describe the individual parts of the vertex.
+            // Generally this is a 1:1 mapping with a struct's fields, which
+            // it is in our case.
+            attributes: &[
+                wgpu::VertexAttribute {
+                    // Defines the offset in bytes that this attribute starts.
+                    // The first attribute is usually zero, and any future
+                    // attributes are the collective size_of the previous
+                    // attributes data.
+                    offset: 0,
+                    // Tells the shader what location to store this attribute at.
+                    // For example `[[location(0)]] x: vec3<f32>` in the vertex
+                    // shader would correspond to the position field of the struct,
+                    // while `[[location(1)]] x: vec3<f32>` would be the color field.
+                    shader_location: 0,
+                    // Format tells the shader the shape of the attribute.
+                    // `Float32x3` corresponds to vec3<f32> in shader code.
+                    format: wgpu::VertexFormat::Float32x3,
+                },
+                wgpu::VertexAttribute {
+                    offset: std::mem::size_of::<[f32; 3]>() as wgpu::BufferAddress,
+                    shader_location: 1,
+                    format: wgpu::VertexFormat::Float32x3,
+                },
+                wgpu::VertexAttribute {
+                    offset: std::mem::size_of::<[f32; 6]>() as wgpu::BufferAddress,
+                    shader_location: 2,
+                    format: wgpu::VertexFormat::Float32x3,
+                },
+                wgpu::VertexAttribute {
+                    offset: std::mem::size_of::<[f32; 9]>() as wgpu::BufferAddress,
+                    shader_location: 3,
+                    format: wgpu::VertexFormat::Float32x2,
+                },
+            ],
+        }
+    }
+}
+
+/// Vertex buffer slot each mesh's tangents are bound to, after the
+/// vertices and the instances.
+pub const TANGENT_SLOT: u32 = 2;
+
+/// Layout of the tangents every [`Mesh`](crate::mesh::Mesh) keeps
+/// in a buffer of their own, one `[f32; 4]` for each vertex. The
+/// direction of the bitangent is in `w`, as 1 or -1, and meshes
+/// without tangents have all zeros.
+pub fn tangent_buffer_layout<'a>() -> wgpu::VertexBufferLayout<'a> {
+    const ATTRIBUTES: [wgpu::VertexAttribute; 1] = wgpu::vertex_attr_array![4 => Float32x4];
+    wgpu::VertexBufferLayout {
+        array_stride: std::mem::size_of::<[f32; 4]>() as wgpu::BufferAddress,
+        step_mode: wgpu::VertexStepMode::Vertex,
+        attributes: &ATTRIBUTES,
+    }
+}
+
+/// Vertex data uploaded to the GPU, along with the number
+/// of vertices it holds.
+pub struct VertexBuffer {
+    buffer: wgpu::Buffer,
+    len: u32,
+    #[allow(dead_code)]
+    size: wgpu::BufferAddress,
+}
+
+impl VertexBuffer {
+    pub fn from_slice<V: bytemuck::Pod>(device: &wgpu::Device, vertices: &[V]) -> Self {
+        // `create_buffer_init` requires the DeviceExt extension trait.
+        let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Vertex Buffer"),
+            contents: bytemuck::cast_slice(vertices),
+            usage: wgpu::BufferUsages::VERTEX,
+        });
+
+        VertexBuffer {
+            buffer,
+            len: vertices.len() as u32,
+            size: std::mem::size_of_val(vertices) as wgpu::BufferAddress,
+        }
+    }
+
+    #[allow(dead_code)]
+    pub fn buffer(&self) -> &wgpu::Buffer {
+        &self.buffer
+    }
+
+    /// Number of vertices in the buffer.
+    pub fn len(&self) -> u32 {
+        self.len
+    }
+
+    /// Size of the vertices in bytes.
+    #[allow(dead_code)]
+    pub fn size(&self) -> wgpu::BufferAddress {
+        self.size
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn slice(&self) -> wgpu::BufferSlice<'_> {
+        self.buffer.slice(..)
+    }
+}
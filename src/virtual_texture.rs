@@ -0,0 +1,504 @@
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    num::NonZeroU32,
+};
+
+use wgpu::util::DeviceExt;
+
+use crate::texture::Texture;
+
+/// Width and height of a page, in texels. Virtual and physical
+/// sizes are rounded down to whole pages.
+pub const PAGE_SIZE: u32 = 128;
+
+/// The WGSL of `sample_virtual(uv: vec2<f32>) -> vec4<f32>`, added
+/// to the start of shaders that sample a [`VirtualTexture`]. Its
+/// bindings are in group 1, where the texture's bind group goes.
+pub const WGSL: &str = include_str!("virtual_texture.wgsl");
+
+/// Where a page is kept in the physical texture, in pages.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct PageSlot {
+    pub x: u32,
+    pub y: u32,
+}
+
+/// Sizes the shader works out texture coordinates from.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct VirtualTextureUniform {
+    /// Pages across and down the virtual texture.
+    pages: [u32; 2],
+    /// Slots across and down the physical texture.
+    slots: [u32; 2],
+}
+
+/// A texture too big to keep on the GPU, of which only the pages
+/// that are needed are kept in a smaller physical texture.
+///
+/// Shaders sample it through a page table, with a texel for each
+/// page of the virtual texture saying which slot of the physical
+/// texture has it, if any. Pages that aren't there yet sample as
+/// grey.
+///
+/// Pages don't load themselves. Whatever draws with the texture
+/// reports the pages it needs with [`VirtualTexture::request`], and
+/// the ones that weren't there are queued as page faults, which
+/// something on the CPU streams in with
+/// [`VirtualTexture::mark_resident`] and
+/// [`VirtualTexture::write_page`]. Once every slot is taken, making
+/// a page resident evicts the one that's been there longest.
+///
+/// Pages are sampled without borders, so filtering blends in a
+/// little of the neighbouring slot at their edges.
+pub struct VirtualTexture {
+    physical: wgpu::Texture,
+    page_table: wgpu::Texture,
+    bind_group_layout: wgpu::BindGroupLayout,
+    bind_group: wgpu::BindGroup,
+    pages: PageTable,
+}
+
+/// Which pages of a [`VirtualTexture`] are in which slots, and which
+/// were asked for, apart from the textures they're kept in.
+struct PageTable {
+    /// Pages across and down the virtual texture.
+    pages: (u32, u32),
+    /// Slots across and down the physical texture.
+    slots: (u32, u32),
+    /// CPU copy of the page table, uploaded by
+    /// [`VirtualTexture::upload_page_table`] when it's changed.
+    entries: Vec<[u8; 4]>,
+    dirty: bool,
+    resident: HashMap<(u32, u32), PageSlot>,
+    /// Resident pages, from the one made resident first.
+    residency_order: VecDeque<(u32, u32)>,
+    free_slots: Vec<PageSlot>,
+    page_faults: VecDeque<(u32, u32)>,
+    /// Pages in `page_faults`, so a page is only queued once.
+    faulted: HashSet<(u32, u32)>,
+}
+
+impl VirtualTexture {
+    /// `virtual_size` is how big the whole texture is, and
+    /// `physical_size` how much of it the GPU has room for, both
+    /// in texels.
+    ///
+    /// # Panics
+    ///
+    /// If either size is smaller than a page, or the physical
+    /// texture has more than 256 slots across or down, which the
+    /// page table can't index.
+    pub fn new(device: &wgpu::Device, virtual_size: (u32, u32), physical_size: (u32, u32)) -> Self {
+        let pages = (virtual_size.0 / PAGE_SIZE, virtual_size.1 / PAGE_SIZE);
+        let slots = (physical_size.0 / PAGE_SIZE, physical_size.1 / PAGE_SIZE);
+        let page_table = PageTable::new(pages, slots);
+
+        let physical = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Virtual Texture Physical Cache"),
+            size: wgpu::Extent3d {
+                width: slots.0 * PAGE_SIZE,
+                height: slots.1 * PAGE_SIZE,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: Texture::FORMAT,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+        });
+        // Slot x and y in red and green, and whether it's resident
+        // in blue.
+        let page_table_texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Virtual Texture Page Table"),
+            size: wgpu::Extent3d {
+                width: pages.0,
+                height: pages.1,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rgba8Uint,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+        });
+
+        let uniform = VirtualTextureUniform {
+            pages: [pages.0, pages.1],
+            slots: [slots.0, slots.1],
+        };
+        let uniform_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Virtual Texture Uniform"),
+            contents: bytemuck::bytes_of(&uniform),
+            usage: wgpu::BufferUsages::UNIFORM,
+        });
+
+        let bind_group_layout = create_bind_group_layout(device);
+        let physical_view = physical.create_view(&wgpu::TextureViewDescriptor::default());
+        let page_table_view =
+            page_table_texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Virtual Texture Bind Group"),
+            layout: &bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&page_table_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(&physical_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::Sampler(&sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: uniform_buffer.as_entire_binding(),
+                },
+            ],
+        });
+
+        VirtualTexture {
+            physical,
+            page_table: page_table_texture,
+            bind_group_layout,
+            bind_group,
+            pages: page_table,
+        }
+    }
+
+    pub fn bind_group_layout(&self) -> &wgpu::BindGroupLayout {
+        &self.bind_group_layout
+    }
+
+    pub fn bind_group(&self) -> &wgpu::BindGroup {
+        &self.bind_group
+    }
+
+    /// Pages across and down the virtual texture.
+    pub fn page_count(&self) -> (u32, u32) {
+        self.pages.pages
+    }
+
+    /// Number of pages the physical texture has room for.
+    pub fn capacity(&self) -> usize {
+        (self.pages.slots.0 * self.pages.slots.1) as usize
+    }
+
+    pub fn is_resident(&self, page_x: u32, page_y: u32) -> bool {
+        self.pages.resident.contains_key(&(page_x, page_y))
+    }
+
+    /// Reports that a page is needed, queueing a page fault if it
+    /// isn't resident and hasn't been queued already.
+    pub fn request(&mut self, page_x: u32, page_y: u32) {
+        self.pages.request(page_x, page_y);
+    }
+
+    /// Takes the queued page faults, oldest first, for them to be
+    /// streamed in.
+    pub fn drain_page_faults(&mut self) -> impl Iterator<Item = (u32, u32)> + '_ {
+        self.pages.drain_page_faults()
+    }
+
+    /// Gives the page a slot in the physical texture, evicting the
+    /// page that's been resident longest when they're all taken.
+    /// Its texels still need writing with
+    /// [`VirtualTexture::write_page`].
+    ///
+    /// Pages that are already resident keep their slot.
+    pub fn mark_resident(&mut self, page_x: u32, page_y: u32) -> PageSlot {
+        self.pages.mark_resident(page_x, page_y)
+    }
+
+    /// Frees the page's slot, so it samples as missing again.
+    /// Does nothing if the page isn't resident.
+    pub fn evict(&mut self, page_x: u32, page_y: u32) {
+        self.pages.evict(page_x, page_y);
+    }
+
+    /// Uploads a resident page's texels, `PAGE_SIZE` rows of
+    /// `PAGE_SIZE` RGBA pixels in sRGB.
+    ///
+    /// # Panics
+    ///
+    /// If the page isn't resident, or `rgba` is the wrong size.
+    pub fn write_page(&self, queue: &wgpu::Queue, page_x: u32, page_y: u32, rgba: &[u8]) {
+        let slot = self.pages.resident[&(page_x, page_y)];
+        assert_eq!(
+            rgba.len(),
+            (PAGE_SIZE * PAGE_SIZE * 4) as usize,
+            "pages are {0}x{0} RGBA pixels",
+            PAGE_SIZE
+        );
+
+        queue.write_texture(
+            wgpu::ImageCopyTexture {
+                texture: &self.physical,
+                mip_level: 0,
+                origin: wgpu::Origin3d {
+                    x: slot.x * PAGE_SIZE,
+                    y: slot.y * PAGE_SIZE,
+                    z: 0,
+                },
+                aspect: wgpu::TextureAspect::All,
+            },
+            rgba,
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: NonZeroU32::new(4 * PAGE_SIZE),
+                rows_per_image: NonZeroU32::new(PAGE_SIZE),
+            },
+            wgpu::Extent3d {
+                width: PAGE_SIZE,
+                height: PAGE_SIZE,
+                depth_or_array_layers: 1,
+            },
+        );
+    }
+
+    /// Uploads the page table if pages were made resident or evicted
+    /// since the last upload. Must be called before drawing with
+    /// the texture.
+    pub fn upload_page_table(&mut self, queue: &wgpu::Queue) {
+        if !self.pages.dirty {
+            return;
+        }
+
+        queue.write_texture(
+            wgpu::ImageCopyTexture {
+                texture: &self.page_table,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            },
+            bytemuck::cast_slice(&self.pages.entries),
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: NonZeroU32::new(4 * self.pages.pages.0),
+                rows_per_image: NonZeroU32::new(self.pages.pages.1),
+            },
+            wgpu::Extent3d {
+                width: self.pages.pages.0,
+                height: self.pages.pages.1,
+                depth_or_array_layers: 1,
+            },
+        );
+        self.pages.dirty = false;
+    }
+}
+
+impl PageTable {
+    /// # Panics
+    ///
+    /// If there's no room for a page, or more than 256 slots across
+    /// or down, which the table's entries can't index.
+    fn new(pages: (u32, u32), slots: (u32, u32)) -> Self {
+        assert!(
+            pages.0 > 0 && pages.1 > 0 && slots.0 > 0 && slots.1 > 0,
+            "virtual textures need room for at least one page"
+        );
+        assert!(
+            slots.0 <= 256 && slots.1 <= 256,
+            "physical textures can have at most 256 slots across and down"
+        );
+
+        // Filled in from the end, so the first slots are handed out first.
+        let free_slots = (0..slots.1)
+            .rev()
+            .flat_map(|y| (0..slots.0).rev().map(move |x| PageSlot { x, y }))
+            .collect();
+
+        PageTable {
+            pages,
+            slots,
+            entries: vec![[0; 4]; (pages.0 * pages.1) as usize],
+            // The table on the GPU starts out uninitialized.
+            dirty: true,
+            resident: HashMap::new(),
+            residency_order: VecDeque::new(),
+            free_slots,
+            page_faults: VecDeque::new(),
+            faulted: HashSet::new(),
+        }
+    }
+
+    /// See [`VirtualTexture::request`].
+    fn request(&mut self, page_x: u32, page_y: u32) {
+        self.check_page(page_x, page_y);
+        let page = (page_x, page_y);
+        if !self.resident.contains_key(&page) && self.faulted.insert(page) {
+            log::debug!("virtual texture page fault at {:?}", page);
+            self.page_faults.push_back(page);
+        }
+    }
+
+    /// See [`VirtualTexture::drain_page_faults`].
+    fn drain_page_faults(&mut self) -> impl Iterator<Item = (u32, u32)> + '_ {
+        self.faulted.clear();
+        self.page_faults.drain(..)
+    }
+
+    /// See [`VirtualTexture::mark_resident`].
+    fn mark_resident(&mut self, page_x: u32, page_y: u32) -> PageSlot {
+        self.check_page(page_x, page_y);
+        let page = (page_x, page_y);
+        if let Some(&slot) = self.resident.get(&page) {
+            return slot;
+        }
+
+        if self.free_slots.is_empty() {
+            let (oldest_x, oldest_y) = self
+                .residency_order
+                .front()
+                .copied()
+                .expect("full caches have resident pages");
+            self.evict(oldest_x, oldest_y);
+        }
+        let slot = self.free_slots.pop().expect("a slot was just freed");
+
+        self.resident.insert(page, slot);
+        self.residency_order.push_back(page);
+        self.set_entry(page, [slot.x as u8, slot.y as u8, 1, 0]);
+        slot
+    }
+
+    /// See [`VirtualTexture::evict`].
+    fn evict(&mut self, page_x: u32, page_y: u32) {
+        let page = (page_x, page_y);
+        if let Some(slot) = self.resident.remove(&page) {
+            self.residency_order.retain(|&resident| resident != page);
+            self.free_slots.push(slot);
+            self.set_entry(page, [0; 4]);
+        }
+    }
+
+    fn set_entry(&mut self, (page_x, page_y): (u32, u32), entry: [u8; 4]) {
+        self.entries[(page_y * self.pages.0 + page_x) as usize] = entry;
+        self.dirty = true;
+    }
+
+    fn check_page(&self, page_x: u32, page_y: u32) {
+        assert!(
+            page_x < self.pages.0 && page_y < self.pages.1,
+            "page ({}, {}) is outside of the {}x{} pages of the virtual texture",
+            page_x,
+            page_y,
+            self.pages.0,
+            self.pages.1
+        );
+    }
+}
+
+fn create_bind_group_layout(device: &wgpu::Device) -> wgpu::BindGroupLayout {
+    let [physical, sampler] = Texture::bind_group_layout_entry(1);
+    device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+        label: Some("Virtual Texture Bind Group Layout"),
+        entries: &[
+            wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Texture {
+                    multisampled: false,
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                    sample_type: wgpu::TextureSampleType::Uint,
+                },
+                count: None,
+            },
+            physical,
+            sampler,
+            wgpu::BindGroupLayoutEntry {
+                binding: 3,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            },
+        ],
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn faults(table: &mut PageTable) -> Vec<(u32, u32)> {
+        table.drain_page_faults().collect()
+    }
+
+    #[test]
+    fn requests_queue_each_missing_page_once() {
+        let mut table = PageTable::new((4, 4), (2, 2));
+        table.request(1, 2);
+        table.request(3, 0);
+        table.request(1, 2);
+        assert_eq!(faults(&mut table), [(1, 2), (3, 0)]);
+        assert!(faults(&mut table).is_empty());
+
+        // Until it's resident, a page faults again the next time.
+        table.request(1, 2);
+        table.mark_resident(3, 0);
+        table.request(3, 0);
+        assert_eq!(faults(&mut table), [(1, 2)]);
+    }
+
+    #[test]
+    fn resident_pages_point_at_their_slot() {
+        let mut table = PageTable::new((4, 4), (2, 2));
+        let first = table.mark_resident(2, 1);
+        let second = table.mark_resident(0, 3);
+        assert_eq!(first, PageSlot { x: 0, y: 0 });
+        assert_eq!(second, PageSlot { x: 1, y: 0 });
+        assert_eq!(table.mark_resident(2, 1), first);
+
+        assert_eq!(table.entries[4 + 2], [0, 0, 1, 0]);
+        assert_eq!(table.entries[3 * 4], [1, 0, 1, 0]);
+        assert_eq!(table.entries[0], [0; 4]);
+    }
+
+    #[test]
+    fn full_caches_evict_the_oldest_page() {
+        let mut table = PageTable::new((4, 4), (1, 2));
+        let oldest = table.mark_resident(0, 0);
+        table.mark_resident(1, 0);
+        let slot = table.mark_resident(2, 0);
+
+        assert_eq!(slot, oldest);
+        assert!(!table.resident.contains_key(&(0, 0)));
+        assert_eq!(table.entries[0], [0; 4]);
+        assert_eq!(table.entries[2], [oldest.x as u8, oldest.y as u8, 1, 0]);
+    }
+
+    #[test]
+    fn evicting_frees_the_slot() {
+        let mut table = PageTable::new((4, 4), (1, 1));
+        let slot = table.mark_resident(1, 1);
+        table.dirty = false;
+        table.evict(1, 1);
+        assert!(table.dirty);
+        assert_eq!(table.entries[4 + 1], [0; 4]);
+
+        // Evicting again, or a page that was never there, does nothing.
+        table.dirty = false;
+        table.evict(1, 1);
+        table.evict(2, 2);
+        assert!(!table.dirty);
+        assert_eq!(table.mark_resident(3, 3), slot);
+    }
+
+    #[test]
+    #[should_panic(expected = "outside of the 4x4 pages")]
+    fn pages_outside_the_texture_panic() {
+        PageTable::new((4, 4), (1, 1)).request(4, 0);
+    }
+}
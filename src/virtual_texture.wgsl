@@ -0,0 +1,43 @@
+// Sampling a virtual texture through its page table, added to the
+// start of shaders that call `sample_virtual`. See virtual_texture.rs.
+
+[[block]]
+struct VirtualTextureUniform {
+    // Pages across and down the virtual texture.
+    pages: vec2<u32>;
+    // Slots across and down the physical texture.
+    slots: vec2<u32>;
+};
+
+// A texel per page, with the slot in red and green, and blue set
+// when the page is resident.
+[[group(1), binding(0)]]
+var t_page_table: texture_2d<u32>;
+[[group(1), binding(1)]]
+var t_physical: texture_2d<f32>;
+[[group(1), binding(2)]]
+var s_physical: sampler;
+[[group(1), binding(3)]]
+var<uniform> virtual_texture: VirtualTextureUniform;
+
+// What pages that aren't resident yet look like.
+let MISSING_PAGE_COLOR: vec4<f32> = vec4<f32>(0.5, 0.5, 0.5, 1.0);
+
+// The virtual texture at `uv`, from 0 to 1 across the whole of it.
+fn sample_virtual(uv: vec2<f32>) -> vec4<f32> {
+    let pages = vec2<f32>(virtual_texture.pages);
+    let page_uv = clamp(uv, vec2<f32>(0.0), vec2<f32>(1.0)) * pages;
+    let page = min(vec2<u32>(page_uv), virtual_texture.pages - vec2<u32>(1u));
+
+    // Sampled before the branch, since sampling needs the
+    // neighbouring pixels to take the same path.
+    let entry = textureLoad(t_page_table, vec2<i32>(page), 0);
+    let within_page = page_uv - vec2<f32>(page);
+    let physical_uv = (vec2<f32>(entry.xy) + within_page) / vec2<f32>(virtual_texture.slots);
+    let color = textureSample(t_physical, s_physical, physical_uv);
+
+    if (entry.z == 0u) {
+        return MISSING_PAGE_COLOR;
+    }
+    return color;
+}
@@ -0,0 +1,308 @@
+use crate::{bounds::Aabb, mesh::Mesh, vertex::Vertex};
+
+/// The kind of a voxel, with [`EMPTY`] for none.
+pub type VoxelType = u8;
+
+pub const EMPTY: VoxelType = 0;
+
+/// The most quads [`VoxelGrid::greedy_mesh`] can build, since each
+/// has four vertices and the indices are 16 bit.
+const MAX_QUADS: usize = (u16::MAX as usize + 1) / 4;
+
+/// A face of the grid that needs drawing, between a solid voxel and
+/// an empty one.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+struct Face {
+    voxel_type: VoxelType,
+    /// Whether the face points along the axis, rather than against it.
+    positive: bool,
+}
+
+/// A box of voxels, one unit wide each, with the grid's corner at the
+/// origin.
+///
+/// The grid remembers when it was changed, so its mesh is only
+/// rebuilt by [`VoxelGrid::remesh`] when it needs to be.
+#[derive(Debug, Clone)]
+pub struct VoxelGrid {
+    size: [u32; 3],
+    voxels: Vec<VoxelType>,
+    /// Colors of the voxel types, by type. Types without one are white.
+    palette: Vec<[f32; 3]>,
+    dirty: bool,
+}
+
+impl VoxelGrid {
+    /// An empty grid of `sx` by `sy` by `sz` voxels.
+    pub fn new(sx: u32, sy: u32, sz: u32) -> Self {
+        VoxelGrid {
+            size: [sx, sy, sz],
+            voxels: vec![EMPTY; (sx * sy * sz) as usize],
+            palette: Vec::new(),
+            dirty: true,
+        }
+    }
+
+    pub fn size(&self) -> [u32; 3] {
+        self.size
+    }
+
+    fn index(&self, x: u32, y: u32, z: u32) -> Option<usize> {
+        let [sx, sy, sz] = self.size;
+        (x < sx && y < sy && z < sz).then(|| ((z * sy + y) * sx + x) as usize)
+    }
+
+    /// The voxel at `(x, y, z)`. Everything outside the grid is empty.
+    pub fn get(&self, x: u32, y: u32, z: u32) -> VoxelType {
+        self.index(x, y, z)
+            .map_or(EMPTY, |index| self.voxels[index])
+    }
+
+    /// Sets the voxel at `(x, y, z)`, ignoring voxels outside the grid.
+    pub fn set(&mut self, x: u32, y: u32, z: u32, voxel_type: VoxelType) {
+        if let Some(index) = self.index(x, y, z) {
+            if self.voxels[index] != voxel_type {
+                self.voxels[index] = voxel_type;
+                self.dirty = true;
+            }
+        }
+    }
+
+    /// Gives the faces of a voxel type a vertex color.
+    pub fn set_color(&mut self, voxel_type: VoxelType, color: [f32; 3]) {
+        let index = voxel_type as usize;
+        if self.palette.len() <= index {
+            self.palette.resize(index + 1, [1.0; 3]);
+        }
+        self.palette[index] = color;
+        self.dirty = true;
+    }
+
+    fn color(&self, voxel_type: VoxelType) -> [f32; 3] {
+        self.palette
+            .get(voxel_type as usize)
+            .copied()
+            .unwrap_or([1.0; 3])
+    }
+
+    /// Whether the grid changed since its mesh was last built.
+    #[allow(dead_code)]
+    pub fn is_dirty(&self) -> bool {
+        self.dirty
+    }
+
+    /// The voxel at a position that may be just outside the grid.
+    fn get_signed(&self, position: [i64; 3]) -> VoxelType {
+        if position.iter().any(|&p| p < 0) {
+            return EMPTY;
+        }
+        self.get(position[0] as u32, position[1] as u32, position[2] as u32)
+    }
+
+    /// Builds the faces between solid and empty voxels, merging
+    /// neighbouring faces of the same type into as few quads as it can.
+    ///
+    /// The grid is swept one slice at a time along each axis. The faces
+    /// of a slice are laid out in a mask, and each quad is grown from
+    /// the first face left in the mask, first as wide as it'll go and
+    /// then as tall as every row of that width allows.
+    ///
+    /// Texture coordinates count voxels across each quad, so textures
+    /// set to repeat tile once per voxel. The indices are 16 bit, so
+    /// this panics when more than 16384 quads are needed.
+    pub fn greedy_mesh(&self) -> (Vec<Vertex>, Vec<u16>) {
+        let mut vertices = Vec::new();
+        let mut indices = Vec::new();
+
+        for axis in 0..3 {
+            // The two axes across the slices.
+            let (u, v) = ((axis + 1) % 3, (axis + 2) % 3);
+            let (width, height) = (self.size[u] as usize, self.size[v] as usize);
+            let mut mask = vec![None; width * height];
+
+            // Slice `d` holds the faces between layers `d - 1` and `d`.
+            for d in 0..=self.size[axis] as i64 {
+                for j in 0..height {
+                    for i in 0..width {
+                        let mut position = [0; 3];
+                        position[axis] = d;
+                        position[u] = i as i64;
+                        position[v] = j as i64;
+                        let front = self.get_signed(position);
+                        position[axis] = d - 1;
+                        let back = self.get_signed(position);
+
+                        mask[j * width + i] = match (back != EMPTY, front != EMPTY) {
+                            (true, false) => Some(Face {
+                                voxel_type: back,
+                                positive: true,
+                            }),
+                            (false, true) => Some(Face {
+                                voxel_type: front,
+                                positive: false,
+                            }),
+                            _ => None,
+                        };
+                    }
+                }
+
+                for j in 0..height {
+                    let mut i = 0;
+                    while i < width {
+                        let face = match mask[j * width + i] {
+                            Some(face) => face,
+                            None => {
+                                i += 1;
+                                continue;
+                            }
+                        };
+
+                        let mut w = 1;
+                        while i + w < width && mask[j * width + i + w] == Some(face) {
+                            w += 1;
+                        }
+                        let mut h = 1;
+                        while j + h < height
+                            && (0..w).all(|k| mask[(j + h) * width + i + k] == Some(face))
+                        {
+                            h += 1;
+                        }
+                        for row in j..j + h {
+                            for cell in &mut mask[row * width + i..row * width + i + w] {
+                                *cell = None;
+                            }
+                        }
+
+                        let mut corner = [0.0; 3];
+                        corner[axis] = d as f32;
+                        corner[u] = i as f32;
+                        corner[v] = j as f32;
+                        let mut du = [0.0; 3];
+                        du[u] = w as f32;
+                        let mut dv = [0.0; 3];
+                        dv[v] = h as f32;
+                        self.push_quad(&mut vertices, &mut indices, axis, face, corner, du, dv);
+                        i += w;
+                    }
+                }
+            }
+        }
+
+        (vertices, indices)
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn push_quad(
+        &self,
+        vertices: &mut Vec<Vertex>,
+        indices: &mut Vec<u16>,
+        axis: usize,
+        face: Face,
+        corner: [f32; 3],
+        du: [f32; 3],
+        dv: [f32; 3],
+    ) {
+        let add = |a: [f32; 3], b: [f32; 3]| [a[0] + b[0], a[1] + b[1], a[2] + b[2]];
+        let mut normal = [0.0; 3];
+        normal[axis] = if face.positive { 1.0 } else { -1.0 };
+        let color = self.color(face.voxel_type);
+        let (w, h) = (du[(axis + 1) % 3], dv[(axis + 2) % 3]);
+
+        // `du` and `dv` cross along the axis, so going from one to the
+        // other is counter-clockwise seen from the positive side.
+        let corners = [
+            (corner, [0.0, 0.0]),
+            (add(corner, du), [w, 0.0]),
+            (add(add(corner, du), dv), [w, h]),
+            (add(corner, dv), [0.0, h]),
+        ];
+        assert!(
+            vertices.len() + corners.len() <= u16::MAX as usize + 1,
+            "voxel mesh with more than {} quads doesn't fit 16-bit indices",
+            MAX_QUADS
+        );
+        let base = vertices.len() as u16;
+        vertices.extend(corners.iter().map(|&(position, tex_coords)| Vertex {
+            position,
+            color,
+            normal,
+            tex_coords,
+        }));
+        if face.positive {
+            indices.extend_from_slice(&[base, base + 1, base + 2, base, base + 2, base + 3]);
+        } else {
+            indices.extend_from_slice(&[base, base + 2, base + 1, base, base + 3, base + 2]);
+        }
+    }
+
+    /// A new mesh of the grid, if it changed since the last one.
+    pub fn remesh(&mut self, device: &wgpu::Device) -> Option<Mesh> {
+        if !self.dirty {
+            return None;
+        }
+        self.dirty = false;
+
+        let (vertices, indices) = self.greedy_mesh();
+        let [sx, sy, sz] = self.size;
+        let bounds = Aabb {
+            min: [0.0; 3],
+            max: [sx as f32, sy as f32, sz as f32],
+        };
+        Some(Mesh::upload(device, &vertices, &indices).with_bounds(bounds))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn merges_the_faces_of_neighbouring_voxels() {
+        let mut grid = VoxelGrid::new(2, 1, 1);
+        grid.set(0, 0, 0, 1);
+        grid.set(1, 0, 0, 1);
+
+        // One quad for each side of the 2x1x1 box.
+        let (vertices, indices) = grid.greedy_mesh();
+        assert_eq!(vertices.len(), 6 * 4);
+        assert_eq!(indices.len(), 6 * 6);
+        let long_sides = vertices
+            .chunks(4)
+            .filter(|quad| {
+                quad.iter()
+                    .any(|vertex| vertex.tex_coords[0] == 2.0 || vertex.tex_coords[1] == 2.0)
+            })
+            .count();
+        assert_eq!(long_sides, 4);
+    }
+
+    #[test]
+    fn keeps_the_faces_of_different_types_apart() {
+        let mut grid = VoxelGrid::new(2, 1, 1);
+        grid.set(0, 0, 0, 1);
+        grid.set(1, 0, 0, 2);
+
+        // The long sides are split in two, and the faces between
+        // the voxels aren't drawn.
+        let (vertices, _) = grid.greedy_mesh();
+        assert_eq!(vertices.len(), 10 * 4);
+    }
+
+    #[test]
+    #[should_panic(expected = "16-bit indices")]
+    fn panics_when_the_quads_dont_fit_16_bit_indices() {
+        // Every other voxel of a checkerboard is solid, so none of
+        // their faces can be merged.
+        let mut grid = VoxelGrid::new(20, 20, 20);
+        for z in 0..20 {
+            for y in 0..20 {
+                for x in 0..20 {
+                    if (x + y + z) % 2 == 0 {
+                        grid.set(x, y, z, 1);
+                    }
+                }
+            }
+        }
+        grid.greedy_mesh();
+    }
+}
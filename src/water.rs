@@ -0,0 +1,386 @@
+use cgmath::Point3;
+
+use crate::{
+    camera::{Camera, CameraBuffer},
+    depth::DepthBuffer,
+    hdr_render_target::HDR_FORMAT,
+    mesh::Mesh,
+    msaa::MsaaConfig,
+    pipeline::RenderPipelineBuilder,
+    render_pass_guard::RenderPassBuilder,
+    render_target::RenderTarget,
+    uniform::UniformBinding,
+    vertex::Vertex,
+};
+
+/// Color the reflection and refraction are cleared to,
+/// where nothing is drawn.
+const CLEAR_COLOR: wgpu::Color = wgpu::Color {
+    r: 0.1,
+    g: 0.2,
+    b: 0.3,
+    a: 1.0,
+};
+
+/// Where the water is, and what it looks like.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct WaterConfig {
+    /// Center of the water on the XZ plane.
+    pub center: [f32; 2],
+    /// Width of the square of water.
+    pub size: f32,
+    /// Height of the surface.
+    pub height: f32,
+    /// Multiplied into what's seen through the water.
+    pub tint: [f32; 4],
+}
+
+impl Default for WaterConfig {
+    fn default() -> Self {
+        WaterConfig {
+            center: [0.0, 0.0],
+            size: 10.0,
+            height: 0.0,
+            tint: [0.6, 0.8, 0.9, 1.0],
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct WaterUniform {
+    eye: [f32; 4],
+    tint: [f32; 4],
+}
+
+/// A flat plane of water, reflecting the scene above it and
+/// showing the scene below through it.
+///
+/// Before the water is drawn, the scene is drawn twice more into
+/// off-screen targets: once from a camera mirrored under the
+/// surface for the reflection, and once from the real camera
+/// with everything above the surface clipped for the refraction.
+pub struct WaterPass {
+    config: WaterConfig,
+    reflection_rt: RenderTarget,
+    refraction_rt: RenderTarget,
+    water_mesh: Mesh,
+    /// Keeps only what's above the water, seen from below.
+    reflection_camera: CameraBuffer,
+    /// Keeps only what's below the water.
+    refraction_camera: CameraBuffer,
+    /// The scene pipeline, built without multisampling to match
+    /// the off-screen targets.
+    scene_pipeline: wgpu::RenderPipeline,
+    sampler: wgpu::Sampler,
+    texture_layout: wgpu::BindGroupLayout,
+    texture_bind_group: wgpu::BindGroup,
+    uniform: UniformBinding<WaterUniform>,
+    pipeline: wgpu::RenderPipeline,
+    pipeline_layout: wgpu::PipelineLayout,
+    shader: wgpu::ShaderModule,
+}
+
+impl WaterPass {
+    /// The water is drawn with the camera's bind group at group 0.
+    /// `scene_pipeline` should draw the scene with the same layout
+    /// as the main scene pipeline, into the HDR format, and with a
+    /// single sample.
+    pub fn new(
+        device: &wgpu::Device,
+        config: &wgpu::SurfaceConfiguration,
+        camera: &Camera,
+        camera_layout: &wgpu::BindGroupLayout,
+        scene_pipeline: wgpu::RenderPipeline,
+        msaa: MsaaConfig,
+        water: WaterConfig,
+    ) -> Self {
+        let (width, height) = (config.width, config.height);
+        let reflection_rt =
+            RenderTarget::new(device, width, height, &[HDR_FORMAT]).with_depth(device);
+        let refraction_rt =
+            RenderTarget::new(device, width, height, &[HDR_FORMAT]).with_depth(device);
+
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Water Sampler"),
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            ..Default::default()
+        });
+        let texture_entry = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                multisampled: false,
+                view_dimension: wgpu::TextureViewDimension::D2,
+                sample_type: wgpu::TextureSampleType::Float { filterable: true },
+            },
+            count: None,
+        };
+        let texture_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Water Texture Bind Group Layout"),
+            entries: &[
+                texture_entry(0),
+                texture_entry(1),
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler {
+                        filtering: true,
+                        comparison: false,
+                    },
+                    count: None,
+                },
+            ],
+        });
+        let texture_bind_group = create_texture_bind_group(
+            device,
+            &texture_layout,
+            &reflection_rt,
+            &refraction_rt,
+            &sampler,
+        );
+
+        let uniform = UniformBinding::new(
+            device,
+            "Water Uniform",
+            wgpu::ShaderStages::FRAGMENT,
+            &WaterUniform {
+                eye: camera.eye.to_homogeneous().into(),
+                tint: water.tint,
+            },
+        );
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Water Pipeline Layout"),
+            bind_group_layouts: &[camera_layout, &texture_layout, &uniform.bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let shader = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+            label: Some("Water Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("water.wgsl").into()),
+        });
+        let pipeline = create_pipeline(device, &pipeline_layout, &shader, msaa);
+
+        // A square on the XZ plane, facing up.
+        let half = water.size * 0.5;
+        let [x, z] = water.center;
+        let corners = [
+            ([x - half, z + half], [0.0, 1.0]),
+            ([x + half, z + half], [1.0, 1.0]),
+            ([x + half, z - half], [1.0, 0.0]),
+            ([x - half, z - half], [0.0, 0.0]),
+        ];
+        let vertices = corners
+            .iter()
+            .map(|([x, z], tex_coords)| Vertex {
+                position: [*x, water.height, *z],
+                color: [1.0, 1.0, 1.0],
+                normal: [0.0, 1.0, 0.0],
+                tex_coords: *tex_coords,
+            })
+            .collect::<Vec<_>>();
+        let water_mesh = Mesh::upload(device, &vertices, &[0u16, 1, 2, 0, 2, 3]);
+
+        let mut water_pass = WaterPass {
+            config: water,
+            reflection_rt,
+            refraction_rt,
+            water_mesh,
+            reflection_camera: CameraBuffer::new(device, camera),
+            refraction_camera: CameraBuffer::new(device, camera),
+            scene_pipeline,
+            sampler,
+            texture_layout,
+            texture_bind_group,
+            uniform,
+            pipeline,
+            pipeline_layout,
+            shader,
+        };
+        water_pass
+            .reflection_camera
+            .set_clip_plane([0.0, 1.0, 0.0, -water.height]);
+        water_pass
+            .refraction_camera
+            .set_clip_plane([0.0, -1.0, 0.0, water.height]);
+        water_pass
+    }
+
+    #[allow(dead_code)]
+    pub fn config(&self) -> &WaterConfig {
+        &self.config
+    }
+
+    /// Follows the camera, for the next reflection and refraction.
+    pub fn update(&mut self, queue: &wgpu::Queue, camera: &Camera) {
+        // Moving the eye and target under the surface, and keeping
+        // the up direction, sees the scene as the water would
+        // reflect it, only upside down.
+        let mirror =
+            |point: Point3<f32>| Point3::new(point.x, 2.0 * self.config.height - point.y, point.z);
+        let mirrored = Camera {
+            eye: mirror(camera.eye),
+            target: mirror(camera.target),
+            up: camera.up,
+            aspect: camera.aspect,
+            projection: camera.projection,
+            jitter: camera.jitter,
+        };
+        self.reflection_camera.update(queue, &mirrored);
+        self.refraction_camera.update(queue, camera);
+
+        self.uniform.update(
+            queue,
+            &WaterUniform {
+                eye: camera.eye.to_homogeneous().into(),
+                tint: self.config.tint,
+            },
+        );
+    }
+
+    /// Records the pass drawing the reflection. `draw` should draw
+    /// the scene, everything but the camera at group 0 bound, with
+    /// the pipeline it's given already set.
+    pub fn render_reflection<'a>(
+        &'a self,
+        encoder: &'a mut wgpu::CommandEncoder,
+        draw: impl FnOnce(&mut wgpu::RenderPass<'a>),
+    ) {
+        self.render_scene(
+            "Water Reflection Pass",
+            encoder,
+            &self.reflection_rt,
+            &self.reflection_camera,
+            draw,
+        );
+    }
+
+    /// Records the pass drawing what's under the water, the
+    /// same way as [`render_reflection`](Self::render_reflection).
+    pub fn render_refraction<'a>(
+        &'a self,
+        encoder: &'a mut wgpu::CommandEncoder,
+        draw: impl FnOnce(&mut wgpu::RenderPass<'a>),
+    ) {
+        self.render_scene(
+            "Water Refraction Pass",
+            encoder,
+            &self.refraction_rt,
+            &self.refraction_camera,
+            draw,
+        );
+    }
+
+    fn render_scene<'a>(
+        &'a self,
+        label: &'a str,
+        encoder: &'a mut wgpu::CommandEncoder,
+        target: &'a RenderTarget,
+        camera: &'a CameraBuffer,
+        draw: impl FnOnce(&mut wgpu::RenderPass<'a>),
+    ) {
+        let color_attachments = target.color_attachment_array(CLEAR_COLOR);
+        let mut render_pass = RenderPassBuilder::begin_render_pass(
+            encoder,
+            &wgpu::RenderPassDescriptor {
+                label: Some(label),
+                color_attachments: &color_attachments,
+                depth_stencil_attachment: target.depth_stencil_attachment(),
+            },
+        );
+        render_pass.set_pipeline(&self.scene_pipeline);
+        render_pass.set_bind_group(0, &camera.binding().bind_group, &[]);
+        draw(&mut render_pass);
+    }
+
+    /// Draws the water surface. Should come after the reflection and
+    /// refraction are rendered, and after the opaque geometry.
+    pub fn draw<'a>(
+        &'a self,
+        render_pass: &mut wgpu::RenderPass<'a>,
+        camera_bind_group: &'a wgpu::BindGroup,
+    ) {
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, camera_bind_group, &[]);
+        render_pass.set_bind_group(1, &self.texture_bind_group, &[]);
+        render_pass.set_bind_group(2, &self.uniform.bind_group, &[]);
+        self.water_mesh.draw(render_pass);
+    }
+
+    /// Recreates the reflection and refraction at the new size.
+    pub fn resize(&mut self, device: &wgpu::Device, width: u32, height: u32) {
+        self.reflection_rt.resize(device, width, height);
+        self.refraction_rt.resize(device, width, height);
+        self.texture_bind_group = create_texture_bind_group(
+            device,
+            &self.texture_layout,
+            &self.reflection_rt,
+            &self.refraction_rt,
+            &self.sampler,
+        );
+    }
+
+    /// Recreates the water pipeline to match the sample count of the pass.
+    pub fn set_msaa(&mut self, device: &wgpu::Device, msaa: MsaaConfig) {
+        self.pipeline = create_pipeline(device, &self.pipeline_layout, &self.shader, msaa);
+    }
+}
+
+fn create_texture_bind_group(
+    device: &wgpu::Device,
+    layout: &wgpu::BindGroupLayout,
+    reflection: &RenderTarget,
+    refraction: &RenderTarget,
+    sampler: &wgpu::Sampler,
+) -> wgpu::BindGroup {
+    device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some("Water Texture Bind Group"),
+        layout,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(&reflection.colors()[0].view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: wgpu::BindingResource::TextureView(&refraction.colors()[0].view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 2,
+                resource: wgpu::BindingResource::Sampler(sampler),
+            },
+        ],
+    })
+}
+
+fn create_pipeline(
+    device: &wgpu::Device,
+    layout: &wgpu::PipelineLayout,
+    shader: &wgpu::ShaderModule,
+    msaa: MsaaConfig,
+) -> wgpu::RenderPipeline {
+    RenderPipelineBuilder::new()
+        .label("Water Pipeline")
+        .vertex_shader(shader, "main")
+        .vertex_layouts(&[Vertex::vertex_buffer_layout()])
+        .fragment_shader(
+            shader,
+            "main",
+            &[wgpu::ColorTargetState {
+                format: HDR_FORMAT,
+                blend: Some(wgpu::BlendState::REPLACE),
+                write_mask: wgpu::ColorWrites::ALL,
+            }],
+        )
+        // Seen from underneath too, when the camera dips below it.
+        .cull_mode(None)
+        .depth_stencil(DepthBuffer::depth_stencil_state())
+        .multisample(msaa.count)
+        .build(device, layout)
+        .expect("failed to build water pipeline")
+}
@@ -0,0 +1,72 @@
+// Draws the water surface, blending what's reflected in it with
+// what's seen through it.
+
+[[block]]
+struct CameraUniform {
+    view_proj: mat4x4<f32>;
+};
+
+[[group(0), binding(0)]]
+var<uniform> camera: CameraUniform;
+
+[[group(1), binding(0)]]
+var t_reflection: texture_2d<f32>;
+[[group(1), binding(1)]]
+var t_refraction: texture_2d<f32>;
+[[group(1), binding(2)]]
+var s_water: sampler;
+
+[[block]]
+struct WaterUniform {
+    // Where the camera is, for the angle it sees the water at.
+    eye: vec4<f32>;
+    // Multiplied into what's seen through the water.
+    tint: vec4<f32>;
+};
+
+[[group(2), binding(0)]]
+var<uniform> water: WaterUniform;
+
+struct VertexInput {
+    [[location(0)]] position: vec3<f32>;
+};
+
+struct VertexOutput {
+    [[builtin(position)]] clip_position: vec4<f32>;
+    [[location(0)]] world_position: vec3<f32>;
+    // The clip position again, since the builtin one is in
+    // pixels by the time the fragment shader sees it.
+    [[location(1)]] screen_position: vec4<f32>;
+};
+
+[[stage(vertex)]]
+fn main(model: VertexInput) -> VertexOutput {
+    var out: VertexOutput;
+    out.world_position = model.position;
+    out.clip_position = camera.view_proj * vec4<f32>(model.position, 1.0);
+    out.screen_position = out.clip_position;
+    return out;
+}
+
+// Reflectance of water seen straight on.
+let BASE_REFLECTANCE: f32 = 0.02;
+
+[[stage(fragment)]]
+fn main(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    let ndc = in.screen_position.xy / in.screen_position.w;
+    // The refraction was drawn from the same camera, so it lines up
+    // on screen. The reflection was drawn from below, upside down.
+    let refraction_uv = ndc * vec2<f32>(0.5, -0.5) + vec2<f32>(0.5, 0.5);
+    let reflection_uv = ndc * vec2<f32>(0.5, 0.5) + vec2<f32>(0.5, 0.5);
+
+    let reflection = textureSample(t_reflection, s_water, reflection_uv).rgb;
+    let refraction = textureSample(t_refraction, s_water, refraction_uv).rgb * water.tint.rgb;
+
+    // Schlick's approximation. Water looking straight down lets
+    // most light through, and reflects more at shallow angles.
+    let view_dir = normalize(water.eye.xyz - in.world_position);
+    let cos_theta = clamp(view_dir.y, 0.0, 1.0);
+    let fresnel = BASE_REFLECTANCE + (1.0 - BASE_REFLECTANCE) * pow(1.0 - cos_theta, 5.0);
+
+    return vec4<f32>(mix(refraction, reflection, fresnel), 1.0);
+}
@@ -0,0 +1,218 @@
+use std::ops::Range;
+
+use crate::{
+    depth::DepthBuffer,
+    instance::{InstanceBuffer, InstanceData},
+    mesh::Mesh,
+    msaa::MsaaConfig,
+    pipeline::RenderPipelineBuilder,
+    uniform::UniformBinding,
+    vertex::Vertex,
+};
+
+/// Value the mask pass writes into the stencil buffer.
+const STENCIL_MASK: u32 = 1;
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct OutlineUniform {
+    color: [f32; 4],
+    scale: f32,
+    _padding: [f32; 3],
+}
+
+/// Outlines geometry with a solid color, drawn in two passes with
+/// the stencil buffer of the [`DepthBuffer`].
+///
+/// The geometry is drawn once without any color, marking the pixels
+/// it covers in the stencil buffer, and then again scaled up, only
+/// where it wasn't marked. That leaves the rim around its silhouette,
+/// which polygon mode wireframes don't show.
+///
+/// The outline is drawn over whatever is in front of it, so it
+/// shows where the geometry is even when it's hidden. The stencil
+/// buffer must be cleared to zero at the start of the pass.
+pub struct WireframeOverlay {
+    uniform: UniformBinding<OutlineUniform>,
+    mask_pipeline: wgpu::RenderPipeline,
+    rim_pipeline: wgpu::RenderPipeline,
+    pipeline_layout: wgpu::PipelineLayout,
+    shader: wgpu::ShaderModule,
+    format: wgpu::TextureFormat,
+}
+
+impl WireframeOverlay {
+    /// `scale` is how much bigger than the geometry the outline is,
+    /// like `1.05` for five percent. Drawn with the camera's bind
+    /// group at group 0.
+    pub fn new(
+        device: &wgpu::Device,
+        camera_layout: &wgpu::BindGroupLayout,
+        format: wgpu::TextureFormat,
+        msaa: MsaaConfig,
+        outline_color: [f32; 4],
+        scale: f32,
+    ) -> Self {
+        let uniform = UniformBinding::new(
+            device,
+            "Outline Uniform",
+            wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
+            &OutlineUniform {
+                color: outline_color,
+                scale,
+                _padding: [0.0; 3],
+            },
+        );
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Outline Pipeline Layout"),
+            bind_group_layouts: &[camera_layout, &uniform.bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let shader = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+            label: Some("Outline Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("wireframe_overlay.wgsl").into()),
+        });
+        let (mask_pipeline, rim_pipeline) =
+            create_pipelines(device, &pipeline_layout, &shader, format, msaa);
+
+        WireframeOverlay {
+            uniform,
+            mask_pipeline,
+            rim_pipeline,
+            pipeline_layout,
+            shader,
+            format,
+        }
+    }
+
+    /// Changes the outline's color and how much bigger than the
+    /// geometry it is.
+    #[allow(dead_code)]
+    pub fn set_outline(&self, queue: &wgpu::Queue, color: [f32; 4], scale: f32) {
+        self.uniform.update(
+            queue,
+            &OutlineUniform {
+                color,
+                scale,
+                _padding: [0.0; 3],
+            },
+        );
+    }
+
+    /// Outlines the given instances of `mesh`.
+    pub fn draw<'a>(
+        &'a self,
+        render_pass: &mut wgpu::RenderPass<'a>,
+        camera_bind_group: &'a wgpu::BindGroup,
+        mesh: &'a Mesh,
+        instances: &'a InstanceBuffer,
+        range: Range<u32>,
+    ) {
+        render_pass.set_bind_group(0, camera_bind_group, &[]);
+        render_pass.set_bind_group(1, &self.uniform.bind_group, &[]);
+        render_pass.set_vertex_buffer(1, instances.slice());
+        render_pass.set_stencil_reference(STENCIL_MASK);
+
+        render_pass.set_pipeline(&self.mask_pipeline);
+        mesh.draw_instanced(render_pass, range.clone());
+        render_pass.set_pipeline(&self.rim_pipeline);
+        mesh.draw_instanced(render_pass, range);
+    }
+
+    /// Recreates the pipelines to match the sample count of the pass.
+    pub fn set_msaa(&mut self, device: &wgpu::Device, msaa: MsaaConfig) {
+        let (mask_pipeline, rim_pipeline) = create_pipelines(
+            device,
+            &self.pipeline_layout,
+            &self.shader,
+            self.format,
+            msaa,
+        );
+        self.mask_pipeline = mask_pipeline;
+        self.rim_pipeline = rim_pipeline;
+    }
+}
+
+/// The pipelines marking the geometry in the stencil buffer, and
+/// drawing the outline around the marks.
+fn create_pipelines(
+    device: &wgpu::Device,
+    layout: &wgpu::PipelineLayout,
+    shader: &wgpu::ShaderModule,
+    format: wgpu::TextureFormat,
+    msaa: MsaaConfig,
+) -> (wgpu::RenderPipeline, wgpu::RenderPipeline) {
+    let vertex_layouts = [
+        Vertex::vertex_buffer_layout(),
+        InstanceData::vertex_buffer_layout(),
+    ];
+    let stencil = |compare, pass_op| wgpu::StencilState {
+        front: wgpu::StencilFaceState {
+            compare,
+            fail_op: wgpu::StencilOperation::Keep,
+            depth_fail_op: wgpu::StencilOperation::Keep,
+            pass_op,
+        },
+        back: wgpu::StencilFaceState::IGNORE,
+        read_mask: 0xff,
+        write_mask: 0xff,
+    };
+
+    // The geometry is already in the scene, so the mask only touches
+    // the stencil buffer. It ignores depth like the outline does, so
+    // the parts of the geometry hidden behind something get the same
+    // outline rather than being filled in.
+    let mask_pipeline = RenderPipelineBuilder::new()
+        .label("Outline Mask Pipeline")
+        .vertex_shader(shader, "mask")
+        .vertex_layouts(&vertex_layouts)
+        .fragment_shader(
+            shader,
+            "main",
+            &[wgpu::ColorTargetState {
+                format,
+                blend: None,
+                write_mask: wgpu::ColorWrites::empty(),
+            }],
+        )
+        .depth_stencil(wgpu::DepthStencilState {
+            depth_write_enabled: false,
+            depth_compare: wgpu::CompareFunction::Always,
+            stencil: stencil(
+                wgpu::CompareFunction::Always,
+                wgpu::StencilOperation::Replace,
+            ),
+            ..DepthBuffer::depth_stencil_state()
+        })
+        .multisample(msaa.count)
+        .build(device, layout)
+        .expect("failed to build outline mask pipeline");
+
+    let rim_pipeline = RenderPipelineBuilder::new()
+        .label("Outline Rim Pipeline")
+        .vertex_shader(shader, "rim")
+        .vertex_layouts(&vertex_layouts)
+        .fragment_shader(
+            shader,
+            "main",
+            &[wgpu::ColorTargetState {
+                format,
+                blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                write_mask: wgpu::ColorWrites::ALL,
+            }],
+        )
+        .depth_stencil(wgpu::DepthStencilState {
+            depth_write_enabled: false,
+            depth_compare: wgpu::CompareFunction::Always,
+            stencil: stencil(
+                wgpu::CompareFunction::NotEqual,
+                wgpu::StencilOperation::Keep,
+            ),
+            ..DepthBuffer::depth_stencil_state()
+        })
+        .multisample(msaa.count)
+        .build(device, layout)
+        .expect("failed to build outline rim pipeline");
+
+    (mask_pipeline, rim_pipeline)
+}
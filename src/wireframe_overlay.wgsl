@@ -0,0 +1,55 @@
+// Draws a solid outline around geometry, by drawing it scaled up
+// wherever the stencil buffer doesn't already mark it.
+
+[[block]]
+struct CameraUniform {
+    view_proj: mat4x4<f32>;
+};
+
+[[block]]
+struct OutlineUniform {
+    color: vec4<f32>;
+    // How much bigger the outline is than the geometry.
+    scale: f32;
+};
+
+[[group(0), binding(0)]]
+var<uniform> camera: CameraUniform;
+
+[[group(1), binding(0)]]
+var<uniform> outline: OutlineUniform;
+
+struct InstanceInput {
+    [[location(5)]] model_matrix_0: vec4<f32>;
+    [[location(6)]] model_matrix_1: vec4<f32>;
+    [[location(7)]] model_matrix_2: vec4<f32>;
+    [[location(8)]] model_matrix_3: vec4<f32>;
+};
+
+fn world_position(position: vec3<f32>, instance: InstanceInput) -> vec4<f32> {
+    let model_matrix = mat4x4<f32>(
+        instance.model_matrix_0,
+        instance.model_matrix_1,
+        instance.model_matrix_2,
+        instance.model_matrix_3,
+    );
+    return model_matrix * vec4<f32>(position, 1.0);
+}
+
+// Both passes only need the position of each vertex.
+[[stage(vertex)]]
+fn mask([[location(0)]] position: vec3<f32>, instance: InstanceInput) -> [[builtin(position)]] vec4<f32> {
+    return camera.view_proj * world_position(position, instance);
+}
+
+// Meshes are centered on their origin, so scaling about it
+// grows them outward on every side.
+[[stage(vertex)]]
+fn rim([[location(0)]] position: vec3<f32>, instance: InstanceInput) -> [[builtin(position)]] vec4<f32> {
+    return camera.view_proj * world_position(position * outline.scale, instance);
+}
+
+[[stage(fragment)]]
+fn main() -> [[location(0)]] vec4<f32> {
+    return outline.color;
+}
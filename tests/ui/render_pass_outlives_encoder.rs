@@ -0,0 +1,21 @@
+// A render pass borrows its encoder, so it can't be handed out past
+// the encoder it was started on.
+#[path = "../../src/render_pass_guard.rs"]
+#[allow(dead_code)]
+mod render_pass_guard;
+
+use render_pass_guard::{RenderPassBuilder, RenderPassGuard};
+
+fn begin_pass<'enc>(device: &wgpu::Device) -> RenderPassGuard<'enc> {
+    let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+    RenderPassBuilder::begin_render_pass(
+        &mut encoder,
+        &wgpu::RenderPassDescriptor {
+            label: None,
+            color_attachments: &[],
+            depth_stencil_attachment: None,
+        },
+    )
+}
+
+fn main() {}